@@ -1,7 +1,6 @@
 use anyhow::Result;
-use scylla::cql_to_rust::FromRow;
 use scylla::macros::FromRow;
-use scylla::transport::session::{IntoTypedRows, Session};
+use scylla::transport::session::Session;
 use scylla::SessionBuilder;
 use std::env;
 
@@ -44,11 +43,10 @@ async fn main() -> Result<()> {
         .await?;
 
     // Rows can be parsed as tuples
-    if let Some(rows) = session.query("SELECT a, b, c FROM ks.t", &[]).await?.rows {
-        for row in rows.into_typed::<(i32, i32, String)>() {
-            let (a, b, c) = row?;
-            println!("a, b, c: {}, {}, {}", a, b, c);
-        }
+    let result = session.query("SELECT a, b, c FROM ks.t", &[]).await?;
+    for row in result.rows_typed::<(i32, i32, String)>()? {
+        let (a, b, c) = row?;
+        println!("a, b, c: {}, {}, {}", a, b, c);
     }
 
     // Or as custom structs that derive FromRow
@@ -59,16 +57,16 @@ async fn main() -> Result<()> {
         c: String,
     }
 
-    if let Some(rows) = session.query("SELECT a, b, c FROM ks.t", &[]).await?.rows {
-        for row_data in rows.into_typed::<RowData>() {
-            let row_data = row_data?;
-            println!("row_data: {:?}", row_data);
-        }
+    let result = session.query("SELECT a, b, c FROM ks.t", &[]).await?;
+    for row_data in result.rows_typed::<RowData>()? {
+        let row_data = row_data?;
+        println!("row_data: {:?}", row_data);
     }
 
     // Or simply as untyped rows
     if let Some(rows) = session.query("SELECT a, b, c FROM ks.t", &[]).await?.rows {
         for row in rows {
+            let row = row?;
             let a = row.columns[0].as_ref().unwrap().as_int().unwrap();
             let b = row.columns[1].as_ref().unwrap().as_int().unwrap();
             let c = row.columns[2].as_ref().unwrap().as_text().unwrap();
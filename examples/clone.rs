@@ -44,7 +44,7 @@ async fn main() -> Result<()> {
         .await?
         .rows
     {
-        println!("Read {} rows", rows.len());
+        println!("Read {} rows", rows.rows_count());
     }
 
     Ok(())
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use scylla::frame::value::ValueList;
-use scylla::routing::murmur3_token;
+use scylla::routing::{murmur3_token, Token};
 use scylla::{Session, SessionBuilder};
 use std::env;
 
@@ -29,14 +29,15 @@ async fn main() -> Result<()> {
             .await?;
 
         let serialized_pk = (pk,).serialized()?.into_owned();
-        let t = murmur3_token(prepared.compute_partition_key(&serialized_pk)?).value;
+        let t = match murmur3_token(prepared.compute_partition_key(&serialized_pk)?) {
+            Token::Murmur3(value) => value,
+            token => unreachable!("murmur3_token returned {:?}", token),
+        };
 
         let qt = session
             .query(format!("SELECT token(pk) FROM ks.t where pk = {}", pk), &[])
             .await?
-            .rows
-            .unwrap()
-            .get(0)
+            .first_row()
             .expect("token query no rows!")
             .columns[0]
             .as_ref()
@@ -36,15 +36,14 @@ async fn main() -> Result<()> {
         .query("INSERT INTO ks.dates (d) VALUES (?)", (example_date,))
         .await?;
 
-    if let Some(rows) = session.query("SELECT d from ks.dates", &[]).await?.rows {
-        for row in rows.into_typed::<(NaiveDate,)>() {
-            let (read_date,): (NaiveDate,) = match row {
-                Ok(read_date) => read_date,
-                Err(_) => continue, // We might read a date that does not fit in NaiveDate, skip it
-            };
-
-            println!("Read a date: {:?}", read_date);
-        }
+    let result = session.query("SELECT d from ks.dates", &[]).await?;
+    for row in result.rows_typed::<(NaiveDate,)>()? {
+        let (read_date,): (NaiveDate,) = match row {
+            Ok(read_date) => read_date,
+            Err(_) => continue, // We might read a date that does not fit in NaiveDate, skip it
+        };
+
+        println!("Read a date: {:?}", read_date);
     }
 
     // Dates outside this range must be represented in the raw form - an u32 describing days since -5877641-06-23
@@ -55,6 +54,7 @@ async fn main() -> Result<()> {
 
     if let Some(rows) = session.query("SELECT d from ks.dates", &[]).await?.rows {
         for row in rows {
+            let row = row?;
             let read_days: u32 = match row.columns[0] {
                 Some(CqlValue::Date(days)) => days,
                 _ => panic!("oh no"),
@@ -79,12 +79,11 @@ async fn main() -> Result<()> {
         .query("INSERT INTO ks.times (t) VALUES (?)", (Time(example_time),))
         .await?;
 
-    if let Some(rows) = session.query("SELECT t from ks.times", &[]).await?.rows {
-        for row in rows.into_typed::<(Duration,)>() {
-            let (read_time,): (Duration,) = row?;
+    let result = session.query("SELECT t from ks.times", &[]).await?;
+    for row in result.rows_typed::<(Duration,)>()? {
+        let (read_time,): (Duration,) = row?;
 
-            println!("Read a time: {:?}", read_time);
-        }
+        println!("Read a time: {:?}", read_time);
     }
 
     // Timestamp - milliseconds since unix epoch - 1970-01-01
@@ -1,21 +1,27 @@
 use anyhow::Result;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
+use scylla::frame::response::result::CqlValue;
 use scylla::transport::Compression;
 use scylla::{QueryResult, Session, SessionBuilder};
 use std::env;
 
-fn print_result(result: &QueryResult) {
-    if result.rows.is_none() {
-        println!("OK");
-        return;
-    }
-    for row in result.rows.as_ref().unwrap() {
-        for column in &row.columns {
+fn print_result(result: &QueryResult) -> Result<()> {
+    let rows = match &result.rows {
+        Some(rows) => rows,
+        None => {
+            println!("OK");
+            return Ok(());
+        }
+    };
+    // Values are decoded straight from the received rows
+    for row in rows.raw_rows() {
+        let row = row?;
+        for index in 0..row.len() {
             print!("|");
             print!(
                 " {:16}",
-                match column {
+                match row.column::<Option<CqlValue>>(index)? {
                     None => "null".to_owned(),
                     Some(value) => format!("{:?}", value),
                 }
@@ -23,6 +29,7 @@ fn print_result(result: &QueryResult) {
         }
         println!("|")
     }
+    Ok(())
 }
 
 #[tokio::main]
@@ -49,7 +56,11 @@ async fn main() -> Result<()> {
                 let maybe_res = session.query(line, &[]).await;
                 match maybe_res {
                     Err(err) => println!("Error: {}", err),
-                    Ok(res) => print_result(&res),
+                    Ok(res) => {
+                        if let Err(err) = print_result(&res) {
+                            println!("Error: {}", err);
+                        }
+                    }
                 }
             }
             Err(ReadlineError::Interrupted) => continue,
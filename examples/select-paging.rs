@@ -1,5 +1,4 @@
 use anyhow::Result;
-use futures::stream::StreamExt;
 use scylla::{query::Query, Session, SessionBuilder};
 use std::env;
 
@@ -45,7 +44,7 @@ async fn main() -> Result<()> {
     println!(
         "Paging state: {:#?} ({} rows)",
         res1.paging_state,
-        res1.rows.unwrap().len()
+        res1.rows.unwrap().rows_count()
     );
     let res2 = session
         .query_paged(paged_query.clone(), &[], res1.paging_state)
@@ -53,7 +52,7 @@ async fn main() -> Result<()> {
     println!(
         "Paging state: {:#?} ({} rows)",
         res2.paging_state,
-        res2.rows.unwrap().len()
+        res2.rows.unwrap().rows_count()
     );
     let res3 = session
         .query_paged(paged_query.clone(), &[], res2.paging_state)
@@ -61,7 +60,7 @@ async fn main() -> Result<()> {
     println!(
         "Paging state: {:#?} ({} rows)",
         res3.paging_state,
-        res3.rows.unwrap().len()
+        res3.rows.unwrap().rows_count()
     );
 
     let paged_prepared = session
@@ -71,7 +70,7 @@ async fn main() -> Result<()> {
     println!(
         "Paging state from the prepared statement execution: {:#?} ({} rows)",
         res4.paging_state,
-        res4.rows.unwrap().len()
+        res4.rows.unwrap().rows_count()
     );
     let res5 = session
         .execute_paged(&paged_prepared, &[], res4.paging_state)
@@ -79,7 +78,7 @@ async fn main() -> Result<()> {
     println!(
         "Paging state from the second prepared statement execution: {:#?} ({} rows)",
         res5.paging_state,
-        res5.rows.unwrap().len()
+        res5.rows.unwrap().rows_count()
     );
     let res6 = session
         .execute_paged(&paged_prepared, &[], res5.paging_state)
@@ -87,7 +86,7 @@ async fn main() -> Result<()> {
     println!(
         "Paging state from the third prepared statement execution: {:#?} ({} rows)",
         res6.paging_state,
-        res6.rows.unwrap().len()
+        res6.rows.unwrap().rows_count()
     );
     println!("Ok.");
 
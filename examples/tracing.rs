@@ -2,12 +2,11 @@
 // query() prepare() execute() batch() query_iter() and execute_iter() can be traced
 
 use anyhow::{anyhow, Result};
-use futures::StreamExt;
 use scylla::batch::Batch;
 use scylla::statement::{prepared_statement::PreparedStatement, query::Query, Consistency};
 use scylla::tracing::{GetTracingConfig, TracingInfo};
 use scylla::transport::iterator::RowIterator;
-use scylla::{BatchResult, QueryResult};
+use scylla::QueryResult;
 use scylla::{Session, SessionBuilder};
 use std::env;
 use std::num::NonZeroU32;
@@ -83,7 +82,7 @@ async fn main() -> Result<()> {
     batch.set_tracing(true);
 
     // Run the batch and print its tracing_id
-    let batch_result: BatchResult = session.batch(&batch, ((),)).await?;
+    let batch_result: QueryResult = session.batch(&batch, ((),)).await?;
     println!("Batch tracing id: {:?}\n", batch_result.tracing_id);
 
     // CUSTOM
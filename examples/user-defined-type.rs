@@ -1,7 +1,6 @@
 use anyhow::Result;
-use scylla::cql_to_rust::FromCqlVal;
 use scylla::macros::{FromUserType, IntoUserType};
-use scylla::{IntoTypedRows, Session, SessionBuilder};
+use scylla::{Session, SessionBuilder};
 use std::env;
 
 #[tokio::main]
@@ -47,11 +46,10 @@ async fn main() -> Result<()> {
         .await?;
 
     // And read like any normal value
-    if let Some(rows) = session.query("SELECT my FROM ks.udt_tab", &[]).await?.rows {
-        for row in rows.into_typed::<(MyType,)>() {
-            let (my_val,) = row?;
-            println!("{:?}", my_val)
-        }
+    let result = session.query("SELECT my FROM ks.udt_tab", &[]).await?;
+    for row in result.rows_typed::<(MyType,)>()? {
+        let (my_val,) = row?;
+        println!("{:?}", my_val)
     }
 
     println!("Ok.");
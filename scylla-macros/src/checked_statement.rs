@@ -0,0 +1,1222 @@
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use std::collections::HashMap;
+use std::path::Path;
+use syn::parse::{Parse, ParseStream};
+use syn::{Attribute, Ident, LitStr, Token, Visibility};
+
+/// checked_statement! validates CQL statements against a schema snapshot file
+/// and generates a struct with the statement and its typed values for each of them
+pub fn checked_statement(tokens_input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(tokens_input as Input);
+    match expand(input) {
+        Ok(generated) => TokenStream::from(generated),
+        Err(err) => TokenStream::from(err.to_compile_error()),
+    }
+}
+
+struct Input {
+    snapshot: Option<LitStr>,
+    statements: Vec<StatementDef>,
+}
+
+struct StatementDef {
+    attrs: Vec<Attribute>,
+    vis: Visibility,
+    name: Ident,
+    cql: LitStr,
+}
+
+impl Parse for Input {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut snapshot = None;
+        let ahead = input.fork();
+        if ahead
+            .parse::<Ident>()
+            .is_ok_and(|ident| ident == "snapshot")
+            && ahead.peek(Token![=])
+        {
+            input.parse::<Ident>()?;
+            input.parse::<Token![=]>()?;
+            snapshot = Some(input.parse()?);
+            input.parse::<Token![;]>()?;
+        }
+
+        let mut statements = Vec::new();
+        while !input.is_empty() {
+            let attrs = input.call(Attribute::parse_outer)?;
+            let vis = input.parse()?;
+            let name = input.parse()?;
+            input.parse::<Token![=]>()?;
+            let cql = input.parse()?;
+            input.parse::<Token![;]>()?;
+            statements.push(StatementDef {
+                attrs,
+                vis,
+                name,
+                cql,
+            });
+        }
+
+        Ok(Input {
+            snapshot,
+            statements,
+        })
+    }
+}
+
+fn expand(input: Input) -> syn::Result<TokenStream2> {
+    let error_span = input
+        .snapshot
+        .as_ref()
+        .map_or_else(Span::call_site, LitStr::span);
+    let (schema, snapshot_path) =
+        load_snapshot(input.snapshot.as_ref()).map_err(|err| syn::Error::new(error_span, err))?;
+
+    let mut generated = quote! {
+        // Recompile when the snapshot changes
+        const _: &[u8] = include_bytes!(#snapshot_path);
+    };
+    for statement in input.statements {
+        let checked = check_statement(&statement.cql.value(), &schema)
+            .map_err(|err| syn::Error::new(statement.cql.span(), err))?;
+        generated.extend(generate(&statement, &checked));
+    }
+
+    Ok(generated)
+}
+
+// Schema
+
+struct Schema {
+    keyspaces: HashMap<String, HashMap<String, TableSchema>>,
+}
+
+struct TableSchema {
+    columns: HashMap<String, CqlType>,
+    partition_key: Vec<String>,
+    clustering_key: Vec<String>,
+    static_columns: Vec<String>,
+}
+
+impl TableSchema {
+    fn is_primary_key(&self, column: &str) -> bool {
+        self.partition_key.iter().any(|name| name == column)
+            || self.clustering_key.iter().any(|name| name == column)
+    }
+
+    // Order of columns returned by SELECT *
+    fn all_columns(&self) -> Vec<String> {
+        let mut regular: Vec<&String> = self
+            .columns
+            .keys()
+            .filter(|name| !self.is_primary_key(name) && !self.static_columns.contains(name))
+            .collect();
+        regular.sort_unstable();
+
+        let mut static_columns: Vec<&String> = self.static_columns.iter().collect();
+        static_columns.sort_unstable();
+
+        self.partition_key
+            .iter()
+            .chain(self.clustering_key.iter())
+            .chain(static_columns)
+            .chain(regular)
+            .cloned()
+            .collect()
+    }
+}
+
+// Finds the snapshot: the path given in the macro, SCYLLA_SCHEMA_SNAPSHOT environment variable
+// or scylla_schema.json, relative to the root of the crate
+fn load_snapshot(path: Option<&LitStr>) -> Result<(Schema, String), String> {
+    let relative_path = path
+        .map(LitStr::value)
+        .or_else(|| std::env::var("SCYLLA_SCHEMA_SNAPSHOT").ok())
+        .unwrap_or_else(|| "scylla_schema.json".to_string());
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+    let full_path = Path::new(&manifest_dir).join(&relative_path);
+
+    let text = std::fs::read_to_string(&full_path).map_err(|err| {
+        format!(
+            "Failed to read schema snapshot {}: {}",
+            full_path.display(),
+            err
+        )
+    })?;
+    let json: serde_json::Value = serde_json::from_str(&text)
+        .map_err(|err| format!("Invalid schema snapshot {}: {}", full_path.display(), err))?;
+    let schema = parse_schema(&json)
+        .map_err(|err| format!("Invalid schema snapshot {}: {}", full_path.display(), err))?;
+
+    Ok((schema, full_path.to_string_lossy().into_owned()))
+}
+
+fn parse_schema(json: &serde_json::Value) -> Result<Schema, String> {
+    let object = |value: &serde_json::Value, what: &str| {
+        value
+            .as_object()
+            .cloned()
+            .ok_or_else(|| format!("{} should be an object", what))
+    };
+    let strings = |value: Option<&serde_json::Value>, what: &str| -> Result<Vec<String>, String> {
+        match value {
+            None => Ok(Vec::new()),
+            Some(value) => value
+                .as_array()
+                .and_then(|array| {
+                    array
+                        .iter()
+                        .map(|name| name.as_str().map(str::to_string))
+                        .collect()
+                })
+                .ok_or_else(|| format!("{} should be an array of strings", what)),
+        }
+    };
+
+    let mut keyspaces = HashMap::new();
+    for (keyspace_name, keyspace) in object(&json["keyspaces"], "keyspaces")? {
+        let mut tables = HashMap::new();
+        for (table_name, table) in object(&keyspace["tables"], "tables")? {
+            let columns = object(&table["columns"], "columns")?
+                .into_iter()
+                .map(|(name, typ)| {
+                    typ.as_str()
+                        .map(|typ| (name.clone(), CqlType::parse(typ)))
+                        .ok_or_else(|| format!("Type of column {} should be a string", name))
+                })
+                .collect::<Result<_, String>>()?;
+
+            tables.insert(
+                table_name,
+                TableSchema {
+                    columns,
+                    partition_key: strings(table.get("partition_key"), "partition_key")?,
+                    clustering_key: strings(table.get("clustering_key"), "clustering_key")?,
+                    static_columns: strings(table.get("static_columns"), "static_columns")?,
+                },
+            );
+        }
+        keyspaces.insert(keyspace_name, tables);
+    }
+
+    Ok(Schema { keyspaces })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum CqlType {
+    Native(String),
+    List(Box<CqlType>),
+    Set(Box<CqlType>),
+    Map(Box<CqlType>, Box<CqlType>),
+    Tuple(Vec<CqlType>),
+    // User defined types and types without a Rust counterpart
+    Other(String),
+}
+
+const NATIVE_TYPES: &[&str] = &[
+    "ascii",
+    "bigint",
+    "blob",
+    "boolean",
+    "counter",
+    "date",
+    "decimal",
+    "double",
+    "float",
+    "inet",
+    "int",
+    "smallint",
+    "text",
+    "time",
+    "timestamp",
+    "timeuuid",
+    "tinyint",
+    "uuid",
+    "varint",
+];
+
+impl CqlType {
+    // Parses types in the format used by system_schema, e.g. "frozen<map<text, list<int>>>"
+    fn parse(typ: &str) -> CqlType {
+        let typ = typ.trim();
+        let (name, args) = match typ.find('<') {
+            Some(start) if typ.ends_with('>') => (
+                &typ[..start],
+                split_type_args(&typ[start + 1..typ.len() - 1]),
+            ),
+            _ => (typ, Vec::new()),
+        };
+        let mut args = args.into_iter().map(CqlType::parse);
+
+        match (name.trim().to_lowercase().as_str(), args.len()) {
+            ("frozen", 1) => args.next().unwrap(),
+            ("list", 1) => CqlType::List(Box::new(args.next().unwrap())),
+            ("set", 1) => CqlType::Set(Box::new(args.next().unwrap())),
+            ("map", 2) => CqlType::Map(
+                Box::new(args.next().unwrap()),
+                Box::new(args.next().unwrap()),
+            ),
+            ("tuple", _) => CqlType::Tuple(args.collect()),
+            ("varchar", 0) => CqlType::Native("text".to_string()),
+            (name, 0) if NATIVE_TYPES.contains(&name) => CqlType::Native(name.to_string()),
+            _ => CqlType::Other(typ.to_string()),
+        }
+    }
+
+    // Marker type from scylla::statement::checked::types, None if bound values can't be checked
+    fn marker(&self) -> Option<TokenStream2> {
+        let types = quote!(scylla::statement::checked::types);
+        Some(match self {
+            CqlType::Native(name) => {
+                let marker = match name.as_str() {
+                    "ascii" => "Ascii",
+                    "bigint" => "BigInt",
+                    "blob" => "Blob",
+                    "boolean" => "Boolean",
+                    "counter" => "Counter",
+                    "date" => "Date",
+                    "decimal" => "Decimal",
+                    "double" => "Double",
+                    "float" => "Float",
+                    "inet" => "Inet",
+                    "int" => "Int",
+                    "smallint" => "SmallInt",
+                    "text" => "Text",
+                    "time" => "Time",
+                    "timestamp" => "Timestamp",
+                    "timeuuid" => "Timeuuid",
+                    "tinyint" => "TinyInt",
+                    "uuid" => "Uuid",
+                    "varint" => "Varint",
+                    _ => return None,
+                };
+                let marker = format_ident!("{}", marker);
+                quote!(#types::#marker)
+            }
+            CqlType::List(elem) => {
+                let elem = elem.marker()?;
+                quote!(#types::List<#elem>)
+            }
+            CqlType::Set(elem) => {
+                let elem = elem.marker()?;
+                quote!(#types::Set<#elem>)
+            }
+            CqlType::Map(key, value) => {
+                let key = key.marker()?;
+                let value = value.marker()?;
+                quote!(#types::Map<#key, #value>)
+            }
+            CqlType::Tuple(elems) if !elems.is_empty() && elems.len() <= 8 => {
+                let elems = elems
+                    .iter()
+                    .map(CqlType::marker)
+                    .collect::<Option<Vec<_>>>()?;
+                quote!((#(#elems,)*))
+            }
+            CqlType::Tuple(_) | CqlType::Other(_) => return None,
+        })
+    }
+
+    // Type of the column in a generated row struct
+    fn rust_type(&self) -> TokenStream2 {
+        let private = quote!(scylla::statement::checked::__private);
+        let cql_value = quote!(#private::CqlValue);
+        match self {
+            CqlType::Native(name) => match name.as_str() {
+                "ascii" | "text" => quote!(String),
+                "bigint" => quote!(i64),
+                "blob" => quote!(Vec<u8>),
+                "boolean" => quote!(bool),
+                "counter" => quote!(#private::Counter),
+                "date" => quote!(#private::NaiveDate),
+                "decimal" => quote!(#private::BigDecimal),
+                "double" => quote!(f64),
+                "float" => quote!(f32),
+                "inet" => quote!(std::net::IpAddr),
+                "int" => quote!(i32),
+                "smallint" => quote!(i16),
+                "time" | "timestamp" => quote!(#private::Duration),
+                "timeuuid" | "uuid" => quote!(#private::Uuid),
+                "tinyint" => quote!(i8),
+                "varint" => quote!(#private::BigInt),
+                _ => cql_value,
+            },
+            CqlType::List(elem) | CqlType::Set(elem) => {
+                let elem = elem.rust_type();
+                quote!(Vec<#elem>)
+            }
+            CqlType::Map(key, value) if key.is_hashable() => {
+                let key = key.rust_type();
+                let value = value.rust_type();
+                quote!(std::collections::HashMap<#key, #value>)
+            }
+            CqlType::Tuple(elems) if !elems.is_empty() && elems.len() <= 14 => {
+                let elems = elems.iter().map(CqlType::rust_type);
+                quote!((#(#elems,)*))
+            }
+            CqlType::Map(..) | CqlType::Tuple(_) | CqlType::Other(_) => cql_value,
+        }
+    }
+
+    fn is_hashable(&self) -> bool {
+        match self {
+            CqlType::Native(name) => !matches!(name.as_str(), "float" | "double" | "decimal"),
+            CqlType::List(elem) | CqlType::Set(elem) => elem.is_hashable(),
+            CqlType::Tuple(elems) => elems.len() <= 14 && elems.iter().all(CqlType::is_hashable),
+            CqlType::Map(..) | CqlType::Other(_) => false,
+        }
+    }
+}
+
+// Splits "a, map<b, c>" into ["a", "map<b, c>"]
+fn split_type_args(args: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in args.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ',' if depth == 0 => {
+                result.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !args.trim().is_empty() {
+        result.push(&args[start..]);
+    }
+    result
+}
+
+// Statement analysis
+
+struct CheckedStatement {
+    // Name of each bound value and the CQL type it's bound to
+    binds: Vec<(String, CqlType)>,
+    // Fields of the row struct and their types, only for SELECT
+    row: Option<Vec<(String, TokenStream2)>>,
+}
+
+// What a bind marker is bound to, resolved once the table is known
+enum BindTarget {
+    Column(String),
+    InList(String),
+    Contains(String),
+    ContainsKey(String),
+    Index(String),
+    IndexedValue(String),
+    Int(&'static str),
+    BigInt(&'static str),
+}
+
+enum Selector {
+    All,
+    Columns(Vec<(String, Option<String>)>),
+}
+
+fn check_statement(cql: &str, schema: &Schema) -> Result<CheckedStatement, String> {
+    let mut parser = Parser {
+        tokens: tokenize(cql)?,
+        pos: 0,
+        binds: Vec::new(),
+        columns: Vec::new(),
+    };
+    let (table_name, selector) = parser.statement()?;
+    let table = find_table(schema, table_name)?;
+
+    for column in &parser.columns {
+        if !table.columns.contains_key(column) {
+            return Err(format!("Unknown column {}", column));
+        }
+    }
+
+    let binds = parser
+        .binds
+        .iter()
+        .map(|target| resolve_bind(target, table))
+        .collect::<Result<_, _>>()?;
+
+    let row = selector.map(|selector| {
+        let selected = match selector {
+            Selector::All => table
+                .all_columns()
+                .into_iter()
+                .map(|column| (column, None))
+                .collect(),
+            Selector::Columns(columns) => columns,
+        };
+
+        selected
+            .into_iter()
+            .map(|(column, alias)| {
+                let typ = match column.as_str() {
+                    // COUNT(*) is represented as a column without a name
+                    "" => quote!(i64),
+                    _ if table.is_primary_key(&column) => table.columns[&column].rust_type(),
+                    _ => {
+                        let typ = table.columns[&column].rust_type();
+                        quote!(Option<#typ>)
+                    }
+                };
+                (alias.unwrap_or(column), typ)
+            })
+            .collect()
+    });
+
+    Ok(CheckedStatement { binds, row })
+}
+
+fn find_table(
+    schema: &Schema,
+    (keyspace, table): (Option<String>, String),
+) -> Result<&TableSchema, String> {
+    let tables = match keyspace {
+        Some(keyspace) => schema
+            .keyspaces
+            .get(&keyspace)
+            .ok_or_else(|| format!("Keyspace {} is not in the schema snapshot", keyspace))?,
+        None if schema.keyspaces.len() == 1 => schema.keyspaces.values().next().unwrap(),
+        None => {
+            return Err(format!(
+                "Table {} has to be qualified with a keyspace name, the schema snapshot contains many keyspaces",
+                table
+            ))
+        }
+    };
+
+    tables
+        .get(&table)
+        .ok_or_else(|| format!("Table {} is not in the schema snapshot", table))
+}
+
+fn resolve_bind(target: &BindTarget, table: &TableSchema) -> Result<(String, CqlType), String> {
+    let column_type = |column: &str| table.columns[column].clone();
+    let not_collection = |column: &str, what: &str| {
+        format!("{} requires a collection column, {} isn't", what, column)
+    };
+
+    Ok(match target {
+        BindTarget::Column(column) => (column.clone(), column_type(column)),
+        BindTarget::InList(column) => {
+            (column.clone(), CqlType::List(Box::new(column_type(column))))
+        }
+        BindTarget::Contains(column) => match column_type(column) {
+            CqlType::List(elem) | CqlType::Set(elem) | CqlType::Map(_, elem) => {
+                (column.clone(), *elem)
+            }
+            _ => return Err(not_collection(column, "CONTAINS")),
+        },
+        BindTarget::ContainsKey(column) => match column_type(column) {
+            CqlType::Map(key, _) => (format!("{}_key", column), *key),
+            _ => {
+                return Err(format!(
+                    "CONTAINS KEY requires a map column, {} isn't",
+                    column
+                ))
+            }
+        },
+        BindTarget::Index(column) => match column_type(column) {
+            CqlType::Map(key, _) => (format!("{}_key", column), *key),
+            CqlType::List(_) => (
+                format!("{}_index", column),
+                CqlType::Native("int".to_string()),
+            ),
+            _ => {
+                return Err(format!(
+                    "{} can't be indexed, it isn't a list or a map",
+                    column
+                ))
+            }
+        },
+        BindTarget::IndexedValue(column) => match column_type(column) {
+            CqlType::Map(_, elem) | CqlType::List(elem) => (column.clone(), *elem),
+            _ => {
+                return Err(format!(
+                    "{} can't be indexed, it isn't a list or a map",
+                    column
+                ))
+            }
+        },
+        BindTarget::Int(name) => (name.to_string(), CqlType::Native("int".to_string())),
+        BindTarget::BigInt(name) => (name.to_string(), CqlType::Native("bigint".to_string())),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CqlToken {
+    // Unquoted identifiers and keywords, lowercased
+    Ident(String),
+    QuotedIdent(String),
+    Literal,
+    BindMarker,
+    NamedBindMarker,
+    Symbol(String),
+}
+
+fn tokenize(cql: &str) -> Result<Vec<CqlToken>, String> {
+    let chars: Vec<char> = cql.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    // Returns the index after the closing `end`, which can be doubled to be escaped
+    let skip_quoted = |mut i: usize, end: char| -> Result<(usize, String), String> {
+        let mut content = String::new();
+        loop {
+            match chars.get(i) {
+                None => return Err("Unterminated quoted text".to_string()),
+                Some(&c) if c == end && chars.get(i + 1) == Some(&end) => {
+                    content.push(end);
+                    i += 2;
+                }
+                Some(&c) if c == end => return Ok((i + 1, content)),
+                Some(&c) => {
+                    content.push(c);
+                    i += 1;
+                }
+            }
+        }
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            _ if c.is_whitespace() => i += 1,
+            '-' | '/' if next == Some(c) => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '/' if next == Some('*') => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    i += 1;
+                }
+                i += 2;
+            }
+            '$' if next == Some('$') => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '$' && chars.get(i + 1) == Some(&'$')) {
+                    i += 1;
+                }
+                i += 2;
+                tokens.push(CqlToken::Literal);
+            }
+            '\'' => {
+                i = skip_quoted(i + 1, '\'')?.0;
+                tokens.push(CqlToken::Literal);
+            }
+            '"' => {
+                let (end, name) = skip_quoted(i + 1, '"')?;
+                i = end;
+                tokens.push(CqlToken::QuotedIdent(name));
+            }
+            '?' => {
+                i += 1;
+                tokens.push(CqlToken::BindMarker);
+            }
+            ':' if next.is_some_and(|next| next.is_alphabetic() || next == '_') => {
+                i += 1;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(CqlToken::NamedBindMarker);
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let ident: String = chars[start..i].iter().collect();
+                tokens.push(CqlToken::Ident(ident.to_lowercase()));
+            }
+            // Numbers, blobs and uuids
+            _ if c.is_ascii_digit() || (c == '-' && next.is_some_and(|n| n.is_ascii_digit())) => {
+                i += 1;
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || chars[i] == '.' || chars[i] == '-')
+                {
+                    i += 1;
+                }
+                tokens.push(CqlToken::Literal);
+            }
+            '<' | '>' | '!' if next == Some('=') => {
+                tokens.push(CqlToken::Symbol(format!("{}=", c)));
+                i += 2;
+            }
+            _ => {
+                tokens.push(CqlToken::Symbol(c.to_string()));
+                i += 1;
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<CqlToken>,
+    pos: usize,
+    binds: Vec<BindTarget>,
+    // All referenced columns, checked after the table is known
+    columns: Vec<String>,
+}
+
+type TableName = (Option<String>, String);
+
+impl Parser {
+    fn peek(&self) -> Option<&CqlToken> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&CqlToken> {
+        self.tokens.get(self.pos + offset)
+    }
+
+    fn is_keyword_at(&self, offset: usize, keyword: &str) -> bool {
+        matches!(self.peek_at(offset), Some(CqlToken::Ident(ident)) if ident == keyword)
+    }
+
+    fn is_symbol_at(&self, offset: usize, symbol: &str) -> bool {
+        matches!(self.peek_at(offset), Some(CqlToken::Symbol(s)) if s == symbol)
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword_at(0, keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let found = self.is_symbol_at(0, symbol);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&keyword.to_uppercase()))
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), String> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.unexpected(symbol))
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> String {
+        match self.peek() {
+            Some(token) => format!("Expected {}, found {:?}", expected, token),
+            None => format!("Expected {}, found the end of the statement", expected),
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        match self.peek().cloned() {
+            Some(CqlToken::Ident(name)) | Some(CqlToken::QuotedIdent(name)) => {
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.unexpected("an identifier")),
+        }
+    }
+
+    fn column(&mut self) -> Result<String, String> {
+        let column = self.identifier()?;
+        self.columns.push(column.clone());
+        Ok(column)
+    }
+
+    fn table_name(&mut self) -> Result<TableName, String> {
+        let name = self.identifier()?;
+        if self.eat_symbol(".") {
+            Ok((Some(name), self.identifier()?))
+        } else {
+            Ok((None, name))
+        }
+    }
+
+    // Parses the statement, returns the table and the selected columns for SELECT
+    fn statement(&mut self) -> Result<(TableName, Option<Selector>), String> {
+        let result = if self.eat_keyword("select") {
+            self.select()
+                .map(|(table, selector)| (table, Some(selector)))?
+        } else if self.eat_keyword("insert") {
+            (self.insert()?, None)
+        } else if self.eat_keyword("update") {
+            (self.update()?, None)
+        } else if self.eat_keyword("delete") {
+            (self.delete()?, None)
+        } else {
+            return Err(
+                "Only SELECT, INSERT, UPDATE and DELETE statements can be checked".to_string(),
+            );
+        };
+
+        self.eat_symbol(";");
+        if self.peek().is_some() {
+            return Err(self.unexpected("the end of the statement"));
+        }
+        Ok(result)
+    }
+
+    fn select(&mut self) -> Result<(TableName, Selector), String> {
+        if self.is_keyword_at(0, "json") {
+            return Err("SELECT JSON isn't supported".to_string());
+        }
+        self.eat_keyword("distinct");
+
+        let selector = if self.eat_symbol("*") {
+            Selector::All
+        } else {
+            let mut columns = Vec::new();
+            loop {
+                let column = if self.is_keyword_at(0, "count") && self.is_symbol_at(1, "(") {
+                    self.pos += 2;
+                    if !self.eat_symbol("*") && self.peek() == Some(&CqlToken::Literal) {
+                        self.pos += 1;
+                    }
+                    self.expect_symbol(")")?;
+                    String::new()
+                } else {
+                    if self.is_symbol_at(1, "(") {
+                        return Err(
+                            "Only columns and COUNT(*) can be selected in checked statements"
+                                .to_string(),
+                        );
+                    }
+                    self.column()?
+                };
+
+                let alias = if self.eat_keyword("as") {
+                    Some(self.identifier()?)
+                } else if column.is_empty() {
+                    Some("count".to_string())
+                } else {
+                    None
+                };
+                columns.push((column, alias));
+
+                if !self.eat_symbol(",") {
+                    break;
+                }
+            }
+            Selector::Columns(columns)
+        };
+
+        self.expect_keyword("from")?;
+        let table = self.table_name()?;
+
+        if self.eat_keyword("where") {
+            self.relations()?;
+        }
+        if self.eat_keyword("group") {
+            self.expect_keyword("by")?;
+            self.column()?;
+            while self.eat_symbol(",") {
+                self.column()?;
+            }
+        }
+        if self.eat_keyword("order") {
+            self.expect_keyword("by")?;
+            loop {
+                self.column()?;
+                let _ = self.eat_keyword("asc") || self.eat_keyword("desc");
+                if !self.eat_symbol(",") {
+                    break;
+                }
+            }
+        }
+        if self.eat_keyword("per") {
+            self.expect_keyword("partition")?;
+            self.expect_keyword("limit")?;
+            self.term(BindTarget::Int("per_partition_limit"))?;
+        }
+        if self.eat_keyword("limit") {
+            self.term(BindTarget::Int("limit"))?;
+        }
+        if self.eat_keyword("allow") {
+            self.expect_keyword("filtering")?;
+        }
+        if self.eat_keyword("bypass") {
+            self.expect_keyword("cache")?;
+        }
+        self.using()?;
+
+        Ok((table, selector))
+    }
+
+    fn insert(&mut self) -> Result<TableName, String> {
+        self.expect_keyword("into")?;
+        let table = self.table_name()?;
+        if self.is_keyword_at(0, "json") {
+            return Err("INSERT JSON isn't supported".to_string());
+        }
+
+        self.expect_symbol("(")?;
+        let mut columns = vec![self.column()?];
+        while self.eat_symbol(",") {
+            columns.push(self.column()?);
+        }
+        self.expect_symbol(")")?;
+
+        self.expect_keyword("values")?;
+        self.expect_symbol("(")?;
+        for (i, column) in columns.iter().enumerate() {
+            if i > 0 {
+                self.expect_symbol(",")?;
+            }
+            self.term(BindTarget::Column(column.clone()))?;
+        }
+        self.expect_symbol(")")?;
+
+        if self.eat_keyword("if") {
+            self.expect_keyword("not")?;
+            self.expect_keyword("exists")?;
+        }
+        self.using()?;
+
+        Ok(table)
+    }
+
+    fn update(&mut self) -> Result<TableName, String> {
+        let table = self.table_name()?;
+        self.using()?;
+
+        self.expect_keyword("set")?;
+        loop {
+            self.assignment()?;
+            if !self.eat_symbol(",") {
+                break;
+            }
+        }
+
+        self.expect_keyword("where")?;
+        self.relations()?;
+        self.conditions()?;
+
+        Ok(table)
+    }
+
+    fn delete(&mut self) -> Result<TableName, String> {
+        if !self.is_keyword_at(0, "from") {
+            loop {
+                let column = self.column()?;
+                if self.eat_symbol("[") {
+                    self.term(BindTarget::Index(column))?;
+                    self.expect_symbol("]")?;
+                }
+                if !self.eat_symbol(",") {
+                    break;
+                }
+            }
+        }
+
+        self.expect_keyword("from")?;
+        let table = self.table_name()?;
+        self.using()?;
+
+        self.expect_keyword("where")?;
+        self.relations()?;
+        self.conditions()?;
+
+        Ok(table)
+    }
+
+    // USING TTL ... AND TIMESTAMP ...
+    fn using(&mut self) -> Result<(), String> {
+        if !self.eat_keyword("using") {
+            return Ok(());
+        }
+        loop {
+            if self.eat_keyword("ttl") {
+                self.term(BindTarget::Int("ttl"))?;
+            } else if self.eat_keyword("timestamp") {
+                self.term(BindTarget::BigInt("timestamp"))?;
+            } else if self.eat_keyword("timeout") {
+                self.literal()?;
+            } else {
+                return Err(self.unexpected("TTL, TIMESTAMP or TIMEOUT"));
+            }
+            if !self.eat_keyword("and") {
+                return Ok(());
+            }
+        }
+    }
+
+    // IF EXISTS or IF conditions
+    fn conditions(&mut self) -> Result<(), String> {
+        if !self.eat_keyword("if") {
+            return Ok(());
+        }
+        if self.eat_keyword("exists") {
+            return Ok(());
+        }
+        self.relations()
+    }
+
+    fn assignment(&mut self) -> Result<(), String> {
+        let column = self.column()?;
+
+        if self.eat_symbol("[") {
+            self.term(BindTarget::Index(column.clone()))?;
+            self.expect_symbol("]")?;
+            self.expect_symbol("=")?;
+            return self.term(BindTarget::IndexedValue(column));
+        }
+
+        self.expect_symbol("=")?;
+        let is_column = matches!(
+            self.peek(),
+            Some(CqlToken::Ident(_)) | Some(CqlToken::QuotedIdent(_))
+        );
+        if is_column && (self.is_symbol_at(1, "+") || self.is_symbol_at(1, "-")) {
+            // column = column + value
+            self.column()?;
+            self.pos += 1;
+            self.term(BindTarget::Column(column))
+        } else {
+            // column = value or column = value + column
+            self.term(BindTarget::Column(column))?;
+            if self.eat_symbol("+") {
+                self.column()?;
+            }
+            Ok(())
+        }
+    }
+
+    fn relations(&mut self) -> Result<(), String> {
+        loop {
+            self.relation()?;
+            if !self.eat_keyword("and") {
+                return Ok(());
+            }
+        }
+    }
+
+    fn relation(&mut self) -> Result<(), String> {
+        if self.is_symbol_at(0, "(") || self.is_keyword_at(0, "token") {
+            return Err(
+                "Tuple and TOKEN relations aren't supported in checked statements".to_string(),
+            );
+        }
+        let column = self.column()?;
+
+        if self.eat_symbol("[") {
+            self.term(BindTarget::Index(column.clone()))?;
+            self.expect_symbol("]")?;
+            self.operator()?;
+            return self.term(BindTarget::IndexedValue(column));
+        }
+
+        if self.eat_keyword("in") {
+            if self.eat_symbol("(") {
+                loop {
+                    self.term(BindTarget::Column(column.clone()))?;
+                    if !self.eat_symbol(",") {
+                        break;
+                    }
+                }
+                return self.expect_symbol(")");
+            }
+            return self.term(BindTarget::InList(column));
+        }
+
+        if self.eat_keyword("contains") {
+            if self.eat_keyword("key") {
+                return self.term(BindTarget::ContainsKey(column));
+            }
+            return self.term(BindTarget::Contains(column));
+        }
+
+        self.operator()?;
+        self.term(BindTarget::Column(column))
+    }
+
+    fn operator(&mut self) -> Result<(), String> {
+        for operator in &["=", "<", ">", "<=", ">=", "!="] {
+            if self.eat_symbol(operator) {
+                return Ok(());
+            }
+        }
+        if self.eat_keyword("like") {
+            return Ok(());
+        }
+        Err(self.unexpected("an operator"))
+    }
+
+    // A bind marker or a literal
+    fn term(&mut self, target: BindTarget) -> Result<(), String> {
+        match self.peek() {
+            Some(CqlToken::BindMarker) => {
+                self.pos += 1;
+                self.binds.push(target);
+                Ok(())
+            }
+            _ => self.literal(),
+        }
+    }
+
+    // Skips a literal, a collection literal or a function call
+    fn literal(&mut self) -> Result<(), String> {
+        match self.peek().cloned() {
+            Some(CqlToken::Literal) => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(CqlToken::Ident(_)) => {
+                self.pos += 1;
+                if self.is_symbol_at(0, "(") {
+                    self.skip_group()?;
+                }
+                Ok(())
+            }
+            Some(CqlToken::Symbol(s)) if s == "(" || s == "[" || s == "{" => self.skip_group(),
+            Some(CqlToken::NamedBindMarker) => {
+                Err("Named bind markers aren't supported in checked statements".to_string())
+            }
+            _ => Err(self.unexpected("a value or a bind marker")),
+        }
+    }
+
+    fn skip_group(&mut self) -> Result<(), String> {
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                Some(CqlToken::Symbol(s)) if s == "(" || s == "[" || s == "{" => depth += 1,
+                Some(CqlToken::Symbol(s)) if s == ")" || s == "]" || s == "}" => depth -= 1,
+                Some(CqlToken::BindMarker) | Some(CqlToken::NamedBindMarker) => {
+                    return Err(
+                        "Bind markers inside collection literals and function calls aren't supported in checked statements"
+                            .to_string(),
+                    )
+                }
+                Some(_) => {}
+                None => return Err("Unclosed bracket".to_string()),
+            }
+            self.pos += 1;
+            if depth == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// Code generation
+
+fn generate(statement: &StatementDef, checked: &CheckedStatement) -> TokenStream2 {
+    let StatementDef {
+        attrs,
+        vis,
+        name,
+        cql,
+    } = statement;
+
+    let param_names = unique_idents(checked.binds.iter().map(|(name, _)| name.as_str()));
+    let generics: Vec<Ident> = (0..checked.binds.len())
+        .map(|i| format_ident!("V{}", i))
+        .collect();
+    let bounds = checked.binds.iter().map(|(_, typ)| match typ.marker() {
+        Some(marker) => quote!(scylla::statement::checked::Bind<#marker>),
+        None => quote!(scylla::frame::value::Value),
+    });
+    let values_count = checked.binds.len();
+
+    let mut generated = quote! {
+        #(#attrs)*
+        #vis struct #name;
+
+        impl #name {
+            pub const STATEMENT: &'static str = #cql;
+
+            /// Serializes values bound to the statement, in the order of bind markers
+            #[allow(clippy::too_many_arguments)]
+            pub fn values<#(#generics: #bounds),*>(#(#param_names: #generics),*)
+            -> Result<scylla::frame::value::SerializedValues, scylla::frame::value::SerializeValuesError> {
+                let mut values = scylla::frame::value::SerializedValues::with_capacity_for_values(#values_count);
+                #(values.add_value(&#param_names)?;)*
+                Ok(values)
+            }
+        }
+    };
+
+    if let Some(row) = &checked.row {
+        let row_name = format_ident!("{}Row", name);
+        let field_names = unique_idents(row.iter().map(|(name, _)| name.as_str()));
+        let field_types = row.iter().map(|(_, typ)| typ);
+        let doc = format!("Row returned by [`{}`]", name);
+
+        generated.extend(quote! {
+            #[doc = #doc]
+            #[derive(Debug)]
+            #vis struct #row_name {
+                #(pub #field_names: #field_types,)*
+            }
+
+            impl scylla::cql_to_rust::FromRow for #row_name {
+                fn from_row(row: scylla::frame::response::result::Row)
+                -> Result<Self, scylla::cql_to_rust::FromRowError> {
+                    use scylla::cql_to_rust::{column_from_cql, FromRowError};
+
+                    let mut vals_iter = row.columns.into_iter().enumerate();
+
+                    Ok(#row_name {
+                        #(#field_names: {
+                            let (index, value) = vals_iter
+                                .next()
+                                .ok_or(FromRowError::RowTooShort)?;
+                            column_from_cql(index, value)?
+                        },)*
+                    })
+                }
+            }
+        });
+    }
+
+    generated
+}
+
+// Converts names to snake case identifiers, adding suffixes to repeated ones
+fn unique_idents<'a>(names: impl Iterator<Item = &'a str>) -> Vec<Ident> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    names
+        .map(|name| {
+            let mut ident: String = name
+                .chars()
+                .map(|c| if c.is_alphanumeric() { c } else { '_' })
+                .collect::<String>()
+                .to_lowercase();
+            if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+                ident.insert(0, '_');
+            }
+
+            let count = seen.entry(ident.clone()).or_insert(0);
+            *count += 1;
+            if *count > 1 {
+                ident = format!("{}_{}", ident, count);
+            }
+
+            match syn::parse_str::<Ident>(&ident) {
+                Ok(ident) => ident,
+                // Keywords
+                Err(_) if !matches!(ident.as_str(), "self" | "super" | "crate" | "_") => {
+                    Ident::new_raw(&ident, Span::call_site())
+                }
+                Err(_) => format_ident!("{}_", ident),
+            }
+        })
+        .collect()
+}
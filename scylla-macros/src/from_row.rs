@@ -8,28 +8,28 @@ pub fn from_row_derive(tokens_input: TokenStream) -> TokenStream {
     let (struct_name, struct_fields) =
         crate::parser::parse_struct_with_named_fields(tokens_input, "FromRow");
 
-    // Generates tokens for field_name: field_type::from_cql(vals_iter.next().ok_or(...)?), ...
+    // Generates tokens for field_name: column_from_cql::<field_type>(vals_iter.next().ok_or(...)?), ...
     let set_fields_code = struct_fields.named.iter().map(|field| {
         let field_name = &field.ident;
         let field_type = &field.ty;
 
         quote_spanned! {field.span() =>
-            #field_name: <#field_type as FromCqlVal<Option<CqlValue>>>::from_cql(
-                vals_iter
-                .next()
-                .ok_or(FromRowError::RowTooShort) ?
-            ) ?,
+            #field_name: {
+                let (index, value) = vals_iter
+                    .next()
+                    .ok_or(FromRowError::RowTooShort)?;
+                column_from_cql::<#field_type>(index, value)?
+            },
         }
     });
 
     let generated = quote! {
-        impl FromRow for #struct_name {
+        impl scylla::cql_to_rust::FromRow for #struct_name {
             fn from_row(row: scylla::frame::response::result::Row)
             -> Result<Self, scylla::cql_to_rust::FromRowError> {
-                use scylla::frame::response::result::CqlValue;
-                use scylla::cql_to_rust::{FromCqlVal, FromRow, FromRowError};
+                use scylla::cql_to_rust::{column_from_cql, FromRowError};
 
-                let mut vals_iter = row.columns.into_iter();
+                let mut vals_iter = row.columns.into_iter().enumerate();
 
                 Ok(#struct_name {
                     #(#set_fields_code)*
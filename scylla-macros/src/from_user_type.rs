@@ -22,7 +22,7 @@ pub fn from_user_type_derive(tokens_input: TokenStream) -> TokenStream {
     });
 
     let generated = quote! {
-        impl FromCqlVal<scylla::frame::response::result::CqlValue> for #struct_name {
+        impl scylla::cql_to_rust::FromCqlVal<scylla::frame::response::result::CqlValue> for #struct_name {
             fn from_cql(cql_val: scylla::frame::response::result::CqlValue)
             -> Result<Self, scylla::cql_to_rust::FromCqlValError> {
                 use std::collections::BTreeMap;
@@ -31,7 +31,7 @@ pub fn from_user_type_derive(tokens_input: TokenStream) -> TokenStream {
 
                 // Interpret CqlValue as CQlValue::UserDefinedType
                 let mut fields: BTreeMap<String, Option<CqlValue>> = match cql_val {
-                    CqlValue::UserDefinedType{fields, ..} => fields,
+                    CqlValue::UserDefinedType{fields, ..} => fields.into_iter().collect(),
                     _ => return Err(FromCqlValError::BadCqlType),
                 };
 
@@ -1,9 +1,13 @@
 use proc_macro::TokenStream;
 
+#[cfg(feature = "checked")]
+mod checked_statement;
 mod from_row;
 mod from_user_type;
 mod into_user_type;
 mod parser;
+mod table;
+mod value_list;
 
 /// #[derive(FromRow)] derives FromRow for struct
 /// Works only on simple structs without generics etc
@@ -25,3 +29,29 @@ pub fn from_user_type_derive(tokens_input: TokenStream) -> TokenStream {
 pub fn into_user_type_derive(tokens_input: TokenStream) -> TokenStream {
     into_user_type::into_user_type_derive(tokens_input)
 }
+
+/// #[derive(ValueList)] allows to pass a struct as a list of bound values in queries
+/// Fields are bound in declaration order, or by their names with #[value_list(named)]
+/// Works only on simple structs without generics etc
+#[proc_macro_derive(ValueList, attributes(value_list))]
+pub fn value_list_derive(tokens_input: TokenStream) -> TokenStream {
+    value_list::value_list_derive(tokens_input)
+}
+
+/// #[derive(Table)] maps a struct to a table, allowing to use it with `TableMapper`
+/// Key columns are marked with #[partition_key] and #[clustering_key],
+/// the table can be set with #[table(keyspace = "ks", name = "tab")]
+/// Works only on simple structs without generics etc
+#[proc_macro_derive(Table, attributes(table, partition_key, clustering_key))]
+pub fn table_derive(tokens_input: TokenStream) -> TokenStream {
+    table::table_derive(tokens_input)
+}
+
+/// checked_statement! validates CQL statements against a schema snapshot at compile time
+/// and generates structs with their typed values and rows,
+/// see `scylla::statement::checked` for details
+#[cfg(feature = "checked")]
+#[proc_macro]
+pub fn checked_statement(tokens_input: TokenStream) -> TokenStream {
+    checked_statement::checked_statement(tokens_input)
+}
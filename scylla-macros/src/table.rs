@@ -0,0 +1,130 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{Attribute, Lit, Meta, NestedMeta};
+
+/// #[derive(Table)] implements Table for a struct whose fields are columns of a table
+/// Works only on simple structs without generics etc
+pub fn table_derive(tokens_input: TokenStream) -> TokenStream {
+    let input = syn::parse::<syn::DeriveInput>(tokens_input.clone()).expect("No DeriveInput");
+    let (keyspace, table_name) = parse_table_attribute(&input.attrs, &input.ident.to_string());
+
+    let (struct_name, struct_fields) =
+        crate::parser::parse_struct_with_named_fields(tokens_input, "Table");
+
+    let mut columns: Vec<String> = Vec::new();
+    let mut add_values_code = Vec::new();
+    let mut partition_key = Vec::new();
+    let mut clustering_key = Vec::new();
+
+    for field in struct_fields.named.iter() {
+        let field_name = field.ident.as_ref().unwrap();
+        let field_type = &field.ty;
+        let column = field_name.to_string();
+
+        if has_attribute(&field.attrs, "partition_key") {
+            partition_key.push((column.clone(), field_type));
+        } else if has_attribute(&field.attrs, "clustering_key") {
+            clustering_key.push((column.clone(), field_type));
+        }
+
+        add_values_code.push(quote! {
+            values.add_value(&self.#field_name)?;
+        });
+        columns.push(column);
+    }
+
+    if partition_key.is_empty() {
+        panic!(
+            "derive(Table) requires at least one field marked with #[partition_key] in {}",
+            struct_name
+        );
+    }
+
+    let primary_key: Vec<&String> = partition_key
+        .iter()
+        .chain(clustering_key.iter())
+        .map(|(column, _)| column)
+        .collect();
+    let primary_key_types = partition_key
+        .iter()
+        .chain(clustering_key.iter())
+        .map(|(_, typ)| typ);
+    let keyspace = match keyspace {
+        Some(keyspace) => quote!(Some(#keyspace)),
+        None => quote!(None),
+    };
+    let columns_num = columns.len();
+
+    let generated = quote! {
+        impl scylla::transport::table_mapper::Table for #struct_name {
+            const KEYSPACE: Option<&'static str> = #keyspace;
+            const NAME: &'static str = #table_name;
+            const COLUMNS: &'static [&'static str] = &[#(#columns),*];
+            const PRIMARY_KEY: &'static [&'static str] = &[#(#primary_key),*];
+
+            type PrimaryKey = (#(#primary_key_types,)*);
+
+            fn serialize_values(&self)
+            -> Result<scylla::frame::value::SerializedValues, scylla::frame::value::SerializeValuesError> {
+                let mut values = scylla::frame::value::SerializedValues::with_capacity_for_values(#columns_num);
+                #(#add_values_code)*
+                Ok(values)
+            }
+        }
+    };
+
+    TokenStream::from(generated)
+}
+
+fn has_attribute(attrs: &[Attribute], name: &str) -> bool {
+    attrs.iter().any(|attr| attr.path.is_ident(name))
+}
+
+/// Parses #[table(keyspace = "ks", name = "tab")], by default the table name
+/// is the struct name in snake case and the keyspace is the one used by the session
+fn parse_table_attribute(attrs: &[Attribute], struct_name: &str) -> (Option<String>, String) {
+    let mut keyspace = None;
+    let mut name = None;
+
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("table")) {
+        let nested = match attr.parse_meta() {
+            Ok(Meta::List(list)) => list.nested,
+            _ => panic!("Expected #[table(keyspace = \"...\", name = \"...\")]"),
+        };
+
+        for meta in nested {
+            let (key, value) = match meta {
+                NestedMeta::Meta(Meta::NameValue(name_value)) => match name_value.lit {
+                    Lit::Str(value) => (name_value.path, value.value()),
+                    _ => panic!("Values in #[table(...)] must be string literals"),
+                },
+                _ => panic!("Expected #[table(keyspace = \"...\", name = \"...\")]"),
+            };
+
+            if key.is_ident("keyspace") {
+                keyspace = Some(value);
+            } else if key.is_ident("name") {
+                name = Some(value);
+            } else {
+                panic!("Unknown key in #[table(...)], expected keyspace or name");
+            }
+        }
+    }
+
+    (keyspace, name.unwrap_or_else(|| to_snake_case(struct_name)))
+}
+
+fn to_snake_case(name: &str) -> String {
+    let mut snake_case = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                snake_case.push('_');
+            }
+            snake_case.extend(c.to_lowercase());
+        } else {
+            snake_case.push(c);
+        }
+    }
+    snake_case
+}
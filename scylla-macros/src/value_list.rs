@@ -0,0 +1,65 @@
+use proc_macro::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{Attribute, Meta, NestedMeta};
+
+/// #[derive(ValueList)] allows to pass a struct as a list of bound values.
+/// Fields are bound in declaration order, or by their names with #[value_list(named)]
+/// Works only on simple structs without generics etc
+pub fn value_list_derive(tokens_input: TokenStream) -> TokenStream {
+    let input = syn::parse::<syn::DeriveInput>(tokens_input.clone()).expect("No DeriveInput");
+    let named = parse_value_list_attribute(&input.attrs);
+
+    let (struct_name, struct_fields) =
+        crate::parser::parse_struct_with_named_fields(tokens_input, "ValueList");
+
+    let add_values_code = struct_fields.named.iter().map(|field| {
+        let field_name = &field.ident;
+
+        if named {
+            let name = field_name.as_ref().unwrap().to_string();
+            quote_spanned! {field.span() =>
+                result.add_named_value(#name, &self.#field_name)?;
+            }
+        } else {
+            quote_spanned! {field.span() =>
+                result.add_value(&self.#field_name)?;
+            }
+        }
+    });
+    let values_len = struct_fields.named.len();
+
+    let generated = quote! {
+        impl scylla::frame::value::ValueList for #struct_name {
+            fn serialized(&self) -> scylla::frame::value::SerializedResult {
+                let mut result = scylla::frame::value::SerializedValues::with_capacity_for_values(#values_len);
+                #(#add_values_code)*
+
+                std::result::Result::Ok(std::borrow::Cow::Owned(result))
+            }
+        }
+    };
+
+    TokenStream::from(generated)
+}
+
+/// Checks for #[value_list(named)], which makes fields bound by their names
+fn parse_value_list_attribute(attrs: &[Attribute]) -> bool {
+    let mut named = false;
+
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("value_list")) {
+        let nested = match attr.parse_meta() {
+            Ok(Meta::List(list)) => list.nested,
+            _ => panic!("Expected #[value_list(named)]"),
+        };
+
+        for meta in nested {
+            match meta {
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("named") => named = true,
+                _ => panic!("Unknown option in #[value_list(...)], expected named"),
+            }
+        }
+    }
+
+    named
+}
@@ -1,7 +1,8 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 
 use bytes::BytesMut;
-use scylla::frame::types;
+use scylla::frame::{compress_append, decompress, types};
+use scylla::transport::Compression;
 
 fn types_benchmark(c: &mut Criterion) {
     let mut buf = BytesMut::with_capacity(64);
@@ -35,5 +36,26 @@ fn types_benchmark(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, types_benchmark);
+fn compression_benchmark(c: &mut Criterion) {
+    // Rows of a typical result, repetitive like real data
+    let body: Vec<u8> = (0..4096u32)
+        .flat_map(|i| format!("user{}@example.com;{};", i % 100, i).into_bytes())
+        .take(16 * 1024)
+        .collect();
+
+    for (name, compression) in [("lz4", Compression::Lz4), ("snappy", Compression::Snappy)] {
+        let mut compressed = Vec::new();
+        c.bench_function(&format!("compress_{}", name), |b| {
+            b.iter(|| {
+                compressed.clear();
+                compress_append(&body, compression, &mut compressed).unwrap();
+            })
+        });
+        c.bench_function(&format!("decompress_{}", name), |b| {
+            b.iter(|| decompress(&compressed, compression).unwrap())
+        });
+    }
+}
+
+criterion_group!(benches, types_benchmark, compression_benchmark);
 criterion_main!(benches);
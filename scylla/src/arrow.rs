@@ -0,0 +1,552 @@
+//! Conversion of query results into [Arrow](https://arrow.apache.org) record batches
+//! Available with the `arrow` feature. The schema is built from column metadata received with each page,
+//! so pages of a [`RowIterator`](crate::transport::iterator::RowIterator) can be passed straight to
+//! analytical engines like DataFusion or Polars.
+//!
+//! CQL types are mapped to Arrow types as follows:
+//! * `ascii`, `text` - `Utf8`
+//! * `boolean` - `Boolean`
+//! * `blob` - `Binary`
+//! * `tinyint`, `smallint`, `int`, `bigint`, `counter` - `Int8`, `Int16`, `Int32`, `Int64`, `Int64`
+//! * `float`, `double` - `Float32`, `Float64`
+//! * `date` - `Date32`
+//! * `time` - `Time64(Nanosecond)`
+//! * `timestamp` - `Timestamp(Millisecond, "UTC")`
+//! * `uuid`, `timeuuid`, `inet`, `decimal`, `varint`, `duration` - `Utf8`, in their usual text form
+//! * `list`, `set` - `List`
+//! * `map` - `Map`
+//! * `tuple`, user defined types - `Struct`
+
+#[cfg(feature = "parquet")]
+pub mod parquet;
+
+use std::sync::Arc;
+
+use arrow_array::{
+    ArrayRef, BinaryArray, BooleanArray, Date32Array, Float32Array, Float64Array, Int16Array,
+    Int32Array, Int64Array, Int8Array, ListArray, MapArray, RecordBatch, StringArray, StructArray,
+    Time64NanosecondArray, TimestampMillisecondArray,
+};
+use arrow_buffer::{NullBuffer, OffsetBuffer};
+use arrow_schema::{ArrowError, DataType, Field, Fields, Schema, TimeUnit};
+use thiserror::Error;
+
+use crate::frame::response::result::{ColumnSpec, ColumnType, CqlValue, Row};
+use crate::transport::iterator::Page;
+
+/// Error returned when rows can't be converted to a [`RecordBatch`]
+#[derive(Error, Debug)]
+pub enum ToArrowError {
+    /// A value doesn't match the type of its column from metadata
+    #[error("Value of type {actual} in column {column} doesn't match the column type {expected}")]
+    TypeMismatch {
+        column: usize,
+        expected: String,
+        actual: &'static str,
+    },
+    /// A row has a different number of columns than the metadata describes
+    #[error("Row {row} has {len} columns, expected {expected}")]
+    BadRowLength {
+        row: usize,
+        len: usize,
+        expected: usize,
+    },
+    /// Arrow rejected the built arrays
+    #[error(transparent)]
+    ArrowError(#[from] ArrowError),
+}
+
+/// Returns the Arrow schema of rows with the given columns, all fields are nullable
+pub fn schema(col_specs: &[ColumnSpec]) -> Schema {
+    Schema::new(
+        col_specs
+            .iter()
+            .map(|spec| Field::new(spec.name(), data_type(spec.typ()), true))
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Converts rows with the given columns into a single [`RecordBatch`]
+pub fn to_record_batch(
+    col_specs: &[ColumnSpec],
+    rows: &[Row],
+) -> Result<RecordBatch, ToArrowError> {
+    for (row_idx, row) in rows.iter().enumerate() {
+        if row.columns.len() != col_specs.len() {
+            return Err(ToArrowError::BadRowLength {
+                row: row_idx,
+                len: row.columns.len(),
+                expected: col_specs.len(),
+            });
+        }
+    }
+
+    let columns = col_specs
+        .iter()
+        .enumerate()
+        .map(|(column, spec)| {
+            let values: Vec<Option<&CqlValue>> = rows
+                .iter()
+                .map(|row| row.columns[column].as_ref())
+                .collect();
+            to_array(column, spec.typ(), &values)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(RecordBatch::try_new(Arc::new(schema(col_specs)), columns)?)
+}
+
+impl Page {
+    /// Converts rows of this page into a [`RecordBatch`], see the [`arrow`](crate::arrow) module
+    pub fn to_record_batch(&self) -> Result<RecordBatch, ToArrowError> {
+        to_record_batch(self.metadata.col_specs(), &self.rows)
+    }
+}
+
+fn data_type(typ: &ColumnType) -> DataType {
+    match typ {
+        ColumnType::Ascii | ColumnType::Text => DataType::Utf8,
+        ColumnType::Boolean => DataType::Boolean,
+        ColumnType::Blob => DataType::Binary,
+        ColumnType::TinyInt => DataType::Int8,
+        ColumnType::SmallInt => DataType::Int16,
+        ColumnType::Int => DataType::Int32,
+        ColumnType::BigInt | ColumnType::Counter => DataType::Int64,
+        ColumnType::Float => DataType::Float32,
+        ColumnType::Double => DataType::Float64,
+        ColumnType::Date => DataType::Date32,
+        ColumnType::Time => DataType::Time64(TimeUnit::Nanosecond),
+        ColumnType::Timestamp => DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+        ColumnType::Uuid
+        | ColumnType::Timeuuid
+        | ColumnType::Inet
+        | ColumnType::Decimal
+        | ColumnType::Varint
+        | ColumnType::Duration => DataType::Utf8,
+        ColumnType::List(elem) | ColumnType::Set(elem) => {
+            DataType::List(Arc::new(list_item_field(elem)))
+        }
+        ColumnType::Map(key, value) => {
+            DataType::Map(Arc::new(map_entries_field(key, value)), false)
+        }
+        ColumnType::Tuple(_) | ColumnType::UserDefinedType { .. } => {
+            DataType::Struct(struct_fields(typ))
+        }
+    }
+}
+
+fn list_item_field(elem: &ColumnType) -> Field {
+    Field::new("item", data_type(elem), true)
+}
+
+fn map_entries_field(key: &ColumnType, value: &ColumnType) -> Field {
+    let fields = Fields::from(vec![
+        Field::new("key", data_type(key), false),
+        Field::new("value", data_type(value), true),
+    ]);
+    Field::new("entries", DataType::Struct(fields), false)
+}
+
+// Tuple elements are named by their position
+fn struct_fields(typ: &ColumnType) -> Fields {
+    match typ {
+        ColumnType::Tuple(elems) => elems
+            .iter()
+            .enumerate()
+            .map(|(i, elem)| Field::new(i.to_string(), data_type(elem), true))
+            .collect(),
+        ColumnType::UserDefinedType { field_types, .. } => field_types
+            .iter()
+            .map(|(name, typ)| Field::new(name, data_type(typ), true))
+            .collect(),
+        _ => Fields::empty(),
+    }
+}
+
+// Builds an array of values of a single column, nested values are converted recursively
+fn to_array(
+    column: usize,
+    typ: &ColumnType,
+    values: &[Option<&CqlValue>],
+) -> Result<ArrayRef, ToArrowError> {
+    let array: ArrayRef = match typ {
+        ColumnType::Ascii | ColumnType::Text => {
+            let strings = convert_values(column, typ, values, |v| match v {
+                CqlValue::Ascii(s) | CqlValue::Text(s) => Some(s.as_str()),
+                _ => None,
+            })?;
+            Arc::new(StringArray::from(strings))
+        }
+        ColumnType::Boolean => Arc::new(BooleanArray::from(convert_values(
+            column,
+            typ,
+            values,
+            CqlValue::as_boolean,
+        )?)),
+        ColumnType::Blob => Arc::new(BinaryArray::from(convert_values(
+            column,
+            typ,
+            values,
+            |v| v.as_blob().map(Vec::as_slice),
+        )?)),
+        ColumnType::TinyInt => Arc::new(Int8Array::from(convert_values(
+            column,
+            typ,
+            values,
+            CqlValue::as_tinyint,
+        )?)),
+        ColumnType::SmallInt => Arc::new(Int16Array::from(convert_values(
+            column,
+            typ,
+            values,
+            CqlValue::as_smallint,
+        )?)),
+        ColumnType::Int => Arc::new(Int32Array::from(convert_values(
+            column,
+            typ,
+            values,
+            CqlValue::as_int,
+        )?)),
+        ColumnType::BigInt => Arc::new(Int64Array::from(convert_values(
+            column,
+            typ,
+            values,
+            CqlValue::as_bigint,
+        )?)),
+        ColumnType::Counter => Arc::new(Int64Array::from(convert_values(
+            column,
+            typ,
+            values,
+            |v| v.as_counter().map(|c| c.0),
+        )?)),
+        ColumnType::Float => Arc::new(Float32Array::from(convert_values(
+            column,
+            typ,
+            values,
+            CqlValue::as_float,
+        )?)),
+        ColumnType::Double => Arc::new(Float64Array::from(convert_values(
+            column,
+            typ,
+            values,
+            CqlValue::as_double,
+        )?)),
+        ColumnType::Date => {
+            let days = convert_values(column, typ, values, |v| match v {
+                // Days since -5877641-06-23, 2^31 days before unix epoch
+                CqlValue::Date(days) => Some((i64::from(*days) - (1 << 31)) as i32),
+                _ => None,
+            })?;
+            Arc::new(Date32Array::from(days))
+        }
+        ColumnType::Time => {
+            let nanos = convert_values(column, typ, values, |v| match v {
+                CqlValue::Time(since_midnight) => since_midnight.num_nanoseconds(),
+                _ => None,
+            })?;
+            Arc::new(Time64NanosecondArray::from(nanos))
+        }
+        ColumnType::Timestamp => {
+            let millis = convert_values(column, typ, values, |v| match v {
+                CqlValue::Timestamp(since_epoch) => Some(since_epoch.num_milliseconds()),
+                _ => None,
+            })?;
+            Arc::new(TimestampMillisecondArray::from(millis).with_timezone("UTC"))
+        }
+        ColumnType::Uuid | ColumnType::Timeuuid => {
+            let uuids = convert_values(column, typ, values, |v| {
+                v.as_uuid().map(|uuid| uuid.to_string())
+            })?;
+            Arc::new(StringArray::from(uuids))
+        }
+        ColumnType::Inet => {
+            let addrs = convert_values(column, typ, values, |v| {
+                v.as_inet().map(|addr| addr.to_string())
+            })?;
+            Arc::new(StringArray::from(addrs))
+        }
+        ColumnType::Decimal => {
+            let decimals = convert_values(column, typ, values, |v| match v {
+                CqlValue::Decimal(d) => Some(d.to_string()),
+                _ => None,
+            })?;
+            Arc::new(StringArray::from(decimals))
+        }
+        ColumnType::Varint => {
+            let varints = convert_values(column, typ, values, |v| match v {
+                CqlValue::Varint(i) => Some(i.to_string()),
+                _ => None,
+            })?;
+            Arc::new(StringArray::from(varints))
+        }
+        ColumnType::Duration => {
+            let durations = convert_values(column, typ, values, |v| {
+                v.as_cql_duration().map(|d| d.to_string())
+            })?;
+            Arc::new(StringArray::from(durations))
+        }
+        ColumnType::List(elem) | ColumnType::Set(elem) => {
+            let mut lengths = Vec::with_capacity(values.len());
+            let mut elems: Vec<Option<&CqlValue>> = Vec::new();
+            for value in values {
+                match value {
+                    Some(CqlValue::List(list)) | Some(CqlValue::Set(list)) => {
+                        lengths.push(list.len());
+                        elems.extend(list.iter().map(Some));
+                    }
+                    Some(other) => return Err(type_mismatch(column, typ, other)),
+                    None => lengths.push(0),
+                }
+            }
+
+            Arc::new(ListArray::try_new(
+                Arc::new(list_item_field(elem)),
+                OffsetBuffer::from_lengths(lengths),
+                to_array(column, elem, &elems)?,
+                nulls(values),
+            )?)
+        }
+        ColumnType::Map(key_type, value_type) => {
+            let mut lengths = Vec::with_capacity(values.len());
+            let mut keys: Vec<Option<&CqlValue>> = Vec::new();
+            let mut map_values: Vec<Option<&CqlValue>> = Vec::new();
+            for value in values {
+                match value {
+                    Some(CqlValue::Map(pairs)) => {
+                        lengths.push(pairs.len());
+                        for (key, value) in pairs {
+                            keys.push(Some(key));
+                            map_values.push(Some(value));
+                        }
+                    }
+                    Some(other) => return Err(type_mismatch(column, typ, other)),
+                    None => lengths.push(0),
+                }
+            }
+
+            let entries_field = map_entries_field(key_type, value_type);
+            let entries_fields = match entries_field.data_type() {
+                DataType::Struct(fields) => fields.clone(),
+                _ => unreachable!("Map entries are always a struct"),
+            };
+            let entries = StructArray::try_new(
+                entries_fields,
+                vec![
+                    to_array(column, key_type, &keys)?,
+                    to_array(column, value_type, &map_values)?,
+                ],
+                None,
+            )?;
+
+            Arc::new(MapArray::try_new(
+                Arc::new(entries_field),
+                OffsetBuffer::from_lengths(lengths),
+                entries,
+                nulls(values),
+                false,
+            )?)
+        }
+        ColumnType::Tuple(elem_types) => {
+            let mut children = Vec::with_capacity(elem_types.len());
+            for (i, elem_type) in elem_types.iter().enumerate() {
+                let elems = values
+                    .iter()
+                    .map(|value| match value {
+                        Some(CqlValue::Tuple(elems)) => Ok(elems.get(i)),
+                        Some(other) => Err(type_mismatch(column, typ, other)),
+                        None => Ok(None),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                children.push(to_array(column, elem_type, &elems)?);
+            }
+
+            Arc::new(StructArray::try_new(
+                struct_fields(typ),
+                children,
+                nulls(values),
+            )?)
+        }
+        ColumnType::UserDefinedType { field_types, .. } => {
+            let mut children = Vec::with_capacity(field_types.len());
+            for (name, field_type) in field_types {
+                let fields = values
+                    .iter()
+                    .map(|value| match value {
+                        Some(CqlValue::UserDefinedType { fields, .. }) => Ok(fields
+                            .iter()
+                            .find(|(field_name, _)| field_name == name)
+                            .and_then(|(_, value)| value.as_ref())),
+                        Some(other) => Err(type_mismatch(column, typ, other)),
+                        None => Ok(None),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                children.push(to_array(column, field_type, &fields)?);
+            }
+
+            Arc::new(StructArray::try_new(
+                struct_fields(typ),
+                children,
+                nulls(values),
+            )?)
+        }
+    };
+
+    Ok(array)
+}
+
+// Converts each non-null value, `convert` returns None if the value has a wrong type
+fn convert_values<'a, T>(
+    column: usize,
+    typ: &ColumnType,
+    values: &[Option<&'a CqlValue>],
+    convert: impl Fn(&'a CqlValue) -> Option<T>,
+) -> Result<Vec<Option<T>>, ToArrowError> {
+    values
+        .iter()
+        .map(|value| match value {
+            Some(value) => convert(value)
+                .map(Some)
+                .ok_or_else(|| type_mismatch(column, typ, value)),
+            None => Ok(None),
+        })
+        .collect()
+}
+
+fn nulls(values: &[Option<&CqlValue>]) -> Option<NullBuffer> {
+    if values.iter().all(Option::is_some) {
+        return None;
+    }
+    Some(values.iter().map(Option::is_some).collect())
+}
+
+fn type_mismatch(column: usize, expected: &ColumnType, actual: &CqlValue) -> ToArrowError {
+    ToArrowError::TypeMismatch {
+        column,
+        expected: expected.to_string(),
+        actual: actual.type_name(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_record_batch, ToArrowError};
+    use crate::frame::response::result::{ColumnSpec, ColumnType, CqlValue, Row};
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Int32Type, TimestampMillisecondType};
+    use arrow_array::Array;
+    use arrow_schema::{DataType, TimeUnit};
+    use chrono::Duration;
+
+    // Columns: a int, b text, c list<int>, d map<text, int>, e timestamp, f tuple<int, text>
+    fn col_specs() -> Vec<ColumnSpec> {
+        vec![
+            ColumnSpec::new("a", ColumnType::Int),
+            ColumnSpec::new("b", ColumnType::Text),
+            ColumnSpec::new("c", ColumnType::List(Box::new(ColumnType::Int))),
+            ColumnSpec::new(
+                "d",
+                ColumnType::Map(Box::new(ColumnType::Text), Box::new(ColumnType::Int)),
+            ),
+            ColumnSpec::new("e", ColumnType::Timestamp),
+            ColumnSpec::new(
+                "f",
+                ColumnType::Tuple(vec![ColumnType::Int, ColumnType::Text]),
+            ),
+        ]
+    }
+
+    #[test]
+    fn rows_to_record_batch() {
+        let rows = vec![
+            Row {
+                columns: vec![
+                    Some(CqlValue::Int(1)),
+                    Some(CqlValue::Text("one".into())),
+                    Some(CqlValue::List(vec![CqlValue::Int(1), CqlValue::Int(2)])),
+                    Some(CqlValue::Map(vec![(
+                        CqlValue::Text("k".into()),
+                        CqlValue::Int(3),
+                    )])),
+                    Some(CqlValue::Timestamp(Duration::milliseconds(1000))),
+                    Some(CqlValue::Tuple(vec![
+                        CqlValue::Int(4),
+                        CqlValue::Text("four".into()),
+                    ])),
+                ],
+            },
+            Row {
+                columns: vec![Some(CqlValue::Int(2)), None, None, None, None, None],
+            },
+        ];
+
+        let batch = to_record_batch(&col_specs(), &rows).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.num_columns(), 6);
+
+        let schema = batch.schema();
+        assert_eq!(schema.field(0).name(), "a");
+        assert_eq!(schema.field(1).data_type(), &DataType::Utf8);
+        assert_eq!(
+            schema.field(4).data_type(),
+            &DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()))
+        );
+
+        let a = batch.column(0).as_primitive::<Int32Type>();
+        assert_eq!(a.values(), &[1, 2]);
+
+        let b = batch.column(1).as_string::<i32>();
+        assert_eq!(b.value(0), "one");
+        assert!(b.is_null(1));
+
+        let c = batch.column(2).as_list::<i32>();
+        assert_eq!(c.value(0).as_primitive::<Int32Type>().values(), &[1, 2]);
+        assert!(c.is_null(1));
+
+        let d = batch.column(3).as_map();
+        assert_eq!(d.keys().as_string::<i32>().value(0), "k");
+        assert_eq!(d.values().as_primitive::<Int32Type>().value(0), 3);
+        assert!(d.is_null(1));
+
+        let e = batch.column(4).as_primitive::<TimestampMillisecondType>();
+        assert_eq!(e.value(0), 1000);
+        assert!(e.is_null(1));
+
+        let f = batch.column(5).as_struct();
+        assert_eq!(f.column(0).as_primitive::<Int32Type>().value(0), 4);
+        assert_eq!(f.column(1).as_string::<i32>().value(0), "four");
+        assert!(f.is_null(1));
+    }
+
+    #[test]
+    fn type_mismatch() {
+        let mut columns: Vec<Option<CqlValue>> = (0..6).map(|_| None).collect();
+        columns[1] = Some(CqlValue::Int(1));
+
+        match to_record_batch(&col_specs(), &[Row { columns }]) {
+            Err(ToArrowError::TypeMismatch {
+                column,
+                expected,
+                actual,
+            }) => {
+                assert_eq!(column, 1);
+                assert_eq!(expected, "text");
+                assert_eq!(actual, "int");
+            }
+            other => panic!("Expected TypeMismatch, got {:?}", other),
+        }
+
+        assert!(matches!(
+            to_record_batch(
+                &col_specs(),
+                &[Row {
+                    columns: vec![None]
+                }]
+            ),
+            Err(ToArrowError::BadRowLength {
+                row: 0,
+                len: 1,
+                expected: 6
+            })
+        ));
+    }
+}
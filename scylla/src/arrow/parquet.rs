@@ -0,0 +1,251 @@
+//! Export of query results into [Parquet](https://parquet.apache.org) files
+//! Available with the `parquet` feature. Rows are converted into Arrow record batches
+//! (see the [`arrow`](crate::arrow) module for the type mapping) and written using
+//! [`ArrowWriter`], so a whole table can be offloaded without keeping it in memory.
+
+use std::io::Write;
+use std::sync::Arc;
+
+use ::parquet::arrow::ArrowWriter;
+use ::parquet::errors::ParquetError;
+use ::parquet::file::properties::{WriterProperties, DEFAULT_MAX_ROW_GROUP_SIZE};
+use thiserror::Error;
+
+pub use ::parquet::basic::Compression;
+
+use super::ToArrowError;
+use crate::frame::response::result::{ColumnSpec, Row};
+use crate::transport::row_sink::RowSink;
+
+// Number of rows converted into a single record batch before handing it to the writer
+const BATCH_ROWS: usize = 4096;
+
+/// Error returned when rows can't be written to a Parquet file
+#[derive(Error, Debug)]
+pub enum ToParquetError {
+    /// Rows couldn't be converted into a record batch
+    #[error(transparent)]
+    ToArrowError(#[from] ToArrowError),
+    /// Writing the file has failed
+    #[error(transparent)]
+    ParquetError(#[from] ParquetError),
+    /// [`RowSink::begin`] wasn't called before writing rows, or the sink is already finished
+    #[error("Parquet sink is not ready to accept rows")]
+    NotStarted,
+}
+
+/// Writes rows into a Parquet file with one column per result column.
+/// Rows are buffered and converted in batches, a row group is closed every `row_group_size` rows.
+///
+/// # Example
+/// ```rust
+/// # use scylla::Session;
+/// # use std::error::Error;
+/// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+/// use scylla::arrow::parquet::{Compression, ParquetRowSink};
+/// use std::fs::File;
+///
+/// let mut parquet = ParquetRowSink::new(File::create("tab.parquet")?)
+///     .row_group_size(100_000)
+///     .compression(Compression::SNAPPY);
+///
+/// session
+///     .query_iter("SELECT a, b FROM ks.tab", &[])
+///     .await?
+///     .drain_into(&mut parquet)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ParquetRowSink<W: Write + Send> {
+    state: SinkState<W>,
+    row_group_size: usize,
+    compression: Compression,
+    col_specs: Vec<ColumnSpec>,
+    rows: Vec<Row>,
+}
+
+enum SinkState<W: Write + Send> {
+    NotStarted(W),
+    Writing(Box<ArrowWriter<W>>),
+    Finished(W),
+    Failed,
+}
+
+impl<W: Write + Send> ParquetRowSink<W> {
+    /// Creates a sink writing a Parquet file to `writer`.
+    /// By default row groups have up to 1024 * 1024 rows and pages are compressed using Snappy
+    pub fn new(writer: W) -> Self {
+        ParquetRowSink {
+            state: SinkState::NotStarted(writer),
+            row_group_size: DEFAULT_MAX_ROW_GROUP_SIZE,
+            compression: Compression::SNAPPY,
+            col_specs: Vec::new(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Sets the maximum number of rows in a single row group
+    /// # Panics
+    /// Panics if `row_group_size` is 0
+    pub fn row_group_size(mut self, row_group_size: usize) -> Self {
+        assert!(row_group_size > 0, "row group size must be positive");
+        self.row_group_size = row_group_size;
+        self
+    }
+
+    /// Sets the compression codec used for column chunks
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Returns the underlying writer.
+    /// Returns `None` if writing was started but [`RowSink::finish`] wasn't called or has failed
+    pub fn into_inner(self) -> Option<W> {
+        match self.state {
+            SinkState::NotStarted(writer) | SinkState::Finished(writer) => Some(writer),
+            SinkState::Writing(_) | SinkState::Failed => None,
+        }
+    }
+
+    fn write_buffered_rows(&mut self) -> Result<(), ToParquetError> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+
+        let writer = match &mut self.state {
+            SinkState::Writing(writer) => writer,
+            _ => return Err(ToParquetError::NotStarted),
+        };
+
+        let batch = super::to_record_batch(&self.col_specs, &self.rows)?;
+        self.rows.clear();
+        writer.write(&batch)?;
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> RowSink for ParquetRowSink<W> {
+    type Error = ToParquetError;
+
+    fn begin(&mut self, col_specs: &[ColumnSpec]) -> Result<(), ToParquetError> {
+        let writer = match std::mem::replace(&mut self.state, SinkState::Failed) {
+            SinkState::NotStarted(writer) => writer,
+            other => {
+                self.state = other;
+                return Err(ToParquetError::NotStarted);
+            }
+        };
+
+        let props = WriterProperties::builder()
+            .set_max_row_group_size(self.row_group_size)
+            .set_compression(self.compression)
+            .build();
+        let schema = Arc::new(super::schema(col_specs));
+
+        self.state =
+            SinkState::Writing(Box::new(ArrowWriter::try_new(writer, schema, Some(props))?));
+        self.col_specs = col_specs.to_vec();
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: Row) -> Result<(), ToParquetError> {
+        if !matches!(self.state, SinkState::Writing(_)) {
+            return Err(ToParquetError::NotStarted);
+        }
+
+        self.rows.push(row);
+        if self.rows.len() >= BATCH_ROWS.min(self.row_group_size) {
+            self.write_buffered_rows()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), ToParquetError> {
+        self.write_buffered_rows()?;
+
+        match std::mem::replace(&mut self.state, SinkState::Failed) {
+            SinkState::Writing(writer) => {
+                let mut writer = writer.into_inner()?;
+                writer.flush().map_err(ParquetError::from)?;
+                self.state = SinkState::Finished(writer);
+                Ok(())
+            }
+            other => {
+                self.state = other;
+                Err(ToParquetError::NotStarted)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParquetRowSink, ToParquetError};
+    use crate::frame::response::result::{ColumnSpec, ColumnType, CqlValue, Row};
+    use crate::transport::row_sink::RowSink;
+    use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Int32Type;
+    use bytes::Bytes;
+
+    // Columns: a int, b text
+    fn col_specs() -> Vec<ColumnSpec> {
+        vec![
+            ColumnSpec::new("a", ColumnType::Int),
+            ColumnSpec::new("b", ColumnType::Text),
+        ]
+    }
+
+    fn row(a: i32, b: Option<&str>) -> Row {
+        Row {
+            columns: vec![
+                Some(CqlValue::Int(a)),
+                b.map(|b| CqlValue::Text(b.to_string())),
+            ],
+        }
+    }
+
+    #[test]
+    fn rows_to_parquet() {
+        let mut sink = ParquetRowSink::new(Vec::new()).row_group_size(2);
+        sink.begin(&col_specs()).unwrap();
+        for i in 0..5 {
+            let text = format!("row {}", i);
+            let b = if i == 3 { None } else { Some(text.as_str()) };
+            sink.write_row(row(i, b)).unwrap();
+        }
+        sink.finish().unwrap();
+
+        let file = Bytes::from(sink.into_inner().unwrap());
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+        assert_eq!(builder.metadata().num_row_groups(), 3);
+        assert_eq!(builder.schema().field(0).name(), "a");
+        assert_eq!(builder.schema().field(1).name(), "b");
+
+        let mut a_values: Vec<i32> = Vec::new();
+        let mut b_values: Vec<Option<String>> = Vec::new();
+        for batch in builder.build().unwrap() {
+            let batch = batch.unwrap();
+            let a = batch.column(0).as_primitive::<Int32Type>();
+            a_values.extend(a.values().iter());
+            let b = batch.column(1).as_string::<i32>();
+            b_values.extend(b.iter().map(|b| b.map(str::to_string)));
+        }
+
+        assert_eq!(a_values, vec![0, 1, 2, 3, 4]);
+        assert_eq!(b_values[4].as_deref(), Some("row 4"));
+        assert_eq!(b_values[3], None);
+    }
+
+    #[test]
+    fn write_before_begin() {
+        let mut sink = ParquetRowSink::new(Vec::new());
+        assert!(matches!(
+            sink.write_row(row(1, None)),
+            Err(ToParquetError::NotStarted)
+        ));
+        assert!(sink.into_inner().unwrap().is_empty());
+    }
+}
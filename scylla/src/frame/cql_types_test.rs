@@ -1,7 +1,7 @@
 use crate::cql_to_rust::FromCqlVal;
 use crate::frame::response::result::CqlValue;
-use crate::frame::value::Counter;
 use crate::frame::value::Value;
+use crate::frame::value::{Counter, CqlDuration};
 use crate::frame::value::{Date, Time, Timestamp};
 use crate::transport::session::IntoTypedRows;
 use crate::transport::session::Session;
@@ -119,6 +119,21 @@ async fn test_varint() {
     run_tests::<BigInt>(&tests, "varint").await;
 }
 
+#[tokio::test]
+async fn test_cql_duration() {
+    let tests = [
+        "0s",
+        "1y2mo3d",
+        "2w",
+        "-5h30m",
+        "1mo1d1ns",
+        "1h2m3s4ms5us6ns",
+        "-2y",
+    ];
+
+    run_tests::<CqlDuration>(&tests, "duration").await;
+}
+
 #[tokio::test]
 async fn test_decimal() {
     let tests = [
@@ -317,8 +332,8 @@ async fn test_date() {
             .query("SELECT val from ks.date_tests", &[])
             .await
             .unwrap()
-            .rows
-            .unwrap()[0]
+            .first_row()
+            .unwrap()
             .columns[0]
             .as_ref()
             .map(|cql_val| match cql_val {
@@ -13,7 +13,7 @@ pub enum FrameError {
     Lz4BodyDecompression,
     #[error("Received frame marked as coming from a client")]
     FrameFromClient,
-    #[error("Received a frame from version {0}, but only 4 is supported")]
+    #[error("Received a frame from version {0}, but only 4 and 5 are supported")]
     VersionNotSupported(u8),
     #[error("Connection was closed before body was read: missing {0} out of {1}")]
     ConnectionClosed(usize, usize),
@@ -21,6 +21,10 @@ pub enum FrameError {
     FrameDecompression,
     #[error("Frame compression failed.")]
     FrameCompression,
+    #[error("Checksum of a segment header doesn't match")]
+    SegmentHeaderChecksum,
+    #[error("Checksum of a segment payload doesn't match")]
+    SegmentPayloadChecksum,
     #[error("std io error encountered while processing")]
     StdIoError(#[from] std::io::Error),
     #[error("Unrecognized opcode{0}")]
@@ -1,6 +1,7 @@
 pub mod frame_errors;
 pub mod request;
 pub mod response;
+pub mod segment;
 pub mod server_event_type;
 pub mod types;
 pub mod value;
@@ -15,8 +16,8 @@ mod cql_collections_test;
 mod cql_types_test;
 
 use crate::frame::frame_errors::FrameError;
-use crate::transport::Compression;
-use bytes::{Buf, BufMut, Bytes};
+use crate::transport::{Compression, ProtocolVersion};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use tokio::io::{AsyncRead, AsyncReadExt};
 use uuid::Uuid;
 
@@ -36,30 +37,40 @@ pub const FLAG_WARNING: u8 = 0x08;
 
 pub struct SerializedRequest {
     data: Vec<u8>,
+    // Size of the request body before compression
+    body_len: usize,
 }
 
 impl SerializedRequest {
+    /// Serializes the request into a frame of the given protocol version.
+    /// The body is compressed only if it has at least `compression_min_size` bytes,
+    /// compressing small bodies costs more than sending a few bytes more.
+    /// Protocol v5 compresses segments instead of frame bodies, so `compression` should be `None`.
     pub fn make<R: Request>(
         req: &R,
+        version: ProtocolVersion,
         compression: Option<Compression>,
+        compression_min_size: usize,
         tracing: bool,
     ) -> Result<SerializedRequest, FrameError> {
         let mut flags = 0;
         let mut data = vec![0; HEADER_SIZE];
+        req.serialize_for_version(&mut data, version)?;
+        let body_len = data.len() - HEADER_SIZE;
 
         if let Some(compression) = compression {
-            flags |= FLAG_COMPRESSION;
-            let body = req.to_bytes()?;
-            compress_append(&body, compression, &mut data)?;
-        } else {
-            req.serialize(&mut data)?;
+            if body_len >= compression_min_size {
+                flags |= FLAG_COMPRESSION;
+                let body = data.split_off(HEADER_SIZE);
+                compress_append(&body, compression, &mut data)?;
+            }
         }
 
         if tracing {
             flags |= FLAG_TRACING;
         }
 
-        data[0] = 4; // We only support version 4 for now
+        data[0] = version.as_u8();
         data[1] = flags;
         // Leave space for the stream number
         data[4] = R::OPCODE as u8;
@@ -67,7 +78,7 @@ impl SerializedRequest {
         let req_size = (data.len() - HEADER_SIZE) as u32;
         data[5..9].copy_from_slice(&req_size.to_be_bytes());
 
-        Ok(Self { data })
+        Ok(Self { data, body_len })
     }
 
     pub fn set_stream(&mut self, stream: i16) {
@@ -77,6 +88,11 @@ impl SerializedRequest {
     pub fn get_data(&self) -> &[u8] {
         &self.data[..]
     }
+
+    /// Size of the request body before compression, without the frame header
+    pub fn body_len(&self) -> usize {
+        self.body_len
+    }
 }
 
 // Parts of the frame header which are not determined by the request/response type.
@@ -103,14 +119,56 @@ pub async fn read_response_frame(
     let mut raw_header = [0u8; HEADER_SIZE];
     reader.read_exact(&mut raw_header[..]).await?;
 
-    let mut buf = &raw_header[..];
+    let (frame_params, opcode, length) = parse_response_header(&raw_header)?;
+
+    let mut raw_body = Vec::with_capacity(length).limit(length);
+    while raw_body.has_remaining_mut() {
+        let n = reader.read_buf(&mut raw_body).await?;
+        if n == 0 {
+            // EOF, too early
+            return Err(FrameError::ConnectionClosed(
+                raw_body.remaining_mut(),
+                length,
+            ));
+        }
+    }
+
+    Ok((frame_params, opcode, raw_body.into_inner().into()))
+}
+
+/// Reads a response frame sent in segments of protocol v5.
+/// Frames can span many segments and segments can contain many frames,
+/// `buffer` keeps data of the following frames between calls
+pub async fn read_response_frame_from_segments(
+    reader: &mut (impl AsyncRead + Unpin),
+    compressed: bool,
+    buffer: &mut BytesMut,
+) -> Result<(FrameParams, ResponseOpcode, Bytes), FrameError> {
+    loop {
+        if buffer.len() >= HEADER_SIZE {
+            let (frame_params, opcode, length) = parse_response_header(&buffer[..HEADER_SIZE])?;
+            if buffer.len() >= HEADER_SIZE + length {
+                let mut frame = buffer.split_to(HEADER_SIZE + length).freeze();
+                frame.advance(HEADER_SIZE);
+                return Ok((frame_params, opcode, frame));
+            }
+        }
+
+        let payload = segment::read_segment(reader, compressed).await?;
+        buffer.extend_from_slice(&payload);
+    }
+}
+
+fn parse_response_header(
+    raw_header: &[u8],
+) -> Result<(FrameParams, ResponseOpcode, usize), FrameError> {
+    let mut buf = raw_header;
 
-    // TODO: Validate version
     let version = buf.get_u8();
     if version & 0x80 != 0x80 {
         return Err(FrameError::FrameFromClient);
     }
-    if version & 0x7F != 0x04 {
+    if version & 0x7F != 0x04 && version & 0x7F != 0x05 {
         return Err(FrameError::VersionNotSupported(version & 0x7f));
     }
 
@@ -128,19 +186,7 @@ pub async fn read_response_frame(
     // TODO: Guard from frames that are too large
     let length = buf.get_u32() as usize;
 
-    let mut raw_body = Vec::with_capacity(length).limit(length);
-    while raw_body.has_remaining_mut() {
-        let n = reader.read_buf(&mut raw_body).await?;
-        if n == 0 {
-            // EOF, too early
-            return Err(FrameError::ConnectionClosed(
-                raw_body.remaining_mut(),
-                length,
-            ));
-        }
-    }
-
-    Ok((frame_params, opcode, raw_body.into_inner().into()))
+    Ok((frame_params, opcode, length))
 }
 
 pub struct ResponseBodyWithExtensions {
@@ -247,3 +293,51 @@ pub fn decompress(mut comp_body: &[u8], compression: Compression) -> Result<Vec<
             .map_err(|_| FrameError::FrameDecompression),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{decompress, request, SerializedRequest, FLAG_COMPRESSION, HEADER_SIZE};
+    use crate::frame::request::Request;
+    use crate::transport::Compression;
+    use crate::transport::ProtocolVersion::V4;
+
+    fn query(contents: &str) -> request::Query<'static> {
+        request::Query {
+            contents: contents.to_string(),
+            parameters: Default::default(),
+        }
+    }
+
+    #[test]
+    fn compressed_body_round_trip() {
+        let query = query(&"SELECT a FROM ks.t; ".repeat(50));
+        let body = query.to_bytes().unwrap();
+
+        for compression in [Compression::Lz4, Compression::Snappy] {
+            let request = SerializedRequest::make(&query, V4, Some(compression), 0, false).unwrap();
+            let data = request.get_data();
+            assert_ne!(data[1] & FLAG_COMPRESSION, 0);
+            assert!(data.len() - HEADER_SIZE < body.len());
+
+            let decompressed = decompress(&data[HEADER_SIZE..], compression).unwrap();
+            assert_eq!(decompressed, body);
+        }
+    }
+
+    #[test]
+    fn small_bodies_are_not_compressed() {
+        let query = query("SELECT a FROM ks.t");
+        let body = query.to_bytes().unwrap();
+
+        let request =
+            SerializedRequest::make(&query, V4, Some(Compression::Lz4), body.len() + 1, false)
+                .unwrap();
+        let data = request.get_data();
+        assert_eq!(data[1] & FLAG_COMPRESSION, 0);
+        assert_eq!(&data[HEADER_SIZE..], &body[..]);
+
+        let request =
+            SerializedRequest::make(&query, V4, Some(Compression::Lz4), body.len(), false).unwrap();
+        assert_ne!(request.get_data()[1] & FLAG_COMPRESSION, 0);
+    }
+}
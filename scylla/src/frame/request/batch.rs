@@ -3,13 +3,15 @@ use bytes::{BufMut, Bytes};
 use std::convert::TryInto;
 
 use crate::frame::{
-    request::{Request, RequestOpcode},
+    request::{query::write_flags, Request, RequestOpcode},
     types,
     value::BatchValues,
 };
+use crate::transport::ProtocolVersion;
 
 // Batch flags
-const FLAG_WITH_SERIAL_CONSISTENCY: u8 = 0x10;
+const FLAG_WITH_SERIAL_CONSISTENCY: u32 = 0x10;
+const FLAG_WITH_DEFAULT_TIMESTAMP: u32 = 0x20;
 
 pub struct Batch<'a, StatementsIter, Values>
 where
@@ -21,6 +23,8 @@ where
     pub batch_type: BatchType,
     pub consistency: types::Consistency,
     pub serial_consistency: Option<types::Consistency>,
+    /// Microseconds since unix epoch, used as the write timestamp of all statements
+    pub timestamp: Option<i64>,
     pub values: Values,
 }
 
@@ -46,16 +50,29 @@ where
     const OPCODE: RequestOpcode = RequestOpcode::Batch;
 
     fn serialize(&self, buf: &mut impl BufMut) -> Result<(), ParseError> {
+        self.serialize_for_version(buf, ProtocolVersion::V4)
+    }
+
+    fn serialize_for_version(
+        &self,
+        buf: &mut impl BufMut,
+        version: ProtocolVersion,
+    ) -> Result<(), ParseError> {
         // Serializing type of batch
         buf.put_u8(self.batch_type as u8);
 
         // Serializing queries
         types::write_short(self.statements_count.try_into()?, buf);
 
-        for (statement_num, statement) in self.statements.clone().enumerate() {
-            statement.serialize(buf)?;
-            self.values.write_nth_to_request(statement_num, buf)?;
-        }
+        // Statement count is checked against values count before sending the batch
+        let mut statements = self.statements.clone();
+        self.values
+            .write_all_to_request(buf, |statement_num, buf| {
+                statements
+                    .next()
+                    .unwrap_or_else(|| panic!("No statement for values number {}", statement_num))
+                    .serialize(buf)
+            })?;
 
         // Serializing consistency
         types::write_consistency(self.consistency, buf);
@@ -66,12 +83,20 @@ where
             flags |= FLAG_WITH_SERIAL_CONSISTENCY;
         }
 
-        buf.put_u8(flags);
+        if self.timestamp.is_some() {
+            flags |= FLAG_WITH_DEFAULT_TIMESTAMP;
+        }
+
+        write_flags(flags, version, buf);
 
         if let Some(serial_consistency) = self.serial_consistency {
             types::write_consistency(serial_consistency, buf);
         }
 
+        if let Some(timestamp) = self.timestamp {
+            types::write_long(timestamp, buf);
+        }
+
         Ok(())
     }
 }
@@ -92,3 +117,64 @@ impl BatchStatement<'_> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Batch, BatchStatement, BatchType};
+    use crate::frame::request::Request;
+    use crate::frame::types::Consistency;
+
+    #[test]
+    fn serialize_serial_consistency() {
+        let batch = Batch {
+            statements: std::iter::once(BatchStatement::Query { text: "a" }),
+            statements_count: 1,
+            batch_type: BatchType::Logged,
+            consistency: Consistency::One,
+            serial_consistency: Some(Consistency::Serial),
+            timestamp: None,
+            values: ((),),
+        };
+        let mut buf: Vec<u8> = Vec::new();
+        batch.serialize(&mut buf).unwrap();
+        assert_eq!(
+            buf,
+            vec![
+                0, // batch type
+                0, 1, // statements count
+                0, 0, 0, 0, 1, b'a', // query statement
+                0, 0, // values count
+                0, 1,    // consistency
+                0x10, // flags with FLAG_WITH_SERIAL_CONSISTENCY
+                0, 8, // serial consistency
+            ]
+        );
+    }
+
+    #[test]
+    fn serialize_timestamp() {
+        let batch = Batch {
+            statements: std::iter::once(BatchStatement::Query { text: "a" }),
+            statements_count: 1,
+            batch_type: BatchType::Unlogged,
+            consistency: Consistency::One,
+            serial_consistency: None,
+            timestamp: Some(0x0102),
+            values: ((),),
+        };
+        let mut buf: Vec<u8> = Vec::new();
+        batch.serialize(&mut buf).unwrap();
+        assert_eq!(
+            buf,
+            vec![
+                1, // batch type
+                0, 1, // statements count
+                0, 0, 0, 0, 1, b'a', // query statement
+                0, 0, // values count
+                0, 1,    // consistency
+                0x20, // flags with FLAG_WITH_DEFAULT_TIMESTAMP
+                0, 0, 0, 0, 0, 0, 1, 2, // timestamp
+            ]
+        );
+    }
+}
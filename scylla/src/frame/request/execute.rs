@@ -4,10 +4,14 @@ use bytes::{BufMut, Bytes};
 use crate::{
     frame::request::{query, Request, RequestOpcode},
     frame::types,
+    transport::ProtocolVersion,
 };
 
 pub struct Execute<'a> {
     pub id: Bytes,
+    /// Id of the result metadata returned when the statement was prepared, sent only in protocol v5.
+    /// If it doesn't match the node's id, the node sends the new metadata with the result
+    pub result_metadata_id: Option<Bytes>,
     pub parameters: query::QueryParameters<'a>,
 }
 
@@ -15,11 +19,24 @@ impl Request for Execute<'_> {
     const OPCODE: RequestOpcode = RequestOpcode::Execute;
 
     fn serialize(&self, buf: &mut impl BufMut) -> Result<(), ParseError> {
+        self.serialize_for_version(buf, ProtocolVersion::V4)
+    }
+
+    fn serialize_for_version(
+        &self,
+        buf: &mut impl BufMut,
+        version: ProtocolVersion,
+    ) -> Result<(), ParseError> {
         // Serializing statement id
         types::write_short_bytes(&self.id[..], buf)?;
 
+        if version >= ProtocolVersion::V5 {
+            let result_metadata_id = self.result_metadata_id.as_deref().unwrap_or(&[]);
+            types::write_short_bytes(result_metadata_id, buf)?;
+        }
+
         // Serializing params
-        self.parameters.serialize(buf)?;
+        self.parameters.serialize_for_version(buf, version)?;
         Ok(())
     }
 }
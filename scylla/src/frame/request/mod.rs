@@ -8,6 +8,7 @@ pub mod register;
 pub mod startup;
 
 use crate::frame::frame_errors::ParseError;
+use crate::transport::ProtocolVersion;
 use bytes::{BufMut, Bytes};
 use num_enum::TryFromPrimitive;
 
@@ -18,7 +19,7 @@ pub use prepare::Prepare;
 pub use query::Query;
 pub use startup::Startup;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, TryFromPrimitive)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, TryFromPrimitive)]
 #[repr(u8)]
 pub enum RequestOpcode {
     Startup = 0x01,
@@ -36,6 +37,16 @@ pub trait Request {
 
     fn serialize(&self, buf: &mut impl BufMut) -> Result<(), ParseError>;
 
+    /// Serializes the request in the format of the given protocol version,
+    /// by default the format is the same in all versions
+    fn serialize_for_version(
+        &self,
+        buf: &mut impl BufMut,
+        _version: ProtocolVersion,
+    ) -> Result<(), ParseError> {
+        self.serialize(buf)
+    }
+
     fn to_bytes(&self) -> Result<Bytes, ParseError> {
         let mut v = Vec::new();
         self.serialize(&mut v)?;
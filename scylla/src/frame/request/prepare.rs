@@ -2,19 +2,48 @@ use crate::frame::frame_errors::ParseError;
 use bytes::BufMut;
 
 use crate::{
+    frame::request::query::{check_keyspace_supported, write_flags},
     frame::request::{Request, RequestOpcode},
     frame::types,
+    transport::ProtocolVersion,
 };
 
+// Prepare flags, protocol v5 only
+const FLAG_WITH_KEYSPACE: u32 = 0x01;
+
 pub struct Prepare<'a> {
     pub query: &'a str,
+    /// Keyspace of unqualified table names, instead of the connection's keyspace.
+    /// Requires protocol v5
+    pub keyspace: Option<&'a str>,
 }
 
 impl<'a> Request for Prepare<'a> {
     const OPCODE: RequestOpcode = RequestOpcode::Prepare;
 
     fn serialize(&self, buf: &mut impl BufMut) -> Result<(), ParseError> {
+        self.serialize_for_version(buf, ProtocolVersion::V4)
+    }
+
+    fn serialize_for_version(
+        &self,
+        buf: &mut impl BufMut,
+        version: ProtocolVersion,
+    ) -> Result<(), ParseError> {
+        check_keyspace_supported(self.keyspace, version)?;
         types::write_long_string(self.query, buf)?;
+
+        if version >= ProtocolVersion::V5 {
+            let mut flags = 0;
+            if self.keyspace.is_some() {
+                flags |= FLAG_WITH_KEYSPACE;
+            }
+            write_flags(flags, version, buf);
+
+            if let Some(keyspace) = self.keyspace {
+                types::write_string(keyspace, buf)?;
+            }
+        }
         Ok(())
     }
 }
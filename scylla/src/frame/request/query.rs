@@ -5,17 +5,20 @@ use crate::{
     frame::request::{Request, RequestOpcode},
     frame::types,
     frame::value::SerializedValues,
+    transport::ProtocolVersion,
 };
 
 // Query flags
 // Unused flags are commented out so that they don't trigger warnings
-const FLAG_VALUES: u8 = 0x01;
-// const FLAG_SKIP_METADATA: u8 = 0x02;
-const FLAG_PAGE_SIZE: u8 = 0x04;
-const FLAG_WITH_PAGING_STATE: u8 = 0x08;
-const FLAG_WITH_SERIAL_CONSISTENCY: u8 = 0x10;
-// const FLAG_WITH_DEFAULT_TIMESTAMP: u8 = 0x20;
-// const FLAG_WITH_NAMES_FOR_VALUES: u8 = 0x40;
+const FLAG_VALUES: u32 = 0x01;
+// const FLAG_SKIP_METADATA: u32 = 0x02;
+const FLAG_PAGE_SIZE: u32 = 0x04;
+const FLAG_WITH_PAGING_STATE: u32 = 0x08;
+const FLAG_WITH_SERIAL_CONSISTENCY: u32 = 0x10;
+const FLAG_WITH_DEFAULT_TIMESTAMP: u32 = 0x20;
+const FLAG_WITH_NAMES_FOR_VALUES: u32 = 0x40;
+// Protocol v5 only
+const FLAG_WITH_KEYSPACE: u32 = 0x80;
 
 pub struct Query<'a> {
     pub contents: String,
@@ -26,8 +29,16 @@ impl Request for Query<'_> {
     const OPCODE: RequestOpcode = RequestOpcode::Query;
 
     fn serialize(&self, buf: &mut impl BufMut) -> Result<(), ParseError> {
+        self.serialize_for_version(buf, ProtocolVersion::V4)
+    }
+
+    fn serialize_for_version(
+        &self,
+        buf: &mut impl BufMut,
+        version: ProtocolVersion,
+    ) -> Result<(), ParseError> {
         types::write_long_string(&self.contents, buf)?;
-        self.parameters.serialize(buf)?;
+        self.parameters.serialize_for_version(buf, version)?;
         Ok(())
     }
 }
@@ -37,7 +48,12 @@ pub struct QueryParameters<'a> {
     pub serial_consistency: Option<types::Consistency>,
     pub page_size: Option<i32>,
     pub paging_state: Option<Bytes>,
+    /// Microseconds since unix epoch, used as the write timestamp
+    pub timestamp: Option<i64>,
     pub values: &'a SerializedValues,
+    /// Keyspace of unqualified table names, instead of the connection's keyspace.
+    /// Requires protocol v5, not allowed in EXECUTE
+    pub keyspace: Option<&'a str>,
 }
 
 impl Default for QueryParameters<'_> {
@@ -47,13 +63,24 @@ impl Default for QueryParameters<'_> {
             serial_consistency: None,
             page_size: None,
             paging_state: None,
+            timestamp: None,
             values: SerializedValues::EMPTY,
+            keyspace: None,
         }
     }
 }
 
 impl QueryParameters<'_> {
     pub fn serialize(&self, buf: &mut impl BufMut) -> Result<(), ParseError> {
+        self.serialize_for_version(buf, ProtocolVersion::V4)
+    }
+
+    pub fn serialize_for_version(
+        &self,
+        buf: &mut impl BufMut,
+        version: ProtocolVersion,
+    ) -> Result<(), ParseError> {
+        check_keyspace_supported(self.keyspace, version)?;
         types::write_consistency(self.consistency, buf);
 
         let mut flags = 0;
@@ -73,7 +100,19 @@ impl QueryParameters<'_> {
             flags |= FLAG_WITH_SERIAL_CONSISTENCY;
         }
 
-        buf.put_u8(flags);
+        if self.timestamp.is_some() {
+            flags |= FLAG_WITH_DEFAULT_TIMESTAMP;
+        }
+
+        if self.values.has_names() {
+            flags |= FLAG_WITH_NAMES_FOR_VALUES;
+        }
+
+        if self.keyspace.is_some() {
+            flags |= FLAG_WITH_KEYSPACE;
+        }
+
+        write_flags(flags, version, buf);
 
         if !self.values.is_empty() {
             self.values.write_to_request(buf);
@@ -91,6 +130,124 @@ impl QueryParameters<'_> {
             types::write_consistency(serial_consistency, buf);
         }
 
+        if let Some(timestamp) = self.timestamp {
+            types::write_long(timestamp, buf);
+        }
+
+        if let Some(keyspace) = self.keyspace {
+            types::write_string(keyspace, buf)?;
+        }
+
         Ok(())
     }
 }
+
+// Flags are a byte in protocol v4 and an int in v5
+pub(crate) fn write_flags(flags: u32, version: ProtocolVersion, buf: &mut impl BufMut) {
+    match version {
+        ProtocolVersion::V4 => buf.put_u8(flags as u8),
+        ProtocolVersion::V5 => buf.put_u32(flags),
+    }
+}
+
+// Keyspaces of single requests were added in protocol v5
+pub(crate) fn check_keyspace_supported(
+    keyspace: Option<&str>,
+    version: ProtocolVersion,
+) -> Result<(), ParseError> {
+    match (keyspace, version) {
+        (Some(keyspace), ProtocolVersion::V4) => Err(ParseError::BadData(format!(
+            "Setting keyspace {} of a single request requires protocol v5",
+            keyspace
+        ))),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QueryParameters;
+    use crate::frame::types::Consistency;
+    use crate::frame::value::SerializedValues;
+    use crate::transport::ProtocolVersion;
+
+    #[test]
+    fn serialize_serial_consistency() {
+        let parameters = QueryParameters {
+            consistency: Consistency::Quorum,
+            serial_consistency: Some(Consistency::LocalSerial),
+            ..Default::default()
+        };
+        let mut buf: Vec<u8> = Vec::new();
+        parameters.serialize(&mut buf).unwrap();
+        // Consistency, flags with FLAG_WITH_SERIAL_CONSISTENCY, serial consistency
+        assert_eq!(buf, vec![0x00, 0x04, 0x10, 0x00, 0x09]);
+
+        let parameters = QueryParameters {
+            consistency: Consistency::Quorum,
+            ..Default::default()
+        };
+        let mut buf: Vec<u8> = Vec::new();
+        parameters.serialize(&mut buf).unwrap();
+        assert_eq!(buf, vec![0x00, 0x04, 0x00]);
+    }
+
+    #[test]
+    fn serialize_timestamp() {
+        let parameters = QueryParameters {
+            consistency: Consistency::One,
+            serial_consistency: Some(Consistency::Serial),
+            timestamp: Some(0x0102),
+            ..Default::default()
+        };
+        let mut buf: Vec<u8> = Vec::new();
+        parameters.serialize(&mut buf).unwrap();
+        // Timestamp goes after serial consistency
+        assert_eq!(
+            buf,
+            vec![0x00, 0x01, 0x30, 0x00, 0x08, 0, 0, 0, 0, 0, 0, 0x01, 0x02]
+        );
+    }
+
+    #[test]
+    fn serialize_named_values() {
+        let mut values = SerializedValues::new();
+        values.add_named_value("a", &1_i8).unwrap();
+        let parameters = QueryParameters {
+            consistency: Consistency::One,
+            values: &values,
+            ..Default::default()
+        };
+        let mut buf: Vec<u8> = Vec::new();
+        parameters.serialize(&mut buf).unwrap();
+        // Flags with FLAG_VALUES and FLAG_WITH_NAMES_FOR_VALUES, each value preceded by its name
+        assert_eq!(
+            buf,
+            vec![0x00, 0x01, 0x41, 0x00, 0x01, 0x00, 0x01, b'a', 0x00, 0x00, 0x00, 0x01, 0x01]
+        );
+    }
+
+    #[test]
+    fn serialize_keyspace() {
+        let parameters = QueryParameters {
+            consistency: Consistency::One,
+            timestamp: Some(1),
+            keyspace: Some("ks"),
+            ..Default::default()
+        };
+        let mut buf: Vec<u8> = Vec::new();
+        parameters
+            .serialize_for_version(&mut buf, ProtocolVersion::V5)
+            .unwrap();
+        // Flags are an int in v5, FLAG_WITH_KEYSPACE is set and the keyspace goes last
+        assert_eq!(
+            buf,
+            vec![0x00, 0x01, 0, 0, 0, 0xa0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0x00, 0x02, b'k', b's']
+        );
+
+        let mut buf: Vec<u8> = Vec::new();
+        assert!(parameters
+            .serialize_for_version(&mut buf, ProtocolVersion::V4)
+            .is_err());
+    }
+}
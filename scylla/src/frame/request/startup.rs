@@ -16,7 +16,16 @@ impl Request for Startup {
     const OPCODE: RequestOpcode = RequestOpcode::Startup;
 
     fn serialize(&self, buf: &mut impl BufMut) -> Result<(), ParseError> {
-        types::write_string_map(&self.options, buf)?;
+        // Options are sorted, so that the body doesn't depend on the order of the HashMap
+        // and recorded connections can be replayed
+        let mut options: Vec<_> = self.options.iter().collect();
+        options.sort_unstable();
+
+        types::write_short_length(options.len(), buf)?;
+        for (key, value) in options {
+            types::write_string(key, buf)?;
+            types::write_string(value, buf)?;
+        }
         Ok(())
     }
 }
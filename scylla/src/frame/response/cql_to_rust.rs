@@ -1,9 +1,10 @@
-use super::result::{CqlValue, Row};
-use crate::frame::value::Counter;
+use super::result::{ColumnSpec, CqlValue, Row};
+use crate::frame::value::{Counter, CqlDuration};
 use bigdecimal::BigDecimal;
-use chrono::{Duration, NaiveDate};
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
 use num_bigint::BigInt;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
 use std::hash::Hash;
 use std::net::IpAddr;
 use thiserror::Error;
@@ -11,10 +12,86 @@ use uuid::Uuid;
 
 #[derive(Error, Debug, Clone, PartialEq, Eq)]
 pub enum FromRowError {
-    #[error("Bad CQL value")]
-    BadCqlVal(#[from] FromCqlValError),
+    #[error(
+        "Bad CQL value{}: {err}",
+        .column.as_ref().map(|column| format!(" in column {}", column)).unwrap_or_default()
+    )]
+    BadCqlVal {
+        err: FromCqlValError,
+        /// The failing column, `None` if the error was converted from a bare `FromCqlValError`
+        column: Option<ColumnContext>,
+    },
     #[error("Row too short")]
     RowTooShort,
+    #[error("Malformed row in the result: {0}")]
+    MalformedRow(String),
+}
+
+/// Describes the column whose value couldn't be converted to a rust type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnContext {
+    /// Index of the column in the row
+    pub index: usize,
+    /// Name of the column, known only when the row was read together with result metadata
+    pub name: Option<String>,
+    /// Index of the row in the query result, known only when rows were read from a result
+    pub row_index: Option<usize>,
+    /// Rust type to which the value was converted
+    pub expected_type: &'static str,
+    /// CQL type of the column, `None` if it isn't known and the value was null
+    pub actual_type: Option<String>,
+}
+
+impl fmt::Display for ColumnContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.index)?;
+        if let Some(name) = &self.name {
+            write!(f, " ({})", name)?;
+        }
+        if let Some(row_index) = self.row_index {
+            write!(f, " of row {}", row_index)?;
+        }
+        write!(f, ", expected {}", self.expected_type)?;
+        if let Some(actual_type) = &self.actual_type {
+            write!(f, ", got {}", actual_type)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<FromCqlValError> for FromRowError {
+    fn from(err: FromCqlValError) -> Self {
+        FromRowError::BadCqlVal { err, column: None }
+    }
+}
+
+impl FromRowError {
+    /// Fills in the name and type of the failing column using metadata of the row
+    pub(crate) fn with_column_specs(mut self, col_specs: &[ColumnSpec]) -> Self {
+        if let FromRowError::BadCqlVal {
+            column: Some(column),
+            ..
+        } = &mut self
+        {
+            if let Some(spec) = col_specs.get(column.index) {
+                column.name = Some(spec.name().to_string());
+                column.actual_type = Some(spec.typ().to_string());
+            }
+        }
+        self
+    }
+
+    /// Fills in the index of the row in which the failing column is
+    pub(crate) fn with_row_index(mut self, row_index: usize) -> Self {
+        if let FromRowError::BadCqlVal {
+            column: Some(column),
+            ..
+        } = &mut self
+        {
+            column.row_index = Some(row_index);
+        }
+        self
+    }
 }
 
 #[derive(Error, Debug, PartialEq, Eq)]
@@ -43,6 +120,37 @@ pub trait FromRow: Sized {
     fn from_row(row: Row) -> Result<Self, FromRowError>;
 }
 
+/// Converts the value of the column at `index`, describing the column if it fails.  
+/// Used by `FromRow` implementations
+pub fn column_from_cql<T: FromCqlVal<Option<CqlValue>>>(
+    index: usize,
+    value: Option<CqlValue>,
+) -> Result<T, FromRowError> {
+    convert_column(index, value).map_err(|(err, column)| FromRowError::BadCqlVal {
+        err,
+        column: Some(column),
+    })
+}
+
+// Converts the value, returning the description of the column on failure
+pub(crate) fn convert_column<T: FromCqlVal<Option<CqlValue>>>(
+    index: usize,
+    value: Option<CqlValue>,
+) -> Result<T, (FromCqlValError, ColumnContext)> {
+    let actual_type = value.as_ref().map(CqlValue::type_name);
+
+    T::from_cql(value).map_err(|err| {
+        let column = ColumnContext {
+            index,
+            name: None,
+            row_index: None,
+            expected_type: std::any::type_name::<T>(),
+            actual_type: actual_type.map(str::to_string),
+        };
+        (err, column)
+    })
+}
+
 // Implement from_cql<Option<CqlValue>> for every type that has from_cql<CqlValue>
 // This tries to unwrap the option or fails with an error
 impl<T: FromCqlVal<CqlValue>> FromCqlVal<Option<CqlValue>> for T {
@@ -75,6 +183,7 @@ macro_rules! impl_from_cql_val {
 impl_from_cql_val!(i32, as_int); // i32::from_cql<CqlValue>
 impl_from_cql_val!(i64, as_bigint); // i64::from_cql<CqlValue>
 impl_from_cql_val!(Counter, as_counter); // Counter::from_cql<CqlValue>
+impl_from_cql_val!(CqlDuration, as_cql_duration); // CqlDuration::from_cql<CqlValue>
 impl_from_cql_val!(i16, as_smallint); // i16::from_cql<CqlValue>
 impl_from_cql_val!(BigInt, into_varint); // BigInt::from_cql<CqlValue>
 impl_from_cql_val!(i8, as_tinyint); // i8::from_cql<CqlValue>
@@ -89,6 +198,13 @@ impl_from_cql_val!(Uuid, as_uuid); // Uuid::from_cql<CqlValue>
 impl_from_cql_val!(BigDecimal, into_decimal); // BigDecimal::from_cql<CqlValue>
 impl_from_cql_val!(Duration, as_duration); // Duration::from_cql<CqlValue>
 
+// CqlValue::from_cql<CqlValue>, allows to read values of any type
+impl FromCqlVal<CqlValue> for CqlValue {
+    fn from_cql(cql_val: CqlValue) -> Result<Self, FromCqlValError> {
+        Ok(cql_val)
+    }
+}
+
 // Vec<T>::from_cql<CqlValue>
 impl<T: FromCqlVal<CqlValue>> FromCqlVal<CqlValue> for Vec<T> {
     fn from_cql(cql_val: CqlValue) -> Result<Self, FromCqlValError> {
@@ -114,6 +230,54 @@ impl<T1: FromCqlVal<CqlValue> + Eq + Hash, T2: FromCqlVal<CqlValue>> FromCqlVal<
     }
 }
 
+impl<K: FromCqlVal<CqlValue> + Ord, V: FromCqlVal<CqlValue>> FromCqlVal<CqlValue>
+    for BTreeMap<K, V>
+{
+    fn from_cql(cql_val: CqlValue) -> Result<Self, FromCqlValError> {
+        cql_val
+            .into_pair_vec()
+            .ok_or(FromCqlValError::BadCqlType)?
+            .into_iter()
+            .map(|(key, value)| Ok((K::from_cql(key)?, V::from_cql(value)?)))
+            .collect()
+    }
+}
+
+impl<T: FromCqlVal<CqlValue> + Eq + Hash> FromCqlVal<CqlValue> for HashSet<T> {
+    fn from_cql(cql_val: CqlValue) -> Result<Self, FromCqlValError> {
+        cql_val
+            .into_vec()
+            .ok_or(FromCqlValError::BadCqlType)?
+            .into_iter()
+            .map(T::from_cql)
+            .collect()
+    }
+}
+
+impl<T: FromCqlVal<CqlValue> + Ord> FromCqlVal<CqlValue> for BTreeSet<T> {
+    fn from_cql(cql_val: CqlValue) -> Result<Self, FromCqlValError> {
+        cql_val
+            .into_vec()
+            .ok_or(FromCqlValError::BadCqlType)?
+            .into_iter()
+            .map(T::from_cql)
+            .collect()
+    }
+}
+
+// DateTime<Utc>::from_cql<CqlValue>, only timestamps are accepted
+impl FromCqlVal<CqlValue> for DateTime<Utc> {
+    fn from_cql(cql_val: CqlValue) -> Result<Self, FromCqlValError> {
+        match cql_val {
+            CqlValue::Timestamp(since_epoch) => Utc
+                .timestamp_millis_opt(since_epoch.num_milliseconds())
+                .single()
+                .ok_or(FromCqlValError::BadCqlType),
+            _ => Err(FromCqlValError::BadCqlType),
+        }
+    }
+}
+
 // This macro implements FromRow for tuple of types that have FromCqlVal
 macro_rules! impl_tuple_from_row {
     ( $($Ti:tt),+ ) => {
@@ -122,15 +286,15 @@ macro_rules! impl_tuple_from_row {
             $($Ti: FromCqlVal<Option<CqlValue>>),+
         {
             fn from_row(row: Row) -> Result<Self, FromRowError> {
-                let mut vals_iter = row.columns.into_iter();
+                let mut vals_iter = row.columns.into_iter().enumerate();
 
                 Ok((
-                    $(
-                        $Ti::from_cql(vals_iter
-                                      .next()
-                                      .ok_or(FromRowError::RowTooShort) ?
-                                     ) ?
-                    ,)+
+                    $({
+                        let (index, value) = vals_iter
+                            .next()
+                            .ok_or(FromRowError::RowTooShort)?;
+                        column_from_cql::<$Ti>(index, value)?
+                    },)+
                 ))
             }
         }
@@ -198,13 +362,14 @@ impl_tuple_from_cql!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14
 
 #[cfg(test)]
 mod tests {
-    use super::{CqlValue, FromCqlVal, FromCqlValError, FromRow, FromRowError, Row};
+    use super::{ColumnContext, CqlValue, FromCqlVal, FromCqlValError, FromRow, FromRowError, Row};
     use crate as scylla;
     use crate::frame::value::Counter;
-    use crate::macros::FromRow;
+    use crate::macros::{FromRow, FromUserType};
     use bigdecimal::BigDecimal;
-    use chrono::{Duration, NaiveDate};
+    use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
     use num_bigint::{BigInt, ToBigInt};
+    use std::collections::{BTreeMap, BTreeSet, HashSet};
     use std::net::{IpAddr, Ipv4Addr};
     use std::str::FromStr;
     use uuid::Uuid;
@@ -348,6 +513,44 @@ mod tests {
         assert_eq!(Ok(vec![1, 2, 3]), Vec::<i32>::from_cql(cql_val));
     }
 
+    #[test]
+    fn sets_and_maps_from_cql() {
+        let set = CqlValue::Set(vec![CqlValue::Int(2), CqlValue::Int(1), CqlValue::Int(2)]);
+        let expected: BTreeSet<i32> = vec![1, 2].into_iter().collect();
+        assert_eq!(Ok(expected), BTreeSet::<i32>::from_cql(set.clone()));
+        let expected: HashSet<i32> = vec![1, 2].into_iter().collect();
+        assert_eq!(Ok(expected), HashSet::<i32>::from_cql(set));
+
+        let map = CqlValue::Map(vec![
+            (CqlValue::Int(2), CqlValue::Text("b".to_string())),
+            (CqlValue::Int(1), CqlValue::Text("a".to_string())),
+        ]);
+        let expected: BTreeMap<i32, String> = vec![(1, "a".to_string()), (2, "b".to_string())]
+            .into_iter()
+            .collect();
+        assert_eq!(Ok(expected), BTreeMap::<i32, String>::from_cql(map));
+
+        assert_eq!(
+            Err(FromCqlValError::BadCqlType),
+            BTreeSet::<i32>::from_cql(CqlValue::Int(1))
+        );
+    }
+
+    #[test]
+    fn date_time_from_cql() {
+        let timestamp = CqlValue::Timestamp(Duration::milliseconds(1_234_567));
+        assert_eq!(
+            Ok(Utc.timestamp_millis_opt(1_234_567).unwrap()),
+            DateTime::<Utc>::from_cql(timestamp)
+        );
+
+        let time = CqlValue::Time(Duration::milliseconds(1_234_567));
+        assert_eq!(
+            Err(FromCqlValError::BadCqlType),
+            DateTime::<Utc>::from_cql(time)
+        );
+    }
+
     #[test]
     fn tuple_from_row() {
         let row = Row {
@@ -392,19 +595,42 @@ mod tests {
 
         assert_eq!(
             <(i32,)>::from_row(row),
-            Err(FromRowError::BadCqlVal(FromCqlValError::ValIsNull))
+            Err(FromRowError::BadCqlVal {
+                err: FromCqlValError::ValIsNull,
+                column: Some(ColumnContext {
+                    index: 0,
+                    name: None,
+                    row_index: None,
+                    expected_type: "i32",
+                    actual_type: None,
+                })
+            })
         );
     }
 
     #[test]
     fn from_row_wrong_type() {
         let row = Row {
-            columns: vec![Some(CqlValue::Int(1234))],
+            columns: vec![Some(CqlValue::Int(1)), Some(CqlValue::Int(1234))],
         };
 
+        let err = <(i32, String)>::from_row(row).unwrap_err();
+        assert_eq!(
+            err,
+            FromRowError::BadCqlVal {
+                err: FromCqlValError::BadCqlType,
+                column: Some(ColumnContext {
+                    index: 1,
+                    name: None,
+                    row_index: None,
+                    expected_type: "alloc::string::String",
+                    actual_type: Some("int".to_string()),
+                })
+            }
+        );
         assert_eq!(
-            <(String,)>::from_row(row),
-            Err(FromRowError::BadCqlVal(FromCqlValError::BadCqlType))
+            err.to_string(),
+            "Bad CQL value in column 1, expected alloc::string::String, got int: Bad CQL type"
         );
     }
 
@@ -417,6 +643,36 @@ mod tests {
         assert_eq!(<(i32, i32)>::from_row(row), Err(FromRowError::RowTooShort));
     }
 
+    #[test]
+    fn from_row_error_from_cql_val_error() {
+        struct Manual {
+            _value: i32,
+        }
+
+        impl FromRow for Manual {
+            fn from_row(row: Row) -> Result<Self, FromRowError> {
+                let value = row.columns.into_iter().next().flatten();
+                Ok(Manual {
+                    _value: i32::from_cql(value)?,
+                })
+            }
+        }
+
+        let err = Manual::from_row(Row {
+            columns: vec![None],
+        })
+        .err()
+        .unwrap();
+        assert_eq!(
+            err,
+            FromRowError::BadCqlVal {
+                err: FromCqlValError::ValIsNull,
+                column: None,
+            }
+        );
+        assert_eq!(err.to_string(), "Bad CQL value: Value is null");
+    }
+
     #[test]
     fn struct_from_row() {
         #[derive(FromRow)]
@@ -440,4 +696,104 @@ mod tests {
         assert_eq!(my_row.b, None);
         assert_eq!(my_row.c, Some(vec![1, 2]));
     }
+
+    #[test]
+    fn struct_from_row_errors() {
+        #[derive(FromRow, Debug)]
+        struct MyRow {
+            _a: i32,
+            _b: String,
+        }
+
+        let row = |columns: Vec<Option<CqlValue>>| Row { columns };
+
+        let err = MyRow::from_row(row(vec![Some(CqlValue::Int(1)), None])).unwrap_err();
+        assert!(matches!(
+            err,
+            FromRowError::BadCqlVal {
+                err: FromCqlValError::ValIsNull,
+                column: Some(ColumnContext { index: 1, .. }),
+            }
+        ));
+
+        let err = MyRow::from_row(row(vec![
+            Some(CqlValue::Text("a".to_string())),
+            Some(CqlValue::Text("b".to_string())),
+        ]))
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            FromRowError::BadCqlVal {
+                err: FromCqlValError::BadCqlType,
+                column: Some(ColumnContext { index: 0, .. }),
+            }
+        ));
+
+        assert_eq!(
+            MyRow::from_row(row(vec![Some(CqlValue::Int(1))])).unwrap_err(),
+            FromRowError::RowTooShort
+        );
+
+        // Tuples with too few fields
+        assert_eq!(
+            <(i32, i32)>::from_cql(CqlValue::Tuple(vec![CqlValue::Int(1)])),
+            Err(FromCqlValError::BadCqlType)
+        );
+    }
+
+    #[test]
+    fn struct_from_user_type() {
+        #[derive(FromUserType, Debug, PartialEq)]
+        struct MyUdt {
+            b: Option<String>,
+            a: i32,
+            c: Option<i64>,
+        }
+
+        // Fields are matched by name, order in the type doesn't matter
+        // and fields missing from the value are null
+        let fields = vec![
+            ("b".to_string(), Some(CqlValue::Text("text".to_string()))),
+            ("a".to_string(), Some(CqlValue::Int(16))),
+        ];
+        let udt = CqlValue::UserDefinedType {
+            keyspace: "ks".to_string(),
+            type_name: "my_udt".to_string(),
+            fields: fields.clone(),
+        };
+
+        assert_eq!(
+            MyUdt::from_cql(udt),
+            Ok(MyUdt {
+                a: 16,
+                b: Some("text".to_string()),
+                c: None,
+            })
+        );
+
+        // Null in a non-optional field
+        let mut null_fields = fields.clone();
+        null_fields[1].1 = None;
+        let udt = CqlValue::UserDefinedType {
+            keyspace: "ks".to_string(),
+            type_name: "my_udt".to_string(),
+            fields: null_fields,
+        };
+        assert_eq!(MyUdt::from_cql(udt), Err(FromCqlValError::ValIsNull));
+
+        // Field which doesn't exist in the struct
+        let mut extra_fields = fields;
+        extra_fields.push(("d".to_string(), Some(CqlValue::Int(1))));
+        let udt = CqlValue::UserDefinedType {
+            keyspace: "ks".to_string(),
+            type_name: "my_udt".to_string(),
+            fields: extra_fields,
+        };
+        assert_eq!(MyUdt::from_cql(udt), Err(FromCqlValError::BadCqlType));
+
+        assert_eq!(
+            MyUdt::from_cql(CqlValue::Int(1)),
+            Err(FromCqlValError::BadCqlType)
+        );
+    }
 }
@@ -1,7 +1,9 @@
 use crate::frame::frame_errors::ParseError;
 use crate::frame::types;
 use crate::transport::errors::{DbError, QueryError, WriteType};
+use crate::transport::ProtocolVersion;
 use byteorder::ReadBytesExt;
+use bytes::{Buf, BufMut, Bytes};
 
 #[derive(Debug)]
 pub struct Error {
@@ -11,6 +13,13 @@ pub struct Error {
 
 impl Error {
     pub fn deserialize(buf: &mut &[u8]) -> Result<Self, ParseError> {
+        Self::deserialize_for_version(buf, ProtocolVersion::V4)
+    }
+
+    pub fn deserialize_for_version(
+        buf: &mut &[u8],
+        version: ProtocolVersion,
+    ) -> Result<Self, ParseError> {
         let code = types::read_int(buf)?;
         let reason = types::read_string(buf)?.to_owned();
 
@@ -42,7 +51,7 @@ impl Error {
                 consistency: types::read_consistency(buf)?,
                 received: types::read_int(buf)?,
                 required: types::read_int(buf)?,
-                numfailures: types::read_int(buf)?,
+                numfailures: read_numfailures(buf, version)?,
                 data_present: buf.read_u8()? != 0,
             },
             0x1400 => DbError::FunctionFailure {
@@ -54,9 +63,15 @@ impl Error {
                 consistency: types::read_consistency(buf)?,
                 received: types::read_int(buf)?,
                 required: types::read_int(buf)?,
-                numfailures: types::read_int(buf)?,
+                numfailures: read_numfailures(buf, version)?,
                 write_type: WriteType::from(types::read_string(buf)?),
             },
+            0x1600 => DbError::CdcWriteFailure,
+            0x1700 => DbError::CasWriteUnknown {
+                consistency: types::read_consistency(buf)?,
+                received: types::read_int(buf)?,
+                required: types::read_int(buf)?,
+            },
             0x2000 => DbError::SyntaxError,
             0x2100 => DbError::Unauthorized,
             0x2200 => DbError::Invalid,
@@ -65,12 +80,150 @@ impl Error {
                 keyspace: types::read_string(buf)?.to_string(),
                 table: types::read_string(buf)?.to_string(),
             },
-            0x2500 => DbError::Unprepared,
+            0x2500 => DbError::Unprepared {
+                statement_id: Bytes::copy_from_slice(types::read_short_bytes(buf)?),
+            },
             _ => DbError::Other(code),
         };
 
         Ok(Error { error, reason })
     }
+
+    /// Serializes the body of an ERROR response, used to simulate errors in tests
+    pub fn serialize(&self, buf: &mut impl BufMut) -> Result<(), ParseError> {
+        let code: i32 = match &self.error {
+            DbError::ServerError => 0x0000,
+            DbError::ProtocolError => 0x000A,
+            DbError::AuthenticationError => 0x0100,
+            DbError::Unavailable { .. } => 0x1000,
+            DbError::Overloaded => 0x1001,
+            DbError::IsBootstrapping => 0x1002,
+            DbError::TruncateError => 0x1003,
+            DbError::WriteTimeout { .. } => 0x1100,
+            DbError::ReadTimeout { .. } => 0x1200,
+            DbError::ReadFailure { .. } => 0x1300,
+            DbError::FunctionFailure { .. } => 0x1400,
+            DbError::WriteFailure { .. } => 0x1500,
+            DbError::CdcWriteFailure => 0x1600,
+            DbError::CasWriteUnknown { .. } => 0x1700,
+            DbError::SyntaxError => 0x2000,
+            DbError::Unauthorized => 0x2100,
+            DbError::Invalid => 0x2200,
+            DbError::ConfigError => 0x2300,
+            DbError::AlreadyExists { .. } => 0x2400,
+            DbError::Unprepared { .. } => 0x2500,
+            DbError::Other(code) => *code,
+        };
+        types::write_int(code, buf);
+        types::write_string(&self.reason, buf)?;
+
+        match &self.error {
+            DbError::Unavailable {
+                consistency,
+                required,
+                alive,
+            } => {
+                types::write_consistency(*consistency, buf);
+                types::write_int(*required, buf);
+                types::write_int(*alive, buf);
+            }
+            DbError::WriteTimeout {
+                consistency,
+                received,
+                required,
+                write_type,
+            } => {
+                types::write_consistency(*consistency, buf);
+                types::write_int(*received, buf);
+                types::write_int(*required, buf);
+                types::write_string(write_type.as_str(), buf)?;
+            }
+            DbError::ReadTimeout {
+                consistency,
+                received,
+                required,
+                data_present,
+            } => {
+                types::write_consistency(*consistency, buf);
+                types::write_int(*received, buf);
+                types::write_int(*required, buf);
+                buf.put_u8(*data_present as u8);
+            }
+            DbError::ReadFailure {
+                consistency,
+                received,
+                required,
+                numfailures,
+                data_present,
+            } => {
+                types::write_consistency(*consistency, buf);
+                types::write_int(*received, buf);
+                types::write_int(*required, buf);
+                types::write_int(*numfailures, buf);
+                buf.put_u8(*data_present as u8);
+            }
+            DbError::FunctionFailure {
+                keyspace,
+                function,
+                arg_types,
+            } => {
+                types::write_string(keyspace, buf)?;
+                types::write_string(function, buf)?;
+                types::write_string_list(arg_types, buf)?;
+            }
+            DbError::WriteFailure {
+                consistency,
+                received,
+                required,
+                numfailures,
+                write_type,
+            } => {
+                types::write_consistency(*consistency, buf);
+                types::write_int(*received, buf);
+                types::write_int(*required, buf);
+                types::write_int(*numfailures, buf);
+                types::write_string(write_type.as_str(), buf)?;
+            }
+            DbError::CasWriteUnknown {
+                consistency,
+                received,
+                required,
+            } => {
+                types::write_consistency(*consistency, buf);
+                types::write_int(*received, buf);
+                types::write_int(*required, buf);
+            }
+            DbError::AlreadyExists { keyspace, table } => {
+                types::write_string(keyspace, buf)?;
+                types::write_string(table, buf)?;
+            }
+            DbError::Unprepared { statement_id } => types::write_short_bytes(statement_id, buf)?,
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+// Protocol v5 sends a map from addresses of failed nodes to their failure codes
+// instead of the number of failures
+fn read_numfailures(buf: &mut &[u8], version: ProtocolVersion) -> Result<i32, ParseError> {
+    let numfailures = types::read_int(buf)?;
+    if version >= ProtocolVersion::V5 {
+        for _ in 0..numfailures {
+            // Address of the node without port
+            let address_len = buf.read_u8()? as usize;
+            if buf.len() < address_len {
+                return Err(ParseError::BadData(
+                    "Not enough bytes for node address in failure reasons".to_string(),
+                ));
+            }
+            buf.advance(address_len);
+            // Failure code
+            types::read_short(buf)?;
+        }
+    }
+    Ok(numfailures)
 }
 
 impl From<Error> for QueryError {
@@ -84,6 +237,8 @@ mod tests {
     use super::Error;
     use crate::statement::Consistency;
     use crate::transport::errors::{DbError, WriteType};
+    use crate::transport::ProtocolVersion;
+    use bytes::Bytes;
     use std::convert::TryInto;
 
     // Serializes the beginning of an ERROR response - error code and message
@@ -102,7 +257,7 @@ mod tests {
     // Tests deserialization of all errors without and additional data
     #[test]
     fn deserialize_simple_errors() {
-        let simple_error_mappings: [(i32, DbError); 12] = [
+        let simple_error_mappings: [(i32, DbError); 11] = [
             (0x0000, DbError::ServerError),
             (0x000A, DbError::ProtocolError),
             (0x0100, DbError::AuthenticationError),
@@ -113,7 +268,6 @@ mod tests {
             (0x2100, DbError::Unauthorized),
             (0x2200, DbError::Invalid),
             (0x2300, DbError::ConfigError),
-            (0x2500, DbError::Unprepared),
             (0x1234, DbError::Other(0x1234)),
         ];
 
@@ -285,6 +439,59 @@ mod tests {
         assert_eq!(error.reason, "message 2");
     }
 
+    #[test]
+    fn deserialize_v5_failures() {
+        // Reasons of failures: two IPv4 nodes and one IPv6 node
+        let mut reasons: Vec<u8> = Vec::new();
+        reasons.extend(&3_i32.to_be_bytes());
+        for address in [&[127, 0, 0, 1][..], &[127, 0, 0, 2][..], &[0; 16][..]] {
+            reasons.push(address.len() as u8);
+            reasons.extend(address);
+            reasons.extend(&0x0001_i16.to_be_bytes());
+        }
+
+        let mut bytes = make_error_request_bytes(0x1300, "read failure");
+        bytes.extend(&0x0003_i16.to_be_bytes());
+        bytes.extend(&4_i32.to_be_bytes());
+        bytes.extend(&5_i32.to_be_bytes());
+        bytes.extend(&reasons);
+        bytes.push(0);
+
+        let error =
+            Error::deserialize_for_version(&mut bytes.as_slice(), ProtocolVersion::V5).unwrap();
+        assert_eq!(
+            error.error,
+            DbError::ReadFailure {
+                consistency: Consistency::Three,
+                received: 4,
+                required: 5,
+                numfailures: 3,
+                data_present: false,
+            }
+        );
+
+        let mut bytes = make_error_request_bytes(0x1500, "write failure");
+        bytes.extend(&0x0001_i16.to_be_bytes());
+        bytes.extend(&1_i32.to_be_bytes());
+        bytes.extend(&2_i32.to_be_bytes());
+        bytes.extend(&reasons);
+        bytes.extend(&3_u16.to_be_bytes());
+        bytes.extend(b"CAS");
+
+        let error =
+            Error::deserialize_for_version(&mut bytes.as_slice(), ProtocolVersion::V5).unwrap();
+        assert_eq!(
+            error.error,
+            DbError::WriteFailure {
+                consistency: Consistency::One,
+                received: 1,
+                required: 2,
+                numfailures: 3,
+                write_type: WriteType::Cas,
+            }
+        );
+    }
+
     #[test]
     fn deserialize_already_exists() {
         let mut bytes = make_error_request_bytes(0x2400, "message 2");
@@ -311,4 +518,92 @@ mod tests {
         );
         assert_eq!(error.reason, "message 2");
     }
+
+    #[test]
+    fn deserialize_unprepared() {
+        let mut bytes = make_error_request_bytes(0x2500, "message 3");
+        bytes.extend(&3_u16.to_be_bytes());
+        bytes.extend(&[1, 2, 3]);
+
+        let error: Error = Error::deserialize(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(
+            error.error,
+            DbError::Unprepared {
+                statement_id: Bytes::from_static(&[1, 2, 3]),
+            }
+        );
+        assert_eq!(error.reason, "message 3");
+    }
+
+    #[test]
+    fn serialize_deserialize_roundtrip() {
+        let errors = vec![
+            DbError::ServerError,
+            DbError::Overloaded,
+            DbError::Unprepared {
+                statement_id: Bytes::from_static(&[1, 2, 3]),
+            },
+            DbError::Other(0x1234),
+            DbError::Unavailable {
+                consistency: Consistency::Quorum,
+                required: 2,
+                alive: 1,
+            },
+            DbError::WriteTimeout {
+                consistency: Consistency::One,
+                received: 0,
+                required: 1,
+                write_type: WriteType::UnloggedBatch,
+            },
+            DbError::ReadTimeout {
+                consistency: Consistency::Two,
+                received: 1,
+                required: 2,
+                data_present: true,
+            },
+            DbError::ReadFailure {
+                consistency: Consistency::All,
+                received: 1,
+                required: 3,
+                numfailures: 2,
+                data_present: false,
+            },
+            DbError::FunctionFailure {
+                keyspace: "ks".to_string(),
+                function: "f".to_string(),
+                arg_types: vec!["int".to_string()],
+            },
+            DbError::WriteFailure {
+                consistency: Consistency::LocalQuorum,
+                received: 1,
+                required: 2,
+                numfailures: 1,
+                write_type: WriteType::Other("CUSTOM".to_string()),
+            },
+            DbError::AlreadyExists {
+                keyspace: "ks".to_string(),
+                table: "t".to_string(),
+            },
+            DbError::CdcWriteFailure,
+            DbError::CasWriteUnknown {
+                consistency: Consistency::Serial,
+                received: 1,
+                required: 2,
+            },
+        ];
+
+        for db_error in errors {
+            let error = Error {
+                error: db_error.clone(),
+                reason: "reason".to_string(),
+            };
+            let mut bytes: Vec<u8> = Vec::new();
+            error.serialize(&mut bytes).unwrap();
+
+            let deserialized: Error = Error::deserialize(&mut bytes.as_slice()).unwrap();
+            assert_eq!(deserialized.error, db_error);
+            assert_eq!(deserialized.reason, "reason");
+        }
+    }
 }
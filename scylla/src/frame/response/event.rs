@@ -3,26 +3,26 @@ use crate::frame::server_event_type::EventType;
 use crate::frame::types;
 use std::net::SocketAddr;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Event {
     TopologyChange(TopologyChangeEvent),
     StatusChange(StatusChangeEvent),
     SchemaChange(SchemaChangeEvent),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TopologyChangeEvent {
     NewNode(SocketAddr),
     RemovedNode(SocketAddr),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum StatusChangeEvent {
     Up(SocketAddr),
     Down(SocketAddr),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum SchemaChangeEvent {
     KeyspaceChange {
         change_type: SchemaChangeType,
@@ -52,7 +52,7 @@ pub enum SchemaChangeEvent {
     },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum SchemaChangeType {
     Created,
     Updated,
@@ -74,6 +74,17 @@ impl Event {
 }
 
 impl SchemaChangeEvent {
+    /// Name of the keyspace affected by the change
+    pub fn keyspace_name(&self) -> &str {
+        match self {
+            Self::KeyspaceChange { keyspace_name, .. }
+            | Self::TableChange { keyspace_name, .. }
+            | Self::TypeChange { keyspace_name, .. }
+            | Self::FunctionChange { keyspace_name, .. }
+            | Self::AggregateChange { keyspace_name, .. } => keyspace_name,
+        }
+    }
+
     pub fn deserialize(buf: &mut &[u8]) -> Result<Self, ParseError> {
         let type_of_change_string = types::read_string(buf)?;
         let type_of_change = match type_of_change_string {
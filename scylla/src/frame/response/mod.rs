@@ -6,6 +6,8 @@ pub mod result;
 pub mod supported;
 
 use crate::frame::frame_errors::ParseError;
+use crate::transport::ProtocolVersion;
+use bytes::Bytes;
 use num_enum::TryFromPrimitive;
 
 pub use error::Error;
@@ -37,15 +39,26 @@ pub enum Response {
 }
 
 impl Response {
-    pub fn deserialize(opcode: ResponseOpcode, buf: &mut &[u8]) -> Result<Response, ParseError> {
+    pub fn deserialize(opcode: ResponseOpcode, body: &Bytes) -> Result<Response, ParseError> {
+        Self::deserialize_for_version(opcode, body, ProtocolVersion::V4)
+    }
+
+    pub fn deserialize_for_version(
+        opcode: ResponseOpcode,
+        body: &Bytes,
+        version: ProtocolVersion,
+    ) -> Result<Response, ParseError> {
+        let buf = &mut &body[..];
         let response = match opcode {
-            ResponseOpcode::Error => Response::Error(Error::deserialize(buf)?),
+            ResponseOpcode::Error => Response::Error(Error::deserialize_for_version(buf, version)?),
             ResponseOpcode::Ready => Response::Ready,
             ResponseOpcode::Authenticate => {
                 Response::Authenticate(authenticate::Authenticate::deserialize(buf)?)
             }
             ResponseOpcode::Supported => Response::Supported(Supported::deserialize(buf)?),
-            ResponseOpcode::Result => Response::Result(result::deserialize(buf)?),
+            ResponseOpcode::Result => {
+                Response::Result(result::deserialize_for_version(body, version)?)
+            }
             ResponseOpcode::Event => Response::Event(event::Event::deserialize(buf)?),
             ResponseOpcode::AuthChallenge => {
                 Response::AuthChallenge(authenticate::AuthChallenge::deserialize(buf)?)
@@ -57,4 +70,22 @@ impl Response {
 
         Ok(response)
     }
+
+    /// Name of the response type used in error messages, e.g. `READY` or `RESULT Rows`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Response::Error(_) => "ERROR",
+            Response::Ready => "READY",
+            Response::Result(result::Result::Void) => "RESULT Void",
+            Response::Result(result::Result::Rows(_)) => "RESULT Rows",
+            Response::Result(result::Result::SetKeyspace(_)) => "RESULT SetKeyspace",
+            Response::Result(result::Result::Prepared(_)) => "RESULT Prepared",
+            Response::Result(result::Result::SchemaChange(_)) => "RESULT SchemaChange",
+            Response::Authenticate(_) => "AUTHENTICATE",
+            Response::AuthSuccess(_) => "AUTH_SUCCESS",
+            Response::AuthChallenge(_) => "AUTH_CHALLENGE",
+            Response::Supported(_) => "SUPPORTED",
+            Response::Event(_) => "EVENT",
+        }
+    }
 }
@@ -1,7 +1,10 @@
-use crate::cql_to_rust::{FromRow, FromRowError};
+use crate::cql_to_rust::{
+    convert_column, ColumnContext, FromCqlVal, FromCqlValError, FromRow, FromRowError,
+};
 use crate::frame::response::event::SchemaChangeEvent;
-use crate::frame::value::Counter;
+use crate::frame::value::{Counter, CqlDuration};
 use crate::frame::{frame_errors::ParseError, types};
+use crate::transport::ProtocolVersion;
 use bigdecimal::BigDecimal;
 use byteorder::{BigEndian, ReadBytesExt};
 use bytes::{Buf, Bytes};
@@ -9,12 +12,14 @@ use chrono::prelude::*;
 use chrono::Duration;
 use num_bigint::BigInt;
 use std::{
-    collections::BTreeMap,
+    collections::HashMap,
     convert::{TryFrom, TryInto},
     net::IpAddr,
     result::Result as StdResult,
     str,
+    sync::Arc,
 };
+use thiserror::Error;
 use uuid::Uuid;
 
 #[derive(Debug)]
@@ -25,6 +30,8 @@ pub struct SetKeyspace {
 #[derive(Debug)]
 pub struct Prepared {
     pub id: Bytes,
+    /// Id of the result metadata, sent only in protocol v5
+    pub result_metadata_id: Option<Bytes>,
     pub prepared_metadata: PreparedMetadata,
     result_metadata: ResultMetadata,
 }
@@ -34,14 +41,28 @@ pub struct SchemaChange {
     pub event: SchemaChangeEvent,
 }
 
-#[derive(Clone, Debug)]
+/// Keyspace and table of a column
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct TableSpec {
     pub ks_name: String,
     table_name: String,
 }
 
-#[derive(Debug, Clone)]
-enum ColumnType {
+impl TableSpec {
+    /// Name of the keyspace
+    pub fn ks_name(&self) -> &str {
+        &self.ks_name
+    }
+
+    /// Name of the table
+    pub fn table_name(&self) -> &str {
+        &self.table_name
+    }
+}
+
+/// CQL type of a column
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColumnType {
     Ascii,
     Boolean,
     Blob,
@@ -49,6 +70,7 @@ enum ColumnType {
     Date,
     Decimal,
     Double,
+    Duration,
     Float,
     Int,
     BigInt,
@@ -72,7 +94,53 @@ enum ColumnType {
     Varint,
 }
 
-#[derive(Debug, PartialEq)]
+// Formats the type as it is written in CQL, e.g. map<text, list<int>>
+impl std::fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColumnType::Ascii => write!(f, "ascii"),
+            ColumnType::Boolean => write!(f, "boolean"),
+            ColumnType::Blob => write!(f, "blob"),
+            ColumnType::Counter => write!(f, "counter"),
+            ColumnType::Date => write!(f, "date"),
+            ColumnType::Decimal => write!(f, "decimal"),
+            ColumnType::Duration => write!(f, "duration"),
+            ColumnType::Double => write!(f, "double"),
+            ColumnType::Float => write!(f, "float"),
+            ColumnType::Int => write!(f, "int"),
+            ColumnType::BigInt => write!(f, "bigint"),
+            ColumnType::Text => write!(f, "text"),
+            ColumnType::Timestamp => write!(f, "timestamp"),
+            ColumnType::Inet => write!(f, "inet"),
+            ColumnType::List(elem) => write!(f, "list<{}>", elem),
+            ColumnType::Map(key, value) => write!(f, "map<{}, {}>", key, value),
+            ColumnType::Set(elem) => write!(f, "set<{}>", elem),
+            ColumnType::UserDefinedType {
+                type_name,
+                keyspace,
+                ..
+            } => write!(f, "{}.{}", keyspace, type_name),
+            ColumnType::SmallInt => write!(f, "smallint"),
+            ColumnType::TinyInt => write!(f, "tinyint"),
+            ColumnType::Time => write!(f, "time"),
+            ColumnType::Timeuuid => write!(f, "timeuuid"),
+            ColumnType::Tuple(elems) => {
+                write!(f, "tuple<")?;
+                for (i, elem) in elems.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", elem)?;
+                }
+                write!(f, ">")
+            }
+            ColumnType::Uuid => write!(f, "uuid"),
+            ColumnType::Varint => write!(f, "varint"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum CqlValue {
     Ascii(String),
     Boolean(bool),
@@ -83,6 +151,7 @@ pub enum CqlValue {
     /// Can be converted to chrono::NaiveDate (-262145-1-1 to 262143-12-31) using as_date
     Date(u32),
     Double(f64),
+    Duration(CqlDuration),
     Float(f32),
     Int(i32),
     BigInt(i64),
@@ -96,7 +165,8 @@ pub enum CqlValue {
     UserDefinedType {
         keyspace: String,
         type_name: String,
-        fields: BTreeMap<String, Option<CqlValue>>,
+        /// Order of `fields` vector must match the order of fields as defined in the UDT
+        fields: Vec<(String, Option<CqlValue>)>,
     },
     SmallInt(i16),
     TinyInt(i8),
@@ -109,6 +179,37 @@ pub enum CqlValue {
 }
 
 impl CqlValue {
+    /// Returns the name of this value's CQL type, without the types of nested values
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Ascii(_) => "ascii",
+            Self::Boolean(_) => "boolean",
+            Self::Blob(_) => "blob",
+            Self::Counter(_) => "counter",
+            Self::Decimal(_) => "decimal",
+            Self::Date(_) => "date",
+            Self::Double(_) => "double",
+            Self::Duration(_) => "duration",
+            Self::Float(_) => "float",
+            Self::Int(_) => "int",
+            Self::BigInt(_) => "bigint",
+            Self::Text(_) => "text",
+            Self::Timestamp(_) => "timestamp",
+            Self::Inet(_) => "inet",
+            Self::List(_) => "list",
+            Self::Map(_) => "map",
+            Self::Set(_) => "set",
+            Self::UserDefinedType { .. } => "user defined type",
+            Self::SmallInt(_) => "smallint",
+            Self::TinyInt(_) => "tinyint",
+            Self::Time(_) => "time",
+            Self::Timeuuid(_) => "timeuuid",
+            Self::Tuple(_) => "tuple",
+            Self::Uuid(_) => "uuid",
+            Self::Varint(_) => "varint",
+        }
+    }
+
     pub fn as_ascii(&self) -> Option<&String> {
         match self {
             Self::Ascii(s) => Some(&s),
@@ -138,6 +239,13 @@ impl CqlValue {
         }
     }
 
+    pub fn as_cql_duration(&self) -> Option<CqlDuration> {
+        match self {
+            Self::Duration(d) => Some(*d),
+            _ => None,
+        }
+    }
+
     pub fn as_counter(&self) -> Option<Counter> {
         match self {
             Self::Counter(i) => Some(*i),
@@ -290,13 +398,43 @@ impl CqlValue {
     // TODO
 }
 
-#[derive(Debug, Clone)]
+/// Name, type and table of a column returned by the database or of a bind marker
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ColumnSpec {
     pub table_spec: TableSpec,
     name: String,
     typ: ColumnType,
 }
 
+impl ColumnSpec {
+    #[cfg(test)]
+    pub(crate) fn new(name: &str, typ: ColumnType) -> Self {
+        ColumnSpec {
+            table_spec: TableSpec {
+                ks_name: "ks".to_string(),
+                table_name: "t".to_string(),
+            },
+            name: name.to_string(),
+            typ,
+        }
+    }
+
+    /// Name of the column
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// CQL type of the column
+    pub fn typ(&self) -> &ColumnType {
+        &self.typ
+    }
+
+    /// Keyspace and table of the column
+    pub fn table_spec(&self) -> &TableSpec {
+        &self.table_spec
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct ResultMetadata {
     col_count: usize,
@@ -304,13 +442,29 @@ pub struct ResultMetadata {
     col_specs: Vec<ColumnSpec>,
 }
 
+impl ResultMetadata {
+    /// Specifications of the returned columns, in the order of values in each row
+    pub fn col_specs(&self) -> &[ColumnSpec] {
+        &self.col_specs
+    }
+}
+
+/// Metadata of a prepared statement, describing its bind markers
 #[derive(Debug, Clone)]
 pub struct PreparedMetadata {
+    pub(crate) flags: i32,
     pub col_count: usize,
     pub pk_indexes: Vec<u16>,
     pub col_specs: Vec<ColumnSpec>,
 }
 
+impl PreparedMetadata {
+    /// Flags of the metadata, Scylla marks LWT statements with a bit negotiated in STARTUP
+    pub fn flags(&self) -> i32 {
+        self.flags
+    }
+}
+
 #[derive(Debug, Default, PartialEq)]
 pub struct Row {
     pub columns: Vec<Option<CqlValue>>,
@@ -321,13 +475,462 @@ impl Row {
     pub fn into_typed<RowT: FromRow>(self) -> StdResult<RowT, FromRowError> {
         RowT::from_row(self)
     }
+
+    /// Converts Row into [`NamedRow`], which allows accessing values by column names.  
+    /// `col_specs` are the specifications of the columns of this row, e.g. `QueryResult::col_specs`
+    pub fn into_named(self, col_specs: &[ColumnSpec]) -> NamedRow {
+        NamedRow::new(col_names(col_specs), self)
+    }
+}
+
+/// Collects names of the columns, to be shared by many [`NamedRow`]s
+pub(crate) fn col_names(col_specs: &[ColumnSpec]) -> Arc<[String]> {
+    col_specs.iter().map(|spec| spec.name.clone()).collect()
 }
 
+/// A row with values accessible by column names  
+/// Useful when columns of the result aren't known at compile time, e.g. in tools running queries
+/// given by the user. Names of the columns are shared by all rows of a result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedRow {
+    names: Arc<[String]>,
+    columns: Vec<Option<CqlValue>>,
+}
+
+/// Error returned when accessing a column of a [`NamedRow`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum NamedColumnError {
+    #[error("No column named {0} in the row")]
+    NoSuchColumn(String),
+    #[error("Bad CQL value in column {column}: {err}")]
+    BadCqlVal {
+        err: FromCqlValError,
+        column: ColumnContext,
+    },
+}
+
+impl NamedRow {
+    pub(crate) fn new(names: Arc<[String]>, row: Row) -> Self {
+        NamedRow {
+            names,
+            columns: row.columns,
+        }
+    }
+
+    /// Returns the number of columns in this row
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Checks whether this row has no columns
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Returns names of the columns, in the order of their values
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(String::as_str)
+    }
+
+    /// Returns the value of the column with the given name, `None` if there is no such column.  
+    /// `Some(None)` means that the value is null
+    pub fn value(&self, name: &str) -> Option<Option<&CqlValue>> {
+        let index = self.index_of(name)?;
+        self.columns.get(index).map(Option::as_ref)
+    }
+
+    /// Converts the value of the column with the given name to a rust type
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::frame::response::result::{NamedColumnError, NamedRow};
+    /// # fn example(row: NamedRow) -> Result<(), NamedColumnError> {
+    /// let a: i32 = row.get("a")?;
+    /// let b: Option<String> = row.get("b")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get<T: FromCqlVal<Option<CqlValue>>>(
+        &self,
+        name: &str,
+    ) -> StdResult<T, NamedColumnError> {
+        let no_such_column = || NamedColumnError::NoSuchColumn(name.to_string());
+        let index = self.index_of(name).ok_or_else(no_such_column)?;
+        let value = self.columns.get(index).ok_or_else(no_such_column)?;
+
+        convert_column(index, value.clone()).map_err(|(err, mut column)| {
+            column.name = Some(name.to_string());
+            NamedColumnError::BadCqlVal { err, column }
+        })
+    }
+
+    /// Returns an iterator over pairs of column names and values
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&CqlValue>)> {
+        self.names().zip(self.columns.iter().map(Option::as_ref))
+    }
+
+    /// Converts this row into a map from column names to values
+    pub fn into_map(self) -> HashMap<String, Option<CqlValue>> {
+        self.names.iter().cloned().zip(self.columns).collect()
+    }
+
+    /// Converts this row back into a [`Row`], dropping column names
+    pub fn into_row(self) -> Row {
+        Row {
+            columns: self.columns,
+        }
+    }
+
+    fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|col_name| col_name == name)
+    }
+}
+
+/// Rows returned by the database  
+/// Rows are kept in the serialized form in which they were received and deserialized only when they are read.
+/// Iterating over `Rows` copies each value into an owned [`Row`],
+/// [`Rows::raw_rows`] reads values straight from the received data instead.
 #[derive(Debug, Default)]
 pub struct Rows {
     pub metadata: ResultMetadata,
     rows_count: usize,
-    pub rows: Vec<Row>,
+    raw_rows: Bytes,
+}
+
+impl Rows {
+    /// Returns the number of rows in this result
+    pub fn rows_count(&self) -> usize {
+        self.rows_count
+    }
+
+    /// Deserializes all rows at once
+    pub fn into_rows(self) -> StdResult<Vec<Row>, ParseError> {
+        self.into_iter().collect()
+    }
+
+    // Serializes rows with the given columns, used to build results in tests
+    #[cfg(test)]
+    pub(crate) fn new_for_test(
+        col_specs: Vec<ColumnSpec>,
+        rows: Vec<Vec<Option<CqlValue>>>,
+    ) -> Self {
+        use crate::frame::value::Value;
+
+        let mut raw_rows = Vec::new();
+        for row in &rows {
+            for value in row {
+                value.serialize(&mut raw_rows).unwrap();
+            }
+        }
+
+        Rows {
+            metadata: ResultMetadata {
+                col_count: col_specs.len(),
+                paging_state: None,
+                col_specs,
+            },
+            rows_count: rows.len(),
+            raw_rows: raw_rows.into(),
+        }
+    }
+
+    /// Iterates over rows without deserializing them  
+    /// Values in each [`RawRow`] are decoded only when they are accessed
+    pub fn raw_rows(&self) -> RawRowsIter<'_> {
+        RawRowsIter {
+            col_specs: &self.metadata.col_specs,
+            remaining_rows: self.rows_count,
+            buf: &self.raw_rows,
+        }
+    }
+}
+
+impl IntoIterator for Rows {
+    type Item = StdResult<Row, ParseError>;
+    type IntoIter = RowsIntoIter;
+
+    fn into_iter(self) -> RowsIntoIter {
+        RowsIntoIter {
+            metadata: self.metadata,
+            remaining_rows: self.rows_count,
+            raw_rows: self.raw_rows,
+        }
+    }
+}
+
+/// Iterator deserializing [`Rows`] one at a time into owned [`Row`]s  
+/// After a malformed row it returns no more rows, as the following ones can't be located
+#[derive(Debug, Default)]
+pub struct RowsIntoIter {
+    metadata: ResultMetadata,
+    remaining_rows: usize,
+    raw_rows: Bytes,
+}
+
+impl RowsIntoIter {
+    /// Metadata of the rows being deserialized
+    pub fn metadata(&self) -> &ResultMetadata {
+        &self.metadata
+    }
+
+    /// Returns metadata of the rows, dropping the remaining ones
+    pub fn into_metadata(self) -> ResultMetadata {
+        self.metadata
+    }
+
+    /// Returns rows which weren't deserialized yet
+    pub fn into_remaining_rows(self) -> Rows {
+        Rows {
+            metadata: self.metadata,
+            rows_count: self.remaining_rows,
+            raw_rows: self.raw_rows,
+        }
+    }
+}
+
+impl Iterator for RowsIntoIter {
+    type Item = StdResult<Row, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining_rows == 0 {
+            return None;
+        }
+
+        let mut buf: &[u8] = &self.raw_rows;
+        let row_res = deser_row(&self.metadata.col_specs, &mut buf);
+        let row_size = self.raw_rows.len() - buf.len();
+
+        match row_res {
+            Ok(row) => {
+                self.raw_rows.advance(row_size);
+                self.remaining_rows -= 1;
+                Some(Ok(row))
+            }
+            Err(e) => {
+                // Following rows can't be located after a malformed one
+                self.remaining_rows = 0;
+                Some(Err(e))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining_rows, Some(self.remaining_rows))
+    }
+}
+
+impl ExactSizeIterator for RowsIntoIter {}
+
+/// Iterator over [`RawRow`]s, returned by [`Rows::raw_rows`]
+#[derive(Debug, Clone)]
+pub struct RawRowsIter<'a> {
+    col_specs: &'a [ColumnSpec],
+    remaining_rows: usize,
+    buf: &'a [u8],
+}
+
+impl<'a> Iterator for RawRowsIter<'a> {
+    type Item = StdResult<RawRow<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining_rows == 0 {
+            return None;
+        }
+
+        // Only lengths of the values are read to find where each of them is
+        let mut columns = Vec::with_capacity(self.col_specs.len());
+        for _ in self.col_specs {
+            match types::read_bytes_opt(&mut self.buf) {
+                Ok(value) => columns.push(value),
+                Err(e) => {
+                    // Following rows can't be located after a malformed one
+                    self.remaining_rows = 0;
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        self.remaining_rows -= 1;
+        Some(Ok(RawRow {
+            col_specs: self.col_specs,
+            columns,
+        }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining_rows, Some(self.remaining_rows))
+    }
+}
+
+impl ExactSizeIterator for RawRowsIter<'_> {}
+
+/// A row which wasn't deserialized yet  
+/// Each column is decoded only when it's accessed, so reading a subset of columns
+/// doesn't pay for decoding the rest. Strings and blobs can be borrowed without copying.
+#[derive(Debug, Clone)]
+pub struct RawRow<'a> {
+    col_specs: &'a [ColumnSpec],
+    columns: Vec<Option<&'a [u8]>>,
+}
+
+/// Error returned when accessing a column of a [`RawRow`]
+#[derive(Error, Debug)]
+pub enum RawColumnError {
+    #[error("No column with index {0} in the row")]
+    NoSuchColumn(usize),
+    #[error("No column named {0} in the row")]
+    NoSuchColumnName(String),
+    #[error(transparent)]
+    FromCqlValError(#[from] FromCqlValError),
+    #[error(transparent)]
+    ParseError(#[from] ParseError),
+}
+
+impl<'a> RawRow<'a> {
+    /// Returns the number of columns in this row
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Checks whether this row has no columns
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Decodes the value of the column with the given index
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::frame::response::result::{RawColumnError, RawRow};
+    /// # fn example(row: RawRow) -> Result<(), RawColumnError> {
+    /// let a: i32 = row.column(0)?;
+    /// // Text is borrowed from the received rows
+    /// let b: Option<&str> = row.column(1)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn column<T: FromRawCqlVal<'a>>(&self, index: usize) -> StdResult<T, RawColumnError> {
+        let value = self
+            .columns
+            .get(index)
+            .ok_or(RawColumnError::NoSuchColumn(index))?;
+
+        T::from_raw_cql(&self.col_specs[index].typ, *value)
+    }
+
+    /// Decodes the value of the column with the given name
+    pub fn column_by_name<T: FromRawCqlVal<'a>>(&self, name: &str) -> StdResult<T, RawColumnError> {
+        let index = self
+            .col_specs
+            .iter()
+            .position(|col_spec| col_spec.name == name)
+            .ok_or_else(|| RawColumnError::NoSuchColumnName(name.to_string()))?;
+
+        self.column(index)
+    }
+
+    /// Returns the serialized value of the column with the given index, `None` inside means null
+    pub fn raw_column(&self, index: usize) -> Option<Option<&'a [u8]>> {
+        self.columns.get(index).copied()
+    }
+
+    /// Decodes all columns of this row
+    pub fn into_row(self) -> StdResult<Row, ParseError> {
+        let mut columns = Vec::with_capacity(self.columns.len());
+        for (col_spec, value) in self.col_specs.iter().zip(self.columns) {
+            columns.push(match value {
+                Some(mut buf) => Some(deser_cql_value(&col_spec.typ, &mut buf)?),
+                None => None,
+            });
+        }
+        Ok(Row { columns })
+    }
+}
+
+/// This trait defines a way to decode a rust type straight from a serialized CQL value  
+/// Unlike [`FromCqlVal`] it can borrow from the received data, e.g. `&str` or `&[u8]`
+pub trait FromRawCqlVal<'a>: Sized {
+    /// `value` is `None` if the value is null
+    fn from_raw_cql(typ: &ColumnType, value: Option<&'a [u8]>) -> StdResult<Self, RawColumnError>;
+}
+
+impl<'a, T: FromRawCqlVal<'a>> FromRawCqlVal<'a> for Option<T> {
+    fn from_raw_cql(typ: &ColumnType, value: Option<&'a [u8]>) -> StdResult<Self, RawColumnError> {
+        match value {
+            Some(_) => Ok(Some(T::from_raw_cql(typ, value)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<'a> FromRawCqlVal<'a> for &'a str {
+    fn from_raw_cql(typ: &ColumnType, value: Option<&'a [u8]>) -> StdResult<Self, RawColumnError> {
+        let buf = value.ok_or(FromCqlValError::ValIsNull)?;
+        match typ {
+            ColumnType::Ascii if !buf.is_ascii() => {
+                Err(ParseError::BadData("String is not ascii!".to_string()).into())
+            }
+            ColumnType::Ascii | ColumnType::Text => Ok(str::from_utf8(buf)
+                .map_err(|e| ParseError::BadData(format!("Invalid UTF-8 string: {}", e)))?),
+            _ => Err(FromCqlValError::BadCqlType.into()),
+        }
+    }
+}
+
+impl<'a> FromRawCqlVal<'a> for &'a [u8] {
+    fn from_raw_cql(typ: &ColumnType, value: Option<&'a [u8]>) -> StdResult<Self, RawColumnError> {
+        let buf = value.ok_or(FromCqlValError::ValIsNull)?;
+        match typ {
+            ColumnType::Blob => Ok(buf),
+            _ => Err(FromCqlValError::BadCqlType.into()),
+        }
+    }
+}
+
+// Owned types are decoded through CqlValue, which doesn't allocate for fixed size values
+macro_rules! impl_from_raw_cql_val_using_cql_value {
+    ($($T:ty),*) => {
+        $(
+            impl<'a> FromRawCqlVal<'a> for $T {
+                fn from_raw_cql(
+                    typ: &ColumnType,
+                    value: Option<&'a [u8]>,
+                ) -> StdResult<Self, RawColumnError> {
+                    let mut buf = value.ok_or(FromCqlValError::ValIsNull)?;
+                    let cql_val = deser_cql_value(typ, &mut buf)?;
+                    Ok(<$T>::from_cql(cql_val)?)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_raw_cql_val_using_cql_value!(
+    i8,
+    i16,
+    i32,
+    i64,
+    f32,
+    f64,
+    bool,
+    Counter,
+    CqlDuration,
+    Uuid,
+    IpAddr,
+    Duration,
+    NaiveDate,
+    DateTime<Utc>,
+    BigInt,
+    BigDecimal,
+    String,
+    Vec<u8>
+);
+
+impl<'a> FromRawCqlVal<'a> for CqlValue {
+    fn from_raw_cql(typ: &ColumnType, value: Option<&'a [u8]>) -> StdResult<Self, RawColumnError> {
+        let mut buf = value.ok_or(FromCqlValError::ValIsNull)?;
+        Ok(deser_cql_value(typ, &mut buf)?)
+    }
 }
 
 #[derive(Debug)]
@@ -371,6 +974,7 @@ fn deser_type(buf: &mut &[u8]) -> StdResult<ColumnType, ParseError> {
         0x0012 => Time,
         0x0013 => SmallInt,
         0x0014 => TinyInt,
+        0x0015 => Duration,
         0x0020 => List(Box::new(deser_type(buf)?)),
         0x0021 => Map(Box::new(deser_type(buf)?), Box::new(deser_type(buf)?)),
         0x0022 => Set(Box::new(deser_type(buf)?)),
@@ -437,6 +1041,8 @@ fn deser_result_metadata(buf: &mut &[u8]) -> StdResult<ResultMetadata, ParseErro
     let global_tables_spec = flags & 0x0001 != 0;
     let has_more_pages = flags & 0x0002 != 0;
     let no_metadata = flags & 0x0004 != 0;
+    // Protocol v5 only, the metadata changed since the statement was prepared
+    let metadata_changed = flags & 0x0008 != 0;
 
     let col_count: usize = types::read_int(buf)?.try_into()?;
 
@@ -446,6 +1052,12 @@ fn deser_result_metadata(buf: &mut &[u8]) -> StdResult<ResultMetadata, ParseErro
         None
     };
 
+    if metadata_changed {
+        // Id of the new metadata, the metadata itself is always sent
+        // because the driver doesn't skip metadata in results
+        types::read_short_bytes(buf)?;
+    }
+
     if no_metadata {
         return Ok(ResultMetadata {
             col_count,
@@ -491,6 +1103,7 @@ fn deser_prepared_metadata(buf: &mut &[u8]) -> StdResult<PreparedMetadata, Parse
     let col_specs = deser_col_specs(buf, &global_table_spec, col_count)?;
 
     Ok(PreparedMetadata {
+        flags,
         col_count,
         pk_indexes,
         col_specs,
@@ -552,6 +1165,17 @@ fn deser_cql_value(typ: &ColumnType, buf: &mut &[u8]) -> StdResult<CqlValue, Par
             }
             CqlValue::Double(buf.read_f64::<BigEndian>()?)
         }
+        Duration => {
+            let months = i32::try_from(types::read_vint(buf)?)?;
+            let days = i32::try_from(types::read_vint(buf)?)?;
+            let nanoseconds = types::read_vint(buf)?;
+
+            CqlValue::Duration(CqlDuration {
+                months,
+                days,
+                nanoseconds,
+            })
+        }
         Float => {
             if buf.len() != 4 {
                 return Err(ParseError::BadData(format!(
@@ -608,7 +1232,7 @@ fn deser_cql_value(typ: &ColumnType, buf: &mut &[u8]) -> StdResult<CqlValue, Par
             }
             let millis = buf.read_i64::<BigEndian>()?;
 
-            CqlValue::Timestamp(Duration::milliseconds(millis))
+            CqlValue::Timestamp(chrono::Duration::milliseconds(millis))
         }
         Time => {
             if buf.len() != 8 {
@@ -626,7 +1250,7 @@ fn deser_cql_value(typ: &ColumnType, buf: &mut &[u8]) -> StdResult<CqlValue, Par
                 }));
             }
 
-            CqlValue::Time(Duration::nanoseconds(nanoseconds))
+            CqlValue::Time(chrono::Duration::nanoseconds(nanoseconds))
         }
         Timeuuid => {
             if buf.len() != 16 {
@@ -703,7 +1327,7 @@ fn deser_cql_value(typ: &ColumnType, buf: &mut &[u8]) -> StdResult<CqlValue, Par
             keyspace,
             field_types,
         } => {
-            let mut fields: BTreeMap<String, Option<CqlValue>> = BTreeMap::new();
+            let mut fields: Vec<(String, Option<CqlValue>)> = Vec::with_capacity(field_types.len());
 
             for (field_name, field_type) in field_types {
                 let mut field_value: Option<CqlValue> = None;
@@ -711,7 +1335,7 @@ fn deser_cql_value(typ: &ColumnType, buf: &mut &[u8]) -> StdResult<CqlValue, Par
                     field_value = Some(deser_cql_value(&field_type, &mut field_val_bytes)?);
                 }
 
-                fields.insert(field_name.clone(), field_value);
+                fields.push((field_name.clone(), field_value));
             }
 
             CqlValue::UserDefinedType {
@@ -731,7 +1355,20 @@ fn deser_cql_value(typ: &ColumnType, buf: &mut &[u8]) -> StdResult<CqlValue, Par
     })
 }
 
-fn deser_rows(buf: &mut &[u8]) -> StdResult<Rows, ParseError> {
+fn deser_row(col_specs: &[ColumnSpec], buf: &mut &[u8]) -> StdResult<Row, ParseError> {
+    let mut columns = Vec::with_capacity(col_specs.len());
+    for col_spec in col_specs {
+        let v = if let Some(mut b) = types::read_bytes_opt(buf)? {
+            Some(deser_cql_value(&col_spec.typ, &mut b)?)
+        } else {
+            None
+        };
+        columns.push(v);
+    }
+    Ok(Row { columns })
+}
+
+fn deser_rows(body: &Bytes, buf: &mut &[u8]) -> StdResult<Rows, ParseError> {
     let metadata = deser_result_metadata(buf)?;
 
     // TODO: the protocol allows an optimization (which must be explicitly requested on query by
@@ -742,23 +1379,14 @@ fn deser_rows(buf: &mut &[u8]) -> StdResult<Rows, ParseError> {
 
     let rows_count: usize = types::read_int(buf)?.try_into()?;
 
-    let mut rows = Vec::with_capacity(rows_count);
-    for _ in 0..rows_count {
-        let mut columns = Vec::with_capacity(metadata.col_count);
-        for i in 0..metadata.col_count {
-            let v = if let Some(mut b) = types::read_bytes_opt(buf)? {
-                Some(deser_cql_value(&metadata.col_specs[i].typ, &mut b)?)
-            } else {
-                None
-            };
-            columns.push(v);
-        }
-        rows.push(Row { columns });
-    }
+    // Rows take the rest of the body, keep them without copying
+    let raw_rows = body.slice_ref(buf);
+    buf.advance(buf.len());
+
     Ok(Rows {
         metadata,
         rows_count,
-        rows,
+        raw_rows,
     })
 }
 
@@ -768,14 +1396,20 @@ fn deser_set_keyspace(buf: &mut &[u8]) -> StdResult<SetKeyspace, ParseError> {
     Ok(SetKeyspace { keyspace_name })
 }
 
-fn deser_prepared(buf: &mut &[u8]) -> StdResult<Prepared, ParseError> {
+fn deser_prepared(buf: &mut &[u8], version: ProtocolVersion) -> StdResult<Prepared, ParseError> {
     let id_len = types::read_short(buf)? as usize;
     let id: Bytes = buf[0..id_len].to_owned().into();
     buf.advance(id_len);
+    let result_metadata_id = if version >= ProtocolVersion::V5 {
+        Some(Bytes::copy_from_slice(types::read_short_bytes(buf)?))
+    } else {
+        None
+    };
     let prepared_metadata = deser_prepared_metadata(buf)?;
     let result_metadata = deser_result_metadata(buf)?;
     Ok(Prepared {
         id,
+        result_metadata_id,
         prepared_metadata,
         result_metadata,
     })
@@ -788,13 +1422,21 @@ fn deser_schema_change(buf: &mut &[u8]) -> StdResult<SchemaChange, ParseError> {
     })
 }
 
-pub fn deserialize(buf: &mut &[u8]) -> StdResult<Result, ParseError> {
+pub fn deserialize(body: &Bytes) -> StdResult<Result, ParseError> {
+    deserialize_for_version(body, ProtocolVersion::V4)
+}
+
+pub fn deserialize_for_version(
+    body: &Bytes,
+    version: ProtocolVersion,
+) -> StdResult<Result, ParseError> {
     use self::Result::*;
+    let buf = &mut &body[..];
     Ok(match types::read_int(buf)? {
         0x0001 => Void,
-        0x0002 => Rows(deser_rows(buf)?),
+        0x0002 => Rows(deser_rows(body, buf)?),
         0x0003 => SetKeyspace(deser_set_keyspace(buf)?),
-        0x0004 => Prepared(deser_prepared(buf)?),
+        0x0004 => Prepared(deser_prepared(buf, version)?),
         0x0005 => SchemaChange(deser_schema_change(buf)?),
         k => {
             return Err(ParseError::BadData(format!(
@@ -808,7 +1450,7 @@ pub fn deserialize(buf: &mut &[u8]) -> StdResult<Result, ParseError> {
 #[cfg(test)]
 mod tests {
     use crate as scylla;
-    use crate::frame::value::Counter;
+    use crate::frame::value::{Counter, CqlDuration};
     use bigdecimal::BigDecimal;
     use chrono::Duration;
     use chrono::NaiveDate;
@@ -1236,4 +1878,221 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_duration_deserialize() {
+        let mut typ_buf: &[u8] = &[0x00, 0x15];
+        let typ = super::deser_type(&mut typ_buf).unwrap();
+        assert!(matches!(typ, ColumnType::Duration));
+
+        // 1 month, -2 days, 3 nanoseconds as zigzag encoded vints
+        let buf: &[u8] = &[0x02, 0x03, 0x06];
+        let cql_val = super::deser_cql_value(&typ, &mut &buf[..]).unwrap();
+        let expected = CqlDuration {
+            months: 1,
+            days: -2,
+            nanoseconds: 3,
+        };
+        assert_eq!(cql_val, CqlValue::Duration(expected));
+        assert_eq!(cql_val.as_cql_duration(), Some(expected));
+
+        // Months don't fit in i32
+        let buf: &[u8] = &[0xf8, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        assert!(super::deser_cql_value(&typ, &mut &buf[..]).is_err());
+    }
+
+    // Serializes a Rows result with columns (a int, b text) containing the given rows
+    fn rows_result_body(rows: &[(i32, Option<&str>)]) -> bytes::Bytes {
+        use super::{ColumnSpec, ColumnType, CqlValue, Rows};
+        use crate::frame::types;
+
+        let rows = Rows::new_for_test(
+            vec![
+                ColumnSpec::new("a", ColumnType::Int),
+                ColumnSpec::new("b", ColumnType::Text),
+            ],
+            rows.iter()
+                .map(|(a, b)| vec![Some(CqlValue::Int(*a)), b.map(|b| CqlValue::Text(b.into()))])
+                .collect(),
+        );
+
+        let mut buf: Vec<u8> = Vec::new();
+        types::write_int(0x0002, &mut buf); // Rows
+        types::write_int(0x0001, &mut buf); // Global table spec
+        types::write_int(2, &mut buf);
+        types::write_string("ks", &mut buf).unwrap();
+        types::write_string("t", &mut buf).unwrap();
+        types::write_string("a", &mut buf).unwrap();
+        types::write_short(0x0009, &mut buf);
+        types::write_string("b", &mut buf).unwrap();
+        types::write_short(0x000D, &mut buf);
+
+        types::write_int(rows.rows_count as i32, &mut buf);
+        buf.extend_from_slice(&rows.raw_rows);
+
+        buf.into()
+    }
+
+    #[test]
+    fn test_deserialize_rows_lazily() {
+        use super::{Result, Row};
+
+        let body = rows_result_body(&[(1, Some("abc")), (2, None)]);
+        let rows = match super::deserialize(&body).unwrap() {
+            Result::Rows(rows) => rows,
+            other => panic!("Expected Rows, got {:?}", other),
+        };
+        assert_eq!(rows.rows_count(), 2);
+
+        let mut rows_iter = rows.into_iter();
+        assert_eq!(rows_iter.len(), 2);
+        assert_eq!(
+            rows_iter.next().unwrap().unwrap(),
+            Row {
+                columns: vec![
+                    Some(CqlValue::Int(1)),
+                    Some(CqlValue::Text("abc".to_string()))
+                ]
+            }
+        );
+        assert_eq!(rows_iter.len(), 1);
+        assert_eq!(
+            rows_iter.next().unwrap().unwrap(),
+            Row {
+                columns: vec![Some(CqlValue::Int(2)), None]
+            }
+        );
+        assert!(rows_iter.next().is_none());
+    }
+
+    #[test]
+    fn test_deserialize_malformed_rows() {
+        use super::Result;
+
+        let mut body: Vec<u8> = rows_result_body(&[(1, Some("abc")), (2, Some("def"))]).to_vec();
+        // Cut the last cell in half
+        body.truncate(body.len() - 2);
+
+        let rows = match super::deserialize(&body.into()).unwrap() {
+            Result::Rows(rows) => rows,
+            other => panic!("Expected Rows, got {:?}", other),
+        };
+
+        let mut rows_iter = rows.into_iter();
+        assert!(rows_iter.next().unwrap().is_ok());
+        assert!(rows_iter.next().unwrap().is_err());
+        assert!(rows_iter.next().is_none());
+    }
+
+    #[test]
+    fn test_from_row_error_column_context() {
+        use super::Result;
+        use crate::cql_to_rust::{ColumnContext, FromCqlValError, FromRowError};
+
+        let body = rows_result_body(&[(1, Some("abc"))]);
+        let rows = match super::deserialize(&body).unwrap() {
+            Result::Rows(rows) => rows,
+            other => panic!("Expected Rows, got {:?}", other),
+        };
+        let col_specs = rows.metadata.col_specs().to_vec();
+        let row = rows.into_rows().unwrap().pop().unwrap();
+
+        let err = row
+            .into_typed::<(i32, i64)>()
+            .unwrap_err()
+            .with_column_specs(&col_specs);
+
+        assert_eq!(
+            err,
+            FromRowError::BadCqlVal {
+                err: FromCqlValError::BadCqlType,
+                column: Some(ColumnContext {
+                    index: 1,
+                    name: Some("b".to_string()),
+                    row_index: None,
+                    expected_type: "i64",
+                    actual_type: Some("text".to_string()),
+                })
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Bad CQL value in column 1 (b), expected i64, got text: Bad CQL type"
+        );
+    }
+
+    #[test]
+    fn test_column_type_display() {
+        let map = ColumnType::Map(
+            Box::new(ColumnType::Text),
+            Box::new(ColumnType::List(Box::new(ColumnType::Int))),
+        );
+        assert_eq!(map.to_string(), "map<text, list<int>>");
+
+        let tuple = ColumnType::Tuple(vec![
+            ColumnType::BigInt,
+            ColumnType::Set(Box::new(ColumnType::Uuid)),
+        ]);
+        assert_eq!(tuple.to_string(), "tuple<bigint, set<uuid>>");
+
+        let udt = ColumnType::UserDefinedType {
+            type_name: "address".to_string(),
+            keyspace: "ks".to_string(),
+            field_types: vec![("street".to_string(), ColumnType::Text)],
+        };
+        assert_eq!(udt.to_string(), "ks.address");
+    }
+
+    #[test]
+    fn test_raw_rows() {
+        use super::{RawColumnError, RawRow, Result, Row};
+        use crate::cql_to_rust::FromCqlValError;
+
+        let body = rows_result_body(&[(1, Some("abc")), (2, None)]);
+        let rows = match super::deserialize(&body).unwrap() {
+            Result::Rows(rows) => rows,
+            other => panic!("Expected Rows, got {:?}", other),
+        };
+
+        let raw_rows: Vec<RawRow> = rows
+            .raw_rows()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap();
+        assert_eq!(raw_rows.len(), 2);
+        assert_eq!(raw_rows[0].len(), 2);
+
+        // Text is borrowed straight from the received body
+        let text: &str = raw_rows[0].column(1).unwrap();
+        assert_eq!(text, "abc");
+        assert_eq!(text.as_ptr(), body[body.len() - 3 - 4 - 4 - 4..].as_ptr());
+
+        assert_eq!(raw_rows[0].column::<i32>(0).unwrap(), 1);
+        assert_eq!(raw_rows[1].column_by_name::<i32>("a").unwrap(), 2);
+        assert_eq!(raw_rows[1].column::<Option<&str>>(1).unwrap(), None);
+        assert_eq!(raw_rows[1].raw_column(1), Some(None));
+
+        assert!(matches!(
+            raw_rows[1].column::<&str>(1),
+            Err(RawColumnError::FromCqlValError(FromCqlValError::ValIsNull))
+        ));
+        assert!(matches!(
+            raw_rows[0].column::<&str>(0),
+            Err(RawColumnError::FromCqlValError(FromCqlValError::BadCqlType))
+        ));
+        assert!(matches!(
+            raw_rows[0].column::<i32>(2),
+            Err(RawColumnError::NoSuchColumn(2))
+        ));
+        assert!(matches!(
+            raw_rows[0].column_by_name::<i32>("c"),
+            Err(RawColumnError::NoSuchColumnName(_))
+        ));
+
+        assert_eq!(
+            raw_rows[1].clone().into_row().unwrap(),
+            Row {
+                columns: vec![Some(CqlValue::Int(2)), None]
+            }
+        );
+    }
 }
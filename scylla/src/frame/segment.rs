@@ -0,0 +1,245 @@
+//! Segments wrapping frames in protocol v5
+//!
+//! After the handshake, frames are sent inside segments with checksums of their headers and payloads.
+//! A segment contains either whole frames (it's self-contained) or a part of one large frame.
+//! If LZ4 compression is used, payloads of segments are compressed instead of frame bodies.
+
+use super::frame_errors::FrameError;
+use compress::lz4;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Maximal size of a segment's payload, larger frames are split into many segments
+pub const MAX_PAYLOAD_SIZE: usize = (1 << 17) - 1;
+
+const CRC24_INIT: u32 = 0x875060;
+const CRC24_POLY: u32 = 0x1974F0B;
+// Bytes hashed before the payload, so that a payload of zeros doesn't have a zero checksum
+const CRC32_INITIAL_BYTES: [u8; 4] = [0xFA, 0x2D, 0x55, 0xCA];
+
+// Lengths of headers without their checksums
+const HEADER_SIZE: usize = 3;
+const COMPRESSED_HEADER_SIZE: usize = 5;
+const HEADER_CRC_SIZE: usize = 3;
+const PAYLOAD_CRC_SIZE: usize = 4;
+
+fn crc24(bytes: &[u8]) -> u32 {
+    let mut crc = CRC24_INIT;
+    for byte in bytes {
+        crc ^= (*byte as u32) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x1000000 != 0 {
+                crc ^= CRC24_POLY;
+            }
+        }
+    }
+    crc
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&CRC32_INITIAL_BYTES);
+    hasher.update(bytes);
+    hasher.finalize()
+}
+
+/// Appends segments containing the frame to `out`.
+/// If `compress` is set, payloads of at least `compression_min_size` bytes are compressed with LZ4.
+pub fn write_segments(
+    frame: &[u8],
+    compress: bool,
+    compression_min_size: usize,
+    out: &mut Vec<u8>,
+) {
+    let self_contained = frame.len() <= MAX_PAYLOAD_SIZE;
+    for payload in frame.chunks(MAX_PAYLOAD_SIZE) {
+        if compress {
+            write_compressed_segment(payload, self_contained, compression_min_size, out);
+        } else {
+            let header = payload.len() as u64 | (self_contained as u64) << 17;
+            write_header(header, HEADER_SIZE, out);
+            write_payload(payload, out);
+        }
+    }
+}
+
+fn write_compressed_segment(
+    payload: &[u8],
+    self_contained: bool,
+    compression_min_size: usize,
+    out: &mut Vec<u8>,
+) {
+    let mut compressed = Vec::new();
+    if payload.len() >= compression_min_size {
+        lz4::encode_block(payload, &mut compressed);
+    }
+
+    // Payloads which don't get smaller are sent uncompressed, marked by uncompressed length 0
+    let (payload, uncompressed_len) = if !compressed.is_empty() && compressed.len() < payload.len()
+    {
+        (&compressed[..], payload.len())
+    } else {
+        (payload, 0)
+    };
+
+    let header =
+        payload.len() as u64 | (uncompressed_len as u64) << 17 | (self_contained as u64) << 34;
+    write_header(header, COMPRESSED_HEADER_SIZE, out);
+    write_payload(payload, out);
+}
+
+fn write_header(header: u64, header_size: usize, out: &mut Vec<u8>) {
+    let header = &header.to_le_bytes()[..header_size];
+    out.extend_from_slice(header);
+    out.extend_from_slice(&crc24(header).to_le_bytes()[..HEADER_CRC_SIZE]);
+}
+
+fn write_payload(payload: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(payload);
+    out.extend_from_slice(&crc32(payload).to_le_bytes());
+}
+
+/// Reads a segment and returns its uncompressed payload
+pub async fn read_segment(
+    reader: &mut (impl AsyncRead + Unpin),
+    compressed: bool,
+) -> Result<Vec<u8>, FrameError> {
+    let header_size = if compressed {
+        COMPRESSED_HEADER_SIZE
+    } else {
+        HEADER_SIZE
+    };
+
+    let mut raw_header = [0u8; COMPRESSED_HEADER_SIZE + HEADER_CRC_SIZE];
+    let raw_header = &mut raw_header[..header_size + HEADER_CRC_SIZE];
+    reader.read_exact(raw_header).await?;
+
+    let (header, header_crc) = raw_header.split_at(header_size);
+    if crc24(header) != read_le(header_crc) as u32 {
+        return Err(FrameError::SegmentHeaderChecksum);
+    }
+
+    let header = read_le(header);
+    let payload_len = (header & MAX_PAYLOAD_SIZE as u64) as usize;
+    let uncompressed_len = if compressed {
+        ((header >> 17) & MAX_PAYLOAD_SIZE as u64) as usize
+    } else {
+        0
+    };
+
+    let mut payload = vec![0; payload_len + PAYLOAD_CRC_SIZE];
+    reader.read_exact(&mut payload).await?;
+    let payload_crc = read_le(&payload[payload_len..]) as u32;
+    payload.truncate(payload_len);
+    if crc32(&payload) != payload_crc {
+        return Err(FrameError::SegmentPayloadChecksum);
+    }
+
+    if uncompressed_len == 0 {
+        return Ok(payload);
+    }
+
+    let mut uncompressed = Vec::with_capacity(uncompressed_len);
+    lz4::decode_block(&payload, &mut uncompressed);
+    if uncompressed.len() != uncompressed_len {
+        return Err(FrameError::Lz4BodyDecompression);
+    }
+    Ok(uncompressed)
+}
+
+// Reads an integer written in little endian using the given number of bytes
+fn read_le(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf[..bytes.len()].copy_from_slice(bytes);
+    u64::from_le_bytes(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc24, read_segment, write_segments, MAX_PAYLOAD_SIZE};
+    use crate::frame::frame_errors::FrameError;
+
+    async fn read_all_segments(mut data: &[u8], compressed: bool) -> Vec<u8> {
+        let mut payloads = Vec::new();
+        while !data.is_empty() {
+            payloads.extend(read_segment(&mut data, compressed).await.unwrap());
+        }
+        payloads
+    }
+
+    #[test]
+    fn header_checksum() {
+        // Checksum of an empty header is the initial value
+        assert_eq!(crc24(&[]), 0x875060);
+        assert_ne!(crc24(&[1, 0, 2]), crc24(&[1, 0, 3]));
+        assert!(crc24(&[0xff, 0xff, 0xff]) < 1 << 24);
+    }
+
+    #[tokio::test]
+    async fn segments_round_trip() {
+        let small_frame: Vec<u8> = (0..100).collect();
+        let large_frame: Vec<u8> = (0..3 * MAX_PAYLOAD_SIZE).map(|i| (i % 7) as u8).collect();
+
+        for compressed in [false, true] {
+            for frame in [&small_frame, &large_frame] {
+                let mut data = Vec::new();
+                write_segments(frame, compressed, 0, &mut data);
+                assert_eq!(&read_all_segments(&data, compressed).await, frame);
+            }
+        }
+
+        // Uncompressed segments: header, header checksum, payload and payload checksum
+        let mut data = Vec::new();
+        write_segments(&small_frame, false, 0, &mut data);
+        assert_eq!(data.len(), 3 + 3 + 100 + 4);
+        // Payload length and self-contained flag
+        assert_eq!(&data[..3], &[100, 0, 0x02]);
+
+        // Large frames are split into segments which aren't self-contained
+        let mut data = Vec::new();
+        write_segments(&large_frame, false, 0, &mut data);
+        assert_eq!(&data[..3], &[0xff, 0xff, 0x01]);
+
+        // Repetitive frames get smaller
+        let mut data = Vec::new();
+        write_segments(&large_frame, true, 0, &mut data);
+        assert!(data.len() < large_frame.len() / 2);
+    }
+
+    #[tokio::test]
+    async fn small_payloads_are_not_compressed() {
+        let frame = vec![0u8; 100];
+
+        let mut data = Vec::new();
+        write_segments(&frame, true, 101, &mut data);
+        // Header, header checksum, whole payload and its checksum
+        assert_eq!(data.len(), 5 + 3 + 100 + 4);
+        assert_eq!(read_all_segments(&data, true).await, frame);
+
+        let mut data = Vec::new();
+        write_segments(&frame, true, 100, &mut data);
+        assert!(data.len() < 100);
+        assert_eq!(read_all_segments(&data, true).await, frame);
+    }
+
+    #[tokio::test]
+    async fn corrupted_segments() {
+        let frame: Vec<u8> = (0..100).collect();
+        let mut data = Vec::new();
+        write_segments(&frame, false, 0, &mut data);
+
+        let mut corrupted_header = data.clone();
+        corrupted_header[0] ^= 1;
+        assert!(matches!(
+            read_segment(&mut &corrupted_header[..], false).await,
+            Err(FrameError::SegmentHeaderChecksum)
+        ));
+
+        let mut corrupted_payload = data.clone();
+        corrupted_payload[50] ^= 1;
+        assert!(matches!(
+            read_segment(&mut &corrupted_payload[..], false).await,
+            Err(FrameError::SegmentPayloadChecksum)
+        ));
+    }
+}
@@ -123,6 +123,84 @@ fn type_long() {
     }
 }
 
+// Variable length integer, the number of leading ones in the first byte is the number
+// of bytes following it, e.g. used in the duration type
+pub fn read_unsigned_vint(buf: &mut &[u8]) -> Result<u64, ParseError> {
+    let first_byte = buf.read_u8()?;
+    let extra_bytes = first_byte.leading_ones() as usize;
+
+    let mut v = u64::from(first_byte & 0xffu8.checked_shr(extra_bytes as u32).unwrap_or(0));
+    for byte in read_raw_bytes(extra_bytes, buf)? {
+        v = (v << 8) | u64::from(*byte);
+    }
+    Ok(v)
+}
+
+pub fn write_unsigned_vint(v: u64, buf: &mut impl BufMut) {
+    let significant_bits = 64 - (v | 1).leading_zeros() as usize;
+    // Every additional byte adds 7 bits of space, up to 8 additional bytes
+    let extra_bytes = ((significant_bits - 1) / 7).min(8);
+
+    let bytes = v.to_be_bytes();
+    if extra_bytes == 8 {
+        buf.put_u8(0xff);
+        buf.put_slice(&bytes);
+    } else {
+        let value_bytes = &bytes[7 - extra_bytes..];
+        buf.put_u8(value_bytes[0] | !(0xffu8 >> extra_bytes));
+        buf.put_slice(&value_bytes[1..]);
+    }
+}
+
+// Signed variable length integer, zigzag encoded so that small negative values are short
+pub fn read_vint(buf: &mut &[u8]) -> Result<i64, ParseError> {
+    let v = read_unsigned_vint(buf)?;
+    Ok((v >> 1) as i64 ^ -((v & 1) as i64))
+}
+
+pub fn write_vint(v: i64, buf: &mut impl BufMut) {
+    write_unsigned_vint(((v << 1) ^ (v >> 63)) as u64, buf);
+}
+
+#[test]
+fn type_vint() {
+    let vals = vec![
+        i64::MIN,
+        i64::MIN + 1,
+        -(1 << 56),
+        -65,
+        -64,
+        -1,
+        0,
+        1,
+        63,
+        64,
+        1 << 55,
+        1 << 56,
+        i64::MAX,
+    ];
+    for val in vals.iter() {
+        let mut buf = Vec::new();
+        write_vint(*val, &mut buf);
+        assert_eq!(read_vint(&mut &buf[..]).unwrap(), *val);
+    }
+
+    let encoded: Vec<(u64, &[u8])> = vec![
+        (0, &[0x00]),
+        (127, &[0x7f]),
+        (128, &[0x80, 0x80]),
+        (16383, &[0xbf, 0xff]),
+        (16384, &[0xc0, 0x40, 0x00]),
+        (u64::MAX, &[0xff; 9]),
+    ];
+    for (val, bytes) in encoded {
+        let mut buf = Vec::new();
+        write_unsigned_vint(val, &mut buf);
+        assert_eq!(buf, bytes);
+        assert_eq!(read_unsigned_vint(&mut &buf[..]).unwrap(), val);
+    }
+}
+
 pub fn read_short(buf: &mut &[u8]) -> Result<i16, ParseError> {
     let v = buf.read_i16::<BigEndian>()?;
     Ok(v)
@@ -138,7 +216,7 @@ fn read_short_length(buf: &mut &[u8]) -> Result<usize, ParseError> {
     Ok(v)
 }
 
-fn write_short_length(v: usize, buf: &mut impl BufMut) -> Result<(), ParseError> {
+pub fn write_short_length(v: usize, buf: &mut impl BufMut) -> Result<(), ParseError> {
     let v: i16 = v.try_into()?;
     write_short(v as i16, buf);
     Ok(())
@@ -178,6 +256,12 @@ pub fn write_bytes(v: &[u8], buf: &mut impl BufMut) -> Result<(), ParseError> {
     Ok(())
 }
 
+pub fn read_short_bytes<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8], ParseError> {
+    let len = read_short_length(buf)?;
+    let v = read_raw_bytes(len, buf)?;
+    Ok(v)
+}
+
 pub fn write_short_bytes(v: &[u8], buf: &mut impl BufMut) -> Result<(), ParseError> {
     write_short_length(v.len(), buf)?;
     buf.put_slice(v);
@@ -3,13 +3,21 @@ use bytes::BufMut;
 use chrono::prelude::*;
 use chrono::Duration;
 use num_bigint::BigInt;
+use smallvec::SmallVec;
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::convert::TryInto;
+use std::hash::BuildHasher;
 use std::net::IpAddr;
 use thiserror::Error;
 use uuid::Uuid;
 
+use super::frame_errors::ParseError;
+use super::response::result::CqlValue;
+use super::types;
+use crate::routing::Token;
+
 /// Every value being sent in a query must implement this trait
 /// serialize() should write the Value as [bytes] to the provided buffer
 pub trait Value {
@@ -48,12 +56,200 @@ pub struct Timestamp(pub Duration);
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct Time(pub Duration);
 
+/// Value of the CQL `duration` type, e.g. `1mo2d3h`.
+/// Months, days and nanoseconds are separate, because their lengths vary,
+/// all of them have to be non-negative or all non-positive
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct CqlDuration {
+    pub months: i32,
+    pub days: i32,
+    pub nanoseconds: i64,
+}
+
+const NANOS_PER_MICRO: i64 = 1_000;
+const NANOS_PER_MILLI: i64 = 1_000 * NANOS_PER_MICRO;
+const NANOS_PER_SECOND: i64 = 1_000 * NANOS_PER_MILLI;
+const NANOS_PER_MINUTE: i64 = 60 * NANOS_PER_SECOND;
+const NANOS_PER_HOUR: i64 = 60 * NANOS_PER_MINUTE;
+
+// Units of the duration format, in the order they have to appear
+const DURATION_UNITS: &[(&str, DurationUnit)] = &[
+    ("y", DurationUnit::Months(12)),
+    ("mo", DurationUnit::Months(1)),
+    ("w", DurationUnit::Days(7)),
+    ("d", DurationUnit::Days(1)),
+    ("h", DurationUnit::Nanoseconds(NANOS_PER_HOUR)),
+    ("m", DurationUnit::Nanoseconds(NANOS_PER_MINUTE)),
+    ("s", DurationUnit::Nanoseconds(NANOS_PER_SECOND)),
+    ("ms", DurationUnit::Nanoseconds(NANOS_PER_MILLI)),
+    ("us", DurationUnit::Nanoseconds(NANOS_PER_MICRO)),
+    ("\u{b5}s", DurationUnit::Nanoseconds(NANOS_PER_MICRO)),
+    ("ns", DurationUnit::Nanoseconds(1)),
+];
+
+#[derive(Clone, Copy)]
+enum DurationUnit {
+    Months(i64),
+    Days(i64),
+    Nanoseconds(i64),
+}
+
+/// Formats the duration as it is written in CQL, e.g. `1y2mo3d4h5m6s7ms8us9ns`
+impl std::fmt::Display for CqlDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.months == 0 && self.days == 0 && self.nanoseconds == 0 {
+            return write!(f, "0s");
+        }
+        if self.months < 0 || self.days < 0 || self.nanoseconds < 0 {
+            write!(f, "-")?;
+        }
+
+        let months = i64::from(self.months).abs();
+        let days = i64::from(self.days).abs();
+        // abs of i64::MIN doesn't fit in i64
+        let nanoseconds = i128::from(self.nanoseconds).abs();
+        let parts = [(months / 12, "y"), (months % 12, "mo"), (days, "d")];
+        for (value, unit) in parts.iter() {
+            if *value != 0 {
+                write!(f, "{}{}", value, unit)?;
+            }
+        }
+
+        let nanosecond_parts = [
+            (nanoseconds / i128::from(NANOS_PER_HOUR), "h"),
+            (
+                nanoseconds % i128::from(NANOS_PER_HOUR) / i128::from(NANOS_PER_MINUTE),
+                "m",
+            ),
+            (
+                nanoseconds % i128::from(NANOS_PER_MINUTE) / i128::from(NANOS_PER_SECOND),
+                "s",
+            ),
+            (
+                nanoseconds % i128::from(NANOS_PER_SECOND) / i128::from(NANOS_PER_MILLI),
+                "ms",
+            ),
+            (
+                nanoseconds % i128::from(NANOS_PER_MILLI) / i128::from(NANOS_PER_MICRO),
+                "us",
+            ),
+            (nanoseconds % i128::from(NANOS_PER_MICRO), "ns"),
+        ];
+        for (value, unit) in nanosecond_parts.iter() {
+            if *value != 0 {
+                write!(f, "{}{}", value, unit)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses the duration format used in CQL, e.g. `1h30m` or `-2d`
+impl std::str::FromStr for CqlDuration {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad_duration = || ParseError::BadData(format!("Invalid duration: {}", s));
+
+        let (negative, mut rest) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        if rest.is_empty() {
+            return Err(bad_duration());
+        }
+
+        let (mut months, mut days, mut nanoseconds) = (0i64, 0i64, 0i64);
+        // Index in DURATION_UNITS after the last used unit, units can't repeat or go back
+        let mut next_unit = 0;
+        while !rest.is_empty() {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .ok_or_else(bad_duration)?;
+            let value: i64 = rest[..digits].parse().map_err(|_| bad_duration())?;
+            rest = &rest[digits..];
+
+            let unit_len = rest
+                .find(|c: char| c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let unit = rest[..unit_len].to_ascii_lowercase();
+            rest = &rest[unit_len..];
+
+            let unit_index = DURATION_UNITS[next_unit..]
+                .iter()
+                .position(|(name, _)| *name == unit)
+                .ok_or_else(bad_duration)?
+                + next_unit;
+            next_unit = unit_index + 1;
+
+            let (total, multiplier) = match DURATION_UNITS[unit_index].1 {
+                DurationUnit::Months(multiplier) => (&mut months, multiplier),
+                DurationUnit::Days(multiplier) => (&mut days, multiplier),
+                DurationUnit::Nanoseconds(multiplier) => (&mut nanoseconds, multiplier),
+            };
+            *total = value
+                .checked_mul(multiplier)
+                .and_then(|v| total.checked_add(v))
+                .ok_or_else(bad_duration)?;
+        }
+
+        let sign = if negative { -1 } else { 1 };
+        Ok(CqlDuration {
+            months: (sign * months).try_into().map_err(|_| bad_duration())?,
+            days: (sign * days).try_into().map_err(|_| bad_duration())?,
+            nanoseconds: sign * nanoseconds,
+        })
+    }
+}
+
+/// Allows to use an iterator of [`ValueList`]s as [`BatchValues`]
+///
+/// A batch is serialized by cloning the iterator and traversing it once,
+/// so it works best with cheap to clone iterators, like `slice::Iter` or a `map` over it.
+/// # Example
+/// ```rust
+/// # use scylla::frame::value::BatchValuesFromIter;
+/// let rows: Vec<(i32, String)> = vec![(1, "a".to_string()), (2, "b".to_string())];
+/// let batch_values = BatchValuesFromIter::new(rows.iter());
+/// ```
+#[derive(Clone)]
+pub struct BatchValuesFromIter<IT> {
+    iter: IT,
+}
+
+/// [`BatchValues`] which use the same [`ValueList`] for every statement in a batch
+/// # Example
+/// ```rust
+/// # use scylla::frame::value::RepeatedBatchValues;
+/// // Batch with 3 statements, each of them bound to (1, 2)
+/// let batch_values = RepeatedBatchValues::new((1_i32, 2_i32), 3);
+/// ```
+#[derive(Clone)]
+pub struct RepeatedBatchValues<VL: ValueList> {
+    values: VL,
+    count: usize,
+}
+
+// Values of small statements fit in this many bytes and are kept without a heap allocation
+const INLINE_VALUES_SIZE: usize = 64;
+
+// The scratch buffer is dropped after serializing a value bigger than this,
+// so that a single big value doesn't keep its memory for the lifetime of the thread
+const MAX_SCRATCH_CAPACITY: usize = 64 * 1024;
+
+thread_local! {
+    // Value::serialize writes to a Vec, values are serialized here and then copied into SerializedValues
+    static SERIALIZE_SCRATCH: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
 /// Keeps a buffer with serialized Values
 /// Allows adding new Values and iterating over serialized ones
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SerializedValues {
-    serialized_values: Vec<u8>,
+    serialized_values: SmallVec<[u8; INLINE_VALUES_SIZE]>,
     values_num: i16,
+    // Each value is preceded by its name, all values have to be named or none of them
+    contains_names: bool,
 }
 
 #[derive(Debug, Error, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -62,6 +258,10 @@ pub enum SerializeValuesError {
     TooManyValues,
     #[error(transparent)]
     ValueTooBig(#[from] ValueTooBig),
+    #[error("Mixing named and not named values is not allowed")]
+    MixingNamedAndNotNamedValues,
+    #[error("Named values can't be used in batches")]
+    NamedValuesInBatch,
 }
 
 pub type SerializedResult<'a> = Result<Cow<'a, SerializedValues>, SerializeValuesError>;
@@ -73,14 +273,61 @@ pub trait ValueList {
     /// returns Cow<SerializedValues> to make impl ValueList for SerializedValues efficient
     fn serialized(&self) -> SerializedResult<'_>;
 
+    /// Converts this ValueList into owned SerializedValues  
+    /// Lists which already are serialized are moved instead of being copied
+    fn into_serialized(self) -> Result<SerializedValues, SerializeValuesError>
+    where
+        Self: Sized,
+    {
+        Ok(self.serialized()?.into_owned())
+    }
+
     fn write_to_request(&self, buf: &mut impl BufMut) -> Result<(), SerializeValuesError> {
         let serialized = self.serialized()?;
+        // Value lists are written this way only in batches, which don't support names in protocol v4
+        if serialized.has_names() {
+            return Err(SerializeValuesError::NamedValuesInBatch);
+        }
         SerializedValues::write_to_request(&serialized, buf);
 
         Ok(())
     }
 }
 
+/// Creates values bound to statements by names of bind markers instead of positions.
+/// Values can have different types, the macro evaluates to a [`SerializedResult`],
+/// so serialization errors are returned when the statement is executed.
+///
+/// # Example
+/// ```rust
+/// # use scylla::Session;
+/// # use std::error::Error;
+/// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+/// use scylla::named_values;
+///
+/// session
+///     .query(
+///         "INSERT INTO ks.tab (a, b) VALUES (:a, :b)",
+///         named_values! {"b" => "text", "a" => 1_i32},
+///     )
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! named_values {
+    ($($name:expr => $value:expr),* $(,)?) => {{
+        #[allow(clippy::redundant_closure_call)]
+        let values: $crate::frame::value::SerializedResult<'static> = (|| {
+            #[allow(unused_mut)]
+            let mut values = $crate::frame::value::SerializedValues::new();
+            $(values.add_named_value($name, &$value)?;)*
+            Ok(::std::borrow::Cow::Owned(values))
+        })();
+        values
+    }};
+}
+
 /// Represents List of ValueList for Batch statement
 pub trait BatchValues {
     fn len(&self) -> usize;
@@ -91,6 +338,24 @@ pub trait BatchValues {
         buf: &mut impl BufMut,
     ) -> Result<(), SerializeValuesError>;
 
+    /// Writes values of all statements, in order.
+    /// `before_each` is called with the statement's index before its values are written,
+    /// e.g. to write the statement itself.
+    ///
+    /// By default calls [`write_nth_to_request`](BatchValues::write_nth_to_request) for each statement,
+    /// implementations which can't get to the n-th value list quickly should traverse them once instead.
+    fn write_all_to_request<B: BufMut, E: From<SerializeValuesError>>(
+        &self,
+        buf: &mut B,
+        mut before_each: impl FnMut(usize, &mut B) -> Result<(), E>,
+    ) -> Result<(), E> {
+        for n in 0..self.len() {
+            before_each(n, buf)?;
+            self.write_nth_to_request(n, buf)?;
+        }
+        Ok(())
+    }
+
     fn is_empty(&self) -> bool {
         self.len() == 0
     }
@@ -100,45 +365,150 @@ impl SerializedValues {
     /// Creates empty value list
     pub const fn new() -> Self {
         SerializedValues {
-            serialized_values: Vec::new(),
+            serialized_values: SmallVec::new_const(),
             values_num: 0,
+            contains_names: false,
         }
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
         SerializedValues {
-            serialized_values: Vec::with_capacity(capacity),
+            serialized_values: SmallVec::with_capacity(capacity),
             values_num: 0,
+            contains_names: false,
         }
     }
 
+    // Most values of simple types fit in 4 bytes of length and 8 bytes of value,
+    // reserving that much avoids growing the buffer many times for small statements
+    #[doc(hidden)]
+    pub fn with_capacity_for_values(values_num: usize) -> Self {
+        const ESTIMATED_VALUE_SIZE: usize = 12;
+        SerializedValues::with_capacity(values_num * ESTIMATED_VALUE_SIZE)
+    }
+
     /// A const empty instance, useful for taking references
     pub const EMPTY: &'static SerializedValues = &SerializedValues::new();
 
+    /// Removes all values, keeping the allocated buffer  
+    /// Allows to reuse the same SerializedValues as a scratch buffer for many statements
+    pub fn clear(&mut self) {
+        self.serialized_values.clear();
+        self.values_num = 0;
+        self.contains_names = false;
+    }
+
     /// Serializes value and appends it to the list
     pub fn add_value(&mut self, val: &impl Value) -> Result<(), SerializeValuesError> {
+        if self.contains_names {
+            return Err(SerializeValuesError::MixingNamedAndNotNamedValues);
+        }
         if self.values_num == i16::max_value() {
             return Err(SerializeValuesError::TooManyValues);
         }
 
-        let len_before_serialize: usize = self.serialized_values.len();
+        self.append_serialized(|buf| val.serialize(buf))?;
 
-        if let Err(e) = val.serialize(&mut self.serialized_values) {
-            self.serialized_values.resize(len_before_serialize, 0);
-            return Err(SerializeValuesError::from(e));
+        self.values_num += 1;
+        Ok(())
+    }
+
+    /// Serializes value and appends it to the list along with the name of its bind marker.
+    /// The database binds named values by name instead of position,
+    /// a list can't contain both named and not named values.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::frame::value::SerializedValues;
+    /// let mut values = SerializedValues::new();
+    /// values.add_named_value("b", &"text").unwrap();
+    /// values.add_named_value("a", &1_i32).unwrap();
+    ///
+    /// assert!(values.add_value(&2_i32).is_err());
+    /// ```
+    pub fn add_named_value(
+        &mut self,
+        name: &str,
+        val: &impl Value,
+    ) -> Result<(), SerializeValuesError> {
+        if self.values_num > 0 && !self.contains_names {
+            return Err(SerializeValuesError::MixingNamedAndNotNamedValues);
         }
+        if self.values_num == i16::MAX {
+            return Err(SerializeValuesError::TooManyValues);
+        }
+
+        self.append_serialized(|buf| {
+            types::write_string(name, buf).map_err(|_| ValueTooBig)?;
+            val.serialize(buf)
+        })?;
 
         self.values_num += 1;
+        self.contains_names = true;
         Ok(())
     }
 
+    // Runs `serialize` on a reused buffer and appends its output, nothing is appended if it fails
+    fn append_serialized(
+        &mut self,
+        serialize: impl FnOnce(&mut Vec<u8>) -> Result<(), ValueTooBig>,
+    ) -> Result<(), ValueTooBig> {
+        let serialized_values = &mut self.serialized_values;
+        let append = |buf: &mut Vec<u8>| {
+            buf.clear();
+            let result = serialize(buf);
+            if result.is_ok() {
+                serialized_values.extend_from_slice(buf);
+            }
+            if buf.capacity() > MAX_SCRATCH_CAPACITY {
+                *buf = Vec::new();
+            }
+            result
+        };
+
+        SERIALIZE_SCRATCH.with(|scratch| match scratch.try_borrow_mut() {
+            Ok(mut scratch) => append(&mut scratch),
+            // A Value serializing other values into SerializedValues, use a separate buffer
+            Err(_) => append(&mut Vec::new()),
+        })
+    }
+
+    /// Appends all values from `other` after values of this list
+    pub fn append(&mut self, other: &SerializedValues) -> Result<(), SerializeValuesError> {
+        if !self.is_empty() && !other.is_empty() && self.contains_names != other.contains_names {
+            return Err(SerializeValuesError::MixingNamedAndNotNamedValues);
+        }
+        let values_num = self
+            .values_num
+            .checked_add(other.values_num)
+            .ok_or(SerializeValuesError::TooManyValues)?;
+
+        self.serialized_values
+            .extend_from_slice(&other.serialized_values);
+        self.values_num = values_num;
+        self.contains_names |= other.contains_names;
+        Ok(())
+    }
+
+    /// Iterates over serialized values, skipping their names
     pub fn iter(&self) -> impl Iterator<Item = Option<&[u8]>> {
+        self.iter_name_value_pairs().map(|(_, value)| value)
+    }
+
+    /// Iterates over serialized values along with their names, which are `None` for not named values
+    pub fn iter_name_value_pairs(&self) -> impl Iterator<Item = (Option<&str>, Option<&[u8]>)> {
         SerializedValuesIterator {
             serialized_values: &self.serialized_values,
+            contains_names: self.contains_names,
             next_offset: 0,
         }
     }
 
+    /// Checks whether values are named, see [`add_named_value`](SerializedValues::add_named_value)
+    pub fn has_names(&self) -> bool {
+        self.contains_names
+    }
+
     pub fn write_to_request(&self, buf: &mut impl BufMut) {
         buf.put_i16(self.values_num);
         buf.put(&self.serialized_values[..]);
@@ -156,13 +526,25 @@ impl SerializedValues {
 #[derive(Clone, Copy)]
 pub struct SerializedValuesIterator<'a> {
     serialized_values: &'a [u8],
+    contains_names: bool,
     next_offset: usize,
 }
 
 impl<'a> Iterator for SerializedValuesIterator<'a> {
-    type Item = Option<&'a [u8]>;
+    type Item = (Option<&'a str>, Option<&'a [u8]>);
 
     fn next(&mut self) -> Option<Self::Item> {
+        let name = if self.contains_names {
+            let mut buf = &self.serialized_values[self.next_offset..];
+            let len_before = buf.len();
+            // Names were written by add_named_value, so they are valid
+            let name = types::read_string(&mut buf).ok()?;
+            self.next_offset += len_before - buf.len();
+            Some(name)
+        } else {
+            None
+        };
+
         // Read next value's 4 byte size, return it, advance
 
         if self.next_offset + 4 > self.serialized_values.len() {
@@ -179,7 +561,7 @@ impl<'a> Iterator for SerializedValuesIterator<'a> {
         if next_val_len < 0 {
             // Next value was NULL
             self.next_offset += 4;
-            return Some(None);
+            return Some((name, None));
         }
 
         // Found next value - get the slice and return it
@@ -187,7 +569,7 @@ impl<'a> Iterator for SerializedValuesIterator<'a> {
         let result: &[u8] =
             &self.serialized_values[(self.next_offset + 4)..(self.next_offset + 4 + val_len)];
         self.next_offset += 4 + val_len;
-        Some(Some(result))
+        Some((name, Some(result)))
     }
 }
 
@@ -276,6 +658,15 @@ impl Value for Timestamp {
     }
 }
 
+/// Serialized as a timestamp, milliseconds since unix epoch
+impl<Tz: TimeZone> Value for DateTime<Tz> {
+    fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
+        buf.put_i32(8);
+        buf.put_i64(self.timestamp_millis());
+        Ok(())
+    }
+}
+
 impl Value for Time {
     fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
         buf.put_i32(8);
@@ -284,6 +675,19 @@ impl Value for Time {
     }
 }
 
+impl Value for CqlDuration {
+    fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
+        let mut bytes = Vec::with_capacity(19);
+        types::write_vint(self.months.into(), &mut bytes);
+        types::write_vint(self.days.into(), &mut bytes);
+        types::write_vint(self.nanoseconds, &mut bytes);
+
+        buf.put_i32(bytes.len() as i32);
+        buf.put_slice(&bytes);
+        Ok(())
+    }
+}
+
 impl Value for bool {
     fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
         buf.put_i32(1);
@@ -335,6 +739,24 @@ impl Value for BigInt {
     }
 }
 
+impl Value for Token {
+    /// Serializes the token as the result of `token(...)` in CQL: `bigint` for Murmur3 tokens,
+    /// `varint` for Random tokens and `blob` for ByteOrdered tokens
+    fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
+        match self {
+            Token::Murmur3(value) => value.serialize(buf),
+            Token::Random(value) => BigInt::from(*value).serialize(buf),
+            Token::ByteOrdered(key) => {
+                let val_len: i32 = key.len().try_into().map_err(|_| ValueTooBig)?;
+                buf.put_i32(val_len);
+                buf.extend_from_slice(key);
+
+                Ok(())
+            }
+        }
+    }
+}
+
 impl Value for &str {
     fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
         let str_bytes: &[u8] = self.as_bytes();
@@ -418,47 +840,146 @@ impl<V: Value> Value for MaybeUnset<V> {
 }
 
 // Every &impl Value should also implement Value
-impl<T: Value> Value for &T {
+impl<T: Value + ?Sized> Value for &T {
     fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
         <T as Value>::serialize(*self, buf)
     }
 }
 
-impl<K: Value, V: Value> Value for HashMap<K, V> {
+// Allows to build lists of values with types known only at runtime, e.g. Vec<Box<dyn Value>>
+impl<T: Value + ?Sized> Value for Box<T> {
     fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
-        let bytes_num_pos: usize = buf.len();
-        buf.put_i32(0);
-
-        buf.put_i32(self.len().try_into().map_err(|_| ValueTooBig)?);
-        for (key, value) in self {
-            <K as Value>::serialize(key, buf)?;
-            <V as Value>::serialize(value, buf)?;
-        }
+        <T as Value>::serialize(self.as_ref(), buf)
+    }
+}
 
-        let written_bytes: usize = buf.len() - bytes_num_pos - 4;
-        let written_bytes_i32: i32 = written_bytes.try_into().map_err(|_| ValueTooBig)?;
-        buf[bytes_num_pos..(bytes_num_pos + 4)].copy_from_slice(&written_bytes_i32.to_be_bytes());
+impl<K: Value, V: Value, S: BuildHasher> Value for HashMap<K, V, S> {
+    fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
+        serialize_map(self.iter(), buf)
+    }
+}
 
-        Ok(())
+impl<K: Value, V: Value> Value for BTreeMap<K, V> {
+    fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
+        serialize_map(self.iter(), buf)
     }
 }
 
 impl<T: Value> Value for Vec<T> {
     fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
-        let bytes_num_pos: usize = buf.len();
-        buf.put_i32(0);
+        serialize_list_or_set(self.iter(), buf)
+    }
+}
+
+impl<T: Value> Value for [T] {
+    fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
+        serialize_list_or_set(self.iter(), buf)
+    }
+}
+
+impl<T: Value, S: BuildHasher> Value for HashSet<T, S> {
+    fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
+        serialize_list_or_set(self.iter(), buf)
+    }
+}
+
+impl<T: Value> Value for BTreeSet<T> {
+    fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
+        serialize_list_or_set(self.iter(), buf)
+    }
+}
+
+// Lists and sets are serialized as the number of elements followed by the elements
+fn serialize_list_or_set<'a, T: Value + 'a>(
+    elements: impl ExactSizeIterator<Item = &'a T>,
+    buf: &mut Vec<u8>,
+) -> Result<(), ValueTooBig> {
+    let bytes_num_pos: usize = buf.len();
+    buf.put_i32(0);
+
+    buf.put_i32(elements.len().try_into().map_err(|_| ValueTooBig)?);
+    for element in elements {
+        element.serialize(buf)?;
+    }
+
+    let written_bytes: usize = buf.len() - bytes_num_pos - 4;
+    let written_bytes_i32: i32 = written_bytes.try_into().map_err(|_| ValueTooBig)?;
+    buf[bytes_num_pos..(bytes_num_pos + 4)].copy_from_slice(&written_bytes_i32.to_be_bytes());
 
-        buf.put_i32(self.len().try_into().map_err(|_| ValueTooBig)?);
-        for value in self {
-            <T as Value>::serialize(value, buf)?;
+    Ok(())
+}
+
+// Maps are serialized as the number of entries followed by keys and values, one after another
+fn serialize_map<'a, K: Value + 'a, V: Value + 'a>(
+    entries: impl ExactSizeIterator<Item = (&'a K, &'a V)>,
+    buf: &mut Vec<u8>,
+) -> Result<(), ValueTooBig> {
+    let bytes_num_pos: usize = buf.len();
+    buf.put_i32(0);
+
+    buf.put_i32(entries.len().try_into().map_err(|_| ValueTooBig)?);
+    for (key, value) in entries {
+        key.serialize(buf)?;
+        value.serialize(buf)?;
+    }
+
+    let written_bytes: usize = buf.len() - bytes_num_pos - 4;
+    let written_bytes_i32: i32 = written_bytes.try_into().map_err(|_| ValueTooBig)?;
+    buf[bytes_num_pos..(bytes_num_pos + 4)].copy_from_slice(&written_bytes_i32.to_be_bytes());
+
+    Ok(())
+}
+
+/// Values of fields of a user defined type are serialized in the order of field names,
+/// so it has to match the order of fields in the type's definition
+impl Value for CqlValue {
+    fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
+        match self {
+            CqlValue::Ascii(s) | CqlValue::Text(s) => s.serialize(buf),
+            CqlValue::Boolean(b) => b.serialize(buf),
+            CqlValue::Blob(b) => b.serialize(buf),
+            CqlValue::Counter(c) => c.serialize(buf),
+            CqlValue::Duration(d) => d.serialize(buf),
+            CqlValue::Decimal(d) => d.serialize(buf),
+            CqlValue::Date(d) => Date(*d).serialize(buf),
+            CqlValue::Double(d) => d.serialize(buf),
+            CqlValue::Float(f) => f.serialize(buf),
+            CqlValue::Int(i) => i.serialize(buf),
+            CqlValue::BigInt(i) => i.serialize(buf),
+            CqlValue::SmallInt(i) => i.serialize(buf),
+            CqlValue::TinyInt(i) => i.serialize(buf),
+            CqlValue::Timestamp(t) => Timestamp(*t).serialize(buf),
+            CqlValue::Time(t) => Time(*t).serialize(buf),
+            CqlValue::Inet(i) => i.serialize(buf),
+            CqlValue::Uuid(u) | CqlValue::Timeuuid(u) => u.serialize(buf),
+            CqlValue::Varint(v) => v.serialize(buf),
+            CqlValue::List(values) | CqlValue::Set(values) => values.serialize(buf),
+            CqlValue::Map(entries) => serialize_map(entries.iter().map(|(k, v)| (k, v)), buf),
+            CqlValue::Tuple(fields) => serialize_fields(fields.iter(), buf),
+            CqlValue::UserDefinedType { fields, .. } => {
+                serialize_fields(fields.iter().map(|(_, value)| value), buf)
+            }
         }
+    }
+}
 
-        let written_bytes: usize = buf.len() - bytes_num_pos - 4;
-        let written_bytes_i32: i32 = written_bytes.try_into().map_err(|_| ValueTooBig)?;
-        buf[bytes_num_pos..(bytes_num_pos + 4)].copy_from_slice(&written_bytes_i32.to_be_bytes());
+// Tuples and user defined types are serialized as values of their fields, one after another
+fn serialize_fields(
+    fields: impl Iterator<Item = impl Value>,
+    buf: &mut Vec<u8>,
+) -> Result<(), ValueTooBig> {
+    let bytes_num_pos: usize = buf.len();
+    buf.put_i32(0);
 
-        Ok(())
+    for field in fields {
+        field.serialize(buf)?;
     }
+
+    let written_bytes: usize = buf.len() - bytes_num_pos - 4;
+    let written_bytes_i32: i32 = written_bytes.try_into().map_err(|_| ValueTooBig)?;
+    buf[bytes_num_pos..(bytes_num_pos + 4)].copy_from_slice(&written_bytes_i32.to_be_bytes());
+
+    Ok(())
 }
 
 macro_rules! impl_value_for_tuple {
@@ -529,7 +1050,7 @@ impl ValueList for [u8; 0] {
 // Implement ValueList for slices of Value types
 impl<T: Value> ValueList for &[T] {
     fn serialized(&self) -> SerializedResult<'_> {
-        let mut result = SerializedValues::with_capacity(self.len());
+        let mut result = SerializedValues::with_capacity_for_values(self.len());
         for val in *self {
             result.add_value(val)?;
         }
@@ -541,7 +1062,7 @@ impl<T: Value> ValueList for &[T] {
 // Implement ValueList for Vec<Value>
 impl<T: Value> ValueList for Vec<T> {
     fn serialized(&self) -> SerializedResult<'_> {
-        let mut result = SerializedValues::with_capacity(self.len());
+        let mut result = SerializedValues::with_capacity_for_values(self.len());
         for val in self {
             result.add_value(val)?;
         }
@@ -550,13 +1071,36 @@ impl<T: Value> ValueList for Vec<T> {
     }
 }
 
+// Implement ValueList for maps, which bind values by names of bind markers
+impl<K: AsRef<str>, T: Value, S: BuildHasher> ValueList for HashMap<K, T, S> {
+    fn serialized(&self) -> SerializedResult<'_> {
+        let mut result = SerializedValues::with_capacity_for_values(self.len());
+        for (name, val) in self {
+            result.add_named_value(name.as_ref(), val)?;
+        }
+
+        Ok(Cow::Owned(result))
+    }
+}
+
+impl<K: AsRef<str>, T: Value> ValueList for BTreeMap<K, T> {
+    fn serialized(&self) -> SerializedResult<'_> {
+        let mut result = SerializedValues::with_capacity_for_values(self.len());
+        for (name, val) in self {
+            result.add_named_value(name.as_ref(), val)?;
+        }
+
+        Ok(Cow::Owned(result))
+    }
+}
+
 // Implement ValueList for tuples of Values of size up to 16
 
 // Here is an example implemetation for (T0, )
 // Further variants are done using a macro
 impl<T0: Value> ValueList for (T0,) {
     fn serialized(&self) -> SerializedResult<'_> {
-        let mut result = SerializedValues::with_capacity(1);
+        let mut result = SerializedValues::with_capacity_for_values(1);
         result.add_value(&self.0)?;
         Ok(Cow::Owned(result))
     }
@@ -569,7 +1113,7 @@ macro_rules! impl_value_list_for_tuple {
             $($Ti: Value),+
         {
             fn serialized(&self) -> SerializedResult<'_> {
-                let mut result = SerializedValues::with_capacity($size);
+                let mut result = SerializedValues::with_capacity_for_values($size);
                 $(
                     result.add_value(&self.$FieldI) ?;
                 )*
@@ -613,12 +1157,20 @@ impl ValueList for SerializedValues {
     fn serialized(&self) -> SerializedResult<'_> {
         Ok(Cow::Borrowed(self))
     }
+
+    fn into_serialized(self) -> Result<SerializedValues, SerializeValuesError> {
+        Ok(self)
+    }
 }
 
 impl<'b> ValueList for Cow<'b, SerializedValues> {
     fn serialized(&self) -> SerializedResult<'_> {
         Ok(Cow::Borrowed(self.as_ref()))
     }
+
+    fn into_serialized(self) -> Result<SerializedValues, SerializeValuesError> {
+        Ok(self.into_owned())
+    }
 }
 
 impl<'b> ValueList for SerializedResult<'b> {
@@ -628,6 +1180,10 @@ impl<'b> ValueList for SerializedResult<'b> {
             Err(e) => Err(*e),
         }
     }
+
+    fn into_serialized(self) -> Result<SerializedValues, SerializeValuesError> {
+        self.map(Cow::into_owned)
+    }
 }
 
 //
@@ -734,6 +1290,77 @@ impl_batch_values_for_tuple!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T
 impl_batch_values_for_tuple!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15;
                              0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15; 16);
 
+impl<IT> BatchValuesFromIter<IT>
+where
+    IT: Iterator + ExactSizeIterator + Clone,
+    IT::Item: ValueList,
+{
+    pub fn new(iter: IT) -> Self {
+        BatchValuesFromIter { iter }
+    }
+}
+
+impl<IT> BatchValues for BatchValuesFromIter<IT>
+where
+    IT: Iterator + ExactSizeIterator + Clone,
+    IT::Item: ValueList,
+{
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+
+    fn write_nth_to_request(
+        &self,
+        n: usize,
+        buf: &mut impl BufMut,
+    ) -> Result<(), SerializeValuesError> {
+        let len = self.iter.len();
+        self.iter
+            .clone()
+            .nth(n)
+            .unwrap_or_else(|| panic!("Tried to serialize ValueList with an out of range index! index: {}, ValueList len: {}", n, len))
+            .write_to_request(buf)
+    }
+
+    fn write_all_to_request<B: BufMut, E: From<SerializeValuesError>>(
+        &self,
+        buf: &mut B,
+        mut before_each: impl FnMut(usize, &mut B) -> Result<(), E>,
+    ) -> Result<(), E> {
+        // Traversing the iterator once keeps serialization linear for iterators without random access
+        for (n, values) in self.iter.clone().enumerate() {
+            before_each(n, buf)?;
+            values.write_to_request(buf)?;
+        }
+        Ok(())
+    }
+}
+
+impl<VL: ValueList> RepeatedBatchValues<VL> {
+    /// Creates BatchValues for a batch with `count` statements, all of them bound to `values`
+    pub fn new(values: VL, count: usize) -> Self {
+        RepeatedBatchValues { values, count }
+    }
+}
+
+impl<VL: ValueList> BatchValues for RepeatedBatchValues<VL> {
+    fn len(&self) -> usize {
+        self.count
+    }
+
+    fn write_nth_to_request(
+        &self,
+        n: usize,
+        buf: &mut impl BufMut,
+    ) -> Result<(), SerializeValuesError> {
+        if n >= self.count {
+            panic!("Tried to serialize ValueList with an out of range index! index: {}, ValueList len: {}", n, self.count);
+        }
+
+        self.values.write_to_request(buf)
+    }
+}
+
 // Every &impl BatchValues should also implement BatchValues
 impl<T: BatchValues> BatchValues for &T {
     fn len(&self) -> usize {
@@ -748,4 +1375,12 @@ impl<T: BatchValues> BatchValues for &T {
         <T as BatchValues>::write_nth_to_request(*self, n, buf)?;
         Ok(())
     }
+
+    fn write_all_to_request<B: BufMut, E: From<SerializeValuesError>>(
+        &self,
+        buf: &mut B,
+        before_each: impl FnMut(usize, &mut B) -> Result<(), E>,
+    ) -> Result<(), E> {
+        <T as BatchValues>::write_all_to_request(*self, buf, before_each)
+    }
 }
@@ -1,12 +1,16 @@
+use super::response::result::CqlValue;
 use super::value::{
-    BatchValues, Date, MaybeUnset, SerializeValuesError, SerializedResult, SerializedValues, Time,
-    Timestamp, Unset, Value, ValueList, ValueTooBig,
+    BatchValues, BatchValuesFromIter, CqlDuration, Date, MaybeUnset, RepeatedBatchValues,
+    SerializeValuesError, SerializedResult, SerializedValues, Time, Timestamp, Unset, Value,
+    ValueList, ValueTooBig,
 };
 use bytes::BufMut;
 use chrono::Duration;
-use chrono::NaiveDate;
+use chrono::{FixedOffset, NaiveDate, TimeZone, Utc};
 use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::convert::TryInto;
+use std::iter::FromIterator;
 use uuid::Uuid;
 
 fn serialized(val: impl Value) -> Vec<u8> {
@@ -109,6 +113,112 @@ fn timestamp_serialization() {
     }
 }
 
+#[test]
+fn date_time_serialization() {
+    let utc = Utc.timestamp_millis_opt(1_234_567).unwrap();
+    let mut expected_bytes: Vec<u8> = vec![0, 0, 0, 8];
+    expected_bytes.extend_from_slice(&1_234_567_i64.to_be_bytes());
+    assert_eq!(serialized(utc), expected_bytes);
+
+    // The same instant in another timezone
+    let offset = FixedOffset::east_opt(3600).unwrap();
+    assert_eq!(serialized(utc.with_timezone(&offset)), expected_bytes);
+}
+
+#[test]
+fn collection_serialization() {
+    // Lists and sets have the same format: length of the whole value, number of elements, elements
+    let expected_list: Vec<u8> = vec![
+        0, 0, 0, 20, 0, 0, 0, 2, 0, 0, 0, 4, 0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 2,
+    ];
+    assert_eq!(serialized(vec![1_i32, 2]), expected_list);
+    assert_eq!(serialized(&[1_i32, 2][..]), expected_list);
+    assert_eq!(
+        serialized(BTreeSet::from_iter(vec![2_i32, 1])),
+        expected_list
+    );
+
+    let single_element: HashSet<i32> = vec![1].into_iter().collect();
+    assert_eq!(
+        serialized(single_element),
+        vec![0, 0, 0, 12, 0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 1]
+    );
+
+    // Maps contain keys and values, one after another
+    let expected_map: Vec<u8> = vec![
+        0, 0, 0, 17, 0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 1, 0, 0, 0, 1, 7,
+    ];
+    let btree_map: BTreeMap<i32, i8> = vec![(1, 7)].into_iter().collect();
+    let hash_map: HashMap<i32, i8> = vec![(1, 7)].into_iter().collect();
+    assert_eq!(serialized(btree_map), expected_map);
+    assert_eq!(serialized(hash_map), expected_map);
+
+    // Nested collections are serialized as values of the outer one
+    let nested: Vec<BTreeSet<i8>> = vec![BTreeSet::new(), vec![3].into_iter().collect()];
+    assert_eq!(
+        serialized(nested),
+        vec![
+            0, 0, 0, 25, 0, 0, 0, 2, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 9, 0, 0, 0, 1, 0, 0, 0, 1, 3
+        ]
+    );
+}
+
+#[test]
+fn cql_duration_serialization() {
+    let duration = CqlDuration {
+        months: 1,
+        days: -2,
+        nanoseconds: 3,
+    };
+    assert_eq!(serialized(duration), vec![0, 0, 0, 3, 0x02, 0x03, 0x06]);
+
+    let max = CqlDuration {
+        months: i32::MAX,
+        days: i32::MAX,
+        nanoseconds: i64::MAX,
+    };
+    let mut expected = vec![0, 0, 0, 19];
+    // Months and days take 5 bytes, nanoseconds take 9
+    expected.extend_from_slice(&[0xf0, 0xff, 0xff, 0xff, 0xfe]);
+    expected.extend_from_slice(&[0xf0, 0xff, 0xff, 0xff, 0xfe]);
+    expected.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe]);
+    assert_eq!(serialized(max), expected);
+}
+
+#[test]
+fn cql_duration_text_format() {
+    let tests = [
+        ("0s", 0, 0, 0),
+        ("1y2mo3d", 14, 3, 0),
+        ("2w", 0, 14, 0),
+        ("-5h30m", 0, 0, -19_800_000_000_000),
+        ("1h2m3s4ms5us6ns", 0, 0, 3_723_004_005_006),
+        ("7\u{b5}s", 0, 0, 7_000),
+        ("1MO1D", 1, 1, 0),
+    ];
+    for (text, months, days, nanoseconds) in tests.iter() {
+        let duration: CqlDuration = text.parse().unwrap();
+        let expected = CqlDuration {
+            months: *months,
+            days: *days,
+            nanoseconds: *nanoseconds,
+        };
+        assert_eq!(duration, expected, "{}", text);
+        assert_eq!(
+            duration.to_string().parse::<CqlDuration>().unwrap(),
+            expected
+        );
+    }
+    assert_eq!(
+        "1y2mo3d4h".parse::<CqlDuration>().unwrap().to_string(),
+        "1y2mo3d4h"
+    );
+
+    for invalid in ["", "-", "1", "h", "1d1y", "1h1h", "1x", "3000000000d"].iter() {
+        assert!(invalid.parse::<CqlDuration>().is_err(), "{}", invalid);
+    }
+}
+
 #[test]
 fn timeuuid_serialization() {
     // A few random timeuuids generated manually
@@ -269,6 +379,56 @@ fn slice_value_list() {
     );
 }
 
+#[test]
+fn cql_value_serialization() {
+    assert_eq!(serialized(CqlValue::Int(5)), serialized(5_i32));
+    assert_eq!(
+        serialized(CqlValue::Text("abc".to_string())),
+        serialized("abc")
+    );
+    assert_eq!(serialized(CqlValue::Date(4)), serialized(Date(4)));
+    assert_eq!(
+        serialized(CqlValue::Timestamp(Duration::milliseconds(42))),
+        serialized(Timestamp(Duration::milliseconds(42)))
+    );
+    assert_eq!(
+        serialized(CqlValue::List(vec![
+            CqlValue::BigInt(1),
+            CqlValue::BigInt(2)
+        ])),
+        serialized(vec![1_i64, 2_i64])
+    );
+
+    let mut map: HashMap<i32, String> = HashMap::new();
+    map.insert(1, "a".to_string());
+    assert_eq!(
+        serialized(CqlValue::Map(vec![(
+            CqlValue::Int(1),
+            CqlValue::Text("a".to_string())
+        )])),
+        serialized(map)
+    );
+
+    assert_eq!(
+        serialized(CqlValue::Tuple(vec![
+            CqlValue::Int(1),
+            CqlValue::Boolean(true)
+        ])),
+        serialized((1_i32, true))
+    );
+
+    // Fields are sent in the order of the definition, not sorted by name
+    let udt = CqlValue::UserDefinedType {
+        keyspace: "ks".to_string(),
+        type_name: "t".to_string(),
+        fields: vec![
+            ("b".to_string(), Some(CqlValue::SmallInt(7))),
+            ("a".to_string(), None),
+        ],
+    };
+    assert_eq!(serialized(udt), serialized((7_i16, None::<i32>)));
+}
+
 #[test]
 fn vec_value_list() {
     let values: Vec<i32> = vec![1, 2, 3];
@@ -286,6 +446,36 @@ fn vec_value_list() {
     );
 }
 
+#[test]
+fn dynamic_value_list() {
+    let cql_values: Vec<Option<CqlValue>> = vec![
+        Some(CqlValue::Int(1)),
+        None,
+        Some(CqlValue::Text("a".to_string())),
+    ];
+    let serialized: SerializedValues = cql_values.serialized().unwrap().into_owned();
+    assert_eq!(
+        serialized.iter().collect::<Vec<_>>(),
+        vec![Some([0, 0, 0, 1].as_ref()), None, Some([97].as_ref())]
+    );
+
+    let boxed_values: Vec<Box<dyn Value>> = vec![
+        Box::new(1_i32),
+        Box::new(None::<String>),
+        Box::new(Unset),
+        Box::new(CqlValue::Text("a".to_string())),
+    ];
+    let mut expected = SerializedValues::new();
+    expected.add_value(&1_i32).unwrap();
+    expected.add_value(&None::<String>).unwrap();
+    expected.add_value(&Unset).unwrap();
+    expected.add_value(&"a").unwrap();
+    assert_eq!(boxed_values.serialized().unwrap().into_owned(), expected);
+
+    let refs: Vec<&dyn Value> = vec![&1_i32, &"a"];
+    assert_eq!(refs.serialized().unwrap().len(), 2);
+}
+
 #[test]
 fn tuple_value_list() {
     fn check_i8_tuple(tuple: impl ValueList, expected: core::ops::Range<u8>) {
@@ -375,6 +565,23 @@ fn ref_value_list() {
     );
 }
 
+#[test]
+fn serialized_values_append() {
+    let mut values = SerializedValues::new();
+    values.add_value(&1_i32).unwrap();
+
+    let mut other = SerializedValues::new();
+    other.add_value(&"a").unwrap();
+    other.add_value(&None::<i32>).unwrap();
+
+    values.append(&other).unwrap();
+    assert_eq!(values.len(), 3);
+    assert_eq!(
+        values.iter().collect::<Vec<_>>(),
+        vec![Some([0, 0, 0, 1].as_ref()), Some(b"a".as_ref()), None]
+    );
+}
+
 #[test]
 fn serialized_values_value_list() {
     let mut ser_values = SerializedValues::new();
@@ -397,6 +604,86 @@ fn cow_serialized_values_value_list() {
     assert_eq!(cow_ser_values.as_ref(), serialized.as_ref());
 }
 
+#[test]
+fn into_serialized_value_list() {
+    // The value doesn't fit in the inline buffer, so it's kept on the heap
+    let mut ser_values = SerializedValues::new();
+    ser_values.add_value(&"qwertyuiop".repeat(10)).unwrap();
+    let value_ptr: *const u8 = ser_values.iter().next().unwrap().unwrap().as_ptr();
+
+    // Already serialized values are moved, not copied
+    let moved: SerializedValues = ser_values.into_serialized().unwrap();
+    assert_eq!(moved.iter().next().unwrap().unwrap().as_ptr(), value_ptr);
+
+    let from_tuple: SerializedValues = (1_i32, "qwertyuiop").into_serialized().unwrap();
+    assert_eq!(from_tuple.len(), 2);
+}
+
+#[test]
+fn serialized_values_outgrow_inline_buffer() {
+    let mut ser_values = SerializedValues::new();
+    let long_text = "a".repeat(1000);
+    for i in 0..100_i32 {
+        ser_values.add_value(&i).unwrap();
+    }
+    ser_values.add_value(&long_text).unwrap();
+
+    let values: Vec<_> = ser_values.iter().collect();
+    assert_eq!(values.len(), 101);
+    assert_eq!(values[99], Some(99_i32.to_be_bytes().as_ref()));
+    assert_eq!(values[100], Some(long_text.as_bytes()));
+}
+
+#[test]
+fn serialized_values_failed_value_is_not_added() {
+    // Writes a part of the value before failing
+    struct FailingValue;
+    impl Value for FailingValue {
+        fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
+            buf.put_i32(4);
+            Err(ValueTooBig)
+        }
+    }
+
+    // Serializes other values while being serialized
+    struct NestedValue;
+    impl Value for NestedValue {
+        fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
+            let mut inner = SerializedValues::new();
+            inner.add_value(&1_i32).unwrap();
+            inner.add_value(&2_i32).unwrap();
+            (inner.iter().count() as i32).serialize(buf)
+        }
+    }
+
+    let mut ser_values = SerializedValues::new();
+    ser_values.add_value(&1_i32).unwrap();
+    assert!(ser_values.add_value(&FailingValue).is_err());
+    ser_values.add_value(&NestedValue).unwrap();
+
+    assert_eq!(
+        ser_values.iter().collect::<Vec<_>>(),
+        vec![Some([0, 0, 0, 1].as_ref()), Some([0, 0, 0, 2].as_ref())]
+    );
+}
+
+#[test]
+fn serialized_values_clear() {
+    let mut ser_values = SerializedValues::new();
+    ser_values.add_value(&1_i32).unwrap();
+    ser_values.add_value(&2_i32).unwrap();
+
+    ser_values.clear();
+    assert!(ser_values.is_empty());
+    assert_eq!(ser_values.iter().next(), None);
+
+    ser_values.add_value(&3_i32).unwrap();
+    assert_eq!(
+        ser_values.iter().collect::<Vec<_>>(),
+        vec![Some([0, 0, 0, 3].as_ref())]
+    );
+}
+
 #[test]
 fn serialized_result_value_list() {
     let ser_result: SerializedResult = (1_i32,).serialized();
@@ -407,6 +694,145 @@ fn serialized_result_value_list() {
     assert!(matches!(ser_ser_result, Cow::Borrowed(_)));
 }
 
+#[test]
+fn named_values() {
+    let mut values = SerializedValues::new();
+    values.add_named_value("a", &8_i8).unwrap();
+    values.add_named_value("bc", &None::<i8>).unwrap();
+    assert!(values.has_names());
+    assert_eq!(values.len(), 2);
+
+    let mut request = Vec::<u8>::new();
+    values.write_to_request(&mut request);
+    assert_eq!(
+        request,
+        vec![0, 2, 0, 1, b'a', 0, 0, 0, 1, 8, 0, 2, b'b', b'c', 255, 255, 255, 255]
+    );
+
+    assert_eq!(
+        values.iter().collect::<Vec<_>>(),
+        vec![Some([8].as_ref()), None]
+    );
+    assert_eq!(
+        values.iter_name_value_pairs().collect::<Vec<_>>(),
+        vec![(Some("a"), Some([8].as_ref())), (Some("bc"), None)]
+    );
+
+    // Named and not named values can't be mixed
+    assert_eq!(
+        values.add_value(&1_i8),
+        Err(SerializeValuesError::MixingNamedAndNotNamedValues)
+    );
+    let not_named: SerializedValues = (1_i8,).serialized().unwrap().into_owned();
+    assert_eq!(
+        values.append(&not_named),
+        Err(SerializeValuesError::MixingNamedAndNotNamedValues)
+    );
+    let mut not_named = not_named;
+    assert_eq!(
+        not_named.add_named_value("a", &1_i8),
+        Err(SerializeValuesError::MixingNamedAndNotNamedValues)
+    );
+    assert_eq!(values.len(), 2);
+
+    values.clear();
+    assert!(!values.has_names());
+    values.add_value(&1_i8).unwrap();
+}
+
+#[test]
+fn map_value_lists() {
+    let mut expected = SerializedValues::new();
+    expected.add_named_value("a", &1_i32).unwrap();
+    expected.add_named_value("b", &2_i32).unwrap();
+
+    let btree_map: BTreeMap<&str, i32> = vec![("b", 2), ("a", 1)].into_iter().collect();
+    assert_eq!(btree_map.serialized().unwrap().into_owned(), expected);
+
+    let hash_map: HashMap<String, i32> = vec![("a".to_string(), 1)].into_iter().collect();
+    let serialized = hash_map.serialized().unwrap();
+    assert!(serialized.has_names());
+    assert_eq!(
+        serialized.iter_name_value_pairs().collect::<Vec<_>>(),
+        vec![(Some("a"), Some([0, 0, 0, 1].as_ref()))]
+    );
+}
+
+#[test]
+fn named_values_macro() {
+    let values: SerializedResult = crate::named_values! {"b" => "x", "a" => 1_i32};
+    assert_eq!(
+        values.unwrap().iter_name_value_pairs().collect::<Vec<_>>(),
+        vec![
+            (Some("b"), Some([b'x'].as_ref())),
+            (Some("a"), Some([0, 0, 0, 1].as_ref()))
+        ]
+    );
+
+    let empty: SerializedResult = crate::named_values! {};
+    assert!(empty.unwrap().is_empty());
+
+    struct TooBigValue;
+    impl Value for TooBigValue {
+        fn serialize(&self, _buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
+            Err(ValueTooBig)
+        }
+    }
+    let too_big: SerializedResult = crate::named_values! {"a" => TooBigValue};
+    assert_eq!(too_big, Err(SerializeValuesError::ValueTooBig(ValueTooBig)));
+}
+
+#[test]
+fn named_batch_values() {
+    let values: BTreeMap<&str, i32> = vec![("a", 1)].into_iter().collect();
+    let mut request: Vec<u8> = Vec::new();
+    assert_eq!(
+        vec![values].write_nth_to_request(0, &mut request),
+        Err(SerializeValuesError::NamedValuesInBatch)
+    );
+}
+
+#[test]
+fn derive_value_list() {
+    use crate as scylla;
+    use crate::ValueList;
+
+    #[derive(ValueList)]
+    struct Positional {
+        a: i32,
+        b: Option<String>,
+        c: &'static str,
+    }
+
+    let values = Positional {
+        a: 1,
+        b: None,
+        c: "x",
+    };
+    let expected: SerializedValues = (1_i32, None::<String>, "x")
+        .serialized()
+        .unwrap()
+        .into_owned();
+    assert_eq!(values.serialized().unwrap().into_owned(), expected);
+
+    #[derive(ValueList)]
+    #[value_list(named)]
+    struct Named {
+        a: i32,
+        bc: Vec<i8>,
+    }
+
+    let values = Named { a: 2, bc: vec![3] };
+    let serialized = values.serialized().unwrap();
+    assert_eq!(
+        serialized.iter_name_value_pairs().collect::<Vec<_>>(),
+        vec![
+            (Some("a"), Some([0, 0, 0, 2].as_ref())),
+            (Some("bc"), Some([0, 0, 0, 1, 0, 0, 0, 1, 3].as_ref()))
+        ]
+    );
+}
+
 #[test]
 fn slice_batch_values() {
     let batch_values: &[&[i8]] = &[&[1, 2], &[2, 3, 4, 5], &[6]];
@@ -666,3 +1092,72 @@ fn ref_batch_values() {
         assert_eq!(request, vec![0, 2, 0, 0, 0, 1, 1, 0, 0, 0, 1, 2]);
     }
 }
+
+#[test]
+fn iter_batch_values() {
+    let values: Vec<(i8, i8)> = vec![(1, 2), (3, 4)];
+    let batch_values = BatchValuesFromIter::new(values.iter());
+
+    assert_eq!(batch_values.len(), 2);
+
+    {
+        let mut request: Vec<u8> = Vec::new();
+        batch_values.write_nth_to_request(1, &mut request).unwrap();
+        assert_eq!(request, vec![0, 2, 0, 0, 0, 1, 3, 0, 0, 0, 1, 4]);
+    }
+
+    // Iterators can map values on the fly
+    let mapped_batch_values = BatchValuesFromIter::new(values.iter().map(|(a, _)| (*a,)));
+    assert_eq!(mapped_batch_values.len(), 2);
+
+    {
+        let mut request: Vec<u8> = Vec::new();
+        mapped_batch_values
+            .write_nth_to_request(0, &mut request)
+            .unwrap();
+        assert_eq!(request, vec![0, 1, 0, 0, 0, 1, 1]);
+    }
+}
+
+#[test]
+fn iter_batch_values_are_traversed_once() {
+    let values: Vec<(i8,)> = (0..100).map(|i| (i,)).collect();
+    let advanced = std::cell::Cell::new(0);
+    let batch_values =
+        BatchValuesFromIter::new(values.iter().inspect(|_| advanced.set(advanced.get() + 1)));
+
+    let mut request: Vec<u8> = Vec::new();
+    let mut statements: Vec<usize> = Vec::new();
+    batch_values
+        .write_all_to_request(&mut request, |n, _| {
+            statements.push(n);
+            Ok::<(), SerializeValuesError>(())
+        })
+        .unwrap();
+
+    assert_eq!(advanced.get(), 100);
+    assert_eq!(statements, (0..100).collect::<Vec<usize>>());
+
+    // Same bytes as serializing each statement's values separately
+    let mut expected: Vec<u8> = Vec::new();
+    for n in 0..100 {
+        values
+            .as_slice()
+            .write_nth_to_request(n, &mut expected)
+            .unwrap();
+    }
+    assert_eq!(request, expected);
+}
+
+#[test]
+fn repeated_batch_values() {
+    let batch_values = RepeatedBatchValues::new((1_i8, 2_i8), 3);
+
+    assert_eq!(batch_values.len(), 3);
+
+    for n in 0..3 {
+        let mut request: Vec<u8> = Vec::new();
+        batch_values.write_nth_to_request(n, &mut request).unwrap();
+        assert_eq!(request, vec![0, 2, 0, 0, 0, 1, 1, 0, 0, 0, 1, 2]);
+    }
+}
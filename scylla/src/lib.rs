@@ -94,7 +94,11 @@
 #[macro_use]
 pub mod macros;
 
+#[cfg(feature = "arrow")]
+pub mod arrow;
 pub mod frame;
+#[cfg(feature = "proxy")]
+pub mod proxy;
 pub mod routing;
 pub mod statement;
 pub mod tracing;
@@ -102,17 +106,27 @@ pub mod transport;
 
 pub use macros::*;
 pub use statement::batch;
+pub use statement::checked;
 pub use statement::prepared_statement;
 pub use statement::query;
+pub use statement::query_builder;
 
 pub use frame::response::cql_to_rust;
 
-pub use transport::connection::{BatchResult, QueryResult};
+pub use transport::connection::{
+    ConditionalResult, FirstRowError, FirstRowTypedError, MaybeFirstRowTypedError, QueryResult,
+    RowsExpectedError, SingleRowError, SingleRowTypedError,
+};
 pub use transport::session::{IntoTypedRows, Session, SessionConfig};
 pub use transport::session_builder::SessionBuilder;
 
+pub use transport::address_translator;
+pub use transport::authentication;
+pub use transport::host_filter;
 pub use transport::load_balancing;
+pub use transport::reconnection_policy;
 pub use transport::retry_policy;
 pub use transport::speculative_execution;
+pub use transport::timestamp_generator;
 
-pub use transport::metrics::Metrics;
+pub use transport::metrics::{Metrics, MetricsDelta, MetricsSnapshot, MetricsView, NodeMetrics};
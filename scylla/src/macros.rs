@@ -10,5 +10,19 @@ pub use scylla_macros::FromUserType;
 /// Works only on simple structs without generics etc
 pub use scylla_macros::IntoUserType;
 
+/// #[derive(ValueList)] allows to pass struct as a list of values bound in queries.
+/// Fields are bound in declaration order, or by their names with `#[value_list(named)]`
+/// Works only on simple structs without generics etc
+pub use scylla_macros::ValueList;
+
+/// #[derive(Table)] maps a struct to a table, see [`TableMapper`](crate::transport::table_mapper::TableMapper)
+/// Works only on simple structs without generics etc
+pub use scylla_macros::Table;
+
+/// checked_statement! validates statements against a schema snapshot at compile time,
+/// see [`checked`](crate::statement::checked)
+#[cfg(feature = "checked-statements")]
+pub use scylla_macros::checked_statement;
+
 // Reexports for derive(IntoUserType)
 pub use bytes::{BufMut, Bytes, BytesMut};
@@ -0,0 +1,211 @@
+use bytes::{Buf, BufMut, Bytes};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::ProxyError;
+use crate::frame::request::RequestOpcode;
+use crate::frame::response::{error::Error, ResponseOpcode};
+use crate::frame::{types, FrameParams, FLAG_COMPRESSION};
+use crate::transport::errors::DbError;
+
+const HEADER_SIZE: usize = 9;
+
+/// Frame sent by the driver to the node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestFrame {
+    pub params: FrameParams,
+    pub opcode: RequestOpcode,
+    /// Body of the frame, compressed if the connection uses compression
+    pub body: Bytes,
+}
+
+/// Frame sent by the node to the driver
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseFrame {
+    pub params: FrameParams,
+    pub opcode: ResponseOpcode,
+    /// Body of the frame, compressed if the connection uses compression
+    pub body: Bytes,
+}
+
+impl RequestFrame {
+    pub(super) fn to_bytes(&self) -> Bytes {
+        frame_to_bytes(self.params, self.opcode as u8, &self.body)
+    }
+}
+
+impl ResponseFrame {
+    /// Creates an ERROR response to `request`, which can be sent to the driver instead of the node's response
+    pub fn forged_error(request: &RequestFrame, error: DbError, reason: impl Into<String>) -> Self {
+        let mut body = Vec::new();
+        Error {
+            error,
+            reason: reason.into(),
+        }
+        .serialize(&mut body)
+        .expect("Serializing error response failed");
+
+        ResponseFrame {
+            params: FrameParams {
+                version: request.params.version | 0x80,
+                flags: 0,
+                stream: request.params.stream,
+            },
+            opcode: ResponseOpcode::Error,
+            body: body.into(),
+        }
+    }
+
+    pub(super) fn to_bytes(&self) -> Bytes {
+        frame_to_bytes(self.params, self.opcode as u8, &self.body)
+    }
+
+    // Removes shard-aware ports from a SUPPORTED response,
+    // otherwise the driver would connect to these ports directly, bypassing the proxy
+    pub(super) fn strip_shard_aware_ports(&mut self) -> Result<(), ProxyError> {
+        if self.opcode != ResponseOpcode::Supported || self.params.flags & FLAG_COMPRESSION != 0 {
+            return Ok(());
+        }
+
+        let mut options: HashMap<String, Vec<String>> =
+            types::read_string_multimap(&mut &self.body[..])
+                .map_err(|_| ProxyError::InvalidFrame("Invalid SUPPORTED response body"))?;
+        options.remove("SCYLLA_SHARD_AWARE_PORT");
+        options.remove("SCYLLA_SHARD_AWARE_PORT_SSL");
+
+        let mut body = Vec::new();
+        types::write_string_multimap(&options, &mut body)
+            .map_err(|_| ProxyError::InvalidFrame("Invalid SUPPORTED response body"))?;
+        self.body = body.into();
+        Ok(())
+    }
+}
+
+fn frame_to_bytes(params: FrameParams, opcode: u8, body: &[u8]) -> Bytes {
+    let mut data = Vec::with_capacity(HEADER_SIZE + body.len());
+    data.put_u8(params.version);
+    data.put_u8(params.flags);
+    data.put_i16(params.stream);
+    data.put_u8(opcode);
+    data.put_u32(body.len() as u32);
+    data.extend_from_slice(body);
+    data.into()
+}
+
+// Reads the next frame, returns None if the connection was closed before it started
+async fn read_frame(
+    reader: &mut (impl AsyncRead + Unpin),
+) -> Result<Option<(FrameParams, u8, Bytes)>, ProxyError> {
+    let mut raw_header = [0u8; HEADER_SIZE];
+    let mut read = 0;
+    while read < HEADER_SIZE {
+        let n = reader.read(&mut raw_header[read..]).await?;
+        if n == 0 {
+            if read == 0 {
+                return Ok(None);
+            }
+            return Err(ProxyError::InvalidFrame(
+                "Connection closed in the middle of a frame",
+            ));
+        }
+        read += n;
+    }
+
+    let (params, opcode, length) = parse_header(&raw_header);
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+
+    Ok(Some((params, opcode, body.into())))
+}
+
+// Parses a frame from the beginning of `buf`, which has to contain all of it
+fn parse_frame(buf: &mut &[u8]) -> Result<(FrameParams, u8, Bytes), ProxyError> {
+    if buf.len() < HEADER_SIZE {
+        return Err(ProxyError::InvalidFrame("Incomplete frame header"));
+    }
+    let (params, opcode, length) = parse_header(&buf[..HEADER_SIZE]);
+    buf.advance(HEADER_SIZE);
+
+    if buf.len() < length {
+        return Err(ProxyError::InvalidFrame("Incomplete frame body"));
+    }
+    let body = Bytes::copy_from_slice(&buf[..length]);
+    buf.advance(length);
+
+    Ok((params, opcode, body))
+}
+
+fn parse_header(mut raw_header: &[u8]) -> (FrameParams, u8, usize) {
+    let params = FrameParams {
+        version: raw_header.get_u8(),
+        flags: raw_header.get_u8(),
+        stream: raw_header.get_i16(),
+    };
+    let opcode = raw_header.get_u8();
+    let length = raw_header.get_u32() as usize;
+    (params, opcode, length)
+}
+
+pub(super) async fn read_request_frame(
+    reader: &mut (impl AsyncRead + Unpin),
+) -> Result<Option<RequestFrame>, ProxyError> {
+    match read_frame(reader).await? {
+        Some((params, opcode, body)) => request_frame(params, opcode, body).map(Some),
+        None => Ok(None),
+    }
+}
+
+pub(super) fn parse_request_frame(buf: &mut &[u8]) -> Result<RequestFrame, ProxyError> {
+    let (params, opcode, body) = parse_frame(buf)?;
+    request_frame(params, opcode, body)
+}
+
+fn request_frame(params: FrameParams, opcode: u8, body: Bytes) -> Result<RequestFrame, ProxyError> {
+    if params.version & 0x80 != 0 {
+        return Err(ProxyError::InvalidFrame(
+            "Response frame sent by the driver",
+        ));
+    }
+    let opcode =
+        RequestOpcode::try_from(opcode).map_err(|_| ProxyError::UnknownRequestOpcode(opcode))?;
+
+    Ok(RequestFrame {
+        params,
+        opcode,
+        body,
+    })
+}
+
+pub(super) async fn read_response_frame(
+    reader: &mut (impl AsyncRead + Unpin),
+) -> Result<Option<ResponseFrame>, ProxyError> {
+    match read_frame(reader).await? {
+        Some((params, opcode, body)) => response_frame(params, opcode, body).map(Some),
+        None => Ok(None),
+    }
+}
+
+pub(super) fn parse_response_frame(buf: &mut &[u8]) -> Result<ResponseFrame, ProxyError> {
+    let (params, opcode, body) = parse_frame(buf)?;
+    response_frame(params, opcode, body)
+}
+
+fn response_frame(
+    params: FrameParams,
+    opcode: u8,
+    body: Bytes,
+) -> Result<ResponseFrame, ProxyError> {
+    if params.version & 0x80 == 0 {
+        return Err(ProxyError::InvalidFrame("Request frame sent by the node"));
+    }
+    let opcode =
+        ResponseOpcode::try_from(opcode).map_err(|_| ProxyError::UnknownResponseOpcode(opcode))?;
+
+    Ok(ResponseFrame {
+        params,
+        opcode,
+        body,
+    })
+}
@@ -0,0 +1,1184 @@
+//! Simulated node, which answers statements with responses chosen by the test
+
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use uuid::Uuid;
+
+use super::frame::{read_request_frame, RequestFrame, ResponseFrame};
+use super::ProxyError;
+use crate::frame::request::RequestOpcode;
+use crate::frame::response::error::Error;
+use crate::frame::response::event::{
+    Event, SchemaChangeEvent, SchemaChangeType, StatusChangeEvent, TopologyChangeEvent,
+};
+use crate::frame::response::result::{ColumnType, CqlValue};
+use crate::frame::response::ResponseOpcode;
+use crate::frame::types;
+use crate::frame::value::Value;
+use crate::frame::FrameParams;
+use crate::transport::errors::DbError;
+
+/// Response of the mock node to a statement
+#[derive(Debug, Clone)]
+pub enum MockResponse {
+    /// Result without rows, returned e.g. by INSERT
+    Void,
+    /// Rows with given columns
+    Rows {
+        columns: Vec<(String, ColumnType)>,
+        rows: Vec<Vec<Option<CqlValue>>>,
+    },
+    /// Error with a message
+    Error(DbError, String),
+}
+
+impl MockResponse {
+    /// Rows with given column names and types, each row has to contain a value for every column
+    pub fn rows(columns: &[(&str, ColumnType)], rows: Vec<Vec<Option<CqlValue>>>) -> Self {
+        MockResponse::Rows {
+            columns: columns
+                .iter()
+                .map(|(name, typ)| (name.to_string(), typ.clone()))
+                .collect(),
+            rows,
+        }
+    }
+
+    pub fn error(error: DbError, message: impl Into<String>) -> Self {
+        MockResponse::Error(error, message.into())
+    }
+}
+
+/// Rule choosing the response to statements containing the pattern.
+/// Both are compared ignoring case and whitespace differences, an empty pattern matches all statements
+#[derive(Debug, Clone)]
+pub struct MockRule {
+    pattern: String,
+    response: MockResponse,
+    times: Option<usize>,
+    delay: Option<Duration>,
+}
+
+impl MockRule {
+    pub fn new(pattern: &str, response: MockResponse) -> Self {
+        MockRule {
+            pattern: normalize_statement(pattern),
+            response,
+            times: None,
+            delay: None,
+        }
+    }
+
+    /// The rule is used only for the first `times` matching statements
+    pub fn times(mut self, times: usize) -> Self {
+        self.times = Some(times);
+        self
+    }
+
+    /// The response is sent after a delay, responses to other requests aren't held back
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+}
+
+fn normalize_statement(statement: &str) -> String {
+    statement
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Configuration of a simulated node, started with [`run`](MockNode::run)
+///
+/// The node accepts connections from the driver and answers statements using [`MockRule`]s,
+/// the first matching rule is used. Statements not matching any rule get the default response.
+/// `USE` statements and queries to system tables, which the driver sends when connecting,
+/// are answered as a single node cluster would, unless a rule matches them.
+/// Like Scylla, the node marks prepared statements containing ` IF ` as lightweight transactions
+/// when the driver enables `SCYLLA_LWT_ADD_METADATA_MARK`.
+pub struct MockNode {
+    listen_addr: SocketAddr,
+    rules: Vec<MockRule>,
+    default_response: MockResponse,
+    authentication: Option<MockAuthentication>,
+}
+
+impl MockNode {
+    pub fn new() -> Self {
+        MockNode {
+            listen_addr: SocketAddr::from(([127, 0, 0, 1], 0)),
+            rules: Vec::new(),
+            default_response: MockResponse::Void,
+            authentication: None,
+        }
+    }
+
+    /// Address on which the node accepts connections, by default a random port on localhost
+    pub fn listen_addr(mut self, listen_addr: SocketAddr) -> Self {
+        self.listen_addr = listen_addr;
+        self
+    }
+
+    /// Adds a rule, rules are checked in the order of adding
+    pub fn rule(mut self, rule: MockRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Response to statements not matching any rule, `Void` by default
+    pub fn default_response(mut self, response: MockResponse) -> Self {
+        self.default_response = response;
+        self
+    }
+
+    /// Requires authentication with the authenticator of the given name.
+    /// The node expects the driver to send `tokens` in order: after the n-th token
+    /// it sends a challenge containing `challenge n`, after the last one it accepts
+    /// the connection. Any other token is rejected with an `AuthenticationError`
+    pub fn authentication(
+        mut self,
+        authenticator_name: impl Into<String>,
+        tokens: Vec<Vec<u8>>,
+    ) -> Self {
+        self.authentication = Some(MockAuthentication {
+            authenticator_name: authenticator_name.into(),
+            tokens,
+        });
+        self
+    }
+
+    /// Starts accepting connections
+    pub async fn run(self) -> Result<RunningMockNode, ProxyError> {
+        let listener = TcpListener::bind(self.listen_addr).await?;
+        let addr = listener.local_addr()?;
+
+        let state = Arc::new(Mutex::new(MockState {
+            rules: self.rules,
+            default_response: self.default_response,
+            authentication: self.authentication,
+            prepared: HashMap::new(),
+            received_statements: Vec::new(),
+            registered_connections: Vec::new(),
+            address: addr.ip(),
+            host_id: Uuid::from_u128(rand::random()),
+            schema_version: Uuid::from_u128(rand::random()),
+            lwt_mark_enabled: false,
+        }));
+        let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+        let (error_sender, error_receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn(accept_connections(
+            listener,
+            state.clone(),
+            shutdown_receiver,
+            error_sender,
+        ));
+
+        Ok(RunningMockNode {
+            addr,
+            state,
+            shutdown_sender,
+            error_receiver,
+        })
+    }
+}
+
+impl Default for MockNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Handle to a running mock node, which stops when it's dropped
+pub struct RunningMockNode {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    shutdown_sender: watch::Sender<bool>,
+    error_receiver: mpsc::UnboundedReceiver<ProxyError>,
+}
+
+impl RunningMockNode {
+    /// Address on which the node accepts connections
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Replaces the rules, also for already open connections
+    pub fn set_rules(&self, rules: Vec<MockRule>) {
+        self.state.lock().unwrap().rules = rules;
+    }
+
+    /// Adds a rule after the existing ones
+    pub fn add_rule(&self, rule: MockRule) {
+        self.state.lock().unwrap().rules.push(rule);
+    }
+
+    /// Forgets all prepared statements, as a restarted node would.
+    /// Executing them results in an `Unprepared` error until they're prepared again
+    pub fn forget_prepared_statements(&self) {
+        self.state.lock().unwrap().prepared.clear();
+    }
+
+    /// Sends the event to all connections which registered for events,
+    /// e.g. the driver's control connection
+    pub fn send_event(&self, event: &Event) -> Result<(), ProxyError> {
+        let mut body = Vec::new();
+        write_event(event, &mut body)?;
+        let frame = ResponseFrame {
+            params: FrameParams {
+                version: 0x84,
+                flags: 0,
+                stream: -1,
+            },
+            opcode: ResponseOpcode::Event,
+            body: body.into(),
+        }
+        .to_bytes();
+
+        // Closed connections are forgotten
+        self.state
+            .lock()
+            .unwrap()
+            .registered_connections
+            .retain(|connection| connection.send(frame.clone()).is_ok());
+        Ok(())
+    }
+
+    /// Statements received so far, in the order of arrival.
+    /// Executed prepared statements and statements in batches are included,
+    /// queries to system tables answered by the node itself aren't
+    pub fn received_statements(&self) -> Vec<String> {
+        self.state.lock().unwrap().received_statements.clone()
+    }
+
+    /// Stops the node and closes all connections.
+    /// Returns the first error which occurred, e.g. an invalid frame
+    pub async fn finish(mut self) -> Result<(), ProxyError> {
+        let _ = self.shutdown_sender.send(true);
+
+        let mut result = Ok(());
+        while let Some(error) = self.error_receiver.recv().await {
+            if result.is_ok() {
+                result = Err(error);
+            }
+        }
+        result
+    }
+}
+
+struct MockAuthentication {
+    authenticator_name: String,
+    tokens: Vec<Vec<u8>>,
+}
+
+struct MockState {
+    rules: Vec<MockRule>,
+    default_response: MockResponse,
+    authentication: Option<MockAuthentication>,
+    // Statements by prepared statement id
+    prepared: HashMap<Bytes, String>,
+    received_statements: Vec<String>,
+    // Channels sending frames to connections which sent REGISTER
+    registered_connections: Vec<mpsc::UnboundedSender<Bytes>>,
+    address: IpAddr,
+    host_id: Uuid,
+    schema_version: Uuid,
+    // Set when a STARTUP enabled marking LWT statements
+    lwt_mark_enabled: bool,
+}
+
+// Bit of prepared metadata flags marking LWT statements, same as in Scylla
+const LWT_MASK: u32 = 0x80000000;
+
+// Result of handling a request, the body isn't serialized yet
+enum Reply {
+    Ready,
+    Authenticate(String),
+    AuthChallenge(Vec<u8>),
+    AuthSuccess,
+    Supported,
+    Result(MockResponse, Option<Duration>),
+    SetKeyspace(String),
+    // Id, number of bind markers and whether the statement is a marked LWT
+    Prepared(Bytes, usize, bool),
+    Error(DbError, String),
+}
+
+impl MockState {
+    fn handle(&mut self, request: &RequestFrame) -> Result<Reply, ProxyError> {
+        // Like Scylla, the mock node supports only protocol v4
+        let version = request.params.version & 0x7f;
+        if version != 4 {
+            return Ok(Reply::Error(
+                DbError::ProtocolError,
+                format!(
+                    "Invalid or unsupported protocol version ({}); supported versions are (4)",
+                    version
+                ),
+            ));
+        }
+
+        let body = &mut &request.body[..];
+        Ok(match request.opcode {
+            RequestOpcode::Options => Reply::Supported,
+            RequestOpcode::Startup => {
+                let options = types::read_string_map(body).map_err(invalid_body)?;
+                if options.contains_key("SCYLLA_LWT_ADD_METADATA_MARK") {
+                    self.lwt_mark_enabled = true;
+                }
+                match &self.authentication {
+                    Some(authentication) => {
+                        Reply::Authenticate(authentication.authenticator_name.clone())
+                    }
+                    None => Reply::Ready,
+                }
+            }
+            RequestOpcode::Register => Reply::Ready,
+            RequestOpcode::Query => {
+                let statement = types::read_long_string(body).map_err(invalid_body)?;
+                self.statement_reply(statement)
+            }
+            RequestOpcode::Prepare => {
+                let statement = types::read_long_string(body).map_err(invalid_body)?;
+                let hash = crate::routing::hash3_x64_128(statement.as_bytes()) as i64;
+                let id = Bytes::copy_from_slice(&hash.to_be_bytes());
+                self.prepared.insert(id.clone(), statement.to_string());
+                let is_lwt = self.lwt_mark_enabled && statement.to_uppercase().contains(" IF ");
+                Reply::Prepared(id, count_bind_markers(statement), is_lwt)
+            }
+            RequestOpcode::Execute => {
+                let id = read_short_bytes(body)?;
+                match self.prepared.get(id).cloned() {
+                    Some(statement) => self.statement_reply(&statement),
+                    None => Reply::Error(
+                        DbError::Unprepared {
+                            statement_id: Bytes::copy_from_slice(id),
+                        },
+                        "Unknown prepared statement".into(),
+                    ),
+                }
+            }
+            RequestOpcode::Batch => self.batch_reply(body)?,
+            RequestOpcode::AuthResponse => {
+                let token = types::read_bytes_opt(body).map_err(invalid_body)?;
+                self.auth_reply(token)
+            }
+        })
+    }
+
+    fn statement_reply(&mut self, statement: &str) -> Reply {
+        if let Some((response, delay)) = self.find_response(statement) {
+            self.received_statements.push(statement.to_string());
+            return match response {
+                MockResponse::Error(error, message) if delay.is_none() => {
+                    Reply::Error(error, message)
+                }
+                response => Reply::Result(response, delay),
+            };
+        }
+
+        if let Some(rows) = self.system_table_rows(statement) {
+            return Reply::Result(rows, None);
+        }
+
+        self.received_statements.push(statement.to_string());
+        if let Some(keyspace) = used_keyspace(statement) {
+            return Reply::SetKeyspace(keyspace);
+        }
+
+        match self.default_response.clone() {
+            MockResponse::Error(error, message) => Reply::Error(error, message),
+            response => Reply::Result(response, None),
+        }
+    }
+
+    fn auth_reply(&self, token: Option<&[u8]>) -> Reply {
+        let tokens = match &self.authentication {
+            Some(authentication) => &authentication.tokens,
+            None => {
+                return Reply::Error(
+                    DbError::ProtocolError,
+                    "Authentication isn't required".into(),
+                )
+            }
+        };
+        match tokens.iter().position(|t| Some(&t[..]) == token) {
+            Some(n) if n + 1 == tokens.len() => Reply::AuthSuccess,
+            Some(n) => Reply::AuthChallenge(format!("challenge {}", n + 1).into_bytes()),
+            None => Reply::Error(DbError::AuthenticationError, "Bad credentials".into()),
+        }
+    }
+
+    // A batch fails with the error of the first statement whose rule returns an error,
+    // otherwise it succeeds after the longest delay of the matched rules.
+    // A batch with an unknown prepared statement is rejected before any statement is received
+    fn batch_reply(&mut self, body: &mut &[u8]) -> Result<Reply, ProxyError> {
+        let _batch_type = read_u8(body)?;
+        let statements_count = types::read_short(body).map_err(invalid_body)?;
+
+        let mut statements = Vec::with_capacity(statements_count.max(0) as usize);
+        for _ in 0..statements_count {
+            let statement = match read_u8(body)? {
+                0 => types::read_long_string(body)
+                    .map_err(invalid_body)?
+                    .to_string(),
+                _ => {
+                    let id = read_short_bytes(body)?;
+                    match self.prepared.get(id) {
+                        Some(statement) => statement.clone(),
+                        None => {
+                            return Ok(Reply::Error(
+                                DbError::Unprepared {
+                                    statement_id: Bytes::copy_from_slice(id),
+                                },
+                                "Unknown prepared statement".into(),
+                            ))
+                        }
+                    }
+                }
+            };
+
+            // Skip the values
+            let values_count = types::read_short(body).map_err(invalid_body)?;
+            for _ in 0..values_count {
+                let len = types::read_int(body).map_err(invalid_body)?;
+                let len: usize = len.max(0).try_into().unwrap();
+                if body.len() < len {
+                    return Err(invalid_body(()));
+                }
+                *body = &body[len..];
+            }
+
+            statements.push(statement);
+        }
+
+        let mut error = None;
+        let mut delay: Option<Duration> = None;
+        for statement in statements {
+            self.received_statements.push(statement.clone());
+            if error.is_none() {
+                match self.find_response(&statement) {
+                    Some((MockResponse::Error(db_error, message), _)) => {
+                        error = Some(Reply::Error(db_error, message));
+                    }
+                    Some((_, Some(rule_delay))) => delay = delay.max(Some(rule_delay)),
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(error.unwrap_or(Reply::Result(MockResponse::Void, delay)))
+    }
+
+    fn find_response(&mut self, statement: &str) -> Option<(MockResponse, Option<Duration>)> {
+        let statement = normalize_statement(statement);
+        for rule in self.rules.iter_mut() {
+            if rule.times == Some(0) || !statement.contains(&rule.pattern) {
+                continue;
+            }
+            if let Some(times) = &mut rule.times {
+                *times -= 1;
+            }
+            return Some((rule.response.clone(), rule.delay));
+        }
+        None
+    }
+
+    // Answers queries to system tables as a single node cluster with an empty schema would
+    fn system_table_rows(&self, statement: &str) -> Option<MockResponse> {
+        let normalized = normalize_statement(statement);
+        let from = normalized.find(" from ")?;
+        let table = normalized[from + 6..].split(' ').next()?;
+        let is_local = match table {
+            "system.local" => true,
+            "system.peers" => false,
+            _ if table.starts_with("system_schema.") => false,
+            _ => return None,
+        };
+
+        let selected = normalized.strip_prefix("select ")?[..from - 7].to_string();
+        let columns: Vec<(String, ColumnType, Option<CqlValue>)> = selected
+            .split(',')
+            .map(|column| self.local_column(column.trim()))
+            .collect();
+
+        let rows = if is_local {
+            vec![columns.iter().map(|(_, _, value)| value.clone()).collect()]
+        } else {
+            Vec::new()
+        };
+        Some(MockResponse::Rows {
+            columns: columns
+                .into_iter()
+                .map(|(name, typ, _)| (name, typ))
+                .collect(),
+            rows,
+        })
+    }
+
+    fn local_column(&self, name: &str) -> (String, ColumnType, Option<CqlValue>) {
+        let text = |value: &str| Some(CqlValue::Text(value.to_string()));
+        let (typ, value) = match name {
+            "rpc_address" | "broadcast_address" | "listen_address" | "peer" => {
+                (ColumnType::Inet, Some(CqlValue::Inet(self.address)))
+            }
+            "data_center" => (ColumnType::Text, text("datacenter1")),
+            "rack" => (ColumnType::Text, text("rack1")),
+            "tokens" => (
+                ColumnType::Set(Box::new(ColumnType::Text)),
+                Some(CqlValue::Set(vec![CqlValue::Text("0".to_string())])),
+            ),
+            "host_id" => (ColumnType::Uuid, Some(CqlValue::Uuid(self.host_id))),
+            "schema_version" => (ColumnType::Uuid, Some(CqlValue::Uuid(self.schema_version))),
+            "cluster_name" => (ColumnType::Text, text("Mock Cluster")),
+            "release_version" => (ColumnType::Text, text("3.0.8")),
+            "cql_version" => (ColumnType::Text, text("3.3.1")),
+            "native_protocol_version" => (ColumnType::Text, text("4")),
+            "key" => (ColumnType::Text, text("local")),
+            "partitioner" => (
+                ColumnType::Text,
+                text("org.apache.cassandra.dht.Murmur3Partitioner"),
+            ),
+            _ => (ColumnType::Blob, None),
+        };
+        (name.to_string(), typ, value)
+    }
+}
+
+fn invalid_body<E>(_: E) -> ProxyError {
+    ProxyError::InvalidFrame("Invalid request body")
+}
+
+fn read_u8(buf: &mut &[u8]) -> Result<u8, ProxyError> {
+    let (first, rest) = buf.split_first().ok_or_else(|| invalid_body(()))?;
+    *buf = rest;
+    Ok(*first)
+}
+
+fn read_short_bytes<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8], ProxyError> {
+    let len: usize = types::read_short(buf)
+        .map_err(invalid_body)?
+        .try_into()
+        .map_err(invalid_body)?;
+    if buf.len() < len {
+        return Err(invalid_body(()));
+    }
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(bytes)
+}
+
+// Statement is `USE keyspace`, possibly with a quoted keyspace name
+fn used_keyspace(statement: &str) -> Option<String> {
+    let statement = statement.trim().trim_end_matches(';').trim_end();
+    let mut words = statement.splitn(2, char::is_whitespace);
+    if !words.next()?.eq_ignore_ascii_case("use") {
+        return None;
+    }
+    let keyspace = words.next()?.trim();
+    Some(
+        match keyspace.strip_prefix('"').and_then(|k| k.strip_suffix('"')) {
+            Some(quoted) => quoted.replace("\"\"", "\""),
+            None => keyspace.to_lowercase(),
+        },
+    )
+}
+
+// Counts `?` markers outside of string literals and quoted names
+fn count_bind_markers(statement: &str) -> usize {
+    let mut count = 0;
+    let mut quote: Option<char> = None;
+    for c in statement.chars() {
+        match (quote, c) {
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '?') => count += 1,
+            _ => {}
+        }
+    }
+    count
+}
+
+fn write_type(typ: &ColumnType, buf: &mut Vec<u8>) -> Result<(), ProxyError> {
+    let id: i16 = match typ {
+        ColumnType::Ascii => 0x0001,
+        ColumnType::BigInt => 0x0002,
+        ColumnType::Blob => 0x0003,
+        ColumnType::Boolean => 0x0004,
+        ColumnType::Counter => 0x0005,
+        ColumnType::Decimal => 0x0006,
+        ColumnType::Double => 0x0007,
+        ColumnType::Float => 0x0008,
+        ColumnType::Int => 0x0009,
+        ColumnType::Timestamp => 0x000B,
+        ColumnType::Uuid => 0x000C,
+        ColumnType::Text => 0x000D,
+        ColumnType::Varint => 0x000E,
+        ColumnType::Timeuuid => 0x000F,
+        ColumnType::Inet => 0x0010,
+        ColumnType::Date => 0x0011,
+        ColumnType::Time => 0x0012,
+        ColumnType::SmallInt => 0x0013,
+        ColumnType::TinyInt => 0x0014,
+        ColumnType::Duration => 0x0015,
+        ColumnType::List(_) => 0x0020,
+        ColumnType::Map(_, _) => 0x0021,
+        ColumnType::Set(_) => 0x0022,
+        ColumnType::UserDefinedType { .. } => 0x0030,
+        ColumnType::Tuple(_) => 0x0031,
+    };
+    types::write_short(id, buf);
+
+    match typ {
+        ColumnType::List(element) | ColumnType::Set(element) => write_type(element, buf)?,
+        ColumnType::Map(key, value) => {
+            write_type(key, buf)?;
+            write_type(value, buf)?;
+        }
+        ColumnType::UserDefinedType {
+            type_name,
+            keyspace,
+            field_types,
+        } => {
+            types::write_string(keyspace, buf).map_err(invalid_body)?;
+            types::write_string(type_name, buf).map_err(invalid_body)?;
+            types::write_short(field_types.len().try_into().map_err(invalid_body)?, buf);
+            for (name, typ) in field_types {
+                types::write_string(name, buf).map_err(invalid_body)?;
+                write_type(typ, buf)?;
+            }
+        }
+        ColumnType::Tuple(element_types) => {
+            types::write_short(element_types.len().try_into().map_err(invalid_body)?, buf);
+            for typ in element_types {
+                write_type(typ, buf)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// Columns share a single, empty table spec
+fn write_column_specs<'a>(
+    columns: impl ExactSizeIterator<Item = (&'a str, &'a ColumnType)>,
+    buf: &mut Vec<u8>,
+) -> Result<(), ProxyError> {
+    types::write_string("", buf).map_err(invalid_body)?;
+    types::write_string("", buf).map_err(invalid_body)?;
+    for (name, typ) in columns {
+        types::write_string(name, buf).map_err(invalid_body)?;
+        write_type(typ, buf)?;
+    }
+    Ok(())
+}
+
+fn write_event(event: &Event, buf: &mut Vec<u8>) -> Result<(), ProxyError> {
+    match event {
+        Event::TopologyChange(change) => {
+            let (change_type, addr) = match change {
+                TopologyChangeEvent::NewNode(addr) => ("NEW_NODE", addr),
+                TopologyChangeEvent::RemovedNode(addr) => ("REMOVED_NODE", addr),
+            };
+            types::write_string("TOPOLOGY_CHANGE", buf).map_err(invalid_body)?;
+            types::write_string(change_type, buf).map_err(invalid_body)?;
+            types::write_inet(*addr, buf);
+        }
+        Event::StatusChange(change) => {
+            let (change_type, addr) = match change {
+                StatusChangeEvent::Up(addr) => ("UP", addr),
+                StatusChangeEvent::Down(addr) => ("DOWN", addr),
+            };
+            types::write_string("STATUS_CHANGE", buf).map_err(invalid_body)?;
+            types::write_string(change_type, buf).map_err(invalid_body)?;
+            types::write_inet(*addr, buf);
+        }
+        Event::SchemaChange(change) => {
+            types::write_string("SCHEMA_CHANGE", buf).map_err(invalid_body)?;
+            let change_type = match change {
+                SchemaChangeEvent::KeyspaceChange { change_type, .. }
+                | SchemaChangeEvent::TableChange { change_type, .. }
+                | SchemaChangeEvent::TypeChange { change_type, .. }
+                | SchemaChangeEvent::FunctionChange { change_type, .. }
+                | SchemaChangeEvent::AggregateChange { change_type, .. } => change_type,
+            };
+            let change_type = match change_type {
+                SchemaChangeType::Created => "CREATED",
+                SchemaChangeType::Updated => "UPDATED",
+                SchemaChangeType::Dropped => "DROPPED",
+                SchemaChangeType::Invalid => "INVALID",
+            };
+            types::write_string(change_type, buf).map_err(invalid_body)?;
+
+            let (target, name, arguments) = match change {
+                SchemaChangeEvent::KeyspaceChange { .. } => ("KEYSPACE", None, None),
+                SchemaChangeEvent::TableChange { object_name, .. } => {
+                    ("TABLE", Some(object_name), None)
+                }
+                SchemaChangeEvent::TypeChange { type_name, .. } => ("TYPE", Some(type_name), None),
+                SchemaChangeEvent::FunctionChange {
+                    function_name,
+                    arguments,
+                    ..
+                } => ("FUNCTION", Some(function_name), Some(arguments)),
+                SchemaChangeEvent::AggregateChange {
+                    aggregate_name,
+                    arguments,
+                    ..
+                } => ("AGGREGATE", Some(aggregate_name), Some(arguments)),
+            };
+            types::write_string(target, buf).map_err(invalid_body)?;
+            types::write_string(change.keyspace_name(), buf).map_err(invalid_body)?;
+            if let Some(name) = name {
+                types::write_string(name, buf).map_err(invalid_body)?;
+            }
+            if let Some(arguments) = arguments {
+                types::write_short(arguments.len().try_into().map_err(invalid_body)?, buf);
+                for argument in arguments {
+                    types::write_string(argument, buf).map_err(invalid_body)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn serialize_reply(reply: Reply) -> Result<(ResponseOpcode, Vec<u8>), ProxyError> {
+    let mut body = Vec::new();
+    let opcode = match reply {
+        Reply::Ready => ResponseOpcode::Ready,
+        Reply::Authenticate(authenticator_name) => {
+            types::write_string(&authenticator_name, &mut body).map_err(invalid_body)?;
+            ResponseOpcode::Authenticate
+        }
+        Reply::AuthChallenge(token) => {
+            types::write_bytes(&token, &mut body).map_err(invalid_body)?;
+            ResponseOpcode::AuthChallenge
+        }
+        Reply::AuthSuccess => {
+            types::write_int(-1, &mut body);
+            ResponseOpcode::AuthSuccess
+        }
+        Reply::Supported => {
+            let mut options = HashMap::new();
+            options.insert("CQL_VERSION".to_string(), vec!["3.3.1".to_string()]);
+            options.insert("COMPRESSION".to_string(), Vec::new());
+            options.insert(
+                "SCYLLA_LWT_ADD_METADATA_MARK".to_string(),
+                vec![format!("LWT_OPTIMIZATION_META_BIT_MASK={}", LWT_MASK)],
+            );
+            types::write_string_multimap(&options, &mut body).map_err(invalid_body)?;
+            ResponseOpcode::Supported
+        }
+        Reply::Error(error, reason) | Reply::Result(MockResponse::Error(error, reason), _) => {
+            Error { error, reason }
+                .serialize(&mut body)
+                .map_err(invalid_body)?;
+            ResponseOpcode::Error
+        }
+        Reply::Result(MockResponse::Void, _) => {
+            types::write_int(0x0001, &mut body);
+            ResponseOpcode::Result
+        }
+        Reply::Result(MockResponse::Rows { columns, rows }, _) => {
+            types::write_int(0x0002, &mut body);
+            // Global table spec
+            types::write_int(0x0001, &mut body);
+            types::write_int(columns.len().try_into().map_err(invalid_body)?, &mut body);
+            write_column_specs(
+                columns.iter().map(|(name, typ)| (name.as_str(), typ)),
+                &mut body,
+            )?;
+            types::write_int(rows.len().try_into().map_err(invalid_body)?, &mut body);
+            for row in rows {
+                if row.len() != columns.len() {
+                    return Err(ProxyError::InvalidFrame(
+                        "Mocked row has a different number of values than columns",
+                    ));
+                }
+                for value in row {
+                    value.serialize(&mut body).map_err(invalid_body)?;
+                }
+            }
+            ResponseOpcode::Result
+        }
+        Reply::SetKeyspace(keyspace) => {
+            types::write_int(0x0003, &mut body);
+            types::write_string(&keyspace, &mut body).map_err(invalid_body)?;
+            ResponseOpcode::Result
+        }
+        Reply::Prepared(id, bind_markers, is_lwt) => {
+            types::write_int(0x0004, &mut body);
+            types::write_short_bytes(&id, &mut body).map_err(invalid_body)?;
+
+            // Bind markers, without partition key indexes, so the driver doesn't compute tokens
+            let lwt_flag = if is_lwt { LWT_MASK as i32 } else { 0 };
+            types::write_int(0x0001 | lwt_flag, &mut body);
+            types::write_int(bind_markers.try_into().map_err(invalid_body)?, &mut body);
+            types::write_int(0, &mut body);
+            let names: Vec<String> = (0..bind_markers).map(|i| format!("bind_{}", i)).collect();
+            write_column_specs(
+                names.iter().map(|name| (name.as_str(), &ColumnType::Blob)),
+                &mut body,
+            )?;
+
+            // Result metadata isn't sent, rows always contain it
+            types::write_int(0x0004, &mut body);
+            types::write_int(0, &mut body);
+            ResponseOpcode::Result
+        }
+    };
+    Ok((opcode, body))
+}
+
+// Simulated node answering requests on its own, without a real node behind it
+pub(super) trait RequestHandler: Send + Sync + 'static {
+    // Returns the response and the delay after which it's sent
+    fn handle(
+        &self,
+        request: &RequestFrame,
+    ) -> Result<(ResponseFrame, Option<Duration>), ProxyError>;
+
+    // Called when a connection sends REGISTER, events for it can be sent to `frames`
+    fn register_for_events(&self, _frames: &mpsc::UnboundedSender<Bytes>) {}
+}
+
+impl RequestHandler for Mutex<MockState> {
+    fn handle(
+        &self,
+        request: &RequestFrame,
+    ) -> Result<(ResponseFrame, Option<Duration>), ProxyError> {
+        let reply = self.lock().unwrap().handle(request)?;
+        let delay = match &reply {
+            Reply::Result(_, delay) => *delay,
+            _ => None,
+        };
+
+        let (opcode, body) = serialize_reply(reply)?;
+        let response = ResponseFrame {
+            params: FrameParams {
+                version: request.params.version | 0x80,
+                flags: 0,
+                stream: request.params.stream,
+            },
+            opcode,
+            body: body.into(),
+        };
+        Ok((response, delay))
+    }
+
+    fn register_for_events(&self, frames: &mpsc::UnboundedSender<Bytes>) {
+        self.lock()
+            .unwrap()
+            .registered_connections
+            .push(frames.clone());
+    }
+}
+
+pub(super) async fn accept_connections(
+    listener: TcpListener,
+    handler: Arc<impl RequestHandler>,
+    mut shutdown: watch::Receiver<bool>,
+    errors: mpsc::UnboundedSender<ProxyError>,
+) {
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let stream = match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(err) => {
+                        let _ = errors.send(err.into());
+                        return;
+                    }
+                };
+
+                let handler = handler.clone();
+                let shutdown = shutdown.clone();
+                let errors = errors.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = serve_connection(stream, handler, shutdown).await {
+                        if !super::is_connection_closed(&err) {
+                            let _ = errors.send(err);
+                        }
+                    }
+                });
+            }
+            _ = shutdown.changed() => return,
+        }
+    }
+}
+
+async fn serve_connection(
+    stream: TcpStream,
+    handler: Arc<impl RequestHandler>,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<(), ProxyError> {
+    let (mut read_half, mut write_half) = stream.into_split();
+    let (responses, mut responses_receiver) = mpsc::unbounded_channel::<Bytes>();
+
+    let serve_requests = async {
+        while let Some(request) = read_request_frame(&mut read_half).await? {
+            let (response, delay) = handler.handle(&request)?;
+            if request.opcode == RequestOpcode::Register {
+                handler.register_for_events(&responses);
+            }
+            match delay {
+                Some(delay) => super::send_delayed(&responses, response.to_bytes(), delay),
+                None => super::send(&responses, response.to_bytes()),
+            }
+        }
+        Ok::<(), ProxyError>(())
+    };
+
+    let write_responses = async {
+        while let Some(response) = responses_receiver.recv().await {
+            write_half.write_all(&response).await?;
+        }
+        Ok::<(), ProxyError>(())
+    };
+
+    tokio::select! {
+        result = serve_requests => result,
+        result = write_responses => result,
+        _ = shutdown.changed() => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{count_bind_markers, used_keyspace, MockNode, MockResponse, MockRule};
+    use crate::batch::Batch;
+    use crate::frame::response::result::{ColumnType, CqlValue};
+    use crate::frame::value::BatchValuesFromIter;
+    use crate::transport::errors::{DbError, QueryError};
+    use crate::{IntoTypedRows, Session, SessionBuilder};
+    use std::time::Duration;
+
+    #[test]
+    fn parse_statements() {
+        assert_eq!(used_keyspace("USE Ks;"), Some("ks".to_string()));
+        assert_eq!(
+            used_keyspace("use \"My\"\"Ks\""),
+            Some("My\"Ks".to_string())
+        );
+        assert_eq!(used_keyspace("SELECT * FROM users"), None);
+
+        assert_eq!(
+            count_bind_markers("SELECT * FROM t WHERE a = ? AND b = ?"),
+            2
+        );
+        assert_eq!(
+            count_bind_markers("INSERT INTO t (a, b) VALUES ('?', ?)"),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn session_with_mock_node() {
+        let node = MockNode::new()
+            .rule(
+                MockRule::new(
+                    "SELECT name FROM ks.users",
+                    MockResponse::error(DbError::Overloaded, "busy"),
+                )
+                .times(1),
+            )
+            .rule(MockRule::new(
+                "select name from ks.users",
+                MockResponse::rows(
+                    &[("name", ColumnType::Text)],
+                    vec![
+                        vec![Some(CqlValue::Text("ann".to_string()))],
+                        vec![Some(CqlValue::Text("bob".to_string()))],
+                    ],
+                ),
+            ))
+            .rule(
+                MockRule::new("INSERT INTO ks.slow", MockResponse::Void)
+                    .delay(Duration::from_millis(50)),
+            )
+            .run()
+            .await
+            .unwrap();
+
+        let session: Session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .build()
+            .await
+            .unwrap();
+
+        // The first query gets the error, later ones get the rows
+        let error = session
+            .query("SELECT name FROM ks.users", &[])
+            .await
+            .unwrap_err();
+        assert!(matches!(error, QueryError::DbError(DbError::Overloaded, _)));
+
+        let rows = session
+            .query("SELECT   name FROM ks.users WHERE id = 1", &[])
+            .await
+            .unwrap()
+            .rows
+            .unwrap();
+        let names: Vec<String> = rows
+            .into_typed::<(String,)>()
+            .map(|row| row.unwrap().0)
+            .collect();
+        assert_eq!(names, vec!["ann", "bob"]);
+
+        let prepared = session
+            .prepare("SELECT name FROM ks.users WHERE id = ?")
+            .await
+            .unwrap();
+        let rows = session
+            .execute(&prepared, (1,))
+            .await
+            .unwrap()
+            .rows
+            .unwrap();
+        assert_eq!(rows.rows_count(), 2);
+
+        session.use_keyspace("ks", false).await.unwrap();
+        session
+            .query("INSERT INTO ks.slow (a) VALUES (1)", &[])
+            .await
+            .unwrap();
+
+        let mut batch: Batch = Default::default();
+        batch.append_statement("INSERT INTO ks.t (a) VALUES (?)");
+        batch.append_statement(prepared);
+        session.batch(&batch, ((1,), (2,))).await.unwrap();
+
+        assert_eq!(
+            node.received_statements(),
+            vec![
+                "SELECT name FROM ks.users",
+                "SELECT   name FROM ks.users WHERE id = 1",
+                "SELECT name FROM ks.users WHERE id = ?",
+                "USE ks",
+                "INSERT INTO ks.slow (a) VALUES (1)",
+                "INSERT INTO ks.t (a) VALUES (?)",
+                "SELECT name FROM ks.users WHERE id = ?",
+            ]
+        );
+
+        node.finish().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reprepare_forgotten_statements() {
+        let node = MockNode::new().run().await.unwrap();
+        let session: Session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .build()
+            .await
+            .unwrap();
+
+        let prepared = session
+            .prepare("INSERT INTO ks.t (a) VALUES (?)")
+            .await
+            .unwrap();
+        session.execute(&prepared, (1,)).await.unwrap();
+
+        node.forget_prepared_statements();
+        session.execute(&prepared, (2,)).await.unwrap();
+
+        // The node reports one unknown statement at a time, each of them is reprepared
+        let other = session
+            .prepare("INSERT INTO ks.t (b) VALUES (?)")
+            .await
+            .unwrap();
+        node.forget_prepared_statements();
+        let mut batch: Batch = Default::default();
+        batch.append_statement(prepared.clone());
+        batch.append_statement(other);
+        batch.append_statement(prepared);
+        session.batch(&batch, ((3,), (4,), (5,))).await.unwrap();
+
+        // Executions rejected as unprepared aren't received
+        let insert = "INSERT INTO ks.t (a) VALUES (?)";
+        let other_insert = "INSERT INTO ks.t (b) VALUES (?)";
+        assert_eq!(
+            node.received_statements(),
+            vec![insert, insert, insert, other_insert, insert]
+        );
+
+        node.finish().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn batch_of_repeated_statement() {
+        let node = MockNode::new().run().await.unwrap();
+        let session: Session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .build()
+            .await
+            .unwrap();
+
+        let prepared = session
+            .prepare("INSERT INTO ks.t (a, b) VALUES (?, ?)")
+            .await
+            .unwrap();
+        let rows: Vec<(i32, String)> = (0..100).map(|i| (i, i.to_string())).collect();
+
+        let mut batch: Batch = Default::default();
+        batch.append_statement_n(prepared, rows.len());
+
+        node.forget_prepared_statements();
+        session
+            .batch(&batch, BatchValuesFromIter::new(rows.iter()))
+            .await
+            .unwrap();
+
+        let insert = "INSERT INTO ks.t (a, b) VALUES (?, ?)";
+        assert_eq!(node.received_statements(), vec![insert; 100]);
+
+        node.finish().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn query_result_column_specs() {
+        let node = MockNode::new()
+            .default_response(MockResponse::rows(
+                &[("id", ColumnType::Int), ("name", ColumnType::Text)],
+                vec![vec![
+                    Some(CqlValue::Int(1)),
+                    Some(CqlValue::Text("ann".to_string())),
+                ]],
+            ))
+            .run()
+            .await
+            .unwrap();
+        let session: Session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .build()
+            .await
+            .unwrap();
+
+        let result = session
+            .query("SELECT id, name FROM ks.users", &[])
+            .await
+            .unwrap();
+        let names: Vec<&str> = result.col_specs.iter().map(|spec| spec.name()).collect();
+        assert_eq!(names, vec!["id", "name"]);
+        let (index, spec) = result.get_column_spec("name").unwrap();
+        assert_eq!(index, 1);
+        assert!(matches!(spec.typ(), ColumnType::Text));
+        assert!(result.get_column_spec("age").is_none());
+        assert_eq!(
+            result.single_row_typed::<(i32, String)>().unwrap(),
+            (1, "ann".to_string())
+        );
+
+        node.finish().await.unwrap();
+    }
+}
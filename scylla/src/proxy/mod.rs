@@ -0,0 +1,642 @@
+//! In-process proxy for testing the driver's behaviour when nodes or the network misbehave
+//!
+//! The proxy sits between the driver and a node, real or simulated, and passes CQL frames
+//! in both directions. Rules allow tests to intercept, delay, drop or rewrite chosen frames,
+//! respond with errors instead of the node or break connections.
+//! It requires the tokio runtime and is enabled with the `proxy` feature.
+//!
+//! The proxy removes shard-aware ports from `SUPPORTED` responses,
+//! otherwise the driver would connect to these ports directly.
+//! Other nodes of a cluster are contacted directly, unless they have their own proxies
+//! and the driver is configured to use them (e.g. with [`node_port_override`](crate::SessionBuilder::node_port_override)).
+//!
+//! A proxy created with [`record`](Proxy::record) records the traffic, which can be served back
+//! without a node by [`ReplayNode`].
+//!
+//! [`MockNode`] simulates a node instead, answering statements with responses chosen by the test,
+//! so code using a [`Session`](crate::Session) can be tested without a running cluster.
+//!
+//! # Example
+//! ```rust,ignore
+//! use scylla::proxy::{Condition, Proxy, RequestReaction, RequestRule};
+//! use scylla::transport::errors::DbError;
+//!
+//! let proxy = Proxy::new(node_addr)
+//!     .request_rules(vec![RequestRule::new(
+//!         Condition::body_contains("SELECT").and(Condition::TrueForLimitedTimes(1)),
+//!         RequestReaction::RespondWithError(DbError::Overloaded),
+//!     )])
+//!     .run()
+//!     .await?;
+//!
+//! let session = SessionBuilder::new()
+//!     .known_node_addr(proxy.addr())
+//!     .build()
+//!     .await?;
+//!
+//! // ... test the driver ...
+//!
+//! proxy.finish().await?;
+//! ```
+
+mod frame;
+mod mock;
+mod recording;
+mod rules;
+
+pub use frame::{RequestFrame, ResponseFrame};
+pub use mock::{MockNode, MockResponse, MockRule, RunningMockNode};
+pub use recording::{RecordedExchange, Recording, ReplayNode, RunningReplayNode};
+pub use rules::{
+    Condition, RequestReaction, RequestRewriter, RequestRule, ResponseReaction, ResponseRewriter,
+    ResponseRule,
+};
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+
+use crate::frame::request::RequestOpcode;
+use bytes::Bytes;
+use rules::EvaluationContext;
+
+#[derive(Error, Debug)]
+pub enum ProxyError {
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("Invalid frame: {0}")]
+    InvalidFrame(&'static str),
+    #[error("Unknown request opcode: {0:#04x}")]
+    UnknownRequestOpcode(u8),
+    #[error("Unknown response opcode: {0:#04x}")]
+    UnknownResponseOpcode(u8),
+}
+
+/// Configuration of a proxy to a single node, started with [`run`](Proxy::run)
+pub struct Proxy {
+    node_addr: SocketAddr,
+    listen_addr: SocketAddr,
+    request_rules: Vec<RequestRule>,
+    response_rules: Vec<ResponseRule>,
+    record: bool,
+}
+
+impl Proxy {
+    /// Creates a proxy to the node at `node_addr`, without any rules it passes all frames unchanged
+    pub fn new(node_addr: SocketAddr) -> Self {
+        Proxy {
+            node_addr,
+            listen_addr: SocketAddr::from(([127, 0, 0, 1], 0)),
+            request_rules: Vec::new(),
+            response_rules: Vec::new(),
+            record: false,
+        }
+    }
+
+    /// Address on which the proxy accepts connections, by default a random port on localhost
+    pub fn listen_addr(mut self, listen_addr: SocketAddr) -> Self {
+        self.listen_addr = listen_addr;
+        self
+    }
+
+    /// Rules applied to requests sent by the driver
+    pub fn request_rules(mut self, rules: Vec<RequestRule>) -> Self {
+        self.request_rules = rules;
+        self
+    }
+
+    /// Rules applied to responses sent by the node
+    pub fn response_rules(mut self, rules: Vec<ResponseRule>) -> Self {
+        self.response_rules = rules;
+        self
+    }
+
+    /// Records requests sent by the driver together with responses sent by the node,
+    /// see [`RunningProxy::recording`]
+    pub fn record(mut self) -> Self {
+        self.record = true;
+        self
+    }
+
+    /// Starts accepting connections, each of them is forwarded to the node
+    pub async fn run(self) -> Result<RunningProxy, ProxyError> {
+        let listener = TcpListener::bind(self.listen_addr).await?;
+        let addr = listener.local_addr()?;
+
+        let shared = Arc::new(SharedRules {
+            request_rules: Mutex::new(self.request_rules),
+            response_rules: Mutex::new(self.response_rules),
+        });
+        let recording = match self.record {
+            true => Some(Arc::new(Mutex::new(Recording::default()))),
+            false => None,
+        };
+        let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+        let (error_sender, error_receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn(accept_connections(
+            listener,
+            self.node_addr,
+            shared.clone(),
+            recording.clone(),
+            shutdown_receiver,
+            error_sender,
+        ));
+
+        Ok(RunningProxy {
+            addr,
+            shared,
+            recording,
+            shutdown_sender,
+            error_receiver,
+        })
+    }
+}
+
+/// Handle to a running proxy, which stops when it's dropped
+pub struct RunningProxy {
+    addr: SocketAddr,
+    shared: Arc<SharedRules>,
+    recording: Option<Arc<Mutex<Recording>>>,
+    shutdown_sender: watch::Sender<bool>,
+    error_receiver: mpsc::UnboundedReceiver<ProxyError>,
+}
+
+impl RunningProxy {
+    /// Address on which the proxy accepts connections
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Replaces rules applied to requests, also on already open connections
+    pub fn set_request_rules(&self, rules: Vec<RequestRule>) {
+        *self.shared.request_rules.lock().unwrap() = rules;
+    }
+
+    /// Replaces rules applied to responses, also on already open connections
+    pub fn set_response_rules(&self, rules: Vec<ResponseRule>) {
+        *self.shared.response_rules.lock().unwrap() = rules;
+    }
+
+    /// Removes all rules, after that all frames are passed unchanged
+    pub fn turn_off_rules(&self) {
+        self.set_request_rules(Vec::new());
+        self.set_response_rules(Vec::new());
+    }
+
+    /// Requests and responses exchanged so far, empty unless the proxy was created with [`record`](Proxy::record).
+    /// Requests are recorded as sent by the driver and responses as sent by the node, before applying rules.
+    /// Requests to which the node didn't respond and events aren't recorded
+    pub fn recording(&self) -> Recording {
+        match &self.recording {
+            Some(recording) => recording.lock().unwrap().clone(),
+            None => Recording::default(),
+        }
+    }
+
+    /// Stops the proxy and closes all connections.
+    /// Returns the first error which occurred in the proxy, e.g. an invalid frame.
+    /// Connections closed or reset by either side aren't errors
+    pub async fn finish(mut self) -> Result<(), ProxyError> {
+        let _ = self.shutdown_sender.send(true);
+
+        let mut result = Ok(());
+        while let Some(error) = self.error_receiver.recv().await {
+            if result.is_ok() {
+                result = Err(error);
+            }
+        }
+        result
+    }
+}
+
+struct SharedRules {
+    request_rules: Mutex<Vec<RequestRule>>,
+    response_rules: Mutex<Vec<ResponseRule>>,
+}
+
+impl SharedRules {
+    fn request_reaction(&self, connection_seq_no: usize, frame: &RequestFrame) -> RequestReaction {
+        let ctx = EvaluationContext {
+            connection_seq_no,
+            request_opcode: Some(frame.opcode),
+            response_opcode: None,
+            body: &frame.body,
+        };
+
+        let mut rules = self.request_rules.lock().unwrap();
+        for rule in rules.iter_mut() {
+            if rule.condition.eval(&ctx) {
+                if let Some(feedback) = &rule.feedback {
+                    let _ = feedback.send(frame.clone());
+                }
+                return rule.reaction.clone();
+            }
+        }
+        RequestReaction::Forward
+    }
+
+    fn response_reaction(
+        &self,
+        connection_seq_no: usize,
+        request_opcode: Option<RequestOpcode>,
+        frame: &ResponseFrame,
+    ) -> ResponseReaction {
+        let ctx = EvaluationContext {
+            connection_seq_no,
+            request_opcode,
+            response_opcode: Some(frame.opcode),
+            body: &frame.body,
+        };
+
+        let mut rules = self.response_rules.lock().unwrap();
+        for rule in rules.iter_mut() {
+            if rule.condition.eval(&ctx) {
+                if let Some(feedback) = &rule.feedback {
+                    let _ = feedback.send(frame.clone());
+                }
+                return rule.reaction.clone();
+            }
+        }
+        ResponseReaction::Forward
+    }
+}
+
+async fn accept_connections(
+    listener: TcpListener,
+    node_addr: SocketAddr,
+    shared: Arc<SharedRules>,
+    recording: Option<Arc<Mutex<Recording>>>,
+    mut shutdown: watch::Receiver<bool>,
+    errors: mpsc::UnboundedSender<ProxyError>,
+) {
+    let mut connection_seq_no = 0;
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let driver_stream = match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(err) => {
+                        let _ = errors.send(err.into());
+                        return;
+                    }
+                };
+
+                let connection = ProxiedConnection {
+                    seq_no: connection_seq_no,
+                    shared: shared.clone(),
+                    recording: recording.clone(),
+                    in_flight: Mutex::new(HashMap::new()),
+                };
+                let shutdown = shutdown.clone();
+                let errors = errors.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = connection.run(driver_stream, node_addr, shutdown).await {
+                        if !is_connection_closed(&err) {
+                            let _ = errors.send(err);
+                        }
+                    }
+                });
+                connection_seq_no += 1;
+            }
+            _ = shutdown.changed() => return,
+        }
+    }
+}
+
+fn is_connection_closed(err: &ProxyError) -> bool {
+    match err {
+        ProxyError::IoError(err) => matches!(
+            err.kind(),
+            io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::UnexpectedEof
+        ),
+        _ => false,
+    }
+}
+
+struct ProxiedConnection {
+    seq_no: usize,
+    shared: Arc<SharedRules>,
+    recording: Option<Arc<Mutex<Recording>>>,
+    // Requests waiting for a response, by stream id
+    in_flight: Mutex<HashMap<i16, RequestFrame>>,
+}
+
+impl ProxiedConnection {
+    // Forwards frames until either side closes the connection, a rule drops it or the proxy is stopped
+    async fn run(
+        &self,
+        driver_stream: TcpStream,
+        node_addr: SocketAddr,
+        mut shutdown: watch::Receiver<bool>,
+    ) -> Result<(), ProxyError> {
+        let node_stream = TcpStream::connect(node_addr).await?;
+        let (driver_read, driver_write) = driver_stream.into_split();
+        let (node_read, node_write) = node_stream.into_split();
+
+        let (to_driver, to_driver_receiver) = mpsc::unbounded_channel();
+        let (to_node, to_node_receiver) = mpsc::unbounded_channel();
+
+        tokio::select! {
+            result = self.forward_requests(driver_read, to_node, to_driver.clone()) => result,
+            result = self.forward_responses(node_read, to_driver) => result,
+            result = write_frames(driver_write, to_driver_receiver) => result,
+            result = write_frames(node_write, to_node_receiver) => result,
+            _ = shutdown.changed() => Ok(()),
+        }
+    }
+
+    async fn forward_requests(
+        &self,
+        mut driver_read: tokio::net::tcp::OwnedReadHalf,
+        to_node: mpsc::UnboundedSender<Bytes>,
+        to_driver: mpsc::UnboundedSender<Bytes>,
+    ) -> Result<(), ProxyError> {
+        while let Some(request) = frame::read_request_frame(&mut driver_read).await? {
+            self.in_flight
+                .lock()
+                .unwrap()
+                .insert(request.params.stream, request.clone());
+
+            match self.shared.request_reaction(self.seq_no, &request) {
+                RequestReaction::Forward => send(&to_node, request.to_bytes()),
+                RequestReaction::Delay(delay) => send_delayed(&to_node, request.to_bytes(), delay),
+                RequestReaction::Drop => {}
+                RequestReaction::DropConnection => return Ok(()),
+                RequestReaction::RespondWithError(error) => {
+                    self.in_flight
+                        .lock()
+                        .unwrap()
+                        .remove(&request.params.stream);
+                    let response =
+                        ResponseFrame::forged_error(&request, error, "Error forged by proxy");
+                    send(&to_driver, response.to_bytes());
+                }
+                RequestReaction::Rewrite(rewrite) => send(&to_node, rewrite(request).to_bytes()),
+            }
+        }
+        Ok(())
+    }
+
+    async fn forward_responses(
+        &self,
+        mut node_read: tokio::net::tcp::OwnedReadHalf,
+        to_driver: mpsc::UnboundedSender<Bytes>,
+    ) -> Result<(), ProxyError> {
+        while let Some(mut response) = frame::read_response_frame(&mut node_read).await? {
+            response.strip_shard_aware_ports()?;
+
+            // Events are sent on stream -1 and don't respond to any request
+            let request = self
+                .in_flight
+                .lock()
+                .unwrap()
+                .remove(&response.params.stream);
+            let request_opcode = request.as_ref().map(|request| request.opcode);
+
+            if let (Some(recording), Some(request)) = (&self.recording, request) {
+                recording.lock().unwrap().exchanges.push(RecordedExchange {
+                    request,
+                    response: response.clone(),
+                });
+            }
+
+            match self
+                .shared
+                .response_reaction(self.seq_no, request_opcode, &response)
+            {
+                ResponseReaction::Forward => send(&to_driver, response.to_bytes()),
+                ResponseReaction::Delay(delay) => {
+                    send_delayed(&to_driver, response.to_bytes(), delay)
+                }
+                ResponseReaction::Drop => {}
+                ResponseReaction::DropConnection => return Ok(()),
+                ResponseReaction::Rewrite(rewrite) => {
+                    send(&to_driver, rewrite(response).to_bytes())
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Sending fails only when the connection is being closed, then the frame isn't needed anymore
+fn send(sender: &mpsc::UnboundedSender<Bytes>, frame: Bytes) {
+    let _ = sender.send(frame);
+}
+
+fn send_delayed(sender: &mpsc::UnboundedSender<Bytes>, frame: Bytes, delay: std::time::Duration) {
+    let sender = sender.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        send(&sender, frame);
+    });
+}
+
+async fn write_frames(
+    mut writer: tokio::net::tcp::OwnedWriteHalf,
+    mut frames: mpsc::UnboundedReceiver<Bytes>,
+) -> Result<(), ProxyError> {
+    while let Some(frame) = frames.recv().await {
+        writer.write_all(&frame).await?;
+    }
+    // All senders are held by the other futures, so this doesn't happen before they finish
+    std::future::pending().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Condition, Proxy, RequestReaction, RequestRule, ResponseReaction, ResponseRule};
+    use crate::frame::request::{self, RequestOpcode};
+    use crate::frame::response::{error::Error, ResponseOpcode};
+    use crate::frame::{read_response_frame, types, FrameParams, SerializedRequest};
+    use crate::transport::errors::DbError;
+    use crate::transport::ProtocolVersion;
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc;
+
+    // Simulated node, which responds to each request with SUPPORTED containing the request's opcode
+    async fn run_fake_node() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut header = [0u8; 9];
+                    while stream.read_exact(&mut header).await.is_ok() {
+                        let length =
+                            u32::from_be_bytes([header[5], header[6], header[7], header[8]]);
+                        let mut body = vec![0; length as usize];
+                        stream.read_exact(&mut body).await.unwrap();
+
+                        let mut options = HashMap::new();
+                        options.insert("OPCODE".to_string(), vec![header[4].to_string()]);
+                        options.insert(
+                            "SCYLLA_SHARD_AWARE_PORT".to_string(),
+                            vec!["19042".to_string()],
+                        );
+                        let mut body = Vec::new();
+                        types::write_string_multimap(&options, &mut body).unwrap();
+
+                        let mut response = vec![0x84, 0, header[2], header[3], 0x06];
+                        response.extend_from_slice(&(body.len() as u32).to_be_bytes());
+                        response.extend_from_slice(&body);
+                        if stream.write_all(&response).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    async fn send_options(stream: &mut TcpStream, stream_id: i16) {
+        let mut request =
+            SerializedRequest::make(&request::Options {}, ProtocolVersion::V4, None, 0, false)
+                .unwrap();
+        request.set_stream(stream_id);
+        stream.write_all(request.get_data()).await.unwrap();
+    }
+
+    async fn send_startup(stream: &mut TcpStream, stream_id: i16) {
+        let mut request = SerializedRequest::make(
+            &request::Startup {
+                options: HashMap::new(),
+            },
+            ProtocolVersion::V4,
+            None,
+            0,
+            false,
+        )
+        .unwrap();
+        request.set_stream(stream_id);
+        stream.write_all(request.get_data()).await.unwrap();
+    }
+
+    async fn read_supported(stream: &mut TcpStream) -> (FrameParams, HashMap<String, Vec<String>>) {
+        let (params, opcode, body) = read_response_frame(stream).await.unwrap();
+        assert_eq!(opcode, ResponseOpcode::Supported);
+        (params, types::read_string_multimap(&mut &body[..]).unwrap())
+    }
+
+    #[tokio::test]
+    async fn forwards_frames_without_shard_aware_port() {
+        let node_addr = run_fake_node().await;
+        let proxy = Proxy::new(node_addr).run().await.unwrap();
+
+        let mut driver = TcpStream::connect(proxy.addr()).await.unwrap();
+        send_options(&mut driver, 5).await;
+        let (params, options) = read_supported(&mut driver).await;
+
+        assert_eq!(params.stream, 5);
+        assert_eq!(options.get("OPCODE"), Some(&vec!["5".to_string()]));
+        assert!(!options.contains_key("SCYLLA_SHARD_AWARE_PORT"));
+
+        drop(driver);
+        proxy.finish().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn request_rules() {
+        let node_addr = run_fake_node().await;
+        let (feedback_sender, mut feedback_receiver) = mpsc::unbounded_channel();
+        let proxy = Proxy::new(node_addr)
+            .request_rules(vec![
+                RequestRule::new(
+                    Condition::RequestOpcode(RequestOpcode::Options)
+                        .and(Condition::TrueForLimitedTimes(1)),
+                    RequestReaction::RespondWithError(DbError::Overloaded),
+                )
+                .with_feedback(feedback_sender),
+                RequestRule::new(
+                    Condition::RequestOpcode(RequestOpcode::Startup),
+                    RequestReaction::Drop,
+                ),
+            ])
+            .run()
+            .await
+            .unwrap();
+
+        let mut driver = TcpStream::connect(proxy.addr()).await.unwrap();
+
+        // The first OPTIONS gets an error from the proxy
+        send_options(&mut driver, 1).await;
+        let (params, opcode, body) = read_response_frame(&mut driver).await.unwrap();
+        assert_eq!((params.stream, opcode), (1, ResponseOpcode::Error));
+        let error = Error::deserialize(&mut &body[..]).unwrap();
+        assert_eq!(error.error, DbError::Overloaded);
+        assert_eq!(
+            feedback_receiver.recv().await.unwrap().opcode,
+            RequestOpcode::Options
+        );
+
+        // STARTUP is dropped, so the next response is for the second OPTIONS
+        send_startup(&mut driver, 2).await;
+        send_options(&mut driver, 3).await;
+        let (params, _) = read_supported(&mut driver).await;
+        assert_eq!(params.stream, 3);
+
+        // After turning off the rules STARTUP is forwarded
+        proxy.turn_off_rules();
+        send_startup(&mut driver, 4).await;
+        let (params, options) = read_supported(&mut driver).await;
+        assert_eq!(params.stream, 4);
+        assert_eq!(options.get("OPCODE"), Some(&vec!["1".to_string()]));
+
+        drop(driver);
+        proxy.finish().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn response_rules() {
+        let node_addr = run_fake_node().await;
+        let proxy = Proxy::new(node_addr)
+            .response_rules(vec![
+                ResponseRule::new(
+                    Condition::RequestOpcode(RequestOpcode::Startup),
+                    ResponseReaction::DropConnection,
+                ),
+                ResponseRule::new(
+                    Condition::ResponseOpcode(ResponseOpcode::Supported)
+                        .and(Condition::ConnectionSeqNo(0)),
+                    ResponseReaction::Delay(Duration::from_millis(100)),
+                ),
+            ])
+            .run()
+            .await
+            .unwrap();
+
+        // Response to the first OPTIONS is delayed, so the second one comes first
+        let mut driver = TcpStream::connect(proxy.addr()).await.unwrap();
+        send_options(&mut driver, 1).await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        proxy.set_response_rules(vec![ResponseRule::new(
+            Condition::RequestOpcode(RequestOpcode::Startup),
+            ResponseReaction::DropConnection,
+        )]);
+        send_options(&mut driver, 2).await;
+        assert_eq!(read_supported(&mut driver).await.0.stream, 2);
+        assert_eq!(read_supported(&mut driver).await.0.stream, 1);
+
+        // Response to STARTUP closes the connection
+        send_startup(&mut driver, 3).await;
+        let mut buf = [0u8; 1];
+        assert_eq!(driver.read(&mut buf).await.unwrap(), 0);
+
+        proxy.finish().await.unwrap();
+    }
+}
@@ -0,0 +1,467 @@
+//! Recording CQL traffic with the proxy and serving it back without a node
+
+use bytes::{Bytes, BytesMut};
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryInto;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, watch};
+
+use super::frame::{parse_request_frame, parse_response_frame, RequestFrame, ResponseFrame};
+use super::mock::{accept_connections, RequestHandler};
+use super::ProxyError;
+use crate::frame::frame_errors::ParseError;
+use crate::frame::request::RequestOpcode;
+use crate::frame::types;
+use crate::frame::FLAG_COMPRESSION;
+use crate::transport::errors::DbError;
+
+// The same in QUERY, EXECUTE and BATCH requests
+const FLAG_WITH_DEFAULT_TIMESTAMP: u8 = 0x20;
+
+/// Request sent by the driver together with the node's response to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedExchange {
+    pub request: RequestFrame,
+    pub response: ResponseFrame,
+}
+
+impl RecordedExchange {
+    /// Text of the statement sent in a `QUERY` or `PREPARE` request, if it isn't compressed
+    pub fn statement(&self) -> Option<&str> {
+        match self.request.opcode {
+            RequestOpcode::Query | RequestOpcode::Prepare
+                if self.request.params.flags & FLAG_COMPRESSION == 0 =>
+            {
+                types::read_long_string(&mut &self.request.body[..]).ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Requests and responses captured by a proxy created with [`Proxy::record`](super::Proxy::record),
+/// which can be served back by [`ReplayNode`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recording {
+    /// Exchanges in the order of responses
+    pub exchanges: Vec<RecordedExchange>,
+}
+
+impl Recording {
+    /// Serializes the recording as frames, each request followed by the response to it
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for exchange in &self.exchanges {
+            data.extend_from_slice(&exchange.request.to_bytes());
+            data.extend_from_slice(&exchange.response.to_bytes());
+        }
+        data
+    }
+
+    /// Deserializes a recording serialized with [`to_bytes`](Recording::to_bytes)
+    pub fn from_bytes(mut data: &[u8]) -> Result<Self, ProxyError> {
+        let mut exchanges = Vec::new();
+        while !data.is_empty() {
+            let request = parse_request_frame(&mut data)?;
+            let response = parse_response_frame(&mut data)?;
+            exchanges.push(RecordedExchange { request, response });
+        }
+        Ok(Recording { exchanges })
+    }
+
+    /// Saves the recording to a file, e.g. to use it in regression tests
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ProxyError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Loads a recording saved with [`save`](Recording::save)
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProxyError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+/// Simulated node serving back responses from a [`Recording`], started with [`run`](ReplayNode::run)
+///
+/// Each request gets the recorded response to a request with the same opcode and body,
+/// ignoring client-side timestamps.
+/// If the same request was recorded multiple times, the responses are returned in the recorded
+/// order and the last one is repeated afterwards. Requests which weren't recorded get
+/// a `ServerError` and are available in [`RunningReplayNode::unmatched_requests`].
+pub struct ReplayNode {
+    recording: Recording,
+    listen_addr: SocketAddr,
+}
+
+impl ReplayNode {
+    pub fn new(recording: Recording) -> Self {
+        ReplayNode {
+            recording,
+            listen_addr: SocketAddr::from(([127, 0, 0, 1], 0)),
+        }
+    }
+
+    /// Address on which the node accepts connections, by default a random port on localhost
+    pub fn listen_addr(mut self, listen_addr: SocketAddr) -> Self {
+        self.listen_addr = listen_addr;
+        self
+    }
+
+    /// Starts accepting connections
+    pub async fn run(self) -> Result<RunningReplayNode, ProxyError> {
+        let listener = TcpListener::bind(self.listen_addr).await?;
+        let addr = listener.local_addr()?;
+
+        let mut responses: HashMap<(RequestOpcode, Bytes), VecDeque<ResponseFrame>> =
+            HashMap::new();
+        for exchange in self.recording.exchanges {
+            responses
+                .entry(matching_key(&exchange.request))
+                .or_default()
+                .push_back(exchange.response);
+        }
+
+        let state = Arc::new(Mutex::new(ReplayState {
+            responses,
+            unmatched_requests: Vec::new(),
+        }));
+        let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+        let (error_sender, error_receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn(accept_connections(
+            listener,
+            state.clone(),
+            shutdown_receiver,
+            error_sender,
+        ));
+
+        Ok(RunningReplayNode {
+            addr,
+            state,
+            shutdown_sender,
+            error_receiver,
+        })
+    }
+}
+
+/// Handle to a running replay node, which stops when it's dropped
+pub struct RunningReplayNode {
+    addr: SocketAddr,
+    state: Arc<Mutex<ReplayState>>,
+    shutdown_sender: watch::Sender<bool>,
+    error_receiver: mpsc::UnboundedReceiver<ProxyError>,
+}
+
+impl RunningReplayNode {
+    /// Address on which the node accepts connections
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Requests which didn't match any recorded request, in the order of arrival
+    pub fn unmatched_requests(&self) -> Vec<RequestFrame> {
+        self.state.lock().unwrap().unmatched_requests.clone()
+    }
+
+    /// Stops the node and closes all connections.
+    /// Returns the first error which occurred, e.g. an invalid frame
+    pub async fn finish(mut self) -> Result<(), ProxyError> {
+        let _ = self.shutdown_sender.send(true);
+
+        let mut result = Ok(());
+        while let Some(error) = self.error_receiver.recv().await {
+            if result.is_ok() {
+                result = Err(error);
+            }
+        }
+        result
+    }
+}
+
+struct ReplayState {
+    // Recorded responses by request opcode and body without the timestamp
+    responses: HashMap<(RequestOpcode, Bytes), VecDeque<ResponseFrame>>,
+    unmatched_requests: Vec<RequestFrame>,
+}
+
+impl RequestHandler for Mutex<ReplayState> {
+    fn handle(
+        &self,
+        request: &RequestFrame,
+    ) -> Result<(ResponseFrame, Option<Duration>), ProxyError> {
+        let mut state = self.lock().unwrap();
+
+        let recorded = state
+            .responses
+            .get_mut(&matching_key(request))
+            .and_then(|responses| {
+                if responses.len() > 1 {
+                    responses.pop_front()
+                } else {
+                    responses.front().cloned()
+                }
+            });
+
+        let response = match recorded {
+            Some(mut response) => {
+                response.params.stream = request.params.stream;
+                response
+            }
+            None => {
+                state.unmatched_requests.push(request.clone());
+                ResponseFrame::forged_error(
+                    request,
+                    DbError::ServerError,
+                    "No recorded response to the request",
+                )
+            }
+        };
+        Ok((response, None))
+    }
+}
+
+// Client-side timestamps differ between recorded and replayed requests,
+// so they are removed from request bodies before matching
+fn matching_key(request: &RequestFrame) -> (RequestOpcode, Bytes) {
+    let flags_position = match request.opcode {
+        RequestOpcode::Query | RequestOpcode::Execute | RequestOpcode::Batch
+            if request.params.flags & FLAG_COMPRESSION == 0 =>
+        {
+            flags_position(request.opcode, &request.body).ok()
+        }
+        _ => None,
+    };
+
+    let body = match flags_position {
+        // The timestamp is the last field of the body
+        Some(position)
+            if request.body[position] & FLAG_WITH_DEFAULT_TIMESTAMP != 0
+                && request.body.len() >= position + 9 =>
+        {
+            let mut body = BytesMut::from(&request.body[..request.body.len() - 8]);
+            body[position] &= !FLAG_WITH_DEFAULT_TIMESTAMP;
+            body.freeze()
+        }
+        _ => request.body.clone(),
+    };
+    (request.opcode, body)
+}
+
+// Position of the flags byte in the body of a QUERY, EXECUTE or BATCH request
+fn flags_position(opcode: RequestOpcode, body: &[u8]) -> Result<usize, ParseError> {
+    let mut buf = body;
+    match opcode {
+        RequestOpcode::Query => {
+            types::read_long_string(&mut buf)?;
+        }
+        RequestOpcode::Execute => {
+            let id_len = types::read_short(&mut buf)?;
+            skip(&mut buf, id_len)?;
+        }
+        _ => {
+            // Batch type
+            skip(&mut buf, 1)?;
+            let statements_count = types::read_short(&mut buf)?;
+            for _ in 0..statements_count {
+                let kind = buf.first().copied();
+                skip(&mut buf, 1)?;
+                match kind {
+                    Some(0) => {
+                        types::read_long_string(&mut buf)?;
+                    }
+                    _ => {
+                        let id_len = types::read_short(&mut buf)?;
+                        skip(&mut buf, id_len)?;
+                    }
+                }
+                let values_count = types::read_short(&mut buf)?;
+                for _ in 0..values_count {
+                    types::read_bytes_opt(&mut buf)?;
+                }
+            }
+        }
+    }
+    // Consistency goes before the flags
+    skip(&mut buf, 2)?;
+
+    match body.len() - buf.len() {
+        position if position < body.len() => Ok(position),
+        _ => Err(ParseError::BadData("Request body too short".to_string())),
+    }
+}
+
+fn skip(buf: &mut &[u8], len: impl TryInto<usize>) -> Result<(), ParseError> {
+    let len: usize = len
+        .try_into()
+        .map_err(|_| ParseError::BadData("Negative length".to_string()))?;
+    if buf.len() < len {
+        return Err(ParseError::BadData("Request body too short".to_string()));
+    }
+    *buf = &buf[len..];
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{matching_key, RecordedExchange, Recording, ReplayNode};
+    use crate::frame::request::{batch, query, Request, RequestOpcode};
+    use crate::frame::response::result::{ColumnType, CqlValue};
+    use crate::frame::types::Consistency;
+    use crate::frame::value::SerializedValues;
+    use crate::frame::FrameParams;
+    use crate::proxy::{MockNode, MockResponse, MockRule, Proxy, RequestFrame, ResponseFrame};
+    use crate::transport::errors::{DbError, QueryError};
+    use crate::{IntoTypedRows, Session, SessionBuilder};
+
+    #[test]
+    fn recording_serialization() {
+        let request = RequestFrame {
+            params: FrameParams {
+                version: 0x04,
+                flags: 0,
+                stream: 3,
+            },
+            opcode: RequestOpcode::Query,
+            body: b"\x00\x00\x00\x08SELECT 1\x00\x01\x00".to_vec().into(),
+        };
+        let response = ResponseFrame::forged_error(&request, DbError::Overloaded, "busy");
+        let recording = Recording {
+            exchanges: vec![RecordedExchange { request, response }; 2],
+        };
+
+        assert_eq!(recording.exchanges[0].statement(), Some("SELECT 1"));
+        assert_eq!(
+            Recording::from_bytes(&recording.to_bytes()).unwrap(),
+            recording
+        );
+        assert!(Recording::from_bytes(&recording.to_bytes()[1..]).is_err());
+    }
+
+    fn request(opcode: RequestOpcode, request: &impl Request) -> RequestFrame {
+        let mut body: Vec<u8> = Vec::new();
+        request.serialize(&mut body).unwrap();
+        RequestFrame {
+            params: FrameParams {
+                version: 0x04,
+                flags: 0,
+                stream: 0,
+            },
+            opcode,
+            body: body.into(),
+        }
+    }
+
+    #[test]
+    fn timestamps_are_ignored_when_matching() {
+        let mut values = SerializedValues::new();
+        values.add_value(&1_i32).unwrap();
+
+        let query = |timestamp: Option<i64>| {
+            request(
+                RequestOpcode::Query,
+                &query::Query {
+                    contents: "INSERT INTO ks.t (a) VALUES (?)".to_string(),
+                    parameters: query::QueryParameters {
+                        consistency: Consistency::One,
+                        serial_consistency: Some(Consistency::Serial),
+                        timestamp,
+                        values: &values,
+                        ..Default::default()
+                    },
+                },
+            )
+        };
+        assert_eq!(matching_key(&query(Some(1))), matching_key(&query(Some(2))));
+        assert_eq!(matching_key(&query(Some(1))), matching_key(&query(None)));
+
+        let batch = |timestamp: Option<i64>| {
+            request(
+                RequestOpcode::Batch,
+                &batch::Batch {
+                    statements: vec![
+                        batch::BatchStatement::Query { text: "INSERT" },
+                        batch::BatchStatement::Prepared {
+                            id: &b"id"[..].into(),
+                        },
+                    ]
+                    .into_iter(),
+                    statements_count: 2,
+                    batch_type: batch::BatchType::Logged,
+                    consistency: Consistency::One,
+                    serial_consistency: None,
+                    timestamp,
+                    values: ((), &values),
+                },
+            )
+        };
+        assert_eq!(matching_key(&batch(Some(1))), matching_key(&batch(Some(2))));
+        assert_eq!(matching_key(&batch(Some(1))), matching_key(&batch(None)));
+        assert_ne!(matching_key(&batch(Some(1))), matching_key(&query(Some(1))));
+    }
+
+    async fn query_names(session: &Session) -> Result<Vec<String>, QueryError> {
+        let prepared = session
+            .prepare("SELECT name FROM ks.users WHERE id = ?")
+            .await?;
+        let rows = session.execute(&prepared, (1,)).await?.rows.unwrap();
+        Ok(rows
+            .into_typed::<(String,)>()
+            .map(|row| row.unwrap().0)
+            .collect())
+    }
+
+    #[tokio::test]
+    async fn record_and_replay() {
+        let node = MockNode::new()
+            .rule(MockRule::new(
+                "SELECT name FROM ks.users",
+                MockResponse::rows(
+                    &[("name", ColumnType::Text)],
+                    vec![vec![Some(CqlValue::Text("ann".to_string()))]],
+                ),
+            ))
+            .run()
+            .await
+            .unwrap();
+        let proxy = Proxy::new(node.addr()).record().run().await.unwrap();
+
+        let session: Session = SessionBuilder::new()
+            .known_node_addr(proxy.addr())
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(query_names(&session).await.unwrap(), vec!["ann"]);
+        drop(session);
+
+        let recording = proxy.recording();
+        assert!(recording
+            .exchanges
+            .iter()
+            .any(|e| e.statement() == Some("SELECT name FROM ks.users WHERE id = ?")));
+        proxy.finish().await.unwrap();
+        node.finish().await.unwrap();
+
+        let replay = ReplayNode::new(recording).run().await.unwrap();
+        let session: Session = SessionBuilder::new()
+            .known_node_addr(replay.addr())
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(query_names(&session).await.unwrap(), vec!["ann"]);
+        assert!(replay.unmatched_requests().is_empty());
+
+        let error = session.query("SELECT 1", &[]).await.unwrap_err();
+        assert!(matches!(
+            error,
+            QueryError::DbError(DbError::ServerError, _)
+        ));
+        assert_eq!(replay.unmatched_requests().len(), 1);
+
+        replay.finish().await.unwrap();
+    }
+}
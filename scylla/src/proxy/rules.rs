@@ -0,0 +1,207 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use super::frame::{RequestFrame, ResponseFrame};
+use crate::frame::request::RequestOpcode;
+use crate::frame::response::ResponseOpcode;
+use crate::transport::errors::DbError;
+
+/// Condition deciding whether a rule applies to a frame
+#[derive(Debug, Clone)]
+pub enum Condition {
+    True,
+    False,
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+
+    /// The request has the given opcode.
+    /// In response rules it checks the request to which the frame is a response
+    RequestOpcode(RequestOpcode),
+
+    /// The response has the given opcode, always false in request rules
+    ResponseOpcode(ResponseOpcode),
+
+    /// Body of the frame contains the given bytes, e.g. a fragment of the query text.
+    /// Doesn't work with compressed bodies
+    BodyContains(Vec<u8>),
+
+    /// The frame was sent on the n-th connection accepted by the proxy, counting from 0
+    ConnectionSeqNo(usize),
+
+    /// True for the first n frames on which it's evaluated, false afterwards.
+    /// Put it last in `And` to count only frames matching the other conditions
+    TrueForLimitedTimes(usize),
+}
+
+impl Condition {
+    pub fn and(self, other: Condition) -> Condition {
+        Condition::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: Condition) -> Condition {
+        Condition::Or(Box::new(self), Box::new(other))
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Condition {
+        Condition::Not(Box::new(self))
+    }
+
+    /// Body of the frame contains the given text
+    pub fn body_contains(text: &str) -> Condition {
+        Condition::BodyContains(text.as_bytes().to_vec())
+    }
+
+    pub(super) fn eval(&mut self, ctx: &EvaluationContext) -> bool {
+        match self {
+            Condition::True => true,
+            Condition::False => false,
+            Condition::Not(c) => !c.eval(ctx),
+            Condition::And(c1, c2) => c1.eval(ctx) && c2.eval(ctx),
+            Condition::Or(c1, c2) => c1.eval(ctx) || c2.eval(ctx),
+            Condition::RequestOpcode(opcode) => ctx.request_opcode == Some(*opcode),
+            Condition::ResponseOpcode(opcode) => ctx.response_opcode == Some(*opcode),
+            Condition::BodyContains(needle) => {
+                needle.is_empty() || ctx.body.windows(needle.len()).any(|w| w == &needle[..])
+            }
+            Condition::ConnectionSeqNo(seq_no) => ctx.connection_seq_no == *seq_no,
+            Condition::TrueForLimitedTimes(times) => {
+                let result = *times > 0;
+                *times = times.saturating_sub(1);
+                result
+            }
+        }
+    }
+}
+
+pub(super) struct EvaluationContext<'a> {
+    pub(super) connection_seq_no: usize,
+    pub(super) request_opcode: Option<RequestOpcode>,
+    pub(super) response_opcode: Option<ResponseOpcode>,
+    pub(super) body: &'a [u8],
+}
+
+pub type RequestRewriter = Arc<dyn Fn(RequestFrame) -> RequestFrame + Send + Sync>;
+pub type ResponseRewriter = Arc<dyn Fn(ResponseFrame) -> ResponseFrame + Send + Sync>;
+
+/// What the proxy does with a request frame sent by the driver
+#[derive(Clone)]
+pub enum RequestReaction {
+    /// Pass the frame to the node unchanged
+    Forward,
+    /// Pass the frame to the node after a delay, other frames aren't held back
+    Delay(Duration),
+    /// Don't pass the frame, the driver won't get a response to it
+    Drop,
+    /// Close the connection, both to the driver and to the node
+    DropConnection,
+    /// Don't pass the frame, respond to the driver with an error instead
+    RespondWithError(DbError),
+    /// Pass the frame returned by the function to the node
+    Rewrite(RequestRewriter),
+}
+
+/// What the proxy does with a response frame sent by the node
+#[derive(Clone)]
+pub enum ResponseReaction {
+    /// Pass the frame to the driver unchanged
+    Forward,
+    /// Pass the frame to the driver after a delay, other frames aren't held back
+    Delay(Duration),
+    /// Don't pass the frame, the driver won't get the response
+    Drop,
+    /// Close the connection, both to the driver and to the node
+    DropConnection,
+    /// Pass the frame returned by the function to the driver
+    Rewrite(ResponseRewriter),
+}
+
+/// Rule applied to requests sent by the driver.
+/// The first rule whose condition is true decides what happens with the frame
+#[derive(Clone)]
+pub struct RequestRule {
+    pub(super) condition: Condition,
+    pub(super) reaction: RequestReaction,
+    pub(super) feedback: Option<mpsc::UnboundedSender<RequestFrame>>,
+}
+
+impl RequestRule {
+    pub fn new(condition: Condition, reaction: RequestReaction) -> Self {
+        RequestRule {
+            condition,
+            reaction,
+            feedback: None,
+        }
+    }
+
+    /// Frames to which the rule is applied are also sent to the channel,
+    /// so that the test can check what the driver sent
+    pub fn with_feedback(mut self, feedback: mpsc::UnboundedSender<RequestFrame>) -> Self {
+        self.feedback = Some(feedback);
+        self
+    }
+}
+
+/// Rule applied to responses sent by the node.
+/// The first rule whose condition is true decides what happens with the frame
+#[derive(Clone)]
+pub struct ResponseRule {
+    pub(super) condition: Condition,
+    pub(super) reaction: ResponseReaction,
+    pub(super) feedback: Option<mpsc::UnboundedSender<ResponseFrame>>,
+}
+
+impl ResponseRule {
+    pub fn new(condition: Condition, reaction: ResponseReaction) -> Self {
+        ResponseRule {
+            condition,
+            reaction,
+            feedback: None,
+        }
+    }
+
+    /// Frames to which the rule is applied are also sent to the channel,
+    /// so that the test can check what the node sent
+    pub fn with_feedback(mut self, feedback: mpsc::UnboundedSender<ResponseFrame>) -> Self {
+        self.feedback = Some(feedback);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Condition, EvaluationContext};
+    use crate::frame::request::RequestOpcode;
+
+    fn ctx(body: &[u8]) -> EvaluationContext<'_> {
+        EvaluationContext {
+            connection_seq_no: 1,
+            request_opcode: Some(RequestOpcode::Query),
+            response_opcode: None,
+            body,
+        }
+    }
+
+    #[test]
+    fn conditions() {
+        let ctx = ctx(b"SELECT * FROM ks.t");
+
+        assert!(Condition::RequestOpcode(RequestOpcode::Query).eval(&ctx));
+        assert!(!Condition::RequestOpcode(RequestOpcode::Execute).eval(&ctx));
+        assert!(Condition::body_contains("ks.t").eval(&ctx));
+        assert!(!Condition::body_contains("ks.u").eval(&ctx));
+        assert!(Condition::ConnectionSeqNo(1)
+            .and(Condition::body_contains("SELECT"))
+            .eval(&ctx));
+        assert!(Condition::False.or(Condition::False.not()).eval(&ctx));
+
+        let mut limited = Condition::body_contains("INSERT").and(Condition::TrueForLimitedTimes(2));
+        let mut limited_matching = Condition::True.and(Condition::TrueForLimitedTimes(2));
+        let results: Vec<(bool, bool)> = (0..3)
+            .map(|_| (limited.eval(&ctx), limited_matching.eval(&ctx)))
+            .collect();
+        assert_eq!(results, vec![(false, true), (false, true), (false, false)]);
+    }
+}
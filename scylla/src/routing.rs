@@ -13,9 +13,23 @@ pub struct Node {
     pub addr: SocketAddr,
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
-pub struct Token {
-    pub value: i64,
+/// Position of a partition on the token ring, computed from the partition key by the partitioner.
+/// Tokens of each partitioner have their own representation, all tokens of a cluster use the same one
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug)]
+pub enum Token {
+    /// Token of `Murmur3Partitioner`, also used by Scylla's `CDCPartitioner`
+    Murmur3(i64),
+    /// Token of `RandomPartitioner`, from 0 to 2^127
+    Random(u128),
+    /// Token of `ByteOrderedPartitioner`, the partition key itself
+    ByteOrdered(Bytes),
+}
+
+/// Range of tokens from `start` to `end`, both inclusive
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct TokenRange {
+    pub start: Token,
+    pub end: Token,
 }
 
 pub type Shard = u32;
@@ -27,19 +41,163 @@ pub struct ShardInfo {
     pub msb_ignore: u8,
 }
 
-impl std::str::FromStr for Token {
-    type Err = std::num::ParseIntError;
-    fn from_str(s: &str) -> Result<Token, std::num::ParseIntError> {
-        Ok(Token { value: s.parse()? })
+impl Token {
+    /// The smallest token of the given partitioner
+    pub fn min(partitioner: Partitioner) -> Token {
+        match partitioner {
+            Partitioner::Murmur3 | Partitioner::Cdc => Token::Murmur3(i64::MIN),
+            Partitioner::Random => Token::Random(0),
+            Partitioner::ByteOrdered => Token::ByteOrdered(Bytes::new()),
+        }
+    }
+
+    /// The largest token of the given partitioner
+    pub fn max(partitioner: Partitioner) -> Token {
+        match partitioner {
+            Partitioner::Murmur3 | Partitioner::Cdc => Token::Murmur3(i64::MAX),
+            Partitioner::Random => Token::Random(RANDOM_TOKEN_MAX),
+            Partitioner::ByteOrdered => {
+                Token::ByteOrdered(Bytes::from(vec![0xff; MAX_PARTITION_KEY_LEN]))
+            }
+        }
+    }
+
+    /// Returns the smallest token greater than this one,
+    /// or `None` if this is the largest token of its partitioner
+    pub fn next(&self) -> Option<Token> {
+        match self {
+            Token::Murmur3(value) => value.checked_add(1).map(Token::Murmur3),
+            Token::Random(value) if *value >= RANDOM_TOKEN_MAX => None,
+            Token::Random(value) => Some(Token::Random(value + 1)),
+            // Keys are ordered lexicographically, so a shorter key is followed by itself and a zero byte.
+            // A key of the maximum length is followed by its prefix with the last byte incremented
+            Token::ByteOrdered(key) if key.len() < MAX_PARTITION_KEY_LEN => {
+                let mut next = Vec::with_capacity(key.len() + 1);
+                next.extend_from_slice(key);
+                next.push(0);
+                Some(Token::ByteOrdered(next.into()))
+            }
+            Token::ByteOrdered(key) => {
+                let prefix_len = key.iter().rposition(|byte| *byte != 0xff)?;
+                let mut next = key[..=prefix_len].to_vec();
+                next[prefix_len] += 1;
+                Some(Token::ByteOrdered(next.into()))
+            }
+        }
     }
 }
 
-pub fn murmur3_token(pk: Bytes) -> Token {
-    Token {
-        value: hash3_x64_128(&pk) as i64,
+// Tokens of RandomPartitioner are absolute values of 128-bit signed integers
+const RANDOM_TOKEN_MAX: u128 = 1 << 127;
+
+// Partition keys are serialized with a 16-bit length
+const MAX_PARTITION_KEY_LEN: usize = u16::MAX as usize;
+
+pub fn murmur3_token(pk: impl AsRef<[u8]>) -> Token {
+    Token::Murmur3(hash3_x64_128(pk.as_ref()) as i64)
+}
+
+/// Computes the token of `RandomPartitioner`, the absolute value of the key's MD5 digest
+/// read as a signed integer
+pub fn random_token(pk: impl AsRef<[u8]>) -> Token {
+    let digest: [u8; 16] = md5::compute(pk.as_ref()).into();
+    Token::Random(i128::from_be_bytes(digest).unsigned_abs())
+}
+
+/// Partitioner computing tokens of partition keys.  
+/// Tables use the partitioner of the cluster, except for tables with their own partitioner,
+/// e.g. CDC log tables in Scylla.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Partitioner {
+    /// `Murmur3Partitioner`, the default one
+    #[default]
+    Murmur3,
+    /// `RandomPartitioner`, tokens are MD5 hashes of partition keys
+    Random,
+    /// `ByteOrderedPartitioner`, tokens are partition keys themselves
+    ByteOrdered,
+    /// Scylla's `CDCPartitioner` used by CDC log tables, tokens are stored in CDC stream ids
+    Cdc,
+}
+
+/// Error returned when a token can't be parsed using the given partitioner
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Invalid token {token} for {partitioner:?} partitioner")]
+pub struct ParseTokenError {
+    pub token: String,
+    pub partitioner: Partitioner,
+}
+
+impl Partitioner {
+    /// Finds the partitioner with given class name, as it appears in `system.local`
+    /// and `system_schema.tables`, e.g. "org.apache.cassandra.dht.Murmur3Partitioner".
+    /// Returns `None` for unknown partitioners
+    pub fn from_class_name(class_name: &str) -> Option<Self> {
+        match class_name.rsplit('.').next()? {
+            "Murmur3Partitioner" => Some(Partitioner::Murmur3),
+            "RandomPartitioner" => Some(Partitioner::Random),
+            "ByteOrderedPartitioner" => Some(Partitioner::ByteOrdered),
+            "CDCPartitioner" => Some(Partitioner::Cdc),
+            _ => None,
+        }
+    }
+
+    /// Computes the token of a serialized partition key,
+    /// see [`PreparedStatement::compute_partition_key`](crate::prepared_statement::PreparedStatement::compute_partition_key)
+    pub fn token(&self, partition_key: &[u8]) -> Token {
+        match self {
+            Partitioner::Murmur3 => murmur3_token(partition_key),
+            Partitioner::Random => random_token(partition_key),
+            Partitioner::ByteOrdered => Token::ByteOrdered(Bytes::copy_from_slice(partition_key)),
+            Partitioner::Cdc => {
+                // Keys are stream ids, their first 8 bytes are the token.
+                // Keys of other length get the minimum token
+                if partition_key.len() != 16 {
+                    return Token::min(Partitioner::Cdc);
+                }
+                let mut value = [0u8; 8];
+                value.copy_from_slice(&partition_key[..8]);
+                Token::Murmur3(i64::from_be_bytes(value))
+            }
+        }
+    }
+
+    /// Parses a token from its text representation, as it appears in `system.peers`
+    pub fn parse_token(&self, token: &str) -> Result<Token, ParseTokenError> {
+        let error = || ParseTokenError {
+            token: token.to_string(),
+            partitioner: *self,
+        };
+
+        match self {
+            Partitioner::Murmur3 | Partitioner::Cdc => {
+                token.parse().map(Token::Murmur3).map_err(|_| error())
+            }
+            Partitioner::Random => match token.parse() {
+                Ok(value) if value <= RANDOM_TOKEN_MAX => Ok(Token::Random(value)),
+                _ => Err(error()),
+            },
+            Partitioner::ByteOrdered => {
+                // Tokens are hex-encoded keys
+                let key = token
+                    .as_bytes()
+                    .chunks(2)
+                    .map(|digits| match digits {
+                        [high, low] => Some((hex_digit(*high)? << 4) | hex_digit(*low)?),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<u8>>>()
+                    .ok_or_else(error)?;
+                Ok(Token::ByteOrdered(key.into()))
+            }
+        }
     }
 }
 
+fn hex_digit(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
 impl ShardInfo {
     pub fn new(shard: u16, nr_shards: u16, msb_ignore: u8) -> Self {
         assert!(nr_shards > 0);
@@ -50,10 +208,16 @@ impl ShardInfo {
         }
     }
 
-    pub fn shard_of(&self, token: Token) -> Shard {
-        let mut biased_token = (token.value as u64).wrapping_add(1u64 << 63);
+    /// Computes the shard owning the token. Returns `None` for tokens of partitioners
+    /// other than Murmur3, their partitions aren't assigned to shards by this function
+    pub fn shard_of(&self, token: &Token) -> Option<Shard> {
+        let value = match token {
+            Token::Murmur3(value) => *value,
+            Token::Random(_) | Token::ByteOrdered(_) => return None,
+        };
+        let mut biased_token = (value as u64).wrapping_add(1u64 << 63);
         biased_token <<= self.msb_ignore;
-        (((biased_token as u128) * (self.nr_shards as u128)) >> 64) as Shard
+        Some((((biased_token as u128) * (self.nr_shards as u128)) >> 64) as Shard)
     }
 
     /// If we connect to Scylla using Scylla's shard aware port, then Scylla assigns a shard to the
@@ -225,24 +389,130 @@ fn fmix(mut k: Wrapping<i64>) -> Wrapping<i64> {
 mod tests {
     use super::ShardInfo;
     use super::Token;
+    use super::{ParseTokenError, Partitioner};
+    use bytes::Bytes;
     use std::collections::HashSet;
 
+    #[test]
+    fn partitioners_from_class_names() {
+        let partitioner = Partitioner::from_class_name;
+        assert_eq!(
+            partitioner("org.apache.cassandra.dht.Murmur3Partitioner"),
+            Some(Partitioner::Murmur3)
+        );
+        assert_eq!(
+            partitioner("org.apache.cassandra.dht.RandomPartitioner"),
+            Some(Partitioner::Random)
+        );
+        assert_eq!(
+            partitioner("org.apache.cassandra.dht.ByteOrderedPartitioner"),
+            Some(Partitioner::ByteOrdered)
+        );
+        assert_eq!(
+            partitioner("com.scylladb.dht.CDCPartitioner"),
+            Some(Partitioner::Cdc)
+        );
+        assert_eq!(
+            partitioner("Murmur3Partitioner"),
+            Some(Partitioner::Murmur3)
+        );
+        assert_eq!(
+            partitioner("org.apache.cassandra.dht.OrderPreservingPartitioner"),
+            None
+        );
+    }
+
+    #[test]
+    fn partitioner_tokens() {
+        let key = 1_i32.to_be_bytes();
+        assert_eq!(Partitioner::Murmur3.token(&key), super::murmur3_token(key));
+        assert_eq!(
+            Partitioner::Murmur3.parse_token("-42"),
+            Ok(Token::Murmur3(-42))
+        );
+
+        // MD5 of int 1 is f1450306... which is negative, the token is its absolute value
+        assert_eq!(
+            Partitioner::Random.token(&key),
+            Token::Random(19580090105725936846312850328329299579)
+        );
+        assert_eq!(
+            Partitioner::Random.parse_token("170141183460469231731687303715884105728"),
+            Ok(Token::Random(1 << 127))
+        );
+        assert!(Partitioner::Random
+            .parse_token("170141183460469231731687303715884105729")
+            .is_err());
+        assert!(Partitioner::Random.parse_token("-1").is_err());
+
+        // Byte ordered tokens are keys, ordered lexicographically
+        assert_eq!(
+            Partitioner::ByteOrdered.token(&key),
+            Token::ByteOrdered(Bytes::from_static(&[0, 0, 0, 1]))
+        );
+        assert_eq!(
+            Partitioner::ByteOrdered.parse_token("00000001"),
+            Ok(Partitioner::ByteOrdered.token(&key))
+        );
+        assert_eq!(
+            Partitioner::ByteOrdered.token(&[]),
+            Token::min(Partitioner::ByteOrdered)
+        );
+        assert!(Partitioner::ByteOrdered.token(b"a") < Partitioner::ByteOrdered.token(b"ab"));
+        assert!(Partitioner::ByteOrdered.token(b"ab") < Partitioner::ByteOrdered.token(b"b"));
+        assert!(Partitioner::ByteOrdered.token(&[0x7f]) < Partitioner::ByteOrdered.token(&[0x80]));
+        assert_eq!(
+            Partitioner::ByteOrdered.parse_token("0g"),
+            Err(ParseTokenError {
+                token: "0g".to_string(),
+                partitioner: Partitioner::ByteOrdered
+            })
+        );
+
+        // CDC tokens are first 8 bytes of stream ids
+        let mut stream_id = vec![0xff; 8];
+        stream_id.extend_from_slice(&[0x12; 8]);
+        assert_eq!(Partitioner::Cdc.token(&stream_id), Token::Murmur3(-1));
+        assert_eq!(Partitioner::Cdc.token(&key), Token::Murmur3(i64::MIN));
+
+        assert!(Partitioner::Murmur3.parse_token("0x12").is_err());
+    }
+
+    #[test]
+    fn next_tokens() {
+        assert_eq!(Token::Murmur3(-1).next(), Some(Token::Murmur3(0)));
+        assert_eq!(Token::Murmur3(i64::MAX).next(), None);
+        assert_eq!(Token::Random(0).next(), Some(Token::Random(1)));
+        assert_eq!(Token::Random(1 << 127).next(), None);
+
+        let key = Token::ByteOrdered(Bytes::from_static(b"a"));
+        let next = key.next().unwrap();
+        assert_eq!(next, Token::ByteOrdered(Bytes::from_static(b"a\0")));
+        assert!(key < next && next < Token::ByteOrdered(Bytes::from_static(b"a\x01")));
+
+        // Keys of the maximum length can't be extended
+        let mut longest_key = vec![0xff; u16::MAX as usize];
+        longest_key[0] = b'a';
+        assert_eq!(
+            Token::ByteOrdered(longest_key.into()).next(),
+            Some(Token::ByteOrdered(Bytes::from_static(b"b")))
+        );
+        assert_eq!(Token::max(Partitioner::ByteOrdered).next(), None);
+    }
+
     #[test]
     fn test_shard_of() {
         /* Test values taken from the gocql driver.  */
         let shard_info = ShardInfo::new(0, 4, 12);
         assert_eq!(
-            shard_info.shard_of(Token {
-                value: -9219783007514621794
-            }),
-            3
+            shard_info.shard_of(&Token::Murmur3(-9219783007514621794)),
+            Some(3)
         );
         assert_eq!(
-            shard_info.shard_of(Token {
-                value: 9222582454147032830
-            }),
-            3
+            shard_info.shard_of(&Token::Murmur3(9222582454147032830)),
+            Some(3)
         );
+        assert_eq!(shard_info.shard_of(&Token::Random(42)), None);
     }
 
     #[test]
@@ -1,5 +1,8 @@
+use crate::frame::value::{SerializeValuesError, SerializedValues, ValueList};
 use crate::statement::{prepared_statement::PreparedStatement, query::Query};
 use crate::transport::retry_policy::RetryPolicy;
+use std::sync::Arc;
+use std::time::Duration;
 
 pub use super::Consistency;
 use super::StatementConfig;
@@ -13,6 +16,8 @@ pub struct Batch {
     pub(crate) config: StatementConfig,
 
     statements: Vec<BatchStatement>,
+    // Values bound to statements at append time, values[i] belong to statements[i]
+    values: Vec<SerializedValues>,
     batch_type: BatchType,
 }
 
@@ -28,6 +33,114 @@ impl Batch {
     /// Appends a new statement to the batch.
     pub fn append_statement(&mut self, statement: impl Into<BatchStatement>) {
         self.statements.push(statement.into());
+        self.values.push(SerializedValues::new());
+    }
+
+    /// Appends the same statement `n` times, e.g. a prepared `INSERT` executed with many value sets.
+    /// Values for all of them can be passed to `Session::batch` using
+    /// [`BatchValuesFromIter`](crate::frame::value::BatchValuesFromIter).
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// use scylla::batch::Batch;
+    /// use scylla::frame::value::BatchValuesFromIter;
+    ///
+    /// let prepared = session.prepare("INSERT INTO ks.tab (a, b) VALUES(?, ?)").await?;
+    /// let rows: Vec<(i32, String)> = vec![(1, "a".to_string()), (2, "b".to_string())];
+    ///
+    /// let mut batch: Batch = Default::default();
+    /// batch.append_statement_n(prepared, rows.len());
+    ///
+    /// session.batch(&batch, BatchValuesFromIter::new(rows.iter())).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn append_statement_n(&mut self, statement: impl Into<BatchStatement>, n: usize) {
+        let statement: BatchStatement = statement.into();
+        self.statements.reserve(n);
+        self.values.reserve(n);
+        for _ in 0..n {
+            self.statements.push(statement.clone());
+            self.values.push(SerializedValues::new());
+        }
+    }
+
+    /// Appends a new statement to the batch along with values bound to it.
+    /// Values are serialized and kept in the batch, a batch with bound values is performed using
+    /// [`Session::batch_with_bound_values`](crate::Session::batch_with_bound_values).
+    /// Statements appended using [`Batch::append_statement`] have empty values.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// use scylla::batch::Batch;
+    ///
+    /// let mut batch: Batch = Default::default();
+    /// batch.append_statement_with_values("INSERT INTO ks.tab (a, b) VALUES(?, ?)", (1, 2))?;
+    /// batch.append_statement_with_values("INSERT INTO ks.tab (a, b) VALUES(?, ?)", (3, 4))?;
+    /// batch.append_statement("INSERT INTO ks.tab (a, b) VALUES(5, 6)");
+    ///
+    /// session.batch_with_bound_values(&batch).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn append_statement_with_values(
+        &mut self,
+        statement: impl Into<BatchStatement>,
+        values: impl ValueList,
+    ) -> Result<(), SerializeValuesError> {
+        let serialized_values: SerializedValues = values.serialized()?.into_owned();
+
+        self.statements.push(statement.into());
+        self.values.push(serialized_values);
+        Ok(())
+    }
+
+    /// Splits the batch into batches containing at most `max_statements` consecutive statements each,
+    /// along with values bound using [`Batch::append_statement_with_values`].
+    /// Chunks have the same type and configuration as the original batch.
+    ///
+    /// This is useful for oversized batches of statements modifying a single partition,
+    /// which can be then executed one after another. Note that the chunks are separate batches,
+    /// so the whole set of statements is no longer applied atomically.
+    ///
+    /// # Panics
+    /// Panics if `max_statements` is 0.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// use scylla::batch::Batch;
+    ///
+    /// let mut batch: Batch = Default::default();
+    /// for i in 0..1000 {
+    ///     batch.append_statement_with_values("INSERT INTO ks.tab (a, b) VALUES(0, ?)", (i,))?;
+    /// }
+    ///
+    /// for chunk in batch.split_into_chunks(100) {
+    ///     session.batch_with_bound_values(&chunk).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn split_into_chunks(&self, max_statements: usize) -> Vec<Batch> {
+        self.statements
+            .chunks(max_statements)
+            .zip(self.values.chunks(max_statements))
+            .map(|(statements, values)| Batch {
+                config: self.config.clone(),
+                statements: statements.to_vec(),
+                values: values.to_vec(),
+                batch_type: self.batch_type,
+            })
+            .collect()
     }
 
     /// Gets type of batch.
@@ -40,13 +153,21 @@ impl Batch {
         self.statements.as_ref()
     }
 
+    /// Returns values bound to statements using [`Batch::append_statement_with_values`],
+    /// one entry for each statement in the batch.
+    /// [`Session::batch_with_bound_values`](crate::Session::batch_with_bound_values) sends them along with the statements
+    pub fn get_values(&self) -> &[SerializedValues] {
+        self.values.as_ref()
+    }
+
     /// Sets the consistency to be used when executing this batch.
     pub fn set_consistency(&mut self, c: Consistency) {
-        self.config.consistency = c;
+        self.config.consistency = Some(c);
     }
 
     /// Gets the consistency to be used when executing this batch.
-    pub fn get_consistency(&self) -> Consistency {
+    /// `None` means that the session's default consistency is used.
+    pub fn get_consistency(&self) -> Option<Consistency> {
         self.config.consistency
     }
 
@@ -58,10 +179,36 @@ impl Batch {
 
     /// Gets the serial consistency to be used when executing this batch.
     /// (Ignored unless the batch is an LWT)
+    /// `None` means that the session's default serial consistency is used.
     pub fn get_serial_consistency(&self) -> Option<Consistency> {
         self.config.serial_consistency
     }
 
+    /// Sets the timestamp of this batch, in microseconds since unix epoch.
+    /// It's used as the write timestamp, like `USING TIMESTAMP` in the statement text.
+    /// With `None` the session's timestamp generator is used.
+    pub fn set_timestamp(&mut self, timestamp: Option<i64>) {
+        self.config.timestamp = timestamp;
+    }
+
+    /// Gets the timestamp of this batch.
+    /// `None` means that the session's timestamp generator is used.
+    pub fn get_timestamp(&self) -> Option<i64> {
+        self.config.timestamp
+    }
+
+    /// Sets the time after which executing this batch fails with a timeout error.
+    /// With `None` the session's default request timeout is used.
+    pub fn set_request_timeout(&mut self, timeout: Option<Duration>) {
+        self.config.request_timeout = timeout;
+    }
+
+    /// Gets the request timeout of this batch.
+    /// `None` means that the session's default request timeout is used.
+    pub fn get_request_timeout(&self) -> Option<Duration> {
+        self.config.request_timeout
+    }
+
     /// Sets the idempotence of this statement
     /// A query is idempotent if it can be applied multiple times without changing the result of the initial application
     /// If set to `true` we can be sure that it is idempotent
@@ -78,17 +225,17 @@ impl Batch {
 
     /// Sets a custom [`RetryPolicy`] to be used with this statement
     /// By default Session's retry policy is used, this allows to use a custom retry policy
-    pub fn set_retry_policy(&mut self, retry_policy: Box<dyn RetryPolicy>) {
+    pub fn set_retry_policy(&mut self, retry_policy: Arc<dyn RetryPolicy>) {
         self.config.retry_policy = Some(retry_policy);
     }
 
     /// Gets custom [`RetryPolicy`] used by this statement
-    pub fn get_retry_policy(&self) -> &Option<Box<dyn RetryPolicy>> {
+    pub fn get_retry_policy(&self) -> &Option<Arc<dyn RetryPolicy>> {
         &self.config.retry_policy
     }
 
     /// Enable or disable CQL Tracing for this batch
-    /// If enabled session.batch() will return a BatchResult containing tracing_id
+    /// If enabled session.batch() will return a QueryResult containing tracing_id
     /// which can be used to query tracing information about the execution of this query
     pub fn set_tracing(&mut self, should_trace: bool) {
         self.config.tracing = should_trace;
@@ -104,6 +251,7 @@ impl Default for Batch {
     fn default() -> Self {
         Self {
             statements: Vec::new(),
+            values: Vec::new(),
             batch_type: BatchType::Logged,
             config: Default::default(),
         }
@@ -134,3 +282,80 @@ impl From<PreparedStatement> for BatchStatement {
         BatchStatement::PreparedStatement(p)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Batch, BatchStatement};
+    use crate::frame::value::{BatchValues, SerializedValues};
+
+    #[test]
+    fn append_statement_with_values() {
+        let mut batch: Batch = Default::default();
+        batch
+            .append_statement_with_values("INSERT INTO ks.tab (a, b) VALUES(?, ?)", (1_i8, 2_i8))
+            .unwrap();
+        batch.append_statement("INSERT INTO ks.tab (a, b) VALUES(3, 4)");
+
+        assert_eq!(batch.get_statements().len(), 2);
+        assert_eq!(batch.get_values().len(), 2);
+        assert_eq!(batch.get_values()[1], SerializedValues::new());
+
+        let mut request: Vec<u8> = Vec::new();
+        batch
+            .get_values()
+            .write_nth_to_request(0, &mut request)
+            .unwrap();
+        assert_eq!(request, vec![0, 2, 0, 0, 0, 1, 1, 0, 0, 0, 1, 2]);
+    }
+
+    #[test]
+    fn append_statement_n() {
+        let mut batch: Batch = Default::default();
+        batch.append_statement("INSERT INTO ks.tab (a, b) VALUES(1, 2)");
+        batch.append_statement_n("INSERT INTO ks.tab (a, b) VALUES(?, ?)", 3);
+        batch.append_statement_n("INSERT INTO ks.tab (a, b) VALUES(3, 4)", 0);
+
+        let statements: Vec<&str> = batch
+            .get_statements()
+            .iter()
+            .map(|statement| match statement {
+                BatchStatement::Query(query) => query.get_contents(),
+                BatchStatement::PreparedStatement(_) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            statements,
+            vec![
+                "INSERT INTO ks.tab (a, b) VALUES(1, 2)",
+                "INSERT INTO ks.tab (a, b) VALUES(?, ?)",
+                "INSERT INTO ks.tab (a, b) VALUES(?, ?)",
+                "INSERT INTO ks.tab (a, b) VALUES(?, ?)",
+            ]
+        );
+        assert_eq!(batch.get_values(), vec![SerializedValues::new(); 4]);
+    }
+
+    #[test]
+    fn split_into_chunks() {
+        let mut batch: Batch = Default::default();
+        batch.set_tracing(true);
+        for i in 0..5_i8 {
+            batch
+                .append_statement_with_values("INSERT INTO ks.tab (a, b) VALUES(0, ?)", (i,))
+                .unwrap();
+        }
+
+        let chunks: Vec<Batch> = batch.split_into_chunks(2);
+        let chunk_lens: Vec<usize> = chunks.iter().map(|c| c.get_statements().len()).collect();
+        assert_eq!(chunk_lens, vec![2, 2, 1]);
+
+        for (chunk_num, chunk) in chunks.iter().enumerate() {
+            assert!(chunk.get_tracing());
+            assert_eq!(chunk.get_values().len(), chunk.get_statements().len());
+            assert_eq!(
+                chunk.get_values(),
+                &batch.get_values()[chunk_num * 2..][..chunk_lens[chunk_num]]
+            );
+        }
+    }
+}
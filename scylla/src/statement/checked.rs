@@ -0,0 +1,303 @@
+//! Statements checked at compile time against a schema snapshot
+//!
+//! The `checked_statement!` macro (enabled with the `checked-statements` feature) validates
+//! CQL statements against a snapshot of the schema, which is a JSON file produced by
+//! [`schema_snapshot`] from a live [`Session`]. Compilation doesn't require a running cluster.
+//!
+//! For each statement the macro generates a struct with:
+//! * `STATEMENT` - text of the statement
+//! * `values(...)` - serializes bound values, each of them has to be compatible with the type of its column
+//!
+//! and for `SELECT` statements also a `...Row` struct with a field for each selected column,
+//! which implements `FromRow`.
+//!
+//! # Example
+//! ```rust,ignore
+//! use scylla::macros::checked_statement;
+//!
+//! checked_statement! {
+//!     // Path relative to the crate root, `scylla_schema.json` by default
+//!     snapshot = "scylla_schema.json";
+//!
+//!     /// Generates `GetUser` and `GetUserRow`
+//!     pub GetUser = "SELECT id, name FROM ks.users WHERE id = ?";
+//!     pub InsertUser = "INSERT INTO ks.users (id, name) VALUES (?, ?)";
+//! }
+//!
+//! session.query(InsertUser::STATEMENT, InsertUser::values(1, "ann")?).await?;
+//!
+//! let rows = session.query(GetUser::STATEMENT, GetUser::values(1)?).await?;
+//! for row in rows.rows_typed::<GetUserRow>()? {
+//!     let row: GetUserRow = row?;
+//!     println!("{}: {:?}", row.id, row.name);
+//! }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::hash::BuildHasher;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use num_bigint::BigInt;
+use serde_json::{json, Map, Value as JsonValue};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::frame::value::{Counter, Date, MaybeUnset, Time, Timestamp, Unset, Value};
+use crate::transport::errors::QueryError;
+use crate::transport::session::Session;
+use crate::transport::ColumnKind;
+
+/// Marker types representing CQL types, used to check types of bound values
+pub mod types {
+    use std::marker::PhantomData;
+
+    pub struct Ascii;
+    pub struct Text;
+    pub struct Boolean;
+    pub struct TinyInt;
+    pub struct SmallInt;
+    pub struct Int;
+    pub struct BigInt;
+    pub struct Counter;
+    pub struct Float;
+    pub struct Double;
+    pub struct Blob;
+    pub struct Uuid;
+    pub struct Timeuuid;
+    pub struct Inet;
+    pub struct Date;
+    pub struct Time;
+    pub struct Timestamp;
+    pub struct Decimal;
+    pub struct Varint;
+    pub struct List<T>(PhantomData<T>);
+    pub struct Set<T>(PhantomData<T>);
+    pub struct Map<K, V>(PhantomData<(K, V)>);
+}
+
+/// Implemented by Rust types which can be bound to a value of CQL type `T`,
+/// `T` is one of the markers from [`types`] or a tuple of them
+pub trait Bind<T>: Value {}
+
+macro_rules! impl_bind {
+    ($marker:ident: $($typ:ty),+) => {
+        $(impl Bind<types::$marker> for $typ {})+
+    };
+}
+
+impl_bind!(Ascii: String, &str);
+impl_bind!(Text: String, &str);
+impl_bind!(Boolean: bool);
+impl_bind!(TinyInt: i8);
+impl_bind!(SmallInt: i16);
+impl_bind!(Int: i32);
+impl_bind!(BigInt: i64);
+impl_bind!(Counter: Counter);
+impl_bind!(Float: f32);
+impl_bind!(Double: f64);
+impl_bind!(Blob: Vec<u8>);
+impl_bind!(Uuid: Uuid);
+impl_bind!(Timeuuid: Uuid);
+impl_bind!(Inet: IpAddr);
+impl_bind!(Date: NaiveDate, Date);
+impl_bind!(Time: Time);
+impl_bind!(Timestamp: Timestamp);
+impl_bind!(Decimal: BigDecimal);
+impl_bind!(Varint: BigInt);
+
+impl<T, V: Bind<T>> Bind<T> for Option<V> {}
+impl<T, V: Bind<T>> Bind<T> for MaybeUnset<V> {}
+impl<T, V: Bind<T>> Bind<T> for &V {}
+impl<T> Bind<T> for Unset {}
+
+impl<T, V: Bind<T>> Bind<types::List<T>> for Vec<V> {}
+impl<T, V: Bind<T>> Bind<types::Set<T>> for Vec<V> {}
+impl<T, V: Bind<T>> Bind<types::Set<T>> for BTreeSet<V> {}
+impl<T, V: Bind<T>, S: BuildHasher> Bind<types::Set<T>> for HashSet<V, S> {}
+impl<K, V, KV: Bind<K>, VV: Bind<V>> Bind<types::Map<K, V>> for BTreeMap<KV, VV> {}
+impl<K, V, KV: Bind<K>, VV: Bind<V>, S: BuildHasher> Bind<types::Map<K, V>> for HashMap<KV, VV, S> {}
+
+macro_rules! impl_bind_for_tuple {
+    ($($T:ident $V:ident),+) => {
+        impl<$($T, $V: Bind<$T>),+> Bind<($($T,)+)> for ($($V,)+) {}
+    };
+}
+
+impl_bind_for_tuple!(T0 V0);
+impl_bind_for_tuple!(T0 V0, T1 V1);
+impl_bind_for_tuple!(T0 V0, T1 V1, T2 V2);
+impl_bind_for_tuple!(T0 V0, T1 V1, T2 V2, T3 V3);
+impl_bind_for_tuple!(T0 V0, T1 V1, T2 V2, T3 V3, T4 V4);
+impl_bind_for_tuple!(T0 V0, T1 V1, T2 V2, T3 V3, T4 V4, T5 V5);
+impl_bind_for_tuple!(T0 V0, T1 V1, T2 V2, T3 V3, T4 V4, T5 V5, T6 V6);
+impl_bind_for_tuple!(T0 V0, T1 V1, T2 V2, T3 V3, T4 V4, T5 V5, T6 V6, T7 V7);
+
+// Types used by code generated with checked_statement!, so that it doesn't require
+// the user's crate to depend on them
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::frame::response::result::CqlValue;
+    pub use crate::frame::value::Counter;
+    pub use bigdecimal::BigDecimal;
+    pub use chrono::{Duration, NaiveDate};
+    pub use num_bigint::BigInt;
+    pub use uuid::Uuid;
+}
+
+#[derive(Error, Debug)]
+pub enum SchemaSnapshotError {
+    #[error(transparent)]
+    QueryError(#[from] QueryError),
+    #[error("Keyspace {0} doesn't exist")]
+    UnknownKeyspace(String),
+    #[error("Writing schema snapshot failed: {0}")]
+    IoError(#[from] io::Error),
+}
+
+/// Fetches the current schema of `keyspaces` and renders it as a snapshot for `checked_statement!`
+pub async fn schema_snapshot(
+    session: &Session,
+    keyspaces: &[&str],
+) -> Result<String, SchemaSnapshotError> {
+    session.refresh_topology().await?;
+    let cluster_data = session.get_cluster_data();
+
+    let mut keyspaces_json = Map::new();
+    for keyspace_name in keyspaces {
+        let keyspace = cluster_data
+            .keyspaces
+            .get(*keyspace_name)
+            .ok_or_else(|| SchemaSnapshotError::UnknownKeyspace(keyspace_name.to_string()))?;
+
+        let tables: Map<String, JsonValue> = keyspace
+            .tables
+            .iter()
+            .map(|(table_name, table)| {
+                let columns: Map<String, JsonValue> = table
+                    .columns
+                    .iter()
+                    .map(|(name, column)| (name.clone(), json!(column.typ)))
+                    .collect();
+                let mut static_columns: Vec<&String> = table
+                    .columns
+                    .iter()
+                    .filter(|(_, column)| column.kind == ColumnKind::Static)
+                    .map(|(name, _)| name)
+                    .collect();
+                static_columns.sort_unstable();
+                let table_json = json!({
+                    "columns": columns,
+                    "partition_key": table.partition_key,
+                    "clustering_key": table.clustering_key,
+                    "static_columns": static_columns,
+                });
+                (table_name.clone(), table_json)
+            })
+            .collect();
+
+        keyspaces_json.insert(keyspace_name.to_string(), json!({ "tables": tables }));
+    }
+
+    // serde_json keeps keys sorted, so the snapshot doesn't change if the schema doesn't
+    Ok(serde_json::to_string_pretty(&json!({ "keyspaces": keyspaces_json })).unwrap() + "\n")
+}
+
+/// Writes the snapshot created by [`schema_snapshot`] to `path`
+pub async fn write_schema_snapshot(
+    session: &Session,
+    keyspaces: &[&str],
+    path: impl AsRef<Path>,
+) -> Result<(), SchemaSnapshotError> {
+    let snapshot = schema_snapshot(session, keyspaces).await?;
+    fs::write(path, snapshot)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{types, Bind};
+    use crate::frame::value::{Timestamp, Unset};
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+    fn check<T, V: Bind<T>>(_value: V) {}
+
+    #[test]
+    fn bind_compatible_types() {
+        check::<types::Text, _>("a");
+        check::<types::Text, _>(&"a".to_string());
+        check::<types::Int, _>(Some(1));
+        check::<types::BigInt, _>(Unset);
+        check::<types::Timestamp, _>(Timestamp(chrono::Duration::seconds(1)));
+        check::<types::List<types::Int>, _>(vec![1, 2]);
+        check::<types::Map<types::Text, types::Set<types::Double>>, _>(
+            vec![("a".to_string(), vec![1.5])]
+                .into_iter()
+                .collect::<HashMap<_, _>>(),
+        );
+        check::<types::Set<types::Int>, _>(BTreeSet::from([1, 2]));
+        check::<types::Set<types::Text>, _>(HashSet::from(["a"]));
+        check::<types::Map<types::Int, types::Text>, _>(BTreeMap::from([(1, "a")]));
+        check::<(types::Int, types::Text), _>((1, "a"));
+    }
+
+    #[cfg(feature = "checked-statements")]
+    mod generated {
+        use crate as scylla;
+        use crate::frame::response::cql_to_rust::FromRow;
+        use crate::frame::response::result::{CqlValue, Row};
+        use crate::frame::value::{SerializedValues, Timestamp};
+        use crate::macros::checked_statement;
+
+        checked_statement! {
+            snapshot = "src/statement/checked_test_schema.json";
+
+            pub GetEvents = "SELECT user_id, time, tags, \"Data\" AS data FROM ks.events WHERE user_id = ? AND time > ? LIMIT ?";
+            pub GetAll = "SELECT * FROM ks.events WHERE user_id IN ? AND tags CONTAINS ?";
+            InsertEvent = "INSERT INTO ks.events (user_id, time, tags) VALUES (?, ?, ['x']) USING TTL ?;";
+            UpdateEvent = "UPDATE ks.events SET tags = tags + ?, \"Data\" = ? WHERE user_id = ? AND time = ? IF EXISTS";
+            DeleteEvent = "DELETE tags FROM ks.events USING TIMESTAMP ? WHERE user_id = ? AND time IN (?, ?)";
+        }
+
+        #[test]
+        fn checked_statements() {
+            let time = Timestamp(chrono::Duration::seconds(1));
+
+            let mut expected = SerializedValues::new();
+            expected.add_value(&5).unwrap();
+            expected.add_value(&time).unwrap();
+            expected.add_value(&10).unwrap();
+            assert_eq!(GetEvents::values(5, time, 10).unwrap(), expected);
+            assert!(GetEvents::STATEMENT.starts_with("SELECT user_id, time"));
+
+            let row = Row {
+                columns: vec![
+                    Some(CqlValue::Int(5)),
+                    Some(CqlValue::Timestamp(chrono::Duration::seconds(1))),
+                    None,
+                    Some(CqlValue::Blob(vec![1])),
+                ],
+            };
+            let row = GetEventsRow::from_row(row).unwrap();
+            assert_eq!(row.user_id, 5);
+            assert_eq!(row.tags, None);
+            assert_eq!(row.data, Some(vec![1]));
+
+            assert_eq!(GetAll::values(vec![1, 2], "x").unwrap().len(), 2);
+            let _columns = |row: GetAllRow| (row.user_id, row.time, row.tags, row.data);
+
+            assert_eq!(InsertEvent::values(1, time, 60).unwrap().len(), 3);
+            assert_eq!(
+                UpdateEvent::values(vec!["y"], None::<Vec<u8>>, 1, time)
+                    .unwrap()
+                    .len(),
+                4
+            );
+            assert_eq!(DeleteEvent::values(10_i64, 1, time, time).unwrap().len(), 4);
+        }
+    }
+}
@@ -1,51 +1,329 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::transport::retry_policy::RetryPolicy;
 use crate::transport::speculative_execution::SpeculativeExecutionPolicy;
 
 pub mod batch;
+pub mod checked;
 pub mod prepared_statement;
 pub mod query;
+pub mod query_builder;
 
 pub use crate::frame::types::Consistency;
 
+#[derive(Clone)]
 pub struct StatementConfig {
-    pub consistency: Consistency,
+    /// If `None`, the session's default consistency is used
+    pub consistency: Option<Consistency>,
+    /// If `None`, the session's default serial consistency is used
     pub serial_consistency: Option<Consistency>,
 
     pub is_idempotent: bool,
 
-    pub retry_policy: Option<Box<dyn RetryPolicy>>,
+    pub retry_policy: Option<Arc<dyn RetryPolicy>>,
     pub speculative_execution_policy: Option<Arc<dyn SpeculativeExecutionPolicy>>,
 
     pub tracing: bool,
+
+    /// If `None`, the session's default number of prefetched pages is used
+    pub prefetch_pages: Option<usize>,
+
+    /// If `None`, the session's timestamp generator is used
+    pub timestamp: Option<i64>,
+
+    /// If `None`, the session's default request timeout is used
+    pub request_timeout: Option<Duration>,
 }
 
 impl Default for StatementConfig {
     fn default() -> Self {
         Self {
-            consistency: Default::default(),
+            consistency: None,
             serial_consistency: None,
             is_idempotent: false,
             retry_policy: None,
             speculative_execution_policy: None,
             tracing: false,
+            prefetch_pages: None,
+            timestamp: None,
+            request_timeout: None,
         }
     }
 }
 
-impl Clone for StatementConfig {
-    fn clone(&self) -> Self {
-        Self {
-            consistency: self.consistency,
-            serial_consistency: self.serial_consistency,
-            is_idempotent: self.is_idempotent,
-            retry_policy: self
-                .retry_policy
-                .as_ref()
-                .map(|policy| policy.clone_boxed()),
-            speculative_execution_policy: self.speculative_execution_policy.clone(),
-            tracing: self.tracing,
+/// Execution settings shared by [`Query`](query::Query), [`PreparedStatement`](prepared_statement::PreparedStatement)
+/// and [`Batch`](batch::Batch), so that generic code can configure or inspect any kind of statement.
+///
+/// The statements also have inherent methods with the same names, the trait has to be imported
+/// only in generic code and to set a speculative execution policy for a single statement.
+/// # Example
+/// ```
+/// use scylla::statement::{Consistency, StatementSettings};
+/// use scylla::query::Query;
+/// use scylla::batch::Batch;
+///
+/// fn make_strict(statement: &mut impl StatementSettings) {
+///     statement.set_consistency(Consistency::All);
+///     statement.set_is_idempotent(true);
+/// }
+///
+/// let mut query = Query::new("SELECT a FROM ks.t".to_string());
+/// make_strict(&mut query);
+///
+/// let mut batch = Batch::default();
+/// make_strict(&mut batch);
+/// ```
+pub trait StatementSettings {
+    /// All execution settings of the statement
+    fn config(&self) -> &StatementConfig;
+
+    /// All execution settings of the statement, for modification
+    fn config_mut(&mut self) -> &mut StatementConfig;
+
+    /// Sets the consistency to be used when executing this statement.
+    fn set_consistency(&mut self, c: Consistency) {
+        self.config_mut().consistency = Some(c);
+    }
+
+    /// Gets the consistency to be used when executing this statement.
+    /// `None` means that the session's default consistency is used.
+    fn get_consistency(&self) -> Option<Consistency> {
+        self.config().consistency
+    }
+
+    /// Sets the serial consistency to be used when executing this statement.
+    /// (Ignored unless the statement is an LWT)
+    fn set_serial_consistency(&mut self, sc: Option<Consistency>) {
+        self.config_mut().serial_consistency = sc;
+    }
+
+    /// Gets the serial consistency to be used when executing this statement.
+    /// `None` means that the session's default serial consistency is used.
+    fn get_serial_consistency(&self) -> Option<Consistency> {
+        self.config().serial_consistency
+    }
+
+    /// Sets the timestamp of this statement, in microseconds since unix epoch.
+    /// It's used as the write timestamp, like `USING TIMESTAMP` in the statement text.
+    /// With `None` the session's timestamp generator is used.
+    fn set_timestamp(&mut self, timestamp: Option<i64>) {
+        self.config_mut().timestamp = timestamp;
+    }
+
+    /// Gets the timestamp of this statement.
+    /// `None` means that the session's timestamp generator is used.
+    fn get_timestamp(&self) -> Option<i64> {
+        self.config().timestamp
+    }
+
+    /// Sets the time after which executing this statement fails with a timeout error.
+    /// With `None` the session's default request timeout is used.
+    fn set_request_timeout(&mut self, timeout: Option<Duration>) {
+        self.config_mut().request_timeout = timeout;
+    }
+
+    /// Gets the request timeout of this statement.
+    /// `None` means that the session's default request timeout is used.
+    fn get_request_timeout(&self) -> Option<Duration> {
+        self.config().request_timeout
+    }
+
+    /// Sets the idempotence of this statement, see [`RetryPolicy`]
+    fn set_is_idempotent(&mut self, is_idempotent: bool) {
+        self.config_mut().is_idempotent = is_idempotent;
+    }
+
+    /// Gets the idempotence of this statement
+    fn get_is_idempotent(&self) -> bool {
+        self.config().is_idempotent
+    }
+
+    /// Sets a custom [`RetryPolicy`] to be used instead of the session's one
+    fn set_retry_policy(&mut self, retry_policy: Arc<dyn RetryPolicy>) {
+        self.config_mut().retry_policy = Some(retry_policy);
+    }
+
+    /// Gets custom [`RetryPolicy`] used by this statement
+    fn get_retry_policy(&self) -> &Option<Arc<dyn RetryPolicy>> {
+        &self.config().retry_policy
+    }
+
+    /// Sets a custom [`SpeculativeExecutionPolicy`] to be used instead of the session's one.
+    /// Speculative execution is used only for idempotent statements
+    fn set_speculative_execution_policy(
+        &mut self,
+        policy: Option<Arc<dyn SpeculativeExecutionPolicy>>,
+    ) {
+        self.config_mut().speculative_execution_policy = policy;
+    }
+
+    /// Gets custom [`SpeculativeExecutionPolicy`] used by this statement
+    fn get_speculative_execution_policy(&self) -> &Option<Arc<dyn SpeculativeExecutionPolicy>> {
+        &self.config().speculative_execution_policy
+    }
+
+    /// Enables or disables CQL tracing for this statement
+    fn set_tracing(&mut self, should_trace: bool) {
+        self.config_mut().tracing = should_trace;
+    }
+
+    /// Gets whether tracing is enabled for this statement
+    fn get_tracing(&self) -> bool {
+        self.config().tracing
+    }
+}
+
+macro_rules! impl_statement_settings {
+    ($($statement:ty),+) => {
+        $(impl StatementSettings for $statement {
+            fn config(&self) -> &StatementConfig {
+                &self.config
+            }
+
+            fn config_mut(&mut self) -> &mut StatementConfig {
+                &mut self.config
+            }
+        })+
+    };
+}
+
+impl_statement_settings!(
+    query::Query,
+    prepared_statement::PreparedStatement,
+    batch::Batch
+);
+
+// Keywords which can't be used as unquoted identifiers
+const RESERVED_KEYWORDS: &[&str] = &[
+    "add",
+    "allow",
+    "alter",
+    "and",
+    "apply",
+    "asc",
+    "authorize",
+    "batch",
+    "begin",
+    "by",
+    "columnfamily",
+    "create",
+    "default",
+    "delete",
+    "desc",
+    "describe",
+    "drop",
+    "entries",
+    "execute",
+    "from",
+    "full",
+    "grant",
+    "if",
+    "in",
+    "index",
+    "infinity",
+    "insert",
+    "into",
+    "is",
+    "keyspace",
+    "limit",
+    "materialized",
+    "mbean",
+    "mbeans",
+    "modify",
+    "nan",
+    "norecursive",
+    "not",
+    "null",
+    "of",
+    "on",
+    "or",
+    "order",
+    "primary",
+    "rename",
+    "replace",
+    "revoke",
+    "schema",
+    "select",
+    "set",
+    "table",
+    "to",
+    "token",
+    "truncate",
+    "unlogged",
+    "unset",
+    "update",
+    "use",
+    "using",
+    "view",
+    "where",
+    "with",
+];
+
+/// Renders a keyspace, table or column name as it has to appear in CQL statement text.
+/// Lowercase names are written as they are, others are quoted, which makes them case sensitive.
+/// Reserved keywords are quoted too, e.g. a column named `order` is written as `"order"`
+pub(crate) fn quote_identifier(identifier: &str) -> String {
+    let is_unquoted_identifier = matches!(identifier.chars().next(), Some(c) if c.is_ascii_lowercase())
+        && identifier
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && !RESERVED_KEYWORDS.contains(&identifier);
+
+    if is_unquoted_identifier {
+        identifier.to_string()
+    } else {
+        format!("\"{}\"", identifier.replace('"', "\"\""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{quote_identifier, Consistency, StatementSettings};
+    use crate::batch::Batch;
+    use crate::query::Query;
+    use crate::transport::speculative_execution::SimpleSpeculativeExecutionPolicy;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn configure(statement: &mut impl StatementSettings) {
+        statement.set_consistency(Consistency::Quorum);
+        statement.set_is_idempotent(true);
+        statement.set_speculative_execution_policy(Some(Arc::new(
+            SimpleSpeculativeExecutionPolicy {
+                max_retry_count: 2,
+                retry_interval: Duration::from_millis(10),
+            },
+        )));
+    }
+
+    #[test]
+    fn generic_statement_settings() {
+        let mut query = Query::new("SELECT a FROM ks.t".to_string());
+        let mut batch = Batch::default();
+        configure(&mut query);
+        configure(&mut batch);
+
+        let statements: [&dyn StatementSettings; 2] = [&query, &batch];
+        for statement in statements.iter() {
+            assert_eq!(statement.get_consistency(), Some(Consistency::Quorum));
+            assert!(statement.get_is_idempotent());
+            assert!(statement.get_speculative_execution_policy().is_some());
+            assert!(!statement.get_tracing());
         }
+        // Inherent methods see the same settings
+        assert_eq!(query.get_consistency(), Some(Consistency::Quorum));
+    }
+
+    #[test]
+    fn test_quote_identifier() {
+        assert_eq!(quote_identifier("users"), "users");
+        assert_eq!(quote_identifier("user_id2"), "user_id2");
+        assert_eq!(quote_identifier("UserId"), "\"UserId\"");
+        assert_eq!(quote_identifier("2fa"), "\"2fa\"");
+        assert_eq!(quote_identifier("a\"b"), "\"a\"\"b\"");
+        assert_eq!(quote_identifier("order"), "\"order\"");
+        assert_eq!(quote_identifier("select"), "\"select\"");
+        assert_eq!(quote_identifier("orders"), "orders");
     }
 }
@@ -7,7 +7,12 @@ use super::StatementConfig;
 use crate::frame::response::result::PreparedMetadata;
 use crate::frame::types::Consistency;
 use crate::frame::value::SerializedValues;
+use crate::routing::Partitioner;
 use crate::transport::retry_policy::RetryPolicy;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Represents a statement prepared on the server.
 #[derive(Clone)]
@@ -16,9 +21,18 @@ pub struct PreparedStatement {
     pub prepare_tracing_ids: Vec<Uuid>,
 
     id: Bytes,
+    // Ids returned by nodes which prepared the statement with a different id,
+    // e.g. nodes running another version during a rolling upgrade
+    node_ids: Arc<HashMap<SocketAddr, Bytes>>,
     metadata: PreparedMetadata,
+    // Sent in EXECUTE in protocol v5
+    result_metadata_id: Option<Bytes>,
     statement: String,
+    // Keyspace the statement was prepared in, instead of the session's keyspace
+    keyspace: Option<String>,
     page_size: Option<i32>,
+    partitioner: Partitioner,
+    is_confirmed_lwt: bool,
 }
 
 impl PreparedStatement {
@@ -30,10 +44,15 @@ impl PreparedStatement {
     ) -> Self {
         Self {
             id,
+            node_ids: Default::default(),
             metadata,
+            result_metadata_id: None,
             statement,
+            keyspace: None,
             prepare_tracing_ids: Vec::new(),
             page_size,
+            partitioner: Default::default(),
+            is_confirmed_lwt: false,
             config: Default::default(),
         }
     }
@@ -42,10 +61,45 @@ impl PreparedStatement {
         &self.id
     }
 
+    /// Gets the id to execute the statement with on the node at `node_address`.  
+    /// Nodes usually agree on the id, but they can differ in heterogeneous clusters,
+    /// e.g. during a rolling upgrade. Nodes which weren't asked to prepare the statement use [`get_id`](PreparedStatement::get_id).
+    pub fn get_id_for_node(&self, node_address: SocketAddr) -> &Bytes {
+        self.node_ids.get(&node_address).unwrap_or(&self.id)
+    }
+
+    pub(crate) fn set_id_for_node(&mut self, node_address: SocketAddr, id: Bytes) {
+        if id == self.id {
+            Arc::make_mut(&mut self.node_ids).remove(&node_address);
+        } else {
+            Arc::make_mut(&mut self.node_ids).insert(node_address, id);
+        }
+    }
+
+    /// Gets the id of the result metadata, returned only by nodes using protocol v5
+    pub fn get_result_metadata_id(&self) -> Option<&Bytes> {
+        self.result_metadata_id.as_ref()
+    }
+
+    pub(crate) fn set_result_metadata_id(&mut self, result_metadata_id: Option<Bytes>) {
+        self.result_metadata_id = result_metadata_id;
+    }
+
     pub fn get_statement(&self) -> &str {
         &self.statement
     }
 
+    /// Gets the keyspace this statement was prepared in, set with [`Query::set_keyspace`](crate::query::Query::set_keyspace).
+    /// `None` means that it was prepared in the session's keyspace.
+    /// Unlike [`get_keyspace_name`](PreparedStatement::get_keyspace_name) it's known also for statements without bind markers.
+    pub fn get_keyspace(&self) -> Option<&str> {
+        self.keyspace.as_deref()
+    }
+
+    pub(crate) fn set_keyspace(&mut self, keyspace: Option<String>) {
+        self.keyspace = keyspace;
+    }
+
     /// Sets the page size for this CQL query.
     pub fn set_page_size(&mut self, page_size: i32) {
         assert!(page_size > 0, "page size must be larger than 0");
@@ -62,6 +116,20 @@ impl PreparedStatement {
         self.page_size
     }
 
+    /// Sets how many pages [`RowIterator`](crate::transport::iterator::RowIterator)
+    /// fetches in advance when this statement is executed with `*_iter`.
+    /// Higher values keep the pipeline full on high-latency links at the cost of memory.
+    /// With 0 a page is fetched only when the iterator asks for it.
+    pub fn set_prefetch_pages(&mut self, prefetch_pages: usize) {
+        self.config.prefetch_pages = Some(prefetch_pages);
+    }
+
+    /// Gets the number of pages fetched in advance by iterators.
+    /// `None` means that the session's default is used.
+    pub fn get_prefetch_pages(&self) -> Option<usize> {
+        self.config.prefetch_pages
+    }
+
     /// Gets tracing ids of queries used to prepare this statement
     pub fn get_prepare_tracing_ids(&self) -> &[Uuid] {
         &self.prepare_tracing_ids
@@ -75,51 +143,105 @@ impl PreparedStatement {
         bound_values: &SerializedValues,
     ) -> Result<Bytes, PartitionKeyError> {
         let mut buf = BytesMut::new();
+        self.write_partition_key(bound_values, &mut buf)?;
+        Ok(buf.freeze())
+    }
 
-        if self.metadata.pk_indexes.len() == 1 {
-            if let Some(v) = bound_values
-                .iter()
-                .nth(self.metadata.pk_indexes[0] as usize)
-                .ok_or_else(|| {
-                    PartitionKeyError::NoPkIndexValue(
-                        self.metadata.pk_indexes[0],
-                        bound_values.len(),
-                    )
-                })?
-            {
-                buf.extend_from_slice(v);
+    /// Same as [`compute_partition_key`](PreparedStatement::compute_partition_key),
+    /// but writes the partition key into `buf`, which is cleared first.
+    /// Reusing one buffer between calls avoids allocating a new key every time.
+    pub fn write_partition_key(
+        &self,
+        bound_values: &SerializedValues,
+        buf: &mut BytesMut,
+    ) -> Result<(), PartitionKeyError> {
+        buf.clear();
+        let pk_indexes = &self.metadata.pk_indexes;
+
+        let no_value =
+            |pk_index: u16| PartitionKeyError::NoPkIndexValue(pk_index, bound_values.len());
+
+        // Named values can be in any order, each of them is found by the name of its bind marker
+        if bound_values.has_names() {
+            let named_value = |pk_index: u16| {
+                let name = self
+                    .metadata
+                    .col_specs
+                    .get(pk_index as usize)
+                    .map(|spec| spec.name());
+                bound_values
+                    .iter_name_value_pairs()
+                    .find(|(value_name, _)| *value_name == name)
+                    .map(|(_, value)| value)
+                    .ok_or_else(|| no_value(pk_index))
+            };
+
+            if let [pk_index] = pk_indexes[..] {
+                let value =
+                    named_value(pk_index)?.ok_or(PartitionKeyError::UnboundPkValue(pk_index))?;
+                buf.extend_from_slice(value);
+            } else {
+                for &pk_index in pk_indexes {
+                    write_pk_component(pk_index, named_value(pk_index)?, buf)?;
+                }
             }
-            return Ok(buf.into());
+            return Ok(());
+        }
+
+        if pk_indexes.len() == 1 {
+            let pk_index = pk_indexes[0];
+            let value = bound_values
+                .iter()
+                .nth(pk_index as usize)
+                .ok_or_else(|| no_value(pk_index))?
+                .ok_or(PartitionKeyError::UnboundPkValue(pk_index))?;
+            buf.extend_from_slice(value);
+            return Ok(());
         }
-        // TODO: consider what happens if a prepared statement is of type (?, something, ?),
-        // where all three parameters form a partition key. The middle one is not available
-        // in bound values.
 
-        // TODO: Optimize - maybe we could check if pk_indexes are sorted and do an allocation-free two-pointer sweep algorithm then?
+        // pk_indexes are ordered as the partition key columns, not as the bound values.
         // We can't just sort them because the hash will break:
         // https://github.com/apache/cassandra/blob/caeecf6456b87886a79f47a2954788e6c856697c/doc/native_protocol_v4.spec#L673
-
-        let values: Vec<Option<&[u8]>> = bound_values.iter().collect();
-        for pk_index in &self.metadata.pk_indexes {
-            // Find value matching current pk_index
-            let next_val: &Option<&[u8]> = values
-                .get(*pk_index as usize)
-                .ok_or_else(|| PartitionKeyError::NoPkIndexValue(*pk_index, bound_values.len()))?;
-
-            // Add value's bytes
-            if let Some(v) = next_val {
-                let v_len_u16: u16 = v
-                    .len()
-                    .try_into()
-                    .map_err(|_| PartitionKeyError::ValueTooLong(v.len()))?;
-
-                buf.put_u16(v_len_u16);
-                buf.extend_from_slice(v);
-                buf.put_u8(0);
+        if pk_indexes.windows(2).all(|w| w[0] < w[1]) {
+            // Both orders agree, so a single pass over the values is enough
+            let mut values = bound_values.iter().enumerate();
+            for &pk_index in pk_indexes {
+                let value = values
+                    .find(|(i, _)| *i == pk_index as usize)
+                    .ok_or_else(|| no_value(pk_index))?
+                    .1;
+                write_pk_component(pk_index, value, buf)?;
+            }
+        } else {
+            // Statements list only a few values, so looking each one up from the start
+            // is cheaper than collecting them all
+            for &pk_index in pk_indexes {
+                let value = bound_values
+                    .iter()
+                    .nth(pk_index as usize)
+                    .ok_or_else(|| no_value(pk_index))?;
+                write_pk_component(pk_index, value, buf)?;
             }
         }
 
-        Ok(buf.into())
+        Ok(())
+    }
+
+    /// Returns metadata of the statement's bind markers, including their names and types
+    pub fn get_prepared_metadata(&self) -> &PreparedMetadata {
+        &self.metadata
+    }
+
+    /// Tells whether the database confirmed that this statement is a lightweight transaction,
+    /// e.g. `INSERT ... IF NOT EXISTS`.  
+    /// Only Scylla reports it, such statements are sent to replicas in ring order,
+    /// so that concurrent transactions on the same partition don't contend on different coordinators.
+    pub fn is_confirmed_lwt(&self) -> bool {
+        self.is_confirmed_lwt
+    }
+
+    pub(crate) fn set_is_confirmed_lwt(&mut self, is_confirmed_lwt: bool) {
+        self.is_confirmed_lwt = is_confirmed_lwt;
     }
 
     /// Returns the name of the keyspace this statement is operating on.
@@ -130,28 +252,76 @@ impl PreparedStatement {
             .map(|col_spec| col_spec.table_spec.ks_name.as_str())
     }
 
-    /// Sets the consistency to be used when executing this batch.
+    /// Returns the name of the table this statement is operating on,
+    /// known only if the statement has bind markers.
+    pub fn get_table_name(&self) -> Option<&str> {
+        self.metadata
+            .col_specs
+            .first()
+            .map(|col_spec| col_spec.table_spec.table_name())
+    }
+
+    /// Sets the partitioner used to compute tokens of partition keys.  
+    /// `Session::prepare` sets the partitioner of the statement's table,
+    /// known from the schema metadata fetched by the session.
+    pub fn set_partitioner(&mut self, partitioner: Partitioner) {
+        self.partitioner = partitioner;
+    }
+
+    /// Gets the partitioner used to compute tokens of partition keys
+    pub fn get_partitioner(&self) -> Partitioner {
+        self.partitioner
+    }
+
+    /// Sets the consistency to be used when executing this statement.
     pub fn set_consistency(&mut self, c: Consistency) {
-        self.config.consistency = c;
+        self.config.consistency = Some(c);
     }
 
-    /// Gets the consistency to be used when executing this batch.
-    pub fn get_consistency(&self) -> Consistency {
+    /// Gets the consistency to be used when executing this statement.
+    /// `None` means that the session's default consistency is used.
+    pub fn get_consistency(&self) -> Option<Consistency> {
         self.config.consistency
     }
 
-    /// Sets the serial consistency to be used when executing this batch.
-    /// (Ignored unless the batch is an LWT)
+    /// Sets the serial consistency to be used when executing this statement.
+    /// (Ignored unless the statement is an LWT)
     pub fn set_serial_consistency(&mut self, sc: Option<Consistency>) {
         self.config.serial_consistency = sc;
     }
 
-    /// Gets the serial consistency to be used when executing this batch.
-    /// (Ignored unless the batch is an LWT)
+    /// Gets the serial consistency to be used when executing this statement.
+    /// (Ignored unless the statement is an LWT)
+    /// `None` means that the session's default serial consistency is used.
     pub fn get_serial_consistency(&self) -> Option<Consistency> {
         self.config.serial_consistency
     }
 
+    /// Sets the timestamp of this statement, in microseconds since unix epoch.
+    /// It's used as the write timestamp, like `USING TIMESTAMP` in the statement text.
+    /// With `None` the session's timestamp generator is used.
+    pub fn set_timestamp(&mut self, timestamp: Option<i64>) {
+        self.config.timestamp = timestamp;
+    }
+
+    /// Gets the timestamp of this statement.
+    /// `None` means that the session's timestamp generator is used.
+    pub fn get_timestamp(&self) -> Option<i64> {
+        self.config.timestamp
+    }
+
+    /// Sets the time after which executing this statement fails with a timeout error.
+    /// With `None` the session's default request timeout is used.
+    pub fn set_request_timeout(&mut self, timeout: Option<Duration>) {
+        self.config.request_timeout = timeout;
+    }
+
+    /// Gets the request timeout of this statement.
+    /// `None` means that the session's default request timeout is used.
+    pub fn get_request_timeout(&self) -> Option<Duration> {
+        self.config.request_timeout
+    }
+
     /// Sets the idempotence of this statement
     /// A query is idempotent if it can be applied multiple times without changing the result of the initial application
     /// If set to `true` we can be sure that it is idempotent
@@ -168,17 +338,17 @@ impl PreparedStatement {
 
     /// Sets a custom [`RetryPolicy`] to be used with this statement
     /// By default Session's retry policy is used, this allows to use a custom retry policy
-    pub fn set_retry_policy(&mut self, retry_policy: Box<dyn RetryPolicy>) {
+    pub fn set_retry_policy(&mut self, retry_policy: Arc<dyn RetryPolicy>) {
         self.config.retry_policy = Some(retry_policy);
     }
 
     /// Gets custom [`RetryPolicy`] used by this statement
-    pub fn get_retry_policy(&self) -> &Option<Box<dyn RetryPolicy>> {
+    pub fn get_retry_policy(&self) -> &Option<Arc<dyn RetryPolicy>> {
         &self.config.retry_policy
     }
 
     /// Enable or disable CQL Tracing for this batch
-    /// If enabled session.batch() will return a BatchResult containing tracing_id
+    /// If enabled session.batch() will return a QueryResult containing tracing_id
     /// which can be used to query tracing information about the execution of this query
     pub fn set_tracing(&mut self, should_trace: bool) {
         self.config.tracing = should_trace;
@@ -196,4 +366,167 @@ pub enum PartitionKeyError {
     NoPkIndexValue(u16, i16),
     #[error("Value bytes too long to create partition key, max 65 535 allowed! value.len(): {0}")]
     ValueTooLong(usize),
+    #[error("Partition key value bound at index {0} is null or unset, partition key must be fully bound")]
+    UnboundPkValue(u16),
+}
+
+// Writes one component of a composite partition key: <len: u16><value><0>
+fn write_pk_component(
+    pk_index: u16,
+    value: Option<&[u8]>,
+    buf: &mut BytesMut,
+) -> Result<(), PartitionKeyError> {
+    let value = value.ok_or(PartitionKeyError::UnboundPkValue(pk_index))?;
+    let value_len: u16 = value
+        .len()
+        .try_into()
+        .map_err(|_| PartitionKeyError::ValueTooLong(value.len()))?;
+
+    buf.put_u16(value_len);
+    buf.extend_from_slice(value);
+    buf.put_u8(0);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PartitionKeyError, PreparedStatement};
+    use crate::frame::response::result::{ColumnSpec, ColumnType, PreparedMetadata};
+    use crate::frame::value::{SerializedValues, ValueList};
+    use bytes::{Bytes, BytesMut};
+    use std::net::SocketAddr;
+
+    fn statement_with_pk_indexes(pk_indexes: Vec<u16>) -> PreparedStatement {
+        let metadata = PreparedMetadata {
+            flags: 0,
+            col_count: 0,
+            pk_indexes,
+            col_specs: Vec::new(),
+        };
+        PreparedStatement::new(Bytes::new(), metadata, String::new(), None)
+    }
+
+    fn serialize(values: impl ValueList) -> SerializedValues {
+        values.serialized().unwrap().into_owned()
+    }
+
+    #[test]
+    fn ids_for_nodes() {
+        let mut stmt = statement_with_pk_indexes(vec![]);
+        let default_id = stmt.get_id().clone();
+        let node1: SocketAddr = "127.0.0.1:9042".parse().unwrap();
+        let node2: SocketAddr = "127.0.0.2:9042".parse().unwrap();
+        let other_id = Bytes::from_static(&[1, 2, 3]);
+
+        stmt.set_id_for_node(node1, other_id.clone());
+        let cloned = stmt.clone();
+        assert_eq!(cloned.get_id_for_node(node1), &other_id);
+        assert_eq!(cloned.get_id_for_node(node2), &default_id);
+        assert_eq!(cloned.get_id(), &default_id);
+
+        // Setting the default id back removes the node's id, clones keep theirs
+        stmt.set_id_for_node(node1, default_id.clone());
+        assert_eq!(stmt.get_id_for_node(node1), &default_id);
+        assert_eq!(cloned.get_id_for_node(node1), &other_id);
+    }
+
+    #[test]
+    fn single_column_partition_key() {
+        let stmt = statement_with_pk_indexes(vec![1]);
+        let values = serialize((1_i8, 2_i8, 3_i8));
+
+        assert_eq!(stmt.compute_partition_key(&values).unwrap(), vec![2]);
+    }
+
+    #[test]
+    fn composite_partition_key() {
+        let values = serialize((1_i8, 2_i16, 3_i8));
+
+        // Partition key columns bound in the same order as they are declared
+        let sorted = statement_with_pk_indexes(vec![0, 2]);
+        assert_eq!(
+            sorted.compute_partition_key(&values).unwrap(),
+            vec![0, 1, 1, 0, 0, 1, 3, 0]
+        );
+
+        // The order of components must follow pk_indexes, not the order of values
+        let unsorted = statement_with_pk_indexes(vec![2, 1, 0]);
+        assert_eq!(
+            unsorted.compute_partition_key(&values).unwrap(),
+            vec![0, 1, 3, 0, 0, 2, 0, 2, 0, 0, 1, 1, 0]
+        );
+    }
+
+    #[test]
+    fn write_partition_key_reuses_buffer() {
+        let stmt = statement_with_pk_indexes(vec![0, 1]);
+        let mut buf = BytesMut::new();
+
+        stmt.write_partition_key(&serialize((1_i8, 2_i8)), &mut buf)
+            .unwrap();
+        assert_eq!(&buf[..], &[0, 1, 1, 0, 0, 1, 2, 0]);
+
+        stmt.write_partition_key(&serialize((3_i8, 4_i8)), &mut buf)
+            .unwrap();
+        assert_eq!(&buf[..], &[0, 1, 3, 0, 0, 1, 4, 0]);
+    }
+
+    #[test]
+    fn named_values_partition_key() {
+        let metadata = PreparedMetadata {
+            flags: 0,
+            col_count: 3,
+            pk_indexes: vec![2, 0],
+            col_specs: ["a", "b", "c"]
+                .iter()
+                .map(|name| ColumnSpec::new(name, ColumnType::TinyInt))
+                .collect(),
+        };
+        let stmt = PreparedStatement::new(Bytes::new(), metadata, String::new(), None);
+
+        // Values are found by names, regardless of their order
+        let mut values = SerializedValues::new();
+        values.add_named_value("b", &2_i8).unwrap();
+        values.add_named_value("a", &1_i8).unwrap();
+        values.add_named_value("c", &3_i8).unwrap();
+        assert_eq!(
+            stmt.compute_partition_key(&values).unwrap(),
+            vec![0, 1, 3, 0, 0, 1, 1, 0]
+        );
+
+        let mut values = SerializedValues::new();
+        values.add_named_value("a", &1_i8).unwrap();
+        assert_eq!(
+            stmt.compute_partition_key(&values),
+            Err(PartitionKeyError::NoPkIndexValue(2, 1))
+        );
+    }
+
+    #[test]
+    fn partition_key_errors() {
+        let values = serialize((1_i8, None::<i8>, 3_i8));
+
+        for pk_indexes in [vec![1], vec![0, 1, 2], vec![2, 1]].iter() {
+            let stmt = statement_with_pk_indexes(pk_indexes.clone());
+            assert_eq!(
+                stmt.compute_partition_key(&values),
+                Err(PartitionKeyError::UnboundPkValue(1))
+            );
+        }
+
+        for pk_indexes in [vec![3], vec![0, 3], vec![3, 0]].iter() {
+            let stmt = statement_with_pk_indexes(pk_indexes.clone());
+            assert_eq!(
+                stmt.compute_partition_key(&values),
+                Err(PartitionKeyError::NoPkIndexValue(3, 3))
+            );
+        }
+
+        let long_value = vec![0_u8; 70000];
+        let stmt = statement_with_pk_indexes(vec![0, 1]);
+        assert_eq!(
+            stmt.compute_partition_key(&serialize((long_value, 1_i8))),
+            Err(PartitionKeyError::ValueTooLong(70000))
+        );
+    }
 }
@@ -1,6 +1,8 @@
 use super::StatementConfig;
 use crate::frame::types::Consistency;
 use crate::transport::retry_policy::RetryPolicy;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// CQL query statement.
 ///
@@ -11,6 +13,7 @@ pub struct Query {
 
     contents: String,
     page_size: Option<i32>,
+    keyspace: Option<String>,
 }
 
 impl Query {
@@ -19,6 +22,7 @@ impl Query {
         Self {
             contents,
             page_size: None,
+            keyspace: None,
             config: Default::default(),
         }
     }
@@ -50,28 +54,85 @@ impl Query {
         self.page_size
     }
 
-    /// Sets the consistency to be used when executing this batch.
+    /// Sets the keyspace of unqualified table names in this query, instead of the session's keyspace
+    /// set with [`use_keyspace`](crate::Session::use_keyspace).
+    /// A statement prepared from this query is prepared in this keyspace.
+    ///
+    /// The name is case sensitive and shouldn't be quoted.
+    /// Requires protocol v5, see [`ProtocolVersion`](crate::transport::ProtocolVersion),
+    /// with v4 executing the query fails. The keyspace isn't used when the query is a part of a batch.
+    pub fn set_keyspace(&mut self, keyspace: Option<String>) {
+        self.keyspace = keyspace;
+    }
+
+    /// Gets the keyspace of this query, `None` means that the session's keyspace is used.
+    pub fn get_keyspace(&self) -> Option<&str> {
+        self.keyspace.as_deref()
+    }
+
+    /// Sets how many pages [`RowIterator`](crate::transport::iterator::RowIterator)
+    /// fetches in advance when this statement is executed with `*_iter`.
+    /// Higher values keep the pipeline full on high-latency links at the cost of memory.
+    /// With 0 a page is fetched only when the iterator asks for it.
+    pub fn set_prefetch_pages(&mut self, prefetch_pages: usize) {
+        self.config.prefetch_pages = Some(prefetch_pages);
+    }
+
+    /// Gets the number of pages fetched in advance by iterators.
+    /// `None` means that the session's default is used.
+    pub fn get_prefetch_pages(&self) -> Option<usize> {
+        self.config.prefetch_pages
+    }
+
+    /// Sets the consistency to be used when executing this query.
     pub fn set_consistency(&mut self, c: Consistency) {
-        self.config.consistency = c;
+        self.config.consistency = Some(c);
     }
 
-    /// Gets the consistency to be used when executing this batch.
-    pub fn get_consistency(&self) -> Consistency {
+    /// Gets the consistency to be used when executing this query.
+    /// `None` means that the session's default consistency is used.
+    pub fn get_consistency(&self) -> Option<Consistency> {
         self.config.consistency
     }
 
-    /// Sets the serial consistency to be used when executing this batch.
-    /// (Ignored unless the batch is an LWT)
+    /// Sets the serial consistency to be used when executing this query.
+    /// (Ignored unless the query is an LWT)
     pub fn set_serial_consistency(&mut self, sc: Option<Consistency>) {
         self.config.serial_consistency = sc;
     }
 
-    /// Gets the serial consistency to be used when executing this batch.
-    /// (Ignored unless the batch is an LWT)
+    /// Gets the serial consistency to be used when executing this query.
+    /// (Ignored unless the query is an LWT)
+    /// `None` means that the session's default serial consistency is used.
     pub fn get_serial_consistency(&self) -> Option<Consistency> {
         self.config.serial_consistency
     }
 
+    /// Sets the timestamp of this query, in microseconds since unix epoch.
+    /// It's used as the write timestamp, like `USING TIMESTAMP` in the statement text.
+    /// With `None` the session's timestamp generator is used.
+    pub fn set_timestamp(&mut self, timestamp: Option<i64>) {
+        self.config.timestamp = timestamp;
+    }
+
+    /// Gets the timestamp of this query.
+    /// `None` means that the session's timestamp generator is used.
+    pub fn get_timestamp(&self) -> Option<i64> {
+        self.config.timestamp
+    }
+
+    /// Sets the time after which executing this query fails with a timeout error.
+    /// With `None` the session's default request timeout is used.
+    pub fn set_request_timeout(&mut self, timeout: Option<Duration>) {
+        self.config.request_timeout = timeout;
+    }
+
+    /// Gets the request timeout of this query.
+    /// `None` means that the session's default request timeout is used.
+    pub fn get_request_timeout(&self) -> Option<Duration> {
+        self.config.request_timeout
+    }
+
     /// Sets the idempotence of this statement
     /// A query is idempotent if it can be applied multiple times without changing the result of the initial application
     /// If set to `true` we can be sure that it is idempotent
@@ -88,17 +149,17 @@ impl Query {
 
     /// Sets a custom [`RetryPolicy`] to be used with this statement
     /// By default Session's retry policy is used, this allows to use a custom retry policy
-    pub fn set_retry_policy(&mut self, retry_policy: Box<dyn RetryPolicy>) {
+    pub fn set_retry_policy(&mut self, retry_policy: Arc<dyn RetryPolicy>) {
         self.config.retry_policy = Some(retry_policy);
     }
 
     /// Gets custom [`RetryPolicy`] used by this statement
-    pub fn get_retry_policy(&self) -> &Option<Box<dyn RetryPolicy>> {
+    pub fn get_retry_policy(&self) -> &Option<Arc<dyn RetryPolicy>> {
         &self.config.retry_policy
     }
 
     /// Enable or disable CQL Tracing for this batch
-    /// If enabled session.batch() will return a BatchResult containing tracing_id
+    /// If enabled session.batch() will return a QueryResult containing tracing_id
     /// which can be used to query tracing information about the execution of this query
     pub fn set_tracing(&mut self, should_trace: bool) {
         self.config.tracing = should_trace;
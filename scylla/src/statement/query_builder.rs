@@ -0,0 +1,637 @@
+//! Builders of `SELECT`, `INSERT`, `UPDATE` and `DELETE` statements
+//!
+//! Values are never written into the statement, they are collected as bound values instead.
+//! Names of tables and columns are quoted when needed, so statements built from
+//! untrusted input can't be turned into a different statement.
+//!
+//! # Example
+//! ```rust
+//! # use scylla::Session;
+//! # use std::error::Error;
+//! # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+//! use scylla::query_builder::{col, select};
+//!
+//! let (query, values) = select("ks.tab")
+//!     .columns(&["a", "b"])
+//!     .and_where(col("a").eq(1))
+//!     .and_where(col("b").gt("x"))
+//!     .limit(10)
+//!     .build()?;
+//! assert_eq!(query.get_contents(), "SELECT a, b FROM ks.tab WHERE a = ? AND b > ? LIMIT ?");
+//!
+//! let result = session.query(query, values).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::frame::value::{SerializeValuesError, SerializedValues, Value};
+use crate::query::Query;
+use crate::statement::quote_identifier;
+
+/// Starts building a `SELECT` statement, `table` can be prefixed with a keyspace name
+pub fn select(table: &str) -> Select {
+    Select {
+        table: table.to_string(),
+        columns: Vec::new(),
+        relations: Vec::new(),
+        order_by: Vec::new(),
+        limit: None,
+        allow_filtering: false,
+    }
+}
+
+/// Starts building an `INSERT` statement, `table` can be prefixed with a keyspace name
+pub fn insert_into(table: &str) -> Insert {
+    Insert {
+        table: table.to_string(),
+        columns: Vec::new(),
+        values: BoundValues::default(),
+        if_not_exists: false,
+        using: Using::default(),
+    }
+}
+
+/// Starts building an `UPDATE` statement, `table` can be prefixed with a keyspace name
+pub fn update(table: &str) -> Update {
+    Update {
+        table: table.to_string(),
+        assignments: Vec::new(),
+        relations: Vec::new(),
+        condition: Condition::None,
+        using: Using::default(),
+    }
+}
+
+/// Starts building a `DELETE` statement, `table` can be prefixed with a keyspace name
+pub fn delete_from(table: &str) -> Delete {
+    Delete {
+        table: table.to_string(),
+        columns: Vec::new(),
+        relations: Vec::new(),
+        condition: Condition::None,
+        timestamp: None,
+    }
+}
+
+/// Refers to a column in `WHERE` and `IF` clauses, e.g. `col("a").eq(1)`
+pub fn col(name: &str) -> Column {
+    Column {
+        name: name.to_string(),
+    }
+}
+
+/// A column which can be compared with a value, see [`col`]
+pub struct Column {
+    name: String,
+}
+
+impl Column {
+    /// `column = value`
+    pub fn eq(self, value: impl Value) -> Relation {
+        self.relation("=", value)
+    }
+
+    /// `column != value`, allowed only in `IF` conditions
+    pub fn ne(self, value: impl Value) -> Relation {
+        self.relation("!=", value)
+    }
+
+    /// `column < value`
+    pub fn lt(self, value: impl Value) -> Relation {
+        self.relation("<", value)
+    }
+
+    /// `column <= value`
+    pub fn le(self, value: impl Value) -> Relation {
+        self.relation("<=", value)
+    }
+
+    /// `column > value`
+    pub fn gt(self, value: impl Value) -> Relation {
+        self.relation(">", value)
+    }
+
+    /// `column >= value`
+    pub fn ge(self, value: impl Value) -> Relation {
+        self.relation(">=", value)
+    }
+
+    /// `column IN (values)`, the values are bound as a single list
+    pub fn is_in<V: Value>(self, values: Vec<V>) -> Relation {
+        self.relation("IN", values)
+    }
+
+    /// `column CONTAINS value`, for collection columns
+    pub fn contains(self, value: impl Value) -> Relation {
+        self.relation("CONTAINS", value)
+    }
+
+    /// `column CONTAINS KEY value`, for map columns
+    pub fn contains_key(self, value: impl Value) -> Relation {
+        self.relation("CONTAINS KEY", value)
+    }
+
+    fn relation(self, operator: &str, value: impl Value) -> Relation {
+        let mut cql = String::new();
+        push_identifier(&mut cql, &self.name);
+        cql.push(' ');
+        cql.push_str(operator);
+        cql.push_str(" ?");
+
+        let mut values = BoundValues::default();
+        values.add(&value);
+        Relation { cql, values }
+    }
+}
+
+/// A comparison of a column with a bound value, created using [`col`]
+pub struct Relation {
+    cql: String,
+    values: BoundValues,
+}
+
+/// Order of rows in `ORDER BY`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+/// Builder of a `SELECT` statement, created using [`select`]
+pub struct Select {
+    table: String,
+    columns: Vec<String>,
+    relations: Vec<Relation>,
+    order_by: Vec<(String, Order)>,
+    limit: Option<i32>,
+    allow_filtering: bool,
+}
+
+impl Select {
+    /// Sets the selected columns, by default all columns are selected
+    pub fn columns(mut self, columns: &[&str]) -> Self {
+        self.columns = columns.iter().map(|column| column.to_string()).collect();
+        self
+    }
+
+    /// Adds a relation to the `WHERE` clause, relations are joined with `AND`
+    pub fn and_where(mut self, relation: Relation) -> Self {
+        self.relations.push(relation);
+        self
+    }
+
+    /// Adds a clustering column to the `ORDER BY` clause
+    pub fn order_by(mut self, column: &str, order: Order) -> Self {
+        self.order_by.push((column.to_string(), order));
+        self
+    }
+
+    /// Sets the maximum number of returned rows
+    pub fn limit(mut self, limit: i32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Adds `ALLOW FILTERING`
+    pub fn allow_filtering(mut self) -> Self {
+        self.allow_filtering = true;
+        self
+    }
+
+    /// Returns the statement and its bound values
+    pub fn build(self) -> Result<(Query, SerializedValues), SerializeValuesError> {
+        let mut cql = String::from("SELECT ");
+        let mut values = BoundValues::default();
+
+        if self.columns.is_empty() {
+            cql.push('*');
+        }
+        push_identifier_list(&mut cql, &self.columns);
+        cql.push_str(" FROM ");
+        push_table(&mut cql, &self.table);
+        push_relations(&mut cql, " WHERE ", &self.relations, &mut values);
+
+        for (i, (column, order)) in self.order_by.iter().enumerate() {
+            cql.push_str(if i == 0 { " ORDER BY " } else { ", " });
+            push_identifier(&mut cql, column);
+            cql.push_str(match order {
+                Order::Asc => " ASC",
+                Order::Desc => " DESC",
+            });
+        }
+        if let Some(limit) = self.limit {
+            cql.push_str(" LIMIT ?");
+            values.add(&limit);
+        }
+        if self.allow_filtering {
+            cql.push_str(" ALLOW FILTERING");
+        }
+
+        values.into_query(cql)
+    }
+}
+
+/// Builder of an `INSERT` statement, created using [`insert_into`]
+pub struct Insert {
+    table: String,
+    columns: Vec<String>,
+    values: BoundValues,
+    if_not_exists: bool,
+    using: Using,
+}
+
+impl Insert {
+    /// Sets the value of a column
+    pub fn value(mut self, column: &str, value: impl Value) -> Self {
+        self.columns.push(column.to_string());
+        self.values.add(&value);
+        self
+    }
+
+    /// Adds `IF NOT EXISTS`, making the insert a lightweight transaction
+    pub fn if_not_exists(mut self) -> Self {
+        self.if_not_exists = true;
+        self
+    }
+
+    /// Sets the time to live of inserted values, in seconds
+    pub fn using_ttl(mut self, ttl: i32) -> Self {
+        self.using.ttl = Some(ttl);
+        self
+    }
+
+    /// Sets the write timestamp, in microseconds since unix epoch
+    pub fn using_timestamp(mut self, timestamp: i64) -> Self {
+        self.using.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Returns the statement and its bound values
+    pub fn build(self) -> Result<(Query, SerializedValues), SerializeValuesError> {
+        let mut cql = String::from("INSERT INTO ");
+        let mut values = self.values;
+
+        push_table(&mut cql, &self.table);
+        cql.push_str(" (");
+        push_identifier_list(&mut cql, &self.columns);
+        cql.push_str(") VALUES (");
+        cql.push_str(&vec!["?"; self.columns.len()].join(", "));
+        cql.push(')');
+        if self.if_not_exists {
+            cql.push_str(" IF NOT EXISTS");
+        }
+        self.using.push(&mut cql, &mut values);
+
+        values.into_query(cql)
+    }
+}
+
+/// Builder of an `UPDATE` statement, created using [`update`]
+pub struct Update {
+    table: String,
+    assignments: Vec<Relation>,
+    relations: Vec<Relation>,
+    condition: Condition,
+    using: Using,
+}
+
+impl Update {
+    /// Sets the value of a column
+    pub fn set(mut self, column: &str, value: impl Value) -> Self {
+        self.assignments.push(col(column).relation("=", value));
+        self
+    }
+
+    /// Adds a relation to the `WHERE` clause, relations are joined with `AND`
+    pub fn and_where(mut self, relation: Relation) -> Self {
+        self.relations.push(relation);
+        self
+    }
+
+    /// Adds `IF EXISTS`, making the update a lightweight transaction
+    pub fn if_exists(mut self) -> Self {
+        self.condition = Condition::Exists;
+        self
+    }
+
+    /// Adds a condition to the `IF` clause, making the update a lightweight transaction
+    pub fn only_if(mut self, relation: Relation) -> Self {
+        self.condition.push(relation);
+        self
+    }
+
+    /// Sets the time to live of updated values, in seconds
+    pub fn using_ttl(mut self, ttl: i32) -> Self {
+        self.using.ttl = Some(ttl);
+        self
+    }
+
+    /// Sets the write timestamp, in microseconds since unix epoch
+    pub fn using_timestamp(mut self, timestamp: i64) -> Self {
+        self.using.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Returns the statement and its bound values
+    pub fn build(self) -> Result<(Query, SerializedValues), SerializeValuesError> {
+        let mut cql = String::from("UPDATE ");
+        let mut values = BoundValues::default();
+
+        push_table(&mut cql, &self.table);
+        self.using.push(&mut cql, &mut values);
+        for (i, assignment) in self.assignments.iter().enumerate() {
+            cql.push_str(if i == 0 { " SET " } else { ", " });
+            cql.push_str(&assignment.cql);
+            values.append(&assignment.values);
+        }
+        push_relations(&mut cql, " WHERE ", &self.relations, &mut values);
+        self.condition.push_to(&mut cql, &mut values);
+
+        values.into_query(cql)
+    }
+}
+
+/// Builder of a `DELETE` statement, created using [`delete_from`]
+pub struct Delete {
+    table: String,
+    columns: Vec<String>,
+    relations: Vec<Relation>,
+    condition: Condition,
+    timestamp: Option<i64>,
+}
+
+impl Delete {
+    /// Sets the deleted columns, by default whole rows are deleted
+    pub fn columns(mut self, columns: &[&str]) -> Self {
+        self.columns = columns.iter().map(|column| column.to_string()).collect();
+        self
+    }
+
+    /// Adds a relation to the `WHERE` clause, relations are joined with `AND`
+    pub fn and_where(mut self, relation: Relation) -> Self {
+        self.relations.push(relation);
+        self
+    }
+
+    /// Adds `IF EXISTS`, making the delete a lightweight transaction
+    pub fn if_exists(mut self) -> Self {
+        self.condition = Condition::Exists;
+        self
+    }
+
+    /// Adds a condition to the `IF` clause, making the delete a lightweight transaction
+    pub fn only_if(mut self, relation: Relation) -> Self {
+        self.condition.push(relation);
+        self
+    }
+
+    /// Sets the write timestamp, in microseconds since unix epoch
+    pub fn using_timestamp(mut self, timestamp: i64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Returns the statement and its bound values
+    pub fn build(self) -> Result<(Query, SerializedValues), SerializeValuesError> {
+        let mut cql = String::from("DELETE ");
+        let mut values = BoundValues::default();
+
+        if !self.columns.is_empty() {
+            push_identifier_list(&mut cql, &self.columns);
+            cql.push(' ');
+        }
+        cql.push_str("FROM ");
+        push_table(&mut cql, &self.table);
+        if let Some(timestamp) = self.timestamp {
+            cql.push_str(" USING TIMESTAMP ?");
+            values.add(&timestamp);
+        }
+        push_relations(&mut cql, " WHERE ", &self.relations, &mut values);
+        self.condition.push_to(&mut cql, &mut values);
+
+        values.into_query(cql)
+    }
+}
+
+// The IF clause of UPDATE and DELETE
+enum Condition {
+    None,
+    Exists,
+    Relations(Vec<Relation>),
+}
+
+impl Condition {
+    fn push(&mut self, relation: Relation) {
+        match self {
+            Condition::Relations(relations) => relations.push(relation),
+            _ => *self = Condition::Relations(vec![relation]),
+        }
+    }
+
+    fn push_to(&self, cql: &mut String, values: &mut BoundValues) {
+        match self {
+            Condition::None => {}
+            Condition::Exists => cql.push_str(" IF EXISTS"),
+            Condition::Relations(relations) => push_relations(cql, " IF ", relations, values),
+        }
+    }
+}
+
+// The USING clause of INSERT and UPDATE
+#[derive(Default)]
+struct Using {
+    ttl: Option<i32>,
+    timestamp: Option<i64>,
+}
+
+impl Using {
+    fn push(&self, cql: &mut String, values: &mut BoundValues) {
+        if let Some(ttl) = self.ttl {
+            cql.push_str(" USING TTL ?");
+            values.add(&ttl);
+        }
+        if let Some(timestamp) = self.timestamp {
+            cql.push_str(if self.ttl.is_some() {
+                " AND TIMESTAMP ?"
+            } else {
+                " USING TIMESTAMP ?"
+            });
+            values.add(&timestamp);
+        }
+    }
+}
+
+// Values collected while building, the first serialization error is reported by build()
+struct BoundValues {
+    values: SerializedValues,
+    error: Option<SerializeValuesError>,
+}
+
+impl Default for BoundValues {
+    fn default() -> Self {
+        BoundValues {
+            values: SerializedValues::new(),
+            error: None,
+        }
+    }
+}
+
+impl BoundValues {
+    fn add(&mut self, value: &impl Value) {
+        if self.error.is_none() {
+            self.error = self.values.add_value(value).err();
+        }
+    }
+
+    fn append(&mut self, other: &BoundValues) {
+        if self.error.is_none() {
+            self.error = other
+                .error
+                .or_else(|| self.values.append(&other.values).err());
+        }
+    }
+
+    fn into_query(self, cql: String) -> Result<(Query, SerializedValues), SerializeValuesError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok((Query::new(cql), self.values)),
+        }
+    }
+}
+
+fn push_relations(
+    cql: &mut String,
+    keyword: &str,
+    relations: &[Relation],
+    values: &mut BoundValues,
+) {
+    for (i, relation) in relations.iter().enumerate() {
+        cql.push_str(if i == 0 { keyword } else { " AND " });
+        cql.push_str(&relation.cql);
+        values.append(&relation.values);
+    }
+}
+
+// Writes keyspace.table or table, each part is quoted when needed
+fn push_table(cql: &mut String, table: &str) {
+    match table.split_once('.') {
+        Some((keyspace, table)) => {
+            push_identifier(cql, keyspace);
+            cql.push('.');
+            push_identifier(cql, table);
+        }
+        None => push_identifier(cql, table),
+    }
+}
+
+fn push_identifier_list(cql: &mut String, identifiers: &[String]) {
+    for (i, identifier) in identifiers.iter().enumerate() {
+        if i > 0 {
+            cql.push_str(", ");
+        }
+        push_identifier(cql, identifier);
+    }
+}
+
+fn push_identifier(cql: &mut String, identifier: &str) {
+    cql.push_str(&quote_identifier(identifier));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{col, delete_from, insert_into, select, update, Order};
+    use crate::frame::value::SerializedValues;
+
+    macro_rules! values {
+        ($($value:expr),*) => {{
+            let mut values = SerializedValues::new();
+            $(values.add_value(&$value).unwrap();)*
+            values
+        }};
+    }
+
+    #[test]
+    fn build_select() {
+        let (query, bound) = select("ks.tab")
+            .and_where(col("a").eq(1))
+            .and_where(col("b").is_in(vec![2, 3]))
+            .order_by("b", Order::Desc)
+            .limit(10)
+            .allow_filtering()
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            query.get_contents(),
+            "SELECT * FROM ks.tab WHERE a = ? AND b IN ? ORDER BY b DESC LIMIT ? ALLOW FILTERING"
+        );
+        assert_eq!(bound, values![1, vec![2, 3], 10]);
+    }
+
+    #[test]
+    fn build_insert() {
+        let (query, bound) = insert_into("tab")
+            .value("a", 1)
+            .value("b", "x")
+            .if_not_exists()
+            .using_timestamp(5)
+            .using_ttl(60)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            query.get_contents(),
+            "INSERT INTO tab (a, b) VALUES (?, ?) IF NOT EXISTS USING TTL ? AND TIMESTAMP ?"
+        );
+        assert_eq!(bound, values![1, "x", 60, 5_i64]);
+    }
+
+    #[test]
+    fn build_update() {
+        // Values are bound in the order of the statement, not of builder calls
+        let (query, bound) = update("ks.tab")
+            .and_where(col("a").eq(1))
+            .only_if(col("c").ne(3))
+            .set("b", "x")
+            .set("c", 4)
+            .using_ttl(60)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            query.get_contents(),
+            "UPDATE ks.tab USING TTL ? SET b = ?, c = ? WHERE a = ? IF c != ?"
+        );
+        assert_eq!(bound, values![60, "x", 4, 1, 3]);
+    }
+
+    #[test]
+    fn build_delete() {
+        let (query, bound) = delete_from("tab")
+            .columns(&["b", "c"])
+            .and_where(col("a").eq(1))
+            .if_exists()
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            query.get_contents(),
+            "DELETE b, c FROM tab WHERE a = ? IF EXISTS"
+        );
+        assert_eq!(bound, values![1]);
+    }
+
+    #[test]
+    fn identifiers_are_quoted() {
+        let (query, _) = select("Ks.tab; DROP TABLE x")
+            .columns(&["a", "B", "c\"d", "order"])
+            .and_where(col("1a").contains_key(1))
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            query.get_contents(),
+            "SELECT a, \"B\", \"c\"\"d\", \"order\" FROM \"Ks\".\"tab; DROP TABLE x\" WHERE \"1a\" CONTAINS KEY ?"
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Translation of node addresses found in `system.peers` to addresses the driver connects to
+//!
+//! Nodes often advertise internal addresses which clients can't reach,
+//! e.g. behind a NAT or in Kubernetes. A translator maps them to reachable ones.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+
+/// Translates addresses of nodes before the driver connects to them.
+///
+/// The address passed to `translate` is the `rpc_address` of a node from `system.peers`
+/// with the port the driver would use to connect to it.
+/// The node the control connection is connected to isn't translated,
+/// its address is already reachable.
+pub trait AddressTranslator: Send + Sync {
+    /// Returns the address the driver should connect to
+    fn translate(&self, address: SocketAddr) -> SocketAddr;
+}
+
+/// Connects to addresses advertised by the nodes, this is the default
+#[derive(Debug, Default, Clone)]
+pub struct IdentityTranslator;
+
+impl AddressTranslator for IdentityTranslator {
+    fn translate(&self, address: SocketAddr) -> SocketAddr {
+        address
+    }
+}
+
+/// Translates IP addresses advertised by the nodes using a fixed map.
+/// Addresses missing in the map aren't translated.
+///
+/// # Example
+/// ```
+/// use scylla::transport::address_translator::MapAddressTranslator;
+/// use std::net::{IpAddr, SocketAddr};
+///
+/// let internal: IpAddr = "10.0.0.1".parse().unwrap();
+/// let external: SocketAddr = "203.0.113.1:9042".parse().unwrap();
+/// let translator = MapAddressTranslator::new(vec![(internal, external)]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct MapAddressTranslator {
+    addresses: HashMap<IpAddr, SocketAddr>,
+}
+
+impl MapAddressTranslator {
+    /// Creates a translator replacing each internal IP address with the given address.
+    /// Ports of translated addresses are taken from the map, so that each node
+    /// can be exposed at a different port.
+    pub fn new(addresses: impl IntoIterator<Item = (IpAddr, SocketAddr)>) -> Self {
+        MapAddressTranslator {
+            addresses: addresses.into_iter().collect(),
+        }
+    }
+}
+
+impl AddressTranslator for MapAddressTranslator {
+    fn translate(&self, address: SocketAddr) -> SocketAddr {
+        self.addresses
+            .get(&address.ip())
+            .copied()
+            .unwrap_or(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AddressTranslator, IdentityTranslator, MapAddressTranslator};
+    use std::net::SocketAddr;
+
+    #[test]
+    fn map_translator() {
+        let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+        let translator = MapAddressTranslator::new(vec![
+            (addr("10.0.0.1:0").ip(), addr("203.0.113.1:9042")),
+            (addr("10.0.0.2:0").ip(), addr("203.0.113.1:9043")),
+        ]);
+
+        assert_eq!(
+            translator.translate(addr("10.0.0.1:9042")),
+            addr("203.0.113.1:9042")
+        );
+        assert_eq!(
+            translator.translate(addr("10.0.0.2:19042")),
+            addr("203.0.113.1:9043")
+        );
+        assert_eq!(
+            translator.translate(addr("10.0.0.3:9042")),
+            addr("10.0.0.3:9042")
+        );
+        assert_eq!(
+            IdentityTranslator.translate(addr("10.0.0.1:9042")),
+            addr("10.0.0.1:9042")
+        );
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn peers_are_translated() {
+        use crate::frame::response::result::{ColumnType, CqlValue};
+        use crate::proxy::{MockNode, MockResponse, MockRule};
+        use crate::SessionBuilder;
+        use std::net::IpAddr;
+        use std::sync::Arc;
+
+        let peer = MockNode::new().run().await.unwrap();
+
+        // The peer advertises an address which isn't reachable
+        let internal: IpAddr = "10.255.255.1".parse().unwrap();
+        let text = |value: &str| Some(CqlValue::Text(value.to_string()));
+        let peers = MockResponse::rows(
+            &[
+                ("peer", ColumnType::Inet),
+                ("rpc_address", ColumnType::Inet),
+                ("data_center", ColumnType::Text),
+                ("rack", ColumnType::Text),
+                ("tokens", ColumnType::Set(Box::new(ColumnType::Text))),
+                ("host_id", ColumnType::Uuid),
+            ],
+            vec![vec![
+                Some(CqlValue::Inet(internal)),
+                Some(CqlValue::Inet(internal)),
+                text("datacenter1"),
+                text("rack1"),
+                Some(CqlValue::Set(vec![CqlValue::Text("100".to_string())])),
+                Some(CqlValue::Uuid(uuid::Uuid::from_u128(2))),
+            ]],
+        );
+        let node = MockNode::new()
+            .rule(MockRule::new("FROM system.peers", peers))
+            .run()
+            .await
+            .unwrap();
+
+        let session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .address_translator(Arc::new(MapAddressTranslator::new(vec![(
+                internal,
+                peer.addr(),
+            )])))
+            .build()
+            .await
+            .unwrap();
+
+        let mut addresses: Vec<SocketAddr> = session
+            .get_cluster_data()
+            .all_nodes
+            .iter()
+            .map(|node| node.address)
+            .collect();
+        addresses.sort_unstable();
+        let mut expected = vec![node.addr(), peer.addr()];
+        expected.sort_unstable();
+        assert_eq!(addresses, expected);
+
+        // Queries are sent to both nodes
+        for _ in 0..4 {
+            session.query("SELECT a FROM ks.t", &[]).await.unwrap();
+        }
+        assert!(!peer.received_statements().is_empty());
+
+        node.finish().await.unwrap();
+        peer.finish().await.unwrap();
+    }
+}
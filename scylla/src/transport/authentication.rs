@@ -0,0 +1,288 @@
+//! Authenticating connections with custom SASL mechanisms
+//!
+//! When a node requires authentication, each new connection asks the [`Authenticator`]
+//! set in [`SessionConfig::authenticator`](crate::SessionConfig::authenticator) to start
+//! an [`AuthenticatorSession`], which exchanges tokens with the node until it accepts
+//! or rejects the credentials. Password authentication set with
+//! [`SessionBuilder::user`](crate::SessionBuilder::user) uses [`PlainTextAuthenticator`].
+//!
+//! # Example
+//! ```
+//! # use scylla::{Session, SessionBuilder};
+//! use futures::future::BoxFuture;
+//! use scylla::authentication::{
+//!     AuthError, AuthInitialResponseAndSession, Authenticator, AuthenticatorSession,
+//! };
+//! use std::sync::Arc;
+//!
+//! // Sends a token obtained from an external service, e.g. a Kerberos ticket
+//! struct TokenAuthenticator;
+//!
+//! struct TokenSession;
+//!
+//! impl Authenticator for TokenAuthenticator {
+//!     fn start_session<'a>(
+//!         &'a self,
+//!         _authenticator_name: &'a str,
+//!     ) -> BoxFuture<'a, Result<AuthInitialResponseAndSession, AuthError>> {
+//!         Box::pin(async move {
+//!             let token = b"initial token".to_vec();
+//!             Ok((Some(token), Box::new(TokenSession) as Box<dyn AuthenticatorSession>))
+//!         })
+//!     }
+//! }
+//!
+//! impl AuthenticatorSession for TokenSession {
+//!     fn evaluate_challenge<'a>(
+//!         &'a mut self,
+//!         challenge: Option<&'a [u8]>,
+//!     ) -> BoxFuture<'a, Result<Option<Vec<u8>>, AuthError>> {
+//!         Box::pin(async move {
+//!             match challenge {
+//!                 Some(challenge) => Ok(Some(challenge.iter().rev().cloned().collect())),
+//!                 None => Err("Expected a challenge".to_string()),
+//!             }
+//!         })
+//!     }
+//! }
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let session: Session = SessionBuilder::new()
+//!     .known_node("127.0.0.1:9042")
+//!     .authenticator(Arc::new(TokenAuthenticator))
+//!     .build()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use futures::future::BoxFuture;
+
+/// Reason of a failed authentication, reported as
+/// [`QueryError::AuthenticationError`](crate::transport::errors::QueryError::AuthenticationError)
+pub type AuthError = String;
+
+/// Initial token sent to the node and the session handling further challenges
+pub type AuthInitialResponseAndSession = (Option<Vec<u8>>, Box<dyn AuthenticatorSession>);
+
+/// Creates an [`AuthenticatorSession`] for every connection to a node which requires authentication
+pub trait Authenticator: Send + Sync {
+    /// Called after the node sent `AUTHENTICATE` with the name of its authenticator,
+    /// e.g. `org.apache.cassandra.auth.PasswordAuthenticator`.
+    fn start_session<'a>(
+        &'a self,
+        authenticator_name: &'a str,
+    ) -> BoxFuture<'a, Result<AuthInitialResponseAndSession, AuthError>>;
+}
+
+/// Authentication of a single connection
+pub trait AuthenticatorSession: Send + Sync {
+    /// Called for every `AUTH_CHALLENGE` sent by the node, returns the token sent back to it
+    fn evaluate_challenge<'a>(
+        &'a mut self,
+        challenge: Option<&'a [u8]>,
+    ) -> BoxFuture<'a, Result<Option<Vec<u8>>, AuthError>>;
+
+    /// Called when the node accepted the credentials, with the final token sent in `AUTH_SUCCESS`.
+    /// Returning an error closes the connection
+    fn success<'a>(&'a mut self, _token: Option<&'a [u8]>) -> BoxFuture<'a, Result<(), AuthError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Authenticates with a username and password using SASL `PLAIN`,
+/// accepted by `PasswordAuthenticator` and `TransitionalAuthenticator`
+#[derive(Debug, Clone)]
+pub struct PlainTextAuthenticator {
+    username: String,
+    password: String,
+}
+
+impl PlainTextAuthenticator {
+    pub fn new(username: impl Into<String>, password: impl Into<String>) -> Self {
+        PlainTextAuthenticator {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+
+    // SASL PLAIN token: empty authorization id, username and password separated by zero bytes
+    fn token(&self) -> Vec<u8> {
+        let mut token = Vec::with_capacity(2 + self.username.len() + self.password.len());
+        token.push(0);
+        token.extend_from_slice(self.username.as_bytes());
+        token.push(0);
+        token.extend_from_slice(self.password.as_bytes());
+        token
+    }
+}
+
+impl Authenticator for PlainTextAuthenticator {
+    fn start_session<'a>(
+        &'a self,
+        _authenticator_name: &'a str,
+    ) -> BoxFuture<'a, Result<AuthInitialResponseAndSession, AuthError>> {
+        Box::pin(async move {
+            let session: Box<dyn AuthenticatorSession> = Box::new(PlainTextSession);
+            Ok((Some(self.token()), session))
+        })
+    }
+}
+
+struct PlainTextSession;
+
+impl AuthenticatorSession for PlainTextSession {
+    fn evaluate_challenge<'a>(
+        &'a mut self,
+        _challenge: Option<&'a [u8]>,
+    ) -> BoxFuture<'a, Result<Option<Vec<u8>>, AuthError>> {
+        Box::pin(async {
+            Err("Challenges aren't supported by the plain text authenticator".to_string())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        AuthError, AuthInitialResponseAndSession, Authenticator, AuthenticatorSession,
+        PlainTextAuthenticator,
+    };
+    use futures::future::BoxFuture;
+
+    #[tokio::test]
+    async fn plain_text_token() {
+        let authenticator = PlainTextAuthenticator::new("cassandra", "pass");
+        let (token, mut session) = authenticator
+            .start_session("org.apache.cassandra.auth.PasswordAuthenticator")
+            .await
+            .unwrap();
+        assert_eq!(token.unwrap(), b"\0cassandra\0pass");
+        assert!(session.evaluate_challenge(Some(b"?")).await.is_err());
+        assert!(session.success(None).await.is_ok());
+    }
+
+    // Answers challenges `challenge n` with `response n`
+    struct ChallengeAuthenticator;
+
+    struct ChallengeSession {
+        challenges: Vec<Vec<u8>>,
+    }
+
+    impl Authenticator for ChallengeAuthenticator {
+        fn start_session<'a>(
+            &'a self,
+            authenticator_name: &'a str,
+        ) -> BoxFuture<'a, Result<AuthInitialResponseAndSession, AuthError>> {
+            Box::pin(async move {
+                assert_eq!(authenticator_name, "com.example.ChallengeAuthenticator");
+                let session = ChallengeSession {
+                    challenges: Vec::new(),
+                };
+                let session: Box<dyn AuthenticatorSession> = Box::new(session);
+                Ok((Some(b"hello".to_vec()), session))
+            })
+        }
+    }
+
+    impl AuthenticatorSession for ChallengeSession {
+        fn evaluate_challenge<'a>(
+            &'a mut self,
+            challenge: Option<&'a [u8]>,
+        ) -> BoxFuture<'a, Result<Option<Vec<u8>>, AuthError>> {
+            Box::pin(async move {
+                let challenge = challenge.ok_or_else(|| "Empty challenge".to_string())?;
+                self.challenges.push(challenge.to_vec());
+                let number = challenge.strip_prefix(b"challenge ").unwrap();
+                Ok(Some([&b"response "[..], number].concat()))
+            })
+        }
+
+        fn success<'a>(
+            &'a mut self,
+            _token: Option<&'a [u8]>,
+        ) -> BoxFuture<'a, Result<(), AuthError>> {
+            Box::pin(async move {
+                assert_eq!(
+                    self.challenges,
+                    vec![b"challenge 1".to_vec(), b"challenge 2".to_vec()]
+                );
+                Ok(())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn challenges() {
+        let (token, mut session) = ChallengeAuthenticator
+            .start_session("com.example.ChallengeAuthenticator")
+            .await
+            .unwrap();
+        assert_eq!(token.unwrap(), b"hello");
+        for n in 1..=2 {
+            let challenge = format!("challenge {}", n);
+            let response = session
+                .evaluate_challenge(Some(challenge.as_bytes()))
+                .await
+                .unwrap();
+            assert_eq!(response.unwrap(), format!("response {}", n).as_bytes());
+        }
+        assert!(session.evaluate_challenge(None).await.is_err());
+        session.success(None).await.unwrap();
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn authenticate_with_mock_node() {
+        use crate::proxy::MockNode;
+        use crate::transport::errors::{DbError, NewSessionError};
+        use crate::{Session, SessionBuilder};
+        use std::sync::Arc;
+
+        let node = MockNode::new()
+            .authentication(
+                "com.example.ChallengeAuthenticator",
+                vec![
+                    b"hello".to_vec(),
+                    b"response 1".to_vec(),
+                    b"response 2".to_vec(),
+                ],
+            )
+            .run()
+            .await
+            .unwrap();
+
+        let session: Session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .authenticator(Arc::new(ChallengeAuthenticator))
+            .build()
+            .await
+            .unwrap();
+        session
+            .query("INSERT INTO ks.t (a) VALUES (1)", &[])
+            .await
+            .unwrap();
+
+        let error = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .build()
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(error, NewSessionError::AuthenticationError(_)));
+
+        let error = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .user("cassandra", "cassandra")
+            .build()
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error,
+            NewSessionError::DbError(DbError::AuthenticationError, _)
+        ));
+
+        node.finish().await.unwrap();
+    }
+}
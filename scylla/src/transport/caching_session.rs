@@ -0,0 +1,332 @@
+//! Session wrapper which prepares statements transparently and caches them
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+
+use super::connection::QueryResult;
+use super::errors::QueryError;
+use super::iterator::RowIterator;
+use super::session::Session;
+use crate::frame::value::ValueList;
+use crate::prepared_statement::PreparedStatement;
+use crate::query::Query;
+
+/// Wraps a [`Session`] and executes statements given as text or [`Query`] as prepared statements.
+/// Each statement is prepared on first use and cached, keyed by its text and
+/// its [keyspace](crate::query::Query::set_keyspace), if it has one.
+/// When the cache is full the least recently used statement is evicted.
+///
+/// Statement options (consistency, page size, retry policy, ...) are taken
+/// from the `Query` passed to each call, not from the one which was prepared first.
+///
+/// # Example
+/// ```rust
+/// # use scylla::Session;
+/// # use std::error::Error;
+/// # async fn check_only_compiles(session: Session) -> Result<(), Box<dyn Error>> {
+/// use scylla::transport::caching_session::CachingSession;
+///
+/// let session = CachingSession::new(session, 100);
+///
+/// // Prepared on the first call, later calls reuse the prepared statement
+/// for i in 0..10 {
+///     session
+///         .execute("INSERT INTO ks.tab (a) VALUES(?)", (i,))
+///         .await?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct CachingSession {
+    session: Session,
+    cache: StatementCache,
+}
+
+impl CachingSession {
+    /// Creates a caching session holding at most `max_capacity` prepared statements
+    ///
+    /// # Panics
+    /// Panics if `max_capacity` is 0.
+    pub fn new(session: Session, max_capacity: usize) -> Self {
+        assert!(max_capacity > 0, "cache capacity must be larger than 0");
+        CachingSession {
+            session,
+            cache: StatementCache::new(max_capacity),
+        }
+    }
+
+    /// Executes the statement as a prepared statement, preparing it first if it isn't cached.
+    /// Works like [`Session::execute`]
+    pub async fn execute(
+        &self,
+        query: impl Into<Query>,
+        values: impl ValueList,
+    ) -> Result<QueryResult, QueryError> {
+        let prepared = self.add_prepared_statement(query).await?;
+        self.session.execute(&prepared, values).await
+    }
+
+    /// Executes the statement as a prepared statement, preparing it first if it isn't cached.
+    /// Works like [`Session::execute_iter`]
+    pub async fn execute_iter(
+        &self,
+        query: impl Into<Query>,
+        values: impl ValueList,
+    ) -> Result<RowIterator, QueryError> {
+        let prepared = self.add_prepared_statement(query).await?;
+        self.session.execute_iter(prepared, values).await
+    }
+
+    /// Executes the statement as a prepared statement, preparing it first if it isn't cached.
+    /// Works like [`Session::execute_paged`]
+    pub async fn execute_paged(
+        &self,
+        query: impl Into<Query>,
+        values: impl ValueList,
+        paging_state: Option<Bytes>,
+    ) -> Result<QueryResult, QueryError> {
+        let prepared = self.add_prepared_statement(query).await?;
+        self.session
+            .execute_paged(&prepared, values, paging_state)
+            .await
+    }
+
+    /// Returns the prepared statement for `query`, preparing and caching it if it isn't cached.
+    /// Options of the returned statement are taken from `query`
+    pub async fn add_prepared_statement(
+        &self,
+        query: impl Into<Query>,
+    ) -> Result<PreparedStatement, QueryError> {
+        let query: Query = query.into();
+        let cached = self.cache.get_or_prepare(&self.session, &query).await?;
+
+        // The cached statement is shared, it's copied only to apply options of the query
+        let mut prepared = PreparedStatement::clone(&cached);
+
+        prepared.config = query.config.clone();
+        match query.get_page_size() {
+            Some(page_size) => prepared.set_page_size(page_size),
+            None => prepared.disable_paging(),
+        }
+        Ok(prepared)
+    }
+
+    /// Checks whether the statement is currently cached
+    pub fn is_cached(&self, statement: &str) -> bool {
+        self.is_cached_in_keyspace(statement, None)
+    }
+
+    /// Checks whether the statement with the given [keyspace](crate::query::Query::set_keyspace) is currently cached
+    pub fn is_cached_in_keyspace(&self, statement: &str, keyspace: Option<&str>) -> bool {
+        self.cache.contains(statement, keyspace)
+    }
+
+    /// Returns the maximum number of cached statements
+    pub fn get_max_capacity(&self) -> usize {
+        self.cache.max_capacity
+    }
+
+    /// Returns the wrapped session, e.g. to execute statements which shouldn't be cached
+    pub fn get_session(&self) -> &Session {
+        &self.session
+    }
+}
+
+// Statements with the same text prepared in different keyspaces are different statements
+struct StatementKey {
+    statement: String,
+    keyspace: Option<String>,
+}
+
+// Allows looking up cache entries by borrowed statement text and keyspace,
+// so a lookup doesn't allocate a new key
+trait StatementKeyRef {
+    fn key(&self) -> (&str, Option<&str>);
+}
+
+impl StatementKeyRef for StatementKey {
+    fn key(&self) -> (&str, Option<&str>) {
+        (&self.statement, self.keyspace.as_deref())
+    }
+}
+
+impl StatementKeyRef for (&str, Option<&str>) {
+    fn key(&self) -> (&str, Option<&str>) {
+        *self
+    }
+}
+
+impl<'a> Borrow<dyn StatementKeyRef + 'a> for StatementKey {
+    fn borrow(&self) -> &(dyn StatementKeyRef + 'a) {
+        self
+    }
+}
+
+// Hash and Eq of the key and its borrowed form have to agree
+impl Hash for dyn StatementKeyRef + '_ {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+impl PartialEq for dyn StatementKeyRef + '_ {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for dyn StatementKeyRef + '_ {}
+
+impl Hash for StatementKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+impl PartialEq for StatementKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for StatementKey {}
+
+// Prepared statements cached by CachingSession and TableMapper.
+// When the cache is full the least recently used statement is evicted
+pub(crate) struct StatementCache {
+    entries: Mutex<CacheEntries>,
+    max_capacity: usize,
+}
+
+#[derive(Default)]
+struct CacheEntries {
+    // Prepared statements by statement text and keyspace, along with the time of their last use
+    statements: HashMap<StatementKey, (Arc<PreparedStatement>, u64)>,
+    // Incremented on each use, serves as the time of last use
+    uses: u64,
+}
+
+impl StatementCache {
+    pub(crate) fn new(max_capacity: usize) -> Self {
+        StatementCache {
+            entries: Mutex::new(CacheEntries::default()),
+            max_capacity,
+        }
+    }
+
+    /// Returns the prepared `query`, preparing and caching it if it isn't cached.
+    /// Concurrent first uses might prepare the statement more than once, which is harmless
+    pub(crate) async fn get_or_prepare(
+        &self,
+        session: &Session,
+        query: &Query,
+    ) -> Result<Arc<PreparedStatement>, QueryError> {
+        if let Some(prepared) = self.get(query.get_contents(), query.get_keyspace()) {
+            return Ok(prepared);
+        }
+
+        let prepared = Arc::new(session.prepare(query.clone()).await?);
+        self.insert(prepared.clone());
+        Ok(prepared)
+    }
+
+    pub(crate) fn contains(&self, statement: &str, keyspace: Option<&str>) -> bool {
+        self.entries
+            .lock()
+            .unwrap()
+            .statements
+            .contains_key(&(statement, keyspace) as &dyn StatementKeyRef)
+    }
+
+    fn get(&self, statement: &str, keyspace: Option<&str>) -> Option<Arc<PreparedStatement>> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.uses += 1;
+        let uses = entries.uses;
+        entries
+            .statements
+            .get_mut(&(statement, keyspace) as &dyn StatementKeyRef)
+            .map(|(prepared, last_use)| {
+                *last_use = uses;
+                prepared.clone()
+            })
+    }
+
+    fn insert(&self, prepared: Arc<PreparedStatement>) {
+        let key = StatementKey {
+            statement: prepared.get_statement().to_string(),
+            keyspace: prepared.get_keyspace().map(str::to_string),
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.statements.contains_key(&key) && entries.statements.len() >= self.max_capacity {
+            // Linear in the cache size, but happens only on misses which require a round trip anyway
+            let least_recently_used = entries
+                .statements
+                .values()
+                .map(|(_, last_use)| *last_use)
+                .min();
+            // Times of last use are unique, so this removes a single entry
+            entries
+                .statements
+                .retain(|_, (_, last_use)| Some(*last_use) != least_recently_used);
+        }
+
+        entries.uses += 1;
+        let uses = entries.uses;
+        entries.statements.insert(key, (prepared, uses));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn caching_session_evicts_least_recently_used() {
+        use super::CachingSession;
+        use crate::proxy::{MockNode, MockResponse};
+        use crate::query::Query;
+        use crate::statement::Consistency;
+        use crate::SessionBuilder;
+
+        let node = MockNode::new()
+            .default_response(MockResponse::Void)
+            .run()
+            .await
+            .unwrap();
+        let session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .build()
+            .await
+            .unwrap();
+        let session = CachingSession::new(session, 2);
+
+        let a = "INSERT INTO ks.t (a) VALUES (?)";
+        let b = "INSERT INTO ks.t (b) VALUES (?)";
+        let c = "INSERT INTO ks.t (c) VALUES (?)";
+
+        session.execute(a, (1,)).await.unwrap();
+        session.execute(b, (2,)).await.unwrap();
+        // Makes b the least recently used statement
+        session.execute(a, (3,)).await.unwrap();
+        session.execute(c, (4,)).await.unwrap();
+
+        assert!(session.is_cached(a));
+        assert!(!session.is_cached(b));
+        assert!(session.is_cached(c));
+        assert_eq!(node.received_statements(), vec![a, b, a, c]);
+
+        // Options come from the query passed to each call
+        let mut query = Query::new(a.to_string());
+        query.set_consistency(Consistency::Two);
+        let prepared = session.add_prepared_statement(query).await.unwrap();
+        assert_eq!(prepared.get_consistency(), Some(Consistency::Two));
+        let prepared = session.add_prepared_statement(a).await.unwrap();
+        assert_eq!(prepared.get_consistency(), None);
+
+        node.finish().await.unwrap();
+    }
+}
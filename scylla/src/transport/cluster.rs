@@ -1,19 +1,59 @@
-use crate::frame::response::event::{Event, StatusChangeEvent};
+use crate::frame::response::event::{
+    Event, SchemaChangeEvent, StatusChangeEvent, TopologyChangeEvent,
+};
 /// Cluster manages up to date information and connections to database nodes
-use crate::routing::Token;
+use crate::routing::{Partitioner, Token, TokenRange};
+use crate::transport::address_translator::AddressTranslator;
 use crate::transport::connection::{Connection, ConnectionConfig, VerifiedKeyspaceName};
-use crate::transport::errors::QueryError;
+use crate::transport::errors::{ProtocolError, QueryError};
+use crate::transport::host_filter::HostFilter;
+use crate::transport::metrics::Metrics;
 use crate::transport::node::{Node, NodeConnections};
-use crate::transport::topology::{Keyspace, TopologyInfo, TopologyReader};
+use crate::transport::replication::{compute_replica_sets, ReplicaSets};
+use crate::transport::runtime;
+use crate::transport::topology::{self, ClusterInfo, Keyspace, TopologyInfo, TopologyReader};
+use crate::transport::ProtocolVersion;
 
 use arc_swap::ArcSwap;
 use futures::future::join_all;
-use futures::{future::RemoteHandle, FutureExt};
+use futures::{future::RemoteHandle, FutureExt, Stream, StreamExt};
 use itertools::Itertools;
-use std::collections::{BTreeMap, HashMap};
-use std::net::SocketAddr;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use tracing::{debug, warn};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{debug, debug_span, warn, Instrument};
+
+/// Event sent by the cluster - a topology, node status or schema change.
+/// Can be received using [`Session::cluster_events`](crate::Session::cluster_events)
+pub type ClusterEvent = Event;
+
+// Number of events kept for subscribers that didn't receive them yet
+const CLUSTER_EVENTS_CHANNEL_CAPACITY: usize = 128;
+
+/// Schema change observed by the driver.
+/// Can be received using [`Session::watch_schema`](crate::Session::watch_schema)
+#[derive(Clone, Debug)]
+pub enum SchemaChangeNotification {
+    /// The cluster sent a schema change event
+    Event(SchemaChangeEvent),
+    /// Schema metadata of the keyspace fetched during topology refresh has changed
+    MetadataChanged { keyspace_name: String },
+}
+
+impl SchemaChangeNotification {
+    /// Name of the keyspace affected by the change
+    pub fn keyspace_name(&self) -> &str {
+        match self {
+            SchemaChangeNotification::Event(event) => event.keyspace_name(),
+            SchemaChangeNotification::MetadataChanged { keyspace_name } => keyspace_name,
+        }
+    }
+}
+
+// Minimal time between refreshes caused by a broken control connection
+const CONTROL_CONNECTION_FAILOVER_COOLDOWN: std::time::Duration = std::time::Duration::from_secs(5);
 
 /// Cluster manages up to date information and connections to database nodes.
 /// All data can be accessed by cloning Arc<ClusterData> in the `data` field
@@ -25,6 +65,15 @@ pub struct Cluster {
     refresh_channel: tokio::sync::mpsc::Sender<RefreshRequest>,
     use_keyspace_channel: tokio::sync::mpsc::Sender<UseKeyspaceRequest>,
 
+    // Used to subscribe to events received from the cluster
+    cluster_events_sender: broadcast::Sender<ClusterEvent>,
+
+    // Used to subscribe to schema change events
+    schema_change_events_sender: broadcast::Sender<SchemaChangeEvent>,
+
+    // Used to subscribe to changes of schema metadata, carries names of the changed keyspaces
+    schema_metadata_changes_sender: broadcast::Sender<String>,
+
     _worker_handle: RemoteHandle<()>,
 }
 
@@ -41,6 +90,9 @@ pub struct ClusterData {
     pub keyspaces: HashMap<String, Keyspace>,
     pub all_nodes: Vec<Arc<Node>>,
     pub datacenters: HashMap<String, Datacenter>,
+    pub cluster_info: ClusterInfo,
+    // Replicas of vnodes for keyspaces using SimpleStrategy or NetworkTopologyStrategy
+    pub(crate) replica_sets: HashMap<String, Arc<ReplicaSets>>,
 }
 
 // Works in the background to keep the cluster updated
@@ -52,6 +104,9 @@ struct ClusterWorker {
     topology_reader: TopologyReader,
     connection_config: ConnectionConfig,
 
+    // Translates addresses of nodes in server events, like addresses read by topology_reader
+    address_translator: Arc<dyn AddressTranslator>,
+
     // To listen for refresh requests
     refresh_channel: tokio::sync::mpsc::Receiver<RefreshRequest>,
 
@@ -61,8 +116,29 @@ struct ClusterWorker {
     // Channel used to receive server events
     server_events_channel: tokio::sync::mpsc::Receiver<Event>,
 
+    // Channel used to pass received server events to subscribers
+    cluster_events_sender: broadcast::Sender<ClusterEvent>,
+
+    // Channel used to pass received schema change events to subscribers
+    schema_change_events_sender: broadcast::Sender<SchemaChangeEvent>,
+
+    // Channel used to notify subscribers about keyspaces with changed schema metadata
+    schema_metadata_changes_sender: broadcast::Sender<String>,
+
     // Keyspace send in "USE <keyspace name>" when opening each connection
     used_keyspace: Option<VerifiedKeyspaceName>,
+
+    // If set, connections are opened right away only to nodes in this datacenter
+    pooled_datacenter: Option<String>,
+
+    // Nodes rejected by the filter are kept in cluster data, but never get connections
+    host_filter: Option<Arc<dyn HostFilter>>,
+
+    // Time between periodic topology refreshes
+    refresh_interval: Duration,
+
+    // Metrics of nodes removed from the cluster are dropped when cluster data is updated
+    metrics: Arc<Metrics>,
 }
 
 #[derive(Debug)]
@@ -77,9 +153,17 @@ struct UseKeyspaceRequest {
 }
 
 impl Cluster {
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         initial_peers: &[SocketAddr],
         connection_config: ConnectionConfig,
+        port_overrides: HashMap<IpAddr, u16>,
+        metadata_timeout: Duration,
+        refresh_interval: Duration,
+        pooled_datacenter: Option<String>,
+        address_translator: Arc<dyn AddressTranslator>,
+        host_filter: Option<Arc<dyn HostFilter>>,
+        metrics: Arc<Metrics>,
     ) -> Result<Cluster, QueryError> {
         let cluster_data = Arc::new(ArcSwap::from(Arc::new(ClusterData {
             known_peers: HashMap::new(),
@@ -87,11 +171,17 @@ impl Cluster {
             keyspaces: HashMap::new(),
             all_nodes: Vec::new(),
             datacenters: HashMap::new(),
+            cluster_info: ClusterInfo::default(),
+            replica_sets: HashMap::new(),
         })));
 
         let (refresh_sender, refresh_receiver) = tokio::sync::mpsc::channel(32);
         let (use_keyspace_sender, use_keyspace_receiver) = tokio::sync::mpsc::channel(32);
         let (server_events_sender, server_events_receiver) = tokio::sync::mpsc::channel(32);
+        let (cluster_events_sender, _) = broadcast::channel(CLUSTER_EVENTS_CHANNEL_CAPACITY);
+        let (schema_change_events_sender, _) = broadcast::channel(CLUSTER_EVENTS_CHANNEL_CAPACITY);
+        let (schema_metadata_changes_sender, _) =
+            broadcast::channel(CLUSTER_EVENTS_CHANNEL_CAPACITY);
 
         let worker = ClusterWorker {
             cluster_data: cluster_data.clone(),
@@ -100,23 +190,41 @@ impl Cluster {
                 initial_peers,
                 connection_config.clone(),
                 server_events_sender,
+                port_overrides,
+                metadata_timeout,
+                address_translator.clone(),
+                host_filter.clone(),
             ),
             connection_config,
+            address_translator,
 
             refresh_channel: refresh_receiver,
             server_events_channel: server_events_receiver,
+            cluster_events_sender: cluster_events_sender.clone(),
+            schema_change_events_sender: schema_change_events_sender.clone(),
+            schema_metadata_changes_sender: schema_metadata_changes_sender.clone(),
 
             use_keyspace_channel: use_keyspace_receiver,
             used_keyspace: None,
+            pooled_datacenter,
+            host_filter,
+            refresh_interval,
+            metrics,
         };
 
-        let (fut, worker_handle) = worker.work().remote_handle();
-        tokio::spawn(fut);
+        let (fut, worker_handle) = worker
+            .work()
+            .instrument(debug_span!("cluster_worker"))
+            .remote_handle();
+        runtime::spawn(fut);
 
         let result = Cluster {
             data: cluster_data,
             refresh_channel: refresh_sender,
             use_keyspace_channel: use_keyspace_sender,
+            cluster_events_sender,
+            schema_change_events_sender,
+            schema_metadata_changes_sender,
             _worker_handle: worker_handle,
         };
 
@@ -164,7 +272,38 @@ impl Cluster {
         response_receiver.await.unwrap() // ClusterWorker always responds
     }
 
-    /// Returns nonempty list of working connections to all shards
+    /// Returns a stream of events received from the cluster after subscribing.
+    /// If the subscriber falls too far behind, the oldest events are skipped.
+    pub fn subscribe_to_events(&self) -> impl Stream<Item = ClusterEvent> + Send + Unpin {
+        broadcast_receiver_to_stream(self.cluster_events_sender.subscribe())
+    }
+
+    /// Returns a receiver of schema change events received from the cluster after subscribing.
+    /// If the receiver falls too far behind, it gets `RecvError::Lagged` and the oldest events are skipped.
+    pub fn schema_change_receiver(&self) -> broadcast::Receiver<SchemaChangeEvent> {
+        self.schema_change_events_sender.subscribe()
+    }
+
+    /// Returns a stream of schema changes affecting the given keyspace observed after subscribing.
+    /// If the subscriber falls too far behind, the oldest notifications are skipped.
+    pub fn watch_schema(
+        &self,
+        keyspace_name: String,
+    ) -> impl Stream<Item = SchemaChangeNotification> + Send + Unpin {
+        let events = broadcast_receiver_to_stream(self.schema_change_receiver())
+            .map(SchemaChangeNotification::Event);
+        let metadata_changes =
+            broadcast_receiver_to_stream(self.schema_metadata_changes_sender.subscribe())
+                .map(|keyspace_name| SchemaChangeNotification::MetadataChanged { keyspace_name });
+
+        futures::stream::select(events, metadata_changes)
+            .filter(move |notification| {
+                futures::future::ready(notification.keyspace_name() == keyspace_name)
+            })
+            .boxed()
+    }
+
+    /// Returns nonempty list of working connections to all shards, one from each pool
     pub async fn get_working_connections(&self) -> Result<Vec<Arc<Connection>>, QueryError> {
         let cluster_data: Arc<ClusterData> = self.get_data();
         let peers = &cluster_data.known_peers;
@@ -173,7 +312,7 @@ impl Cluster {
 
         let mut last_error: Option<QueryError> = None;
 
-        // Takes result of ConnectionKeeper::get_connection() and pushes it onto result list or sets last_error
+        // Takes result of Node::connection_from_pool() and pushes it onto result list or sets last_error
         let mut push_to_result = |get_conn_res: Result<Arc<Connection>, QueryError>| {
             match get_conn_res {
                 Ok(conn) => result.push(conn),
@@ -185,19 +324,24 @@ impl Cluster {
             let connections: Arc<NodeConnections> = node.connections.read().unwrap().clone();
 
             match &*connections {
-                NodeConnections::Single(conn_keeper) => {
-                    push_to_result(conn_keeper.get_connection().await)
+                // Node isn't pooled, it will get the keyspace and prepared statements when connecting
+                NodeConnections::NotOpened => {}
+                NodeConnections::Single(pool) => {
+                    push_to_result(Node::connection_from_pool(pool).await)
                 }
                 NodeConnections::Sharded { shard_conns, .. } => {
-                    for conn_keeper in shard_conns {
-                        push_to_result(conn_keeper.get_connection().await);
+                    for pool in shard_conns {
+                        push_to_result(Node::connection_from_pool(pool).await);
                     }
                 }
             }
         }
 
         if result.is_empty() {
-            return Err(last_error.unwrap()); // By invariant peers is nonempty
+            // All nodes may be ignored because of the host filter or not pooled yet
+            return Err(
+                last_error.unwrap_or(QueryError::ProtocolError(ProtocolError::NoPooledNodes))
+            );
         }
 
         Ok(result)
@@ -214,6 +358,62 @@ impl ClusterData {
         before_wrap.chain(after_wrap).take(self.ring.len())
     }
 
+    /// Returns replicas of the vnode in which `token` lies, in preference order:
+    /// the owner of the vnode first, then the following replicas clockwise on the ring.  
+    /// Replicas are computed using the keyspace's replication strategy, SimpleStrategy and NetworkTopologyStrategy
+    /// are supported. For keyspaces with other strategies, unknown keyspaces or when `keyspace` is `None`
+    /// only the owner of the token is returned.
+    ///
+    /// Replica sets are computed once for each vnode when the topology or schema is refreshed.
+    pub fn replicas(&self, keyspace: Option<&str>, token: &Token) -> &[Arc<Node>] {
+        if let Some(replica_sets) = keyspace.and_then(|ks| self.replica_sets.get(ks)) {
+            return replica_sets.replicas(token);
+        }
+
+        self.ring
+            .range(token..)
+            .next()
+            .or_else(|| self.ring.iter().next())
+            .map(|(_, owner)| std::slice::from_ref(owner))
+            .unwrap_or(&[])
+    }
+
+    /// Returns the partitioner of the given table: its own partitioner if it has one,
+    /// e.g. CDC log tables in Scylla, otherwise the cluster's partitioner
+    pub fn table_partitioner(&self, keyspace: &str, table: &str) -> Partitioner {
+        self.keyspaces
+            .get(keyspace)
+            .and_then(|keyspace| keyspace.tables.get(table))
+            .and_then(|table| table.partitioner.as_deref())
+            .and_then(Partitioner::from_class_name)
+            .unwrap_or_else(|| self.cluster_info.partitioner())
+    }
+
+    /// Splits the whole token ring into ranges of single vnodes, sorted by their tokens.
+    /// Each range is owned by the node at its end token, the range wrapping around the ring
+    /// is split in two
+    pub fn token_ranges(&self) -> Vec<TokenRange> {
+        let partitioner = self.cluster_info.partitioner();
+        let mut ranges = Vec::with_capacity(self.ring.len() + 1);
+        let mut start = Token::min(partitioner);
+        for token in self.ring.keys() {
+            ranges.push(TokenRange {
+                start,
+                end: token.clone(),
+            });
+            match token.next() {
+                Some(next) => start = next,
+                None => return ranges,
+            }
+        }
+
+        ranges.push(TokenRange {
+            start,
+            end: Token::max(partitioner),
+        });
+        ranges
+    }
+
     // Updates information about rack count in each datacenter
     fn update_rack_count(datacenters: &mut HashMap<String, Datacenter>) {
         for datacenter in datacenters.values_mut() {
@@ -226,39 +426,289 @@ impl ClusterData {
         }
     }
 
+    /// Returns the name of the cluster
+    pub fn cluster_name(&self) -> Option<&str> {
+        self.cluster_info.cluster_name.as_deref()
+    }
+
+    /// Returns release version reported by the cluster, e.g. "3.0.8"
+    pub fn release_version(&self) -> Option<&str> {
+        self.cluster_info.release_version.as_deref()
+    }
+
+    /// Returns CQL version used by the cluster, e.g. "3.3.1"
+    pub fn cql_version(&self) -> Option<&str> {
+        self.cluster_info.cql_version.as_deref()
+    }
+
+    /// Returns highest native protocol version supported by the cluster, e.g. "4"
+    pub fn native_protocol_version(&self) -> Option<&str> {
+        self.cluster_info.native_protocol_version.as_deref()
+    }
+
+    /// Returns protocol version negotiated with the cluster on the control connection.
+    /// Connections to other nodes negotiate their versions separately
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.cluster_info.protocol_version
+    }
+
+    /// Renders schema of all non-system keyspaces as CQL statements which recreate it,
+    /// see [`Keyspace::describe`](crate::transport::Keyspace::describe).\
+    /// Keyspaces are sorted by name and separated by empty lines.
+    pub fn describe_schema(&self) -> String {
+        let mut keyspace_names: Vec<&String> = self
+            .keyspaces
+            .keys()
+            .filter(|name| !is_system_keyspace(name))
+            .collect();
+        keyspace_names.sort_unstable();
+
+        keyspace_names
+            .into_iter()
+            .map(|name| self.keyspaces[name].describe(name))
+            .collect::<Vec<String>>()
+            .join("\n\n")
+    }
+
+    /// Finds a node with the given IP address.
+    /// Addresses in server events contain the regular CQL port, which can differ
+    /// from the port we are connected to (e.g. shard-aware port), so only IP is compared.
+    pub fn find_node_by_ip(&self, addr: SocketAddr) -> Option<&Arc<Node>> {
+        self.known_peers.get(&addr).or_else(|| {
+            self.all_nodes
+                .iter()
+                .find(|node| node.address.ip() == addr.ip())
+        })
+    }
+
+    /// Checks if this ClusterData describes exactly the topology held in `info`
+    fn matches_topology_info(&self, info: &TopologyInfo) -> bool {
+        self.keyspaces == info.keyspaces
+            && self.cluster_info == info.cluster_info
+            && self.matches_nodes(info)
+    }
+
+    // Checks if nodes and their tokens are the same as in `info`, schema isn't compared
+    fn matches_nodes(&self, info: &TopologyInfo) -> bool {
+        if self.known_peers.len() != info.peers.len() {
+            return false;
+        }
+
+        let mut tokens_count: usize = 0;
+
+        for peer in &info.peers {
+            let node = match self.known_peers.get(&peer.address) {
+                Some(node) => node,
+                None => return false,
+            };
+
+            if node.datacenter != peer.datacenter
+                || node.rack != peer.rack
+                || node.host_id != peer.host_id
+            {
+                return false;
+            }
+
+            for token in &peer.tokens {
+                match self.ring.get(token) {
+                    Some(owner) if owner.address == peer.address => {}
+                    _ => return false,
+                }
+            }
+
+            tokens_count += peer.tokens.len();
+        }
+
+        tokens_count == self.ring.len()
+    }
+
+    /// Creates a copy of ClusterData without the node with given address
+    fn without_node(&self, address: SocketAddr) -> Self {
+        let is_kept = |node: &Arc<Node>| node.address != address;
+
+        let known_peers = self
+            .known_peers
+            .iter()
+            .filter(|(_, node)| is_kept(node))
+            .map(|(addr, node)| (*addr, node.clone()))
+            .collect();
+
+        let ring = self
+            .ring
+            .iter()
+            .filter(|(_, node)| is_kept(node))
+            .map(|(token, node)| (token.clone(), node.clone()))
+            .collect();
+
+        let all_nodes = self
+            .all_nodes
+            .iter()
+            .filter(|n| is_kept(n))
+            .cloned()
+            .collect();
+
+        let mut datacenters: HashMap<String, Datacenter> = self
+            .datacenters
+            .iter()
+            .map(|(dc_name, dc)| {
+                let nodes: Vec<Arc<Node>> =
+                    dc.nodes.iter().filter(|n| is_kept(n)).cloned().collect();
+                (
+                    dc_name.clone(),
+                    Datacenter {
+                        nodes,
+                        rack_count: 0,
+                    },
+                )
+            })
+            .filter(|(_, dc)| !dc.nodes.is_empty())
+            .collect();
+
+        Self::update_rack_count(&mut datacenters);
+
+        ClusterData {
+            known_peers,
+            ring,
+            keyspaces: self.keyspaces.clone(),
+            all_nodes,
+            datacenters,
+            cluster_info: self.cluster_info.clone(),
+            replica_sets: HashMap::new(),
+        }
+        .with_replica_sets()
+    }
+
+    /// Creates a copy of ClusterData with new keyspaces and cluster info, keeping the nodes and the ring.
+    /// Replica sets are computed only for replication strategies which weren't used before.
+    fn with_schema(&self, keyspaces: HashMap<String, Keyspace>, cluster_info: ClusterInfo) -> Self {
+        let mut cluster_data = ClusterData {
+            known_peers: self.known_peers.clone(),
+            ring: self.ring.clone(),
+            keyspaces,
+            all_nodes: self.all_nodes.clone(),
+            datacenters: self.datacenters.clone(),
+            cluster_info,
+            replica_sets: HashMap::new(),
+        };
+        cluster_data.replica_sets = compute_replica_sets(&cluster_data, Some(self));
+        cluster_data
+    }
+
+    // Replaces schema of a single keyspace, the keyspace is removed if it's None
+    fn with_keyspace(&self, keyspace_name: &str, keyspace: Option<Keyspace>) -> Self {
+        let mut keyspaces = self.keyspaces.clone();
+        match keyspace {
+            Some(keyspace) => keyspaces.insert(keyspace_name.to_string(), keyspace),
+            None => keyspaces.remove(keyspace_name),
+        };
+        self.with_schema(keyspaces, self.cluster_info.clone())
+    }
+
+    // Computes replica sets after the ring or keyspaces are set
+    fn with_replica_sets(mut self) -> Self {
+        self.replica_sets = compute_replica_sets(&self, None);
+        self
+    }
+
     /// Creates new ClusterData using information about topology held in `info`.
     /// Uses provided `known_peers` hashmap to recycle nodes if possible.
+    /// Peers rejected by `host_filter` become ignored nodes, which never open connections.
     pub fn new(
         info: TopologyInfo,
         connection_config: &ConnectionConfig,
         known_peers: &HashMap<SocketAddr, Arc<Node>>,
         used_keyspace: &Option<VerifiedKeyspaceName>,
+        pooled_datacenter: &Option<String>,
+        host_filter: &Option<Arc<dyn HostFilter>>,
     ) -> Self {
-        // Create new updated known_peers and ring
-        let mut new_known_peers: HashMap<SocketAddr, Arc<Node>> =
-            HashMap::with_capacity(info.peers.len());
-        let mut ring: BTreeMap<Token, Arc<Node>> = BTreeMap::new();
-        let mut datacenters: HashMap<String, Datacenter> = HashMap::new();
-        let mut all_nodes: Vec<Arc<Node>> = Vec::with_capacity(info.peers.len());
+        let accepted: Vec<bool> = info
+            .peers
+            .iter()
+            .map(|peer| topology::is_accepted(host_filter, peer))
+            .collect();
+
+        if !accepted.is_empty() && !accepted.contains(&true) {
+            warn!("Host filter rejected all nodes of the cluster, queries will fail");
+        }
+
+        // Open connections to all nodes if there are no accepted nodes in the pooled datacenter,
+        // otherwise the driver would have no connections at all
+        let pooled_datacenter: Option<&String> = match pooled_datacenter {
+            Some(dc)
+                if !info
+                    .peers
+                    .iter()
+                    .zip(&accepted)
+                    .any(|(p, accepted)| *accepted && p.datacenter.as_ref() == Some(dc)) =>
+            {
+                warn!(
+                    "No nodes in datacenter {}, opening connections to all nodes",
+                    dc
+                );
+                None
+            }
+            other => other.as_ref(),
+        };
+
+        let mut nodes: Vec<(Arc<Node>, Vec<Token>)> = Vec::with_capacity(info.peers.len());
+
+        for (peer, accepted) in info.peers.into_iter().zip(accepted) {
+            let open_connections: bool = match pooled_datacenter {
+                Some(dc) => peer.datacenter.as_ref() == Some(dc),
+                None => true,
+            };
 
-        for peer in info.peers {
             // Take existing Arc<Node> if possible, otherwise create new one
-            // Changing rack/datacenter but not ip address seems improbable
+            // Changing rack/datacenter/host_id but not ip address seems improbable
             // so we can just create new node and connections then
             let node: Arc<Node> = match known_peers.get(&peer.address) {
-                Some(node) if node.datacenter == peer.datacenter && node.rack == peer.rack => {
+                Some(node)
+                    if node.datacenter == peer.datacenter
+                        && node.rack == peer.rack
+                        && node.host_id == peer.host_id
+                        && node.is_ignored() != accepted =>
+                {
                     node.clone()
                 }
+                _ if !accepted => {
+                    debug!("Node {} rejected by the host filter", peer.address);
+                    Arc::new(Node::new_ignored(
+                        peer.address,
+                        peer.datacenter,
+                        peer.rack,
+                        peer.host_id,
+                    ))
+                }
                 _ => Arc::new(Node::new(
                     peer.address,
                     connection_config.clone(),
                     peer.datacenter,
                     peer.rack,
+                    peer.host_id,
                     used_keyspace.clone(),
+                    open_connections,
                 )),
             };
 
-            new_known_peers.insert(peer.address, node.clone());
+            nodes.push((node, peer.tokens));
+        }
+
+        Self::from_nodes(nodes, info.keyspaces, info.cluster_info)
+    }
+
+    /// Creates ClusterData consisting of given nodes, each of them owning the given tokens
+    pub(crate) fn from_nodes(
+        nodes: Vec<(Arc<Node>, Vec<Token>)>,
+        keyspaces: HashMap<String, Keyspace>,
+        cluster_info: ClusterInfo,
+    ) -> Self {
+        let mut known_peers: HashMap<SocketAddr, Arc<Node>> = HashMap::with_capacity(nodes.len());
+        let mut ring: BTreeMap<Token, Arc<Node>> = BTreeMap::new();
+        let mut datacenters: HashMap<String, Datacenter> = HashMap::new();
+        let mut all_nodes: Vec<Arc<Node>> = Vec::with_capacity(nodes.len());
+
+        for (node, tokens) in nodes {
+            known_peers.insert(node.address, node.clone());
 
             if let Some(dc) = &node.datacenter {
                 match datacenters.get_mut(dc) {
@@ -273,7 +723,7 @@ impl ClusterData {
                 }
             }
 
-            for token in peer.tokens {
+            for token in tokens {
                 ring.insert(token, node.clone());
             }
 
@@ -283,20 +733,22 @@ impl ClusterData {
         Self::update_rack_count(&mut datacenters);
 
         ClusterData {
-            known_peers: new_known_peers,
+            known_peers,
             ring,
-            keyspaces: info.keyspaces,
+            keyspaces,
             all_nodes,
             datacenters,
+            cluster_info,
+            replica_sets: HashMap::new(),
         }
+        .with_replica_sets()
     }
 }
 
 impl ClusterWorker {
     pub async fn work(mut self) {
-        use tokio::time::{Duration, Instant};
+        use std::time::Instant;
 
-        let refresh_duration = Duration::from_secs(60); // Refresh topology every 60 seconds
         let mut last_refresh_time = Instant::now();
 
         loop {
@@ -304,14 +756,17 @@ impl ClusterWorker {
 
             // Wait until it's time for the next refresh
             let sleep_until: Instant = last_refresh_time
-                .checked_add(refresh_duration)
+                .checked_add(self.refresh_interval)
                 .unwrap_or_else(Instant::now);
 
-            let sleep_future = tokio::time::sleep_until(sleep_until);
-            tokio::pin!(sleep_future);
+            let sleep_future = runtime::sleep_until(sleep_until);
 
             tokio::select! {
                 _ = sleep_future => {},
+                _ = Self::control_connection_broken(&self.topology_reader, last_refresh_time) => {
+                    // Refresh will move the control connection to a working node
+                    warn!("Control connection is broken, refreshing topology");
+                },
                 recv_res = self.refresh_channel.recv() => {
                     match recv_res {
                         Some(request) => cur_request = Some(request),
@@ -321,19 +776,12 @@ impl ClusterWorker {
                 recv_res = self.server_events_channel.recv() => {
                     if let Some(event) = recv_res {
                         debug!("Received server event: {:?}", event);
-                        match event {
-                            Event::TopologyChange(_) => (), // Refresh immediately
-                            Event::StatusChange(status) => {
-                                // If some node went down/up, update it's marker and refresh
-                                // later as planned.
-
-                                match status {
-                                    StatusChangeEvent::Down(addr) => self.change_node_down_marker(addr, true),
-                                    StatusChangeEvent::Up(addr) => self.change_node_down_marker(addr, false),
-                                }
-                                continue;
-                            },
-                            _ => continue, // Don't go to refreshing
+
+                        // Pass the event to subscribers, it's fine if there are none
+                        let _ = self.cluster_events_sender.send(event.clone());
+
+                        if !self.handle_server_event(event).await {
+                            continue; // Don't go to refreshing
                         }
                     } else {
                         // If server_events_channel was closed, than TopologyReader was dropped,
@@ -348,7 +796,7 @@ impl ClusterWorker {
 
                             let cluster_data = self.cluster_data.load_full();
                             let use_keyspace_future = Self::handle_use_keyspace_request(cluster_data, request);
-                            tokio::spawn(use_keyspace_future);
+                            runtime::spawn(use_keyspace_future);
                         },
                         None => return, // If use_keyspace_channel was closed then cluster was dropped, we can stop working
                     }
@@ -370,18 +818,90 @@ impl ClusterWorker {
         }
     }
 
-    fn change_node_down_marker(&mut self, addr: SocketAddr, is_down: bool) {
+    // Resolves when the control connection is broken,
+    // but not sooner than CONTROL_CONNECTION_FAILOVER_COOLDOWN after the last refresh
+    async fn control_connection_broken(
+        topology_reader: &TopologyReader,
+        last_refresh_time: std::time::Instant,
+    ) {
+        topology_reader.wait_until_control_connection_broken().await;
+        runtime::sleep_until(last_refresh_time + CONTROL_CONNECTION_FAILOVER_COOLDOWN).await;
+    }
+
+    // Updates cluster data according to the received event.
+    // Returns true if topology should be refreshed immediately.
+    async fn handle_server_event(&mut self, event: Event) -> bool {
+        match event {
+            Event::TopologyChange(TopologyChangeEvent::NewNode(_)) => true,
+            Event::TopologyChange(TopologyChangeEvent::RemovedNode(addr)) => {
+                // Stop using the removed node right away, refresh will confirm the new topology
+                self.remove_node(self.address_translator.translate(addr));
+                true
+            }
+            Event::StatusChange(status) => {
+                // If some node went down/up, update it's marker and refresh
+                // later as planned.
+                // If the node is unknown it has probably just joined - refresh immediately.
+                match status {
+                    StatusChangeEvent::Down(addr) => {
+                        let addr = self.address_translator.translate(addr);
+                        !self.change_node_down_marker(addr, true)
+                    }
+                    StatusChangeEvent::Up(addr) => {
+                        let addr = self.address_translator.translate(addr);
+                        !self.change_node_down_marker(addr, false)
+                    }
+                }
+            }
+            Event::SchemaChange(schema_change) => {
+                // Notify subscribers right away, then fetch the new schema of the affected keyspace.
+                // Whole topology is refreshed only if that fails
+                let keyspace_name = schema_change.keyspace_name().to_string();
+                let _ = self.schema_change_events_sender.send(schema_change);
+
+                match self.refresh_keyspace(&keyspace_name).await {
+                    Ok(()) => false,
+                    Err(err) => {
+                        warn!(
+                            "Failed to fetch schema of keyspace {} ({}), refreshing topology",
+                            keyspace_name, err
+                        );
+                        true
+                    }
+                }
+            }
+        }
+    }
+
+    // Returns false if there is no node with the given address
+    fn change_node_down_marker(&mut self, addr: SocketAddr, is_down: bool) -> bool {
         let cluster_data = self.cluster_data.load_full();
 
-        let node = match cluster_data.known_peers.get(&addr) {
+        let node = match cluster_data.find_node_by_ip(addr) {
             Some(node) => node,
             None => {
                 warn!("Unknown node address {}", addr);
-                return;
+                return false;
             }
         };
 
         node.change_down_marker(is_down);
+        true
+    }
+
+    fn remove_node(&mut self, addr: SocketAddr) {
+        let cluster_data = self.cluster_data.load_full();
+
+        let node_address = match cluster_data.find_node_by_ip(addr) {
+            Some(node) => node.address,
+            None => return,
+        };
+
+        // known_peers must stay nonempty, the last node is removed only by a refresh
+        if cluster_data.known_peers.len() > 1 {
+            let new_cluster_data = Arc::new(cluster_data.without_node(node_address));
+            self.update_cluster_data(new_cluster_data);
+        }
     }
 
     async fn handle_use_keyspace_request(
@@ -440,19 +960,614 @@ impl ClusterWorker {
         let topo_info = self.topology_reader.read_topology_info().await?;
         let cluster_data: Arc<ClusterData> = self.cluster_data.load_full();
 
-        let new_cluster_data = Arc::new(ClusterData::new(
-            topo_info,
-            &self.connection_config,
-            &cluster_data.known_peers,
-            &self.used_keyspace,
-        ));
+        // In large clusters topology rarely changes, don't rebuild cluster data if it's up to date
+        if cluster_data.matches_topology_info(&topo_info) {
+            debug!("Topology didn't change");
+            return Ok(());
+        }
+
+        let new_cluster_data = if cluster_data.matches_nodes(&topo_info) {
+            // Only the schema changed, nodes, the ring and unaffected replica sets are kept
+            debug!("Schema changed, nodes didn't");
+            Arc::new(cluster_data.with_schema(topo_info.keyspaces, topo_info.cluster_info))
+        } else {
+            let new_addresses: HashSet<SocketAddr> =
+                topo_info.peers.iter().map(|peer| peer.address).collect();
+
+            for address in &new_addresses {
+                if !cluster_data.known_peers.contains_key(address) {
+                    debug!("Adding node {} to cluster data", address);
+                }
+            }
+
+            for address in cluster_data.known_peers.keys() {
+                if !new_addresses.contains(address) {
+                    debug!("Removing node {} from cluster data", address);
+                }
+            }
+
+            // Nodes which didn't change are reused along with their connections
+            Arc::new(ClusterData::new(
+                topo_info,
+                &self.connection_config,
+                &cluster_data.known_peers,
+                &self.used_keyspace,
+                &self.pooled_datacenter,
+                &self.host_filter,
+            ))
+        };
+
+        self.update_cluster_data(new_cluster_data.clone());
+
+        for keyspace_name in changed_keyspaces(&cluster_data.keyspaces, &new_cluster_data.keyspaces)
+        {
+            debug!("Schema of keyspace {} changed", keyspace_name);
+            let _ = self
+                .schema_metadata_changes_sender
+                .send(keyspace_name.clone());
+        }
 
-        self.update_cluster_data(new_cluster_data);
+        Ok(())
+    }
+
+    // Fetches schema of a single keyspace and updates cluster data if it has changed
+    async fn refresh_keyspace(&mut self, keyspace_name: &str) -> Result<(), QueryError> {
+        let keyspace = self.topology_reader.read_keyspace(keyspace_name).await?;
+        let cluster_data: Arc<ClusterData> = self.cluster_data.load_full();
+
+        if cluster_data.keyspaces.get(keyspace_name) == keyspace.as_ref() {
+            return Ok(());
+        }
+
+        debug!("Schema of keyspace {} changed", keyspace_name);
+        let new_cluster_data = cluster_data.with_keyspace(keyspace_name, keyspace);
+        self.update_cluster_data(Arc::new(new_cluster_data));
+
+        let _ = self
+            .schema_metadata_changes_sender
+            .send(keyspace_name.to_string());
 
         Ok(())
     }
 
     fn update_cluster_data(&mut self, new_cluster_data: Arc<ClusterData>) {
+        self.metrics
+            .prune_nodes(|address| new_cluster_data.known_peers.contains_key(address));
         self.cluster_data.store(new_cluster_data);
     }
 }
+
+// Returns names of keyspaces which were created, dropped or modified
+fn changed_keyspaces<'a>(
+    old_keyspaces: &'a HashMap<String, Keyspace>,
+    new_keyspaces: &'a HashMap<String, Keyspace>,
+) -> impl Iterator<Item = &'a String> + 'a {
+    let changed_or_dropped = old_keyspaces
+        .iter()
+        .filter(move |(name, keyspace)| new_keyspaces.get(*name) != Some(*keyspace))
+        .map(|(name, _)| name);
+
+    let created = new_keyspaces
+        .keys()
+        .filter(move |name| !old_keyspaces.contains_key(*name));
+
+    changed_or_dropped.chain(created)
+}
+
+// Converts broadcast receiver to a stream, skipping messages lost because of lagging
+fn broadcast_receiver_to_stream<T: Clone + Send + 'static>(
+    receiver: broadcast::Receiver<T>,
+) -> impl Stream<Item = T> + Send + Unpin {
+    futures::stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(message) => return Some((message, receiver)),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!(
+                        "Cluster event subscriber lagged, skipped {} messages",
+                        skipped
+                    );
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+    .boxed()
+}
+
+// Keyspaces created and managed by the database itself, e.g. system_schema or system_auth
+fn is_system_keyspace(keyspace_name: &str) -> bool {
+    keyspace_name == "system" || keyspace_name.starts_with("system_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::transport::topology::Peer;
+
+    // creates TopologyInfo with 3 nodes, each one owning a single token
+    fn mock_topology_info() -> TopologyInfo {
+        let peers = [("eu", 1), ("eu", 2), ("us", 3)]
+            .iter()
+            .map(|(dc, id)| Peer {
+                datacenter: Some(dc.to_string()),
+                rack: None,
+                host_id: None,
+                address: SocketAddr::from(([127, 0, 0, *id], 9042)),
+                tokens: vec![Token::Murmur3(*id as i64 * 100)],
+            })
+            .collect::<Vec<_>>();
+
+        TopologyInfo {
+            peers,
+            keyspaces: HashMap::new(),
+            cluster_info: Default::default(),
+        }
+    }
+
+    fn mock_cluster_data() -> ClusterData {
+        ClusterData::new(
+            mock_topology_info(),
+            &Default::default(),
+            &HashMap::new(),
+            &None,
+            &None,
+            &None,
+        )
+    }
+
+    // ConnectionKeeper (which lives in Node) requires context of Tokio runtime
+    #[tokio::test]
+    async fn test_find_node_by_ip() {
+        let cluster = mock_cluster_data();
+
+        let same_port = SocketAddr::from(([127, 0, 0, 2], 9042));
+        let other_port = SocketAddr::from(([127, 0, 0, 2], 19042));
+        let unknown = SocketAddr::from(([127, 0, 0, 4], 9042));
+
+        assert_eq!(
+            cluster.find_node_by_ip(same_port).unwrap().address,
+            same_port
+        );
+        assert_eq!(
+            cluster.find_node_by_ip(other_port).unwrap().address,
+            same_port
+        );
+        assert!(cluster.find_node_by_ip(unknown).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_without_node() {
+        let cluster = mock_cluster_data();
+
+        let removed = SocketAddr::from(([127, 0, 0, 3], 9042));
+        let new_cluster = cluster.without_node(removed);
+
+        assert_eq!(new_cluster.known_peers.len(), 2);
+        assert_eq!(new_cluster.all_nodes.len(), 2);
+        assert_eq!(new_cluster.ring.len(), 2);
+        assert!(new_cluster.find_node_by_ip(removed).is_none());
+        assert!(!new_cluster.datacenters.contains_key("us"));
+        assert_eq!(new_cluster.datacenters.get("eu").unwrap().nodes.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_token_ranges() {
+        let range = |start, end| TokenRange {
+            start: Token::Murmur3(start),
+            end: Token::Murmur3(end),
+        };
+
+        assert_eq!(
+            mock_cluster_data().token_ranges(),
+            vec![
+                range(i64::MIN, 100),
+                range(101, 200),
+                range(201, 300),
+                range(301, i64::MAX)
+            ]
+        );
+
+        let mut topology_info = mock_topology_info();
+        topology_info.peers[0].tokens[0] = Token::Murmur3(i64::MIN);
+        topology_info.peers[2].tokens[0] = Token::Murmur3(i64::MAX);
+        let cluster = ClusterData::new(
+            topology_info,
+            &Default::default(),
+            &HashMap::new(),
+            &None,
+            &None,
+            &None,
+        );
+        assert_eq!(
+            cluster.token_ranges(),
+            vec![
+                range(i64::MIN, i64::MIN),
+                range(i64::MIN + 1, 200),
+                range(201, i64::MAX)
+            ]
+        );
+
+        // Ranges of other partitioners span their own tokens
+        let mut topology_info = mock_topology_info();
+        topology_info.cluster_info.partitioner =
+            Some("org.apache.cassandra.dht.RandomPartitioner".to_string());
+        for (i, peer) in topology_info.peers.iter_mut().enumerate() {
+            peer.tokens = vec![Token::Random((i as u128 + 1) << 100)];
+        }
+        let cluster = ClusterData::new(
+            topology_info,
+            &Default::default(),
+            &HashMap::new(),
+            &None,
+            &None,
+            &None,
+        );
+        let ranges = cluster.token_ranges();
+        assert_eq!(ranges.len(), 4);
+        assert_eq!(ranges[0].start, Token::Random(0));
+        assert_eq!(ranges[1].start, Token::Random((1 << 100) + 1));
+        assert_eq!(ranges[3].end, Token::Random(1 << 127));
+    }
+
+    #[tokio::test]
+    async fn test_matches_topology_info() {
+        let cluster = mock_cluster_data();
+        assert!(cluster.matches_topology_info(&mock_topology_info()));
+
+        let mut moved_token = mock_topology_info();
+        moved_token.peers[0].tokens[0] = Token::Murmur3(150);
+        assert!(!cluster.matches_topology_info(&moved_token));
+
+        let mut changed_rack = mock_topology_info();
+        changed_rack.peers[1].rack = Some("r1".to_string());
+        assert!(!cluster.matches_topology_info(&changed_rack));
+
+        let mut removed_node = mock_topology_info();
+        removed_node.peers.pop();
+        assert!(!cluster.matches_topology_info(&removed_node));
+    }
+
+    #[tokio::test]
+    async fn test_with_schema() {
+        use crate::transport::topology::Strategy;
+
+        let keyspace = |replication_factor| Keyspace {
+            strategy: Strategy::SimpleStrategy { replication_factor },
+            tables: HashMap::new(),
+            user_defined_types: HashMap::new(),
+            durable_writes: true,
+        };
+
+        let topology_info = |keyspaces: Vec<(&str, Keyspace)>| TopologyInfo {
+            keyspaces: keyspaces
+                .into_iter()
+                .map(|(name, keyspace)| (name.to_string(), keyspace))
+                .collect(),
+            ..mock_topology_info()
+        };
+        let cluster_data = |info: TopologyInfo| {
+            ClusterData::new(
+                info,
+                &Default::default(),
+                &HashMap::new(),
+                &None,
+                &None,
+                &None,
+            )
+        };
+
+        let cluster = cluster_data(topology_info(vec![
+            ("ks1", keyspace(1)),
+            ("ks2", keyspace(2)),
+        ]));
+
+        // ks2 is altered to use the strategy of ks1, ks3 uses a new one
+        let topology_info = topology_info(vec![
+            ("ks1", keyspace(1)),
+            ("ks2", keyspace(1)),
+            ("ks3", keyspace(3)),
+        ]);
+        assert!(cluster.matches_nodes(&topology_info));
+        assert!(!cluster.matches_topology_info(&topology_info));
+
+        let new_cluster = cluster.with_schema(
+            topology_info.keyspaces.clone(),
+            topology_info.cluster_info.clone(),
+        );
+        assert!(new_cluster.matches_topology_info(&topology_info));
+
+        for (old_node, new_node) in cluster.all_nodes.iter().zip(&new_cluster.all_nodes) {
+            assert!(Arc::ptr_eq(old_node, new_node));
+        }
+
+        let old_rf1 = &cluster.replica_sets["ks1"];
+        assert!(Arc::ptr_eq(old_rf1, &new_cluster.replica_sets["ks1"]));
+        assert!(Arc::ptr_eq(old_rf1, &new_cluster.replica_sets["ks2"]));
+        assert!(!Arc::ptr_eq(old_rf1, &new_cluster.replica_sets["ks3"]));
+
+        // The reused replica sets are the same as computed from scratch
+        let rebuilt = cluster_data(topology_info);
+        let token = Token::Murmur3(150);
+        for keyspace_name in ["ks1", "ks2", "ks3"] {
+            let replicas = |cluster: &ClusterData| -> Vec<SocketAddr> {
+                cluster.replica_sets[keyspace_name]
+                    .replicas(&token)
+                    .iter()
+                    .map(|node| node.address)
+                    .collect()
+            };
+            assert_eq!(replicas(&new_cluster), replicas(&rebuilt));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_with_keyspace() {
+        use crate::transport::topology::Strategy;
+
+        let keyspace = |replication_factor| Keyspace {
+            strategy: Strategy::SimpleStrategy { replication_factor },
+            tables: HashMap::new(),
+            user_defined_types: HashMap::new(),
+            durable_writes: true,
+        };
+
+        let mut topology_info = mock_topology_info();
+        topology_info.keyspaces = [("ks1".to_string(), keyspace(1))].iter().cloned().collect();
+        let cluster = ClusterData::new(
+            topology_info,
+            &Default::default(),
+            &HashMap::new(),
+            &None,
+            &None,
+            &None,
+        );
+
+        let created = cluster.with_keyspace("ks2", Some(keyspace(2)));
+        assert_eq!(created.keyspaces.len(), 2);
+        assert_eq!(created.keyspaces["ks2"], keyspace(2));
+        assert!(created.replica_sets.contains_key("ks2"));
+        assert!(Arc::ptr_eq(
+            &cluster.replica_sets["ks1"],
+            &created.replica_sets["ks1"]
+        ));
+
+        let dropped = created.with_keyspace("ks1", None);
+        assert!(!dropped.keyspaces.contains_key("ks1"));
+        assert!(!dropped.replica_sets.contains_key("ks1"));
+        assert!(dropped.replica_sets.contains_key("ks2"));
+    }
+
+    #[test]
+    fn test_changed_keyspaces() {
+        use crate::transport::topology::Strategy;
+
+        let keyspace = |replication_factor| Keyspace {
+            strategy: Strategy::SimpleStrategy { replication_factor },
+            tables: HashMap::new(),
+            user_defined_types: HashMap::new(),
+            durable_writes: true,
+        };
+
+        let old_keyspaces: HashMap<String, Keyspace> = vec![
+            ("same".to_string(), keyspace(1)),
+            ("altered".to_string(), keyspace(1)),
+            ("dropped".to_string(), keyspace(1)),
+        ]
+        .into_iter()
+        .collect();
+
+        let new_keyspaces: HashMap<String, Keyspace> = vec![
+            ("same".to_string(), keyspace(1)),
+            ("altered".to_string(), keyspace(3)),
+            ("created".to_string(), keyspace(1)),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut changed: Vec<&String> = changed_keyspaces(&old_keyspaces, &new_keyspaces).collect();
+        changed.sort_unstable();
+
+        assert_eq!(changed, vec!["altered", "created", "dropped"]);
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn cluster_info_from_mock_node() {
+        use crate::proxy::MockNode;
+        use crate::SessionBuilder;
+
+        // The mock node supports only protocol v4, so the session falls back to it
+        let node = MockNode::new().run().await.unwrap();
+        let session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .protocol_version(ProtocolVersion::V5)
+            .build()
+            .await
+            .unwrap();
+
+        let cluster_data = session.get_cluster_data();
+        assert_eq!(cluster_data.cluster_name(), Some("Mock Cluster"));
+        assert_eq!(cluster_data.release_version(), Some("3.0.8"));
+        assert_eq!(cluster_data.cql_version(), Some("3.3.1"));
+        assert_eq!(cluster_data.native_protocol_version(), Some("4"));
+        assert_eq!(cluster_data.protocol_version(), ProtocolVersion::V4);
+
+        node.finish().await.unwrap();
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn topology_events_from_mock_node() {
+        use crate::proxy::MockNode;
+        use crate::{Session, SessionBuilder};
+
+        let node = MockNode::new().run().await.unwrap();
+        let session: Session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .build()
+            .await
+            .unwrap();
+        let mut events = session.cluster_events();
+
+        let is_down = |session: &Session| session.get_cluster_data().all_nodes[0].is_down();
+        assert!(!is_down(&session));
+
+        node.send_event(&Event::StatusChange(StatusChangeEvent::Down(node.addr())))
+            .unwrap();
+        assert!(matches!(
+            events.next().await,
+            Some(Event::StatusChange(StatusChangeEvent::Down(_)))
+        ));
+        while !is_down(&session) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        node.send_event(&Event::StatusChange(StatusChangeEvent::Up(node.addr())))
+            .unwrap();
+        assert!(matches!(
+            events.next().await,
+            Some(Event::StatusChange(StatusChangeEvent::Up(_)))
+        ));
+        while is_down(&session) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // A new node triggers a refresh, which finds the same single node
+        let new_node = SocketAddr::from(([127, 0, 0, 2], 9042));
+        node.send_event(&Event::TopologyChange(TopologyChangeEvent::NewNode(
+            new_node,
+        )))
+        .unwrap();
+        assert!(matches!(
+            events.next().await,
+            Some(Event::TopologyChange(TopologyChangeEvent::NewNode(addr))) if addr == new_node
+        ));
+        session.refresh_topology().await.unwrap();
+        assert_eq!(session.get_cluster_data().all_nodes.len(), 1);
+
+        node.finish().await.unwrap();
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn schema_change_events_from_mock_node() {
+        use crate::frame::response::event::SchemaChangeType;
+        use crate::proxy::MockNode;
+        use crate::{Session, SessionBuilder};
+        use futures::StreamExt;
+
+        let node = MockNode::new().run().await.unwrap();
+        let session: Session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .build()
+            .await
+            .unwrap();
+        let mut receiver = session.schema_change_receiver();
+        // Events are received along with changes of metadata noticed during refreshes
+        let mut events = session
+            .watch_schema("ks")
+            .filter_map(|notification| async move {
+                match notification {
+                    SchemaChangeNotification::Event(event) => Some(event),
+                    SchemaChangeNotification::MetadataChanged { .. } => None,
+                }
+            })
+            .boxed();
+
+        node.send_event(&Event::StatusChange(StatusChangeEvent::Up(node.addr())))
+            .unwrap();
+        node.send_event(&Event::SchemaChange(SchemaChangeEvent::TableChange {
+            change_type: SchemaChangeType::Created,
+            keyspace_name: "other_ks".to_string(),
+            object_name: "t".to_string(),
+        }))
+        .unwrap();
+        node.send_event(&Event::SchemaChange(SchemaChangeEvent::TableChange {
+            change_type: SchemaChangeType::Created,
+            keyspace_name: "ks".to_string(),
+            object_name: "t".to_string(),
+        }))
+        .unwrap();
+        node.send_event(&Event::SchemaChange(SchemaChangeEvent::FunctionChange {
+            change_type: SchemaChangeType::Dropped,
+            keyspace_name: "ks".to_string(),
+            function_name: "f".to_string(),
+            arguments: vec!["int".to_string(), "text".to_string()],
+        }))
+        .unwrap();
+
+        // Other events and changes of other keyspaces aren't received
+        match events.next().await.unwrap() {
+            SchemaChangeEvent::TableChange {
+                change_type: SchemaChangeType::Created,
+                keyspace_name,
+                object_name,
+            } => assert_eq!((keyspace_name.as_str(), object_name.as_str()), ("ks", "t")),
+            event => panic!("Unexpected event: {:?}", event),
+        }
+        match events.next().await.unwrap() {
+            SchemaChangeEvent::FunctionChange {
+                change_type: SchemaChangeType::Dropped,
+                function_name,
+                arguments,
+                ..
+            } => {
+                assert_eq!(function_name, "f");
+                assert_eq!(arguments, vec!["int", "text"]);
+            }
+            event => panic!("Unexpected event: {:?}", event),
+        }
+
+        // The receiver gets schema changes of all keyspaces
+        for expected_keyspace in ["other_ks", "ks", "ks"] {
+            let event = receiver.recv().await.unwrap();
+            assert_eq!(event.keyspace_name(), expected_keyspace);
+        }
+
+        node.finish().await.unwrap();
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn periodic_topology_refresh() {
+        use crate::proxy::{MockNode, MockResponse, MockRule};
+        use crate::{Session, SessionBuilder};
+
+        // Matched rules make the node record queries to system tables
+        let node = MockNode::new()
+            .rule(MockRule::new(
+                "from system_schema.types",
+                MockResponse::rows(&[], Vec::new()),
+            ))
+            .run()
+            .await
+            .unwrap();
+        let fetched_types_count = || {
+            node.received_statements()
+                .iter()
+                .filter(|statement| statement.contains("system_schema.types"))
+                .count()
+        };
+
+        let _session: Session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .topology_refresh_interval(Duration::from_millis(20))
+            .build()
+            .await
+            .unwrap();
+        let initial_count = fetched_types_count();
+        assert!(initial_count >= 1);
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while fetched_types_count() < initial_count + 2 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        node.finish().await.unwrap();
+    }
+}
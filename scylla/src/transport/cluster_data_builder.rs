@@ -0,0 +1,231 @@
+use crate::routing::Token;
+use crate::transport::cluster::ClusterData;
+use crate::transport::node::Node;
+use crate::transport::topology::{ClusterInfo, Keyspace, Strategy, Table};
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Builds [`ClusterData`] describing an arbitrary cluster, without connecting to it.
+/// Useful for deterministic tests of custom load balancing policies
+/// and of the replicas chosen by `TokenAwarePolicy`.
+///
+/// Nodes don't open connections unless a query is sent through them.
+/// [`build`](ClusterDataBuilder::build) has to be called inside of the async runtime.
+/// # Example
+/// ```
+/// # use scylla::transport::{ClusterData, ClusterDataBuilder, Strategy};
+/// # use scylla::load_balancing::{LoadBalancingPolicy, Statement, RoundRobinPolicy, TokenAwarePolicy};
+/// # use scylla::routing::Token;
+/// # async fn example() {
+/// let cluster: ClusterData = ClusterDataBuilder::new()
+///     .node("127.0.0.1:9042".parse().unwrap(), "dc1", "rack1", vec![-100, 100])
+///     .node("127.0.0.2:9042".parse().unwrap(), "dc1", "rack2", vec![0])
+///     .keyspace("ks", Strategy::SimpleStrategy { replication_factor: 2 })
+///     .build();
+///
+/// let policy = TokenAwarePolicy::new(Box::new(RoundRobinPolicy::new()));
+/// let mut statement = Statement::default();
+/// statement.token = Some(Token::Murmur3(50));
+/// statement.keyspace = Some("ks");
+/// let plan: Vec<_> = policy.plan(&statement, &cluster).map(|node| node.address).collect();
+/// # }
+/// ```
+#[derive(Default)]
+pub struct ClusterDataBuilder {
+    nodes: Vec<(Node, Vec<Token>)>,
+    keyspaces: HashMap<String, Keyspace>,
+    cluster_info: ClusterInfo,
+}
+
+impl ClusterDataBuilder {
+    /// Creates a builder of an empty cluster
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a node which owns the given tokens of `Murmur3Partitioner`.
+    /// Nodes keep the order of adding in [`ClusterData::all_nodes`]
+    pub fn node(
+        mut self,
+        address: SocketAddr,
+        datacenter: impl Into<String>,
+        rack: impl Into<String>,
+        tokens: impl IntoIterator<Item = i64>,
+    ) -> Self {
+        let node = Node::new(
+            address,
+            Default::default(),
+            Some(datacenter.into()),
+            Some(rack.into()),
+            None,
+            None,
+            false,
+        );
+        let tokens = tokens.into_iter().map(Token::Murmur3).collect();
+        self.nodes.push((node, tokens));
+        self
+    }
+
+    /// Adds a keyspace with the given replication strategy and no tables
+    pub fn keyspace(mut self, name: impl Into<String>, strategy: Strategy) -> Self {
+        self.keyspaces.insert(
+            name.into(),
+            Keyspace {
+                strategy,
+                tables: HashMap::new(),
+                user_defined_types: HashMap::new(),
+                durable_writes: true,
+            },
+        );
+        self
+    }
+
+    /// Adds a table to a keyspace added before
+    /// # Panics
+    /// Panics if the keyspace wasn't added
+    pub fn table(mut self, keyspace: &str, name: impl Into<String>, table: Table) -> Self {
+        self.keyspaces
+            .get_mut(keyspace)
+            .unwrap_or_else(|| panic!("Keyspace {} has to be added before its tables", keyspace))
+            .tables
+            .insert(name.into(), table);
+        self
+    }
+
+    /// Sets information reported by the cluster, e.g. its name
+    pub fn cluster_info(mut self, cluster_info: ClusterInfo) -> Self {
+        self.cluster_info = cluster_info;
+        self
+    }
+
+    pub fn build(self) -> ClusterData {
+        let nodes = self
+            .nodes
+            .into_iter()
+            .map(|(node, tokens)| (Arc::new(node), tokens))
+            .collect();
+
+        ClusterData::from_nodes(nodes, self.keyspaces, self.cluster_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClusterDataBuilder;
+    use crate::load_balancing::{
+        DcAwareRoundRobinPolicy, LoadBalancingPolicy, Statement, TokenAwarePolicy,
+    };
+    use crate::routing::{Partitioner, Token};
+    use crate::transport::{ClusterInfo, Strategy, Table};
+    use std::net::SocketAddr;
+
+    fn addr(id: u8) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, id], 9042))
+    }
+
+    #[tokio::test]
+    async fn build_cluster_data() {
+        let cluster = ClusterDataBuilder::new()
+            .node(addr(1), "waw", "r1", vec![0, 300])
+            .node(addr(2), "waw", "r2", vec![100])
+            .node(addr(3), "her", "r3", vec![200])
+            .keyspace(
+                "ks",
+                Strategy::NetworkTopologyStrategy {
+                    datacenter_repfactors: vec![("waw".to_string(), 2), ("her".to_string(), 1)]
+                        .into_iter()
+                        .collect(),
+                },
+            )
+            .table("ks", "t", Table::default())
+            .build();
+
+        assert_eq!(
+            cluster
+                .all_nodes
+                .iter()
+                .map(|n| n.address)
+                .collect::<Vec<_>>(),
+            vec![addr(1), addr(2), addr(3)]
+        );
+        assert_eq!(cluster.datacenters["waw"].nodes.len(), 2);
+        assert_eq!(cluster.datacenters["waw"].rack_count, 2);
+        assert_eq!(cluster.datacenters["her"].rack_count, 1);
+        assert!(cluster.keyspaces["ks"].tables.contains_key("t"));
+        assert!(cluster.all_nodes.iter().all(|node| !node.is_connected()));
+
+        let ring: Vec<(Token, SocketAddr)> = cluster
+            .ring
+            .iter()
+            .map(|(token, node)| (token.clone(), node.address))
+            .collect();
+        assert_eq!(
+            ring,
+            vec![
+                (Token::Murmur3(0), addr(1)),
+                (Token::Murmur3(100), addr(2)),
+                (Token::Murmur3(200), addr(3)),
+                (Token::Murmur3(300), addr(1))
+            ]
+        );
+
+        let policy =
+            TokenAwarePolicy::new(Box::new(DcAwareRoundRobinPolicy::new("her".to_string())));
+        let statement = Statement {
+            token: Some(Token::Murmur3(150)),
+            keyspace: Some("ks"),
+            ..Default::default()
+        };
+        let mut replicas: Vec<SocketAddr> = policy
+            .plan(&statement, &cluster)
+            .map(|node| node.address)
+            .collect();
+        replicas[1..].sort();
+        assert_eq!(replicas, vec![addr(3), addr(1), addr(2)]);
+    }
+
+    #[tokio::test]
+    async fn table_partitioners() {
+        let cdc_log = Table {
+            partitioner: Some("com.scylladb.dht.CDCPartitioner".to_string()),
+            ..Default::default()
+        };
+        let builder = || {
+            ClusterDataBuilder::new()
+                .keyspace(
+                    "ks",
+                    Strategy::SimpleStrategy {
+                        replication_factor: 1,
+                    },
+                )
+                .table("ks", "t", Table::default())
+                .table("ks", "t_scylla_cdc_log", cdc_log.clone())
+        };
+
+        let cluster = builder().build();
+        assert_eq!(cluster.table_partitioner("ks", "t"), Partitioner::Murmur3);
+        assert_eq!(
+            cluster.table_partitioner("ks", "t_scylla_cdc_log"),
+            Partitioner::Cdc
+        );
+        assert_eq!(
+            cluster.table_partitioner("unknown", "t"),
+            Partitioner::Murmur3
+        );
+
+        // Tables without their own partitioner use the cluster's partitioner
+        let cluster = builder()
+            .cluster_info(ClusterInfo {
+                partitioner: Some("org.apache.cassandra.dht.RandomPartitioner".to_string()),
+                ..Default::default()
+            })
+            .build();
+        assert_eq!(cluster.table_partitioner("ks", "t"), Partitioner::Random);
+        assert_eq!(
+            cluster.table_partitioner("ks", "t_scylla_cdc_log"),
+            Partitioner::Cdc
+        );
+    }
+}
@@ -1,9 +1,9 @@
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use futures::{future::RemoteHandle, FutureExt};
+use thiserror::Error;
 use tokio::io::{split, AsyncRead, AsyncWrite, AsyncWriteExt};
-use tokio::net::{TcpSocket, TcpStream};
-use tokio::sync::{mpsc, oneshot};
-use tracing::{error, warn};
+use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, debug_span, warn, Instrument};
 use uuid::Uuid;
 
 #[cfg(feature = "ssl")]
@@ -13,42 +13,50 @@ use std::pin::Pin;
 #[cfg(feature = "ssl")]
 use tokio_openssl::SslStream;
 
-use std::collections::HashMap;
+#[cfg(feature = "fault-injection")]
+use super::fault_injection::{ConnectFault, FaultInjectionPolicy, RequestFault};
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::io::ErrorKind;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::atomic::{self, AtomicBool};
 use std::sync::Arc;
 use std::sync::Mutex as StdMutex;
-use std::{
-    cmp::Ordering,
-    net::{Ipv4Addr, Ipv6Addr},
-};
 
-use super::errors::{BadKeyspaceName, BadQuery, DbError, QueryError};
+use super::errors::{BadKeyspaceName, BadQuery, DbError, ProtocolError, QueryError};
+use super::runtime::{self, DefaultRuntime, Runtime};
 
 use crate::batch::{Batch, BatchStatement};
+use crate::cql_to_rust::{FromRow, FromRowError};
 use crate::frame::{
     self,
+    frame_errors::ParseError,
     request::{self, batch, execute, query, register, Request},
     response::{event::Event, result, Response, ResponseOpcode},
     server_event_type::EventType,
+    types::Consistency,
     value::{BatchValues, ValueList},
     FrameParams, SerializedRequest,
 };
 use crate::query::Query;
 use crate::routing::ShardInfo;
 use crate::statement::prepared_statement::PreparedStatement;
-use crate::transport::session::IntoTypedRows;
-use crate::transport::Authenticator;
-use crate::transport::Authenticator::{
-    AllowAllAuthenticator, CassandraAllowAllAuthenticator, CassandraPasswordAuthenticator,
-    PasswordAuthenticator, ScyllaTransitionalAuthenticator,
-};
-use crate::transport::Compression;
+use crate::transport::authentication::Authenticator;
+use crate::transport::node::PoolSize;
+use crate::transport::reconnection_policy::{ExponentialReconnectionPolicy, ReconnectionPolicy};
+use crate::transport::session::{IntoTypedRows, TypedRowIter};
+use crate::transport::{Compression, ProtocolVersion};
 
 // Queries for schema agreement
 const LOCAL_VERSION: &str = "SELECT schema_version FROM system.local WHERE key='local'";
 
+// Scylla extension marking prepared LWT statements with a bit in their metadata flags
+const LWT_ADD_METADATA_MARK: &str = "SCYLLA_LWT_ADD_METADATA_MARK";
+const LWT_OPTIMIZATION_META_BIT_MASK: &str = "LWT_OPTIMIZATION_META_BIT_MASK";
+
 pub struct Connection {
     submit_channel: mpsc::Sender<Task>,
     _worker_handle: RemoteHandle<()>,
@@ -57,6 +65,23 @@ pub struct Connection {
     shard_info: Option<ShardInfo>,
     config: ConnectionConfig,
     is_shard_aware: bool,
+    // Bit of prepared metadata flags marking LWT statements, if the extension was negotiated
+    lwt_mask: Option<i32>,
+    // Ids of statements which got a different id when reprepared on this connection
+    reprepared_ids: StdMutex<HashMap<Bytes, Bytes>>,
+    framing: Arc<Framing>,
+    // Limits the number of requests in flight, see ConnectionConfig::max_requests_per_connection
+    in_flight_permits: Arc<Semaphore>,
+}
+
+// How frames are sent after the handshake, shared between the connection and its router.
+// Used only in protocol v5, where frames are wrapped in segments after the handshake
+#[derive(Default)]
+struct Framing {
+    // Set by the reader when the handshake ends
+    segments: AtomicBool,
+    // LZ4 compression was requested in STARTUP, payloads of segments are compressed
+    compressed: AtomicBool,
 }
 
 type ResponseHandler = oneshot::Sender<Result<TaskResponse, QueryError>>;
@@ -64,6 +89,11 @@ type ResponseHandler = oneshot::Sender<Result<TaskResponse, QueryError>>;
 struct Task {
     serialized_request: SerializedRequest,
     response_handler: ResponseHandler,
+    // Held until the response arrives, None for keepalive requests
+    permit: Option<OwnedSemaphorePermit>,
+    // Break the connection after sending the request
+    #[cfg(feature = "fault-injection")]
+    disconnect: bool,
 }
 
 struct TaskResponse {
@@ -74,6 +104,8 @@ struct TaskResponse {
 
 pub struct QueryResponse {
     pub response: Response,
+    /// Address of the node which sent the response
+    pub node: SocketAddr,
     pub tracing_id: Option<Uuid>,
     pub warnings: Vec<String>,
 }
@@ -82,34 +114,504 @@ pub struct QueryResponse {
 /// Contains all rows returned by the database and some more information
 #[derive(Default, Debug)]
 pub struct QueryResult {
-    /// Rows returned by the database
-    pub rows: Option<Vec<result::Row>>,
+    /// Rows returned by the database, kept in the received form until they are read.  
+    /// [`Rows::raw_rows`](result::Rows::raw_rows) reads values without copying them
+    pub rows: Option<result::Rows>,
     /// Warnings returned by the database
     pub warnings: Vec<String>,
     /// CQL Tracing uuid - can only be Some if tracing is enabled for this query
     pub tracing_id: Option<Uuid>,
     /// Paging state returned from the server
     pub paging_state: Option<Bytes>,
+    /// Specification of the returned columns, empty if the result contains no rows
+    pub col_specs: Vec<result::ColumnSpec>,
 }
 
-/// Result of Session::batch(). Contains no rows, only some useful information.
-pub struct BatchResult {
-    /// Warnings returned by the database
-    pub warnings: Vec<String>,
-    /// CQL Tracing uuid - can only be Some if tracing is enabled for this batch
+impl QueryResult {
+    /// Returns an iterator over the received rows parsed as `RowT`.  
+    /// Fails if the result contains no rows, e.g. when it's a result of an `INSERT`
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// let result = session.query("SELECT a, b FROM ks.tab", &[]).await?;
+    ///
+    /// for row in result.rows_typed::<(i32, String)>()? {
+    ///     let (a, b): (i32, String) = row?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn rows_typed<RowT: FromRow>(self) -> Result<TypedRowIter<RowT>, RowsExpectedError> {
+        self.rows
+            .map(IntoTypedRows::into_typed)
+            .ok_or(RowsExpectedError)
+    }
+
+    /// Returns the received rows with values accessible by column names.  
+    /// Fails if the result contains no rows, e.g. when it's a result of an `INSERT`
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// let result = session.query("SELECT * FROM ks.tab", &[]).await?;
+    ///
+    /// for row in result.rows_named()? {
+    ///     let row = row?;
+    ///     let a: i32 = row.get("a")?;
+    ///     for (name, value) in row.iter() {
+    ///         println!("{}: {:?}", name, value);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn rows_named(
+        self,
+    ) -> Result<impl Iterator<Item = Result<result::NamedRow, ParseError>>, RowsExpectedError> {
+        let rows = self.rows.ok_or(RowsExpectedError)?;
+        let names = result::col_names(&self.col_specs);
+
+        Ok(rows
+            .into_iter()
+            .map(move |row| Ok(result::NamedRow::new(names.clone(), row?))))
+    }
+
+    /// Returns names of the returned columns, in the order of values in each row
+    pub fn col_names(&self) -> Vec<&str> {
+        self.col_specs
+            .iter()
+            .map(result::ColumnSpec::name)
+            .collect()
+    }
+
+    /// Returns the number of received rows.  
+    /// Fails if the result contains no rows, e.g. when it's a result of an `INSERT`
+    pub fn rows_num(&self) -> Result<usize, RowsExpectedError> {
+        self.rows
+            .as_ref()
+            .map(result::Rows::rows_count)
+            .ok_or(RowsExpectedError)
+    }
+
+    /// Returns the first received row.  
+    /// Fails if the result contains no rows or the row list is empty
+    pub fn first_row(self) -> Result<result::Row, FirstRowError> {
+        self.maybe_first_row()?.ok_or(FirstRowError::RowsEmpty)
+    }
+
+    /// Returns the first received row parsed as `RowT`.  
+    /// Fails if the result contains no rows, the row list is empty or the row can't be parsed
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// let (count,): (i64,) = session
+    ///     .query("SELECT COUNT(*) FROM ks.tab", &[])
+    ///     .await?
+    ///     .first_row_typed::<(i64,)>()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn first_row_typed<RowT: FromRow>(self) -> Result<RowT, FirstRowTypedError> {
+        Ok(self.first_row()?.into_typed()?)
+    }
+
+    /// Returns the first received row, or `None` if the row list is empty.  
+    /// Fails if the result contains no rows, e.g. when it's a result of an `INSERT`
+    pub fn maybe_first_row(self) -> Result<Option<result::Row>, MaybeFirstRowError> {
+        // Only the first row is deserialized
+        let first_row = self.rows.ok_or(RowsExpectedError)?.into_iter().next();
+        first_row
+            .transpose()
+            .map_err(|err| MaybeFirstRowError::MalformedRow(err.to_string()))
+    }
+
+    /// Returns the first received row parsed as `RowT`, or `None` if the row list is empty.  
+    /// Fails if the result contains no rows or the row can't be parsed
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// let user: Option<(i32, String)> = session
+    ///     .query("SELECT id, name FROM ks.users WHERE id = 1", &[])
+    ///     .await?
+    ///     .maybe_first_row_typed::<(i32, String)>()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn maybe_first_row_typed<RowT: FromRow>(
+        self,
+    ) -> Result<Option<RowT>, MaybeFirstRowTypedError> {
+        match self.maybe_first_row()? {
+            Some(row) => Ok(Some(row.into_typed()?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the only received row.  
+    /// Fails if the result contains no rows or the number of rows isn't exactly one
+    pub fn single_row(self) -> Result<result::Row, SingleRowError> {
+        let rows = self.rows.ok_or(RowsExpectedError)?;
+        if rows.rows_count() != 1 {
+            return Err(SingleRowError::BadNumberOfRows(rows.rows_count()));
+        }
+
+        match rows.into_iter().next() {
+            Some(Ok(row)) => Ok(row),
+            Some(Err(err)) => Err(SingleRowError::MalformedRow(err.to_string())),
+            None => Err(SingleRowError::BadNumberOfRows(0)),
+        }
+    }
+
+    /// Returns the only received row parsed as `RowT`.  
+    /// Fails if the result contains no rows, the number of rows isn't exactly one
+    /// or the row can't be parsed
+    pub fn single_row_typed<RowT: FromRow>(self) -> Result<RowT, SingleRowTypedError> {
+        Ok(self.single_row()?.into_typed()?)
+    }
+
+    /// Returns the index and specification of the column with the given name
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// let result = session.query("SELECT * FROM ks.tab", &[]).await?;
+    /// if let Some((index, spec)) = result.get_column_spec("b") {
+    ///     println!("Column b of type {} is at index {}", spec.typ(), index);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_column_spec<'a>(&'a self, name: &str) -> Option<(usize, &'a result::ColumnSpec)> {
+        self.col_specs
+            .iter()
+            .enumerate()
+            .find(|(_, spec)| spec.name() == name)
+    }
+
+    /// Interprets the result of a conditional statement or batch (one using `IF` conditions).  
+    /// The database returns rows starting with a boolean `[applied]` column,
+    /// this parses them into a [`ConditionalResult`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// use scylla::batch::Batch;
+    ///
+    /// let mut batch: Batch = Default::default();
+    /// batch.append_statement("INSERT INTO ks.tab(a, b) VALUES(1, 2) IF NOT EXISTS");
+    ///
+    /// let result = session.batch(&batch, ((),)).await?.into_conditional_result()?;
+    /// if !result.applied {
+    ///     println!("Batch not applied, current values: {:?}", result.rows);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_conditional_result(self) -> Result<ConditionalResult, ConditionalResultError> {
+        let applied = self.was_applied()?;
+
+        let existing_rows = self
+            .rows
+            .unwrap_or_default()
+            .into_iter()
+            .map(|row_res| {
+                let mut row = row_res?;
+                row.columns.remove(0);
+                Ok(row)
+            })
+            .collect::<Result<_, ParseError>>()
+            .map_err(|err| ConditionalResultError::MalformedRow(err.to_string()))?;
+
+        Ok(ConditionalResult {
+            applied,
+            rows: existing_rows,
+        })
+    }
+
+    /// Checks whether a conditional statement or batch (one using `IF` conditions) was applied,
+    /// by reading the `[applied]` column of the returned rows.  
+    /// Use [`into_conditional_result`](QueryResult::into_conditional_result) to also get the existing values.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// let result = session
+    ///     .query("UPDATE ks.tab SET b = 3 WHERE a = 1 IF b = 2", &[])
+    ///     .await?;
+    ///
+    /// if !result.was_applied()? {
+    ///     println!("Condition not met");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn was_applied(&self) -> Result<bool, ConditionalResultError> {
+        let rows = match &self.rows {
+            Some(rows) if rows.rows_count() > 0 => rows,
+            _ => return Err(ConditionalResultError::NoRows),
+        };
+
+        // A conditional batch returns a row for each checked row, it's applied only if all conditions are met.
+        // Only the [applied] column is decoded
+        let mut applied = true;
+        for row in rows.raw_rows() {
+            let row = row.map_err(|err| ConditionalResultError::MalformedRow(err.to_string()))?;
+            match row.column::<bool>(0) {
+                Ok(row_applied) => applied &= row_applied,
+                Err(result::RawColumnError::ParseError(err)) => {
+                    return Err(ConditionalResultError::MalformedRow(err.to_string()))
+                }
+                Err(_) => return Err(ConditionalResultError::NoAppliedColumn),
+            }
+        }
+
+        Ok(applied)
+    }
+
+    /// Checks whether a lightweight transaction, e.g. `INSERT ... IF NOT EXISTS`, was applied.  
+    /// Same as [`was_applied`](QueryResult::was_applied), the current row of a transaction
+    /// which wasn't applied can be parsed with [`into_existing_row`](ConditionalResult::into_existing_row).
+    pub fn is_lwt_applied(&self) -> Result<bool, ConditionalResultError> {
+        self.was_applied()
+    }
+}
+
+impl ConditionalResult {
+    /// Parses the row that prevented the statement from being applied as `RowT`.  
+    /// Returns `None` if the statement was applied or the database didn't return the existing row,
+    /// e.g. for `INSERT ... IF NOT EXISTS` when there was no row.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// let result = session
+    ///     .query("INSERT INTO ks.tab (a, b) VALUES (1, 2) IF NOT EXISTS", &[])
+    ///     .await?
+    ///     .into_conditional_result()?;
+    ///
+    /// if let Some((a, b)) = result.into_existing_row::<(i32, i32)>().transpose()? {
+    ///     println!("Row already exists: a = {}, b = {}", a, b);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_existing_row<RowT: FromRow>(self) -> Option<Result<RowT, FromRowError>> {
+        if self.applied {
+            return None;
+        }
+
+        self.rows
+            .into_iter()
+            .next()
+            .filter(|row| !row.columns.is_empty())
+            .map(RowT::from_row)
+    }
+}
+
+/// Error returned by [`QueryResult::rows_typed`] when the result contains no rows
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Expected the result to contain rows, but it has none")]
+pub struct RowsExpectedError;
+
+/// Error returned by [`QueryResult::first_row`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum FirstRowError {
+    #[error(transparent)]
+    RowsExpected(#[from] RowsExpectedError),
+    #[error("Rows in the result are empty")]
+    RowsEmpty,
+    #[error("Malformed row in the result: {0}")]
+    MalformedRow(String),
+}
+
+impl From<MaybeFirstRowError> for FirstRowError {
+    fn from(err: MaybeFirstRowError) -> Self {
+        match err {
+            MaybeFirstRowError::RowsExpected(err) => FirstRowError::RowsExpected(err),
+            MaybeFirstRowError::MalformedRow(msg) => FirstRowError::MalformedRow(msg),
+        }
+    }
+}
+
+/// Error returned by [`QueryResult::first_row_typed`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum FirstRowTypedError {
+    #[error(transparent)]
+    RowsExpected(#[from] RowsExpectedError),
+    #[error("Rows in the result are empty")]
+    RowsEmpty,
+    #[error(transparent)]
+    FromRowError(#[from] FromRowError),
+}
+
+impl From<FirstRowError> for FirstRowTypedError {
+    fn from(err: FirstRowError) -> Self {
+        match err {
+            FirstRowError::RowsExpected(err) => FirstRowTypedError::RowsExpected(err),
+            FirstRowError::RowsEmpty => FirstRowTypedError::RowsEmpty,
+            FirstRowError::MalformedRow(msg) => {
+                FirstRowTypedError::FromRowError(FromRowError::MalformedRow(msg))
+            }
+        }
+    }
+}
+
+/// Error returned by [`QueryResult::maybe_first_row`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MaybeFirstRowError {
+    #[error(transparent)]
+    RowsExpected(#[from] RowsExpectedError),
+    #[error("Malformed row in the result: {0}")]
+    MalformedRow(String),
+}
+
+/// Error returned by [`QueryResult::maybe_first_row_typed`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MaybeFirstRowTypedError {
+    #[error(transparent)]
+    RowsExpected(#[from] RowsExpectedError),
+    #[error(transparent)]
+    FromRowError(#[from] FromRowError),
+}
+
+impl From<MaybeFirstRowError> for MaybeFirstRowTypedError {
+    fn from(err: MaybeFirstRowError) -> Self {
+        match err {
+            MaybeFirstRowError::RowsExpected(err) => MaybeFirstRowTypedError::RowsExpected(err),
+            MaybeFirstRowError::MalformedRow(msg) => {
+                MaybeFirstRowTypedError::FromRowError(FromRowError::MalformedRow(msg))
+            }
+        }
+    }
+}
+
+/// Error returned by [`QueryResult::single_row`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SingleRowError {
+    #[error(transparent)]
+    RowsExpected(#[from] RowsExpectedError),
+    #[error("Expected a single row, found {0} rows")]
+    BadNumberOfRows(usize),
+    #[error("Malformed row in the result: {0}")]
+    MalformedRow(String),
+}
+
+/// Error returned by [`QueryResult::single_row_typed`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SingleRowTypedError {
+    #[error(transparent)]
+    RowsExpected(#[from] RowsExpectedError),
+    #[error("Expected a single row, found {0} rows")]
+    BadNumberOfRows(usize),
+    #[error(transparent)]
+    FromRowError(#[from] FromRowError),
+}
+
+impl From<SingleRowError> for SingleRowTypedError {
+    fn from(err: SingleRowError) -> Self {
+        match err {
+            SingleRowError::RowsExpected(err) => SingleRowTypedError::RowsExpected(err),
+            SingleRowError::BadNumberOfRows(rows_num) => {
+                SingleRowTypedError::BadNumberOfRows(rows_num)
+            }
+            SingleRowError::MalformedRow(msg) => {
+                SingleRowTypedError::FromRowError(FromRowError::MalformedRow(msg))
+            }
+        }
+    }
+}
+
+/// Result of a conditional statement or batch, see [`QueryResult::into_conditional_result`]
+#[derive(Default, Debug, PartialEq)]
+pub struct ConditionalResult {
+    /// Whether the conditions were met and the statement was applied
+    pub applied: bool,
+    /// Returned rows with the `[applied]` column removed.  
+    /// When the statement wasn't applied they contain the current values of the checked rows
+    pub rows: Vec<result::Row>,
+}
+
+/// Error returned when a [`QueryResult`] doesn't look like a result of a conditional statement
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ConditionalResultError {
+    #[error("Result of a conditional statement should contain rows")]
+    NoRows,
+    #[error(
+        "Rows returned by a conditional statement should start with a boolean [applied] column"
+    )]
+    NoAppliedColumn,
+    #[error("Malformed row in the result: {0}")]
+    MalformedRow(String),
+}
+
+/// [`QueryResponse`] which is guaranteed not to contain an error response
+pub struct NonErrorQueryResponse {
+    pub response: Response,
+    pub node: SocketAddr,
     pub tracing_id: Option<Uuid>,
+    pub warnings: Vec<String>,
 }
 
 impl QueryResponse {
+    pub fn into_non_error_query_response(self) -> Result<NonErrorQueryResponse, QueryError> {
+        match self.response {
+            Response::Error(err) => Err(err.into()),
+            response => Ok(NonErrorQueryResponse {
+                response,
+                node: self.node,
+                tracing_id: self.tracing_id,
+                warnings: self.warnings,
+            }),
+        }
+    }
+
     pub fn into_query_result(self) -> Result<QueryResult, QueryError> {
-        let (rows, paging_state) = match self.response {
-            Response::Error(err) => return Err(err.into()),
-            Response::Result(result::Result::Rows(rs)) => (Some(rs.rows), rs.metadata.paging_state),
-            Response::Result(_) => (None, None),
-            _ => {
-                return Err(QueryError::ProtocolError(
-                    "Unexpected server response, expected Result or Error",
-                ))
+        self.into_non_error_query_response()?.into_query_result()
+    }
+}
+
+impl NonErrorQueryResponse {
+    pub fn as_schema_change(&self) -> Option<&result::SchemaChange> {
+        match &self.response {
+            Response::Result(result::Result::SchemaChange(sc)) => Some(sc),
+            _ => None,
+        }
+    }
+
+    pub fn into_query_result(self) -> Result<QueryResult, QueryError> {
+        let (rows, paging_state, col_specs) = match self.response {
+            Response::Result(result::Result::Rows(mut rs)) => {
+                // Rows are deserialized only when the user reads them
+                let paging_state = rs.metadata.paging_state.take();
+                let col_specs = rs.metadata.col_specs().to_vec();
+                (Some(rs), paging_state, col_specs)
+            }
+            Response::Result(_) => (None, None, Vec::new()),
+            response => {
+                return Err(ProtocolError::UnexpectedResponse {
+                    node: self.node,
+                    request: "statement",
+                    response: response.name(),
+                }
+                .into())
             }
         };
 
@@ -118,6 +620,7 @@ impl QueryResponse {
             warnings: self.warnings,
             tracing_id: self.tracing_id,
             paging_state,
+            col_specs,
         })
     }
 }
@@ -125,14 +628,36 @@ impl QueryResponse {
 #[derive(Clone)]
 pub struct ConnectionConfig {
     pub compression: Option<Compression>,
+    // highest protocol version used, nodes which don't support v5 are connected to using v4
+    pub protocol_version: ProtocolVersion,
+    // bodies of requests smaller than this are sent uncompressed
+    pub compression_min_size: usize,
     pub tcp_nodelay: bool,
     #[cfg(feature = "ssl")]
     pub ssl_context: Option<SslContext>,
-    pub auth_username: Option<String>,
-    pub auth_password: Option<String>,
+    // server name sent in the TLS handshake (SNI)
+    #[cfg(feature = "ssl")]
+    pub tls_server_name: Option<String>,
+    // used when the node requires authentication
+    pub authenticator: Option<Arc<dyn Authenticator>>,
     pub connect_timeout: std::time::Duration,
+    // used for statements which don't specify their own consistency
+    pub default_consistency: Consistency,
+    pub default_serial_consistency: Option<Consistency>,
     // should be Some only in control connections,
     pub event_sender: Option<mpsc::Sender<Event>>,
+    // nodes which are connected to through unix domain sockets instead of TCP
+    pub unix_socket_overrides: Arc<HashMap<IpAddr, PathBuf>>,
+    // number of connections opened to each node
+    pub pool_size: PoolSize,
+    // decides how long to wait before reopening a broken connection
+    pub reconnection_policy: Arc<dyn ReconnectionPolicy>,
+    // if set, OPTIONS is sent with this interval and the connection breaks if it isn't answered in time
+    pub keepalive_interval: Option<std::time::Duration>,
+    // requests above this limit wait until some response arrives, capped at the number of stream ids
+    pub max_requests_per_connection: usize,
+    #[cfg(feature = "fault-injection")]
+    pub fault_injection_policy: Option<Arc<dyn FaultInjectionPolicy>>,
     /*
     These configuration options will be added in the future:
 
@@ -140,8 +665,6 @@ pub struct ConnectionConfig {
 
     pub load_balancing: Option<String>,
     pub retry_policy: Option<String>,
-
-    pub default_consistency: Option<String>,
     */
 }
 
@@ -149,13 +672,25 @@ impl Default for ConnectionConfig {
     fn default() -> Self {
         Self {
             compression: None,
+            protocol_version: ProtocolVersion::V4,
+            compression_min_size: 0,
             tcp_nodelay: true,
             event_sender: None,
             #[cfg(feature = "ssl")]
             ssl_context: None,
-            auth_username: None,
-            auth_password: None,
+            #[cfg(feature = "ssl")]
+            tls_server_name: None,
+            authenticator: None,
             connect_timeout: std::time::Duration::from_secs(5),
+            default_consistency: Default::default(),
+            default_serial_consistency: None,
+            unix_socket_overrides: Arc::new(HashMap::new()),
+            pool_size: Default::default(),
+            reconnection_policy: Arc::new(ExponentialReconnectionPolicy::default()),
+            keepalive_interval: None,
+            max_requests_per_connection: MAX_STREAMS,
+            #[cfg(feature = "fault-injection")]
+            fault_injection_policy: None,
         }
     }
 }
@@ -170,29 +705,90 @@ impl Connection {
         source_port: Option<u16>,
         config: ConnectionConfig,
     ) -> Result<(Self, ErrorReceiver), QueryError> {
-        let stream_connector = match source_port {
-            Some(p) => {
-                tokio::time::timeout(config.connect_timeout, connect_with_source_port(addr, p))
-                    .await
-            }
-            None => tokio::time::timeout(config.connect_timeout, TcpStream::connect(addr)).await,
-        };
-        let stream = match stream_connector {
-            Ok(stream) => stream?,
-            Err(_) => {
-                return Err(QueryError::TimeoutError);
+        #[cfg(feature = "fault-injection")]
+        if let Some(policy) = &config.fault_injection_policy {
+            match policy.on_connect(addr) {
+                ConnectFault::None => {}
+                ConnectFault::Delay(delay) => runtime::sleep(delay).await,
+                ConnectFault::Fail(kind) => {
+                    return Err(connect_error(std::io::Error::new(
+                        kind,
+                        "Connecting failed because of fault injection",
+                    )))
+                }
             }
-        };
-        let source_port = stream.local_addr()?.port();
-        stream.set_nodelay(config.tcp_nodelay)?;
+        }
+
+        if let Some(path) = config.unix_socket_overrides.get(&addr.ip()) {
+            return Self::new_unix(addr, path.clone(), config).await;
+        }
+
+        let stream = DefaultRuntime::connect(addr, source_port, config.connect_timeout)
+            .await
+            .map_err(connect_error)?;
+        let source_port = DefaultRuntime::local_addr(&stream)?.port();
+        DefaultRuntime::set_nodelay(&stream, config.tcp_nodelay)?;
+
+        Self::new_with_stream(addr, stream, source_port, config).await
+    }
+
+    // Connects to the node at `addr` through the unix domain socket at `path`.
+    // There are no ports, so the requested source port is ignored
+    // and the connection ends up on a random shard
+    #[cfg(unix)]
+    async fn new_unix(
+        addr: SocketAddr,
+        path: PathBuf,
+        config: ConnectionConfig,
+    ) -> Result<(Self, ErrorReceiver), QueryError> {
+        let stream = DefaultRuntime::connect_unix(path, config.connect_timeout)
+            .await
+            .map_err(connect_error)?;
+
+        Self::new_with_stream(addr, stream, 0, config).await
+    }
+
+    #[cfg(not(unix))]
+    async fn new_unix(
+        _addr: SocketAddr,
+        path: PathBuf,
+        _config: ConnectionConfig,
+    ) -> Result<(Self, ErrorReceiver), QueryError> {
+        Err(std::io::Error::new(
+            ErrorKind::Other,
+            format!(
+                "Can't connect to {}, unix domain sockets are not supported on this platform",
+                path.display()
+            ),
+        )
+        .into())
+    }
 
+    async fn new_with_stream(
+        addr: SocketAddr,
+        stream: impl AsyncRead + AsyncWrite + Send + Unpin + 'static,
+        source_port: u16,
+        config: ConnectionConfig,
+    ) -> Result<(Self, ErrorReceiver), QueryError> {
         // TODO: What should be the size of the channel?
         let (sender, receiver) = mpsc::channel(128);
 
         let (error_sender, error_receiver) = tokio::sync::oneshot::channel();
+        let framing = Arc::new(Framing::default());
 
-        let _worker_handle =
-            Self::run_router(config.clone(), stream, receiver, error_sender).await?;
+        let _worker_handle = Self::run_router(
+            addr,
+            config.clone(),
+            stream,
+            receiver,
+            error_sender,
+            framing.clone(),
+        )
+        .await?;
+
+        let in_flight_permits = Arc::new(Semaphore::new(
+            config.max_requests_per_connection.min(MAX_STREAMS),
+        ));
 
         let connection = Connection {
             submit_channel: sender,
@@ -202,6 +798,10 @@ impl Connection {
             shard_info: None,
             config,
             is_shard_aware: false,
+            lwt_mask: None,
+            reprepared_ids: Default::default(),
+            framing,
+            in_flight_permits,
         };
 
         Ok((connection, error_receiver))
@@ -222,10 +822,12 @@ impl Connection {
     }
 
     pub async fn prepare(&self, query: &Query) -> Result<PreparedStatement, QueryError> {
+        self.check_request_keyspace(query.get_keyspace())?;
         let query_response = self
             .send_request(
                 &request::Prepare {
                     query: &query.get_contents(),
+                    keyspace: query.get_keyspace(),
                 },
                 true,
                 query.config.tracing,
@@ -234,16 +836,29 @@ impl Connection {
 
         let mut prepared_statement = match query_response.response {
             Response::Error(err) => return Err(err.into()),
-            Response::Result(result::Result::Prepared(p)) => PreparedStatement::new(
-                p.id,
-                p.prepared_metadata,
-                query.get_contents().to_owned(),
-                query.get_page_size(),
-            ),
-            _ => {
-                return Err(QueryError::ProtocolError(
-                    "PREPARE: Unexpected server response",
-                ))
+            Response::Result(result::Result::Prepared(p)) => {
+                let mut prepared_statement = PreparedStatement::new(
+                    p.id,
+                    p.prepared_metadata,
+                    query.get_contents().to_owned(),
+                    query.get_page_size(),
+                );
+                prepared_statement.set_result_metadata_id(p.result_metadata_id);
+                prepared_statement.set_keyspace(query.get_keyspace().map(str::to_owned));
+                if let Some(mask) = self.lwt_mask {
+                    prepared_statement.set_is_confirmed_lwt(
+                        prepared_statement.get_prepared_metadata().flags() & mask != 0,
+                    );
+                }
+                prepared_statement
+            }
+            response => {
+                return Err(ProtocolError::UnexpectedResponse {
+                    node: self.connect_address,
+                    request: "PREPARE",
+                    response: response.name(),
+                }
+                .into())
             }
         };
 
@@ -255,20 +870,50 @@ impl Connection {
 
     pub async fn authenticate_response(
         &self,
-        username: Option<String>,
-        password: Option<String>,
-        authenticator: Authenticator,
+        response: Option<Vec<u8>>,
     ) -> Result<QueryResponse, QueryError> {
-        self.send_request(
-            &request::AuthResponse {
-                username,
-                password,
-                authenticator,
-            },
-            false,
-            false,
-        )
-        .await
+        self.send_request(&request::AuthResponse { response }, false, false)
+            .await
+    }
+
+    // Exchanges tokens with the node until it accepts or rejects the credentials
+    async fn authenticate(&self, authenticator_name: &str) -> Result<(), QueryError> {
+        let authenticator = self.config.authenticator.as_ref().ok_or_else(|| {
+            QueryError::AuthenticationError(format!(
+                "Node requires authentication with {}, but no credentials were given",
+                authenticator_name
+            ))
+        })?;
+        let (mut response, mut session) = authenticator
+            .start_session(authenticator_name)
+            .await
+            .map_err(QueryError::AuthenticationError)?;
+
+        loop {
+            match self.authenticate_response(response).await?.response {
+                Response::AuthChallenge(challenge) => {
+                    response = session
+                        .evaluate_challenge(challenge.authenticate_message.as_deref())
+                        .await
+                        .map_err(QueryError::AuthenticationError)?;
+                }
+                Response::AuthSuccess(success) => {
+                    return session
+                        .success(success.success_message.as_deref())
+                        .await
+                        .map_err(QueryError::AuthenticationError);
+                }
+                Response::Error(err) => return Err(err.into()),
+                response => {
+                    return Err(ProtocolError::UnexpectedResponse {
+                        node: self.connect_address,
+                        request: "AUTH_RESPONSE",
+                        response: response.name(),
+                    }
+                    .into())
+                }
+            }
+        }
     }
 
     pub async fn query_single_page(
@@ -277,7 +922,9 @@ impl Connection {
         values: impl ValueList,
     ) -> Result<QueryResult, QueryError> {
         let query: Query = query.into();
-        self.query(&query, &values, None).await?.into_query_result()
+        self.query(&query, &values, None, query.get_timestamp())
+            .await?
+            .into_query_result()
     }
 
     pub async fn query_single_page_by_ref(
@@ -286,27 +933,37 @@ impl Connection {
         values: &impl ValueList,
         paging_state: Option<Bytes>,
     ) -> Result<QueryResult, QueryError> {
-        self.query(query, values, paging_state)
+        self.query(query, values, paging_state, query.get_timestamp())
             .await?
             .into_query_result()
     }
 
+    /// `timestamp` is sent instead of the statement's timestamp, so that the session
+    /// can generate it once for all attempts of a request
     pub async fn query(
         &self,
         query: &Query,
         values: impl ValueList,
         paging_state: Option<Bytes>,
+        timestamp: Option<i64>,
     ) -> Result<QueryResponse, QueryError> {
+        self.check_request_keyspace(query.get_keyspace())?;
         let serialized_values = values.serialized()?;
 
         let query_frame = query::Query {
             contents: query.get_contents().to_owned(),
             parameters: query::QueryParameters {
-                consistency: query.get_consistency(),
-                serial_consistency: query.get_serial_consistency(),
+                consistency: query
+                    .get_consistency()
+                    .unwrap_or(self.config.default_consistency),
+                serial_consistency: query
+                    .get_serial_consistency()
+                    .or(self.config.default_serial_consistency),
+                timestamp,
                 values: &serialized_values,
                 page_size: query.get_page_size(),
                 paging_state,
+                keyspace: query.get_keyspace(),
             },
         };
 
@@ -320,27 +977,41 @@ impl Connection {
         values: impl ValueList,
         paging_state: Option<Bytes>,
     ) -> Result<QueryResult, QueryError> {
-        self.execute(prepared_statement, values, paging_state)
-            .await?
-            .into_query_result()
+        self.execute(
+            prepared_statement,
+            values,
+            paging_state,
+            prepared_statement.get_timestamp(),
+        )
+        .await?
+        .into_query_result()
     }
 
+    /// Like in [`query`](Connection::query), `timestamp` replaces the statement's timestamp
     pub async fn execute(
         &self,
         prepared_statement: &PreparedStatement,
         values: impl ValueList,
         paging_state: Option<Bytes>,
+        timestamp: Option<i64>,
     ) -> Result<QueryResponse, QueryError> {
         let serialized_values = values.serialized()?;
 
-        let execute_frame = execute::Execute {
-            id: prepared_statement.get_id().to_owned(),
+        let mut execute_frame = execute::Execute {
+            id: self.statement_id(prepared_statement),
+            result_metadata_id: prepared_statement.get_result_metadata_id().cloned(),
             parameters: query::QueryParameters {
-                consistency: prepared_statement.get_consistency(),
-                serial_consistency: prepared_statement.get_serial_consistency(),
+                consistency: prepared_statement
+                    .get_consistency()
+                    .unwrap_or(self.config.default_consistency),
+                serial_consistency: prepared_statement
+                    .get_serial_consistency()
+                    .or(self.config.default_serial_consistency),
+                timestamp,
                 values: &serialized_values,
                 page_size: prepared_statement.get_page_size(),
                 paging_state,
+                keyspace: None,
             },
         };
 
@@ -349,17 +1020,9 @@ impl Connection {
             .await?;
 
         if let Response::Error(err) = &query_response.response {
-            if err.error == DbError::Unprepared {
+            if let DbError::Unprepared { .. } = err.error {
                 // Repreparation of a statement is needed
-                let reprepare_query: Query = prepared_statement.get_statement().into();
-                let reprepared = self.prepare(&reprepare_query).await?;
-                // Reprepared statement should keep its id - it's the md5 sum
-                // of statement contents
-                if reprepared.get_id() != prepared_statement.get_id() {
-                    return Err(QueryError::ProtocolError(
-                        "Prepared statement Id changed, md5 sum should stay the same",
-                    ));
-                }
+                execute_frame.id = self.reprepare(prepared_statement).await?;
 
                 return self
                     .send_request(&execute_frame, true, prepared_statement.config.tracing)
@@ -374,7 +1037,21 @@ impl Connection {
         &self,
         batch: &Batch,
         values: impl BatchValues,
-    ) -> Result<BatchResult, QueryError> {
+    ) -> Result<QueryResult, QueryError> {
+        self.batch_with_size_check(batch, values, batch.get_timestamp(), |_| {})
+            .await
+    }
+
+    /// Like [`batch`](Connection::batch), `check_size` is called with the size of the serialized
+    /// batch body before it's sent, not counting repeated sends after repreparing statements.
+    /// `timestamp` replaces the batch's timestamp
+    pub(crate) async fn batch_with_size_check(
+        &self,
+        batch: &Batch,
+        values: impl BatchValues,
+        timestamp: Option<i64>,
+        check_size: impl FnOnce(usize),
+    ) -> Result<QueryResult, QueryError> {
         let statements_count = batch.get_statements().len();
         if statements_count != values.len() {
             return Err(QueryError::BadQuery(BadQuery::ValueLenMismatch(
@@ -383,37 +1060,134 @@ impl Connection {
             )));
         }
 
-        let statements_iter = batch.get_statements().iter().map(|s| match s {
-            BatchStatement::Query(q) => batch::BatchStatement::Query {
-                text: q.get_contents(),
-            },
-            BatchStatement::PreparedStatement(s) => {
-                batch::BatchStatement::Prepared { id: s.get_id() }
+        let mut query_response = self
+            .send_batch(batch, &values, timestamp, check_size)
+            .await?;
+
+        // Each error names a single statement unknown to the node, so the batch is sent again
+        // after repreparing it, until no statement is missing. A statement which is still
+        // unknown after repreparing it ends the loop, the error is returned then
+        let mut reprepared: HashSet<Bytes> = HashSet::new();
+        while let Response::Error(err) = &query_response.response {
+            let statement_id = match &err.error {
+                DbError::Unprepared { statement_id } => statement_id,
+                _ => break,
+            };
+            let unprepared_statement =
+                batch
+                    .get_statements()
+                    .iter()
+                    .find_map(|statement| match statement {
+                        BatchStatement::PreparedStatement(prepared_statement)
+                            if self.statement_id(prepared_statement) == statement_id =>
+                        {
+                            Some(prepared_statement)
+                        }
+                        _ => None,
+                    });
+            match unprepared_statement {
+                Some(prepared_statement) if reprepared.insert(statement_id.clone()) => {
+                    self.reprepare(prepared_statement).await?;
+                }
+                _ => break,
             }
-        });
+
+            query_response = self.send_batch(batch, &values, timestamp, |_| {}).await?;
+        }
+
+        query_response.into_query_result()
+    }
+
+    async fn send_batch(
+        &self,
+        batch: &Batch,
+        values: impl BatchValues,
+        timestamp: Option<i64>,
+        check_size: impl FnOnce(usize),
+    ) -> Result<QueryResponse, QueryError> {
+        // Ids of prepared statements on this connection's node, empty for simple queries
+        let statement_ids: Vec<Bytes> = batch
+            .get_statements()
+            .iter()
+            .map(|s| match s {
+                BatchStatement::Query(_) => Bytes::new(),
+                BatchStatement::PreparedStatement(s) => self.statement_id(s),
+            })
+            .collect();
+
+        let statements_iter = batch
+            .get_statements()
+            .iter()
+            .zip(&statement_ids)
+            .map(|(s, id)| match s {
+                BatchStatement::Query(q) => batch::BatchStatement::Query {
+                    text: q.get_contents(),
+                },
+                BatchStatement::PreparedStatement(_) => batch::BatchStatement::Prepared { id },
+            });
 
         let batch_frame = batch::Batch {
             statements: statements_iter,
-            statements_count,
+            statements_count: statement_ids.len(),
             values,
             batch_type: batch.get_type(),
-            consistency: batch.get_consistency(),
-            serial_consistency: batch.get_serial_consistency(),
+            consistency: batch
+                .get_consistency()
+                .unwrap_or(self.config.default_consistency),
+            serial_consistency: batch
+                .get_serial_consistency()
+                .or(self.config.default_serial_consistency),
+            timestamp,
         };
 
-        let query_response = self
-            .send_request(&batch_frame, true, batch.config.tracing)
-            .await?;
+        let serialized_request =
+            self.serialize_request(&batch_frame, true, batch.config.tracing)?;
+        check_size(serialized_request.body_len());
 
-        match query_response.response {
-            Response::Error(err) => Err(err.into()),
-            Response::Result(_) => Ok(BatchResult {
-                warnings: query_response.warnings,
-                tracing_id: query_response.tracing_id,
-            }),
-            _ => Err(QueryError::ProtocolError(
-                "BATCH: Unexpected server response",
-            )),
+        self.send_serialized_request(request::RequestOpcode::Batch, serialized_request)
+            .await
+    }
+
+    // Prepares the statement again after the node reported it as unprepared,
+    // returns its id on this connection's node
+    async fn reprepare(&self, prepared_statement: &PreparedStatement) -> Result<Bytes, QueryError> {
+        let mut reprepare_query: Query = prepared_statement.get_statement().into();
+        reprepare_query.set_keyspace(prepared_statement.get_keyspace().map(str::to_owned));
+        let reprepared = self.prepare(&reprepare_query).await?;
+        // The id usually stays the same, it's the md5 sum of statement contents,
+        // but it can change e.g. when the node was upgraded in the meantime.
+        // Later executions on this connection use the new id
+        if *reprepared.get_id() != self.statement_id(prepared_statement) {
+            self.reprepared_ids.lock().unwrap().insert(
+                prepared_statement.get_id().clone(),
+                reprepared.get_id().clone(),
+            );
+        }
+        Ok(reprepared.get_id().clone())
+    }
+
+    // Protocol v4 has no way to send the keyspace of a single request
+    fn check_request_keyspace(&self, keyspace: Option<&str>) -> Result<(), QueryError> {
+        match keyspace {
+            Some(keyspace) if self.config.protocol_version == ProtocolVersion::V4 => {
+                Err(BadQuery::KeyspaceRequiresProtocolV5(keyspace.to_string()).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Id of the statement on this connection's node
+    fn statement_id(&self, prepared_statement: &PreparedStatement) -> Bytes {
+        match self
+            .reprepared_ids
+            .lock()
+            .unwrap()
+            .get(prepared_statement.get_id())
+        {
+            Some(reprepared_id) => reprepared_id.clone(),
+            None => prepared_statement
+                .get_id_for_node(self.connect_address)
+                .clone(),
         }
     }
 
@@ -423,29 +1197,37 @@ impl Connection {
     ) -> Result<(), QueryError> {
         // Trying to pass keyspace_name as bound value doesn't work
         // We have to send "USE " + keyspace_name
-        let query: Query = match keyspace_name.is_case_sensitive {
-            true => format!("USE \"{}\"", keyspace_name.as_str()).into(),
-            false => format!("USE {}", keyspace_name.as_str()).into(),
-        };
+        let query: Query = format!("USE {}", keyspace_name.to_cql()).into();
 
-        let query_response = self.query(&query, (), None).await?;
+        let query_response = self.query(&query, (), None, None).await?;
 
         match query_response.response {
             Response::Result(result::Result::SetKeyspace(set_keyspace)) => {
-                if set_keyspace.keyspace_name.to_lowercase()
-                    != keyspace_name.as_str().to_lowercase()
-                {
-                    return Err(QueryError::ProtocolError(
-                        "USE <keyspace_name> returned response with different keyspace name",
-                    ));
+                let same_keyspace = match keyspace_name.is_case_sensitive {
+                    true => set_keyspace.keyspace_name == keyspace_name.as_str(),
+                    false => {
+                        set_keyspace.keyspace_name.to_lowercase()
+                            == keyspace_name.as_str().to_lowercase()
+                    }
+                };
+                if !same_keyspace {
+                    return Err(ProtocolError::KeyspaceMismatch {
+                        node: self.connect_address,
+                        requested: keyspace_name.as_str().to_owned(),
+                        received: set_keyspace.keyspace_name,
+                    }
+                    .into());
                 }
 
                 Ok(())
             }
             Response::Error(err) => Err(err.into()),
-            _ => Err(QueryError::ProtocolError(
-                "USE <keyspace_name> returned unexpected response",
-            )),
+            response => Err(ProtocolError::UnexpectedResponse {
+                node: self.connect_address,
+                request: "USE",
+                response: response.name(),
+            }
+            .into()),
         }
     }
 
@@ -464,9 +1246,12 @@ impl Connection {
         {
             Response::Ready => Ok(()),
             Response::Error(err) => Err(err.into()),
-            _ => Err(QueryError::ProtocolError(
-                "Unexpected response to REGISTER message",
-            )),
+            response => Err(ProtocolError::UnexpectedResponse {
+                node: self.connect_address,
+                request: "REGISTER",
+                response: response.name(),
+            }
+            .into()),
         }
     }
 
@@ -475,11 +1260,25 @@ impl Connection {
             .query_single_page(LOCAL_VERSION, &[])
             .await?
             .rows
-            .ok_or(QueryError::ProtocolError("Version query returned not rows"))?
+            .ok_or_else(|| {
+                ProtocolError::system_table(
+                    self.connect_address,
+                    "system.local",
+                    "response is not Rows",
+                )
+            })?
             .into_typed::<(Uuid,)>()
             .next()
-            .ok_or(QueryError::ProtocolError("Admin table returned empty rows"))?
-            .map_err(|_| QueryError::ProtocolError("Row is not uuid type as it should be"))?;
+            .ok_or_else(|| {
+                ProtocolError::system_table(
+                    self.connect_address,
+                    "system.local",
+                    "no rows returned",
+                )
+            })?
+            .map_err(|err| {
+                ProtocolError::system_table(self.connect_address, "system.local", err.to_string())
+            })?;
         Ok(version_id)
     }
 
@@ -489,12 +1288,50 @@ impl Connection {
         compress: bool,
         tracing: bool,
     ) -> Result<QueryResponse, QueryError> {
-        let compression = if compress {
+        let serialized_request = self.serialize_request(request, compress, tracing)?;
+        self.send_serialized_request(R::OPCODE, serialized_request)
+            .await
+    }
+
+    fn serialize_request<R: Request>(
+        &self,
+        request: &R,
+        compress: bool,
+        tracing: bool,
+    ) -> Result<SerializedRequest, QueryError> {
+        // Protocol v5 compresses segments instead of frames
+        let compression = if compress && self.config.protocol_version == ProtocolVersion::V4 {
             self.config.compression
         } else {
             None
         };
-        let serialized_request = SerializedRequest::make(request, compression, tracing)?;
+        let serialized_request = SerializedRequest::make(
+            request,
+            self.config.protocol_version,
+            compression,
+            self.config.compression_min_size,
+            tracing,
+        )
+        .map_err(|err| ProtocolError::invalid_frame(self.connect_address, err))?;
+        Ok(serialized_request)
+    }
+
+    async fn send_serialized_request(
+        &self,
+        #[cfg_attr(not(feature = "fault-injection"), allow(unused_variables))]
+        opcode: request::RequestOpcode,
+        serialized_request: SerializedRequest,
+    ) -> Result<QueryResponse, QueryError> {
+        #[cfg(feature = "fault-injection")]
+        let disconnect = self.inject_request_fault(opcode).await;
+
+        // Wait for a free stream id instead of failing with UnableToAllocStreamId
+        let permit = self
+            .in_flight_permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("Connection never closes its semaphore");
 
         let (sender, receiver) = oneshot::channel();
 
@@ -502,6 +1339,9 @@ impl Connection {
             .send(Task {
                 serialized_request,
                 response_handler: sender,
+                permit: Some(permit),
+                #[cfg(feature = "fault-injection")]
+                disconnect,
             })
             .await
             .map_err(|_| {
@@ -518,27 +1358,59 @@ impl Connection {
             )))
         })??;
 
-        Self::parse_response(task_response, self.config.compression)
+        Self::parse_response(
+            task_response,
+            self.connect_address,
+            self.config.protocol_version,
+            self.config.compression,
+        )
+    }
+
+    // Applies the fault chosen by the fault injection policy for the request,
+    // returns whether the connection should break after sending it
+    #[cfg(feature = "fault-injection")]
+    async fn inject_request_fault(&self, opcode: request::RequestOpcode) -> bool {
+        let policy = match &self.config.fault_injection_policy {
+            Some(policy) => policy,
+            None => return false,
+        };
+
+        match policy.on_request(self.connect_address, opcode) {
+            RequestFault::None => false,
+            RequestFault::Delay(delay) => {
+                runtime::sleep(delay).await;
+                false
+            }
+            RequestFault::Disconnect => true,
+        }
     }
 
     fn parse_response(
         task_response: TaskResponse,
+        node: SocketAddr,
+        version: ProtocolVersion,
         compression: Option<Compression>,
     ) -> Result<QueryResponse, QueryError> {
         let body_with_ext = frame::parse_response_body_extensions(
             task_response.params.flags,
             compression,
             task_response.body,
-        )?;
+        )
+        .map_err(|err| ProtocolError::invalid_frame(node, err))?;
 
         for warn_description in &body_with_ext.warnings {
             warn!(warning = warn_description.as_str());
         }
 
-        let response = Response::deserialize(task_response.opcode, &mut &*body_with_ext.body)?;
+        let response =
+            Response::deserialize_for_version(task_response.opcode, &body_with_ext.body, version)
+                .map_err(|err| {
+                ProtocolError::invalid_frame(node, format!("Error parsing message: {}", err))
+            })?;
 
         Ok(QueryResponse {
             response,
+            node,
             warnings: body_with_ext.warnings,
             tracing_id: body_with_ext.trace_id,
         })
@@ -546,85 +1418,112 @@ impl Connection {
 
     #[cfg(feature = "ssl")]
     async fn run_router(
+        addr: SocketAddr,
         config: ConnectionConfig,
-        stream: TcpStream,
+        stream: impl AsyncRead + AsyncWrite + Send + Unpin + 'static,
         receiver: mpsc::Receiver<Task>,
         error_sender: tokio::sync::oneshot::Sender<QueryError>,
+        framing: Arc<Framing>,
     ) -> Result<RemoteHandle<()>, std::io::Error> {
         let res = match config.ssl_context {
             Some(ref context) => {
-                let ssl = Ssl::new(context)?;
+                let mut ssl = Ssl::new(context)?;
+                // The certificate of the node has to be issued for the server name if it's set,
+                // or for the address of the node otherwise
+                match &config.tls_server_name {
+                    Some(server_name) => {
+                        ssl.set_hostname(server_name)?;
+                        ssl.param_mut().set_host(server_name)?;
+                    }
+                    None => ssl.param_mut().set_ip(addr.ip())?,
+                }
                 let mut stream = SslStream::new(ssl, stream)?;
-                let _pin = Pin::new(&mut stream).connect().await;
-                Self::run_router_spawner(stream, receiver, error_sender, config)
+                Pin::new(&mut stream).connect().await.map_err(|err| {
+                    err.into_io_error()
+                        .unwrap_or_else(|err| std::io::Error::new(ErrorKind::Other, err))
+                })?;
+                Self::run_router_spawner(addr, stream, receiver, error_sender, config, framing)
             }
-            None => Self::run_router_spawner(stream, receiver, error_sender, config),
+            None => Self::run_router_spawner(addr, stream, receiver, error_sender, config, framing),
         };
         Ok(res)
     }
 
     #[cfg(not(feature = "ssl"))]
     async fn run_router(
+        addr: SocketAddr,
         config: ConnectionConfig,
-        stream: TcpStream,
+        stream: impl AsyncRead + AsyncWrite + Send + Unpin + 'static,
         receiver: mpsc::Receiver<Task>,
         error_sender: tokio::sync::oneshot::Sender<QueryError>,
+        framing: Arc<Framing>,
     ) -> Result<RemoteHandle<()>, std::io::Error> {
         Ok(Self::run_router_spawner(
+            addr,
             stream,
             receiver,
             error_sender,
             config,
+            framing,
         ))
     }
 
     fn run_router_spawner(
+        addr: SocketAddr,
         stream: (impl AsyncRead + AsyncWrite + Send + 'static),
         receiver: mpsc::Receiver<Task>,
         error_sender: tokio::sync::oneshot::Sender<QueryError>,
         config: ConnectionConfig,
+        framing: Arc<Framing>,
     ) -> RemoteHandle<()> {
-        let (task, handle) = Self::router(stream, receiver, error_sender, config).remote_handle();
-        tokio::task::spawn(task);
+        // Events logged by the background task are attributed to the connection's node
+        let (task, handle) = Self::router(addr, stream, receiver, error_sender, config, framing)
+            .instrument(debug_span!("connection", node = %addr))
+            .remote_handle();
+        runtime::spawn(task);
         handle
     }
 
     async fn router(
+        addr: SocketAddr,
         stream: (impl AsyncRead + AsyncWrite),
         receiver: mpsc::Receiver<Task>,
         error_sender: tokio::sync::oneshot::Sender<QueryError>,
         config: ConnectionConfig,
+        framing: Arc<Framing>,
     ) {
         let (read_half, write_half) = split(stream);
-        // Why are using a mutex here?
-        //
-        // The handler_map is supposed to be shared between reader and writer
-        // futures, which will be run on the same task. The mutex should not
-        // be normally required here, but Rust complains if we try to use
-        // a RefCell instead of Mutex (the whole future becomes !Sync and we
-        // cannot use it in tasks).
-        //
-        // Notice that this lock will have no contention, because reader
-        // and writer futures are run on the same fiber, and both of them
-        // are carefully written in such a way that they do not hold the lock
-        // across .await points. Therefore, it should not be too expensive.
-        let handler_map = StdMutex::new(ResponseHandlerMap::new());
-
-        let r = Self::reader(read_half, &handler_map, config);
-        let w = Self::writer(write_half, &handler_map, receiver);
-
-        let result = futures::try_join!(r, w);
+        // The writer allocates stream ids and the reader releases them.
+        // Each of them owns its half of the handler map, so they don't need to lock anything
+        let (mut stream_ids, mut handler_map) = response_handler_map();
+        let (keepalive_sender, keepalive_receiver) = mpsc::channel(1);
+        let keepalive_interval = config.keepalive_interval;
+        let protocol_version = config.protocol_version;
+        let compression_min_size = config.compression_min_size;
+
+        let r = Self::reader(addr, read_half, &mut handler_map, config, &framing);
+        let w = Self::writer(
+            write_half,
+            &mut stream_ids,
+            receiver,
+            keepalive_receiver,
+            &framing,
+            compression_min_size,
+        );
+        let k = Self::keepaliver(addr, keepalive_interval, protocol_version, keepalive_sender);
+
+        let result = futures::try_join!(r, w, k);
 
         let error: QueryError = match result {
             Ok(_) => return, // Connection was dropped, we can return
             Err(err) => err,
         };
+        debug!("Connection broken: {}", error);
 
         // Respond to all pending requests with the error
-        let response_handlers: HashMap<i16, ResponseHandler> =
-            handler_map.into_inner().unwrap().into_handlers();
+        let response_handlers = handler_map.into_handlers();
 
-        for (_, handler) in response_handlers {
+        for handler in response_handlers {
             // Ignore sending error, request was dropped
             let _ = handler.send(Err(error.clone()));
         }
@@ -634,12 +1533,36 @@ impl Connection {
     }
 
     async fn reader(
+        addr: SocketAddr,
         mut read_half: (impl AsyncRead + Unpin),
-        handler_map: &StdMutex<ResponseHandlerMap>,
+        handler_map: &mut ResponseHandlerMap,
         config: ConnectionConfig,
+        framing: &Framing,
     ) -> Result<(), QueryError> {
+        // Data of frames which were only partially read from segments
+        let mut segments_buffer = BytesMut::new();
+
         loop {
-            let (params, opcode, body) = frame::read_response_frame(&mut read_half).await?;
+            let (params, opcode, body) = if framing.segments.load(atomic::Ordering::Relaxed) {
+                frame::read_response_frame_from_segments(
+                    &mut read_half,
+                    framing.compressed.load(atomic::Ordering::Relaxed),
+                    &mut segments_buffer,
+                )
+                .await
+            } else {
+                frame::read_response_frame(&mut read_half).await
+            }
+            .map_err(|err| ProtocolError::invalid_frame(addr, err))?;
+
+            // In protocol v5 the node sends segments after it finishes the handshake,
+            // the driver sends them after it gets the response ending the handshake
+            if config.protocol_version == ProtocolVersion::V5
+                && matches!(opcode, ResponseOpcode::Ready | ResponseOpcode::AuthSuccess)
+            {
+                framing.segments.store(true, atomic::Ordering::Relaxed);
+            }
+
             let response = TaskResponse {
                 params,
                 opcode,
@@ -655,21 +1578,21 @@ impl Connection {
                 }
                 Ordering::Equal => {
                     if let Some(event_sender) = config.event_sender.as_ref() {
-                        Self::handle_event(response, config.compression, event_sender).await?;
+                        Self::handle_event(
+                            response,
+                            addr,
+                            config.protocol_version,
+                            config.compression,
+                            event_sender,
+                        )
+                        .await?;
                     }
                     continue;
                 }
                 _ => {}
             }
 
-            let handler = {
-                // We are guaranteed here that handler_map will not be locked
-                // by anybody else, so we can do try_lock().unwrap()
-                let mut lock = handler_map.try_lock().unwrap();
-                lock.take(params.stream)
-            };
-
-            if let Some(handler) = handler {
+            if let Some(handler) = handler_map.take(params.stream) {
                 // Don't care if sending of the response fails. This must
                 // mean that the receiver side was impatient and is not
                 // waiting for the result anymore.
@@ -677,51 +1600,125 @@ impl Connection {
             } else {
                 // Unsolicited frame. This should not happen and indicates
                 // a bug either in the driver, or in the database
-                return Err(QueryError::ProtocolError(
-                    "Received reponse with unexpected StreamId",
-                ));
+                return Err(ProtocolError::UnexpectedStreamId {
+                    node: addr,
+                    stream_id: params.stream,
+                }
+                .into());
             }
         }
     }
 
     async fn writer(
         mut write_half: (impl AsyncWrite + Unpin),
-        handler_map: &StdMutex<ResponseHandlerMap>,
+        stream_ids: &mut StreamIdAllocator,
         mut task_receiver: mpsc::Receiver<Task>,
+        mut keepalive_receiver: mpsc::Receiver<Task>,
+        framing: &Framing,
+        compression_min_size: usize,
     ) -> Result<(), QueryError> {
-        // When the Connection object is dropped, the sender half
-        // of the channel will be dropped, this task will return an error
-        // and the whole worker will be stopped
-        while let Some(task) = task_receiver.recv().await {
-            let stream_id = {
-                // We are guaranteed here that handler_map will not be locked
-                // by anybody else, so we can do try_lock().unwrap()
-                let mut lock = handler_map.try_lock().unwrap();
-
-                if let Some(stream_id) = lock.allocate(task.response_handler) {
-                    stream_id
-                } else {
-                    // TODO: Handle this error better, for now we drop this
-                    // request and return an error to the receiver
-                    error!("Could not allocate stream id");
+        let mut segments = Vec::new();
+
+        loop {
+            let task = tokio::select! {
+                task = task_receiver.recv() => match task {
+                    Some(task) => task,
+                    // When the Connection object is dropped, the sender half
+                    // of the channel will be dropped, this task will return
+                    // and the whole worker will be stopped
+                    None => return Ok(()),
+                },
+                Some(task) = keepalive_receiver.recv() => task,
+            };
+
+            let stream_id = match stream_ids.allocate(task.response_handler, task.permit) {
+                Ok(stream_id) => stream_id,
+                Err(response_handler) => {
+                    // All stream ids are in use, the request isn't sent
+                    let _ = response_handler.send(Err(QueryError::UnableToAllocStreamId));
                     continue;
                 }
             };
 
             let mut req = task.serialized_request;
             req.set_stream(stream_id);
-            write_half.write_all(req.get_data()).await?;
+            if framing.segments.load(atomic::Ordering::Relaxed) {
+                segments.clear();
+                frame::segment::write_segments(
+                    req.get_data(),
+                    framing.compressed.load(atomic::Ordering::Relaxed),
+                    compression_min_size,
+                    &mut segments,
+                );
+                write_half.write_all(&segments).await?;
+            } else {
+                write_half.write_all(req.get_data()).await?;
+            }
+
+            #[cfg(feature = "fault-injection")]
+            if task.disconnect {
+                return Err(QueryError::IoError(Arc::new(std::io::Error::new(
+                    ErrorKind::ConnectionAborted,
+                    "Connection broken because of fault injection",
+                ))));
+            }
         }
+    }
 
-        Ok(())
+    // Sends OPTIONS every keepalive interval, returns an error
+    // if the response doesn't come before the next request is due
+    async fn keepaliver(
+        addr: SocketAddr,
+        keepalive_interval: Option<std::time::Duration>,
+        protocol_version: ProtocolVersion,
+        keepalive_sender: mpsc::Sender<Task>,
+    ) -> Result<(), QueryError> {
+        let interval = match keepalive_interval {
+            Some(interval) => interval,
+            None => return Ok(()),
+        };
+
+        loop {
+            runtime::sleep(interval).await;
+
+            let (response_sender, response_receiver) = oneshot::channel();
+            let task = Task {
+                serialized_request: SerializedRequest::make(
+                    &request::Options {},
+                    protocol_version,
+                    None,
+                    0,
+                    false,
+                )
+                .map_err(|err| ProtocolError::invalid_frame(addr, err))?,
+                response_handler: response_sender,
+                permit: None,
+                #[cfg(feature = "fault-injection")]
+                disconnect: false,
+            };
+            if keepalive_sender.send(task).await.is_err() {
+                // Writer has stopped, the connection is being closed anyway
+                return Ok(());
+            }
+
+            // Any response, even an error, means that the connection works
+            if runtime::timeout(interval, response_receiver).await.is_err() {
+                return Err(QueryError::IoError(Arc::new(std::io::Error::new(
+                    ErrorKind::TimedOut,
+                    "Keepalive request wasn't answered in time",
+                ))));
+            }
+        }
     }
 
     async fn handle_event(
         task_response: TaskResponse,
+        node: SocketAddr,
+        version: ProtocolVersion,
         compression: Option<Compression>,
         event_sender: &mpsc::Sender<Event>,
     ) -> Result<(), QueryError> {
-        let response = Self::parse_response(task_response, compression)?.response;
+        let response = Self::parse_response(task_response, node, version, compression)?.response;
         let event = match response {
             Response::Event(e) => e,
             _ => {
@@ -760,9 +1757,26 @@ impl Connection {
         self.is_shard_aware = is_shard_aware;
     }
 
+    fn set_lwt_mask(&mut self, lwt_mask: Option<i32>) {
+        self.lwt_mask = lwt_mask;
+    }
+
     pub fn get_connect_address(&self) -> SocketAddr {
         self.connect_address
     }
+
+    /// Protocol version negotiated with the node
+    pub fn get_protocol_version(&self) -> ProtocolVersion {
+        self.config.protocol_version
+    }
+}
+
+fn connect_error(err: std::io::Error) -> QueryError {
+    if err.kind() == ErrorKind::TimedOut {
+        QueryError::TimeoutError
+    } else {
+        err.into()
+    }
 }
 
 pub async fn open_connection(
@@ -784,6 +1798,45 @@ pub async fn open_named_connection(
     source_port: Option<u16>,
     config: ConnectionConfig,
     driver_name: Option<String>,
+) -> Result<(Connection, ErrorReceiver), QueryError> {
+    let result =
+        open_connection_with_version(addr, source_port, config.clone(), driver_name.clone()).await;
+
+    match result {
+        // Nodes which don't support protocol v5 reject it with a protocol error
+        Err(QueryError::DbError(DbError::ProtocolError, reason))
+            if config.protocol_version == ProtocolVersion::V5 =>
+        {
+            debug!(
+                "Node {} rejected protocol v5 ({}), connecting using v4",
+                addr, reason
+            );
+            let config = ConnectionConfig {
+                protocol_version: ProtocolVersion::V4,
+                ..config
+            };
+            open_connection_with_version(addr, source_port, config, driver_name).await
+        }
+        result => result,
+    }
+}
+
+// Parses the mask from the value of SCYLLA_LWT_ADD_METADATA_MARK,
+// e.g. "LWT_OPTIMIZATION_META_BIT_MASK=2147483648"
+fn parse_lwt_mark(option: &str) -> Option<i32> {
+    let (key, mask) = option.split_once('=')?;
+    if key != LWT_OPTIMIZATION_META_BIT_MASK {
+        return None;
+    }
+    // The mask is sent as an unsigned number, flags are read as a signed int
+    mask.parse::<u32>().ok().map(|mask| mask as i32)
+}
+
+async fn open_connection_with_version(
+    addr: SocketAddr,
+    source_port: Option<u16>,
+    config: ConnectionConfig,
+    driver_name: Option<String>,
 ) -> Result<(Connection, ErrorReceiver), QueryError> {
     // TODO: shouldn't all this logic be in Connection::new?
     let (mut connection, error_receiver) =
@@ -791,7 +1844,7 @@ pub async fn open_named_connection(
 
     let options_result = connection.get_options().await?;
 
-    let (shard_info, supported_compression, shard_aware_port) = match options_result {
+    let (shard_info, supported_compression, shard_aware_port, lwt_mark) = match options_result {
         Response::Supported(mut supported) => {
             let shard_info = ShardInfo::try_from(&supported.options).ok();
             let supported_compression = supported
@@ -805,24 +1858,52 @@ pub async fn open_named_connection(
                 .into_iter()
                 .next()
                 .and_then(|p| p.parse::<u16>().ok());
-            (shard_info, supported_compression, shard_aware_port)
+            let lwt_mark = supported
+                .options
+                .remove(LWT_ADD_METADATA_MARK)
+                .unwrap_or_else(Vec::new)
+                .into_iter()
+                .find_map(|option| parse_lwt_mark(&option).map(|mask| (option, mask)));
+            (
+                shard_info,
+                supported_compression,
+                shard_aware_port,
+                lwt_mark,
+            )
         }
-        _ => (None, Vec::new(), None),
+        Response::Error(err) => return Err(err.into()),
+        _ => (None, Vec::new(), None, None),
     };
     connection.set_shard_info(shard_info);
     connection.set_is_shard_aware(Some(addr.port()) == shard_aware_port);
+    connection.set_lwt_mask(lwt_mark.as_ref().map(|(_, mask)| *mask));
 
     let mut options = HashMap::new();
     options.insert("CQL_VERSION".to_string(), "4.0.0".to_string()); // FIXME: hardcoded values
     if let Some(name) = driver_name {
         options.insert("DRIVER_NAME".to_string(), name);
     }
+    if let Some((lwt_option, _)) = lwt_mark {
+        // Sending the option back enables marking LWT statements
+        options.insert(LWT_ADD_METADATA_MARK.to_string(), lwt_option);
+    }
     if let Some(compression) = &config.compression {
         let compression_str = compression.to_string();
-        if supported_compression.iter().any(|c| c == &compression_str) {
+        // Protocol v5 supports only LZ4
+        let version_supports_compression =
+            config.protocol_version == ProtocolVersion::V4 || *compression == Compression::Lz4;
+        if version_supports_compression
+            && supported_compression.iter().any(|c| c == &compression_str)
+        {
             // Compression is reported to be supported by the server,
             // request it from the server
             options.insert("COMPRESSION".to_string(), compression.to_string());
+            if config.protocol_version == ProtocolVersion::V5 {
+                connection
+                    .framing
+                    .compressed
+                    .store(true, atomic::Ordering::Relaxed);
+            }
         } else {
             // Fall back to no compression
             connection.config.compression = None;
@@ -832,46 +1913,17 @@ pub async fn open_named_connection(
     match result {
         Response::Ready => {}
         Response::Authenticate(authenticate) => {
-            let authenticator: Authenticator = match &authenticate.authenticator_name as &str {
-                "AllowAllAuthenticator" => AllowAllAuthenticator,
-                "PasswordAuthenticator" => PasswordAuthenticator,
-                "org.apache.cassandra.auth.PasswordAuthenticator" => CassandraPasswordAuthenticator,
-                "org.apache.cassandra.auth.AllowAllAuthenticator" => CassandraAllowAllAuthenticator,
-                "com.scylladb.auth.TransitionalAuthenticator" => ScyllaTransitionalAuthenticator,
-                _ => unimplemented!(
-                    "Authenticator not supported, {}",
-                    authenticate.authenticator_name
-                ),
-            };
-
-            let username = connection.config.auth_username.to_owned();
-            let password = connection.config.auth_password.to_owned();
-
-            let auth_result = connection
-                .authenticate_response(username, password, authenticator)
+            connection
+                .authenticate(&authenticate.authenticator_name)
                 .await?;
-            match auth_result.response {
-                Response::AuthChallenge(authenticate_challenge) => {
-                    let challenge_message = authenticate_challenge.authenticate_message;
-                    unimplemented!("Auth Challenge not implemented yet, {}", challenge_message)
-                }
-                Response::AuthSuccess(_authenticate_success) => {
-                    return Ok((connection, error_receiver));
-                }
-                Response::Error(err) => {
-                    return Err(err.into());
-                }
-                _ => {
-                    return Err(QueryError::ProtocolError(
-                        "Unexpected response to Authenticate Response message",
-                    ))
-                }
-            }
         }
-        _ => {
-            return Err(QueryError::ProtocolError(
-                "Unexpected response to STARTUP message",
-            ))
+        response => {
+            return Err(ProtocolError::UnexpectedResponse {
+                node: connection.connect_address,
+                request: "STARTUP",
+                response: response.name(),
+            }
+            .into())
         }
     }
 
@@ -887,90 +1939,122 @@ pub async fn open_named_connection(
     Ok((connection, error_receiver))
 }
 
-async fn connect_with_source_port(
-    addr: SocketAddr,
-    source_port: u16,
-) -> Result<TcpStream, std::io::Error> {
-    match addr {
-        SocketAddr::V4(_) => {
-            let socket = TcpSocket::new_v4()?;
-            socket.bind(SocketAddr::new(
-                Ipv4Addr::new(0, 0, 0, 0).into(),
-                source_port,
-            ))?;
-            Ok(socket.connect(addr).await?)
+// Stream ids are non-negative i16 values
+const MAX_STREAMS: usize = i16::MAX as usize + 1;
+
+// Creates the two halves of the map of response handlers.
+// The writer registers handlers of sent requests through the allocator and the reader
+// takes them when responses arrive. Handlers and released stream ids are passed
+// between the halves through channels, so each half can be owned by its future.
+fn response_handler_map() -> (StreamIdAllocator, ResponseHandlerMap) {
+    let (registered_sender, registered_receiver) = mpsc::unbounded_channel();
+    let (released_sender, released_receiver) = mpsc::unbounded_channel();
+
+    let allocator = StreamIdAllocator {
+        free_stream_ids: Vec::new(),
+        allocated_count: 0,
+        registered: registered_sender,
+        released: released_receiver,
+    };
+    let map = ResponseHandlerMap {
+        handlers: Vec::new(),
+        registered: registered_receiver,
+        released: released_sender,
+    };
+    (allocator, map)
+}
+
+struct PendingRequest {
+    handler: ResponseHandler,
+    // Frees the place for another request once the stream id is released,
+    // even if the caller stopped waiting for the response earlier
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+// Hands out stream ids, released ones are reused before new ones are allocated
+struct StreamIdAllocator {
+    free_stream_ids: Vec<i16>,
+    allocated_count: usize,
+    registered: mpsc::UnboundedSender<(i16, PendingRequest)>,
+    released: mpsc::UnboundedReceiver<i16>,
+}
+
+impl StreamIdAllocator {
+    // Gives the handler back if all stream ids are in use
+    pub fn allocate(
+        &mut self,
+        response_handler: ResponseHandler,
+        permit: Option<OwnedSemaphorePermit>,
+    ) -> Result<i16, ResponseHandler> {
+        while let Ok(stream_id) = self.released.try_recv() {
+            self.free_stream_ids.push(stream_id);
         }
-        SocketAddr::V6(_) => {
-            let socket = TcpSocket::new_v6()?;
-            socket.bind(SocketAddr::new(
-                Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0).into(),
-                source_port,
-            ))?;
-            Ok(socket.connect(addr).await?)
+
+        let stream_id = match self.free_stream_ids.pop() {
+            Some(stream_id) => stream_id,
+            None if self.allocated_count < MAX_STREAMS => {
+                self.allocated_count += 1;
+                (self.allocated_count - 1) as i16
+            }
+            None => return Err(response_handler),
+        };
+
+        let pending = PendingRequest {
+            handler: response_handler,
+            _permit: permit,
+        };
+        // The handler is registered before the request is written,
+        // so the reader knows it by the time the response arrives
+        match self.registered.send((stream_id, pending)) {
+            Ok(()) => Ok(stream_id),
+            Err(mpsc::error::SendError((stream_id, pending))) => {
+                self.free_stream_ids.push(stream_id);
+                Err(pending.handler)
+            }
         }
     }
 }
 
+// Slab of response handlers indexed by stream id.
+// It grows only up to the highest number of concurrent requests, because
+// the allocator reuses freed stream ids before allocating new ones.
 struct ResponseHandlerMap {
-    stream_set: StreamIdSet,
-    handlers: HashMap<i16, ResponseHandler>,
+    handlers: Vec<Option<PendingRequest>>,
+    registered: mpsc::UnboundedReceiver<(i16, PendingRequest)>,
+    released: mpsc::UnboundedSender<i16>,
 }
 
 impl ResponseHandlerMap {
-    pub fn new() -> Self {
-        Self {
-            stream_set: StreamIdSet::new(),
-            handlers: HashMap::new(),
-        }
-    }
-
-    pub fn allocate(&mut self, response_handler: ResponseHandler) -> Option<i16> {
-        let stream_id = self.stream_set.allocate()?;
-        let prev_handler = self.handlers.insert(stream_id, response_handler);
-        assert!(prev_handler.is_none());
-        Some(stream_id)
-    }
-
     pub fn take(&mut self, stream_id: i16) -> Option<ResponseHandler> {
-        self.stream_set.free(stream_id);
-        self.handlers.remove(&stream_id)
-    }
+        self.receive_registered();
 
-    // Retrieves the map of handlers, used after connection breaks
-    // and we have to respond to all of them with an error
-    pub fn into_handlers(self) -> HashMap<i16, ResponseHandler> {
-        self.handlers
+        let pending = self.handlers.get_mut(stream_id as usize)?.take()?;
+        // The allocator stops only together with the connection
+        let _ = self.released.send(stream_id);
+        Some(pending.handler)
     }
-}
 
-struct StreamIdSet {
-    used_bitmap: Box<[u64]>,
-}
+    // Retrieves all pending handlers, used after connection breaks
+    // and we have to respond to all of them with an error
+    pub fn into_handlers(mut self) -> impl Iterator<Item = ResponseHandler> {
+        self.receive_registered();
 
-impl StreamIdSet {
-    pub fn new() -> Self {
-        const BITMAP_SIZE: usize = (std::i16::MAX as usize + 1) / 64;
-        Self {
-            used_bitmap: vec![0; BITMAP_SIZE].into_boxed_slice(),
-        }
+        self.handlers
+            .into_iter()
+            .flatten()
+            .map(|pending| pending.handler)
     }
 
-    pub fn allocate(&mut self) -> Option<i16> {
-        for (block_id, block) in self.used_bitmap.iter_mut().enumerate() {
-            if *block != !0 {
-                let off = block.trailing_ones();
-                *block |= 1u64 << off;
-                let stream_id = off as i16 + block_id as i16 * 64;
-                return Some(stream_id);
+    fn receive_registered(&mut self) {
+        while let Ok((stream_id, pending)) = self.registered.try_recv() {
+            let index = stream_id as usize;
+            if index >= self.handlers.len() {
+                self.handlers.resize_with(index + 1, || None);
             }
+            let slot = &mut self.handlers[index];
+            assert!(slot.is_none());
+            *slot = Some(pending);
         }
-        None
-    }
-
-    pub fn free(&mut self, stream_id: i16) {
-        let block_id = stream_id as usize / 64;
-        let off = stream_id as usize % 64;
-        self.used_bitmap[block_id] &= !(1 << off);
     }
 }
 
@@ -982,8 +2066,14 @@ pub struct VerifiedKeyspaceName {
 }
 
 impl VerifiedKeyspaceName {
+    /// A name in double quotes, e.g. `"My""Keyspace"`, is unquoted and always case sensitive
     pub fn new(keyspace_name: String, case_sensitive: bool) -> Result<Self, BadKeyspaceName> {
-        Self::verify_keyspace_name_is_valid(&keyspace_name)?;
+        let (keyspace_name, case_sensitive) = match Self::unquote(&keyspace_name)? {
+            Some(unquoted) => (unquoted, true),
+            None => (keyspace_name, case_sensitive),
+        };
+
+        Self::verify_keyspace_name_is_valid(&keyspace_name, case_sensitive)?;
 
         Ok(VerifiedKeyspaceName {
             name: Arc::new(keyspace_name),
@@ -991,23 +2081,65 @@ impl VerifiedKeyspaceName {
         })
     }
 
+    /// Keyspace name without quotes
     pub fn as_str(&self) -> &str {
         self.name.as_str()
     }
 
+    /// Keyspace name to put in a CQL statement,
+    /// case sensitive names are quoted and quotes inside of them are escaped
+    pub fn to_cql(&self) -> String {
+        match self.is_case_sensitive {
+            true => format!("\"{}\"", self.name.replace('"', "\"\"")),
+            false => self.name.to_string(),
+        }
+    }
+
+    // Returns the unescaped name if it's a quoted identifier
+    fn unquote(keyspace_name: &str) -> Result<Option<String>, BadKeyspaceName> {
+        let quoted = match keyspace_name
+            .strip_prefix('"')
+            .and_then(|name| name.strip_suffix('"'))
+        {
+            Some(quoted) => quoted,
+            None => return Ok(None),
+        };
+
+        // Quotes inside of a quoted identifier are escaped by doubling them
+        let mut unquoted = String::with_capacity(quoted.len());
+        let mut chars = quoted.chars();
+        while let Some(character) = chars.next() {
+            if character == '"' && chars.next() != Some('"') {
+                return Err(BadKeyspaceName::IllegalCharacter(
+                    keyspace_name.to_string(),
+                    '"',
+                ));
+            }
+            unquoted.push(character);
+        }
+
+        Ok(Some(unquoted))
+    }
+
     // "Keyspace names can have up to 48 alpha-numeric characters and contain underscores;
     // only letters and numbers are supported as the first character."
     // https://docs.datastax.com/en/cql-oss/3.3/cql/cql_reference/cqlCreateKeyspace.html
     // Despite that cassandra accepts underscore as first character so we do too
     // https://github.com/scylladb/scylla/blob/62551b3bd382c7c47371eb3fc38173bd0cfed44d/test/cql-pytest/test_keyspace.py#L58
     // https://github.com/scylladb/scylla/blob/718976e794790253c4b24e2c78208e11f24e7502/cql3/statements/create_keyspace_statement.cc#L75
-    fn verify_keyspace_name_is_valid(keyspace_name: &str) -> Result<(), BadKeyspaceName> {
+    //
+    // Case sensitive names are quoted and escaped, so they can contain other characters
+    // as long as the database accepts them. Only control characters are rejected.
+    fn verify_keyspace_name_is_valid(
+        keyspace_name: &str,
+        case_sensitive: bool,
+    ) -> Result<(), BadKeyspaceName> {
         if keyspace_name.is_empty() {
             return Err(BadKeyspaceName::Empty);
         }
 
         // Verify that length <= 48
-        let keyspace_name_len: usize = keyspace_name.chars().count(); // Only ascii allowed so it's equal to .len()
+        let keyspace_name_len: usize = keyspace_name.chars().count();
         if keyspace_name_len > 48 {
             return Err(BadKeyspaceName::TooLong(
                 keyspace_name.to_string(),
@@ -1019,6 +2151,7 @@ impl VerifiedKeyspaceName {
         for character in keyspace_name.chars() {
             match character {
                 'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => {}
+                c if case_sensitive && !c.is_control() => {}
                 _ => {
                     return Err(BadKeyspaceName::IllegalCharacter(
                         keyspace_name.to_string(),
@@ -1031,3 +2164,711 @@ impl VerifiedKeyspaceName {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        response_handler_map, ConditionalResult, ConditionalResultError, FirstRowError,
+        FirstRowTypedError, MaybeFirstRowTypedError, QueryResult, RowsExpectedError,
+        SingleRowError, SingleRowTypedError, StreamIdAllocator, VerifiedKeyspaceName,
+    };
+    use crate::cql_to_rust::FromRowError;
+    use crate::frame::response::result::{
+        ColumnSpec, ColumnType, CqlValue, NamedColumnError, Row, Rows,
+    };
+    use crate::transport::errors::BadKeyspaceName;
+    use std::collections::HashMap;
+
+    // Types of the columns are taken from their first non-null value
+    fn query_result_with_rows(rows: Vec<Vec<Option<CqlValue>>>) -> QueryResult {
+        let columns_count = rows.first().map_or(0, Vec::len);
+        let col_specs = (0..columns_count)
+            .map(|index| {
+                let typ = match rows.iter().find_map(|row| row[index].as_ref()) {
+                    Some(CqlValue::Text(_)) => ColumnType::Text,
+                    Some(CqlValue::Boolean(_)) => ColumnType::Boolean,
+                    _ => ColumnType::Int,
+                };
+                ColumnSpec::new(&format!("c{}", index), typ)
+            })
+            .collect();
+
+        QueryResult {
+            rows: Some(Rows::new_for_test(col_specs, rows)),
+            ..Default::default()
+        }
+    }
+
+    // The text value can't be deserialized as the int it's declared to be
+    fn query_result_with_malformed_row() -> QueryResult {
+        let rows = Rows::new_for_test(
+            vec![ColumnSpec::new("a", ColumnType::Int)],
+            vec![vec![Some(CqlValue::Text("abc".to_string()))]],
+        );
+
+        QueryResult {
+            rows: Some(rows),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rows_typed() {
+        let result = query_result_with_rows(vec![
+            vec![
+                Some(CqlValue::Int(1)),
+                Some(CqlValue::Text("a".to_string())),
+            ],
+            vec![Some(CqlValue::Int(2)), None],
+        ]);
+
+        let rows: Vec<(i32, Option<String>)> = result
+            .rows_typed::<(i32, Option<String>)>()
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(rows, vec![(1, Some("a".to_string())), (2, None)]);
+
+        // The second row has a null in a column which isn't parsed as an Option
+        let result = query_result_with_rows(vec![
+            vec![
+                Some(CqlValue::Int(1)),
+                Some(CqlValue::Text("a".to_string())),
+            ],
+            vec![Some(CqlValue::Int(2)), None],
+        ]);
+        let err = result
+            .rows_typed::<(i32, String)>()
+            .unwrap()
+            .nth(1)
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Bad CQL value in column 1 (c1) of row 1, expected alloc::string::String, got text: Value is null"
+        );
+
+        let no_rows = QueryResult::default();
+        assert!(matches!(
+            no_rows.rows_typed::<(i32,)>(),
+            Err(RowsExpectedError)
+        ));
+    }
+
+    #[test]
+    fn rows_named() {
+        let mut result = query_result_with_rows(vec![
+            vec![
+                Some(CqlValue::Int(1)),
+                Some(CqlValue::Text("a".to_string())),
+            ],
+            vec![Some(CqlValue::Int(2)), None],
+        ]);
+        result.col_specs = vec![
+            ColumnSpec::new("id", ColumnType::Int),
+            ColumnSpec::new("name", ColumnType::Text),
+        ];
+        assert_eq!(result.col_names(), vec!["id", "name"]);
+
+        let rows: Vec<_> = result
+            .rows_named()
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].names().collect::<Vec<_>>(), vec!["id", "name"]);
+        assert_eq!(rows[0].get::<i32>("id"), Ok(1));
+        assert_eq!(rows[0].get::<String>("name"), Ok("a".to_string()));
+        assert_eq!(rows[1].get::<Option<String>>("name"), Ok(None));
+        assert_eq!(rows[1].value("name"), Some(None));
+        assert_eq!(rows[1].value("id"), Some(Some(&CqlValue::Int(2))));
+        assert_eq!(rows[1].value("other"), None);
+        assert_eq!(
+            rows[1].get::<i32>("other"),
+            Err(NamedColumnError::NoSuchColumn("other".to_string()))
+        );
+        match rows[0].get::<i64>("id") {
+            Err(NamedColumnError::BadCqlVal { column, .. }) => {
+                assert_eq!(column.index, 0);
+                assert_eq!(column.name.as_deref(), Some("id"));
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        let map: HashMap<String, Option<CqlValue>> = rows[1].clone().into_map();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["id"], Some(CqlValue::Int(2)));
+        assert_eq!(map["name"], None);
+        assert_eq!(
+            rows[1].clone().into_row().into_named(&[
+                ColumnSpec::new("id", ColumnType::Int),
+                ColumnSpec::new("name", ColumnType::Text),
+            ]),
+            rows[1]
+        );
+
+        assert!(matches!(
+            QueryResult::default().rows_named(),
+            Err(RowsExpectedError)
+        ));
+    }
+
+    #[test]
+    fn malformed_rows() {
+        let mut rows_typed = query_result_with_malformed_row()
+            .rows_typed::<(i32,)>()
+            .unwrap();
+        assert!(matches!(
+            rows_typed.next(),
+            Some(Err(FromRowError::MalformedRow(_)))
+        ));
+        assert!(rows_typed.next().is_none());
+
+        assert!(query_result_with_malformed_row()
+            .rows_named()
+            .unwrap()
+            .next()
+            .unwrap()
+            .is_err());
+        assert!(matches!(
+            query_result_with_malformed_row().first_row(),
+            Err(FirstRowError::MalformedRow(_))
+        ));
+        assert!(matches!(
+            query_result_with_malformed_row().maybe_first_row_typed::<(i32,)>(),
+            Err(MaybeFirstRowTypedError::FromRowError(
+                FromRowError::MalformedRow(_)
+            ))
+        ));
+        assert!(matches!(
+            query_result_with_malformed_row().single_row(),
+            Err(SingleRowError::MalformedRow(_))
+        ));
+        // The number of rows is known without deserializing them
+        assert_eq!(query_result_with_malformed_row().rows_num(), Ok(1));
+    }
+
+    #[test]
+    fn first_and_single_row() {
+        let no_rows = QueryResult::default;
+        let empty = || query_result_with_rows(Vec::new());
+        let one_row = || query_result_with_rows(vec![vec![Some(CqlValue::Int(1))]]);
+        let two_rows = || {
+            query_result_with_rows(vec![
+                vec![Some(CqlValue::Int(1))],
+                vec![Some(CqlValue::Int(2))],
+            ])
+        };
+
+        assert_eq!(no_rows().rows_num(), Err(RowsExpectedError));
+        assert_eq!(empty().rows_num(), Ok(0));
+        assert_eq!(two_rows().rows_num(), Ok(2));
+
+        assert_eq!(
+            no_rows().first_row(),
+            Err(FirstRowError::RowsExpected(RowsExpectedError))
+        );
+        assert_eq!(empty().first_row(), Err(FirstRowError::RowsEmpty));
+        assert_eq!(
+            two_rows().first_row().unwrap().columns,
+            vec![Some(CqlValue::Int(1))]
+        );
+        assert_eq!(two_rows().first_row_typed::<(i32,)>(), Ok((1,)));
+        assert_eq!(
+            empty().first_row_typed::<(i32,)>(),
+            Err(FirstRowTypedError::RowsEmpty)
+        );
+        assert!(matches!(
+            one_row().first_row_typed::<(String,)>(),
+            Err(FirstRowTypedError::FromRowError(
+                FromRowError::BadCqlVal { .. }
+            ))
+        ));
+
+        assert_eq!(empty().maybe_first_row(), Ok(None));
+        assert_eq!(empty().maybe_first_row_typed::<(i32,)>(), Ok(None));
+        assert_eq!(two_rows().maybe_first_row_typed::<(i32,)>(), Ok(Some((1,))));
+        assert_eq!(
+            no_rows().maybe_first_row_typed::<(i32,)>(),
+            Err(MaybeFirstRowTypedError::RowsExpected(RowsExpectedError))
+        );
+
+        assert_eq!(one_row().single_row_typed::<(i32,)>(), Ok((1,)));
+        assert_eq!(
+            no_rows().single_row(),
+            Err(SingleRowError::RowsExpected(RowsExpectedError))
+        );
+        assert_eq!(
+            empty().single_row(),
+            Err(SingleRowError::BadNumberOfRows(0))
+        );
+        assert_eq!(
+            two_rows().single_row_typed::<(i32,)>(),
+            Err(SingleRowTypedError::BadNumberOfRows(2))
+        );
+    }
+
+    #[test]
+    fn conditional_result_applied() {
+        let result = query_result_with_rows(vec![vec![Some(CqlValue::Boolean(true))]]);
+
+        assert_eq!(
+            result.into_conditional_result(),
+            Ok(ConditionalResult {
+                applied: true,
+                rows: vec![Row { columns: vec![] }],
+            })
+        );
+    }
+
+    #[test]
+    fn conditional_result_not_applied() {
+        let result = query_result_with_rows(vec![
+            vec![
+                Some(CqlValue::Boolean(false)),
+                Some(CqlValue::Int(1)),
+                Some(CqlValue::Int(2)),
+            ],
+            vec![Some(CqlValue::Boolean(false)), Some(CqlValue::Int(3)), None],
+        ]);
+
+        assert_eq!(
+            result.into_conditional_result(),
+            Ok(ConditionalResult {
+                applied: false,
+                rows: vec![
+                    Row {
+                        columns: vec![Some(CqlValue::Int(1)), Some(CqlValue::Int(2))]
+                    },
+                    Row {
+                        columns: vec![Some(CqlValue::Int(3)), None]
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn was_applied() {
+        let applied = query_result_with_rows(vec![vec![Some(CqlValue::Boolean(true))]]);
+        assert_eq!(applied.was_applied(), Ok(true));
+        assert_eq!(applied.is_lwt_applied(), Ok(true));
+        assert_eq!(
+            applied
+                .into_conditional_result()
+                .unwrap()
+                .into_existing_row::<(i32,)>(),
+            None
+        );
+
+        let not_applied = query_result_with_rows(vec![vec![
+            Some(CqlValue::Boolean(false)),
+            Some(CqlValue::Int(1)),
+            Some(CqlValue::Text("a".to_string())),
+        ]]);
+        assert_eq!(not_applied.was_applied(), Ok(false));
+        assert_eq!(not_applied.is_lwt_applied(), Ok(false));
+        assert_eq!(
+            not_applied
+                .into_conditional_result()
+                .unwrap()
+                .into_existing_row::<(i32, String)>(),
+            Some(Ok((1, "a".to_string())))
+        );
+
+        // A batch is applied only if all of its conditions are met
+        let batch = query_result_with_rows(vec![
+            vec![Some(CqlValue::Boolean(true))],
+            vec![Some(CqlValue::Boolean(false))],
+        ]);
+        assert_eq!(batch.was_applied(), Ok(false));
+
+        assert_eq!(
+            QueryResult::default().was_applied(),
+            Err(ConditionalResultError::NoRows)
+        );
+        assert_eq!(
+            query_result_with_rows(vec![vec![None]]).was_applied(),
+            Err(ConditionalResultError::NoAppliedColumn)
+        );
+    }
+
+    #[test]
+    fn conditional_result_errors() {
+        assert_eq!(
+            QueryResult::default().into_conditional_result(),
+            Err(ConditionalResultError::NoRows)
+        );
+        assert_eq!(
+            query_result_with_rows(vec![]).into_conditional_result(),
+            Err(ConditionalResultError::NoRows)
+        );
+        assert_eq!(
+            query_result_with_rows(vec![vec![]]).into_conditional_result(),
+            Err(ConditionalResultError::NoAppliedColumn)
+        );
+        assert_eq!(
+            query_result_with_rows(vec![vec![Some(CqlValue::Int(1))]]).into_conditional_result(),
+            Err(ConditionalResultError::NoAppliedColumn)
+        );
+    }
+
+    #[test]
+    fn keyspace_names() {
+        let cql = |name: &str, case_sensitive: bool| {
+            VerifiedKeyspaceName::new(name.to_string(), case_sensitive).map(|ks| ks.to_cql())
+        };
+
+        assert_eq!(cql("my_ks", false).unwrap(), "my_ks");
+        assert_eq!(cql("My_Ks", true).unwrap(), "\"My_Ks\"");
+        assert_eq!(cql("my-ks.2", true).unwrap(), "\"my-ks.2\"");
+        // Quotes can't end the identifier early
+        assert_eq!(
+            cql("ks\"; DROP KEYSPACE x; --", true).unwrap(),
+            "\"ks\"\"; DROP KEYSPACE x; --\""
+        );
+
+        // Quoted names are case sensitive, doubled quotes inside of them are unescaped
+        let quoted = VerifiedKeyspaceName::new("\"My\"\"Ks\"".to_string(), false).unwrap();
+        assert!(quoted.is_case_sensitive);
+        assert_eq!(quoted.as_str(), "My\"Ks");
+        assert_eq!(quoted.to_cql(), "\"My\"\"Ks\"");
+
+        assert!(matches!(
+            cql("my-ks", false),
+            Err(BadKeyspaceName::IllegalCharacter(_, '-'))
+        ));
+        assert!(matches!(
+            cql("\"my\"ks\"", false),
+            Err(BadKeyspaceName::IllegalCharacter(_, '"'))
+        ));
+        assert!(matches!(
+            cql("ks\n", true),
+            Err(BadKeyspaceName::IllegalCharacter(_, '\n'))
+        ));
+        assert!(matches!(cql("\"\"", false), Err(BadKeyspaceName::Empty)));
+        assert!(matches!(
+            cql(&"ą".repeat(49), true),
+            Err(BadKeyspaceName::TooLong(_, 49))
+        ));
+    }
+
+    #[test]
+    fn response_handler_map_reuses_stream_ids() {
+        let (mut stream_ids, mut map) = response_handler_map();
+        let mut receivers = Vec::new();
+        let mut allocate = |stream_ids: &mut StreamIdAllocator| {
+            let (sender, receiver) = tokio::sync::oneshot::channel();
+            receivers.push(receiver);
+            stream_ids.allocate(sender, None).ok()
+        };
+
+        assert_eq!(allocate(&mut stream_ids), Some(0));
+        assert_eq!(allocate(&mut stream_ids), Some(1));
+        assert_eq!(allocate(&mut stream_ids), Some(2));
+
+        assert!(map.take(1).is_some());
+        // Stream ids which aren't in use don't have handlers
+        assert!(map.take(1).is_none());
+        assert!(map.take(100).is_none());
+
+        assert_eq!(allocate(&mut stream_ids), Some(1));
+        assert_eq!(allocate(&mut stream_ids), Some(3));
+        assert_eq!(map.into_handlers().count(), 4);
+    }
+
+    #[test]
+    fn response_handler_map_runs_out_of_stream_ids() {
+        let (mut stream_ids, mut map) = response_handler_map();
+        for expected_id in 0..=i16::MAX {
+            let (sender, _) = tokio::sync::oneshot::channel();
+            assert_eq!(stream_ids.allocate(sender, None).ok(), Some(expected_id));
+        }
+
+        let (sender, _) = tokio::sync::oneshot::channel();
+        assert!(stream_ids.allocate(sender, None).is_err());
+
+        map.take(42).unwrap();
+        let (sender, _) = tokio::sync::oneshot::channel();
+        assert_eq!(stream_ids.allocate(sender, None).ok(), Some(42));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn connect_through_unix_socket() {
+        use super::{Connection, ConnectionConfig};
+        use std::collections::HashMap;
+        use std::net::SocketAddr;
+        use std::sync::Arc;
+
+        let path = std::env::temp_dir().join(format!("scylla-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+
+        let addr: SocketAddr = "10.0.0.1:9042".parse().unwrap();
+        let mut overrides = HashMap::new();
+        overrides.insert(addr.ip(), path.clone());
+        let config = ConnectionConfig {
+            unix_socket_overrides: Arc::new(overrides),
+            ..Default::default()
+        };
+
+        // Source port is ignored, there are no ports on unix sockets
+        let (connection, _) = Connection::new(addr, Some(50000), config).await.unwrap();
+        listener.accept().await.unwrap();
+        assert_eq!(connection.get_connect_address(), addr);
+        assert_eq!(connection.get_source_port(), 0);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn keepalive_breaks_unresponsive_connection() {
+        use super::{Connection, ConnectionConfig};
+        use crate::transport::errors::QueryError;
+        use std::io::ErrorKind;
+        use std::time::Duration;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = ConnectionConfig {
+            keepalive_interval: Some(Duration::from_millis(20)),
+            ..Default::default()
+        };
+        let (_connection, error_receiver) =
+            Connection::new(listener.local_addr().unwrap(), None, config)
+                .await
+                .unwrap();
+        let (mut node, _) = listener.accept().await.unwrap();
+
+        // Reads a request header and checks that it's OPTIONS without a body
+        async fn read_options(node: &mut tokio::net::TcpStream) -> [u8; 9] {
+            let mut header = [0u8; 9];
+            node.read_exact(&mut header).await.unwrap();
+            assert_eq!(header[4], 0x05);
+            assert_eq!(&header[5..], &[0, 0, 0, 0]);
+            header
+        }
+
+        // The first OPTIONS is answered with SUPPORTED, with an empty body
+        let header = read_options(&mut node).await;
+        let supported = [0x84, 0, header[2], header[3], 0x06, 0, 0, 0, 2, 0, 0];
+        node.write_all(&supported).await.unwrap();
+
+        // The second one isn't answered, which breaks the connection
+        read_options(&mut node).await;
+        let error = tokio::time::timeout(Duration::from_secs(5), error_receiver)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(error, QueryError::IoError(e) if e.kind() == ErrorKind::TimedOut));
+    }
+
+    #[tokio::test]
+    async fn protocol_v5_segments() {
+        use super::{open_connection, ConnectionConfig};
+        use crate::frame::segment;
+        use crate::transport::{Compression, ProtocolVersion};
+        use std::convert::TryInto;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpStream;
+
+        // Reads a frame which isn't in a segment, returns its header and body
+        async fn read_frame(node: &mut TcpStream) -> ([u8; 9], Vec<u8>) {
+            let mut header = [0u8; 9];
+            node.read_exact(&mut header).await.unwrap();
+            let mut body = vec![0; u32::from_be_bytes(header[5..].try_into().unwrap()) as usize];
+            node.read_exact(&mut body).await.unwrap();
+            (header, body)
+        }
+
+        // Makes a response frame to the request with the given header
+        fn response(request_header: &[u8], opcode: u8, body: &[u8]) -> Vec<u8> {
+            let mut frame = vec![0x85, 0, request_header[2], request_header[3], opcode];
+            frame.extend(&(body.len() as u32).to_be_bytes());
+            frame.extend(body);
+            frame
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let node = tokio::spawn(async move {
+            let (mut node, _) = listener.accept().await.unwrap();
+
+            // The handshake isn't sent in segments
+            let (header, _) = read_frame(&mut node).await;
+            assert_eq!((header[0], header[4]), (5, 0x05));
+            // SUPPORTED with COMPRESSION: [lz4]
+            let mut supported = vec![0, 1, 0, 11];
+            supported.extend(b"COMPRESSION");
+            supported.extend(&[0, 1, 0, 3]);
+            supported.extend(b"lz4");
+            node.write_all(&response(&header, 0x06, &supported))
+                .await
+                .unwrap();
+
+            let (header, _) = read_frame(&mut node).await;
+            assert_eq!((header[0], header[4]), (5, 0x01));
+            node.write_all(&response(&header, 0x02, &[])).await.unwrap();
+
+            // Later frames are sent in compressed segments
+            let query = segment::read_segment(&mut node, true).await.unwrap();
+            assert_eq!((query[0], query[4]), (5, 0x07));
+            let statement = b"SELECT a FROM ks.t";
+            assert_eq!(&query[13..13 + statement.len()], statement);
+            // Flags are an int, after consistency
+            let flags_start = 13 + statement.len() + 2;
+            let flags = u32::from_be_bytes(query[flags_start..flags_start + 4].try_into().unwrap());
+            assert_eq!(flags & !0xff, 0);
+
+            // RESULT Void
+            let mut segments = Vec::new();
+            segment::write_segments(
+                &response(&query, 0x08, &[0, 0, 0, 1]),
+                true,
+                0,
+                &mut segments,
+            );
+            node.write_all(&segments).await.unwrap();
+            node
+        });
+
+        let config = ConnectionConfig {
+            protocol_version: ProtocolVersion::V5,
+            compression: Some(Compression::Lz4),
+            ..Default::default()
+        };
+        let (connection, _) = open_connection(addr, None, config).await.unwrap();
+        assert_eq!(connection.get_protocol_version(), ProtocolVersion::V5);
+
+        let result = connection
+            .query_single_page("SELECT a FROM ks.t", &[])
+            .await
+            .unwrap();
+        assert!(result.rows.is_none());
+        node.await.unwrap();
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn protocol_v5_falls_back_to_v4() {
+        use super::{open_connection, ConnectionConfig};
+        use crate::proxy::MockNode;
+        use crate::query::Query;
+        use crate::transport::errors::{BadQuery, QueryError};
+        use crate::transport::ProtocolVersion;
+
+        // The mock node supports only protocol v4
+        let node = MockNode::new().run().await.unwrap();
+        let config = ConnectionConfig {
+            protocol_version: ProtocolVersion::V5,
+            ..Default::default()
+        };
+        let (connection, _) = open_connection(node.addr(), None, config).await.unwrap();
+        assert_eq!(connection.get_protocol_version(), ProtocolVersion::V4);
+
+        connection
+            .query_single_page("SELECT a FROM ks.t", &[])
+            .await
+            .unwrap();
+        assert_eq!(node.received_statements(), vec!["SELECT a FROM ks.t"]);
+
+        // Keyspaces of single statements can't be sent in v4
+        let mut query = Query::new("SELECT a FROM t".to_string());
+        query.set_keyspace(Some("ks".to_string()));
+        assert!(matches!(
+            connection.query_single_page(query.clone(), &[]).await,
+            Err(QueryError::BadQuery(BadQuery::KeyspaceRequiresProtocolV5(ks))) if ks == "ks"
+        ));
+        assert!(matches!(
+            connection.prepare(&query).await,
+            Err(QueryError::BadQuery(BadQuery::KeyspaceRequiresProtocolV5(
+                _
+            )))
+        ));
+        assert_eq!(node.received_statements(), vec!["SELECT a FROM ks.t"]);
+
+        node.finish().await.unwrap();
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn requests_above_limit_wait_for_responses() {
+        use super::{open_connection, ConnectionConfig};
+        use crate::proxy::{MockNode, MockResponse, MockRule};
+        use futures::future::join_all;
+        use std::time::Duration;
+
+        let node = MockNode::new()
+            .rule(
+                MockRule::new("SELECT a FROM ks.t", MockResponse::Void)
+                    .delay(Duration::from_millis(200)),
+            )
+            .run()
+            .await
+            .unwrap();
+        let config = ConnectionConfig {
+            max_requests_per_connection: 2,
+            ..Default::default()
+        };
+        let (connection, _) = open_connection(node.addr(), None, config).await.unwrap();
+
+        let queries =
+            join_all((0..5).map(|_| connection.query_single_page("SELECT a FROM ks.t", &[])));
+        let check_in_flight = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            // Only two requests were sent, the rest waits for their responses
+            assert_eq!(node.received_statements().len(), 2);
+        };
+        let (results, ()) = tokio::join!(queries, check_in_flight);
+
+        assert!(results.into_iter().all(|result| result.is_ok()));
+        assert_eq!(node.received_statements().len(), 5);
+
+        node.finish().await.unwrap();
+    }
+
+    #[test]
+    fn lwt_mark_parsing() {
+        use super::parse_lwt_mark;
+
+        assert_eq!(
+            parse_lwt_mark("LWT_OPTIMIZATION_META_BIT_MASK=2147483648"),
+            Some(i32::MIN)
+        );
+        assert_eq!(parse_lwt_mark("LWT_OPTIMIZATION_META_BIT_MASK=4"), Some(4));
+        assert_eq!(parse_lwt_mark("LWT_OPTIMIZATION_META_BIT_MASK=x"), None);
+        assert_eq!(parse_lwt_mark("OTHER_MASK=4"), None);
+        assert_eq!(parse_lwt_mark("LWT_OPTIMIZATION_META_BIT_MASK"), None);
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn prepared_lwt_is_marked() {
+        use super::{open_connection, ConnectionConfig};
+        use crate::proxy::MockNode;
+        use crate::query::Query;
+
+        let node = MockNode::new().run().await.unwrap();
+        let (connection, _) = open_connection(node.addr(), None, ConnectionConfig::default())
+            .await
+            .unwrap();
+
+        let insert = Query::new("INSERT INTO ks.t (a) VALUES (?) IF NOT EXISTS".to_string());
+        assert!(connection
+            .prepare(&insert)
+            .await
+            .unwrap()
+            .is_confirmed_lwt());
+
+        let select = Query::new("SELECT a FROM ks.t WHERE a = ?".to_string());
+        assert!(!connection
+            .prepare(&select)
+            .await
+            .unwrap()
+            .is_confirmed_lwt());
+
+        node.finish().await.unwrap();
+    }
+}
@@ -1,15 +1,19 @@
 /// ConnectionKeeper keeps a Connection to some address and works to keep it open
 use crate::routing::ShardInfo;
 use crate::transport::errors::QueryError;
+use crate::transport::reconnection_policy::ReconnectionSchedule;
 use crate::transport::{
     connection,
     connection::{Connection, ConnectionConfig, ErrorReceiver, VerifiedKeyspaceName},
+    runtime,
 };
 
 use futures::{future::RemoteHandle, FutureExt};
 use std::io::ErrorKind;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use tracing::{debug, debug_span, Instrument};
 
 /// ConnectionKeeper keeps a Connection to some address and works to keep it open
 pub struct ConnectionKeeper {
@@ -39,6 +43,61 @@ struct ConnectionKeeperWorker {
 
     // Keyspace send in "USE <keyspace name>" when opening each connection
     used_keyspace: Option<VerifiedKeyspaceName>,
+
+    // Reports whether the connection is broken to the health of the pool it belongs to
+    pool_health: Option<PoolHealthGuard>,
+}
+
+/// Counts connections in a node's pool and how many of them are broken.
+/// Kept up to date by the connection keepers, so pool health can be checked without locking the pool
+#[derive(Debug, Default)]
+pub struct PoolHealth {
+    connections: AtomicUsize,
+    broken: AtomicUsize,
+}
+
+impl PoolHealth {
+    /// Returns true if the pool has connections and all of them are broken
+    pub fn is_broken(&self) -> bool {
+        let broken = self.broken.load(Ordering::Relaxed);
+        broken > 0 && broken >= self.connections.load(Ordering::Relaxed)
+    }
+}
+
+// Counts a keeper's connection in PoolHealth for as long as the keeper's worker runs
+struct PoolHealthGuard {
+    health: Arc<PoolHealth>,
+    is_broken: bool,
+}
+
+impl PoolHealthGuard {
+    fn new(health: Arc<PoolHealth>) -> Self {
+        health.connections.fetch_add(1, Ordering::Relaxed);
+        PoolHealthGuard {
+            health,
+            is_broken: false,
+        }
+    }
+
+    fn set_broken(&mut self, is_broken: bool) {
+        if self.is_broken == is_broken {
+            return;
+        }
+
+        if is_broken {
+            self.health.broken.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.health.broken.fetch_sub(1, Ordering::Relaxed);
+        }
+        self.is_broken = is_broken;
+    }
+}
+
+impl Drop for PoolHealthGuard {
+    fn drop(&mut self) {
+        self.set_broken(false);
+        self.health.connections.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 pub type ShardInfoSender = Arc<std::sync::Mutex<tokio::sync::watch::Sender<Option<ShardInfo>>>>;
@@ -57,12 +116,15 @@ impl ConnectionKeeper {
     /// * `compression` - preferred compression method to use
     /// * `shard_info` - ShardInfo to use, will connect to shard number `shard_info.shard`
     /// * `shard_info_sender` - channel to send new ShardInfo after each connection creation
+    /// * `keyspace_name` - keyspace to use on the connection
+    /// * `pool_health` - health of the pool this connection belongs to, updated when the connection breaks or reconnects
     pub fn new(
         address: SocketAddr,
         config: ConnectionConfig,
         shard_info: Option<ShardInfo>,
         shard_info_sender: Option<ShardInfoSender>,
         keyspace_name: Option<VerifiedKeyspaceName>,
+        pool_health: Option<Arc<PoolHealth>>,
     ) -> Self {
         let (conn_state_sender, conn_state_receiver) =
             tokio::sync::watch::channel(ConnectionState::Initializing);
@@ -77,10 +139,14 @@ impl ConnectionKeeper {
             conn_state_sender,
             use_keyspace_channel: use_keyspace_receiver,
             used_keyspace: keyspace_name,
+            pool_health: pool_health.map(PoolHealthGuard::new),
         };
 
-        let (fut, worker_handle) = worker.work().remote_handle();
-        tokio::spawn(fut);
+        let (fut, worker_handle) = worker
+            .work()
+            .instrument(debug_span!("connection_keeper", node = %address))
+            .remote_handle();
+        runtime::spawn(fut);
 
         ConnectionKeeper {
             conn_state_receiver,
@@ -115,6 +181,19 @@ impl ConnectionKeeper {
         ));
     }
 
+    /// Waits until the connection is broken, returns immediately if it already is
+    pub async fn wait_until_broken(&self) {
+        let mut my_receiver = self.conn_state_receiver.clone();
+
+        while !matches!(&*my_receiver.borrow(), ConnectionState::Broken(_)) {
+            my_receiver
+                .changed()
+                .await
+                .expect("Bug in ConnectionKeeper::wait_until_broken");
+            // Worker can't stop while we have &self to struct with worker_handle
+        }
+    }
+
     /// Wait for the connection to initialize and get it if succesfylly connected
     pub async fn get_connection(&self) -> Result<Arc<Connection>, QueryError> {
         self.wait_until_initialized().await;
@@ -146,6 +225,8 @@ impl ConnectionKeeper {
 }
 
 enum RunConnectionRes {
+    // Opening the connection has failed
+    ConnectError(QueryError),
     // An error occured during the connection
     Error(QueryError),
     // ConnectionKeeper was dropped and channels closed, we should stop
@@ -154,20 +235,31 @@ enum RunConnectionRes {
 
 impl ConnectionKeeperWorker {
     pub async fn work(mut self) {
-        // Reconnect at most every 8 seconds
-        let reconnect_cooldown = tokio::time::Duration::from_secs(8);
-        let mut last_reconnect_time;
+        // Delays between reconnection attempts, a new schedule starts each time the connection breaks
+        let mut reconnection_schedule: Option<Box<dyn ReconnectionSchedule>> = None;
 
         loop {
-            last_reconnect_time = tokio::time::Instant::now();
-
             // Connect and wait for error
             let current_error: QueryError = match self.run_connection().await {
-                RunConnectionRes::Error(e) => e,
+                RunConnectionRes::ConnectError(e) => e,
+                RunConnectionRes::Error(e) => {
+                    reconnection_schedule = None;
+                    e
+                }
                 RunConnectionRes::ShouldStop => return,
             };
 
-            // Mark the connection as broken, wait cooldown and reconnect
+            // Mark the connection as broken, wait the delay from the reconnection policy and reconnect
+            let reconnection_policy = &self.config.reconnection_policy;
+            let delay = reconnection_schedule
+                .get_or_insert_with(|| reconnection_policy.new_schedule())
+                .next_delay();
+            debug!(
+                "Connection unavailable ({}), reconnecting in {:?}",
+                current_error, delay
+            );
+
+            self.set_pool_health_broken(true);
             if self
                 .conn_state_sender
                 .send(ConnectionState::Broken(current_error))
@@ -177,11 +269,7 @@ impl ConnectionKeeperWorker {
                 return;
             }
 
-            let next_reconnect_time = last_reconnect_time
-                .checked_add(reconnect_cooldown)
-                .unwrap_or_else(tokio::time::Instant::now);
-
-            tokio::time::sleep_until(next_reconnect_time).await;
+            runtime::sleep(delay).await;
         }
     }
 
@@ -190,7 +278,7 @@ impl ConnectionKeeperWorker {
         // Connect to the node
         let (connection, mut error_receiver) = match self.open_new_connection().await {
             Ok(opened) => opened,
-            Err(e) => return RunConnectionRes::Error(e),
+            Err(e) => return RunConnectionRes::ConnectError(e),
         };
 
         // Mark connection as Connected
@@ -202,6 +290,7 @@ impl ConnectionKeeperWorker {
             // If the channel was dropped we should stop
             return RunConnectionRes::ShouldStop;
         }
+        self.set_pool_health_broken(false);
 
         // Notify about new shard info
         if let Some(sender) = &self.shard_info_sender {
@@ -250,6 +339,12 @@ impl ConnectionKeeperWorker {
         }
     }
 
+    fn set_pool_health_broken(&mut self, is_broken: bool) {
+        if let Some(pool_health) = &mut self.pool_health {
+            pool_health.set_broken(is_broken);
+        }
+    }
+
     async fn open_new_connection(&self) -> Result<(Arc<Connection>, ErrorReceiver), QueryError> {
         let (connection, error_receiver) = match &self.shard_info {
             Some(info) => self.open_new_connection_to_shard(info).await?,
@@ -286,9 +381,36 @@ impl ConnectionKeeperWorker {
 
 #[cfg(test)]
 mod tests {
-    use super::ConnectionKeeper;
+    use super::{ConnectionKeeper, PoolHealth, PoolHealthGuard};
     use crate::transport::connection::ConnectionConfig;
     use std::net::{SocketAddr, ToSocketAddrs};
+    use std::sync::Arc;
+
+    #[test]
+    fn pool_health() {
+        let health = Arc::new(PoolHealth::default());
+        // An empty pool isn't broken
+        assert!(!health.is_broken());
+
+        let mut first = PoolHealthGuard::new(health.clone());
+        let mut second = PoolHealthGuard::new(health.clone());
+        assert!(!health.is_broken());
+
+        first.set_broken(true);
+        first.set_broken(true);
+        assert!(!health.is_broken());
+        second.set_broken(true);
+        assert!(health.is_broken());
+
+        second.set_broken(false);
+        assert!(!health.is_broken());
+
+        // Only the broken connection is left
+        drop(second);
+        assert!(health.is_broken());
+        drop(first);
+        assert!(!health.is_broken());
+    }
 
     // Open many connections to a node
     // Port collision should occur
@@ -313,8 +435,14 @@ mod tests {
         };
 
         // Get shard info from a single connection, all connections will open to this shard
-        let conn_keeper =
-            ConnectionKeeper::new(connect_address, connection_config.clone(), None, None, None);
+        let conn_keeper = ConnectionKeeper::new(
+            connect_address,
+            connection_config.clone(),
+            None,
+            None,
+            None,
+            None,
+        );
         let shard_info = conn_keeper
             .get_connection()
             .await
@@ -332,6 +460,7 @@ mod tests {
                 shard_info.clone(),
                 None,
                 None,
+                None,
             );
 
             conn_keepers.push(conn_keeper);
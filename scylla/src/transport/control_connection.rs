@@ -0,0 +1,83 @@
+/// ControlConnection is a single connection used to fetch cluster metadata and receive server events
+use crate::transport::connection::{Connection, ConnectionConfig};
+use crate::transport::connection_keeper::ConnectionKeeper;
+use crate::transport::errors::QueryError;
+
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// ControlConnection is a single connection used to fetch cluster metadata and receive server events.
+/// When the node it is connected to becomes unreachable it can be moved to another known node.
+pub struct ControlConnection {
+    address: SocketAddr,
+    connection: ConnectionKeeper,
+    connection_config: ConnectionConfig,
+
+    // when control connection fails, it can be moved to one of known_peers
+    known_peers: Vec<SocketAddr>,
+}
+
+impl ControlConnection {
+    /// Creates new ControlConnection to a random node from `known_peers`
+    pub fn new(known_peers: &[SocketAddr], connection_config: ConnectionConfig) -> Self {
+        let address = *known_peers
+            .choose(&mut thread_rng())
+            .expect("Tried to initialize ControlConnection with empty known_peers list!");
+
+        let connection = Self::open_connection(address, &connection_config);
+
+        ControlConnection {
+            address,
+            connection,
+            connection_config,
+            known_peers: known_peers.into(),
+        }
+    }
+
+    fn open_connection(
+        address: SocketAddr,
+        connection_config: &ConnectionConfig,
+    ) -> ConnectionKeeper {
+        ConnectionKeeper::new(address, connection_config.clone(), None, None, None, None)
+    }
+
+    /// Address of the node this control connection is connected to
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Wait for the connection to initialize and get it if succesfully connected
+    pub async fn get_connection(&self) -> Result<Arc<Connection>, QueryError> {
+        self.connection.get_connection().await
+    }
+
+    /// Waits until the current connection is broken
+    pub async fn wait_until_broken(&self) {
+        self.connection.wait_until_broken().await
+    }
+
+    /// Returns known peers other than the current one, in random order
+    pub fn failover_candidates(&self) -> Vec<SocketAddr> {
+        let mut candidates: Vec<SocketAddr> = self
+            .known_peers
+            .iter()
+            .filter(|&peer| *peer != self.address)
+            .copied()
+            .collect();
+
+        candidates.shuffle(&mut thread_rng());
+        candidates
+    }
+
+    /// Drops the current connection and connects to the given address
+    pub fn reconnect_to(&mut self, address: SocketAddr) {
+        self.address = address;
+        self.connection = Self::open_connection(address, &self.connection_config);
+    }
+
+    pub fn update_known_peers(&mut self, known_peers: Vec<SocketAddr>) {
+        self.known_peers = known_peers;
+    }
+}
@@ -0,0 +1,985 @@
+//! Bulk loading of CSV files using a prepared `INSERT` statement
+//!
+//! Each CSV record is bound to bind markers of the statement and executed,
+//! many statements are executed concurrently. Fields are parsed according to the types
+//! of bind markers, or types set with [`CsvLoader::column_type`]:
+//! * empty fields are loaded as nulls, see [`CsvLoader::null_value`]
+//! * `blob` is written in hex, e.g. `0x01ab`
+//! * `date` is `YYYY-MM-DD`, `time` is `HH:MM:SS[.fff]`
+//! * `timestamp` is `YYYY-MM-DD[ HH:MM:SS[.fff][+ZZZZ]]`, RFC 3339, or milliseconds since unix epoch
+//! * collections, tuples and user defined types use CQL literal syntax, e.g. `['a', 'b']`,
+//!   `{1: 'x'}`, `(1, 'a')` or `{street: 'Main', number: 3}`
+//!
+//! This is the same format as written by [`CsvRowSink`](super::row_sink::CsvRowSink),
+//! so tables exported with it can be loaded back. Quotes aren't kept after parsing,
+//! so empty strings written as `""` are loaded as nulls too, unless another null value is set.
+//!
+//! Available with the `csv` feature.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use bytes::BufMut;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
+use futures::stream::{FuturesUnordered, StreamExt};
+use num_bigint::BigInt;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use super::errors::QueryError;
+use super::runtime;
+use super::session::Session;
+use crate::frame::response::result::ColumnType;
+use crate::frame::value::{
+    CqlDuration, Date, SerializeValuesError, SerializedValues, Time, Timestamp, Value, ValueTooBig,
+};
+use crate::prepared_statement::PreparedStatement;
+
+/// Error which stopped the whole load
+#[derive(Error, Debug)]
+pub enum CsvLoadError {
+    /// Reading the input or parsing its CSV structure has failed
+    #[error("Reading CSV failed: {0}")]
+    Csv(#[from] csv::Error),
+
+    /// The thread reading the input has stopped unexpectedly
+    #[error("Reading CSV stopped unexpectedly")]
+    ReaderStopped,
+
+    /// A column of the CSV header doesn't match any bind marker
+    #[error("CSV column {0} doesn't match any bind marker of the statement")]
+    UnknownColumn(String),
+
+    /// The CSV header contains the same column twice
+    #[error("CSV column {0} appears more than once")]
+    DuplicateColumn(String),
+
+    /// A bind marker has no matching column in the CSV header
+    #[error("Bind marker {0} has no matching CSV column")]
+    MissingColumn(String),
+
+    /// More rows have failed than allowed by [`CsvLoader::max_errors`]
+    #[error("Loading stopped after {} failed rows, last error: {last_error}", progress.rows_failed)]
+    TooManyErrors {
+        progress: LoadProgress,
+        last_error: Box<RowError>,
+    },
+}
+
+/// Error which caused a single CSV record not to be loaded
+#[derive(Error, Debug)]
+pub enum RowError {
+    /// A field couldn't be parsed as the type of its column
+    #[error("Line {line}: can't parse {value:?} in column {column} as {typ}: {reason}")]
+    Parse {
+        line: u64,
+        column: String,
+        typ: String,
+        value: String,
+        reason: String,
+    },
+
+    /// Record has a different number of fields than the statement has bind markers
+    #[error("Line {line}: record has {len} fields, expected {expected}")]
+    BadFieldCount {
+        line: u64,
+        len: usize,
+        expected: usize,
+    },
+
+    /// Bound values couldn't be serialized
+    #[error("Line {line}: {error}")]
+    Serialize {
+        line: u64,
+        error: SerializeValuesError,
+    },
+
+    /// Executing the statement has failed
+    #[error("Line {line}: {error}")]
+    Query { line: u64, error: QueryError },
+}
+
+impl RowError {
+    /// Line of the CSV input on which the failed record starts, counting from 1
+    pub fn line(&self) -> u64 {
+        match self {
+            RowError::Parse { line, .. }
+            | RowError::BadFieldCount { line, .. }
+            | RowError::Serialize { line, .. }
+            | RowError::Query { line, .. } => *line,
+        }
+    }
+}
+
+/// Number of records processed so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LoadProgress {
+    /// Records read from the input, including the ones still being inserted
+    pub rows_read: u64,
+    /// Records inserted successfully
+    pub rows_inserted: u64,
+    /// Records which failed to load
+    pub rows_failed: u64,
+}
+
+/// Loads CSV records into a table by executing a prepared statement for each of them
+///
+/// # Example
+/// ```rust
+/// # use scylla::Session;
+/// # use std::error::Error;
+/// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+/// use scylla::transport::csv_loader::CsvLoader;
+/// use std::fs::File;
+///
+/// let insert = session
+///     .prepare("INSERT INTO ks.tab (a, b) VALUES (?, ?)")
+///     .await?;
+///
+/// let progress = CsvLoader::new(session, &insert)
+///     .concurrency(256)
+///     .max_errors(10)
+///     .on_error(|err| eprintln!("{}", err))
+///     .on_progress(|p| println!("{} rows inserted", p.rows_inserted))
+///     .load(File::open("tab.csv")?)
+///     .await?;
+///
+/// println!("Loaded {} rows, {} failed", progress.rows_inserted, progress.rows_failed);
+/// # Ok(())
+/// # }
+/// ```
+pub struct CsvLoader<'a> {
+    session: &'a Session,
+    statement: &'a PreparedStatement,
+    column_types: HashMap<String, ColumnType>,
+    has_header: bool,
+    delimiter: u8,
+    null_value: String,
+    concurrency: usize,
+    max_errors: u64,
+    progress_interval: u64,
+    on_error: Option<ErrorCallback<'a>>,
+    on_progress: Option<ProgressCallback<'a>>,
+}
+
+type ErrorCallback<'a> = Box<dyn FnMut(&RowError) + Send + 'a>;
+type ProgressCallback<'a> = Box<dyn FnMut(&LoadProgress) + Send + 'a>;
+
+impl<'a> CsvLoader<'a> {
+    /// Creates a loader executing `statement` for each CSV record.
+    /// By default the first record is a header with bind marker names,
+    /// 100 statements are executed concurrently and loading stops on the first error
+    pub fn new(session: &'a Session, statement: &'a PreparedStatement) -> Self {
+        CsvLoader {
+            session,
+            statement,
+            column_types: HashMap::new(),
+            has_header: true,
+            delimiter: b',',
+            null_value: String::new(),
+            concurrency: 100,
+            max_errors: 0,
+            progress_interval: 10_000,
+            on_error: None,
+            on_progress: None,
+        }
+    }
+
+    /// Sets whether the first record is a header.
+    /// Columns of a header are matched with bind markers by name, so their order doesn't matter.
+    /// Without a header fields are bound to bind markers in order
+    pub fn has_header(mut self, has_header: bool) -> Self {
+        self.has_header = has_header;
+        self
+    }
+
+    /// Sets the field delimiter, `b','` by default
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Sets the text of fields which are loaded as nulls, empty fields by default.
+    /// With a different value empty fields of text columns are loaded as empty strings,
+    /// in columns of other types they fail to parse
+    pub fn null_value(mut self, null_value: impl Into<String>) -> Self {
+        self.null_value = null_value.into();
+        self
+    }
+
+    /// Sets how a column is parsed, instead of the type of its bind marker.
+    /// For example text stored in a `blob` column can be loaded as `ColumnType::Text`
+    pub fn column_type(mut self, column: impl Into<String>, typ: ColumnType) -> Self {
+        self.column_types.insert(column.into(), typ);
+        self
+    }
+
+    /// Sets the maximum number of statements executed at the same time
+    /// # Panics
+    /// Panics if `concurrency` is 0
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        assert!(concurrency > 0, "concurrency must be positive");
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Sets how many records can fail before loading is stopped, 0 by default
+    pub fn max_errors(mut self, max_errors: u64) -> Self {
+        self.max_errors = max_errors;
+        self
+    }
+
+    /// Sets a function called with each error that made a record fail
+    pub fn on_error(mut self, on_error: impl FnMut(&RowError) + Send + 'a) -> Self {
+        self.on_error = Some(Box::new(on_error));
+        self
+    }
+
+    /// Sets a function called every [`progress_interval`](CsvLoader::progress_interval)
+    /// processed records and once after loading has finished
+    pub fn on_progress(mut self, on_progress: impl FnMut(&LoadProgress) + Send + 'a) -> Self {
+        self.on_progress = Some(Box::new(on_progress));
+        self
+    }
+
+    /// Sets how many records are processed between calls to the progress function, 10 000 by default
+    pub fn progress_interval(mut self, progress_interval: u64) -> Self {
+        self.progress_interval = progress_interval.max(1);
+        self
+    }
+
+    /// Loads all records from `reader`.
+    /// Input is read on a separate thread where blocking is allowed,
+    /// so reading files doesn't block the async runtime
+    pub async fn load(
+        mut self,
+        reader: impl io::Read + Send + 'static,
+    ) -> Result<LoadProgress, CsvLoadError> {
+        let mut records = self.read_records(reader);
+
+        let header = if self.has_header {
+            Some(records.recv().await.ok_or(CsvLoadError::ReaderStopped)??)
+        } else {
+            None
+        };
+        let columns = self.columns(header.as_ref())?;
+        let null_value = std::mem::take(&mut self.null_value);
+        let session = self.session;
+        let statement = self.statement;
+
+        let mut progress = LoadProgress::default();
+        let mut in_flight = FuturesUnordered::new();
+        let mut input_finished = false;
+
+        loop {
+            while !input_finished && in_flight.len() < self.concurrency {
+                let record = match records.recv().await {
+                    Some(record) => record?,
+                    None => {
+                        input_finished = true;
+                        break;
+                    }
+                };
+                progress.rows_read += 1;
+
+                let line = record.position().map_or(0, |pos| pos.line());
+                match bind_record(&record, line, &columns, &null_value) {
+                    Ok(values) => in_flight.push(async move {
+                        session
+                            .execute(statement, values)
+                            .await
+                            .map_err(|error| RowError::Query { line, error })
+                    }),
+                    Err(error) => self.row_failed(error, &mut progress)?,
+                }
+            }
+
+            match in_flight.next().await {
+                Some(Ok(_)) => {
+                    progress.rows_inserted += 1;
+                    self.report_progress(&progress);
+                }
+                Some(Err(error)) => self.row_failed(error, &mut progress)?,
+                None if input_finished => break,
+                None => {}
+            }
+        }
+
+        if let Some(on_progress) = &mut self.on_progress {
+            on_progress(&progress);
+        }
+        Ok(progress)
+    }
+
+    // Parses CSV on a blocking thread, the header comes first if there is one.
+    // The thread stops after an error or when the receiver is dropped
+    fn read_records(
+        &self,
+        reader: impl io::Read + Send + 'static,
+    ) -> mpsc::Receiver<csv::Result<csv::StringRecord>> {
+        let (sender, receiver) = mpsc::channel(self.concurrency);
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .has_headers(self.has_header)
+            .delimiter(self.delimiter)
+            .flexible(true)
+            .from_reader(reader);
+        let has_header = self.has_header;
+
+        runtime::spawn_blocking(move || {
+            if has_header {
+                let header = csv_reader.headers().cloned();
+                let failed = header.is_err();
+                if sender.blocking_send(header).is_err() || failed {
+                    return;
+                }
+            }
+
+            for record in csv_reader.into_records() {
+                let failed = record.is_err();
+                if sender.blocking_send(record).is_err() || failed {
+                    return;
+                }
+            }
+        });
+
+        receiver
+    }
+
+    // Returns names and types of CSV columns, in the order of bind markers
+    fn columns(
+        &self,
+        header: Option<&csv::StringRecord>,
+    ) -> Result<Vec<BoundColumn>, CsvLoadError> {
+        let markers = &self.statement.get_prepared_metadata().col_specs;
+        let mut columns: Vec<BoundColumn> = markers
+            .iter()
+            .enumerate()
+            .map(|(index, spec)| BoundColumn {
+                name: spec.name().to_string(),
+                typ: self
+                    .column_types
+                    .get(spec.name())
+                    .unwrap_or_else(|| spec.typ())
+                    .clone(),
+                field: index,
+            })
+            .collect();
+
+        if let Some(header) = header {
+            let mut matched = vec![false; columns.len()];
+            for (field, name) in header.iter().enumerate() {
+                let index = columns
+                    .iter()
+                    .position(|column| column.name == name)
+                    .ok_or_else(|| CsvLoadError::UnknownColumn(name.to_string()))?;
+                if matched[index] {
+                    return Err(CsvLoadError::DuplicateColumn(name.to_string()));
+                }
+                matched[index] = true;
+                columns[index].field = field;
+            }
+            if let Some(missing) = matched.iter().position(|matched| !matched) {
+                return Err(CsvLoadError::MissingColumn(columns[missing].name.clone()));
+            }
+        }
+
+        Ok(columns)
+    }
+
+    fn row_failed(
+        &mut self,
+        error: RowError,
+        progress: &mut LoadProgress,
+    ) -> Result<(), CsvLoadError> {
+        progress.rows_failed += 1;
+        if let Some(on_error) = &mut self.on_error {
+            on_error(&error);
+        }
+
+        if progress.rows_failed > self.max_errors {
+            return Err(CsvLoadError::TooManyErrors {
+                progress: *progress,
+                last_error: Box::new(error),
+            });
+        }
+
+        self.report_progress(progress);
+        Ok(())
+    }
+
+    fn report_progress(&mut self, progress: &LoadProgress) {
+        let processed = progress.rows_inserted + progress.rows_failed;
+        if processed % self.progress_interval == 0 {
+            if let Some(on_progress) = &mut self.on_progress {
+                on_progress(progress);
+            }
+        }
+    }
+}
+
+struct BoundColumn {
+    name: String,
+    typ: ColumnType,
+    // Index of the CSV field bound to this column
+    field: usize,
+}
+
+fn bind_record(
+    record: &csv::StringRecord,
+    line: u64,
+    columns: &[BoundColumn],
+    null_value: &str,
+) -> Result<SerializedValues, RowError> {
+    if record.len() != columns.len() {
+        return Err(RowError::BadFieldCount {
+            line,
+            len: record.len(),
+            expected: columns.len(),
+        });
+    }
+
+    let mut values = SerializedValues::with_capacity(record.as_slice().len() + 8 * columns.len());
+    let mut buf: Vec<u8> = Vec::new();
+    for column in columns {
+        let text = &record[column.field];
+
+        buf.clear();
+        if text == null_value {
+            buf.put_i32(-1);
+        } else {
+            serialize_field(&column.typ, text, &mut buf).map_err(|reason| RowError::Parse {
+                line,
+                column: column.name.clone(),
+                typ: column.typ.to_string(),
+                value: text.to_string(),
+                reason,
+            })?;
+        }
+        values
+            .add_value(&Serialized(&buf))
+            .map_err(|error| RowError::Serialize { line, error })?;
+    }
+    Ok(values)
+}
+
+// A value which is already serialized as [bytes]
+struct Serialized<'a>(&'a [u8]);
+
+impl Value for Serialized<'_> {
+    fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), ValueTooBig> {
+        buf.extend_from_slice(self.0);
+        Ok(())
+    }
+}
+
+/// Parses a single CSV field as a value of type `typ` and serializes it as [bytes]
+fn serialize_field(typ: &ColumnType, text: &str, buf: &mut Vec<u8>) -> Result<(), String> {
+    match typ {
+        ColumnType::List(_)
+        | ColumnType::Set(_)
+        | ColumnType::Map(_, _)
+        | ColumnType::Tuple(_)
+        | ColumnType::UserDefinedType { .. } => {
+            let mut parser = LiteralParser { text, pos: 0 };
+            parser.value(typ, buf)?;
+            parser.skip_whitespace();
+            match parser.rest() {
+                "" => Ok(()),
+                rest => Err(format!("unexpected {:?} after the value", rest)),
+            }
+        }
+        _ => serialize_scalar(typ, text, buf),
+    }
+}
+
+fn serialize_scalar(typ: &ColumnType, text: &str, buf: &mut Vec<u8>) -> Result<(), String> {
+    let trimmed = text.trim();
+    let result = match typ {
+        ColumnType::Ascii | ColumnType::Text => text.serialize(buf),
+        ColumnType::Boolean => match trimmed.to_ascii_lowercase().as_str() {
+            "true" => true.serialize(buf),
+            "false" => false.serialize(buf),
+            _ => return Err("expected true or false".to_string()),
+        },
+        ColumnType::Blob => parse_blob(trimmed)?.serialize(buf),
+        ColumnType::Counter | ColumnType::BigInt => parse::<i64>(trimmed)?.serialize(buf),
+        ColumnType::Int => parse::<i32>(trimmed)?.serialize(buf),
+        ColumnType::SmallInt => parse::<i16>(trimmed)?.serialize(buf),
+        ColumnType::TinyInt => parse::<i8>(trimmed)?.serialize(buf),
+        ColumnType::Double => parse::<f64>(trimmed)?.serialize(buf),
+        ColumnType::Float => parse::<f32>(trimmed)?.serialize(buf),
+        ColumnType::Decimal => parse::<BigDecimal>(trimmed)?.serialize(buf),
+        ColumnType::Varint => parse::<BigInt>(trimmed)?.serialize(buf),
+        ColumnType::Uuid | ColumnType::Timeuuid => parse::<Uuid>(trimmed)?.serialize(buf),
+        ColumnType::Inet => parse::<IpAddr>(trimmed)?.serialize(buf),
+        ColumnType::Date => parse_date(trimmed)?.serialize(buf),
+        ColumnType::Timestamp => parse_timestamp(trimmed)?.serialize(buf),
+        ColumnType::Time => parse_time(trimmed)?.serialize(buf),
+        ColumnType::Duration => parse::<CqlDuration>(trimmed)?.serialize(buf),
+        ColumnType::List(_)
+        | ColumnType::Set(_)
+        | ColumnType::Map(_, _)
+        | ColumnType::Tuple(_)
+        | ColumnType::UserDefinedType { .. } => {
+            return Err("expected a scalar type".to_string());
+        }
+    };
+    result.map_err(|ValueTooBig| "value is too big".to_string())
+}
+
+fn parse<T>(text: &str) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    text.parse().map_err(|err: T::Err| err.to_string())
+}
+
+fn parse_blob(text: &str) -> Result<Vec<u8>, String> {
+    let hex = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .ok_or_else(|| "blobs must start with 0x".to_string())?;
+    if hex.len() % 2 != 0 {
+        return Err("odd number of hex digits".to_string());
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| "invalid hex digit".to_string())
+        })
+        .collect()
+}
+
+fn parse_date(text: &str) -> Result<Date, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        let unix_epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+        let days = date.signed_duration_since(unix_epoch).num_days() + (1 << 31);
+        return days
+            .try_into()
+            .map(Date)
+            .map_err(|_| "date out of range".to_string());
+    }
+
+    // Raw number of days, as written for dates out of chrono's range
+    parse::<u32>(text)
+        .map(Date)
+        .map_err(|_| "expected YYYY-MM-DD".to_string())
+}
+
+fn parse_timestamp(text: &str) -> Result<Timestamp, String> {
+    let millis = if let Ok(millis) = text.parse::<i64>() {
+        millis
+    } else if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        time.timestamp_millis()
+    } else if let Ok(time) = DateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f%z") {
+        time.timestamp_millis()
+    } else if let Ok(time) = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f") {
+        time.and_utc().timestamp_millis()
+    } else if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        date.and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc()
+            .timestamp_millis()
+    } else {
+        return Err("expected YYYY-MM-DD HH:MM:SS.fff+ZZZZ or milliseconds".to_string());
+    };
+
+    Ok(Timestamp(chrono::Duration::milliseconds(millis)))
+}
+
+fn parse_time(text: &str) -> Result<Time, String> {
+    if let Ok(time) = NaiveTime::parse_from_str(text, "%H:%M:%S%.f") {
+        return Ok(Time(time.signed_duration_since(NaiveTime::MIN)));
+    }
+
+    // Raw number of nanoseconds since midnight
+    parse::<i64>(text)
+        .map(|nanos| Time(chrono::Duration::nanoseconds(nanos)))
+        .map_err(|_| "expected HH:MM:SS.fff".to_string())
+}
+
+// Parses nested values written using CQL literal syntax, e.g. {'a': [1, 2]}
+struct LiteralParser<'t> {
+    text: &'t str,
+    pos: usize,
+}
+
+impl<'t> LiteralParser<'t> {
+    fn rest(&self) -> &'t str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    // Skips whitespace and consumes `c` if it's the next character
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at position {}", c, self.pos))
+        }
+    }
+
+    // Calls `element` for each element until `close`, returns the number of elements
+    fn elements(
+        &mut self,
+        close: char,
+        mut element: impl FnMut(&mut Self) -> Result<(), String>,
+    ) -> Result<i32, String> {
+        let mut count: i32 = 0;
+        if self.eat(close) {
+            return Ok(count);
+        }
+        loop {
+            element(self)?;
+            count += 1;
+            if self.eat(close) {
+                return Ok(count);
+            }
+            self.expect(',')?;
+        }
+    }
+
+    // Serializes a value as [bytes], nulls are allowed only in tuples and user defined types
+    fn value(&mut self, typ: &ColumnType, buf: &mut Vec<u8>) -> Result<(), String> {
+        let len_pos = buf.len();
+        buf.put_i32(0);
+
+        match typ {
+            ColumnType::List(elem) | ColumnType::Set(elem) => {
+                let close = if self.eat('[') {
+                    ']'
+                } else {
+                    self.expect('{')?;
+                    '}'
+                };
+                let count_pos = buf.len();
+                buf.put_i32(0);
+                let count = self.elements(close, |p| p.value(elem, buf))?;
+                buf[count_pos..count_pos + 4].copy_from_slice(&count.to_be_bytes());
+            }
+            ColumnType::Map(key_type, value_type) => {
+                self.expect('{')?;
+                let count_pos = buf.len();
+                buf.put_i32(0);
+                let count = self.elements('}', |p| {
+                    p.value(key_type, buf)?;
+                    p.expect(':')?;
+                    p.value(value_type, buf)
+                })?;
+                buf[count_pos..count_pos + 4].copy_from_slice(&count.to_be_bytes());
+            }
+            ColumnType::Tuple(types) => {
+                self.expect('(')?;
+                let mut types = types.iter();
+                self.elements(')', |p| match types.next() {
+                    Some(typ) => p.nullable_value(typ, buf),
+                    None => Err("too many tuple elements".to_string()),
+                })?;
+                // Missing trailing elements are null
+                types.for_each(|_| buf.put_i32(-1));
+            }
+            ColumnType::UserDefinedType { field_types, .. } => {
+                self.expect('{')?;
+                let mut fields: Vec<Option<Vec<u8>>> = vec![None; field_types.len()];
+                self.elements('}', |p| {
+                    p.skip_whitespace();
+                    let name = p.token(true);
+                    let index = field_types
+                        .iter()
+                        .position(|(field_name, _)| field_name == name)
+                        .ok_or_else(|| format!("unknown field {}", name))?;
+                    p.expect(':')?;
+
+                    let mut field = Vec::new();
+                    p.nullable_value(&field_types[index].1, &mut field)?;
+                    fields[index] = Some(field);
+                    Ok(())
+                })?;
+                for field in fields {
+                    match field {
+                        Some(field) => buf.extend_from_slice(&field),
+                        None => buf.put_i32(-1),
+                    }
+                }
+            }
+            _ => {
+                // Scalar values are serialized along with their length
+                buf.truncate(len_pos);
+                self.skip_whitespace();
+                return match self.scalar_text()? {
+                    Some(text) => serialize_scalar(typ, &text, buf),
+                    None => Err("null values are not allowed in collections".to_string()),
+                };
+            }
+        }
+
+        let len: i32 = (buf.len() - len_pos - 4)
+            .try_into()
+            .map_err(|_| "value is too big".to_string())?;
+        buf[len_pos..len_pos + 4].copy_from_slice(&len.to_be_bytes());
+        Ok(())
+    }
+
+    fn nullable_value(&mut self, typ: &ColumnType, buf: &mut Vec<u8>) -> Result<(), String> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let is_null = rest
+            .get(..4)
+            .is_some_and(|word| word.eq_ignore_ascii_case("null"))
+            && !rest[4..].starts_with(|c: char| c.is_alphanumeric());
+        if is_null {
+            self.pos += 4;
+            buf.put_i32(-1);
+            Ok(())
+        } else {
+            self.value(typ, buf)
+        }
+    }
+
+    // Reads a quoted string or a bare token, returns None for a bare null
+    fn scalar_text(&mut self) -> Result<Option<String>, String> {
+        if !self.rest().starts_with('\'') {
+            let token = self.token(false);
+            if token.eq_ignore_ascii_case("null") {
+                return Ok(None);
+            }
+            return Ok(Some(token.to_string()));
+        }
+
+        let mut text = String::new();
+        let mut chars = self.rest()[1..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            if c != '\'' {
+                text.push(c);
+                continue;
+            }
+            // '' is an escaped quote, a single ' ends the string
+            if self.rest()[1 + i + 1..].starts_with('\'') {
+                text.push('\'');
+                chars.next();
+            } else {
+                self.pos += 1 + i + 1;
+                return Ok(Some(text));
+            }
+        }
+        Err("unterminated string".to_string())
+    }
+
+    // Reads an unquoted token. It ends before a separator or a closing bracket,
+    // `:` ends it only when followed by whitespace, so times like 12:30:00 can be written unquoted
+    fn token(&mut self, stop_at_any_colon: bool) -> &'t str {
+        let rest = self.rest();
+        let bytes = rest.as_bytes();
+        let mut end = 0;
+        while end < bytes.len() {
+            let ends_token = match bytes[end] {
+                b',' | b']' | b'}' | b')' => true,
+                b':' => {
+                    stop_at_any_colon
+                        || bytes
+                            .get(end + 1)
+                            .is_none_or(|next| next.is_ascii_whitespace())
+                }
+                _ => false,
+            };
+            if ends_token {
+                break;
+            }
+            end += 1;
+        }
+        self.pos += end;
+        rest[..end].trim_end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bind_record, serialize_field, BoundColumn, RowError};
+    use crate::frame::response::result::ColumnType;
+    use crate::frame::value::{SerializedValues, Value, ValueTooBig};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn serialize(typ: &ColumnType, text: &str) -> Result<Vec<u8>, String> {
+        let mut buf = Vec::new();
+        serialize_field(typ, text, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn serialized(value: impl Value) -> Vec<u8> {
+        let mut buf = Vec::new();
+        value.serialize(&mut buf).map_err(|ValueTooBig| ()).unwrap();
+        buf
+    }
+
+    #[test]
+    fn scalars() {
+        assert_eq!(
+            serialize(&ColumnType::Int, " 42").unwrap(),
+            serialized(42_i32)
+        );
+        assert_eq!(
+            serialize(&ColumnType::Text, " a, b ").unwrap(),
+            serialized(" a, b ")
+        );
+        assert_eq!(
+            serialize(&ColumnType::Boolean, "TRUE").unwrap(),
+            serialized(true)
+        );
+        assert_eq!(
+            serialize(&ColumnType::Blob, "0x01ab").unwrap(),
+            serialized(vec![0x01_u8, 0xab])
+        );
+        assert_eq!(
+            serialize(&ColumnType::Inet, "127.0.0.1").unwrap(),
+            serialized(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        );
+        assert!(serialize(&ColumnType::Int, "abc").is_err());
+        assert!(serialize(&ColumnType::Blob, "0x1").is_err());
+    }
+
+    #[test]
+    fn null_values() {
+        let columns = vec![
+            BoundColumn {
+                name: "a".to_string(),
+                typ: ColumnType::Int,
+                field: 0,
+            },
+            BoundColumn {
+                name: "b".to_string(),
+                typ: ColumnType::Text,
+                field: 1,
+            },
+        ];
+        let record = |fields: [&str; 2]| csv::StringRecord::from(fields.to_vec());
+        let values = |a: Option<i32>, b: Option<&str>| {
+            let mut values = SerializedValues::new();
+            values.add_value(&a).unwrap();
+            values.add_value(&b).unwrap();
+            values
+        };
+
+        // Empty fields are nulls by default
+        assert_eq!(
+            bind_record(&record(["", ""]), 1, &columns, "").unwrap(),
+            values(None, None)
+        );
+
+        // With another null value empty text is loaded as it is
+        assert_eq!(
+            bind_record(&record(["NULL", ""]), 1, &columns, "NULL").unwrap(),
+            values(None, Some(""))
+        );
+        assert!(matches!(
+            bind_record(&record(["", "NULL"]), 1, &columns, "NULL"),
+            Err(RowError::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn dates_and_times() {
+        use crate::frame::value::{Date, Time, Timestamp};
+        use chrono::Duration;
+
+        assert_eq!(
+            serialize(&ColumnType::Date, "1970-01-02").unwrap(),
+            serialized(Date((1 << 31) + 1))
+        );
+        assert_eq!(
+            serialize(&ColumnType::Time, "01:01:01.5").unwrap(),
+            serialized(Time(Duration::milliseconds(3_661_500)))
+        );
+
+        let timestamp = serialized(Timestamp(Duration::milliseconds(1_500)));
+        for text in &[
+            "1500",
+            "1970-01-01 00:00:01.500+0000",
+            "1970-01-01T01:00:01.5+01:00",
+            "1970-01-01 00:00:01.5",
+        ] {
+            assert_eq!(serialize(&ColumnType::Timestamp, text).unwrap(), timestamp);
+        }
+    }
+
+    #[test]
+    fn collections() {
+        let list = ColumnType::List(Box::new(ColumnType::Text));
+        assert_eq!(
+            serialize(&list, "['it''s', 'b']").unwrap(),
+            serialized(vec!["it's", "b"])
+        );
+        assert_eq!(
+            serialize(&list, "[]").unwrap(),
+            serialized(Vec::<&str>::new())
+        );
+        assert!(serialize(&list, "['a', null]").is_err());
+        assert!(serialize(&list, "['a'] x").is_err());
+
+        let map = ColumnType::Map(
+            Box::new(ColumnType::Int),
+            Box::new(ColumnType::List(Box::new(ColumnType::Int))),
+        );
+        let mut expected = std::collections::HashMap::new();
+        expected.insert(7_i32, vec![1_i32, 2]);
+        assert_eq!(
+            serialize(&map, "{7: [1, 2]}").unwrap(),
+            serialized(expected)
+        );
+
+        // Unquoted times end at a colon followed by whitespace
+        let times = ColumnType::Map(Box::new(ColumnType::Time), Box::new(ColumnType::Int));
+        assert_eq!(
+            serialize(&times, "{00:01:00: 1}").unwrap(),
+            serialize(&times, "{'00:01:00': 1}").unwrap()
+        );
+
+        let tuple = ColumnType::Tuple(vec![ColumnType::Int, ColumnType::Text, ColumnType::Int]);
+        assert_eq!(
+            serialize(&tuple, "(1, null)").unwrap(),
+            serialized((1_i32, None::<&str>, None::<i32>))
+        );
+    }
+
+    #[test]
+    fn user_defined_type() {
+        let udt = ColumnType::UserDefinedType {
+            type_name: "address".to_string(),
+            keyspace: "ks".to_string(),
+            field_types: vec![
+                ("street".to_string(), ColumnType::Text),
+                ("number".to_string(), ColumnType::Int),
+                ("flat".to_string(), ColumnType::Int),
+            ],
+        };
+
+        // Fields are serialized in the declared order, like a tuple
+        assert_eq!(
+            serialize(&udt, "{number: 3, street: 'Main'}").unwrap(),
+            serialized(("Main", 3_i32, None::<i32>))
+        );
+        assert!(serialize(&udt, "{floor: 1}").is_err());
+    }
+}
@@ -1,11 +1,13 @@
 //! This module contains various erros which can be returned by [`Session`](crate::Session)
 
-use crate::frame::frame_errors::{FrameError, ParseError};
 use crate::frame::value::SerializeValuesError;
 use crate::statement::Consistency;
+use bytes::Bytes;
 use std::io::ErrorKind;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use thiserror::Error;
+use uuid::Uuid;
 
 /// Error that occured during query execution
 #[derive(Error, Debug, Clone)]
@@ -24,11 +26,113 @@ pub enum QueryError {
 
     /// Unexpected or invalid message received
     #[error("Protocol Error: {0}")]
-    ProtocolError(&'static str),
+    ProtocolError(ProtocolError),
 
-    /// Timeout error has occured, function didn't complete in time.
+    /// Client-side timeout, the driver didn't get a response in time.
+    /// The statement might have been applied by the database
     #[error("Timeout Error")]
     TimeoutError,
+
+    /// Authenticator set in the session config failed or the node requires authentication
+    /// and no authenticator was set
+    #[error("Authentication Error: {0}")]
+    AuthenticationError(String),
+
+    /// All stream ids of the connection are used by requests waiting for responses,
+    /// the request wasn't sent
+    #[error("Unable to allocate stream id, too many requests are in flight on the connection")]
+    UnableToAllocStreamId,
+}
+
+/// Unexpected or invalid message received from the database,
+/// along with the context needed to diagnose it
+#[derive(Error, Debug, Clone)]
+pub enum ProtocolError {
+    /// Received frame couldn't be parsed
+    #[error("Invalid frame received from {node}: {reason}")]
+    InvalidFrame {
+        /// Address of the node which sent the frame
+        node: SocketAddr,
+        reason: String,
+    },
+
+    /// Response of a type which isn't valid for the request, e.g. `READY` to a `QUERY`
+    #[error("Unexpected response from {node} to {request}: {response}")]
+    UnexpectedResponse {
+        /// Address of the node which sent the response
+        node: SocketAddr,
+        /// Request which got the response
+        request: &'static str,
+        /// Type of the response
+        response: &'static str,
+    },
+
+    /// Response with a stream id which doesn't belong to any request sent on the connection
+    #[error("Received response with unexpected stream id {stream_id} from {node}")]
+    UnexpectedStreamId { node: SocketAddr, stream_id: i16 },
+
+    /// `USE` statement set a different keyspace than the requested one
+    #[error("USE {requested} set a different keyspace on {node}: {received}")]
+    KeyspaceMismatch {
+        node: SocketAddr,
+        requested: String,
+        received: String,
+    },
+
+    /// Response to a query of a system table has invalid contents, e.g. rows of unexpected types
+    #[error("Invalid response from {node} to {table} query: {reason}")]
+    SystemTable {
+        node: SocketAddr,
+        table: &'static str,
+        reason: String,
+    },
+
+    /// Tracing info of the query was not found in any attempt
+    #[error(
+        "Tracing info {tracing_id} not found, all tracing queries returned an empty result, \
+        maybe information didnt reach this node yet. \
+        Consider using get_tracing_info_custom with bigger interval in GetTracingConfig"
+    )]
+    TracingInfoNotFound { tracing_id: Uuid },
+
+    /// No value was bound for a partition key column of a prepared statement,
+    /// so the token can't be calculated
+    #[error("No value for partition key column with bind index {pk_index}, {values_len} values bound - can't calculate token")]
+    MissingPartitionKeyValue { pk_index: u16, values_len: i16 },
+
+    /// Load balancing returned no nodes to send the query to,
+    /// e.g. `DcAwareRoundRobinPolicy` excluding remote nodes when the local datacenter is empty
+    #[error("Empty query plan - load balancing policy returned no nodes")]
+    EmptyQueryPlan,
+
+    /// Load balancing returned a node rejected by the host filter, the driver never connects to it
+    #[error("Node {node} is ignored, it was rejected by the host filter")]
+    IgnoredNode { node: SocketAddr },
+
+    /// None of the nodes has opened connections, e.g. the host filter rejected all of them
+    #[error("No connections are opened to any node of the cluster")]
+    NoPooledNodes,
+}
+
+impl ProtocolError {
+    pub(crate) fn system_table(
+        node: SocketAddr,
+        table: &'static str,
+        reason: impl Into<String>,
+    ) -> Self {
+        ProtocolError::SystemTable {
+            node,
+            table,
+            reason: reason.into(),
+        }
+    }
+
+    pub(crate) fn invalid_frame(node: SocketAddr, reason: impl ToString) -> Self {
+        ProtocolError::InvalidFrame {
+            node,
+            reason: reason.to_string(),
+        }
+    }
 }
 
 /// An error sent from the database in response to a query
@@ -173,11 +277,33 @@ pub enum DbError {
         write_type: WriteType,
     },
 
+    /// A write to the CDC log of a table failed, sent only in protocol v5
+    #[error("A write to the CDC log of a table failed")]
+    CdcWriteFailure,
+
+    /// It's unknown whether a lightweight transaction was applied,
+    /// not enough nodes responded to the commit of the Paxos round. Sent only in protocol v5
+    #[error(
+        "It's unknown whether a lightweight transaction was applied \
+        (consistency: {consistency}, received: {received}, required: {required})"
+    )]
+    CasWriteUnknown {
+        /// Consistency level of the commit
+        consistency: Consistency,
+        /// Number of nodes that acknowledged the commit
+        received: i32,
+        /// Number of nodes required to acknowledge the commit
+        required: i32,
+    },
+
     /// Tried to execute a prepared statement that is not prepared. Driver shoud prepare it again
     #[error(
         "Tried to execute a prepared statement that is not prepared. Driver shoud prepare it again"
     )]
-    Unprepared,
+    Unprepared {
+        /// Id of the statement which the node doesn't know
+        statement_id: Bytes,
+    },
 
     /// Internal server error. This indicates a server-side bug
     #[error("Internal server error. This indicates a server-side bug")]
@@ -225,16 +351,27 @@ pub enum BadQuery {
     SerializeValuesError(#[from] SerializeValuesError),
 
     /// Number of values provided doesn't match number of statements in a batch
-    #[error("Length of provided values ({0}) must be equal to number of batch statements ({1})")]
+    #[error(
+        "Length of provided values ({0}) must be equal to number of batch statements ({1}), \
+    every statement needs its own ValueList - use () for statements without bound values"
+    )]
     ValueLenMismatch(usize, usize),
 
     /// Serialized values are too long to compute parition key
     #[error("Serialized values are too long to compute parition key! Length: {0}, Max allowed length: {1}")]
     ValuesTooLongForKey(usize, usize),
 
+    /// A value forming the partition key is null or unset, so the statement can't be routed
+    #[error("Partition key value bound at index {0} is null or unset")]
+    UnboundPartitionKeyValue(u16),
+
     /// Passed invalid keyspace name to use
     #[error("Passed invalid keyspace name to use: {0}")]
     BadKeyspaceName(#[from] BadKeyspaceName),
+
+    /// The statement has its own keyspace, which can't be sent on a connection using protocol v4
+    #[error("Setting keyspace {0} of a single statement requires protocol v5")]
+    KeyspaceRequiresProtocolV5(String),
 }
 
 /// Error that occured during session creation
@@ -249,6 +386,10 @@ pub enum NewSessionError {
     #[error("Empty known nodes list")]
     EmptyKnownNodesList,
 
+    /// Options in the session config are invalid or contradict each other
+    #[error("Invalid session config: {0}")]
+    BadConfig(#[from] BadSessionConfig),
+
     /// Database sent a response containing some error with a message
     #[error("Database returned an error: {0}, Error message: {1}")]
     DbError(DbError, String),
@@ -263,11 +404,21 @@ pub enum NewSessionError {
 
     /// Unexpected or invalid message received
     #[error("Protocol Error: {0}")]
-    ProtocolError(&'static str),
+    ProtocolError(ProtocolError),
 
     /// Timeout error has occured, couldn't connect to node in time.
     #[error("Timeout Error")]
     TimeoutError,
+
+    /// Authenticator set in the session config failed or the node requires authentication
+    /// and no authenticator was set
+    #[error("Authentication Error: {0}")]
+    AuthenticationError(String),
+
+    /// All stream ids of the connection are used by requests waiting for responses,
+    /// the request wasn't sent
+    #[error("Unable to allocate stream id, too many requests are in flight on the connection")]
+    UnableToAllocStreamId,
 }
 
 /// Invalid keyspace name given to `Session::use_keyspace()`
@@ -281,11 +432,33 @@ pub enum BadKeyspaceName {
     #[error("Keyspace name too long, must be up to 48 characters, found {1} characters. Bad keyspace name: '{0}'")]
     TooLong(String, usize),
 
-    /// Illegal character - only alpha-numeric and underscores allowed.
-    #[error("Illegal character found: '{1}', only alpha-numeric and underscores allowed. Bad keyspace name: '{0}'")]
+    /// Illegal character - only alpha-numeric and underscores allowed in case insensitive names,
+    /// control characters and unescaped quotes aren't allowed in case sensitive ones.
+    #[error("Illegal character found: '{1}', only alpha-numeric and underscores allowed in case insensitive names. Bad keyspace name: '{0}'")]
     IllegalCharacter(String, char),
 }
 
+/// Invalid option or combination of options in [`SessionConfig`](crate::transport::session::SessionConfig),
+/// detected before connecting to the cluster
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum BadSessionConfig {
+    /// A timeout or interval is zero, the name of the option is given
+    #[error("{0} must be larger than 0")]
+    ZeroDuration(&'static str),
+
+    /// A limit of concurrent requests is zero, the name of the option is given
+    #[error("{0} must be larger than 0")]
+    ZeroRequestLimit(&'static str),
+
+    /// Default serial consistency is neither `Serial` nor `LocalSerial`
+    #[error("Default serial consistency must be Serial or LocalSerial, got {0:?}")]
+    NonSerialConsistency(Consistency),
+
+    /// Server name for TLS is set, but TLS isn't enabled with an SSL context
+    #[error("TLS server name is set, but no SSL context was given to enable TLS")]
+    TlsServerNameWithoutSsl,
+}
+
 impl std::fmt::Display for WriteType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
@@ -304,15 +477,15 @@ impl From<SerializeValuesError> for QueryError {
     }
 }
 
-impl From<ParseError> for QueryError {
-    fn from(_parse_error: ParseError) -> QueryError {
-        QueryError::ProtocolError("Error parsing message")
+impl From<ProtocolError> for QueryError {
+    fn from(protocol_error: ProtocolError) -> QueryError {
+        QueryError::ProtocolError(protocol_error)
     }
 }
 
-impl From<FrameError> for QueryError {
-    fn from(_frame_error: FrameError) -> QueryError {
-        QueryError::ProtocolError("Error parsing message frame")
+impl From<ProtocolError> for NewSessionError {
+    fn from(protocol_error: ProtocolError) -> NewSessionError {
+        NewSessionError::ProtocolError(protocol_error)
     }
 }
 
@@ -330,6 +503,8 @@ impl From<QueryError> for NewSessionError {
             QueryError::IoError(e) => NewSessionError::IoError(e),
             QueryError::ProtocolError(m) => NewSessionError::ProtocolError(m),
             QueryError::TimeoutError => NewSessionError::TimeoutError,
+            QueryError::AuthenticationError(e) => NewSessionError::AuthenticationError(e),
+            QueryError::UnableToAllocStreamId => NewSessionError::UnableToAllocStreamId,
         }
     }
 }
@@ -341,6 +516,22 @@ impl From<BadKeyspaceName> for QueryError {
 }
 
 impl QueryError {
+    /// Checks if the statement surely wasn't applied by the database and a retry might succeed,
+    /// e.g. on another node. Such errors can be retried even if the statement isn't idempotent.
+    ///
+    /// Timeouts and broken connections aren't safe to retry, the statement might have been applied.
+    pub fn is_safe_to_retry(&self) -> bool {
+        match self {
+            QueryError::DbError(db_error, _) => db_error.is_safe_to_retry(),
+            QueryError::UnableToAllocStreamId => true,
+            QueryError::BadQuery(_)
+            | QueryError::IoError(_)
+            | QueryError::ProtocolError(_)
+            | QueryError::TimeoutError
+            | QueryError::AuthenticationError(_) => false,
+        }
+    }
+
     /// Checks if this error indicates that a chosen source port/address cannot be bound.
     /// This is caused by one of the following:
     /// - The source address is already used by another socket,
@@ -357,6 +548,55 @@ impl QueryError {
     }
 }
 
+impl DbError {
+    /// Checks if the database surely didn't apply the statement and a retry might succeed,
+    /// see [`QueryError::is_safe_to_retry`]
+    pub fn is_safe_to_retry(&self) -> bool {
+        match self {
+            // The coordinator rejected the statement before executing it
+            DbError::Unavailable { .. } | DbError::IsBootstrapping | DbError::Unprepared { .. } => {
+                true
+            }
+            // Reads don't modify data
+            DbError::ReadTimeout { .. } => true,
+            DbError::SyntaxError
+            | DbError::Invalid
+            | DbError::AlreadyExists { .. }
+            | DbError::FunctionFailure { .. }
+            | DbError::AuthenticationError
+            | DbError::Unauthorized
+            | DbError::ConfigError
+            | DbError::Overloaded
+            | DbError::TruncateError
+            | DbError::WriteTimeout { .. }
+            | DbError::ReadFailure { .. }
+            | DbError::WriteFailure { .. }
+            | DbError::CdcWriteFailure
+            | DbError::CasWriteUnknown { .. }
+            | DbError::ServerError
+            | DbError::ProtocolError
+            | DbError::Other(_) => false,
+        }
+    }
+}
+
+impl WriteType {
+    /// Returns the name of the write type used in the protocol
+    pub fn as_str(&self) -> &str {
+        match self {
+            WriteType::Simple => "SIMPLE",
+            WriteType::Batch => "BATCH",
+            WriteType::UnloggedBatch => "UNLOGGED_BATCH",
+            WriteType::Counter => "COUNTER",
+            WriteType::BatchLog => "BATCH_LOG",
+            WriteType::Cas => "CAS",
+            WriteType::View => "VIEW",
+            WriteType::Cdc => "CDC",
+            WriteType::Other(write_type) => write_type.as_str(),
+        }
+    }
+}
+
 impl From<&str> for WriteType {
     fn from(write_type_str: &str) -> WriteType {
         match write_type_str {
@@ -375,8 +615,9 @@ impl From<&str> for WriteType {
 
 #[cfg(test)]
 mod tests {
-    use super::{DbError, QueryError, WriteType};
+    use super::{DbError, NewSessionError, ProtocolError, QueryError, WriteType};
     use crate::statement::Consistency;
+    use std::net::SocketAddr;
 
     #[test]
     fn write_type_from_str() {
@@ -431,4 +672,72 @@ mod tests {
 
         assert_eq!(query_error_displayed, expected_querr_msg);
     }
+
+    // Protocol errors display the context in which they occured
+    #[test]
+    fn protocol_error_context() {
+        let node: SocketAddr = "127.0.0.1:9042".parse().unwrap();
+
+        let query_error: QueryError = ProtocolError::UnexpectedResponse {
+            node,
+            request: "REGISTER",
+            response: "RESULT Void",
+        }
+        .into();
+        assert_eq!(
+            query_error.to_string(),
+            "Protocol Error: Unexpected response from 127.0.0.1:9042 to REGISTER: RESULT Void"
+        );
+
+        let session_error: NewSessionError =
+            ProtocolError::system_table(node, "system.local", "no rows returned").into();
+        assert_eq!(
+            session_error.to_string(),
+            "Protocol Error: Invalid response from 127.0.0.1:9042 to system.local query: no rows returned"
+        );
+
+        let query_error: QueryError =
+            ProtocolError::UnexpectedStreamId { node, stream_id: 7 }.into();
+        assert!(matches!(
+            NewSessionError::from(query_error),
+            NewSessionError::ProtocolError(ProtocolError::UnexpectedStreamId { stream_id: 7, .. })
+        ));
+    }
+
+    #[test]
+    fn safe_to_retry() {
+        let db_error = |error| QueryError::DbError(error, String::new());
+
+        assert!(QueryError::UnableToAllocStreamId.is_safe_to_retry());
+        assert!(db_error(DbError::IsBootstrapping).is_safe_to_retry());
+        assert!(db_error(DbError::Unavailable {
+            consistency: Consistency::Quorum,
+            required: 2,
+            alive: 1,
+        })
+        .is_safe_to_retry());
+        assert!(db_error(DbError::ReadTimeout {
+            consistency: Consistency::Quorum,
+            received: 1,
+            required: 2,
+            data_present: false,
+        })
+        .is_safe_to_retry());
+
+        // The statement might have been applied
+        assert!(!QueryError::TimeoutError.is_safe_to_retry());
+        let io_error = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "test");
+        assert!(!QueryError::from(io_error).is_safe_to_retry());
+        assert!(!db_error(DbError::WriteTimeout {
+            consistency: Consistency::Quorum,
+            received: 1,
+            required: 2,
+            write_type: WriteType::Simple,
+        })
+        .is_safe_to_retry());
+        assert!(!db_error(DbError::Overloaded).is_safe_to_retry());
+
+        // Retrying wouldn't help
+        assert!(!db_error(DbError::SyntaxError).is_safe_to_retry());
+    }
 }
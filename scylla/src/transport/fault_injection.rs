@@ -0,0 +1,182 @@
+//! Injecting failures into connections, for testing how the driver and applications handle them
+//!
+//! Enabled with the `fault-injection` feature. A [`FaultInjectionPolicy`] set with
+//! [`SessionBuilder::fault_injection_policy`](crate::SessionBuilder::fault_injection_policy)
+//! decides which connection attempts fail, which requests are delayed
+//! and after which requests the connection breaks, so that connection pools, retries
+//! and iterators can be tested deterministically, without misbehaving nodes.
+//!
+//! # Example
+//! ```
+//! # use scylla::{Session, SessionBuilder};
+//! # use scylla::frame::request::RequestOpcode;
+//! use scylla::transport::fault_injection::{FaultInjectionPolicy, RequestFault};
+//! use std::net::SocketAddr;
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//! use std::sync::Arc;
+//!
+//! // Breaks the connection after every third request
+//! #[derive(Default)]
+//! struct BreakEveryThirdRequest(AtomicUsize);
+//!
+//! impl FaultInjectionPolicy for BreakEveryThirdRequest {
+//!     fn on_request(&self, _addr: SocketAddr, _opcode: RequestOpcode) -> RequestFault {
+//!         match self.0.fetch_add(1, Ordering::Relaxed) % 3 {
+//!             2 => RequestFault::Disconnect,
+//!             _ => RequestFault::None,
+//!         }
+//!     }
+//! }
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let session: Session = SessionBuilder::new()
+//!     .known_node("127.0.0.1:9042")
+//!     .fault_injection_policy(Arc::new(BreakEveryThirdRequest::default()))
+//!     .build()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::frame::request::RequestOpcode;
+
+/// Fault injected when opening a connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectFault {
+    /// Connect normally
+    None,
+    /// Wait before connecting, the delay doesn't count towards the connect timeout
+    Delay(Duration),
+    /// Fail with an IO error of the given kind without connecting,
+    /// e.g. `ErrorKind::TimedOut` is reported as a timeout
+    Fail(ErrorKind),
+}
+
+/// Fault injected when sending a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestFault {
+    /// Send the request normally
+    None,
+    /// Wait before sending the request
+    Delay(Duration),
+    /// Send the request and break the connection before the response arrives.
+    /// All requests waiting for a response on the connection fail
+    Disconnect,
+}
+
+/// Decides which faults are injected into connections.
+/// Called for every connection attempt and every request, including the ones sent
+/// by the driver itself, e.g. `STARTUP` or topology queries
+pub trait FaultInjectionPolicy: Send + Sync {
+    /// Called before connecting to the node at `addr`
+    fn on_connect(&self, _addr: SocketAddr) -> ConnectFault {
+        ConnectFault::None
+    }
+
+    /// Called before sending a request with `opcode` to the node at `addr`
+    fn on_request(&self, _addr: SocketAddr, _opcode: RequestOpcode) -> RequestFault {
+        RequestFault::None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConnectFault, FaultInjectionPolicy, RequestFault};
+    use crate::frame::request::RequestOpcode;
+    use crate::transport::connection::{Connection, ConnectionConfig};
+    use crate::transport::errors::QueryError;
+    use std::io::ErrorKind;
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+
+    // Returns the faults set by the test
+    #[derive(Default)]
+    struct SetFaults {
+        connect: Mutex<Option<ConnectFault>>,
+        query: Mutex<Option<RequestFault>>,
+    }
+
+    impl FaultInjectionPolicy for SetFaults {
+        fn on_connect(&self, _addr: SocketAddr) -> ConnectFault {
+            self.connect
+                .lock()
+                .unwrap()
+                .clone()
+                .unwrap_or(ConnectFault::None)
+        }
+
+        fn on_request(&self, _addr: SocketAddr, opcode: RequestOpcode) -> RequestFault {
+            match opcode {
+                RequestOpcode::Query => self.query.lock().unwrap().take(),
+                _ => None,
+            }
+            .unwrap_or(RequestFault::None)
+        }
+    }
+
+    #[tokio::test]
+    async fn connect_faults() {
+        let policy = Arc::new(SetFaults::default());
+        let config = ConnectionConfig {
+            fault_injection_policy: Some(policy.clone()),
+            ..Default::default()
+        };
+        // Nothing listens on this address, but no connection attempt is made
+        let addr: SocketAddr = "127.0.0.1:1".parse().unwrap();
+
+        *policy.connect.lock().unwrap() = Some(ConnectFault::Fail(ErrorKind::ConnectionRefused));
+        match Connection::new(addr, None, config.clone()).await {
+            Err(QueryError::IoError(err)) => assert_eq!(err.kind(), ErrorKind::ConnectionRefused),
+            other => panic!("Unexpected result: {:?}", other.map(|_| ())),
+        }
+
+        *policy.connect.lock().unwrap() = Some(ConnectFault::Fail(ErrorKind::TimedOut));
+        assert!(matches!(
+            Connection::new(addr, None, config).await,
+            Err(QueryError::TimeoutError)
+        ));
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn request_faults() {
+        use crate::proxy::MockNode;
+        use crate::{Session, SessionBuilder};
+        use std::time::{Duration, Instant};
+
+        let node = MockNode::new().run().await.unwrap();
+        let policy = Arc::new(SetFaults::default());
+        let session: Session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .fault_injection_policy(policy.clone())
+            .build()
+            .await
+            .unwrap();
+
+        *policy.query.lock().unwrap() = Some(RequestFault::Delay(Duration::from_millis(100)));
+        let start = Instant::now();
+        session
+            .query("INSERT INTO ks.t (a) VALUES (1)", &[])
+            .await
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        // The node receives the statement, but the driver doesn't get the response
+        *policy.query.lock().unwrap() = Some(RequestFault::Disconnect);
+        let error = session
+            .query("INSERT INTO ks.t (a) VALUES (2)", &[])
+            .await
+            .unwrap_err();
+        assert!(matches!(error, QueryError::IoError(_)));
+        let sent = "INSERT INTO ks.t (a) VALUES (2)".to_string();
+        while !node.received_statements().contains(&sent) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        node.finish().await.unwrap();
+    }
+}
@@ -0,0 +1,242 @@
+//! Filters deciding which nodes of the cluster the driver may connect to
+//!
+//! Nodes rejected by the filter are ignored: they are kept in the cluster topology,
+//! so the token ring stays complete, but no connections are opened to them
+//! and queries are never sent to them, see [`Node::is_ignored`](crate::transport::Node::is_ignored).
+//! Nodes given as known nodes are used as contact points regardless of the filter.
+
+use std::collections::HashSet;
+use std::net::IpAddr;
+
+use super::topology::Peer;
+
+/// Decides whether the driver may connect to a node found in `system.peers`
+pub trait HostFilter: Send + Sync {
+    /// Returns true if connections to the node may be opened
+    fn accept(&self, peer: &Peer) -> bool;
+}
+
+/// Accepts all nodes
+#[derive(Debug, Default, Clone)]
+pub struct AcceptAllHostFilter;
+
+impl HostFilter for AcceptAllHostFilter {
+    fn accept(&self, _peer: &Peer) -> bool {
+        true
+    }
+}
+
+/// Accepts only nodes with the given IP addresses
+#[derive(Debug, Clone)]
+pub struct AllowListHostFilter {
+    allowed: HashSet<IpAddr>,
+}
+
+impl AllowListHostFilter {
+    /// Creates a filter accepting nodes with one of `addresses`.
+    /// Ports aren't compared, because nodes can be reached at different ports, e.g. shard-aware port.
+    pub fn new(addresses: impl IntoIterator<Item = IpAddr>) -> Self {
+        AllowListHostFilter {
+            allowed: addresses.into_iter().collect(),
+        }
+    }
+}
+
+impl HostFilter for AllowListHostFilter {
+    fn accept(&self, peer: &Peer) -> bool {
+        self.allowed.contains(&peer.address.ip())
+    }
+}
+
+/// Accepts only nodes in the given datacenters
+#[derive(Debug, Clone)]
+pub struct DcHostFilter {
+    datacenters: HashSet<String>,
+}
+
+impl DcHostFilter {
+    /// Creates a filter accepting nodes in one of `datacenters`.
+    /// Nodes with unknown datacenter are rejected
+    pub fn new(datacenters: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        DcHostFilter {
+            datacenters: datacenters.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl HostFilter for DcHostFilter {
+    fn accept(&self, peer: &Peer) -> bool {
+        match &peer.datacenter {
+            Some(dc) => self.datacenters.contains(dc),
+            None => false,
+        }
+    }
+}
+
+/// Accepts only nodes with addresses in the given subnet, e.g. 10.0.0.0/8
+#[derive(Debug, Clone)]
+pub struct SubnetHostFilter {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl SubnetHostFilter {
+    /// Creates a filter accepting addresses with the same first `prefix_len` bits as `network`.
+    /// IPv4 nodes never match an IPv6 subnet and vice versa.
+    ///
+    /// # Panics
+    /// Panics if `prefix_len` is longer than the address (32 bits for IPv4, 128 for IPv6)
+    pub fn new(network: IpAddr, prefix_len: u8) -> Self {
+        let max_len = match network {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        assert!(
+            prefix_len <= max_len,
+            "prefix length {} is longer than the address",
+            prefix_len
+        );
+        SubnetHostFilter {
+            network,
+            prefix_len,
+        }
+    }
+
+    fn contains(&self, address: IpAddr) -> bool {
+        match (self.network, address) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                prefix_matches(&network.octets(), &address.octets(), self.prefix_len)
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                prefix_matches(&network.octets(), &address.octets(), self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl HostFilter for SubnetHostFilter {
+    fn accept(&self, peer: &Peer) -> bool {
+        self.contains(peer.address.ip())
+    }
+}
+
+// Checks if the first `prefix_len` bits of both addresses are equal
+fn prefix_matches(network: &[u8], address: &[u8], prefix_len: u8) -> bool {
+    let full_bytes = prefix_len as usize / 8;
+    let remaining_bits = prefix_len % 8;
+
+    if network[..full_bytes] != address[..full_bytes] {
+        return false;
+    }
+    if remaining_bits == 0 {
+        return true;
+    }
+
+    let mask = !(0xffu8 >> remaining_bits);
+    network[full_bytes] & mask == address[full_bytes] & mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AllowListHostFilter, DcHostFilter, HostFilter, SubnetHostFilter};
+    use crate::transport::topology::Peer;
+    use std::net::{IpAddr, SocketAddr};
+
+    fn peer(address: &str, datacenter: Option<&str>) -> Peer {
+        Peer {
+            address: address.parse().unwrap(),
+            tokens: Vec::new(),
+            datacenter: datacenter.map(String::from),
+            rack: None,
+            host_id: None,
+        }
+    }
+
+    #[test]
+    fn allow_list_compares_ips() {
+        let filter = AllowListHostFilter::new(vec!["10.0.0.1".parse::<IpAddr>().unwrap()]);
+        assert!(filter.accept(&peer("10.0.0.1:9042", None)));
+        assert!(filter.accept(&peer("10.0.0.1:19042", None)));
+        assert!(!filter.accept(&peer("10.0.0.2:9042", None)));
+    }
+
+    #[test]
+    fn dc_filter() {
+        let filter = DcHostFilter::new(vec!["eu", "us"]);
+        assert!(filter.accept(&peer("10.0.0.1:9042", Some("eu"))));
+        assert!(filter.accept(&peer("10.0.0.1:9042", Some("us"))));
+        assert!(!filter.accept(&peer("10.0.0.1:9042", Some("asia"))));
+        assert!(!filter.accept(&peer("10.0.0.1:9042", None)));
+    }
+
+    #[test]
+    fn subnet_filter() {
+        let accepts = |network: &str, prefix_len: u8, address: &str| {
+            let address: SocketAddr = address.parse().unwrap();
+            SubnetHostFilter::new(network.parse().unwrap(), prefix_len)
+                .accept(&peer(&address.to_string(), None))
+        };
+
+        assert!(accepts("10.0.0.0", 8, "10.1.2.3:9042"));
+        assert!(!accepts("10.0.0.0", 8, "11.1.2.3:9042"));
+        assert!(accepts("192.168.16.0", 20, "192.168.31.255:9042"));
+        assert!(!accepts("192.168.16.0", 20, "192.168.32.0:9042"));
+        assert!(accepts("192.168.1.7", 32, "192.168.1.7:9042"));
+        assert!(!accepts("192.168.1.7", 32, "192.168.1.6:9042"));
+        assert!(accepts("0.0.0.0", 0, "1.2.3.4:9042"));
+
+        assert!(accepts("fd00::", 8, "[fd12::1]:9042"));
+        assert!(!accepts("fd00::", 8, "[fe80::1]:9042"));
+        assert!(!accepts("10.0.0.0", 8, "[::ffff:10.0.0.1]:9042"));
+        assert!(!accepts("::", 0, "10.0.0.1:9042"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn subnet_prefix_too_long() {
+        SubnetHostFilter::new("10.0.0.0".parse().unwrap(), 33);
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn rejected_nodes_are_ignored() {
+        use crate::proxy::MockNode;
+        use crate::transport::errors::{ProtocolError, QueryError};
+        use crate::SessionBuilder;
+        use std::sync::Arc;
+
+        let node = MockNode::new().run().await.unwrap();
+
+        // The mock node is in datacenter1
+        let session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .host_filter(Arc::new(DcHostFilter::new(vec!["datacenter1"])))
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(session.get_cluster_data().all_nodes.len(), 1);
+        session.query("SELECT a FROM ks.t", &[]).await.unwrap();
+
+        // The contact point is still used to read the topology
+        let session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .host_filter(Arc::new(DcHostFilter::new(vec!["other_dc"])))
+            .build()
+            .await
+            .unwrap();
+        let cluster_data = session.get_cluster_data();
+        assert_eq!(cluster_data.all_nodes.len(), 1);
+        assert!(cluster_data.all_nodes[0].is_ignored());
+        assert!(!cluster_data.ring.is_empty());
+        assert!(session.query("SELECT a FROM ks.t", &[]).await.is_err());
+        assert!(matches!(
+            session.prepare("SELECT a FROM ks.t WHERE a = ?").await,
+            Err(QueryError::ProtocolError(ProtocolError::NoPooledNodes))
+        ));
+        assert!(!cluster_data.all_nodes[0].is_connected());
+        assert_eq!(node.received_statements(), vec!["SELECT a FROM ks.t"]);
+
+        node.finish().await.unwrap();
+    }
+}
@@ -2,23 +2,25 @@
 
 use std::future::Future;
 use std::mem;
+use std::net::{Ipv4Addr, SocketAddr};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use bytes::Bytes;
-use futures::Stream;
+use futures::{future::RemoteHandle, FutureExt, Stream};
 use std::result::Result;
 use thiserror::Error;
 use tokio::sync::mpsc;
+use tracing::{debug, debug_span, trace, Instrument};
 
-use super::errors::QueryError;
+use super::errors::{ProtocolError, QueryError};
 use crate::cql_to_rust::{FromRow, FromRowError};
 
 use crate::frame::{
     response::{
         result,
-        result::{Row, Rows},
+        result::{ColumnSpec, ResultMetadata, Row, Rows, RowsIntoIter},
         Response,
     },
     value::SerializedValues,
@@ -29,23 +31,107 @@ use crate::statement::{prepared_statement::PreparedStatement, query::Query};
 use crate::transport::cluster::ClusterData;
 use crate::transport::connection::{Connection, QueryResponse};
 use crate::transport::load_balancing::{LoadBalancingPolicy, Statement};
-use crate::transport::metrics::Metrics;
+use crate::transport::metrics::{Metrics, RequestTarget};
 use crate::transport::node::Node;
+use crate::transport::request_listener::{RequestInfo, RequestKind, RequestListener};
 use crate::transport::retry_policy::{QueryInfo, RetryDecision, RetrySession};
+use crate::transport::row_sink::{DrainError, RowSink};
+use crate::transport::runtime;
+use crate::transport::session::{sleep_before_retry, with_consistency};
 use uuid::Uuid;
 
 /// Iterator over rows returned by paged queries  
 /// Allows to easily access rows without worrying about handling multiple pages
 pub struct RowIterator {
-    current_row_idx: usize,
-    current_page: Rows,
+    current_page: ReceivedPage,
     page_receiver: mpsc::Receiver<Result<ReceivedPage, QueryError>>,
     tracing_ids: Vec<Uuid>,
+    warnings: Vec<String>,
+    paging_state: Option<Bytes>,
+    prefetch_pages: usize,
+
+    // Used to ask the worker for the next page when pages aren't prefetched
+    page_requests: Option<mpsc::UnboundedSender<()>>,
+    page_requested: bool,
+
+    // Set after an error, no more rows are returned then
+    failed: bool,
+
+    // Dropping the handle cancels the worker together with its outstanding page queries
+    _worker_handle: RemoteHandle<()>,
 }
 
+// Rows of a page are deserialized one by one while the iterator reads them
 struct ReceivedPage {
+    pub rows: RowsIntoIter,
+    pub tracing_id: Option<Uuid>,
+    pub warnings: Vec<String>,
+    // Node which sent the page, reported in errors of rows which fail to parse
+    pub node: SocketAddr,
+}
+
+// An empty page has no rows which could fail to parse, so its node doesn't matter
+impl Default for ReceivedPage {
+    fn default() -> Self {
+        ReceivedPage {
+            rows: RowsIntoIter::default(),
+            tracing_id: None,
+            warnings: Vec::new(),
+            node: SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+        }
+    }
+}
+
+/// A single page of rows returned by a paged query
+#[derive(Debug)]
+pub struct Page {
+    /// Rows contained in this page
+    pub rows: Vec<Row>,
+    /// Metadata returned with this page, contains the paging state used to fetch the next page
+    pub metadata: ResultMetadata,
+    /// CQL Tracing uuid of the query which fetched this page - can only be Some if tracing is enabled
+    pub tracing_id: Option<Uuid>,
+    /// Warnings returned by the database with this page
+    pub warnings: Vec<String>,
+}
+
+/// A single page of rows which weren't deserialized yet, returned by `RowIterator::next_raw_page`  
+/// Use [`Rows::raw_rows`] to decode only the needed columns
+#[derive(Debug)]
+pub struct RawPage {
+    /// Rows contained in this page, together with their metadata
     pub rows: Rows,
+    /// CQL Tracing uuid of the query which fetched this page - can only be Some if tracing is enabled
     pub tracing_id: Option<Uuid>,
+    /// Warnings returned by the database with this page
+    pub warnings: Vec<String>,
+}
+
+impl ReceivedPage {
+    fn into_raw_page(self) -> RawPage {
+        RawPage {
+            rows: self.rows.into_remaining_rows(),
+            tracing_id: self.tracing_id,
+            warnings: self.warnings,
+        }
+    }
+
+    // Deserializes the remaining rows of this page
+    fn into_page(mut self) -> Result<Page, QueryError> {
+        let node = self.node;
+        let rows: Vec<Row> = self
+            .rows
+            .by_ref()
+            .collect::<Result<_, _>>()
+            .map_err(|err| ProtocolError::invalid_frame(node, err))?;
+
+        Ok(Page {
+            rows,
+            metadata: self.rows.into_metadata(),
+            tracing_id: self.tracing_id,
+            warnings: self.warnings,
+        })
+    }
 }
 
 /// Fetching pages is asynchronous so `RowIterator` does not implement the `Iterator` trait.  
@@ -57,14 +143,9 @@ impl Stream for RowIterator {
         let mut s = self.as_mut();
 
         if s.is_current_page_exhausted() {
-            match Pin::new(&mut s.page_receiver).poll_recv(cx) {
+            match s.poll_receive_page(cx) {
                 Poll::Ready(Some(Ok(received_page))) => {
-                    s.current_page = received_page.rows;
-                    s.current_row_idx = 0;
-
-                    if let Some(tracing_id) = received_page.tracing_id {
-                        s.tracing_ids.push(tracing_id);
-                    }
+                    s.current_page = received_page;
                 }
                 Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
                 Poll::Ready(None) => return Poll::Ready(None),
@@ -72,11 +153,13 @@ impl Stream for RowIterator {
             }
         }
 
-        let idx = s.current_row_idx;
-        if idx < s.current_page.rows.len() {
-            let row = mem::take(&mut s.current_page.rows[idx]);
-            s.current_row_idx += 1;
-            return Poll::Ready(Some(Ok(row)));
+        if let Some(row_res) = s.current_page.rows.next() {
+            // Rows following a malformed one can't be read, so the iteration ends with the error
+            s.failed |= row_res.is_err();
+            let node = s.current_page.node;
+            return Poll::Ready(Some(
+                row_res.map_err(|err| ProtocolError::invalid_frame(node, err).into()),
+            ));
         }
 
         // We probably got a zero-sized page
@@ -91,6 +174,7 @@ impl RowIterator {
     pub fn into_typed<RowT: FromRow>(self) -> TypedRowIterator<RowT> {
         TypedRowIterator {
             row_iterator: self,
+            row_index: 0,
             phantom_data: Default::default(),
         }
     }
@@ -102,8 +186,13 @@ impl RowIterator {
         load_balancer: Arc<dyn LoadBalancingPolicy>,
         cluster_data: Arc<ClusterData>,
         metrics: Arc<Metrics>,
+        request_listener: Option<Arc<dyn RequestListener>>,
     ) -> RowIterator {
-        let (sender, receiver) = mpsc::channel(1);
+        // Session sets the number of prefetched pages before creating the iterator
+        let prefetch_pages = query.config.prefetch_pages.unwrap_or(1);
+        let (sender, receiver) = mpsc::channel(prefetch_pages.max(1));
+        let (page_requests_sender, page_requests) = page_requests_channel(prefetch_pages);
+        let span = debug_span!("query_iter", statement = query.get_contents());
 
         let worker_task = async move {
             let query_ref = &query;
@@ -111,8 +200,17 @@ impl RowIterator {
 
             let choose_connection = |node: Arc<Node>| async move { node.random_connection().await };
 
-            let page_query = |connection: Arc<Connection>, paging_state: Option<Bytes>| async move {
-                connection.query(query_ref, values_ref, paging_state).await
+            let page_query = |connection: Arc<Connection>,
+                              paging_state: Option<Bytes>,
+                              consistency: Option<Consistency>| async move {
+                connection
+                    .query(
+                        &with_consistency(query_ref, consistency),
+                        values_ref,
+                        paging_state,
+                        query_ref.get_timestamp(),
+                    )
+                    .await
             };
 
             let worker = RowIteratorWorker {
@@ -120,27 +218,45 @@ impl RowIterator {
                 choose_connection,
                 page_query,
                 statement_info: Statement::default(),
+                request: RequestInfo {
+                    kind: RequestKind::QueryIter,
+                    statement: Some(query_ref.get_contents()),
+                },
                 query_is_idempotent: query.config.is_idempotent,
-                query_consistency: query.config.consistency,
+                // Session sets the consistency before creating the iterator
+                query_consistency: query.config.consistency.unwrap_or_default(),
+                consistency_override: None,
                 retry_session,
                 load_balancer,
                 metrics,
+                request_listener,
                 paging_state: None,
+                page_received: false,
+                page_requests,
+                page_request_received: false,
             };
 
             worker.work(cluster_data).await;
         };
 
-        tokio::task::spawn(worker_task);
+        let (worker_fut, worker_handle) = worker_task.instrument(span).remote_handle();
+        runtime::spawn(worker_fut);
 
         RowIterator {
-            current_row_idx: 0,
             current_page: Default::default(),
             page_receiver: receiver,
             tracing_ids: Vec::new(),
+            warnings: Vec::new(),
+            paging_state: None,
+            prefetch_pages,
+            page_requests: page_requests_sender,
+            page_requested: false,
+            failed: false,
+            _worker_handle: worker_handle,
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new_for_prepared_statement(
         prepared: PreparedStatement,
         values: SerializedValues,
@@ -149,24 +265,38 @@ impl RowIterator {
         load_balancer: Arc<dyn LoadBalancingPolicy>,
         cluster_data: Arc<ClusterData>,
         metrics: Arc<Metrics>,
+        request_listener: Option<Arc<dyn RequestListener>>,
     ) -> RowIterator {
-        let (sender, receiver) = mpsc::channel(1);
-
-        let statement_info = Statement {
-            token: Some(token),
-            keyspace: None,
-        };
+        // Session sets the number of prefetched pages before creating the iterator
+        let prefetch_pages = prepared.config.prefetch_pages.unwrap_or(1);
+        let (sender, receiver) = mpsc::channel(prefetch_pages.max(1));
+        let (page_requests_sender, page_requests) = page_requests_channel(prefetch_pages);
+        let span = debug_span!("execute_iter", statement = prepared.get_statement());
 
         let worker_task = async move {
             let prepared_ref = &prepared;
             let values_ref = &values;
+            let token_ref = &token;
+
+            let statement_info = Statement {
+                token: Some(token.clone()),
+                keyspace: prepared_ref.get_keyspace_name(),
+                is_confirmed_lwt: prepared_ref.is_confirmed_lwt(),
+            };
 
             let choose_connection =
-                |node: Arc<Node>| async move { node.connection_for_token(token).await };
+                |node: Arc<Node>| async move { node.connection_for_token(token_ref).await };
 
-            let page_query = |connection: Arc<Connection>, paging_state: Option<Bytes>| async move {
+            let page_query = |connection: Arc<Connection>,
+                              paging_state: Option<Bytes>,
+                              consistency: Option<Consistency>| async move {
                 connection
-                    .execute(prepared_ref, values_ref, paging_state)
+                    .execute(
+                        &with_consistency(prepared_ref, consistency),
+                        values_ref,
+                        paging_state,
+                        prepared_ref.get_timestamp(),
+                    )
                     .await
             };
 
@@ -175,25 +305,112 @@ impl RowIterator {
                 choose_connection,
                 page_query,
                 statement_info,
+                request: RequestInfo {
+                    kind: RequestKind::ExecuteIter,
+                    statement: Some(prepared_ref.get_statement()),
+                },
                 query_is_idempotent: prepared.config.is_idempotent,
-                query_consistency: prepared.config.consistency,
+                // Session sets the consistency before creating the iterator
+                query_consistency: prepared.config.consistency.unwrap_or_default(),
+                consistency_override: None,
                 retry_session,
                 load_balancer,
                 metrics,
+                request_listener,
                 paging_state: None,
+                page_received: false,
+                page_requests,
+                page_request_received: false,
             };
 
             worker.work(cluster_data).await;
         };
 
-        tokio::task::spawn(worker_task);
+        let (worker_fut, worker_handle) = worker_task.instrument(span).remote_handle();
+        runtime::spawn(worker_fut);
 
         RowIterator {
-            current_row_idx: 0,
             current_page: Default::default(),
             page_receiver: receiver,
             tracing_ids: Vec::new(),
+            warnings: Vec::new(),
+            paging_state: None,
+            prefetch_pages,
+            page_requests: page_requests_sender,
+            page_requested: false,
+            failed: false,
+            _worker_handle: worker_handle,
+        }
+    }
+
+    /// Returns the next row, or `None` if there are no more rows  
+    /// Works like `StreamExt::next`, but doesn't require importing the trait
+    pub async fn next(&mut self) -> Option<Result<Row, QueryError>> {
+        futures::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Reads all remaining rows into a collection, e.g. a `Vec<Row>`, stopping at the first error
+    pub async fn try_collect<C: Default + Extend<Row>>(mut self) -> Result<C, QueryError> {
+        let mut collection = C::default();
+        while let Some(row) = self.next().await {
+            collection.extend(std::iter::once(row?));
+        }
+        Ok(collection)
+    }
+
+    /// Converts this iterator into an iterator over whole pages of rows  
+    /// If some rows were already read from the current page, the first page contains only the remaining ones
+    pub fn into_pages(self) -> PageIterator {
+        PageIterator { row_iterator: self }
+    }
+
+    /// Returns the next page of rows, or `None` if there are no more pages  
+    /// If some rows were already read from the current page, only the remaining ones are returned.  
+    /// When the statement was configured with 0 prefetched pages the page query is sent only when this method is called.
+    pub async fn next_page(&mut self) -> Option<Result<Page, QueryError>> {
+        futures::future::poll_fn(|cx| self.poll_next_page(cx)).await
+    }
+
+    /// Returns the next page without deserializing its rows, or `None` if there are no more pages  
+    /// Works like [`RowIterator::next_page`], but allows to decode only the needed columns of each row
+    pub async fn next_raw_page(&mut self) -> Option<Result<RawPage, QueryError>> {
+        futures::future::poll_fn(|cx| self.poll_next_received_page(cx))
+            .await
+            .map(|page_res| page_res.map(ReceivedPage::into_raw_page))
+    }
+
+    /// Writes all remaining rows into `sink`, fetching pages as needed.  
+    /// [`RowSink::begin`] receives column specs of the first page, [`RowSink::finish`] is called
+    /// once all rows were written. See [`CsvRowSink`](crate::transport::row_sink::CsvRowSink) for an example
+    pub async fn drain_into<S: RowSink>(
+        mut self,
+        sink: &mut S,
+    ) -> Result<(), DrainError<S::Error>> {
+        let mut begun = false;
+
+        while let Some(page) = self.next_page().await {
+            let page = page?;
+            if !begun {
+                sink.begin(page.metadata.col_specs())
+                    .map_err(DrainError::SinkError)?;
+                begun = true;
+            }
+            for row in page.rows {
+                sink.write_row(row).map_err(DrainError::SinkError)?;
+            }
+        }
+
+        if !begun {
+            sink.begin(&[]).map_err(DrainError::SinkError)?;
         }
+        sink.finish().map_err(DrainError::SinkError)
+    }
+
+    /// Returns the paging state of the last received page  
+    /// It can be passed to `Session::query_paged` or `Session::execute_paged` to continue
+    /// reading right after this page. `None` means that the last page was reached or nothing was received yet.
+    pub fn paging_state(&self) -> Option<&Bytes> {
+        self.paging_state.as_ref()
     }
 
     /// If tracing was enabled returns tracing ids of all finished page queries
@@ -201,13 +418,138 @@ impl RowIterator {
         &self.tracing_ids
     }
 
+    /// Returns warnings returned by the database in all finished page queries
+    pub fn get_warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Returns the number of pages fetched in the background ahead of the one being read  
+    /// Set with `SessionBuilder::prefetch_pages` or per statement with `set_prefetch_pages`,
+    /// 0 means that a page is fetched only when it's needed
+    pub fn prefetch_pages(&self) -> usize {
+        self.prefetch_pages
+    }
+
+    /// Returns specifications of the columns of the last received page  
+    /// Empty until the first row is read
+    pub fn col_specs(&self) -> &[ColumnSpec] {
+        self.current_page.rows.metadata().col_specs()
+    }
+
     fn is_current_page_exhausted(&self) -> bool {
-        self.current_row_idx >= self.current_page.rows.len()
+        self.current_page.rows.len() == 0
+    }
+
+    fn poll_receive_page(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<ReceivedPage, QueryError>>> {
+        if self.failed {
+            return Poll::Ready(None);
+        }
+
+        if let Some(page_requests) = &self.page_requests {
+            if !self.page_requested {
+                // Fails only when the worker has finished, then poll_recv will return the end of pages
+                let _ = page_requests.send(());
+                self.page_requested = true;
+            }
+        }
+
+        let received = self.page_receiver.poll_recv(cx);
+
+        if let Poll::Ready(received_opt) = &received {
+            self.page_requested = false;
+            self.failed = matches!(received_opt, Some(Err(_)));
+
+            if let Some(Ok(received_page)) = received_opt {
+                self.paging_state = received_page.rows.metadata().paging_state.clone();
+
+                if let Some(tracing_id) = received_page.tracing_id {
+                    self.tracing_ids.push(tracing_id);
+                }
+                self.warnings.extend_from_slice(&received_page.warnings);
+            }
+        }
+
+        received
+    }
+
+    fn poll_next_received_page(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<ReceivedPage, QueryError>>> {
+        if !self.is_current_page_exhausted() {
+            // Some rows of the current page weren't read yet, return them first
+            return Poll::Ready(Some(Ok(mem::take(&mut self.current_page))));
+        }
+
+        self.poll_receive_page(cx)
+    }
+
+    fn poll_next_page(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Page, QueryError>>> {
+        let page = self
+            .poll_next_received_page(cx)
+            .map(|received| received.map(|page_res| page_res.and_then(ReceivedPage::into_page)));
+
+        if let Poll::Ready(Some(Err(_))) = &page {
+            self.failed = true;
+        }
+        page
+    }
+}
+
+/// Iterator over whole pages returned by paged queries  
+/// Returned by `RowIterator::into_pages`
+pub struct PageIterator {
+    row_iterator: RowIterator,
+}
+
+impl PageIterator {
+    /// If tracing was enabled returns tracing ids of all finished page queries
+    pub fn get_tracing_ids(&self) -> &[Uuid] {
+        self.row_iterator.get_tracing_ids()
+    }
+
+    /// Returns warnings returned by the database in all finished page queries
+    pub fn get_warnings(&self) -> &[String] {
+        self.row_iterator.get_warnings()
+    }
+
+    /// Returns the number of pages fetched in the background, see [`RowIterator::prefetch_pages`]
+    pub fn prefetch_pages(&self) -> usize {
+        self.row_iterator.prefetch_pages()
+    }
+}
+
+/// Fetching pages is asynchronous so `PageIterator` does not implement the `Iterator` trait.  
+/// Instead it uses the asynchronous `Stream` trait
+impl Stream for PageIterator {
+    type Item = Result<Page, QueryError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.row_iterator.poll_next_page(cx)
+    }
+}
+
+// Pages are requested explicitly only when they shouldn't be prefetched
+fn page_requests_channel(
+    prefetch_pages: usize,
+) -> (
+    Option<mpsc::UnboundedSender<()>>,
+    Option<mpsc::UnboundedReceiver<()>>,
+) {
+    if prefetch_pages == 0 {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (Some(sender), Some(receiver))
+    } else {
+        (None, None)
     }
 }
 
 // RowIteratorWorker works in the background to fetch pages
 // RowIterator receives them through a channel
+// The worker task is owned by RowIterator and cancelled when the iterator is dropped
 struct RowIteratorWorker<'a, ConnFunc, QueryFunc> {
     sender: mpsc::Sender<Result<ReceivedPage, QueryError>>,
 
@@ -216,99 +558,194 @@ struct RowIteratorWorker<'a, ConnFunc, QueryFunc> {
     choose_connection: ConnFunc,
 
     // Closure used to perform a single page query
-    // AsyncFn(Arc<Connection>, Option<Bytes>) -> Result<QueryResponse, QueryError>
+    // AsyncFn(Arc<Connection>, Option<Bytes>, Option<Consistency>) -> Result<QueryResponse, QueryError>
     page_query: QueryFunc,
 
     statement_info: Statement<'a>,
+    request: RequestInfo<'a>,
     query_is_idempotent: bool,
     query_consistency: Consistency,
+    // Consistency set by the retry policy for the current page
+    consistency_override: Option<Consistency>,
 
     retry_session: Box<dyn RetrySession>,
     load_balancer: Arc<dyn LoadBalancingPolicy>,
     metrics: Arc<Metrics>,
+    request_listener: Option<Arc<dyn RequestListener>>,
 
     paging_state: Option<Bytes>,
+    // Whether a page was received since the last error
+    page_received: bool,
+
+    // If present, each page is queried only after RowIterator requests it
+    page_requests: Option<mpsc::UnboundedReceiver<()>>,
+    page_request_received: bool,
 }
 
 impl<ConnFunc, ConnFut, QueryFunc, QueryFut> RowIteratorWorker<'_, ConnFunc, QueryFunc>
 where
     ConnFunc: Fn(Arc<Node>) -> ConnFut,
     ConnFut: Future<Output = Result<Arc<Connection>, QueryError>>,
-    QueryFunc: Fn(Arc<Connection>, Option<Bytes>) -> QueryFut,
+    QueryFunc: Fn(Arc<Connection>, Option<Bytes>, Option<Consistency>) -> QueryFut,
     QueryFut: Future<Output = Result<QueryResponse, QueryError>>,
 {
     async fn work(mut self, cluster_data: Arc<ClusterData>) {
-        let query_plan = self.load_balancer.plan(&self.statement_info, &cluster_data);
-
-        let mut last_error: QueryError =
-            QueryError::ProtocolError("Empty query plan - driver bug!");
-
-        'nodes_in_plan: for node in query_plan {
-            // For each node in the plan choose a connection to use
-            // This connection will be reused for same node retries to preserve paging cache on the shard
-            let connection: Arc<Connection> = match (self.choose_connection)(node).await {
-                Ok(connection) => connection,
-                Err(e) => {
-                    last_error = e;
-                    // Broken connection doesn't count as a failed query, don't log in metrics
-                    continue 'nodes_in_plan;
-                }
-            };
+        let mut last_error: QueryError = QueryError::ProtocolError(ProtocolError::EmptyQueryPlan);
 
-            'same_node_retries: loop {
-                // Query pages until an error occurs
-                let queries_result: Result<(), QueryError> = self.query_pages(&connection).await;
+        // Node which failed the last page query, it's skipped when planning the next attempt
+        let mut failed_node: Option<Arc<Node>> = None;
 
-                last_error = match queries_result {
-                    Ok(()) => return,
-                    Err(error) => error,
-                };
+        if let Some(listener) = &self.request_listener {
+            listener.on_request_start(&self.request);
+        }
 
-                // Use retry policy to decide what to do next
-                let query_info = QueryInfo {
-                    error: &last_error,
-                    is_idempotent: self.query_is_idempotent,
-                    consistency: self.query_consistency,
-                };
+        // Each page is planned like a non-paged query. The plan is reused while consecutive
+        // attempts to fetch the same page fail, and computed again once some page succeeds
+        'plans: loop {
+            let query_plan = self.load_balancer.plan(&self.statement_info, &cluster_data);
 
-                match self.retry_session.decide_should_retry(query_info) {
-                    RetryDecision::RetrySameNode => {
-                        self.metrics.inc_retries_num();
-                        continue 'same_node_retries;
+            'nodes_in_plan: for node in query_plan {
+                if let Some(failed) = &failed_node {
+                    if Arc::ptr_eq(failed, &node) {
+                        continue 'nodes_in_plan;
                     }
-                    RetryDecision::RetryNextNode => {
-                        self.metrics.inc_retries_num();
+                }
+
+                // For each node in the plan choose a connection to use
+                // This connection will be reused for same node retries to preserve paging cache on the shard
+                let connection: Arc<Connection> = match (self.choose_connection)(node.clone()).await
+                {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        debug!("No working connection to node {}: {}", node.address, e);
+                        last_error = e;
+                        // Broken connection doesn't count as a failed query, don't log in metrics
                         continue 'nodes_in_plan;
                     }
-                    RetryDecision::DontRetry => break 'nodes_in_plan,
                 };
+
+                let target = self.metrics.target(node.address, &connection);
+
+                'same_node_retries: loop {
+                    // Query pages until an error occurs
+                    let queries_result: Result<(), QueryError> =
+                        self.query_pages(&connection, node.address, &target).await;
+
+                    last_error = match queries_result {
+                        Ok(()) => return,
+                        Err(error) => {
+                            debug!(
+                                "Fetching a page from node {} failed: {}",
+                                node.address, error
+                            );
+                            error
+                        }
+                    };
+
+                    // Use retry policy to decide what to do next
+                    let query_info = QueryInfo {
+                        error: &last_error,
+                        is_idempotent: self.query_is_idempotent,
+                        consistency: self.consistency_override.unwrap_or(self.query_consistency),
+                    };
+
+                    let page_received = mem::take(&mut self.page_received);
+
+                    let decision = self.retry_session.decide_should_retry(query_info);
+                    debug!("Retry policy decision: {:?}", decision);
+                    if let Some(listener) = &self.request_listener {
+                        listener.on_retry_decision(
+                            &self.request,
+                            node.address,
+                            &last_error,
+                            &decision,
+                        );
+                    }
+                    match decision {
+                        RetryDecision::RetrySameNode(delay) => {
+                            self.metrics.inc_retries_num(&target);
+                            sleep_before_retry(delay).await;
+                            continue 'same_node_retries;
+                        }
+                        RetryDecision::RetryNextNode(delay) if page_received => {
+                            // The plan was made before some pages were fetched, plan the failed page anew
+                            self.metrics.inc_retries_num(&target);
+                            sleep_before_retry(delay).await;
+                            failed_node = Some(node);
+                            continue 'plans;
+                        }
+                        RetryDecision::RetryNextNode(delay) => {
+                            self.metrics.inc_retries_num(&target);
+                            sleep_before_retry(delay).await;
+                            continue 'nodes_in_plan;
+                        }
+                        RetryDecision::RetryWithConsistency(consistency) => {
+                            self.metrics.inc_retries_num(&target);
+                            self.consistency_override = Some(consistency);
+                            continue 'same_node_retries;
+                        }
+                        RetryDecision::DontRetry => break 'plans,
+                    };
+                }
             }
+
+            break 'plans;
+        }
+
+        if let Some(listener) = &self.request_listener {
+            listener.on_error(&self.request, &last_error);
         }
 
         // Send last_error to RowIterator - query failed fully
-        let _ = self.sender.send(Err(last_error)).await;
+        if self.sender.send(Err(last_error)).await.is_err() {
+            debug!("RowIterator was dropped before receiving the query error");
+        }
     }
 
     // Given a working connection query as many pages as possible until the first error
-    async fn query_pages(&mut self, connection: &Arc<Connection>) -> Result<(), QueryError> {
+    async fn query_pages(
+        &mut self,
+        connection: &Arc<Connection>,
+        node: SocketAddr,
+        target: &RequestTarget,
+    ) -> Result<(), QueryError> {
         loop {
-            self.metrics.inc_total_paged_queries();
+            if !self.wait_for_page_request().await {
+                // RowIterator was dropped - should shutdown
+                return Ok(());
+            }
+
+            self.metrics.inc_total_paged_queries(target);
             let query_start = std::time::Instant::now();
+            trace!("Fetching a page from node {}", node);
+            if let Some(listener) = &self.request_listener {
+                listener.on_attempt(&self.request, node);
+            }
 
-            let query_response: QueryResponse =
-                (self.page_query)(connection.clone(), self.paging_state.clone()).await?;
+            let query_response: QueryResponse = (self.page_query)(
+                connection.clone(),
+                self.paging_state.clone(),
+                self.consistency_override,
+            )
+            .await?;
 
             match query_response.response {
-                Response::Result(result::Result::Rows(mut rows)) => {
+                Response::Result(result::Result::Rows(rows)) => {
+                    let latency = query_start.elapsed();
                     let _ = self
                         .metrics
-                        .log_query_latency(query_start.elapsed().as_millis() as u64);
+                        .log_query_latency(target, latency.as_millis() as u64);
+                    if let Some(listener) = &self.request_listener {
+                        listener.on_success(&self.request, node, latency);
+                    }
 
-                    self.paging_state = rows.metadata.paging_state.take();
+                    self.paging_state = rows.metadata.paging_state.clone();
 
                     let received_page = ReceivedPage {
-                        rows,
+                        rows: rows.into_iter(),
                         tracing_id: query_response.tracing_id,
+                        warnings: query_response.warnings,
+                        node: query_response.node,
                     };
 
                     // Send next page to RowIterator
@@ -316,6 +753,7 @@ where
                         // channel was closed, RowIterator was dropped - should shutdown
                         return Ok(());
                     }
+                    self.page_request_received = false;
 
                     if self.paging_state.is_none() {
                         // Reached the last query, shutdown
@@ -324,21 +762,38 @@ where
 
                     // Query succeded, reset retry policy for future retries
                     self.retry_session.reset();
+                    self.consistency_override = None;
+                    self.page_received = true;
                 }
                 Response::Error(err) => {
-                    self.metrics.inc_failed_paged_queries();
+                    self.metrics.inc_failed_paged_queries(target);
                     return Err(err.into());
                 }
-                _ => {
-                    self.metrics.inc_failed_paged_queries();
+                response => {
+                    self.metrics.inc_failed_paged_queries(target);
 
-                    return Err(QueryError::ProtocolError(
-                        "Unexpected response to next page query",
-                    ));
+                    return Err(ProtocolError::UnexpectedResponse {
+                        node: query_response.node,
+                        request: "next page query",
+                        response: response.name(),
+                    }
+                    .into());
                 }
             }
         }
     }
+
+    // Waits until RowIterator requests the next page, returns false if it was dropped
+    // A request stays valid until the page is sent, so retries don't wait for another one
+    async fn wait_for_page_request(&mut self) -> bool {
+        match &mut self.page_requests {
+            Some(page_requests) if !self.page_request_received => {
+                self.page_request_received = page_requests.recv().await.is_some();
+                self.page_request_received
+            }
+            _ => true,
+        }
+    }
 }
 
 /// Iterator over rows returned by paged queries
@@ -346,14 +801,71 @@ where
 /// Returned by `RowIterator::into_typed`
 pub struct TypedRowIterator<RowT> {
     row_iterator: RowIterator,
+    row_index: usize,
     phantom_data: std::marker::PhantomData<RowT>,
 }
 
+impl<RowT: FromRow> TypedRowIterator<RowT> {
+    /// Returns the next row parsed as `RowT`, or `None` if there are no more rows  
+    /// Works like `StreamExt::next`, but doesn't require importing the trait
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// let mut rows = session
+    ///     .query_iter("SELECT a, b FROM ks.t", &[])
+    ///     .await?
+    ///     .into_typed::<(i32, i32)>();
+    ///
+    /// while let Some((a, b)) = rows.next().await.transpose()? {
+    ///     println!("a, b: {}, {}", a, b);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn next(&mut self) -> Option<Result<RowT, NextRowError>> {
+        futures::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Reads all remaining rows parsed as `RowT` into a collection, e.g. a `Vec<RowT>`,
+    /// stopping at the first error
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// let rows: Vec<(i32, String)> = session
+    ///     .query_iter("SELECT a, b FROM ks.t", &[])
+    ///     .await?
+    ///     .into_typed::<(i32, String)>()
+    ///     .try_collect()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn try_collect<C: Default + Extend<RowT>>(mut self) -> Result<C, NextRowError> {
+        let mut collection = C::default();
+        while let Some(row) = self.next().await {
+            collection.extend(std::iter::once(row?));
+        }
+        Ok(collection)
+    }
+}
+
 impl<RowT> TypedRowIterator<RowT> {
     /// If tracing was enabled returns tracing ids of all finished page queries
     pub fn get_tracing_ids(&self) -> &[Uuid] {
         self.row_iterator.get_tracing_ids()
     }
+
+    /// Returns warnings returned by the database in all finished page queries
+    pub fn get_warnings(&self) -> &[String] {
+        self.row_iterator.get_warnings()
+    }
+
+    /// Returns the number of pages fetched in the background, see [`RowIterator::prefetch_pages`]
+    pub fn prefetch_pages(&self) -> usize {
+        self.row_iterator.prefetch_pages()
+    }
 }
 
 /// Couldn't get next typed row from the iterator
@@ -383,7 +895,18 @@ impl<RowT: FromRow> Stream for TypedRowIterator<RowT> {
             };
 
         let next_ready: Option<Self::Item> = match next_elem {
-            Some(Ok(next_row)) => Some(RowT::from_row(next_row).map_err(|e| e.into())),
+            Some(Ok(next_row)) => {
+                let row_index = s.row_index;
+                s.row_index += 1;
+
+                Some(RowT::from_row(next_row).map_err(|e| {
+                    // The row comes from the current page, its metadata describes the columns
+                    let col_specs = s.row_iterator.current_page.rows.metadata().col_specs();
+                    e.with_column_specs(col_specs)
+                        .with_row_index(row_index)
+                        .into()
+                }))
+            }
             Some(Err(e)) => Some(Err(e.into())),
             None => None,
         };
@@ -394,3 +917,71 @@ impl<RowT: FromRow> Stream for TypedRowIterator<RowT> {
 
 // TypedRowIterator can be moved freely for any RowT so it's Unpin
 impl<RowT> Unpin for TypedRowIterator<RowT> {}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn next_and_try_collect() {
+        use super::NextRowError;
+        use crate::frame::response::result::{ColumnType, CqlValue, Row};
+        use crate::proxy::{MockNode, MockResponse, MockRule};
+        use crate::{IntoTypedRows, SessionBuilder};
+
+        let rows = (1..=3).map(|a| vec![Some(CqlValue::Int(a))]).collect();
+        let node = MockNode::new()
+            .rule(MockRule::new(
+                "SELECT a FROM ks.t",
+                MockResponse::rows(&[("a", ColumnType::Int)], rows),
+            ))
+            .default_response(MockResponse::Void)
+            .run()
+            .await
+            .unwrap();
+        let session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .build()
+            .await
+            .unwrap();
+        let select = "SELECT a FROM ks.t";
+
+        let mut typed = session
+            .query_iter(select, &[])
+            .await
+            .unwrap()
+            .into_typed::<(i32,)>();
+        for expected in 1..=3 {
+            assert_eq!(typed.next().await.unwrap().unwrap(), (expected,));
+        }
+        assert!(typed.next().await.is_none());
+
+        let collected: Vec<(i32,)> = session
+            .query_iter(select, &[])
+            .await
+            .unwrap()
+            .into_typed::<(i32,)>()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(collected, vec![(1,), (2,), (3,)]);
+
+        let mut untyped = session.query_iter(select, &[]).await.unwrap();
+        assert_eq!(
+            untyped.next().await.unwrap().unwrap().columns,
+            vec![Some(CqlValue::Int(1))]
+        );
+        let remaining: Vec<Row> = untyped.try_collect().await.unwrap();
+        assert_eq!(remaining.into_typed::<(i32,)>().count(), 2);
+
+        let bad_type = session
+            .query_iter(select, &[])
+            .await
+            .unwrap()
+            .into_typed::<(String,)>()
+            .try_collect::<Vec<_>>()
+            .await;
+        assert!(matches!(bad_type, Err(NextRowError::FromRowError(_))));
+
+        node.finish().await.unwrap();
+    }
+}
@@ -7,9 +7,12 @@ use std::sync::{
 };
 
 /// A data-center aware Round-robin load balancing policy.
+/// Nodes in the local datacenter are tried first, nodes in other datacenters afterwards,
+/// unless [`include_remote_nodes`](DcAwareRoundRobinPolicy::include_remote_nodes) is disabled.
 pub struct DcAwareRoundRobinPolicy {
     index: AtomicUsize,
     local_dc: String,
+    include_remote_nodes: bool,
 }
 
 impl DcAwareRoundRobinPolicy {
@@ -17,9 +20,25 @@ impl DcAwareRoundRobinPolicy {
         Self {
             index: AtomicUsize::new(0),
             local_dc,
+            include_remote_nodes: true,
         }
     }
 
+    /// Whether nodes in other datacenters are used when no local node is available, `true` by default.
+    /// When disabled, queries fail if all nodes in the local datacenter are down
+    /// instead of being sent to a remote datacenter.
+    ///
+    /// # Example
+    /// ```
+    /// use scylla::transport::load_balancing::DcAwareRoundRobinPolicy;
+    ///
+    /// let policy = DcAwareRoundRobinPolicy::new("us_east".to_string()).include_remote_nodes(false);
+    /// ```
+    pub fn include_remote_nodes(mut self, include_remote_nodes: bool) -> Self {
+        self.include_remote_nodes = include_remote_nodes;
+        self
+    }
+
     fn is_local_node(node: &Node, local_dc: &str) -> bool {
         node.datacenter.as_deref() == Some(local_dc)
     }
@@ -38,12 +57,11 @@ impl DcAwareRoundRobinPolicy {
     ) -> impl Iterator<Item = Arc<Node>> + Clone + 'a {
         // local_dc is moved into filter closure so clone is needed
         let local_dc = self.local_dc.clone();
+        let include_remote_nodes = self.include_remote_nodes;
 
-        cluster
-            .all_nodes
-            .iter()
-            .cloned()
-            .filter(move |node| !DcAwareRoundRobinPolicy::is_local_node(node, &local_dc))
+        cluster.all_nodes.iter().cloned().filter(move |node| {
+            include_remote_nodes && !DcAwareRoundRobinPolicy::is_local_node(node, &local_dc)
+        })
     }
 }
 
@@ -64,12 +82,15 @@ impl LoadBalancingPolicy for DcAwareRoundRobinPolicy {
             super::slice_rotated_left(local_nodes, local_nodes_rotation).cloned();
 
         let remote_nodes = self.retrieve_remote_nodes(cluster);
-        let remote_nodes_count = cluster.all_nodes.len() - local_nodes.len();
+        let remote_nodes_count = match self.include_remote_nodes {
+            true => cluster.all_nodes.len() - local_nodes.len(),
+            false => 0,
+        };
         let remote_nodes_rotation = super::compute_rotation(index, remote_nodes_count);
         let rotated_remote_nodes = super::iter_rotated_left(remote_nodes, remote_nodes_rotation);
 
         let plan = rotated_local_nodes.chain(rotated_remote_nodes);
-        Box::new(plan)
+        Box::new(super::up_nodes_first(plan))
     }
 
     fn name(&self) -> String {
@@ -84,22 +105,36 @@ impl ChildLoadBalancingPolicy for DcAwareRoundRobinPolicy {
     ) -> Box<dyn Iterator<Item = Arc<Node>> + Send + Sync> {
         let index = self.index.fetch_add(1, ORDER_TYPE);
 
-        let (local_nodes, remote_nodes): (Vec<_>, Vec<_>) = plan
+        let (mut local_nodes, mut remote_nodes): (Vec<_>, Vec<_>) = plan
             .into_iter()
             .partition(|node| DcAwareRoundRobinPolicy::is_local_node(node, &self.local_dc));
+        if !self.include_remote_nodes {
+            remote_nodes.clear();
+        }
 
         let local_nodes_rotation = super::compute_rotation(index, local_nodes.len());
-        let rotated_local_nodes = super::slice_rotated_left(&local_nodes, local_nodes_rotation);
+        local_nodes.rotate_left(local_nodes_rotation);
 
         let remote_nodes_rotation = super::compute_rotation(index, remote_nodes.len());
-        let rotated_remote_nodes = super::slice_rotated_left(&remote_nodes, remote_nodes_rotation);
-
-        let plan = rotated_local_nodes
-            .chain(rotated_remote_nodes)
-            .cloned()
-            .collect::<Vec<_>>()
-            .into_iter();
-        Box::new(plan)
+        remote_nodes.rotate_left(remote_nodes_rotation);
+
+        let plan = local_nodes.into_iter().chain(remote_nodes);
+        Box::new(super::up_nodes_first(plan))
+    }
+
+    fn apply_child_policy_in_order(
+        &self,
+        plan: Vec<Arc<Node>>,
+    ) -> Box<dyn Iterator<Item = Arc<Node>> + Send + Sync> {
+        let (local_nodes, mut remote_nodes): (Vec<_>, Vec<_>) = plan
+            .into_iter()
+            .partition(|node| DcAwareRoundRobinPolicy::is_local_node(node, &self.local_dc));
+        if !self.include_remote_nodes {
+            remote_nodes.clear();
+        }
+
+        let plan = local_nodes.into_iter().chain(remote_nodes);
+        Box::new(super::up_nodes_first(plan))
     }
 }
 
@@ -135,4 +170,40 @@ mod tests {
 
         assert_eq!(plans, expected_plans);
     }
+
+    #[tokio::test]
+    async fn test_dc_aware_round_robin_policy_without_remote_nodes() {
+        let cluster = tests::mock_cluster_data_for_round_robin_tests();
+
+        let policy = DcAwareRoundRobinPolicy::new("eu".to_string()).include_remote_nodes(false);
+
+        let plans = (0..4)
+            .map(|_| {
+                tests::get_plan_and_collect_node_identifiers(
+                    &policy,
+                    &tests::EMPTY_STATEMENT,
+                    &cluster,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let expected_plans = vec![vec![1, 2, 3], vec![2, 3, 1], vec![3, 1, 2], vec![1, 2, 3]];
+
+        assert_eq!(plans, expected_plans);
+
+        let child_plan: Vec<u16> = policy
+            .apply_child_policy(cluster.all_nodes.clone())
+            .map(|node| node.address.port())
+            .collect();
+        assert_eq!(child_plan, vec![2, 3, 1]);
+
+        // Unknown local datacenter gives an empty plan
+        let policy = DcAwareRoundRobinPolicy::new("asia".to_string()).include_remote_nodes(false);
+        assert!(tests::get_plan_and_collect_node_identifiers(
+            &policy,
+            &tests::EMPTY_STATEMENT,
+            &cluster
+        )
+        .is_empty());
+    }
 }
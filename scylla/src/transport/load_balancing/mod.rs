@@ -6,6 +6,7 @@
 use super::{cluster::ClusterData, node::Node};
 use crate::routing::Token;
 
+use std::collections::VecDeque;
 use std::sync::Arc;
 
 mod dc_aware_round_robin;
@@ -20,6 +21,14 @@ pub use token_aware::TokenAwarePolicy;
 pub struct Statement<'a> {
     pub token: Option<Token>,
     pub keyspace: Option<&'a str>,
+    pub(crate) is_confirmed_lwt: bool,
+}
+
+impl Statement<'_> {
+    /// Statement is a lightweight transaction, see [`PreparedStatement::is_confirmed_lwt`](crate::prepared_statement::PreparedStatement::is_confirmed_lwt)
+    pub fn is_confirmed_lwt(&self) -> bool {
+        self.is_confirmed_lwt
+    }
 }
 
 /// Policy that decides which nodes to contact for each query
@@ -43,6 +52,17 @@ pub trait ChildLoadBalancingPolicy: LoadBalancingPolicy {
         &self,
         plan: Vec<Arc<Node>>,
     ) -> Box<dyn Iterator<Item = Arc<Node>> + Send + Sync>;
+
+    /// Like [`apply_child_policy`](ChildLoadBalancingPolicy::apply_child_policy), but keeps
+    /// the relative order of nodes in the plan, only filtering them or moving some of them to the end.  
+    /// Used for lightweight transactions, which are sent to replicas in ring order.
+    /// By default only nodes which are down are moved to the end.
+    fn apply_child_policy_in_order(
+        &self,
+        plan: Vec<Arc<Node>>,
+    ) -> Box<dyn Iterator<Item = Arc<Node>> + Send + Sync> {
+        Box::new(up_nodes_first(plan.into_iter()))
+    }
 }
 
 // Does safe modulo
@@ -71,11 +91,49 @@ fn slice_rotated_left<'a, T>(slice: &'a [T], mid: usize) -> impl Iterator<Item =
     begin.iter().chain(end.iter())
 }
 
+// Moves nodes which are not up to the end of the plan, keeping the relative order.
+// Nodes ignored because of the host filter are removed from the plan.
+// The plan is consumed lazily, only nodes which are not up are buffered.
+fn up_nodes_first<I: Iterator<Item = Arc<Node>>>(plan: I) -> UpNodesFirst<I> {
+    UpNodesFirst {
+        plan: Some(plan),
+        down_nodes: VecDeque::new(),
+    }
+}
+
+struct UpNodesFirst<I> {
+    // None after all up nodes were returned
+    plan: Option<I>,
+    down_nodes: VecDeque<Arc<Node>>,
+}
+
+impl<I: Iterator<Item = Arc<Node>>> Iterator for UpNodesFirst<I> {
+    type Item = Arc<Node>;
+
+    fn next(&mut self) -> Option<Arc<Node>> {
+        if let Some(plan) = &mut self.plan {
+            for node in plan {
+                if node.is_ignored() {
+                    continue;
+                }
+                if node.is_up() {
+                    return Some(node);
+                }
+                self.down_nodes.push_back(node);
+            }
+            self.plan = None;
+        }
+
+        self.down_nodes.pop_front()
+    }
+}
+
 impl Default for Statement<'_> {
     fn default() -> Self {
         Statement {
             token: None,
             keyspace: None,
+            is_confirmed_lwt: false,
         }
     }
 }
@@ -117,6 +175,31 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_up_nodes_first_is_lazy() {
+        let cluster = mock_cluster_data_for_round_robin_tests();
+        cluster.all_nodes[1].change_down_marker(true);
+
+        let consumed = std::cell::Cell::new(0);
+        let mut plan = up_nodes_first(
+            cluster
+                .all_nodes
+                .iter()
+                .cloned()
+                .inspect(|_| consumed.set(consumed.get() + 1)),
+        );
+
+        assert_eq!(plan.next().unwrap().address.port(), 1);
+        assert_eq!(consumed.get(), 1);
+
+        // The down node is skipped and returned at the end
+        assert_eq!(plan.next().unwrap().address.port(), 3);
+        assert_eq!(consumed.get(), 3);
+
+        let rest: Vec<u16> = plan.map(|node| node.address.port()).collect();
+        assert_eq!(rest, vec![4, 5, 2]);
+    }
+
     pub fn id_to_invalid_addr(id: u16) -> SocketAddr {
         SocketAddr::from(([255, 255, 255, 255], id))
     }
@@ -129,6 +212,7 @@ mod tests {
             .map(|(dc, id)| Peer {
                 datacenter: Some(dc.to_string()),
                 rack: None,
+                host_id: None,
                 address: tests::id_to_invalid_addr(*id),
                 tokens: Vec::new(),
             })
@@ -137,14 +221,39 @@ mod tests {
         let info = TopologyInfo {
             peers,
             keyspaces: HashMap::new(),
+            cluster_info: Default::default(),
         };
 
-        ClusterData::new(info, &Default::default(), &HashMap::new(), &None)
+        cluster_data_without_connections(info)
+    }
+
+    // Nodes of the returned ClusterData never open connections,
+    // so their health depends only on the down marker
+    pub fn cluster_data_without_connections(info: TopologyInfo) -> ClusterData {
+        let nodes = info
+            .peers
+            .into_iter()
+            .map(|peer| {
+                let node = Node::new(
+                    peer.address,
+                    Default::default(),
+                    peer.datacenter,
+                    peer.rack,
+                    peer.host_id,
+                    None,
+                    false,
+                );
+                (Arc::new(node), peer.tokens)
+            })
+            .collect();
+
+        ClusterData::from_nodes(nodes, info.keyspaces, info.cluster_info)
     }
 
     pub const EMPTY_STATEMENT: Statement = Statement {
         token: None,
         keyspace: None,
+        is_confirmed_lwt: false,
     };
 
     pub fn get_plan_and_collect_node_identifiers<L: LoadBalancingPolicy>(
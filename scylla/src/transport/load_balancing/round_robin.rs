@@ -39,7 +39,7 @@ impl LoadBalancingPolicy for RoundRobinPolicy {
         let rotation = super::compute_rotation(index, nodes_count);
         let rotated_nodes = super::slice_rotated_left(&cluster.all_nodes, rotation).cloned();
 
-        Box::new(rotated_nodes)
+        Box::new(super::up_nodes_first(rotated_nodes))
     }
 
     fn name(&self) -> String {
@@ -57,7 +57,7 @@ impl ChildLoadBalancingPolicy for RoundRobinPolicy {
         let len = plan.len(); // borrow checker forces making such a variable
 
         plan.rotate_left(super::compute_rotation(index, len));
-        Box::new(plan.into_iter())
+        Box::new(super::up_nodes_first(plan.into_iter()))
     }
 }
 
@@ -95,4 +95,30 @@ mod tests {
 
         assert_eq!(plans, expected_plans);
     }
+
+    #[tokio::test]
+    async fn test_round_robin_policy_down_nodes_last() {
+        let cluster = tests::mock_cluster_data_for_round_robin_tests();
+        cluster.all_nodes[1].change_down_marker(true);
+
+        let policy = RoundRobinPolicy::new();
+
+        let plans = (0..3)
+            .map(|_| {
+                tests::get_plan_and_collect_node_identifiers(
+                    &policy,
+                    &tests::EMPTY_STATEMENT,
+                    &cluster,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let expected_plans = vec![
+            vec![1, 3, 4, 5, 2],
+            vec![3, 4, 5, 1, 2],
+            vec![3, 4, 5, 1, 2],
+        ];
+
+        assert_eq!(plans, expected_plans);
+    }
 }
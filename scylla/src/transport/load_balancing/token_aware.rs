@@ -1,10 +1,7 @@
 use super::{ChildLoadBalancingPolicy, LoadBalancingPolicy, Statement};
-use crate::routing::Token;
-use crate::transport::topology::Strategy;
 use crate::transport::{cluster::ClusterData, node::Node};
 
-use itertools::Itertools;
-use std::{collections::HashMap, sync::Arc};
+use std::sync::Arc;
 
 /// A wrapper load balancing policy that adds token awareness to a child policy.
 pub struct TokenAwarePolicy {
@@ -15,91 +12,6 @@ impl TokenAwarePolicy {
     pub fn new(child_policy: Box<dyn ChildLoadBalancingPolicy>) -> Self {
         Self { child_policy }
     }
-
-    fn simple_strategy_replicas(
-        cluster: &ClusterData,
-        token: &Token,
-        replication_factor: usize,
-    ) -> Vec<Arc<Node>> {
-        cluster
-            .ring_range(&token)
-            .unique()
-            .take(replication_factor)
-            .collect()
-    }
-
-    fn network_topology_strategy_replicas(
-        cluster: &ClusterData,
-        token: &Token,
-        datacenter_repfactors: &HashMap<String, usize>,
-    ) -> Vec<Arc<Node>> {
-        let mut acceptable_repeats = datacenter_repfactors
-            .iter()
-            .map(|(dc_name, repfactor)| {
-                let rack_count = cluster
-                    .datacenters
-                    .get(dc_name)
-                    .map(|dc| dc.rack_count)
-                    .unwrap_or(0);
-
-                (dc_name.as_str(), repfactor.saturating_sub(rack_count))
-            })
-            .collect::<HashMap<&str, usize>>();
-
-        let desired_result_len: usize = datacenter_repfactors.values().sum();
-
-        let mut result: Vec<Arc<Node>> = Vec::with_capacity(desired_result_len);
-        for node in cluster.ring_range(&token).unique() {
-            let current_node_dc = match &node.datacenter {
-                None => continue,
-                Some(dc) => dc,
-            };
-
-            let repfactor = match datacenter_repfactors.get(current_node_dc) {
-                None => continue,
-                Some(r) => r,
-            };
-
-            let picked_nodes_from_current_dc = || {
-                result
-                    .iter()
-                    .filter(|node| node.datacenter.as_ref() == Some(current_node_dc))
-            };
-
-            if *repfactor == picked_nodes_from_current_dc().count() {
-                // found enough nodes in this datacenter
-                continue;
-            }
-
-            let current_node_rack = node.rack.as_ref();
-            let current_node_rack_count = picked_nodes_from_current_dc()
-                .filter(|node| node.rack.as_ref() == current_node_rack)
-                .count();
-
-            if current_node_rack_count == 0 {
-                // new rack
-                result.push(node.clone());
-            } else {
-                // we’ve already found a node in this rack
-
-                // unwrap, because we already know repfactor
-                let repeats = acceptable_repeats
-                    .get_mut(current_node_dc.as_str())
-                    .unwrap();
-                if *repeats > 0 {
-                    // we must pick multiple nodes in the same rack
-                    *repeats -= 1;
-                    result.push(node.clone());
-                }
-            }
-
-            if result.len() == desired_result_len {
-                break;
-            }
-        }
-
-        result
-    }
 }
 
 impl LoadBalancingPolicy for TokenAwarePolicy {
@@ -108,31 +20,19 @@ impl LoadBalancingPolicy for TokenAwarePolicy {
         statement: &Statement,
         cluster: &'a ClusterData,
     ) -> Box<dyn Iterator<Item = Arc<Node>> + Send + Sync + 'a> {
-        match statement.token {
+        match &statement.token {
             Some(token) => {
-                let keyspace = statement.keyspace.and_then(|k| cluster.keyspaces.get(k));
-
-                let strategy = keyspace.map(|k| &k.strategy);
-
-                let replicas = match strategy {
-                    Some(Strategy::SimpleStrategy { replication_factor }) => {
-                        Self::simple_strategy_replicas(cluster, &token, *replication_factor)
-                    }
-                    Some(Strategy::NetworkTopologyStrategy {
-                        datacenter_repfactors,
-                    }) => Self::network_topology_strategy_replicas(
-                        cluster,
-                        &token,
-                        datacenter_repfactors,
-                    ),
-                    _ => {
-                        // default to simple strategy with replication factor = 1
-                        let replication_factor = 1;
-                        Self::simple_strategy_replicas(cluster, &token, replication_factor)
-                    }
-                };
-
-                self.child_policy.apply_child_policy(replicas)
+                // Replicas are precomputed using the keyspace's replication strategy,
+                // with an unknown strategy only the owner of the token is used
+                let replicas = cluster.replicas(statement.keyspace, token).to_vec();
+
+                if statement.is_confirmed_lwt() {
+                    // All clients try the primary replica first, so that concurrent
+                    // transactions on a partition don't contend on different coordinators
+                    self.child_policy.apply_child_policy_in_order(replicas)
+                } else {
+                    self.child_policy.apply_child_policy(replicas)
+                }
             }
             // fallback to child policy
             None => self.child_policy.plan(statement, cluster),
@@ -151,6 +51,7 @@ impl LoadBalancingPolicy for TokenAwarePolicy {
 mod tests {
     use super::*;
 
+    use crate::routing::Token;
     use crate::transport::load_balancing::tests;
     use crate::transport::topology::Keyspace;
     use crate::transport::topology::Peer;
@@ -171,36 +72,41 @@ mod tests {
         let tests = [
             Test {
                 statement: Statement {
-                    token: Some(Token { value: 160 }),
+                    token: Some(Token::Murmur3(160)),
                     keyspace: Some("keyspace_with_simple_strategy_replication_factor_2"),
+                    ..Default::default()
                 },
                 expected_plan: vec![3, 1],
             },
             Test {
                 statement: Statement {
-                    token: Some(Token { value: 60 }),
+                    token: Some(Token::Murmur3(60)),
                     keyspace: Some("keyspace_with_simple_strategy_replication_factor_3"),
+                    ..Default::default()
                 },
                 expected_plan: vec![1, 2, 3],
             },
             Test {
                 statement: Statement {
-                    token: Some(Token { value: 500 }),
+                    token: Some(Token::Murmur3(500)),
                     keyspace: Some("keyspace_with_simple_strategy_replication_factor_3"),
+                    ..Default::default()
                 },
                 expected_plan: vec![1, 2, 3],
             },
             Test {
                 statement: Statement {
-                    token: Some(Token { value: 60 }),
+                    token: Some(Token::Murmur3(60)),
                     keyspace: Some("invalid"),
+                    ..Default::default()
                 },
                 expected_plan: vec![1],
             },
             Test {
                 statement: Statement {
-                    token: Some(Token { value: 60 }),
+                    token: Some(Token::Murmur3(60)),
                     keyspace: None,
+                    ..Default::default()
                 },
                 expected_plan: vec![1],
             },
@@ -222,8 +128,9 @@ mod tests {
         let policy = TokenAwarePolicy::new(Box::new(DumbPolicy {}));
 
         let statement = Statement {
-            token: Some(Token { value: 0 }),
+            token: Some(Token::Murmur3(0)),
             keyspace: Some("keyspace_with_nts"),
+            ..Default::default()
         };
 
         let plan = tests::get_plan_and_collect_node_identifiers(&policy, &statement, &cluster);
@@ -231,6 +138,38 @@ mod tests {
         assert_eq!(plan, expected_plan);
     }
 
+    #[tokio::test]
+    async fn test_token_aware_policy_with_lwt() {
+        use crate::transport::load_balancing::{DcAwareRoundRobinPolicy, RoundRobinPolicy};
+
+        let cluster = mock_cluster_data_for_token_aware_tests();
+        let statement = Statement {
+            token: Some(Token::Murmur3(160)),
+            keyspace: Some("keyspace_with_simple_strategy_replication_factor_3"),
+            is_confirmed_lwt: true,
+        };
+
+        // Replicas are always tried in ring order, starting from the primary one
+        let policy = TokenAwarePolicy::new(Box::new(RoundRobinPolicy::new()));
+        for _ in 0..3 {
+            let plan = tests::get_plan_and_collect_node_identifiers(&policy, &statement, &cluster);
+            assert_eq!(plan, vec![3, 1, 2]);
+        }
+
+        // Local replicas go first, keeping the ring order in each datacenter
+        let cluster = mock_cluster_data_for_nts_token_aware_tests();
+        let statement = Statement {
+            token: Some(Token::Murmur3(0)),
+            keyspace: Some("keyspace_with_nts"),
+            is_confirmed_lwt: true,
+        };
+        let policy = TokenAwarePolicy::new(Box::new(DcAwareRoundRobinPolicy::new("her".into())));
+        for _ in 0..3 {
+            let plan = tests::get_plan_and_collect_node_identifiers(&policy, &statement, &cluster);
+            assert_eq!(plan, vec![5, 6, 8, 1, 4]);
+        }
+    }
+
     #[tokio::test]
     async fn test_token_aware_fallback_policy() {
         let cluster = mock_cluster_data_for_token_aware_tests();
@@ -255,28 +194,27 @@ mod tests {
             Peer {
                 datacenter: Some("eu".into()),
                 rack: None,
+                host_id: None,
                 address: tests::id_to_invalid_addr(1),
                 tokens: vec![
-                    Token { value: 100 },
-                    Token { value: 250 },
-                    Token { value: 500 },
+                    Token::Murmur3(100),
+                    Token::Murmur3(250),
+                    Token::Murmur3(500),
                 ],
             },
             Peer {
                 datacenter: Some("eu".into()),
                 rack: None,
+                host_id: None,
                 address: tests::id_to_invalid_addr(2),
-                tokens: vec![
-                    Token { value: 50 },
-                    Token { value: 150 },
-                    Token { value: 300 },
-                ],
+                tokens: vec![Token::Murmur3(50), Token::Murmur3(150), Token::Murmur3(300)],
             },
             Peer {
                 datacenter: Some("us".into()),
                 rack: None,
+                host_id: None,
                 address: tests::id_to_invalid_addr(3),
-                tokens: vec![Token { value: 200 }, Token { value: 400 }],
+                tokens: vec![Token::Murmur3(200), Token::Murmur3(400)],
             },
         ];
 
@@ -287,6 +225,9 @@ mod tests {
                     strategy: Strategy::SimpleStrategy {
                         replication_factor: 2,
                     },
+                    tables: HashMap::new(),
+                    user_defined_types: HashMap::new(),
+                    durable_writes: true,
                 },
             ),
             (
@@ -295,6 +236,9 @@ mod tests {
                     strategy: Strategy::SimpleStrategy {
                         replication_factor: 3,
                     },
+                    tables: HashMap::new(),
+                    user_defined_types: HashMap::new(),
+                    durable_writes: true,
                 },
             ),
         ]
@@ -305,9 +249,10 @@ mod tests {
         let info = TopologyInfo {
             peers: Vec::from(peers),
             keyspaces,
+            cluster_info: Default::default(),
         };
 
-        ClusterData::new(info, &Default::default(), &HashMap::new(), &None)
+        tests::cluster_data_without_connections(info)
     }
 
     // creates ClusterData with info about 8 nodes living in two different datacenters
@@ -328,50 +273,58 @@ mod tests {
             Peer {
                 datacenter: Some("waw".into()),
                 rack: Some("r1".into()),
+                host_id: None,
                 address: tests::id_to_invalid_addr(1),
-                tokens: vec![Token { value: 50 }, Token { value: 200 }],
+                tokens: vec![Token::Murmur3(50), Token::Murmur3(200)],
             },
             Peer {
                 datacenter: Some("waw".into()),
                 rack: Some("r1".into()),
+                host_id: None,
                 address: tests::id_to_invalid_addr(2),
-                tokens: vec![Token { value: 150 }],
+                tokens: vec![Token::Murmur3(150)],
             },
             Peer {
                 datacenter: Some("waw".into()),
                 rack: Some("r2".into()),
+                host_id: None,
                 address: tests::id_to_invalid_addr(3),
-                tokens: vec![Token { value: 510 }],
+                tokens: vec![Token::Murmur3(510)],
             },
             Peer {
                 datacenter: Some("waw".into()),
                 rack: Some("r2".into()),
+                host_id: None,
                 address: tests::id_to_invalid_addr(4),
-                tokens: vec![Token { value: 300 }],
+                tokens: vec![Token::Murmur3(300)],
             },
             Peer {
                 datacenter: Some("her".into()),
                 rack: Some("r3".into()),
+                host_id: None,
                 address: tests::id_to_invalid_addr(5),
-                tokens: vec![Token { value: 100 }],
+                tokens: vec![Token::Murmur3(100)],
             },
             Peer {
                 datacenter: Some("her".into()),
                 rack: Some("r3".into()),
+                host_id: None,
                 address: tests::id_to_invalid_addr(6),
-                tokens: vec![Token { value: 250 }],
+                tokens: vec![Token::Murmur3(250)],
             },
             Peer {
                 datacenter: Some("her".into()),
                 rack: Some("r4".into()),
+                host_id: None,
                 address: tests::id_to_invalid_addr(7),
-                tokens: vec![Token { value: 500 }],
+                tokens: vec![Token::Murmur3(500)],
             },
             Peer {
                 datacenter: Some("her".into()),
                 rack: Some("r4".into()),
+                host_id: None,
                 address: tests::id_to_invalid_addr(8),
-                tokens: vec![Token { value: 400 }],
+                tokens: vec![Token::Murmur3(400)],
             },
         ];
 
@@ -384,6 +337,9 @@ mod tests {
                         .cloned()
                         .collect::<HashMap<_, _>>(),
                 },
+                tables: HashMap::new(),
+                user_defined_types: HashMap::new(),
+                durable_writes: true,
             },
         )]
         .iter()
@@ -393,9 +349,10 @@ mod tests {
         let info = TopologyInfo {
             peers: Vec::from(peers),
             keyspaces,
+            cluster_info: Default::default(),
         };
 
-        ClusterData::new(info, &Default::default(), &HashMap::new(), &None)
+        tests::cluster_data_without_connections(info)
     }
 
     // Used as child policy for TokenAwarePolicy tests
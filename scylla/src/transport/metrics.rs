@@ -1,6 +1,13 @@
+use crate::routing::Shard;
+use crate::transport::connection::Connection;
+use arc_swap::ArcSwap;
 use histogram::Histogram;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::SocketAddr;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
 
 const ORDER_TYPE: Ordering = Ordering::Relaxed;
 
@@ -28,14 +35,272 @@ impl std::fmt::Display for MetricsError<'_> {
     }
 }
 
-#[derive(Default, Debug)]
+/// Values of all counters at some moment, see [`Metrics::snapshot`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    /// When the snapshot was taken
+    pub taken_at: Instant,
+    pub errors_num: u64,
+    pub queries_num: u64,
+    pub errors_iter_num: u64,
+    pub queries_iter_num: u64,
+    pub retries_num: u64,
+    pub oversized_batches_num: u64,
+}
+
+impl MetricsSnapshot {
+    fn zero(taken_at: Instant) -> Self {
+        MetricsSnapshot {
+            taken_at,
+            errors_num: 0,
+            queries_num: 0,
+            errors_iter_num: 0,
+            queries_iter_num: 0,
+            retries_num: 0,
+            oversized_batches_num: 0,
+        }
+    }
+
+    /// Computes how the counters changed between `earlier` and this snapshot
+    pub fn delta_since(&self, earlier: &MetricsSnapshot) -> MetricsDelta {
+        MetricsDelta {
+            interval: self.taken_at.saturating_duration_since(earlier.taken_at),
+            errors_num: self.errors_num.saturating_sub(earlier.errors_num),
+            queries_num: self.queries_num.saturating_sub(earlier.queries_num),
+            errors_iter_num: self.errors_iter_num.saturating_sub(earlier.errors_iter_num),
+            queries_iter_num: self
+                .queries_iter_num
+                .saturating_sub(earlier.queries_iter_num),
+            retries_num: self.retries_num.saturating_sub(earlier.retries_num),
+            oversized_batches_num: self
+                .oversized_batches_num
+                .saturating_sub(earlier.oversized_batches_num),
+        }
+    }
+}
+
+/// Changes of counters over an interval, see [`Metrics::take_interval`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricsDelta {
+    /// Length of the interval
+    pub interval: Duration,
+    pub errors_num: u64,
+    pub queries_num: u64,
+    pub errors_iter_num: u64,
+    pub queries_iter_num: u64,
+    pub retries_num: u64,
+    pub oversized_batches_num: u64,
+}
+
+impl MetricsDelta {
+    /// Nonpaged queries and pages of paged queries per second
+    pub fn queries_per_sec(&self) -> f64 {
+        self.per_sec(self.queries_num + self.queries_iter_num)
+    }
+
+    /// Errors in nonpaged and paged queries per second
+    pub fn errors_per_sec(&self) -> f64 {
+        self.per_sec(self.errors_num + self.errors_iter_num)
+    }
+
+    /// Retries per second
+    pub fn retries_per_sec(&self) -> f64 {
+        self.per_sec(self.retries_num)
+    }
+
+    fn per_sec(&self, count: u64) -> f64 {
+        match self.interval.as_secs_f64() {
+            secs if secs > 0.0 => count as f64 / secs,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Metrics of the node and shard which a request was sent to, see [`Metrics::target`].
+/// They're looked up once for each attempt, not for every recorded value
+#[derive(Debug, Clone)]
+pub(crate) struct RequestTarget {
+    node: Arc<NodeMetrics>,
+    shard: Option<Arc<NodeMetrics>>,
+}
+
+impl RequestTarget {
+    // Updates counters of the node and its shard
+    fn record(&self, record: impl Fn(&NodeMetrics)) {
+        record(&self.node);
+        if let Some(shard) = &self.shard {
+            record(shard);
+        }
+    }
+
+    // Latencies of shard-aware requests are recorded only for the shard,
+    // node latencies merge latencies of its shards when they're read
+    fn log_query_latency(&self, latency: u64) {
+        self.shard
+            .as_ref()
+            .unwrap_or(&self.node)
+            .log_query_latency(latency);
+    }
+}
+
+/// Counters and latencies of requests sent to a single node, see [`MetricsView::per_node`].  
+/// For shard-aware nodes they are also bucketed by shard, see [`NodeMetrics::per_shard`].
+#[derive(Debug)]
+pub struct NodeMetrics {
+    errors_num: AtomicU64,
+    queries_num: AtomicU64,
+    errors_iter_num: AtomicU64,
+    queries_iter_num: AtomicU64,
+    retries_num: AtomicU64,
+    // Latencies of requests which weren't sent to a known shard
+    histogram: Mutex<Histogram>,
+    shards: ArcSwap<HashMap<Shard, Arc<NodeMetrics>>>,
+}
+
+impl NodeMetrics {
+    fn new() -> Self {
+        Self {
+            errors_num: AtomicU64::new(0),
+            queries_num: AtomicU64::new(0),
+            errors_iter_num: AtomicU64::new(0),
+            queries_iter_num: AtomicU64::new(0),
+            retries_num: AtomicU64::new(0),
+            histogram: Mutex::new(Histogram::new()),
+            shards: ArcSwap::from_pointee(HashMap::new()),
+        }
+    }
+
+    fn log_query_latency(&self, latency: u64) {
+        let _ = self.histogram.lock().unwrap().increment(latency);
+    }
+
+    // Latencies of the node merged with latencies of its shards
+    fn merged_histogram(&self) -> Histogram {
+        let mut merged = Histogram::new();
+        merged.merge(&self.histogram.lock().unwrap());
+        for shard in self.shards.load().values() {
+            merged.merge(&shard.histogram.lock().unwrap());
+        }
+        merged
+    }
+
+    /// Returns average latency in milliseconds
+    pub fn get_latency_avg_ms(&self) -> Result<u64, MetricsError<'_>> {
+        Ok(self.merged_histogram().mean()?)
+    }
+
+    /// Returns latency from histogram for a given percentile
+    /// # Arguments
+    ///
+    /// * `percentile` - float value (0.0 - 100.0)
+    pub fn get_latency_percentile_ms(&self, percentile: f64) -> Result<u64, MetricsError<'_>> {
+        Ok(self.merged_histogram().percentile(percentile)?)
+    }
+
+    /// Returns counter for errors occured in nonpaged queries
+    pub fn get_errors_num(&self) -> u64 {
+        self.errors_num.load(ORDER_TYPE)
+    }
+
+    /// Returns counter for nonpaged queries
+    pub fn get_queries_num(&self) -> u64 {
+        self.queries_num.load(ORDER_TYPE)
+    }
+
+    /// Returns counter for errors occured in paged queries
+    pub fn get_errors_iter_num(&self) -> u64 {
+        self.errors_iter_num.load(ORDER_TYPE)
+    }
+
+    /// Returns counter for pages requested in paged queries
+    pub fn get_queries_iter_num(&self) -> u64 {
+        self.queries_iter_num.load(ORDER_TYPE)
+    }
+
+    /// Returns counter measuring how many times a retry policy has decided to retry
+    /// a query which failed on this node
+    pub fn get_retries_num(&self) -> u64 {
+        self.retries_num.load(ORDER_TYPE)
+    }
+
+    /// Returns metrics of each shard of the node which received some requests.  
+    /// Empty for nodes which aren't shard-aware, and for the metrics of a single shard.
+    pub fn per_shard(&self) -> HashMap<Shard, Arc<NodeMetrics>> {
+        HashMap::clone(&self.shards.load())
+    }
+}
+
+/// Metrics of each node which received some requests, see [`Metrics::view`].
+/// Nodes are fixed when the view is taken, while their metrics keep being updated
+#[derive(Debug, Clone)]
+pub struct MetricsView {
+    nodes: Arc<HashMap<SocketAddr, Arc<NodeMetrics>>>,
+}
+
+impl MetricsView {
+    /// Returns metrics of each node by its address,
+    /// e.g. to spot a single node which is slow or returns errors:
+    /// ```rust
+    /// # use scylla::Session;
+    /// # fn report(session: &Session) {
+    /// for (node, metrics) in session.get_metrics().view().per_node() {
+    ///     println!(
+    ///         "{}: {} queries, {} errors",
+    ///         node,
+    ///         metrics.get_queries_num(),
+    ///         metrics.get_errors_num()
+    ///     );
+    /// }
+    /// # }
+    /// ```
+    pub fn per_node(&self) -> &HashMap<SocketAddr, Arc<NodeMetrics>> {
+        &self.nodes
+    }
+}
+
+// Returns metrics stored under the key, inserting them if they're missing.
+// Lookups don't lock, the map is copied only when a node or shard gets its first request
+fn get_or_insert<K: Hash + Eq + Copy>(
+    map: &ArcSwap<HashMap<K, Arc<NodeMetrics>>>,
+    key: K,
+) -> Arc<NodeMetrics> {
+    if let Some(metrics) = map.load().get(&key) {
+        return metrics.clone();
+    }
+
+    let mut inserted: Option<Arc<NodeMetrics>> = None;
+    map.rcu(|current| {
+        let mut updated = HashMap::clone(current);
+        inserted = Some(
+            updated
+                .entry(key)
+                .or_insert_with(|| Arc::new(NodeMetrics::new()))
+                .clone(),
+        );
+        updated
+    });
+    inserted.expect("rcu calls the update at least once")
+}
+
+#[derive(Debug)]
 pub struct Metrics {
     errors_num: AtomicU64,
     queries_num: AtomicU64,
     errors_iter_num: AtomicU64,
     queries_iter_num: AtomicU64,
     retries_num: AtomicU64,
+    oversized_batches_num: AtomicU64,
     histogram: Arc<Mutex<Histogram>>,
+    // Nodes which aren't in the cluster anymore are removed by prune_nodes()
+    nodes: ArcSwap<HashMap<SocketAddr, Arc<NodeMetrics>>>,
+    // Snapshot taken by the last take_interval() call
+    last_interval_snapshot: Mutex<MetricsSnapshot>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Metrics {
@@ -46,34 +311,86 @@ impl Metrics {
             errors_iter_num: AtomicU64::new(0),
             queries_iter_num: AtomicU64::new(0),
             retries_num: AtomicU64::new(0),
+            oversized_batches_num: AtomicU64::new(0),
             histogram: Arc::new(Mutex::new(Histogram::new())),
+            nodes: ArcSwap::from_pointee(HashMap::new()),
+            last_interval_snapshot: Mutex::new(MetricsSnapshot::zero(Instant::now())),
+        }
+    }
+
+    /// Returns metrics of the node and shard of the connection, which requests are recorded in
+    pub(crate) fn target(&self, node: SocketAddr, connection: &Connection) -> RequestTarget {
+        let shard = connection
+            .get_shard_info()
+            .as_ref()
+            .map(|info| info.shard as Shard);
+        self.target_for_shard(node, shard)
+    }
+
+    fn target_for_shard(&self, node: SocketAddr, shard: Option<Shard>) -> RequestTarget {
+        let node = get_or_insert(&self.nodes, node);
+        let shard = shard.map(|shard| get_or_insert(&node.shards, shard));
+        RequestTarget { node, shard }
+    }
+
+    /// Removes metrics of nodes which aren't part of the cluster anymore
+    pub(crate) fn prune_nodes(&self, is_in_cluster: impl Fn(&SocketAddr) -> bool) {
+        if self.nodes.load().keys().all(&is_in_cluster) {
+            return;
         }
+        self.nodes.rcu(|current| {
+            current
+                .iter()
+                .filter(|(address, _)| is_in_cluster(address))
+                .map(|(address, metrics)| (*address, metrics.clone()))
+                .collect::<HashMap<_, _>>()
+        });
     }
 
     /// Increments counter for errors that occured in nonpaged queries.
-    pub(crate) fn inc_failed_nonpaged_queries(&self) {
+    pub(crate) fn inc_failed_nonpaged_queries(&self, target: &RequestTarget) {
         self.errors_num.fetch_add(1, ORDER_TYPE);
+        target.record(|m| {
+            m.errors_num.fetch_add(1, ORDER_TYPE);
+        });
     }
 
     /// Increments counter for nonpaged queries.
-    pub(crate) fn inc_total_nonpaged_queries(&self) {
+    pub(crate) fn inc_total_nonpaged_queries(&self, target: &RequestTarget) {
         self.queries_num.fetch_add(1, ORDER_TYPE);
+        target.record(|m| {
+            m.queries_num.fetch_add(1, ORDER_TYPE);
+        });
     }
 
     /// Increments counter for errors that occured in paged queries.
-    pub(crate) fn inc_failed_paged_queries(&self) {
+    pub(crate) fn inc_failed_paged_queries(&self, target: &RequestTarget) {
         self.errors_iter_num.fetch_add(1, ORDER_TYPE);
+        target.record(|m| {
+            m.errors_iter_num.fetch_add(1, ORDER_TYPE);
+        });
     }
 
     /// Increments counter for page queries in paged queries.
     /// If query_iter would return 4 pages then this counter should be incremented 4 times.
-    pub(crate) fn inc_total_paged_queries(&self) {
+    pub(crate) fn inc_total_paged_queries(&self, target: &RequestTarget) {
         self.queries_iter_num.fetch_add(1, ORDER_TYPE);
+        target.record(|m| {
+            m.queries_iter_num.fetch_add(1, ORDER_TYPE);
+        });
     }
 
     /// Increments counter measuring how many times a retry policy has decided to retry a query
-    pub(crate) fn inc_retries_num(&self) {
+    pub(crate) fn inc_retries_num(&self, target: &RequestTarget) {
         self.retries_num.fetch_add(1, ORDER_TYPE);
+        target.record(|m| {
+            m.retries_num.fetch_add(1, ORDER_TYPE);
+        });
+    }
+
+    /// Increments counter of batches exceeding configured size thresholds
+    pub(crate) fn inc_oversized_batches(&self) {
+        self.oversized_batches_num.fetch_add(1, ORDER_TYPE);
     }
 
     /// Saves to histogram latency of completing single query.
@@ -81,8 +398,14 @@ impl Metrics {
     ///
     /// # Arguments
     ///
+    /// * `target` - node and shard which executed the query
     /// * `latency` - time in milliseconds that should be logged
-    pub(crate) fn log_query_latency(&self, latency: u64) -> Result<(), MetricsError> {
+    pub(crate) fn log_query_latency(
+        &self,
+        target: &RequestTarget,
+        latency: u64,
+    ) -> Result<(), MetricsError<'_>> {
+        target.log_query_latency(latency);
         let mut histogram_unlocked = self.histogram.lock().unwrap();
         histogram_unlocked.increment(latency)?;
         Ok(())
@@ -127,4 +450,208 @@ impl Metrics {
     pub fn get_retries_num(&self) -> u64 {
         self.retries_num.load(ORDER_TYPE)
     }
+
+    /// Returns counter of batches which exceeded configured statement count or size thresholds
+    pub fn get_oversized_batches_num(&self) -> u64 {
+        self.oversized_batches_num.load(ORDER_TYPE)
+    }
+
+    /// Returns metrics of each node of the cluster which received some requests.
+    /// Metrics of nodes removed from the cluster are dropped when the topology is refreshed
+    pub fn view(&self) -> MetricsView {
+        MetricsView {
+            nodes: self.nodes.load_full(),
+        }
+    }
+
+    /// Returns current values of all counters.  
+    /// Counters are read one by one, so queries finishing in the meantime
+    /// can be included in some of them and not in others.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            taken_at: Instant::now(),
+            errors_num: self.get_errors_num(),
+            queries_num: self.get_queries_num(),
+            errors_iter_num: self.get_errors_iter_num(),
+            queries_iter_num: self.get_queries_iter_num(),
+            retries_num: self.get_retries_num(),
+            oversized_batches_num: self.get_oversized_batches_num(),
+        }
+    }
+
+    /// Returns current values of all counters and sets them to zero,
+    /// clearing latencies and [per node metrics](Metrics::view) as well.
+    /// No query is lost or counted twice between consecutive calls.  
+    /// The next [`take_interval`](Metrics::take_interval) covers the time since the reset.
+    pub fn snapshot_and_reset(&self) -> MetricsSnapshot {
+        let mut last_interval_snapshot = self.last_interval_snapshot.lock().unwrap();
+        let snapshot = MetricsSnapshot {
+            taken_at: Instant::now(),
+            errors_num: self.errors_num.swap(0, ORDER_TYPE),
+            queries_num: self.queries_num.swap(0, ORDER_TYPE),
+            errors_iter_num: self.errors_iter_num.swap(0, ORDER_TYPE),
+            queries_iter_num: self.queries_iter_num.swap(0, ORDER_TYPE),
+            retries_num: self.retries_num.swap(0, ORDER_TYPE),
+            oversized_batches_num: self.oversized_batches_num.swap(0, ORDER_TYPE),
+        };
+        self.histogram.lock().unwrap().clear();
+        self.nodes.store(Arc::new(HashMap::new()));
+        *last_interval_snapshot = MetricsSnapshot::zero(snapshot.taken_at);
+
+        snapshot
+    }
+
+    /// Returns how the counters changed since the previous call, or since the session
+    /// was created for the first call. Meant for periodic reporting, e.g. of queries per second:
+    /// ```rust
+    /// # use scylla::Session;
+    /// # async fn report(session: &Session) {
+    /// let metrics = session.get_metrics();
+    /// loop {
+    ///     tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+    ///     let interval = metrics.take_interval();
+    ///     println!(
+    ///         "{:.1} queries/s, {:.1} errors/s",
+    ///         interval.queries_per_sec(),
+    ///         interval.errors_per_sec()
+    ///     );
+    /// }
+    /// # }
+    /// ```
+    pub fn take_interval(&self) -> MetricsDelta {
+        let mut last_interval_snapshot = self.last_interval_snapshot.lock().unwrap();
+        let snapshot = self.snapshot();
+        let delta = snapshot.delta_since(&last_interval_snapshot);
+        *last_interval_snapshot = snapshot;
+
+        delta
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Metrics, MetricsDelta};
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::time::Duration;
+
+    const NODE: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9042);
+
+    #[test]
+    fn snapshots_and_intervals() {
+        let metrics = Metrics::new();
+        let target = metrics.target_for_shard(NODE, None);
+        metrics.inc_total_nonpaged_queries(&target);
+        metrics.inc_total_nonpaged_queries(&target);
+        metrics.inc_failed_nonpaged_queries(&target);
+        metrics.inc_total_paged_queries(&target);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.queries_num, 2);
+        assert_eq!(snapshot.errors_num, 1);
+        assert_eq!(snapshot.queries_iter_num, 1);
+
+        let interval = metrics.take_interval();
+        assert_eq!(interval.queries_num, 2);
+        assert_eq!(interval.queries_iter_num, 1);
+
+        // The next interval counts only new queries
+        metrics.inc_total_nonpaged_queries(&target);
+        let interval = metrics.take_interval();
+        assert_eq!(interval.queries_num, 1);
+        assert_eq!(interval.errors_num, 0);
+
+        // Resetting returns the totals and starts counting from zero
+        metrics.inc_retries_num(&target);
+        let reset = metrics.snapshot_and_reset();
+        assert_eq!(reset.queries_num, 3);
+        assert_eq!(reset.retries_num, 1);
+        assert_eq!(metrics.get_queries_num(), 0);
+        assert_eq!(metrics.get_retries_num(), 0);
+        assert!(metrics.get_latency_avg_ms().is_err());
+
+        metrics.inc_total_nonpaged_queries(&target);
+        let interval = metrics.take_interval();
+        assert_eq!(interval.queries_num, 1);
+        assert_eq!(interval.retries_num, 0);
+    }
+
+    #[test]
+    fn per_node_and_shard() {
+        let metrics = Metrics::new();
+        let node_a = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9042);
+        let node_b = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)), 9042);
+        let a_shard_0 = metrics.target_for_shard(node_a, Some(0));
+        let a_shard_1 = metrics.target_for_shard(node_a, Some(1));
+        let b = metrics.target_for_shard(node_b, None);
+
+        metrics.inc_total_nonpaged_queries(&a_shard_0);
+        metrics.log_query_latency(&a_shard_0, 10).unwrap();
+        metrics.inc_total_nonpaged_queries(&a_shard_1);
+        metrics.inc_failed_nonpaged_queries(&a_shard_1);
+        metrics.inc_retries_num(&a_shard_1);
+        metrics.inc_total_paged_queries(&b);
+        metrics.inc_failed_paged_queries(&b);
+        metrics.log_query_latency(&b, 30).unwrap();
+
+        assert_eq!(metrics.get_queries_num(), 2);
+        assert_eq!(metrics.get_latency_avg_ms().unwrap(), 20);
+
+        let view = metrics.view();
+        let per_node = view.per_node();
+        assert_eq!(per_node.len(), 2);
+
+        let a = &per_node[&node_a];
+        assert_eq!(a.get_queries_num(), 2);
+        assert_eq!(a.get_errors_num(), 1);
+        assert_eq!(a.get_retries_num(), 1);
+        assert_eq!(a.get_queries_iter_num(), 0);
+        assert_eq!(a.get_latency_avg_ms().unwrap(), 10);
+
+        let a_shards = a.per_shard();
+        assert_eq!(a_shards.len(), 2);
+        assert_eq!(a_shards[&0].get_queries_num(), 1);
+        assert_eq!(a_shards[&0].get_errors_num(), 0);
+        assert_eq!(a_shards[&0].get_latency_avg_ms().unwrap(), 10);
+        assert_eq!(a_shards[&1].get_errors_num(), 1);
+        assert!(a_shards[&1].get_latency_avg_ms().is_err());
+        assert!(a_shards[&1].per_shard().is_empty());
+
+        let b = &per_node[&node_b];
+        assert_eq!(b.get_queries_iter_num(), 1);
+        assert_eq!(b.get_errors_iter_num(), 1);
+        assert_eq!(b.get_latency_avg_ms().unwrap(), 30);
+        assert!(b.per_shard().is_empty());
+
+        // Nodes removed from the cluster are dropped
+        metrics.prune_nodes(|address| *address == node_b);
+        assert_eq!(
+            metrics.view().per_node().keys().collect::<Vec<_>>(),
+            vec![&node_b]
+        );
+
+        metrics.snapshot_and_reset();
+        assert!(metrics.view().per_node().is_empty());
+    }
+
+    #[test]
+    fn rates() {
+        let delta = MetricsDelta {
+            interval: Duration::from_secs(4),
+            queries_num: 6,
+            queries_iter_num: 2,
+            errors_num: 1,
+            errors_iter_num: 1,
+            ..Default::default()
+        };
+        assert_eq!(delta.queries_per_sec(), 2.0);
+        assert_eq!(delta.errors_per_sec(), 0.5);
+        assert_eq!(delta.retries_per_sec(), 0.0);
+
+        // Empty interval doesn't divide by zero
+        let empty = MetricsDelta {
+            queries_num: 1,
+            ..Default::default()
+        };
+        assert_eq!(empty.queries_per_sec(), 0.0);
+    }
 }
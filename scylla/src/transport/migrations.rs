@@ -0,0 +1,470 @@
+//! Versioned schema migrations
+//!
+//! A [`Migrator`] applies CQL scripts in the order of their versions and records applied ones
+//! in a table managed by the driver, so each script is applied only once.
+//! Schema agreement is awaited after each migration, before the next one starts.
+//!
+//! Statements of a script are executed one by one, without a transaction.
+//! If one of them fails, the migration isn't recorded and will be retried from the start
+//! on the next run, so scripts should use `IF NOT EXISTS` / `IF EXISTS` where possible.
+//! Migrations should be run from a single process at a time.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::Utc;
+use thiserror::Error;
+use tracing::info;
+
+use super::errors::QueryError;
+use super::iterator::NextRowError;
+use super::session::Session;
+use crate::frame::value::Timestamp;
+use crate::query_builder::{insert_into, select};
+use crate::routing::hash3_x64_128;
+
+/// A versioned CQL script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    version: i64,
+    name: String,
+    script: String,
+}
+
+impl Migration {
+    /// Creates a migration, `script` can contain many statements separated with `;`
+    pub fn new(version: i64, name: impl Into<String>, script: impl Into<String>) -> Self {
+        Migration {
+            version,
+            name: name.into(),
+            script: script.into(),
+        }
+    }
+
+    pub fn version(&self) -> i64 {
+        self.version
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn script(&self) -> &str {
+        &self.script
+    }
+
+    /// Returns statements of the script, without comments and trailing `;`
+    pub fn statements(&self) -> Vec<&str> {
+        split_statements(&self.script)
+    }
+
+    // Detects changes of already applied scripts
+    fn checksum(&self) -> i64 {
+        hash3_x64_128(self.script.as_bytes()) as i64
+    }
+}
+
+/// State of a migration, see [`Migrator::status`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationState {
+    /// Not applied yet
+    Pending,
+    /// Applied, the script didn't change since then
+    Applied,
+    /// Applied, but the script has changed since then
+    Modified,
+    /// Applied, but not known to this migrator
+    Unknown,
+}
+
+/// Status of a single migration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: String,
+    pub state: MigrationState,
+}
+
+#[derive(Error, Debug)]
+pub enum MigrationError {
+    #[error(transparent)]
+    QueryError(#[from] QueryError),
+
+    /// Reading the migrations table failed
+    #[error(transparent)]
+    NextRowError(#[from] NextRowError),
+
+    /// Statement of a migration failed, the migration wasn't recorded as applied
+    #[error("Migration {version} failed on statement \"{statement}\": {error}")]
+    StatementFailed {
+        version: i64,
+        statement: String,
+        error: Box<QueryError>,
+    },
+
+    /// Script of an applied migration has changed
+    #[error("Migration {version} was modified after it had been applied")]
+    Modified { version: i64 },
+
+    /// Two migrations have the same version
+    #[error("Migration version {0} is used more than once")]
+    DuplicateVersion(i64),
+
+    /// Schema agreement wasn't reached in time after a migration
+    #[error("Schema agreement not reached after migration {version}")]
+    SchemaAgreementTimeout { version: i64 },
+
+    /// Reading migrations from a directory failed
+    #[error("Reading migrations failed: {0}")]
+    IoError(#[from] io::Error),
+
+    /// Migration file name is not in the `V<version>__<name>.cql` format
+    #[error("Invalid migration file name {0}, expected V<version>__<name>.cql")]
+    InvalidFileName(String),
+}
+
+/// Applies migrations and reports their status
+///
+/// # Example
+/// ```rust
+/// # use scylla::Session;
+/// # use std::error::Error;
+/// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+/// use scylla::transport::migrations::{Migration, Migrator};
+///
+/// let applied = Migrator::new(session, "ks")
+///     .migration(Migration::new(
+///         1,
+///         "create users",
+///         "CREATE TABLE IF NOT EXISTS ks.users (id int PRIMARY KEY, name text);",
+///     ))
+///     .migration(Migration::new(
+///         2,
+///         "add email",
+///         "ALTER TABLE ks.users ADD email text;",
+///     ))
+///     .run()
+///     .await?;
+///
+/// println!("Applied migrations: {:?}", applied);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Migrator<'a> {
+    session: &'a Session,
+    keyspace: String,
+    table: String,
+    migrations: Vec<Migration>,
+    schema_agreement_timeout: Duration,
+}
+
+// An applied migration, as recorded in the migrations table
+struct AppliedMigration {
+    name: String,
+    checksum: i64,
+}
+
+impl<'a> Migrator<'a> {
+    /// Creates a migrator recording applied migrations in `keyspace.schema_migrations`.
+    /// The keyspace must already exist
+    pub fn new(session: &'a Session, keyspace: impl Into<String>) -> Self {
+        Migrator {
+            session,
+            keyspace: keyspace.into(),
+            table: "schema_migrations".to_string(),
+            migrations: Vec::new(),
+            schema_agreement_timeout: Duration::from_secs(60),
+        }
+    }
+
+    /// Sets the name of the table in which applied migrations are recorded
+    pub fn table(mut self, table: impl Into<String>) -> Self {
+        self.table = table.into();
+        self
+    }
+
+    /// Sets how long to wait for schema agreement after each migration, 60 seconds by default
+    pub fn schema_agreement_timeout(mut self, timeout: Duration) -> Self {
+        self.schema_agreement_timeout = timeout;
+        self
+    }
+
+    /// Adds a migration, migrations can be added in any order
+    pub fn migration(mut self, migration: Migration) -> Self {
+        self.migrations.push(migration);
+        self
+    }
+
+    /// Adds all migrations from files named `V<version>__<name>.cql` in `dir`,
+    /// e.g. `V1__create_users.cql`. Other files are ignored
+    pub fn migrations_from_dir(mut self, dir: impl AsRef<Path>) -> Result<Self, MigrationError> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let file_name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) if name.starts_with('V') && name.ends_with(".cql") => name,
+                _ => continue,
+            };
+
+            let (version, name) = file_name[1..file_name.len() - 4]
+                .split_once("__")
+                .and_then(|(version, name)| Some((version.parse::<i64>().ok()?, name)))
+                .ok_or_else(|| MigrationError::InvalidFileName(file_name.to_string()))?;
+
+            let migration =
+                Migration::new(version, name.replace('_', " "), fs::read_to_string(&path)?);
+            self.migrations.push(migration);
+        }
+        Ok(self)
+    }
+
+    /// Returns the status of all migrations, including applied ones unknown to this migrator,
+    /// ordered by version
+    pub async fn status(&self) -> Result<Vec<MigrationStatus>, MigrationError> {
+        let migrations = self.sorted_migrations()?;
+        let mut applied = self.fetch_applied().await?;
+
+        let mut statuses: Vec<MigrationStatus> = migrations
+            .iter()
+            .map(|migration| {
+                let state = match applied.remove(&migration.version) {
+                    None => MigrationState::Pending,
+                    Some(applied) if applied.checksum == migration.checksum() => {
+                        MigrationState::Applied
+                    }
+                    Some(_) => MigrationState::Modified,
+                };
+                MigrationStatus {
+                    version: migration.version,
+                    name: migration.name.clone(),
+                    state,
+                }
+            })
+            .collect();
+
+        statuses.extend(
+            applied
+                .into_iter()
+                .map(|(version, applied)| MigrationStatus {
+                    version,
+                    name: applied.name,
+                    state: MigrationState::Unknown,
+                }),
+        );
+        statuses.sort_by_key(|status| status.version);
+        Ok(statuses)
+    }
+
+    /// Applies all pending migrations in the order of versions, returns versions of applied ones.
+    /// Fails without applying anything if an applied migration was modified
+    pub async fn run(&self) -> Result<Vec<i64>, MigrationError> {
+        let migrations = self.sorted_migrations()?;
+        let applied = self.fetch_applied().await?;
+
+        for migration in &migrations {
+            match applied.get(&migration.version) {
+                Some(applied) if applied.checksum != migration.checksum() => {
+                    return Err(MigrationError::Modified {
+                        version: migration.version,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let mut newly_applied = Vec::new();
+        for migration in migrations {
+            if applied.contains_key(&migration.version) {
+                continue;
+            }
+
+            info!(
+                "Applying migration {} ({})",
+                migration.version, migration.name
+            );
+            self.apply(migration).await?;
+            newly_applied.push(migration.version);
+        }
+        Ok(newly_applied)
+    }
+
+    async fn apply(&self, migration: &Migration) -> Result<(), MigrationError> {
+        for statement in migration.statements() {
+            self.session.query(statement, &[]).await.map_err(|error| {
+                MigrationError::StatementFailed {
+                    version: migration.version,
+                    statement: statement.to_string(),
+                    error: Box::new(error),
+                }
+            })?;
+        }
+
+        let agreed = self
+            .session
+            .await_timed_schema_agreement(self.schema_agreement_timeout)
+            .await?;
+        if !agreed {
+            return Err(MigrationError::SchemaAgreementTimeout {
+                version: migration.version,
+            });
+        }
+
+        let (insert, values) = insert_into(&self.qualified_table())
+            .value("version", migration.version)
+            .value("name", migration.name.as_str())
+            .value("checksum", migration.checksum())
+            .value(
+                "applied_at",
+                Timestamp(chrono::Duration::milliseconds(
+                    Utc::now().timestamp_millis(),
+                )),
+            )
+            .build()
+            .map_err(QueryError::from)?;
+        self.session.query(insert, values).await?;
+        Ok(())
+    }
+
+    fn sorted_migrations(&self) -> Result<Vec<&Migration>, MigrationError> {
+        let mut migrations: Vec<&Migration> = self.migrations.iter().collect();
+        migrations.sort_by_key(|migration| migration.version);
+
+        for pair in migrations.windows(2) {
+            if pair[0].version == pair[1].version {
+                return Err(MigrationError::DuplicateVersion(pair[0].version));
+            }
+        }
+        Ok(migrations)
+    }
+
+    // Creates the migrations table if needed and reads applied migrations from it
+    async fn fetch_applied(&self) -> Result<HashMap<i64, AppliedMigration>, MigrationError> {
+        let table = self.qualified_table();
+        let create_table = format!(
+            "CREATE TABLE IF NOT EXISTS {} \
+             (version bigint PRIMARY KEY, name text, checksum bigint, applied_at timestamp)",
+            table
+        );
+        self.session.query(create_table, &[]).await?;
+
+        let (query, values) = select(&table)
+            .columns(&["version", "name", "checksum"])
+            .build()
+            .map_err(QueryError::from)?;
+        let mut rows =
+            self.session
+                .query_iter(query, values)
+                .await?
+                .into_typed::<(i64, Option<String>, Option<i64>)>();
+
+        let mut applied = HashMap::new();
+        while let Some(row) = rows.next().await {
+            let (version, name, checksum) = row?;
+            applied.insert(
+                version,
+                AppliedMigration {
+                    name: name.unwrap_or_default(),
+                    checksum: checksum.unwrap_or_default(),
+                },
+            );
+        }
+        Ok(applied)
+    }
+
+    fn qualified_table(&self) -> String {
+        format!("{}.{}", self.keyspace, self.table)
+    }
+}
+
+// Splits a script on semicolons which are outside of strings, quoted names,
+// $$ blocks and comments. Comments are removed, empty statements are skipped
+fn split_statements(script: &str) -> Vec<&str> {
+    let bytes = script.as_bytes();
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+
+    // Position of the first byte after the closing `end`, or the end of the script
+    let skip_past = |from: usize, end: &str| {
+        script[from..]
+            .find(end)
+            .map_or(script.len(), |pos| from + pos + end.len())
+    };
+
+    while i < bytes.len() {
+        match &script[i..] {
+            rest if rest.starts_with("--") || rest.starts_with("//") => {
+                i = skip_past(i, "\n");
+            }
+            rest if rest.starts_with("/*") => i = skip_past(i + 2, "*/"),
+            rest if rest.starts_with("$$") => i = skip_past(i + 2, "$$"),
+            // Doubled quotes inside strings are handled as two adjacent strings
+            rest if rest.starts_with('\'') => i = skip_past(i + 1, "'"),
+            rest if rest.starts_with('"') => i = skip_past(i + 1, "\""),
+            rest if rest.starts_with(';') => {
+                statements.push(&script[start..i]);
+                i += 1;
+                start = i;
+            }
+            _ => i += script[i..].chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    statements.push(&script[start..]);
+
+    statements
+        .into_iter()
+        .map(strip_comments)
+        .filter(|statement| !statement.is_empty())
+        .collect()
+}
+
+// Removes leading line comments and surrounding whitespace
+fn strip_comments(mut statement: &str) -> &str {
+    loop {
+        statement = statement.trim();
+        if statement.starts_with("--") || statement.starts_with("//") {
+            statement = statement.split_once('\n').map_or("", |(_, rest)| rest);
+        } else {
+            return statement;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_statements;
+
+    #[test]
+    fn split_script() {
+        let script = "
+            -- Create the table
+            CREATE TABLE ks.t (a int PRIMARY KEY, b text);
+            INSERT INTO ks.t (a, b) VALUES (1, 'x; y''s');
+            /* block; comment */ CREATE FUNCTION ks.f (a int) RETURNS NULL ON NULL INPUT
+                RETURNS int LANGUAGE lua AS $$ return a; $$;
+            ;
+            // trailing comment
+        ";
+
+        assert_eq!(
+            split_statements(script),
+            vec![
+                "CREATE TABLE ks.t (a int PRIMARY KEY, b text)",
+                "INSERT INTO ks.t (a, b) VALUES (1, 'x; y''s')",
+                "/* block; comment */ CREATE FUNCTION ks.f (a int) RETURNS NULL ON NULL INPUT\n                \
+                 RETURNS int LANGUAGE lua AS $$ return a; $$",
+            ]
+        );
+    }
+
+    #[test]
+    fn split_without_trailing_semicolon() {
+        assert_eq!(
+            split_statements("SELECT * FROM ks.t"),
+            vec!["SELECT * FROM ks.t"]
+        );
+        assert!(split_statements("  \n-- only a comment\n").is_empty());
+    }
+}
@@ -1,32 +1,52 @@
+pub mod address_translator;
+pub mod authentication;
+pub mod caching_session;
 mod cluster;
+mod cluster_data_builder;
 pub(crate) mod connection;
 mod connection_keeper;
+mod control_connection;
+#[cfg(feature = "csv")]
+pub mod csv_loader;
+#[cfg(feature = "fault-injection")]
+pub mod fault_injection;
+pub mod host_filter;
 pub mod load_balancing;
+pub mod migrations;
 mod node;
+pub mod reconnection_policy;
+mod replication;
+pub mod request_listener;
 pub mod retry_policy;
+pub mod row_sink;
+pub(crate) mod runtime;
+mod schema_dump;
 pub mod session;
 pub mod session_builder;
 pub mod speculative_execution;
+pub mod table_mapper;
+pub mod timestamp_generator;
+#[cfg(feature = "ssl")]
+pub mod tls;
+pub mod token_range_scan;
 mod topology;
 
 pub mod errors;
 pub mod iterator;
 pub(crate) mod metrics;
 
+pub use cluster::{ClusterData, ClusterEvent, Datacenter, SchemaChangeNotification};
+pub use cluster_data_builder::ClusterDataBuilder;
+pub use node::{Node, PoolSize};
+pub use topology::{
+    ClusterInfo, ClusteringOrder, Column, ColumnKind, Keyspace, Peer, Strategy, Table,
+    UserDefinedType,
+};
+
 mod authenticate_test;
 #[cfg(test)]
 mod session_test;
 
-// All of the Authenticators supported by Scylla
-#[derive(Debug, PartialEq)]
-pub enum Authenticator {
-    AllowAllAuthenticator,
-    PasswordAuthenticator,
-    CassandraPasswordAuthenticator,
-    CassandraAllowAllAuthenticator,
-    ScyllaTransitionalAuthenticator,
-}
-
 /// The wire protocol compression algorithm.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub enum Compression {
@@ -44,3 +64,24 @@ impl ToString for Compression {
         }
     }
 }
+
+/// Version of the CQL binary protocol used on connections.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub enum ProtocolVersion {
+    /// Protocol v4, supported by all Scylla and Cassandra versions used with the driver.
+    #[default]
+    V4,
+    /// Protocol v5, adds frames with checksums, keyspaces of single requests and new error codes.
+    /// Nodes which don't support it are connected to using v4.
+    V5,
+}
+
+impl ProtocolVersion {
+    /// Number of the version sent in frame headers
+    pub fn as_u8(self) -> u8 {
+        match self {
+            ProtocolVersion::V4 => 4,
+            ProtocolVersion::V5 => 5,
+        }
+    }
+}
@@ -2,8 +2,11 @@
 use crate::routing::{ShardInfo, Token};
 use crate::transport::connection::VerifiedKeyspaceName;
 use crate::transport::connection::{Connection, ConnectionConfig};
-use crate::transport::connection_keeper::{ConnectionKeeper, ShardInfoSender};
-use crate::transport::errors::QueryError;
+use crate::transport::connection_keeper::{
+    ConnectionKeeper, ConnectionState, PoolHealth, ShardInfoSender,
+};
+use crate::transport::errors::{ProtocolError, QueryError};
+use crate::transport::runtime;
 use futures::future::join_all;
 
 use futures::{future::RemoteHandle, FutureExt};
@@ -12,35 +15,82 @@ use std::{
     convert::TryInto,
     hash::{Hash, Hasher},
     net::SocketAddr,
+    num::NonZeroUsize,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc, RwLock,
     },
 };
+use tracing::{debug, debug_span, Instrument};
+use uuid::Uuid;
 
 /// Node represents a cluster node along with it's data and connections
 pub struct Node {
     pub address: SocketAddr,
     pub datacenter: Option<String>,
     pub rack: Option<String>,
+    /// Host ID of the node, unique and persistent across address changes
+    pub host_id: Option<Uuid>,
 
     pub connections: Arc<RwLock<Arc<NodeConnections>>>,
+    pool_health: Arc<PoolHealth>,
 
     down_marker: AtomicBool,
 
+    // Set for nodes rejected by the host filter, connections to them are never opened
+    ignored: bool,
+
     use_keyspace_channel: tokio::sync::mpsc::Sender<UseKeyspaceRequest>,
+    open_connections_channel: tokio::sync::mpsc::Sender<OpenConnectionsRequest>,
 
     _worker_handle: RemoteHandle<()>,
 }
 
+/// Number of connections opened to each node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolSize {
+    /// Number of connections to each shard of a node.
+    /// Nodes which aren't shard-aware, ex. Cassandra nodes, get this many connections in total
+    PerShard(NonZeroUsize),
+    /// Number of connections to each node, spread evenly between its shards.
+    /// Each shard gets at least one connection, so nodes with many shards may get more connections
+    PerHost(NonZeroUsize),
+}
+
+/// One connection to each shard
+impl Default for PoolSize {
+    fn default() -> Self {
+        PoolSize::PerShard(NonZeroUsize::new(1).unwrap())
+    }
+}
+
+impl PoolSize {
+    // Number of connections to a node which isn't shard-aware
+    fn connections_per_host(&self) -> usize {
+        match self {
+            PoolSize::PerShard(connections) | PoolSize::PerHost(connections) => connections.get(),
+        }
+    }
+
+    // Number of connections to each shard of a node with nr_shards shards
+    fn connections_per_shard(&self, nr_shards: u16) -> usize {
+        match self {
+            PoolSize::PerShard(per_shard) => per_shard.get(),
+            PoolSize::PerHost(per_host) => (per_host.get() - 1) / nr_shards.max(1) as usize + 1,
+        }
+    }
+}
+
 pub enum NodeConnections {
-    /// Non shard-aware ex. a Cassandra node connection
-    Single(ConnectionKeeper),
+    /// Connections weren't opened yet, they will be opened on first use
+    NotOpened,
+    /// Non shard-aware ex. a Cassandra node connections, always nonempty
+    Single(Vec<ConnectionKeeper>),
     /// Shard aware Scylla node connections
     Sharded {
         shard_info: ShardInfo,
-        /// shard_conns always contains shard_info.nr_shards ConnectionKeepers
-        shard_conns: Vec<ConnectionKeeper>,
+        /// shard_conns always contains shard_info.nr_shards nonempty pools of ConnectionKeepers
+        shard_conns: Vec<Vec<ConnectionKeeper>>,
     },
 }
 
@@ -56,8 +106,14 @@ struct NodeWorker {
     // Channel used to receive use keyspace requests
     use_keyspace_channel: tokio::sync::mpsc::Receiver<UseKeyspaceRequest>,
 
+    // Channel used to receive requests to open connections which weren't opened yet
+    open_connections_channel: tokio::sync::mpsc::Receiver<OpenConnectionsRequest>,
+
     // Keyspace send in "USE <keyspace name>" when opening each connection
     used_keyspace: Option<VerifiedKeyspaceName>,
+
+    // Shared with the node, updated by keepers of the connections in the pool
+    pool_health: Arc<PoolHealth>,
 }
 
 #[derive(Debug)]
@@ -66,6 +122,11 @@ struct UseKeyspaceRequest {
     response_chan: tokio::sync::oneshot::Sender<Result<(), QueryError>>,
 }
 
+#[derive(Debug)]
+struct OpenConnectionsRequest {
+    response_chan: tokio::sync::oneshot::Sender<()>,
+}
+
 impl Node {
     /// Creates new node which starts connecting in the background
     /// # Arguments
@@ -74,28 +135,27 @@ impl Node {
     /// `compression` - preferred compression to use
     /// `datacenter` - optional datacenter name
     /// `rack` - optional rack name
+    /// `host_id` - optional host ID
+    /// `keyspace_name` - keyspace to use on all connections
+    /// `open_connections` - whether to start connecting right away, otherwise connections are opened on first use
     pub fn new(
         address: SocketAddr,
         connection_config: ConnectionConfig,
         datacenter: Option<String>,
         rack: Option<String>,
+        host_id: Option<Uuid>,
         keyspace_name: Option<VerifiedKeyspaceName>,
+        open_connections: bool,
     ) -> Self {
         let (shard_info_sender, shard_info_receiver) = tokio::sync::watch::channel(None);
 
         let shard_info_sender = Arc::new(std::sync::Mutex::new(shard_info_sender));
 
         let (use_keyspace_sender, use_keyspace_receiver) = tokio::sync::mpsc::channel(32);
+        let (open_connections_sender, open_connections_receiver) = tokio::sync::mpsc::channel(32);
 
-        let connections = Arc::new(RwLock::new(Arc::new(NodeConnections::Single(
-            ConnectionKeeper::new(
-                address,
-                connection_config.clone(),
-                None,
-                Some(shard_info_sender.clone()),
-                keyspace_name.clone(),
-            ),
-        ))));
+        let connections = Arc::new(RwLock::new(Arc::new(NodeConnections::NotOpened)));
+        let pool_health = Arc::new(PoolHealth::default());
 
         let worker = NodeWorker {
             node_conns: connections.clone(),
@@ -104,38 +164,99 @@ impl Node {
             shard_info_sender,
             shard_info_receiver,
             use_keyspace_channel: use_keyspace_receiver,
+            open_connections_channel: open_connections_receiver,
             used_keyspace: keyspace_name,
+            pool_health: pool_health.clone(),
         };
 
-        let (fut, worker_handle) = worker.work().remote_handle();
-        tokio::spawn(fut);
+        if open_connections {
+            worker.open_connections();
+        }
+
+        let (fut, worker_handle) = worker
+            .work()
+            .instrument(debug_span!("node_pool", node = %address))
+            .remote_handle();
+        runtime::spawn(fut);
 
         Node {
             address,
             datacenter,
             rack,
+            host_id,
             connections,
+            pool_health,
             down_marker: false.into(),
+            ignored: false,
             use_keyspace_channel: use_keyspace_sender,
+            open_connections_channel: open_connections_sender,
             _worker_handle: worker_handle,
         }
     }
 
+    /// Creates a node rejected by the host filter.
+    /// It's a part of the cluster topology, but the driver never connects to it
+    pub(crate) fn new_ignored(
+        address: SocketAddr,
+        datacenter: Option<String>,
+        rack: Option<String>,
+        host_id: Option<Uuid>,
+    ) -> Self {
+        Node {
+            ignored: true,
+            ..Self::new(
+                address,
+                Default::default(),
+                datacenter,
+                rack,
+                host_id,
+                None,
+                false,
+            )
+        }
+    }
+
+    // Returns current node connections, opens them first if they weren't opened yet
+    async fn get_connections(&self) -> Arc<NodeConnections> {
+        let connections: Arc<NodeConnections> = self.connections.read().unwrap().clone();
+
+        if !matches!(&*connections, NodeConnections::NotOpened) {
+            return connections;
+        }
+
+        let (response_sender, response_receiver) = tokio::sync::oneshot::channel();
+
+        self.open_connections_channel
+            .send(OpenConnectionsRequest {
+                response_chan: response_sender,
+            })
+            .await
+            .expect("Bug in Node::get_connections sending");
+        // Other end of this channel is in NodeWorker, can't be dropped while we have &self to Node with _worker_handle
+
+        response_receiver.await.unwrap(); // NodeWorker always responds
+
+        self.connections.read().unwrap().clone()
+    }
+
     /// Get connection which should be used to connect using given token
     /// If this connection is broken get any random connection to this Node
-    pub async fn connection_for_token(&self, token: Token) -> Result<Arc<Connection>, QueryError> {
-        let connections: Arc<NodeConnections> = self.connections.read().unwrap().clone();
+    pub async fn connection_for_token(&self, token: &Token) -> Result<Arc<Connection>, QueryError> {
+        self.check_not_ignored()?;
+        let connections: Arc<NodeConnections> = self.get_connections().await;
 
         match &*connections {
-            NodeConnections::Single(conn_keeper) => conn_keeper.get_connection().await,
+            NodeConnections::NotOpened => unreachable!("Node connections are opened on first use"),
+            NodeConnections::Single(pool) => Self::connection_from_pool(pool).await,
             NodeConnections::Sharded {
                 shard_info,
                 shard_conns,
             } => {
-                let shard: u16 = shard_info
-                    .shard_of(token)
-                    .try_into()
-                    .expect("Shard number doesn't fit in u16");
+                let shard: u16 = match shard_info.shard_of(token) {
+                    Some(shard) => shard.try_into().expect("Shard number doesn't fit in u16"),
+                    // Only Murmur3 tokens are mapped to shards
+                    None => rand::thread_rng().gen_range(0..shard_info.nr_shards),
+                };
                 Self::connection_for_shard(shard, shard_info.nr_shards, shard_conns).await
             }
         }
@@ -143,10 +264,12 @@ impl Node {
 
     /// Get random connection
     pub async fn random_connection(&self) -> Result<Arc<Connection>, QueryError> {
-        let connections: Arc<NodeConnections> = self.connections.read().unwrap().clone();
+        self.check_not_ignored()?;
+        let connections: Arc<NodeConnections> = self.get_connections().await;
 
         match &*connections {
-            NodeConnections::Single(conn_keeper) => conn_keeper.get_connection().await,
+            NodeConnections::NotOpened => unreachable!("Node connections are opened on first use"),
+            NodeConnections::Single(pool) => Self::connection_from_pool(pool).await,
             NodeConnections::Sharded {
                 shard_info,
                 shard_conns,
@@ -157,25 +280,72 @@ impl Node {
         }
     }
 
+    // Custom load balancing policies may return ignored nodes, queries are never sent to them
+    fn check_not_ignored(&self) -> Result<(), QueryError> {
+        if self.ignored {
+            return Err(ProtocolError::IgnoredNode { node: self.address }.into());
+        }
+        Ok(())
+    }
+
+    /// Returns true if the node was rejected by the host filter.
+    /// Ignored nodes are kept in the cluster topology, but the driver never connects to them
+    pub fn is_ignored(&self) -> bool {
+        self.ignored
+    }
+
     pub fn is_down(&self) -> bool {
         self.down_marker.load(Ordering::Relaxed)
     }
 
+    /// Returns true if the node is considered up.
+    /// Nodes are marked down when the cluster reports them as down,
+    /// and marked up again when they come back.
+    /// A node whose connections were opened, but all of them are broken,
+    /// isn't up either until one of them reconnects.
+    pub fn is_up(&self) -> bool {
+        !self.is_down() && !self.is_pool_broken()
+    }
+
+    // Returns true if connections were opened and all of them are broken.
+    // Called for each node in each query plan, so it doesn't lock the pool
+    fn is_pool_broken(&self) -> bool {
+        self.pool_health.is_broken()
+    }
+
+    /// Returns true if the driver has at least one working connection to this node
+    pub fn is_connected(&self) -> bool {
+        let connections: Arc<NodeConnections> = self.connections.read().unwrap().clone();
+
+        let is_keeper_connected = |keeper: &ConnectionKeeper| {
+            matches!(keeper.connection_state(), ConnectionState::Connected(_))
+        };
+
+        match &*connections {
+            NodeConnections::NotOpened => false,
+            NodeConnections::Single(pool) => pool.iter().any(is_keeper_connected),
+            NodeConnections::Sharded { shard_conns, .. } => {
+                shard_conns.iter().flatten().any(is_keeper_connected)
+            }
+        }
+    }
+
     pub fn change_down_marker(&self, is_down: bool) {
         self.down_marker.store(is_down, Ordering::Relaxed);
     }
 
-    // Tries to get a connection to given shard, if it's broken returns any working connection
+    // Tries to get a connection to given shard, if they are broken returns any working connection
     async fn connection_for_shard(
         shard: u16,
         nr_shards: u16,
-        shard_conns: &[ConnectionKeeper],
+        shard_conns: &[Vec<ConnectionKeeper>],
     ) -> Result<Arc<Connection>, QueryError> {
         // Try getting the desired connection
-        let mut last_error: QueryError = match shard_conns[shard as usize].get_connection().await {
-            Ok(connection) => return Ok(connection),
-            Err(e) => e,
-        };
+        let mut last_error: QueryError =
+            match Self::connection_from_pool(&shard_conns[shard as usize]).await {
+                Ok(connection) => return Ok(connection),
+                Err(e) => e,
+            };
 
         // If this fails try getting any other in random order
         let mut shards_to_try: Vec<u16> = (shard..nr_shards).chain(0..shard).skip(1).collect();
@@ -184,7 +354,7 @@ impl Node {
             let idx = rand::thread_rng().gen_range(0..shards_to_try.len());
             let shard = shards_to_try.swap_remove(idx);
 
-            match shard_conns[shard as usize].get_connection().await {
+            match Self::connection_from_pool(&shard_conns[shard as usize]).await {
                 Ok(conn) => return Ok(conn),
                 Err(e) => last_error = e,
             }
@@ -193,6 +363,23 @@ impl Node {
         Err(last_error)
     }
 
+    // Gets a connection from a random keeper of the pool, if it's broken tries the other ones
+    pub(crate) async fn connection_from_pool(
+        pool: &[ConnectionKeeper],
+    ) -> Result<Arc<Connection>, QueryError> {
+        let first = rand::thread_rng().gen_range(0..pool.len());
+        let mut last_error: Option<QueryError> = None;
+
+        for conn_keeper in pool[first..].iter().chain(pool[..first].iter()) {
+            match conn_keeper.get_connection().await {
+                Ok(conn) => return Ok(conn),
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(last_error.expect("Connection pools are nonempty"))
+    }
+
     pub async fn use_keyspace(
         &self,
         keyspace_name: VerifiedKeyspaceName,
@@ -246,13 +433,30 @@ impl NodeWorker {
 
                             let node_conns = self.node_conns.read().unwrap().clone();
                             let use_keyspace_future = Self::handle_use_keyspace_request(node_conns, request);
-                            tokio::spawn(use_keyspace_future);
+                            runtime::spawn(use_keyspace_future);
                         },
                         None => return,
                     }
 
                     continue; // Don't go to resharding update, wait for the next event
                 },
+                // Wait for a request to open connections
+                recv_res = self.open_connections_channel.recv() => {
+                    match recv_res {
+                        Some(request) => {
+                            let node_conns = self.node_conns.read().unwrap().clone();
+                            if matches!(&*node_conns, NodeConnections::NotOpened) {
+                                self.open_connections();
+                            }
+
+                            // Don't care if nobody waits for the connections
+                            let _ = request.response_chan.send(());
+                        },
+                        None => return,
+                    }
+
+                    continue; // Connections will report ShardInfo, go back to waiting
+                },
             }
 
             let new_shard_info: Option<ShardInfo> = self.shard_info_receiver.borrow().clone();
@@ -265,39 +469,45 @@ impl NodeWorker {
                         continue;
                     }
                 }
-                (None, None) => continue, // Nothing chaged, go back to waiting for a change
+                (None, None) => {
+                    // The first connection to a node which isn't shard-aware has been opened,
+                    // fill the rest of its pool. Otherwise nothing changed, go back to waiting
+                    let node_conns = self.node_conns.read().unwrap().clone();
+                    let pool_size = self.connection_config.pool_size.connections_per_host();
+                    match &*node_conns {
+                        NodeConnections::Single(pool) if pool.len() < pool_size => {}
+                        _ => continue,
+                    }
+                }
                 _ => {}
             }
 
             cur_shard_info = new_shard_info;
+            debug!("Opening connection pool, shard info: {:?}", cur_shard_info);
 
             // We received updated node ShardInfo
             // Create new node connections. It will happen rarely so we can probably afford it
             // TODO: Maybe save some connections instead of recreating?
+            let pool_size = self.connection_config.pool_size;
             let new_connections: NodeConnections = match &cur_shard_info {
-                None => NodeConnections::Single(ConnectionKeeper::new(
-                    self.node_addr,
-                    self.connection_config.clone(),
-                    None,
-                    Some(self.shard_info_sender.clone()),
-                    self.used_keyspace.clone(),
-                )),
+                None => NodeConnections::Single(
+                    (0..pool_size.connections_per_host())
+                        .map(|_| self.new_connection_keeper(None))
+                        .collect(),
+                ),
                 Some(shard_info) => {
-                    let mut connections: Vec<ConnectionKeeper> =
+                    let per_shard = pool_size.connections_per_shard(shard_info.nr_shards);
+                    let mut connections: Vec<Vec<ConnectionKeeper>> =
                         Vec::with_capacity(shard_info.nr_shards as usize);
 
                     for shard in 0..shard_info.nr_shards {
                         let mut cur_conn_shard_info = shard_info.clone();
                         cur_conn_shard_info.shard = shard;
-                        let cur_conn = ConnectionKeeper::new(
-                            self.node_addr,
-                            self.connection_config.clone(),
-                            Some(cur_conn_shard_info),
-                            Some(self.shard_info_sender.clone()),
-                            self.used_keyspace.clone(),
-                        );
-
-                        connections.push(cur_conn);
+                        let shard_pool = (0..per_shard)
+                            .map(|_| self.new_connection_keeper(Some(cur_conn_shard_info.clone())))
+                            .collect();
+
+                        connections.push(shard_pool);
                     }
 
                     NodeConnections::Sharded {
@@ -307,16 +517,38 @@ impl NodeWorker {
                 }
             };
 
-            let mut new_connections_to_swap = Arc::new(new_connections);
-
-            // Update node.connections
-            // Use std::mem::swap to minimalize time spent holding write lock
-            let mut node_conns_lock = self.node_conns.write().unwrap();
-            std::mem::swap(&mut *node_conns_lock, &mut new_connections_to_swap);
-            drop(node_conns_lock);
+            self.swap_connections(new_connections);
         }
     }
 
+    // Opens a single connection, ShardInfo received on it will cause opening connections to all shards
+    fn open_connections(&self) {
+        self.swap_connections(NodeConnections::Single(vec![
+            self.new_connection_keeper(None)
+        ]));
+    }
+
+    fn new_connection_keeper(&self, shard_info: Option<ShardInfo>) -> ConnectionKeeper {
+        ConnectionKeeper::new(
+            self.node_addr,
+            self.connection_config.clone(),
+            shard_info,
+            Some(self.shard_info_sender.clone()),
+            self.used_keyspace.clone(),
+            Some(self.pool_health.clone()),
+        )
+    }
+
+    fn swap_connections(&self, new_connections: NodeConnections) {
+        let mut new_connections_to_swap = Arc::new(new_connections);
+
+        // Update node.connections
+        // Use std::mem::swap to minimalize time spent holding write lock
+        let mut node_conns_lock = self.node_conns.write().unwrap();
+        std::mem::swap(&mut *node_conns_lock, &mut new_connections_to_swap);
+        drop(node_conns_lock);
+    }
+
     async fn handle_use_keyspace_request(
         node_conns: Arc<NodeConnections>,
         request: UseKeyspaceRequest,
@@ -334,12 +566,16 @@ impl NodeWorker {
         let mut use_keyspace_futures = Vec::new();
 
         match &*node_conns {
-            NodeConnections::Single(conn_keeper) => {
-                let fut = conn_keeper.use_keyspace(keyspace_name.clone());
-                use_keyspace_futures.push(fut);
+            // Keyspace will be used when connections are opened
+            NodeConnections::NotOpened => return Ok(()),
+            NodeConnections::Single(pool) => {
+                for conn_keeper in pool {
+                    let fut = conn_keeper.use_keyspace(keyspace_name.clone());
+                    use_keyspace_futures.push(fut);
+                }
             }
             NodeConnections::Sharded { shard_conns, .. } => {
-                for conn_keeper in shard_conns {
+                for conn_keeper in shard_conns.iter().flatten() {
                     let fut = conn_keeper.use_keyspace(keyspace_name.clone());
                     use_keyspace_futures.push(fut);
                 }
@@ -376,3 +612,97 @@ impl NodeWorker {
         Err(QueryError::IoError(io_error.unwrap()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::PoolSize;
+    use std::num::NonZeroUsize;
+
+    #[test]
+    fn pool_size_per_shard() {
+        let size = |n| NonZeroUsize::new(n).unwrap();
+
+        let per_shard = PoolSize::PerShard(size(2));
+        assert_eq!(per_shard.connections_per_host(), 2);
+        assert_eq!(per_shard.connections_per_shard(8), 2);
+
+        let per_host = PoolSize::PerHost(size(10));
+        assert_eq!(per_host.connections_per_host(), 10);
+        assert_eq!(per_host.connections_per_shard(1), 10);
+        assert_eq!(per_host.connections_per_shard(4), 3);
+        assert_eq!(per_host.connections_per_shard(5), 2);
+        // Each shard gets at least one connection
+        assert_eq!(per_host.connections_per_shard(16), 1);
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn pool_is_filled() {
+        use super::NodeConnections;
+        use crate::proxy::MockNode;
+        use crate::SessionBuilder;
+        use std::time::Duration;
+
+        let node = MockNode::new().run().await.unwrap();
+        let session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .pool_size(PoolSize::PerHost(NonZeroUsize::new(3).unwrap()))
+            .build()
+            .await
+            .unwrap();
+
+        // The mock node isn't shard-aware, its pool is filled after the first connection is opened
+        let cluster_data = session.get_cluster_data();
+        let mut pool_len = 0;
+        for _ in 0..100 {
+            let connections = cluster_data.all_nodes[0]
+                .connections
+                .read()
+                .unwrap()
+                .clone();
+            if let NodeConnections::Single(pool) = &*connections {
+                pool_len = pool.len();
+            }
+            if pool_len == 3 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(pool_len, 3);
+
+        session.query("SELECT a FROM ks.t", &[]).await.unwrap();
+        assert!(cluster_data.all_nodes[0].is_connected());
+
+        node.finish().await.unwrap();
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn node_with_broken_pool_is_not_up() {
+        use crate::proxy::MockNode;
+        use crate::SessionBuilder;
+        use std::time::Duration;
+
+        let node = MockNode::new().run().await.unwrap();
+        let session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .build()
+            .await
+            .unwrap();
+
+        session.query("SELECT a FROM ks.t", &[]).await.unwrap();
+        let cluster_data = session.get_cluster_data();
+        assert!(cluster_data.all_nodes[0].is_up());
+
+        // All connections break once the node stops
+        node.finish().await.unwrap();
+        for _ in 0..100 {
+            if !cluster_data.all_nodes[0].is_up() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(!cluster_data.all_nodes[0].is_up());
+        assert!(!cluster_data.all_nodes[0].is_down());
+    }
+}
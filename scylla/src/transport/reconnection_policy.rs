@@ -0,0 +1,200 @@
+//! Policies deciding how long to wait between attempts to reopen a broken connection
+
+use rand::Rng;
+use std::time::Duration;
+
+/// Decides how often the driver tries to reopen broken connections, ex. to a node which is down
+pub trait ReconnectionPolicy: Send + Sync {
+    /// Called when a connection breaks, the schedule is used until the connection is reopened
+    fn new_schedule(&self) -> Box<dyn ReconnectionSchedule>;
+}
+
+/// Delays before the following attempts to reopen a single connection
+pub trait ReconnectionSchedule: Send + Sync {
+    /// Returns the delay before the next attempt
+    fn next_delay(&mut self) -> Duration;
+}
+
+/// Waits the same delay before each attempt
+#[derive(Debug, Clone)]
+pub struct ConstantReconnectionPolicy {
+    delay: Duration,
+}
+
+impl ConstantReconnectionPolicy {
+    pub fn new(delay: Duration) -> Self {
+        ConstantReconnectionPolicy { delay }
+    }
+}
+
+impl ReconnectionPolicy for ConstantReconnectionPolicy {
+    fn new_schedule(&self) -> Box<dyn ReconnectionSchedule> {
+        Box::new(ConstantReconnectionSchedule { delay: self.delay })
+    }
+}
+
+struct ConstantReconnectionSchedule {
+    delay: Duration,
+}
+
+impl ReconnectionSchedule for ConstantReconnectionSchedule {
+    fn next_delay(&mut self) -> Duration {
+        self.delay
+    }
+}
+
+/// Doubles the delay after each failed attempt, starting from `base_delay`, up to `max_delay`.
+/// Each delay is randomly changed by up to 15%, so that connections broken at the same time
+/// aren't reopened at the same time.
+/// By default the delays go from 1 second up to 1 minute
+#[derive(Debug, Clone)]
+pub struct ExponentialReconnectionPolicy {
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl ExponentialReconnectionPolicy {
+    /// # Panics
+    /// Panics if `base_delay` is zero or larger than `max_delay`
+    pub fn new(base_delay: Duration, max_delay: Duration) -> Self {
+        assert!(
+            base_delay > Duration::from_secs(0),
+            "base delay must be positive"
+        );
+        assert!(
+            base_delay <= max_delay,
+            "base delay must not be larger than max delay"
+        );
+        ExponentialReconnectionPolicy {
+            base_delay,
+            max_delay,
+        }
+    }
+}
+
+impl Default for ExponentialReconnectionPolicy {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1), Duration::from_secs(60))
+    }
+}
+
+impl ReconnectionPolicy for ExponentialReconnectionPolicy {
+    fn new_schedule(&self) -> Box<dyn ReconnectionSchedule> {
+        Box::new(ExponentialReconnectionSchedule {
+            next_delay: self.base_delay,
+            max_delay: self.max_delay,
+        })
+    }
+}
+
+struct ExponentialReconnectionSchedule {
+    next_delay: Duration,
+    max_delay: Duration,
+}
+
+impl ReconnectionSchedule for ExponentialReconnectionSchedule {
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.next_delay;
+        self.next_delay = delay
+            .checked_mul(2)
+            .map_or(self.max_delay, |d| d.min(self.max_delay));
+
+        // Jitter could overflow a delay close to Duration::MAX
+        let jitter: f64 = rand::thread_rng().gen_range(0.85..=1.15);
+        Duration::try_from_secs_f64(delay.as_secs_f64() * jitter)
+            .map_or(self.max_delay, |d| d.min(self.max_delay))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConstantReconnectionPolicy, ExponentialReconnectionPolicy, ReconnectionPolicy};
+    use std::time::Duration;
+
+    #[test]
+    fn constant_delays() {
+        let mut schedule = ConstantReconnectionPolicy::new(Duration::from_secs(3)).new_schedule();
+        for _ in 0..5 {
+            assert_eq!(schedule.next_delay(), Duration::from_secs(3));
+        }
+    }
+
+    #[test]
+    fn exponential_delays() {
+        let policy =
+            ExponentialReconnectionPolicy::new(Duration::from_secs(1), Duration::from_secs(10));
+        let mut schedule = policy.new_schedule();
+
+        let in_range = |delay: Duration, expected: f64| {
+            delay.as_secs_f64() >= expected * 0.85 && delay.as_secs_f64() <= expected * 1.15
+        };
+        assert!(in_range(schedule.next_delay(), 1.0));
+        assert!(in_range(schedule.next_delay(), 2.0));
+        assert!(in_range(schedule.next_delay(), 4.0));
+        assert!(in_range(schedule.next_delay(), 8.0));
+        for _ in 0..100 {
+            let delay = schedule.next_delay();
+            assert!(delay <= Duration::from_secs(10));
+            assert!(in_range(delay, 10.0));
+        }
+
+        // Each schedule starts from the base delay
+        assert!(in_range(policy.new_schedule().next_delay(), 1.0));
+    }
+
+    #[test]
+    fn exponential_delays_dont_overflow() {
+        let mut schedule =
+            ExponentialReconnectionPolicy::new(Duration::from_secs(1), Duration::MAX)
+                .new_schedule();
+        for _ in 0..100 {
+            schedule.next_delay();
+        }
+        assert!(schedule.next_delay() >= Duration::MAX.mul_f64(0.85));
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn connections_are_reopened() {
+        use crate::proxy::MockNode;
+        use crate::SessionBuilder;
+        use std::sync::Arc;
+
+        let node = MockNode::new().run().await.unwrap();
+        let addr = node.addr();
+        let session = SessionBuilder::new()
+            .known_node_addr(addr)
+            .reconnection_policy(Arc::new(ConstantReconnectionPolicy::new(
+                Duration::from_millis(10),
+            )))
+            .build()
+            .await
+            .unwrap();
+        session.query("SELECT a FROM ks.t", &[]).await.unwrap();
+
+        let cluster_data = session.get_cluster_data();
+        let wait_until_connected = |connected: bool| {
+            let node = cluster_data.all_nodes[0].clone();
+            async move {
+                for _ in 0..50 {
+                    if node.is_connected() == connected {
+                        return;
+                    }
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                panic!("Node connected: {}, expected {}", !connected, connected);
+            }
+        };
+
+        node.finish().await.unwrap();
+        wait_until_connected(false).await;
+
+        // The node comes back at the same address, with the default policy
+        // reconnecting would take at least a second
+        let node = MockNode::new().listen_addr(addr).run().await.unwrap();
+        wait_until_connected(true).await;
+        session.query("SELECT a FROM ks.t", &[]).await.unwrap();
+
+        node.finish().await.unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+//! Replica sets of the ring's vnodes, computed using replication strategies of keyspaces
+
+use crate::routing::Token;
+use crate::transport::cluster::ClusterData;
+use crate::transport::node::Node;
+use crate::transport::topology::Strategy;
+
+use itertools::Itertools;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Replicas of each vnode of the ring for a single replication strategy
+pub(crate) struct ReplicaSets {
+    // Replicas of the vnode ending at each token of the ring, in preference order
+    replicas: BTreeMap<Token, Vec<Arc<Node>>>,
+}
+
+impl ReplicaSets {
+    fn new(cluster: &ClusterData, strategy: &Strategy) -> Self {
+        let replicas = cluster
+            .ring
+            .keys()
+            .map(|token| {
+                let replicas = match strategy {
+                    Strategy::SimpleStrategy { replication_factor } => {
+                        simple_strategy_replicas(cluster, token, *replication_factor)
+                    }
+                    Strategy::NetworkTopologyStrategy {
+                        datacenter_repfactors,
+                    } => network_topology_strategy_replicas(cluster, token, datacenter_repfactors),
+                    _ => simple_strategy_replicas(cluster, token, 1),
+                };
+                (token.clone(), replicas)
+            })
+            .collect();
+
+        ReplicaSets { replicas }
+    }
+
+    /// Returns replicas of the vnode in which `token` lies
+    pub(crate) fn replicas(&self, token: &Token) -> &[Arc<Node>] {
+        self.replicas
+            .range(token..)
+            .next()
+            .or_else(|| self.replicas.iter().next())
+            .map(|(_, replicas)| replicas.as_slice())
+            .unwrap_or(&[])
+    }
+}
+
+/// Computes replica sets for keyspaces which use SimpleStrategy or NetworkTopologyStrategy.
+/// Keyspaces with the same strategy share their replica sets.
+// Replica sets of `previous` are reused for strategies it already computed,
+// it has to have the same ring and nodes as `cluster`
+pub(crate) fn compute_replica_sets(
+    cluster: &ClusterData,
+    previous: Option<&ClusterData>,
+) -> HashMap<String, Arc<ReplicaSets>> {
+    let mut computed: Vec<(&Strategy, Arc<ReplicaSets>)> = Vec::new();
+    let mut result = HashMap::new();
+
+    if let Some(previous) = previous {
+        for (keyspace_name, replica_sets) in &previous.replica_sets {
+            if let Some(keyspace) = previous.keyspaces.get(keyspace_name) {
+                computed.push((&keyspace.strategy, replica_sets.clone()));
+            }
+        }
+    }
+
+    for (keyspace_name, keyspace) in &cluster.keyspaces {
+        let strategy = &keyspace.strategy;
+        match strategy {
+            Strategy::SimpleStrategy { .. } | Strategy::NetworkTopologyStrategy { .. } => {}
+            // Other strategies use only the owner of the token
+            _ => continue,
+        }
+
+        let replica_sets = match computed.iter().find(|(s, _)| *s == strategy) {
+            Some((_, replica_sets)) => replica_sets.clone(),
+            None => {
+                let replica_sets = Arc::new(ReplicaSets::new(cluster, strategy));
+                computed.push((strategy, replica_sets.clone()));
+                replica_sets
+            }
+        };
+        result.insert(keyspace_name.clone(), replica_sets);
+    }
+
+    result
+}
+
+fn simple_strategy_replicas(
+    cluster: &ClusterData,
+    token: &Token,
+    replication_factor: usize,
+) -> Vec<Arc<Node>> {
+    cluster
+        .ring_range(token)
+        .unique()
+        .take(replication_factor)
+        .collect()
+}
+
+fn network_topology_strategy_replicas(
+    cluster: &ClusterData,
+    token: &Token,
+    datacenter_repfactors: &HashMap<String, usize>,
+) -> Vec<Arc<Node>> {
+    let mut acceptable_repeats = datacenter_repfactors
+        .iter()
+        .map(|(dc_name, repfactor)| {
+            let rack_count = cluster
+                .datacenters
+                .get(dc_name)
+                .map(|dc| dc.rack_count)
+                .unwrap_or(0);
+
+            (dc_name.as_str(), repfactor.saturating_sub(rack_count))
+        })
+        .collect::<HashMap<&str, usize>>();
+
+    // A datacenter can't have more replicas than nodes, the ring isn't searched for the missing ones
+    let desired_result_len: usize = datacenter_repfactors
+        .iter()
+        .map(|(dc_name, repfactor)| {
+            let nodes_count = cluster
+                .datacenters
+                .get(dc_name)
+                .map(|dc| dc.nodes.len())
+                .unwrap_or(0);
+            (*repfactor).min(nodes_count)
+        })
+        .sum();
+
+    let mut result: Vec<Arc<Node>> = Vec::with_capacity(desired_result_len);
+    for node in cluster.ring_range(token).unique() {
+        if result.len() == desired_result_len {
+            break;
+        }
+
+        let current_node_dc = match &node.datacenter {
+            None => continue,
+            Some(dc) => dc,
+        };
+
+        let repfactor = match datacenter_repfactors.get(current_node_dc) {
+            None => continue,
+            Some(r) => r,
+        };
+
+        let picked_nodes_from_current_dc = || {
+            result
+                .iter()
+                .filter(|node| node.datacenter.as_ref() == Some(current_node_dc))
+        };
+
+        if *repfactor == picked_nodes_from_current_dc().count() {
+            // found enough nodes in this datacenter
+            continue;
+        }
+
+        let current_node_rack = node.rack.as_ref();
+        let current_node_rack_count = picked_nodes_from_current_dc()
+            .filter(|node| node.rack.as_ref() == current_node_rack)
+            .count();
+
+        if current_node_rack_count == 0 {
+            // new rack
+            result.push(node.clone());
+        } else {
+            // we’ve already found a node in this rack
+
+            // unwrap, because we already know repfactor
+            let repeats = acceptable_repeats
+                .get_mut(current_node_dc.as_str())
+                .unwrap();
+            if *repeats > 0 {
+                // we must pick multiple nodes in the same rack
+                *repeats -= 1;
+                result.push(node.clone());
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routing::Token;
+    use crate::transport::{ClusterData, ClusterDataBuilder, Strategy};
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    fn addr(id: u8) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, id], 9042))
+    }
+
+    fn replica_ids(cluster: &ClusterData, keyspace: Option<&str>, token: i64) -> Vec<u8> {
+        cluster
+            .replicas(keyspace, &Token::Murmur3(token))
+            .iter()
+            .map(|node| match node.address.ip() {
+                std::net::IpAddr::V4(ip) => ip.octets()[3],
+                std::net::IpAddr::V6(_) => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn replicas_of_tokens() {
+        let nts = Strategy::NetworkTopologyStrategy {
+            datacenter_repfactors: vec![("dc1".to_string(), 2), ("dc2".to_string(), 5)]
+                .into_iter()
+                .collect::<HashMap<_, _>>(),
+        };
+        let cluster = ClusterDataBuilder::new()
+            .node(addr(1), "dc1", "r1", vec![100, 400])
+            .node(addr(2), "dc1", "r2", vec![200])
+            .node(addr(3), "dc2", "r1", vec![300])
+            .node(addr(4), "dc1", "r1", vec![500])
+            .keyspace(
+                "simple",
+                Strategy::SimpleStrategy {
+                    replication_factor: 2,
+                },
+            )
+            .keyspace(
+                "simple_too",
+                Strategy::SimpleStrategy {
+                    replication_factor: 2,
+                },
+            )
+            .keyspace("nts", nts)
+            .keyspace("local", Strategy::LocalStrategy)
+            .build();
+
+        assert_eq!(replica_ids(&cluster, Some("simple"), 150), vec![2, 3]);
+        assert_eq!(replica_ids(&cluster, Some("simple"), 200), vec![2, 3]);
+        assert_eq!(replica_ids(&cluster, Some("simple"), 450), vec![4, 1]);
+        // Tokens after the last vnode wrap around the ring
+        assert_eq!(replica_ids(&cluster, Some("simple"), 600), vec![1, 2]);
+
+        // Keyspaces with the same strategy share replica sets
+        assert!(Arc::ptr_eq(
+            &cluster.replica_sets["simple"],
+            &cluster.replica_sets["simple_too"]
+        ));
+
+        // dc2 has only one node, replicas in dc1 are in different racks
+        assert_eq!(replica_ids(&cluster, Some("nts"), 250), vec![3, 1, 2]);
+        assert_eq!(replica_ids(&cluster, Some("nts"), 450), vec![4, 2, 3]);
+
+        // Other strategies, unknown keyspaces and statements without keyspace use the owner only
+        assert_eq!(replica_ids(&cluster, Some("local"), 150), vec![2]);
+        assert_eq!(replica_ids(&cluster, Some("unknown"), 150), vec![2]);
+        assert_eq!(replica_ids(&cluster, None, 600), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn empty_ring_has_no_replicas() {
+        let cluster = ClusterDataBuilder::new()
+            .keyspace(
+                "simple",
+                Strategy::SimpleStrategy {
+                    replication_factor: 2,
+                },
+            )
+            .build();
+
+        assert!(replica_ids(&cluster, Some("simple"), 0).is_empty());
+        assert!(replica_ids(&cluster, None, 0).is_empty());
+    }
+}
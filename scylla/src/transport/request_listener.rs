@@ -0,0 +1,211 @@
+//! Hooks notified about the execution of requests, e.g. to feed an external metrics system
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use super::errors::QueryError;
+use super::retry_policy::RetryDecision;
+
+/// Kind of a request executed by [`Session`](crate::Session)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RequestKind {
+    /// [`Session::query`](crate::Session::query) and [`Session::query_paged`](crate::Session::query_paged)
+    Query,
+    /// [`Session::execute`](crate::Session::execute) and [`Session::execute_paged`](crate::Session::execute_paged)
+    Execute,
+    /// [`Session::batch`](crate::Session::batch)
+    Batch,
+    /// Fetching pages of [`Session::query_iter`](crate::Session::query_iter)
+    QueryIter,
+    /// Fetching pages of [`Session::execute_iter`](crate::Session::execute_iter)
+    ExecuteIter,
+    /// Requests sent by the driver itself, e.g. fetching the schema version
+    Internal,
+}
+
+/// Describes the request which a [`RequestListener`] is notified about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestInfo<'a> {
+    pub kind: RequestKind,
+    /// Text of the statement, `None` for batches and internal requests
+    pub statement: Option<&'a str>,
+}
+
+/// Receives events about requests executed by [`Session`](crate::Session),
+/// set with [`SessionBuilder::request_listener`](crate::transport::session_builder::SessionBuilder::request_listener).
+///
+/// A request is sent to nodes chosen by the load balancing policy, each of them is an attempt.
+/// Failed attempts are retried as decided by the retry policy.
+/// Iterators make an attempt for each page, so they report success for each received page.
+///
+/// Methods are called on the hot path of the driver, so they should be quick and must not block,
+/// e.g. only update counters. All methods do nothing by default.
+///
+/// # Example
+/// ```
+/// use scylla::transport::request_listener::{RequestInfo, RequestListener};
+/// use std::net::SocketAddr;
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use std::time::Duration;
+///
+/// #[derive(Default)]
+/// struct SlowRequestCounter {
+///     slow_requests: AtomicU64,
+/// }
+///
+/// impl RequestListener for SlowRequestCounter {
+///     fn on_success(&self, _request: &RequestInfo<'_>, _node: SocketAddr, latency: Duration) {
+///         if latency > Duration::from_millis(100) {
+///             self.slow_requests.fetch_add(1, Ordering::Relaxed);
+///         }
+///     }
+/// }
+/// ```
+pub trait RequestListener: Send + Sync {
+    /// Called once when the session starts executing the request, before choosing a node
+    fn on_request_start(&self, _request: &RequestInfo<'_>) {}
+
+    /// Called before sending the request to `node`
+    fn on_attempt(&self, _request: &RequestInfo<'_>, _node: SocketAddr) {}
+
+    /// Called after an attempt failed, with the decision of the retry policy
+    fn on_retry_decision(
+        &self,
+        _request: &RequestInfo<'_>,
+        _node: SocketAddr,
+        _error: &QueryError,
+        _decision: &RetryDecision,
+    ) {
+    }
+
+    /// Called when `node` responded successfully, `latency` is the time of the attempt
+    fn on_success(&self, _request: &RequestInfo<'_>, _node: SocketAddr, _latency: Duration) {}
+
+    /// Called once when the request failed and won't be retried anymore
+    fn on_error(&self, _request: &RequestInfo<'_>, _error: &QueryError) {}
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn listener_receives_request_events() {
+        use super::{RequestInfo, RequestKind, RequestListener};
+        use crate::frame::response::result::ColumnType;
+        use crate::proxy::{MockNode, MockResponse, MockRule};
+        use crate::query::Query;
+        use crate::transport::errors::{DbError, QueryError};
+        use crate::transport::retry_policy::RetryDecision;
+        use crate::SessionBuilder;
+        use std::net::SocketAddr;
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+
+        #[derive(Default)]
+        struct EventRecorder {
+            events: Mutex<Vec<String>>,
+        }
+
+        impl EventRecorder {
+            fn record(&self, request: &RequestInfo<'_>, event: String) {
+                if request.kind != RequestKind::Internal {
+                    self.events
+                        .lock()
+                        .unwrap()
+                        .push(format!("{:?} {}", request.kind, event));
+                }
+            }
+
+            fn take(&self) -> Vec<String> {
+                std::mem::take(&mut self.events.lock().unwrap())
+            }
+        }
+
+        impl RequestListener for EventRecorder {
+            fn on_request_start(&self, request: &RequestInfo<'_>) {
+                self.record(request, format!("start {}", request.statement.unwrap()));
+            }
+
+            fn on_attempt(&self, request: &RequestInfo<'_>, _node: SocketAddr) {
+                self.record(request, "attempt".to_string());
+            }
+
+            fn on_retry_decision(
+                &self,
+                request: &RequestInfo<'_>,
+                _node: SocketAddr,
+                _error: &QueryError,
+                decision: &RetryDecision,
+            ) {
+                self.record(request, format!("{:?}", decision));
+            }
+
+            fn on_success(&self, request: &RequestInfo<'_>, _node: SocketAddr, _latency: Duration) {
+                self.record(request, "success".to_string());
+            }
+
+            fn on_error(&self, request: &RequestInfo<'_>, _error: &QueryError) {
+                self.record(request, "error".to_string());
+            }
+        }
+
+        let node = MockNode::new()
+            .rule(MockRule::new(
+                "INSERT",
+                MockResponse::error(DbError::Overloaded, "overloaded"),
+            ))
+            .rule(MockRule::new(
+                "SELECT a FROM ks.t",
+                MockResponse::rows(&[("a", ColumnType::Int)], vec![]),
+            ))
+            .default_response(MockResponse::Void)
+            .run()
+            .await
+            .unwrap();
+        let recorder = Arc::new(EventRecorder::default());
+        let session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .request_listener(recorder.clone())
+            .build()
+            .await
+            .unwrap();
+        recorder.take();
+
+        session.query("UPDATE ks.t SET a = 1", &[]).await.unwrap();
+        assert_eq!(
+            recorder.take(),
+            vec![
+                "Query start UPDATE ks.t SET a = 1",
+                "Query attempt",
+                "Query success"
+            ]
+        );
+
+        // The default policy retries on the next node, there is none, so the request fails
+        let mut insert = Query::new("INSERT INTO ks.t (a) VALUES (1)".to_string());
+        insert.set_is_idempotent(true);
+        session.query(insert, &[]).await.unwrap_err();
+        assert_eq!(
+            recorder.take(),
+            vec![
+                "Query start INSERT INTO ks.t (a) VALUES (1)",
+                "Query attempt",
+                "Query RetryNextNode(None)",
+                "Query error"
+            ]
+        );
+
+        let mut rows = session.query_iter("SELECT a FROM ks.t", &[]).await.unwrap();
+        while rows.next().await.is_some() {}
+        assert_eq!(
+            recorder.take(),
+            vec![
+                "QueryIter start SELECT a FROM ks.t",
+                "QueryIter attempt",
+                "QueryIter success"
+            ]
+        );
+
+        node.finish().await.unwrap();
+    }
+}
@@ -2,6 +2,9 @@
 //! To decide when to retry a query the `Session` can use any object which implements
 //! the `RetryPolicy` trait
 
+use std::sync::Arc;
+use std::time::Duration;
+
 use crate::statement::Consistency;
 use crate::transport::errors::{DbError, QueryError, WriteType};
 
@@ -17,26 +20,21 @@ pub struct QueryInfo<'a> {
     pub consistency: Consistency,
 }
 
+/// What to do after a query failed. Retries wait for the given delay first, if any
 #[derive(Debug, PartialEq, Eq)]
 pub enum RetryDecision {
-    RetrySameNode,
-    RetryNextNode,
+    RetrySameNode(Option<Duration>),
+    RetryNextNode(Option<Duration>),
+    /// Retry on the same node with the given consistency, used by later retries too
+    RetryWithConsistency(Consistency),
     DontRetry,
 }
 
-/// Specifies a policy used to decide when to retry a query
+/// Specifies a policy used to decide when to retry a query.
+/// Policies are shared using `Arc`, so a single instance can be used by many statements.
 pub trait RetryPolicy: Send + Sync {
     /// Called for each new query, starts a session of deciding about retries
     fn new_session(&self) -> Box<dyn RetrySession>;
-
-    /// Used to clone this RetryPolicy
-    fn clone_boxed(&self) -> Box<dyn RetryPolicy>;
-}
-
-impl Clone for Box<dyn RetryPolicy> {
-    fn clone(&self) -> Box<dyn RetryPolicy> {
-        self.clone_boxed()
-    }
 }
 
 /// Used throughout a single query to decide when to retry it
@@ -69,10 +67,6 @@ impl RetryPolicy for FallthroughRetryPolicy {
     fn new_session(&self) -> Box<dyn RetrySession> {
         Box::new(FallthroughRetrySession)
     }
-
-    fn clone_boxed(&self) -> Box<dyn RetryPolicy> {
-        Box::new(FallthroughRetryPolicy)
-    }
 }
 
 impl RetrySession for FallthroughRetrySession {
@@ -103,10 +97,6 @@ impl RetryPolicy for DefaultRetryPolicy {
     fn new_session(&self) -> Box<dyn RetrySession> {
         Box::new(DefaultRetrySession::new())
     }
-
-    fn clone_boxed(&self) -> Box<dyn RetryPolicy> {
-        Box::new(DefaultRetryPolicy)
-    }
 }
 
 pub struct DefaultRetrySession {
@@ -141,7 +131,7 @@ impl RetrySession for DefaultRetrySession {
             | QueryError::DbError(DbError::ServerError, _)
             | QueryError::DbError(DbError::TruncateError, _) => {
                 if query_info.is_idempotent {
-                    RetryDecision::RetryNextNode
+                    RetryDecision::RetryNextNode(None)
                 } else {
                     RetryDecision::DontRetry
                 }
@@ -154,7 +144,7 @@ impl RetrySession for DefaultRetrySession {
             QueryError::DbError(DbError::Unavailable { .. }, _) => {
                 if !self.was_unavailable_retry {
                     self.was_unavailable_retry = true;
-                    RetryDecision::RetryNextNode
+                    RetryDecision::RetryNextNode(None)
                 } else {
                     RetryDecision::DontRetry
                 }
@@ -176,7 +166,7 @@ impl RetrySession for DefaultRetrySession {
             ) => {
                 if !self.was_read_timeout_retry && received >= required && *data_present {
                     self.was_read_timeout_retry = true;
-                    RetryDecision::RetrySameNode
+                    RetryDecision::RetrySameNode(None)
                 } else {
                     RetryDecision::DontRetry
                 }
@@ -191,13 +181,15 @@ impl RetrySession for DefaultRetrySession {
                     && *write_type == WriteType::BatchLog
                 {
                     self.was_write_timeout_retry = true;
-                    RetryDecision::RetrySameNode
+                    RetryDecision::RetrySameNode(None)
                 } else {
                     RetryDecision::DontRetry
                 }
             }
             // The node is still bootstrapping it can't execute the query, we should try another one
-            QueryError::DbError(DbError::IsBootstrapping, _) => RetryDecision::RetryNextNode,
+            QueryError::DbError(DbError::IsBootstrapping, _) => RetryDecision::RetryNextNode(None),
+            // The connection is busy, the query wasn't sent - another node can execute it
+            QueryError::UnableToAllocStreamId => RetryDecision::RetryNextNode(None),
             // In all other cases propagate the error to the user
             _ => RetryDecision::DontRetry,
         }
@@ -208,13 +200,232 @@ impl RetrySession for DefaultRetrySession {
     }
 }
 
+/// Like [`DefaultRetryPolicy`], but when not enough replicas responded or are alive,
+/// retries once with lower consistency, which the available replicas can satisfy.
+/// Behaviour based on the `DowngradingConsistencyRetryPolicy` of DataStax Java Driver.
+///
+/// Reads and writes might then be done with lower consistency than requested,
+/// so use it only when returning stale data is better than returning an error.
+#[derive(Debug, Default)]
+pub struct DowngradingConsistencyRetryPolicy;
+
+impl DowngradingConsistencyRetryPolicy {
+    pub fn new() -> DowngradingConsistencyRetryPolicy {
+        DowngradingConsistencyRetryPolicy
+    }
+}
+
+impl RetryPolicy for DowngradingConsistencyRetryPolicy {
+    fn new_session(&self) -> Box<dyn RetrySession> {
+        Box::new(DowngradingConsistencyRetrySession::new())
+    }
+}
+
+pub struct DowngradingConsistencyRetrySession {
+    was_retry: bool,
+    default_session: DefaultRetrySession,
+}
+
+impl DowngradingConsistencyRetrySession {
+    pub fn new() -> DowngradingConsistencyRetrySession {
+        DowngradingConsistencyRetrySession {
+            was_retry: false,
+            default_session: DefaultRetrySession::new(),
+        }
+    }
+
+    // Highest consistency which can be satisfied when only `replicas` replicas respond
+    fn max_likely_to_work(current: Consistency, replicas: i32) -> Option<Consistency> {
+        match replicas {
+            _ if current == Consistency::EachQuorum && replicas > 0 => {
+                Some(Consistency::LocalQuorum)
+            }
+            r if r >= 3 => Some(Consistency::Three),
+            2 => Some(Consistency::Two),
+            1 => Some(Consistency::One),
+            _ => None,
+        }
+    }
+
+    // Makes sure that only one retry is done
+    fn retry_once(&mut self, decision: RetryDecision) -> RetryDecision {
+        if self.was_retry {
+            return RetryDecision::DontRetry;
+        }
+        self.was_retry = true;
+        decision
+    }
+
+    // Retries once with the given consistency
+    fn downgrade(&mut self, consistency: Option<Consistency>) -> RetryDecision {
+        match consistency {
+            Some(consistency) => self.retry_once(RetryDecision::RetryWithConsistency(consistency)),
+            None => RetryDecision::DontRetry,
+        }
+    }
+}
+
+impl Default for DowngradingConsistencyRetrySession {
+    fn default() -> DowngradingConsistencyRetrySession {
+        DowngradingConsistencyRetrySession::new()
+    }
+}
+
+impl RetrySession for DowngradingConsistencyRetrySession {
+    fn decide_should_retry(&mut self, query_info: QueryInfo) -> RetryDecision {
+        let consistency = query_info.consistency;
+        let is_serial = matches!(consistency, Consistency::Serial | Consistency::LocalSerial);
+
+        match query_info.error {
+            // Serial consistency can't be downgraded, the default behaviour is used
+            QueryError::DbError(DbError::Unavailable { .. }, _)
+            | QueryError::DbError(DbError::ReadTimeout { .. }, _)
+                if is_serial =>
+            {
+                self.default_session.decide_should_retry(query_info)
+            }
+            // Not enough replicas are alive, retry with as many as there are
+            QueryError::DbError(DbError::Unavailable { alive, .. }, _) => {
+                self.downgrade(Self::max_likely_to_work(consistency, *alive))
+            }
+            // Not enough replicas responded - retry with as many as responded.
+            // If enough responded, but without data - retry once with the same consistency
+            QueryError::DbError(
+                DbError::ReadTimeout {
+                    received,
+                    required,
+                    data_present,
+                    ..
+                },
+                _,
+            ) => {
+                if received < required {
+                    self.downgrade(Self::max_likely_to_work(consistency, *received))
+                } else if !data_present {
+                    self.retry_once(RetryDecision::RetrySameNode(None))
+                } else {
+                    RetryDecision::DontRetry
+                }
+            }
+            // Writes are retried only if they are idempotent. Parts of an unlogged batch might have
+            // been applied, it's retried with as many replicas as responded
+            QueryError::DbError(
+                DbError::WriteTimeout {
+                    received,
+                    write_type,
+                    ..
+                },
+                _,
+            ) if query_info.is_idempotent => match write_type {
+                WriteType::UnloggedBatch => {
+                    self.downgrade(Self::max_likely_to_work(consistency, *received))
+                }
+                WriteType::BatchLog => self.retry_once(RetryDecision::RetrySameNode(None)),
+                _ => RetryDecision::DontRetry,
+            },
+            QueryError::DbError(DbError::WriteTimeout { .. }, _) => RetryDecision::DontRetry,
+            _ => self.default_session.decide_should_retry(query_info),
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = DowngradingConsistencyRetrySession::new();
+    }
+}
+
+/// Wraps another retry policy and delays its retries exponentially: the first retry
+/// waits `base_delay`, each next one twice as long, up to `max_delay`.
+/// Delays chosen by the wrapped policy are kept.
+///
+/// # Example
+/// ```
+/// use scylla::transport::retry_policy::{DefaultRetryPolicy, ExponentialBackoffRetryPolicy};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let policy = ExponentialBackoffRetryPolicy::new(
+///     Arc::new(DefaultRetryPolicy::new()),
+///     Duration::from_millis(10),
+///     Duration::from_secs(1),
+/// );
+/// ```
+pub struct ExponentialBackoffRetryPolicy {
+    inner: Arc<dyn RetryPolicy>,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl ExponentialBackoffRetryPolicy {
+    /// # Panics
+    /// Panics if `base_delay` is larger than `max_delay`
+    pub fn new(inner: Arc<dyn RetryPolicy>, base_delay: Duration, max_delay: Duration) -> Self {
+        assert!(
+            base_delay <= max_delay,
+            "base delay must not be larger than max delay"
+        );
+        ExponentialBackoffRetryPolicy {
+            inner,
+            base_delay,
+            max_delay,
+        }
+    }
+}
+
+impl RetryPolicy for ExponentialBackoffRetryPolicy {
+    fn new_session(&self) -> Box<dyn RetrySession> {
+        Box::new(ExponentialBackoffRetrySession {
+            inner: self.inner.new_session(),
+            base_delay: self.base_delay,
+            max_delay: self.max_delay,
+            next_delay: self.base_delay,
+        })
+    }
+}
+
+struct ExponentialBackoffRetrySession {
+    inner: Box<dyn RetrySession>,
+    base_delay: Duration,
+    max_delay: Duration,
+    next_delay: Duration,
+}
+
+impl ExponentialBackoffRetrySession {
+    fn delay(&mut self, inner_delay: Option<Duration>) -> Option<Duration> {
+        let delay = self.next_delay;
+        self.next_delay = delay
+            .checked_mul(2)
+            .map_or(self.max_delay, |d| d.min(self.max_delay));
+        Some(inner_delay.unwrap_or(delay))
+    }
+}
+
+impl RetrySession for ExponentialBackoffRetrySession {
+    fn decide_should_retry(&mut self, query_info: QueryInfo) -> RetryDecision {
+        match self.inner.decide_should_retry(query_info) {
+            RetryDecision::RetrySameNode(delay) => RetryDecision::RetrySameNode(self.delay(delay)),
+            RetryDecision::RetryNextNode(delay) => RetryDecision::RetryNextNode(self.delay(delay)),
+            other => other,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.next_delay = self.base_delay;
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{DefaultRetryPolicy, QueryInfo, RetryDecision, RetryPolicy};
+    use super::{
+        DefaultRetryPolicy, DowngradingConsistencyRetryPolicy, ExponentialBackoffRetryPolicy,
+        FallthroughRetryPolicy, QueryInfo, RetryDecision, RetryPolicy,
+    };
     use crate::statement::Consistency;
-    use crate::transport::errors::{BadQuery, DbError, QueryError, WriteType};
+    use crate::transport::errors::{BadQuery, DbError, ProtocolError, QueryError, WriteType};
+    use bytes::Bytes;
     use std::io::ErrorKind;
     use std::sync::Arc;
+    use std::time::Duration;
 
     fn make_query_info(error: &QueryError, is_idempotent: bool) -> QueryInfo<'_> {
         QueryInfo {
@@ -270,7 +481,15 @@ mod tests {
                 numfailures: 1,
                 write_type: WriteType::BatchLog,
             },
-            DbError::Unprepared,
+            DbError::CdcWriteFailure,
+            DbError::CasWriteUnknown {
+                consistency: Consistency::Serial,
+                received: 1,
+                required: 2,
+            },
+            DbError::Unprepared {
+                statement_id: Bytes::from_static(b"id"),
+            },
             DbError::ProtocolError,
             DbError::Other(0x124816),
         ];
@@ -280,7 +499,9 @@ mod tests {
         }
 
         default_policy_assert_never_retries(QueryError::BadQuery(BadQuery::ValueLenMismatch(1, 2)));
-        default_policy_assert_never_retries(QueryError::ProtocolError("test"));
+        default_policy_assert_never_retries(QueryError::ProtocolError(
+            ProtocolError::EmptyQueryPlan,
+        ));
     }
 
     // Asserts that for this error policy retries on next on idempotent queries only
@@ -294,7 +515,7 @@ mod tests {
         let mut policy = DefaultRetryPolicy::new().new_session();
         assert_eq!(
             policy.decide_should_retry(make_query_info(&error, true)),
-            RetryDecision::RetryNextNode
+            RetryDecision::RetryNextNode(None)
         );
     }
 
@@ -320,13 +541,25 @@ mod tests {
         let mut policy = DefaultRetryPolicy::new().new_session();
         assert_eq!(
             policy.decide_should_retry(make_query_info(&error, false)),
-            RetryDecision::RetryNextNode
+            RetryDecision::RetryNextNode(None)
         );
 
         let mut policy = DefaultRetryPolicy::new().new_session();
         assert_eq!(
             policy.decide_should_retry(make_query_info(&error, true)),
-            RetryDecision::RetryNextNode
+            RetryDecision::RetryNextNode(None)
+        );
+    }
+
+    // Queries which weren't sent are retried on the next node no matter the idempotence
+    #[test]
+    fn default_unable_to_alloc_stream_id() {
+        let error = QueryError::UnableToAllocStreamId;
+
+        let mut policy = DefaultRetryPolicy::new().new_session();
+        assert_eq!(
+            policy.decide_should_retry(make_query_info(&error, false)),
+            RetryDecision::RetryNextNode(None)
         );
     }
 
@@ -345,7 +578,7 @@ mod tests {
         let mut policy_not_idempotent = DefaultRetryPolicy::new().new_session();
         assert_eq!(
             policy_not_idempotent.decide_should_retry(make_query_info(&error, false)),
-            RetryDecision::RetryNextNode
+            RetryDecision::RetryNextNode(None)
         );
         assert_eq!(
             policy_not_idempotent.decide_should_retry(make_query_info(&error, false)),
@@ -355,7 +588,7 @@ mod tests {
         let mut policy_idempotent = DefaultRetryPolicy::new().new_session();
         assert_eq!(
             policy_idempotent.decide_should_retry(make_query_info(&error, true)),
-            RetryDecision::RetryNextNode
+            RetryDecision::RetryNextNode(None)
         );
         assert_eq!(
             policy_idempotent.decide_should_retry(make_query_info(&error, true)),
@@ -381,7 +614,7 @@ mod tests {
         let mut policy = DefaultRetryPolicy::new().new_session();
         assert_eq!(
             policy.decide_should_retry(make_query_info(&enough_responses_with_data, false)),
-            RetryDecision::RetrySameNode
+            RetryDecision::RetrySameNode(None)
         );
         assert_eq!(
             policy.decide_should_retry(make_query_info(&enough_responses_with_data, false)),
@@ -392,7 +625,7 @@ mod tests {
         let mut policy = DefaultRetryPolicy::new().new_session();
         assert_eq!(
             policy.decide_should_retry(make_query_info(&enough_responses_with_data, true)),
-            RetryDecision::RetrySameNode
+            RetryDecision::RetrySameNode(None)
         );
         assert_eq!(
             policy.decide_should_retry(make_query_info(&enough_responses_with_data, true)),
@@ -475,7 +708,7 @@ mod tests {
         let mut policy = DefaultRetryPolicy::new().new_session();
         assert_eq!(
             policy.decide_should_retry(make_query_info(&good_write_type, true)),
-            RetryDecision::RetrySameNode
+            RetryDecision::RetrySameNode(None)
         );
         assert_eq!(
             policy.decide_should_retry(make_query_info(&good_write_type, true)),
@@ -507,4 +740,301 @@ mod tests {
             RetryDecision::DontRetry
         );
     }
+
+    fn make_query_info_with_consistency(
+        error: &QueryError,
+        is_idempotent: bool,
+        consistency: Consistency,
+    ) -> QueryInfo<'_> {
+        QueryInfo {
+            error,
+            is_idempotent,
+            consistency,
+        }
+    }
+
+    #[test]
+    fn downgrading_unavailable() {
+        let unavailable = |alive| {
+            QueryError::DbError(
+                DbError::Unavailable {
+                    consistency: Consistency::Quorum,
+                    required: 3,
+                    alive,
+                },
+                String::new(),
+            )
+        };
+
+        let error = unavailable(2);
+        let mut policy = DowngradingConsistencyRetryPolicy::new().new_session();
+        assert_eq!(
+            policy.decide_should_retry(make_query_info_with_consistency(
+                &error,
+                false,
+                Consistency::Quorum
+            )),
+            RetryDecision::RetryWithConsistency(Consistency::Two)
+        );
+        // Only one retry
+        assert_eq!(
+            policy.decide_should_retry(make_query_info_with_consistency(
+                &error,
+                false,
+                Consistency::Two
+            )),
+            RetryDecision::DontRetry
+        );
+
+        // No replicas are alive
+        let error = unavailable(0);
+        let mut policy = DowngradingConsistencyRetryPolicy::new().new_session();
+        assert_eq!(
+            policy.decide_should_retry(make_query_info(&error, true)),
+            RetryDecision::DontRetry
+        );
+
+        // EACH_QUORUM is downgraded to LOCAL_QUORUM
+        let error = unavailable(1);
+        let mut policy = DowngradingConsistencyRetryPolicy::new().new_session();
+        assert_eq!(
+            policy.decide_should_retry(make_query_info_with_consistency(
+                &error,
+                true,
+                Consistency::EachQuorum
+            )),
+            RetryDecision::RetryWithConsistency(Consistency::LocalQuorum)
+        );
+    }
+
+    #[test]
+    fn downgrading_read_timeout() {
+        let read_timeout = |received, data_present| {
+            QueryError::DbError(
+                DbError::ReadTimeout {
+                    consistency: Consistency::All,
+                    received,
+                    required: 5,
+                    data_present,
+                },
+                String::new(),
+            )
+        };
+
+        let error = read_timeout(4, true);
+        let mut policy = DowngradingConsistencyRetryPolicy::new().new_session();
+        assert_eq!(
+            policy.decide_should_retry(make_query_info_with_consistency(
+                &error,
+                false,
+                Consistency::All
+            )),
+            RetryDecision::RetryWithConsistency(Consistency::Three)
+        );
+
+        // Enough replicas responded, but without data
+        let error = read_timeout(5, false);
+        let mut policy = DowngradingConsistencyRetryPolicy::new().new_session();
+        assert_eq!(
+            policy.decide_should_retry(make_query_info_with_consistency(
+                &error,
+                false,
+                Consistency::All
+            )),
+            RetryDecision::RetrySameNode(None)
+        );
+
+        let error = read_timeout(5, true);
+        let mut policy = DowngradingConsistencyRetryPolicy::new().new_session();
+        assert_eq!(
+            policy.decide_should_retry(make_query_info(&error, true)),
+            RetryDecision::DontRetry
+        );
+    }
+
+    #[test]
+    fn downgrading_write_timeout() {
+        let write_timeout = |write_type| {
+            QueryError::DbError(
+                DbError::WriteTimeout {
+                    consistency: Consistency::Quorum,
+                    received: 1,
+                    required: 2,
+                    write_type,
+                },
+                String::new(),
+            )
+        };
+
+        let error = write_timeout(WriteType::UnloggedBatch);
+        let mut policy = DowngradingConsistencyRetryPolicy::new().new_session();
+        assert_eq!(
+            policy.decide_should_retry(make_query_info(&error, false)),
+            RetryDecision::DontRetry
+        );
+        assert_eq!(
+            policy.decide_should_retry(make_query_info(&error, true)),
+            RetryDecision::RetryWithConsistency(Consistency::One)
+        );
+
+        let error = write_timeout(WriteType::BatchLog);
+        let mut policy = DowngradingConsistencyRetryPolicy::new().new_session();
+        assert_eq!(
+            policy.decide_should_retry(make_query_info(&error, true)),
+            RetryDecision::RetrySameNode(None)
+        );
+
+        let error = write_timeout(WriteType::Simple);
+        let mut policy = DowngradingConsistencyRetryPolicy::new().new_session();
+        assert_eq!(
+            policy.decide_should_retry(make_query_info(&error, true)),
+            RetryDecision::DontRetry
+        );
+    }
+
+    // Other errors are handled like in the default policy
+    #[test]
+    fn downgrading_other_errors() {
+        let error = QueryError::DbError(DbError::Overloaded, String::new());
+        let mut policy = DowngradingConsistencyRetryPolicy::new().new_session();
+        assert_eq!(
+            policy.decide_should_retry(make_query_info(&error, true)),
+            RetryDecision::RetryNextNode(None)
+        );
+
+        let error = QueryError::DbError(DbError::SyntaxError, String::new());
+        let mut policy = DowngradingConsistencyRetryPolicy::new().new_session();
+        assert_eq!(
+            policy.decide_should_retry(make_query_info(&error, true)),
+            RetryDecision::DontRetry
+        );
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let policy = ExponentialBackoffRetryPolicy::new(
+            Arc::new(DefaultRetryPolicy::new()),
+            Duration::from_millis(10),
+            Duration::from_millis(30),
+        );
+        let error = QueryError::DbError(DbError::IsBootstrapping, String::new());
+        let mut session = policy.new_session();
+
+        let delays: Vec<RetryDecision> = (0..4)
+            .map(|_| session.decide_should_retry(make_query_info(&error, true)))
+            .collect();
+        assert_eq!(
+            delays,
+            vec![
+                RetryDecision::RetryNextNode(Some(Duration::from_millis(10))),
+                RetryDecision::RetryNextNode(Some(Duration::from_millis(20))),
+                RetryDecision::RetryNextNode(Some(Duration::from_millis(30))),
+                RetryDecision::RetryNextNode(Some(Duration::from_millis(30))),
+            ]
+        );
+
+        // Delays start from the base delay after reset
+        session.reset();
+        assert_eq!(
+            session.decide_should_retry(make_query_info(&error, true)),
+            RetryDecision::RetryNextNode(Some(Duration::from_millis(10)))
+        );
+
+        // Not retried errors aren't affected
+        let error = QueryError::DbError(DbError::SyntaxError, String::new());
+        assert_eq!(
+            session.decide_should_retry(make_query_info(&error, true)),
+            RetryDecision::DontRetry
+        );
+
+        // Doubling a huge delay doesn't overflow
+        let policy = ExponentialBackoffRetryPolicy::new(
+            Arc::new(DefaultRetryPolicy::new()),
+            Duration::MAX / 2 + Duration::from_secs(1),
+            Duration::MAX,
+        );
+        let error = QueryError::DbError(DbError::IsBootstrapping, String::new());
+        let mut session = policy.new_session();
+        session.decide_should_retry(make_query_info(&error, true));
+        assert_eq!(
+            session.decide_should_retry(make_query_info(&error, true)),
+            RetryDecision::RetryNextNode(Some(Duration::MAX))
+        );
+
+        let error = QueryError::DbError(DbError::SyntaxError, String::new());
+        let policy = ExponentialBackoffRetryPolicy::new(
+            Arc::new(FallthroughRetryPolicy::new()),
+            Duration::from_millis(10),
+            Duration::from_millis(30),
+        );
+        assert_eq!(
+            policy
+                .new_session()
+                .decide_should_retry(make_query_info(&error, true)),
+            RetryDecision::DontRetry
+        );
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn session_waits_before_retry() {
+        use crate::proxy::{MockNode, MockResponse, MockRule};
+        use crate::query::Query;
+        use crate::SessionBuilder;
+
+        let read_timeout = MockResponse::Error(
+            DbError::ReadTimeout {
+                consistency: Consistency::Quorum,
+                received: 2,
+                required: 2,
+                data_present: true,
+            },
+            String::new(),
+        );
+        let unavailable = MockResponse::Error(
+            DbError::Unavailable {
+                consistency: Consistency::Quorum,
+                required: 2,
+                alive: 1,
+            },
+            String::new(),
+        );
+        let node = MockNode::new()
+            .rule(MockRule::new("SELECT a FROM ks.t", read_timeout).times(1))
+            .rule(MockRule::new("SELECT b FROM ks.t", unavailable).times(1))
+            .run()
+            .await
+            .unwrap();
+        let session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .build()
+            .await
+            .unwrap();
+
+        let mut query = Query::new("SELECT a FROM ks.t".to_string());
+        query.set_retry_policy(Arc::new(ExponentialBackoffRetryPolicy::new(
+            Arc::new(DefaultRetryPolicy::new()),
+            Duration::from_millis(100),
+            Duration::from_secs(1),
+        )));
+        let start = std::time::Instant::now();
+        session.query(query, &[]).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        // Retried with lower consistency
+        let mut query = Query::new("SELECT b FROM ks.t".to_string());
+        query.set_retry_policy(Arc::new(DowngradingConsistencyRetryPolicy::new()));
+        session.query(query, &[]).await.unwrap();
+
+        assert_eq!(
+            node.received_statements(),
+            vec![
+                "SELECT a FROM ks.t",
+                "SELECT a FROM ks.t",
+                "SELECT b FROM ks.t",
+                "SELECT b FROM ks.t"
+            ]
+        );
+        node.finish().await.unwrap();
+    }
 }
@@ -0,0 +1,355 @@
+//! Destinations for rows of a paged query
+//! A [`RowIterator`](super::iterator::RowIterator) can be drained into any [`RowSink`]
+//! using [`RowIterator::drain_into`](super::iterator::RowIterator::drain_into)
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use chrono::{DateTime, NaiveTime, Utc};
+use thiserror::Error;
+
+use super::errors::QueryError;
+use crate::frame::response::result::{ColumnSpec, CqlValue, Row};
+
+/// Receives rows one by one, together with metadata describing their columns
+pub trait RowSink {
+    type Error;
+
+    /// Called once before the first row is written.
+    /// `col_specs` describe the columns of the rows, they are empty if no page was received
+    fn begin(&mut self, _col_specs: &[ColumnSpec]) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Called for each row, in the order they were received
+    fn write_row(&mut self, row: Row) -> Result<(), Self::Error>;
+
+    /// Called once after all rows were written
+    fn finish(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Collects all rows into a `Vec`
+impl RowSink for Vec<Row> {
+    type Error = std::convert::Infallible;
+
+    fn write_row(&mut self, row: Row) -> Result<(), Self::Error> {
+        self.push(row);
+        Ok(())
+    }
+}
+
+/// Error returned by [`RowIterator::drain_into`](super::iterator::RowIterator::drain_into)
+#[derive(Error, Debug)]
+pub enum DrainError<E> {
+    /// Query to fetch the next page has failed
+    #[error(transparent)]
+    QueryError(#[from] QueryError),
+
+    /// The sink failed to accept a row
+    #[error("Writing rows to the sink failed: {0}")]
+    SinkError(E),
+}
+
+/// Writes rows as CSV (RFC 4180), with a header made of column names.
+/// Nulls are written as empty fields and empty values as `""`, so the two can be told apart.
+/// Collections and user defined types are written using CQL literal syntax,
+/// e.g. `[1, 2]` or `{'a': 1}`
+///
+/// # Example
+/// ```rust
+/// # use scylla::Session;
+/// # use std::error::Error;
+/// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+/// use scylla::transport::row_sink::CsvRowSink;
+///
+/// let mut csv = CsvRowSink::new(std::io::stdout());
+/// session
+///     .query_iter("SELECT a, b FROM ks.tab", &[])
+///     .await?
+///     .drain_into(&mut csv)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct CsvRowSink<W: Write> {
+    writer: W,
+    write_header: bool,
+    line: String,
+}
+
+impl<W: Write> CsvRowSink<W> {
+    /// Creates a sink writing CSV to `writer`, header is written by default
+    pub fn new(writer: W) -> Self {
+        CsvRowSink {
+            writer,
+            write_header: true,
+            line: String::new(),
+        }
+    }
+
+    /// Sets whether the first line should contain column names
+    pub fn write_header(mut self, write_header: bool) -> Self {
+        self.write_header = write_header;
+        self
+    }
+
+    /// Returns the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_line(&mut self) -> io::Result<()> {
+        self.line.push_str("\r\n");
+        self.writer.write_all(self.line.as_bytes())?;
+        self.line.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> RowSink for CsvRowSink<W> {
+    type Error = io::Error;
+
+    fn begin(&mut self, col_specs: &[ColumnSpec]) -> io::Result<()> {
+        if !self.write_header || col_specs.is_empty() {
+            return Ok(());
+        }
+
+        for (i, spec) in col_specs.iter().enumerate() {
+            if i > 0 {
+                self.line.push(',');
+            }
+            push_csv_field(&mut self.line, spec.name());
+        }
+        self.write_line()
+    }
+
+    fn write_row(&mut self, row: Row) -> io::Result<()> {
+        let mut field = String::new();
+        for (i, column) in row.columns.iter().enumerate() {
+            if i > 0 {
+                self.line.push(',');
+            }
+            if let Some(value) = column {
+                field.clear();
+                write_value(&mut field, value, false);
+                push_csv_field(&mut self.line, &field);
+            }
+        }
+        self.write_line()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// Appends the field, quoting it if it contains characters special to CSV.
+// Empty values are quoted as well, an unquoted empty field stands for null
+fn push_csv_field(line: &mut String, field: &str) {
+    if field.is_empty() || field.contains(&[',', '"', '\r', '\n'][..]) {
+        line.push('"');
+        line.push_str(&field.replace('"', "\"\""));
+        line.push('"');
+    } else {
+        line.push_str(field);
+    }
+}
+
+// Formats the value, text nested in collections is quoted like in CQL
+fn write_value(out: &mut String, value: &CqlValue, nested: bool) {
+    // Writing to a String can't fail
+    let _ = match value {
+        CqlValue::Ascii(s) | CqlValue::Text(s) => {
+            if nested {
+                write!(out, "'{}'", s.replace('\'', "''"))
+            } else {
+                write!(out, "{}", s)
+            }
+        }
+        CqlValue::Boolean(b) => write!(out, "{}", b),
+        CqlValue::Blob(bytes) => {
+            out.push_str("0x");
+            bytes.iter().try_for_each(|b| write!(out, "{:02x}", b))
+        }
+        CqlValue::Counter(c) => write!(out, "{}", c.0),
+        CqlValue::Decimal(d) => write!(out, "{}", d),
+        CqlValue::Date(days) => match value.as_date() {
+            Some(date) => write!(out, "{}", date),
+            None => write!(out, "{}", days),
+        },
+        CqlValue::Double(d) => write!(out, "{}", d),
+        CqlValue::Duration(d) => write!(out, "{}", d),
+        CqlValue::Float(f) => write!(out, "{}", f),
+        CqlValue::Int(i) => write!(out, "{}", i),
+        CqlValue::BigInt(i) => write!(out, "{}", i),
+        CqlValue::Timestamp(since_epoch) => {
+            let epoch = DateTime::<Utc>::from(std::time::UNIX_EPOCH);
+            match epoch.checked_add_signed(*since_epoch) {
+                Some(time) => write!(out, "{}", time.format("%Y-%m-%d %H:%M:%S%.3f%z")),
+                None => write!(out, "{}", since_epoch.num_milliseconds()),
+            }
+        }
+        CqlValue::Inet(addr) => write!(out, "{}", addr),
+        CqlValue::List(elems) => write_elements(out, elems, "[", "]"),
+        CqlValue::Set(elems) => write_elements(out, elems, "{", "}"),
+        CqlValue::Tuple(elems) => write_elements(out, elems, "(", ")"),
+        CqlValue::Map(pairs) => {
+            out.push('{');
+            for (i, (key, value)) in pairs.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_value(out, key, true);
+                out.push_str(": ");
+                write_value(out, value, true);
+            }
+            out.push('}');
+            Ok(())
+        }
+        CqlValue::UserDefinedType { fields, .. } => {
+            out.push('{');
+            for (i, (name, value)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                out.push_str(name);
+                out.push_str(": ");
+                match value {
+                    Some(value) => write_value(out, value, true),
+                    None => out.push_str("null"),
+                }
+            }
+            out.push('}');
+            Ok(())
+        }
+        CqlValue::SmallInt(i) => write!(out, "{}", i),
+        CqlValue::TinyInt(i) => write!(out, "{}", i),
+        CqlValue::Time(since_midnight) => {
+            let nanos = since_midnight.num_nanoseconds().unwrap_or_default();
+            let secs = (nanos / 1_000_000_000) as u32;
+            match NaiveTime::from_num_seconds_from_midnight_opt(
+                secs,
+                (nanos % 1_000_000_000) as u32,
+            ) {
+                Some(time) => write!(out, "{}", time),
+                None => write!(out, "{}", nanos),
+            }
+        }
+        CqlValue::Timeuuid(uuid) | CqlValue::Uuid(uuid) => write!(out, "{}", uuid),
+        CqlValue::Varint(i) => write!(out, "{}", i),
+    };
+}
+
+fn write_elements(
+    out: &mut String,
+    elems: &[CqlValue],
+    open: &str,
+    close: &str,
+) -> std::fmt::Result {
+    out.push_str(open);
+    for (i, elem) in elems.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_value(out, elem, true);
+    }
+    out.push_str(close);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CsvRowSink, RowSink};
+    use crate::frame::response::result::{ColumnSpec, ColumnType, CqlValue, Row};
+    use chrono::Duration;
+
+    // Column specs of a table with columns "a" and "b, c"
+    fn col_specs() -> Vec<ColumnSpec> {
+        vec![
+            ColumnSpec::new("a", ColumnType::Int),
+            ColumnSpec::new("b, c", ColumnType::Text),
+        ]
+    }
+
+    fn write_csv(col_specs: &[ColumnSpec], rows: Vec<Vec<Option<CqlValue>>>) -> String {
+        let mut sink = CsvRowSink::new(Vec::new());
+        sink.begin(col_specs).unwrap();
+        for columns in rows {
+            sink.write_row(Row { columns }).unwrap();
+        }
+        sink.finish().unwrap();
+        String::from_utf8(sink.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn csv_header_and_quoting() {
+        let csv = write_csv(
+            &col_specs(),
+            vec![
+                vec![Some(CqlValue::Int(1)), Some(CqlValue::Text("plain".into()))],
+                vec![
+                    Some(CqlValue::Int(2)),
+                    Some(CqlValue::Text("a \"b\", c".into())),
+                ],
+                vec![Some(CqlValue::Int(3)), None],
+                vec![Some(CqlValue::Int(4)), Some(CqlValue::Text(String::new()))],
+            ],
+        );
+
+        assert_eq!(
+            csv,
+            "a,\"b, c\"\r\n1,plain\r\n2,\"a \"\"b\"\", c\"\r\n3,\r\n4,\"\"\r\n"
+        );
+    }
+
+    #[test]
+    fn csv_without_header() {
+        let mut sink = CsvRowSink::new(Vec::new()).write_header(false);
+        sink.begin(&col_specs()).unwrap();
+        sink.write_row(Row {
+            columns: vec![Some(CqlValue::Int(1)), Some(CqlValue::Boolean(true))],
+        })
+        .unwrap();
+
+        assert_eq!(sink.into_inner(), b"1,true\r\n");
+    }
+
+    #[test]
+    fn csv_value_formatting() {
+        let udt_fields = vec![
+            ("x".to_string(), Some(CqlValue::Int(1))),
+            ("y".to_string(), None),
+        ];
+
+        let csv = write_csv(
+            &[],
+            vec![vec![
+                Some(CqlValue::Blob(vec![0x01, 0xab])),
+                Some(CqlValue::List(vec![
+                    CqlValue::Text("it's".into()),
+                    CqlValue::Text("b".into()),
+                ])),
+                Some(CqlValue::Map(vec![(
+                    CqlValue::Int(1),
+                    CqlValue::Boolean(false),
+                )])),
+                Some(CqlValue::UserDefinedType {
+                    keyspace: "ks".into(),
+                    type_name: "point".into(),
+                    fields: udt_fields,
+                }),
+                Some(CqlValue::Date(1 << 31)),
+                Some(CqlValue::Timestamp(Duration::milliseconds(1_500))),
+                Some(CqlValue::Time(Duration::seconds(3_661))),
+            ]],
+        );
+
+        assert_eq!(
+            csv,
+            "0x01ab,\"['it''s', 'b']\",{1: false},\"{x: 1, y: null}\",1970-01-01,\
+             1970-01-01 00:00:01.500+0000,01:01:01\r\n"
+        );
+    }
+}
@@ -0,0 +1,456 @@
+//! Async runtime used by the driver to spawn tasks, sleep, open connections and resolve hostnames.
+//!
+//! Tokio is used by default. With the `async-std` feature the runtime is chosen on each call:
+//! tokio when the driver is used from within a tokio runtime, async-std otherwise.
+//! This keeps the feature additive, crates using the driver with tokio keep working
+//! when another crate in the same build enables it.
+//! Channels from `tokio::sync` don't depend on the tokio runtime, so they are used with both.
+
+use futures::future::{BoxFuture, Either};
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::PathBuf;
+#[cfg(feature = "async-std")]
+use std::pin::Pin;
+#[cfg(feature = "async-std")]
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+#[cfg(feature = "async-std")]
+use tokio::io::ReadBuf;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+pub(crate) trait Runtime {
+    type TcpStream: AsyncRead + AsyncWrite + Send + Unpin + 'static;
+    #[cfg(unix)]
+    type UnixStream: AsyncRead + AsyncWrite + Send + Unpin + 'static;
+
+    /// Runs the future in the background, it is not cancelled when the handle is dropped
+    fn spawn(future: impl Future<Output = ()> + Send + 'static);
+
+    /// Runs blocking code on a thread where blocking is allowed
+    fn spawn_blocking(f: impl FnOnce() + Send + 'static);
+
+    fn sleep(duration: Duration) -> BoxFuture<'static, ()>;
+
+    /// Connects to `addr`, binding the local socket to `source_port` if given.
+    /// Fails with `io::ErrorKind::TimedOut` if connecting takes longer than `timeout`
+    fn connect(
+        addr: SocketAddr,
+        source_port: Option<u16>,
+        timeout: Duration,
+    ) -> BoxFuture<'static, io::Result<Self::TcpStream>>;
+
+    /// Connects to the unix domain socket at `path`.
+    /// Fails with `io::ErrorKind::TimedOut` if connecting takes longer than `timeout`
+    #[cfg(unix)]
+    fn connect_unix(
+        path: PathBuf,
+        timeout: Duration,
+    ) -> BoxFuture<'static, io::Result<Self::UnixStream>>;
+
+    fn local_addr(stream: &Self::TcpStream) -> io::Result<SocketAddr>;
+
+    fn set_nodelay(stream: &Self::TcpStream, nodelay: bool) -> io::Result<()>;
+
+    /// Resolves `host`, which has to contain a port if `port` is `None`
+    fn lookup_host(
+        host: String,
+        port: Option<u16>,
+    ) -> BoxFuture<'static, io::Result<Vec<SocketAddr>>>;
+}
+
+#[cfg(not(feature = "async-std"))]
+pub(crate) type DefaultRuntime = TokioRuntime;
+
+#[cfg(feature = "async-std")]
+pub(crate) type DefaultRuntime = DetectedRuntime;
+
+pub(crate) fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+    DefaultRuntime::spawn(future)
+}
+
+#[cfg_attr(not(feature = "csv"), allow(dead_code))]
+pub(crate) fn spawn_blocking(f: impl FnOnce() + Send + 'static) {
+    DefaultRuntime::spawn_blocking(f)
+}
+
+pub(crate) fn sleep(duration: Duration) -> BoxFuture<'static, ()> {
+    DefaultRuntime::sleep(duration)
+}
+
+pub(crate) fn sleep_until(deadline: Instant) -> BoxFuture<'static, ()> {
+    sleep(deadline.saturating_duration_since(Instant::now()))
+}
+
+/// Returned by [`timeout`] when the future didn't complete in time
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Elapsed;
+
+/// Polls the future until it completes or `duration` passes
+pub(crate) async fn timeout<F: Future>(
+    duration: Duration,
+    future: F,
+) -> Result<F::Output, Elapsed> {
+    futures::pin_mut!(future);
+    match futures::future::select(future, sleep(duration)).await {
+        Either::Left((output, _)) => Ok(output),
+        Either::Right(_) => Err(Elapsed),
+    }
+}
+
+fn timed_out() -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, "Connecting timed out")
+}
+
+fn unspecified_addr(addr: &SocketAddr, port: u16) -> SocketAddr {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    match addr {
+        SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port),
+        SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), port),
+    }
+}
+
+pub(crate) struct TokioRuntime;
+
+impl Runtime for TokioRuntime {
+    type TcpStream = tokio::net::TcpStream;
+    #[cfg(unix)]
+    type UnixStream = tokio::net::UnixStream;
+
+    fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+        tokio::spawn(future);
+    }
+
+    fn spawn_blocking(f: impl FnOnce() + Send + 'static) {
+        tokio::task::spawn_blocking(f);
+    }
+
+    fn sleep(duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+
+    fn connect(
+        addr: SocketAddr,
+        source_port: Option<u16>,
+        timeout: Duration,
+    ) -> BoxFuture<'static, io::Result<Self::TcpStream>> {
+        Box::pin(async move {
+            let connect = async {
+                match source_port {
+                    Some(port) => {
+                        let socket = match addr {
+                            SocketAddr::V4(_) => tokio::net::TcpSocket::new_v4()?,
+                            SocketAddr::V6(_) => tokio::net::TcpSocket::new_v6()?,
+                        };
+                        socket.bind(unspecified_addr(&addr, port))?;
+                        socket.connect(addr).await
+                    }
+                    None => tokio::net::TcpStream::connect(addr).await,
+                }
+            };
+
+            tokio::time::timeout(timeout, connect)
+                .await
+                .unwrap_or_else(|_| Err(timed_out()))
+        })
+    }
+
+    #[cfg(unix)]
+    fn connect_unix(
+        path: PathBuf,
+        timeout: Duration,
+    ) -> BoxFuture<'static, io::Result<Self::UnixStream>> {
+        Box::pin(async move {
+            tokio::time::timeout(timeout, tokio::net::UnixStream::connect(path))
+                .await
+                .unwrap_or_else(|_| Err(timed_out()))
+        })
+    }
+
+    fn local_addr(stream: &Self::TcpStream) -> io::Result<SocketAddr> {
+        stream.local_addr()
+    }
+
+    fn set_nodelay(stream: &Self::TcpStream, nodelay: bool) -> io::Result<()> {
+        stream.set_nodelay(nodelay)
+    }
+
+    fn lookup_host(
+        host: String,
+        port: Option<u16>,
+    ) -> BoxFuture<'static, io::Result<Vec<SocketAddr>>> {
+        Box::pin(async move {
+            Ok(match port {
+                Some(port) => tokio::net::lookup_host((host.as_str(), port))
+                    .await?
+                    .collect(),
+                None => tokio::net::lookup_host(host).await?.collect(),
+            })
+        })
+    }
+}
+
+#[cfg(feature = "async-std")]
+pub(crate) struct AsyncStdRuntime;
+
+#[cfg(feature = "async-std")]
+impl Runtime for AsyncStdRuntime {
+    // async-std streams implement the futures-io traits, the driver uses the tokio ones
+    type TcpStream = tokio_util::compat::Compat<async_std::net::TcpStream>;
+    #[cfg(unix)]
+    type UnixStream = tokio_util::compat::Compat<async_std::os::unix::net::UnixStream>;
+
+    fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+        async_std::task::spawn(future);
+    }
+
+    fn spawn_blocking(f: impl FnOnce() + Send + 'static) {
+        async_std::task::spawn_blocking(f);
+    }
+
+    fn sleep(duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(async_std::task::sleep(duration))
+    }
+
+    fn connect(
+        addr: SocketAddr,
+        source_port: Option<u16>,
+        timeout: Duration,
+    ) -> BoxFuture<'static, io::Result<Self::TcpStream>> {
+        use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+        Box::pin(async move {
+            let stream = match source_port {
+                // async-std can't bind a socket before connecting,
+                // so it is connected on a blocking thread
+                Some(port) => {
+                    let stream = async_std::task::spawn_blocking(move || {
+                        use socket2::{Domain, Socket, Type};
+
+                        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+                        socket.bind(&unspecified_addr(&addr, port).into())?;
+                        socket.connect_timeout(&addr.into(), timeout)?;
+                        Ok::<_, io::Error>(std::net::TcpStream::from(socket))
+                    })
+                    .await?;
+                    async_std::net::TcpStream::from(stream)
+                }
+                None => {
+                    async_std::io::timeout(timeout, async_std::net::TcpStream::connect(addr))
+                        .await?
+                }
+            };
+            Ok(stream.compat())
+        })
+    }
+
+    #[cfg(unix)]
+    fn connect_unix(
+        path: PathBuf,
+        timeout: Duration,
+    ) -> BoxFuture<'static, io::Result<Self::UnixStream>> {
+        use async_std::os::unix::net::UnixStream;
+        use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+        Box::pin(async move {
+            let stream = async_std::io::timeout(timeout, UnixStream::connect(path)).await?;
+            Ok(stream.compat())
+        })
+    }
+
+    fn local_addr(stream: &Self::TcpStream) -> io::Result<SocketAddr> {
+        stream.get_ref().local_addr()
+    }
+
+    fn set_nodelay(stream: &Self::TcpStream, nodelay: bool) -> io::Result<()> {
+        stream.get_ref().set_nodelay(nodelay)
+    }
+
+    fn lookup_host(
+        host: String,
+        port: Option<u16>,
+    ) -> BoxFuture<'static, io::Result<Vec<SocketAddr>>> {
+        use async_std::net::ToSocketAddrs;
+
+        Box::pin(async move {
+            Ok(match port {
+                Some(port) => (host.as_str(), port).to_socket_addrs().await?.collect(),
+                None => host.to_socket_addrs().await?.collect(),
+            })
+        })
+    }
+}
+
+/// Uses tokio when called from within a tokio runtime and async-std otherwise
+#[cfg(feature = "async-std")]
+pub(crate) struct DetectedRuntime;
+
+#[cfg(feature = "async-std")]
+fn in_tokio_runtime() -> bool {
+    tokio::runtime::Handle::try_current().is_ok()
+}
+
+#[cfg(feature = "async-std")]
+impl Runtime for DetectedRuntime {
+    type TcpStream =
+        EitherStream<<TokioRuntime as Runtime>::TcpStream, <AsyncStdRuntime as Runtime>::TcpStream>;
+    #[cfg(unix)]
+    type UnixStream = EitherStream<
+        <TokioRuntime as Runtime>::UnixStream,
+        <AsyncStdRuntime as Runtime>::UnixStream,
+    >;
+
+    fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+        if in_tokio_runtime() {
+            TokioRuntime::spawn(future)
+        } else {
+            AsyncStdRuntime::spawn(future)
+        }
+    }
+
+    fn spawn_blocking(f: impl FnOnce() + Send + 'static) {
+        if in_tokio_runtime() {
+            TokioRuntime::spawn_blocking(f)
+        } else {
+            AsyncStdRuntime::spawn_blocking(f)
+        }
+    }
+
+    fn sleep(duration: Duration) -> BoxFuture<'static, ()> {
+        if in_tokio_runtime() {
+            TokioRuntime::sleep(duration)
+        } else {
+            AsyncStdRuntime::sleep(duration)
+        }
+    }
+
+    fn connect(
+        addr: SocketAddr,
+        source_port: Option<u16>,
+        timeout: Duration,
+    ) -> BoxFuture<'static, io::Result<Self::TcpStream>> {
+        use futures::TryFutureExt;
+
+        if in_tokio_runtime() {
+            Box::pin(TokioRuntime::connect(addr, source_port, timeout).map_ok(EitherStream::Tokio))
+        } else {
+            Box::pin(
+                AsyncStdRuntime::connect(addr, source_port, timeout).map_ok(EitherStream::AsyncStd),
+            )
+        }
+    }
+
+    #[cfg(unix)]
+    fn connect_unix(
+        path: PathBuf,
+        timeout: Duration,
+    ) -> BoxFuture<'static, io::Result<Self::UnixStream>> {
+        use futures::TryFutureExt;
+
+        if in_tokio_runtime() {
+            Box::pin(TokioRuntime::connect_unix(path, timeout).map_ok(EitherStream::Tokio))
+        } else {
+            Box::pin(AsyncStdRuntime::connect_unix(path, timeout).map_ok(EitherStream::AsyncStd))
+        }
+    }
+
+    fn local_addr(stream: &Self::TcpStream) -> io::Result<SocketAddr> {
+        match stream {
+            EitherStream::Tokio(stream) => TokioRuntime::local_addr(stream),
+            EitherStream::AsyncStd(stream) => AsyncStdRuntime::local_addr(stream),
+        }
+    }
+
+    fn set_nodelay(stream: &Self::TcpStream, nodelay: bool) -> io::Result<()> {
+        match stream {
+            EitherStream::Tokio(stream) => TokioRuntime::set_nodelay(stream, nodelay),
+            EitherStream::AsyncStd(stream) => AsyncStdRuntime::set_nodelay(stream, nodelay),
+        }
+    }
+
+    fn lookup_host(
+        host: String,
+        port: Option<u16>,
+    ) -> BoxFuture<'static, io::Result<Vec<SocketAddr>>> {
+        if in_tokio_runtime() {
+            TokioRuntime::lookup_host(host, port)
+        } else {
+            AsyncStdRuntime::lookup_host(host, port)
+        }
+    }
+}
+
+/// Stream opened by either of the runtimes
+#[cfg(feature = "async-std")]
+pub(crate) enum EitherStream<T, A> {
+    Tokio(T),
+    AsyncStd(A),
+}
+
+#[cfg(feature = "async-std")]
+impl<T, A> AsyncRead for EitherStream<T, A>
+where
+    T: AsyncRead + Unpin,
+    A: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            EitherStream::Tokio(stream) => Pin::new(stream).poll_read(cx, buf),
+            EitherStream::AsyncStd(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+#[cfg(feature = "async-std")]
+impl<T, A> AsyncWrite for EitherStream<T, A>
+where
+    T: AsyncWrite + Unpin,
+    A: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            EitherStream::Tokio(stream) => Pin::new(stream).poll_write(cx, buf),
+            EitherStream::AsyncStd(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            EitherStream::Tokio(stream) => Pin::new(stream).poll_flush(cx),
+            EitherStream::AsyncStd(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            EitherStream::Tokio(stream) => Pin::new(stream).poll_shutdown(cx),
+            EitherStream::AsyncStd(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sleep, timeout, Elapsed};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn timeout_elapses() {
+        assert_eq!(timeout(Duration::from_secs(10), async { 5 }).await, Ok(5));
+        assert_eq!(
+            timeout(Duration::from_millis(10), sleep(Duration::from_secs(10))).await,
+            Err(Elapsed)
+        );
+    }
+}
@@ -0,0 +1,427 @@
+/// Rendering schema metadata back into CQL statements
+use crate::statement::quote_identifier;
+use crate::transport::topology::{
+    ClusteringOrder, ColumnKind, Keyspace, Strategy, Table, UserDefinedType,
+};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+
+impl Keyspace {
+    /// Renders the keyspace as CQL statements recreating it:
+    /// `CREATE KEYSPACE` followed by `CREATE TYPE` and `CREATE TABLE` statements.
+    /// Types are ordered so that each type is created after the types it uses.
+    /// Statements are separated by empty lines.
+    pub fn describe(&self, keyspace_name: &str) -> String {
+        let mut statements: Vec<String> = Vec::new();
+
+        statements.push(format!(
+            "CREATE KEYSPACE {} WITH replication = {} AND durable_writes = {};",
+            quote_identifier(keyspace_name),
+            self.strategy.describe(),
+            self.durable_writes
+        ));
+
+        for type_name in user_defined_types_in_creation_order(&self.user_defined_types) {
+            statements.push(self.user_defined_types[type_name].describe(keyspace_name, type_name));
+        }
+
+        let mut table_names: Vec<&String> = self.tables.keys().collect();
+        table_names.sort_unstable();
+
+        for table_name in table_names {
+            statements.push(self.tables[table_name].describe(keyspace_name, table_name));
+        }
+
+        statements.join("\n\n")
+    }
+}
+
+impl Strategy {
+    /// Renders the replication map, e.g. `{'class': 'SimpleStrategy', 'replication_factor': '3'}`
+    pub fn describe(&self) -> String {
+        // BTreeMap keeps the options sorted, so that the output is deterministic
+        let (class, options): (&str, BTreeMap<&str, String>) = match self {
+            Strategy::SimpleStrategy { replication_factor } => (
+                "SimpleStrategy",
+                std::iter::once(("replication_factor", replication_factor.to_string())).collect(),
+            ),
+            Strategy::NetworkTopologyStrategy {
+                datacenter_repfactors,
+            } => (
+                "NetworkTopologyStrategy",
+                datacenter_repfactors
+                    .iter()
+                    .map(|(dc, rf)| (dc.as_str(), rf.to_string()))
+                    .collect(),
+            ),
+            Strategy::LocalStrategy => ("LocalStrategy", BTreeMap::new()),
+            Strategy::Other { name, data } => (
+                name.as_str(),
+                data.iter()
+                    .map(|(key, val)| (key.as_str(), val.clone()))
+                    .collect(),
+            ),
+        };
+
+        let mut result = format!("{{'class': {}", quote_string(class));
+        for (key, val) in options {
+            write!(result, ", {}: {}", quote_string(key), quote_string(&val)).unwrap();
+        }
+        result.push('}');
+
+        result
+    }
+}
+
+impl Table {
+    /// Renders `CREATE TABLE` statement for the table
+    pub fn describe(&self, keyspace_name: &str, table_name: &str) -> String {
+        let mut result = format!(
+            "CREATE TABLE {}.{} (\n",
+            quote_identifier(keyspace_name),
+            quote_identifier(table_name)
+        );
+
+        // Key columns go first in key order, the rest is sorted by name
+        let mut other_columns: Vec<&String> = self
+            .columns
+            .iter()
+            .filter(|(_, column)| matches!(column.kind, ColumnKind::Regular | ColumnKind::Static))
+            .map(|(name, _)| name)
+            .collect();
+        other_columns.sort_unstable();
+
+        let column_names = self
+            .partition_key
+            .iter()
+            .chain(self.clustering_key.iter())
+            .chain(other_columns);
+
+        for column_name in column_names {
+            let column = match self.columns.get(column_name) {
+                Some(column) => column,
+                None => continue,
+            };
+
+            write!(
+                result,
+                "    {} {}",
+                quote_identifier(column_name),
+                column.typ
+            )
+            .unwrap();
+
+            if column.kind == ColumnKind::Static {
+                result.push_str(" static");
+            }
+
+            result.push_str(",\n");
+        }
+
+        let partition_key = quote_identifiers(&self.partition_key);
+        let partition_key = if self.partition_key.len() == 1 {
+            partition_key
+        } else {
+            format!("({})", partition_key)
+        };
+
+        // Clustering order and table options form the WITH clause
+        let mut properties: Vec<String> = Vec::with_capacity(self.options.len() + 1);
+
+        if self.clustering_key.is_empty() {
+            write!(result, "    PRIMARY KEY ({})\n)", partition_key).unwrap();
+        } else {
+            write!(
+                result,
+                "    PRIMARY KEY ({}, {})\n)",
+                partition_key,
+                quote_identifiers(&self.clustering_key)
+            )
+            .unwrap();
+
+            let clustering_order: Vec<String> = self
+                .clustering_key
+                .iter()
+                .map(|column_name| {
+                    let order = match self.columns.get(column_name).map(|column| column.kind) {
+                        Some(ColumnKind::Clustering(ClusteringOrder::Descending)) => "DESC",
+                        _ => "ASC",
+                    };
+                    format!("{} {}", quote_identifier(column_name), order)
+                })
+                .collect();
+
+            properties.push(format!(
+                "CLUSTERING ORDER BY ({})",
+                clustering_order.join(", ")
+            ));
+        }
+
+        properties.extend(
+            self.options
+                .iter()
+                .map(|(name, value)| format!("{} = {}", name, value)),
+        );
+
+        if !properties.is_empty() {
+            write!(result, " WITH {}", properties.join("\n    AND ")).unwrap();
+        }
+
+        result.push(';');
+        result
+    }
+}
+
+impl UserDefinedType {
+    /// Renders `CREATE TYPE` statement for the type
+    pub fn describe(&self, keyspace_name: &str, type_name: &str) -> String {
+        let fields: Vec<String> = self
+            .fields
+            .iter()
+            .map(|(name, typ)| format!("    {} {}", quote_identifier(name), typ))
+            .collect();
+
+        format!(
+            "CREATE TYPE {}.{} (\n{}\n);",
+            quote_identifier(keyspace_name),
+            quote_identifier(type_name),
+            fields.join(",\n")
+        )
+    }
+}
+
+// Orders type names so that every type comes after all types used in its fields,
+// types which don't depend on each other are sorted by name
+fn user_defined_types_in_creation_order(types: &HashMap<String, UserDefinedType>) -> Vec<&str> {
+    fn visit<'a>(
+        type_name: &'a str,
+        types: &'a HashMap<String, UserDefinedType>,
+        visited: &mut HashSet<&'a str>,
+        result: &mut Vec<&'a str>,
+    ) {
+        if !visited.insert(type_name) {
+            return;
+        }
+
+        let mut dependencies: Vec<&str> = types[type_name]
+            .fields
+            .iter()
+            .flat_map(|(_, typ)| type_name_parts(typ))
+            .filter(|&part| part != type_name && types.contains_key(part))
+            .collect();
+        dependencies.sort_unstable();
+
+        for dependency in dependencies {
+            if let Some((name, _)) = types.get_key_value(dependency) {
+                visit(name, types, visited, result);
+            }
+        }
+
+        result.push(type_name);
+    }
+
+    let mut type_names: Vec<&String> = types.keys().collect();
+    type_names.sort_unstable();
+
+    let mut visited = HashSet::new();
+    let mut result = Vec::with_capacity(types.len());
+
+    for type_name in type_names {
+        visit(type_name, types, &mut visited, &mut result);
+    }
+
+    result
+}
+
+// Splits a CQL type like "frozen<map<text, \"Address\">>" into names it consists of
+fn type_name_parts(typ: &str) -> impl Iterator<Item = &str> {
+    typ.split(|c: char| c == '<' || c == '>' || c == ',' || c == '"' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+}
+
+fn quote_identifiers(identifiers: &[String]) -> String {
+    identifiers
+        .iter()
+        .map(|identifier| quote_identifier(identifier))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn quote_string(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::user_defined_types_in_creation_order;
+    use crate::transport::topology::{
+        ClusteringOrder, Column, ColumnKind, Keyspace, Strategy, Table, UserDefinedType,
+    };
+    use std::collections::{BTreeMap, HashMap};
+
+    fn column(typ: &str, kind: ColumnKind) -> Column {
+        Column {
+            typ: typ.to_string(),
+            kind,
+        }
+    }
+
+    fn udt(fields: &[(&str, &str)]) -> UserDefinedType {
+        UserDefinedType {
+            fields: fields
+                .iter()
+                .map(|(name, typ)| (name.to_string(), typ.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_describe_strategy() {
+        let simple = Strategy::SimpleStrategy {
+            replication_factor: 3,
+        };
+        assert_eq!(
+            simple.describe(),
+            "{'class': 'SimpleStrategy', 'replication_factor': '3'}"
+        );
+
+        let nts = Strategy::NetworkTopologyStrategy {
+            datacenter_repfactors: [("us".to_string(), 2), ("eu".to_string(), 3)]
+                .iter()
+                .cloned()
+                .collect(),
+        };
+        assert_eq!(
+            nts.describe(),
+            "{'class': 'NetworkTopologyStrategy', 'eu': '3', 'us': '2'}"
+        );
+    }
+
+    #[test]
+    fn test_describe_table() {
+        let table = Table {
+            columns: [
+                ("a", column("int", ColumnKind::PartitionKey)),
+                ("b", column("text", ColumnKind::PartitionKey)),
+                (
+                    "c",
+                    column("int", ColumnKind::Clustering(ClusteringOrder::Descending)),
+                ),
+                (
+                    "d",
+                    column("int", ColumnKind::Clustering(ClusteringOrder::Ascending)),
+                ),
+                ("Value", column("frozen<list<int>>", ColumnKind::Regular)),
+                ("order", column("int", ColumnKind::Regular)),
+                ("s", column("text", ColumnKind::Static)),
+            ]
+            .iter()
+            .map(|(name, column)| (name.to_string(), column.clone()))
+            .collect(),
+            partition_key: vec!["a".to_string(), "b".to_string()],
+            clustering_key: vec!["c".to_string(), "d".to_string()],
+            partitioner: None,
+            options: [
+                ("gc_grace_seconds", "864000"),
+                ("compaction", "{'class': 'SizeTieredCompactionStrategy'}"),
+            ]
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+        };
+
+        assert_eq!(
+            table.describe("ks", "t"),
+            "CREATE TABLE ks.t (\n    \
+                a int,\n    \
+                b text,\n    \
+                c int,\n    \
+                d int,\n    \
+                \"Value\" frozen<list<int>>,\n    \
+                \"order\" int,\n    \
+                s text static,\n    \
+                PRIMARY KEY ((a, b), c, d)\n\
+            ) WITH CLUSTERING ORDER BY (c DESC, d ASC)\n    \
+                AND compaction = {'class': 'SizeTieredCompactionStrategy'}\n    \
+                AND gc_grace_seconds = 864000;"
+        );
+
+        let simple_table = Table {
+            columns: [("id", column("uuid", ColumnKind::PartitionKey))]
+                .iter()
+                .map(|(name, column)| (name.to_string(), column.clone()))
+                .collect(),
+            partition_key: vec!["id".to_string()],
+            clustering_key: vec![],
+            partitioner: None,
+            options: BTreeMap::new(),
+        };
+
+        assert_eq!(
+            simple_table.describe("ks", "t"),
+            "CREATE TABLE ks.t (\n    id uuid,\n    PRIMARY KEY (id)\n);"
+        );
+    }
+
+    #[test]
+    fn test_user_defined_types_order() {
+        let types: HashMap<String, UserDefinedType> = [
+            ("a_person", udt(&[("home", "frozen<z_address>")])),
+            ("z_address", udt(&[("city", "frozen<m_city>")])),
+            ("m_city", udt(&[("name", "text")])),
+            ("b_other", udt(&[("x", "int")])),
+        ]
+        .iter()
+        .map(|(name, udt)| (name.to_string(), udt.clone()))
+        .collect();
+
+        assert_eq!(
+            user_defined_types_in_creation_order(&types),
+            vec!["m_city", "z_address", "a_person", "b_other"]
+        );
+    }
+
+    #[test]
+    fn test_describe_keyspace() {
+        let keyspace = Keyspace {
+            strategy: Strategy::SimpleStrategy {
+                replication_factor: 1,
+            },
+            tables: [(
+                "users".to_string(),
+                Table {
+                    columns: [
+                        ("id", column("int", ColumnKind::PartitionKey)),
+                        ("info", column("frozen<info>", ColumnKind::Regular)),
+                    ]
+                    .iter()
+                    .map(|(name, column)| (name.to_string(), column.clone()))
+                    .collect(),
+                    partition_key: vec!["id".to_string()],
+                    clustering_key: vec![],
+                    partitioner: None,
+                    options: BTreeMap::new(),
+                },
+            )]
+            .iter()
+            .cloned()
+            .collect(),
+            user_defined_types: [("info".to_string(), udt(&[("age", "int"), ("name", "text")]))]
+                .iter()
+                .cloned()
+                .collect(),
+            durable_writes: false,
+        };
+
+        assert_eq!(
+            keyspace.describe("ks"),
+            "CREATE KEYSPACE ks WITH replication = {'class': 'SimpleStrategy', 'replication_factor': '1'} \
+            AND durable_writes = false;\n\
+            \n\
+            CREATE TYPE ks.info (\n    age int,\n    name text\n);\n\
+            \n\
+            CREATE TABLE ks.users (\n    id int,\n    info frozen<info>,\n    PRIMARY KEY (id)\n);"
+        );
+    }
+}
@@ -1,36 +1,55 @@
 //! `Session` is the main object used in the driver.  
 //! It manages all connections to the cluster and allows to perform queries.
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use futures::future::join_all;
+use futures::Stream;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::future::Future;
-use std::net::SocketAddr;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::net::lookup_host;
-use tokio::time::timeout;
-use tracing::{info, warn};
+use tokio::sync::{broadcast, Semaphore};
+use tracing::{debug, debug_span, info, trace, warn, Instrument};
 use uuid::Uuid;
 
-use super::errors::{BadQuery, NewSessionError, QueryError};
+use arc_swap::ArcSwapOption;
+
+use super::errors::{BadQuery, BadSessionConfig, NewSessionError, ProtocolError, QueryError};
 use crate::frame::response::cql_to_rust::FromRowError;
+use crate::frame::response::event::SchemaChangeEvent;
 use crate::frame::response::{result, Response};
 use crate::frame::value::{BatchValues, SerializedValues, ValueList};
 use crate::prepared_statement::{PartitionKeyError, PreparedStatement};
 use crate::query::Query;
-use crate::routing::{murmur3_token, Token};
-use crate::statement::Consistency;
+use crate::routing::Token;
+use crate::statement::{Consistency, StatementSettings};
 use crate::tracing::{GetTracingConfig, TracingEvent, TracingInfo};
 use crate::transport::{
-    cluster::Cluster,
-    connection::{BatchResult, Connection, ConnectionConfig, QueryResult, VerifiedKeyspaceName},
+    address_translator::{AddressTranslator, IdentityTranslator},
+    authentication::{Authenticator, PlainTextAuthenticator},
+    cluster::{Cluster, ClusterData, ClusterEvent, SchemaChangeNotification},
+    connection::{
+        open_connection, Connection, ConnectionConfig, NonErrorQueryResponse, QueryResponse,
+        QueryResult, VerifiedKeyspaceName,
+    },
+    host_filter::HostFilter,
     iterator::RowIterator,
     load_balancing::{LoadBalancingPolicy, RoundRobinPolicy, Statement, TokenAwarePolicy},
     metrics::Metrics,
     node::Node,
+    node::PoolSize,
+    reconnection_policy::{ExponentialReconnectionPolicy, ReconnectionPolicy},
+    request_listener::{RequestInfo, RequestKind, RequestListener},
     retry_policy::{DefaultRetryPolicy, QueryInfo, RetryDecision, RetryPolicy, RetrySession},
+    runtime::{self, timeout, DefaultRuntime, Runtime},
     speculative_execution::SpeculativeExecutionPolicy,
-    Compression,
+    timestamp_generator::{MonotonicTimestampGenerator, TimestampGenerator},
+    Compression, ProtocolVersion,
 };
 use crate::{batch::Batch, statement::StatementConfig};
 use crate::{cql_to_rust::FromRow, transport::speculative_execution};
@@ -38,13 +57,29 @@ use crate::{cql_to_rust::FromRow, transport::speculative_execution};
 #[cfg(feature = "ssl")]
 use openssl::ssl::SslContext;
 
+#[cfg(feature = "fault-injection")]
+use super::fault_injection::FaultInjectionPolicy;
+
 /// `Session` manages connections to the cluster and allows to perform queries
 pub struct Session {
     cluster: Cluster,
     load_balancer: Arc<dyn LoadBalancingPolicy>,
     schema_agreement_interval: Duration,
-    retry_policy: Box<dyn RetryPolicy>,
+    auto_await_schema_agreement_timeout: Option<Duration>,
+    retry_policy: Arc<dyn RetryPolicy>,
+    request_timeout: Option<Duration>,
     speculative_execution_policy: Option<Arc<dyn SpeculativeExecutionPolicy>>,
+    batch_statements_warn_threshold: Option<usize>,
+    batch_size_warn_threshold: Option<usize>,
+    default_consistency: Consistency,
+    timestamp_generator: Option<Arc<dyn TimestampGenerator>>,
+    prefetch_pages: usize,
+    tracing_info_fetch_config: GetTracingConfig,
+    request_listener: Option<Arc<dyn RequestListener>>,
+    // Limits the number of requests executed at once, see SessionConfig::max_concurrent_requests
+    request_permits: Option<Semaphore>,
+    // Keyspace set by the last successful use_keyspace, as reported by get_keyspace
+    keyspace_name: ArcSwapOption<String>,
 
     metrics: Arc<Metrics>,
 }
@@ -56,12 +91,26 @@ pub struct Session {
 pub struct SessionConfig {
     /// List of database servers known on Session startup.
     /// Session will connect to these nodes to retrieve information about other nodes in the cluster.
-    /// Each node can be represented as a hostname or an IP address.
+    /// Each node can be represented as a hostname, an IP address or a path to a unix domain socket.
     pub known_nodes: Vec<KnownNode>,
 
+    /// Ports to use when connecting to nodes with given IP addresses.
+    /// By default Session connects to all nodes using the same port as the initial connection.
+    pub node_port_overrides: HashMap<IpAddr, u16>,
+
+    /// Unix domain sockets to use instead of TCP when connecting to nodes with given IP addresses.
+    /// Known nodes given as [`KnownNode::UnixSocket`] are added here during [`Session::connect`].
+    pub node_unix_socket_overrides: HashMap<IpAddr, PathBuf>,
+
     /// Preferred compression algorithm to use on connections.
     /// If it's not supported by database server Session will fall back to no compression.
     pub compression: Option<Compression>,
+    /// Requests with smaller bodies are sent uncompressed, by default all requests are compressed
+    pub compression_min_size: usize,
+
+    /// Highest version of the CQL protocol used on connections, by default v4.
+    /// Nodes which don't support protocol v5 are connected to using v4.
+    pub protocol_version: ProtocolVersion,
     pub tcp_nodelay: bool,
 
     /// Load balancing policy used by Session
@@ -70,25 +119,123 @@ pub struct SessionConfig {
     pub used_keyspace: Option<String>,
     pub keyspace_case_sensitive: bool,
 
-    pub retry_policy: Box<dyn RetryPolicy>,
+    pub retry_policy: Arc<dyn RetryPolicy>,
+
+    /// Time after which queries, prepared statements and batches which don't set their own
+    /// request timeout fail with [`QueryError::TimeoutError`], retries included.
+    /// If `None`, requests don't time out on the client side.
+    pub request_timeout: Option<Duration>,
+
     pub speculative_execution_policy: Option<Arc<dyn SpeculativeExecutionPolicy>>,
 
     /// Provide our Session with TLS
     #[cfg(feature = "ssl")]
     pub ssl_context: Option<SslContext>,
 
+    /// Server name sent in the TLS handshake (SNI), needed when nodes are behind a proxy routing by it
+    #[cfg(feature = "ssl")]
+    pub tls_server_name: Option<String>,
+
+    /// Policy injecting failures into connections, for testing
+    #[cfg(feature = "fault-injection")]
+    pub fault_injection_policy: Option<Arc<dyn FaultInjectionPolicy>>,
+
     pub auth_username: Option<String>,
     pub auth_password: Option<String>,
 
+    /// Authenticator used for nodes requiring authentication, e.g. with a custom SASL mechanism.
+    /// Takes precedence over `auth_username` and `auth_password`
+    pub authenticator: Option<Arc<dyn Authenticator>>,
+
     pub schema_agreement_interval: Duration,
+
+    /// If set, after a query which changes the schema (DDL) Session will wait
+    /// for schema agreement before returning, for at most the given duration.
+    /// If the cluster doesn't reach agreement in time, the query fails with a timeout error.
+    pub auto_await_schema_agreement_timeout: Option<Duration>,
+
     pub connect_timeout: std::time::Duration,
+
+    /// Maximal time of fetching topology and schema information from a single node.
+    /// If it takes longer, Session will try to fetch it from another node.
+    pub metadata_timeout: Duration,
+
+    /// Interval of the background topology refresh. Apart from it the topology is refreshed
+    /// when the cluster sends a topology change event, so this only bounds how long
+    /// metadata stays stale if events are missed.
+    pub topology_refresh_interval: Duration,
+
+    /// If set, connection pools are opened only to nodes in the given (local) datacenter.
+    /// Nodes in other datacenters are still known, connections to them
+    /// are opened only when a query is sent to them.
+    pub pooled_datacenter: Option<String>,
+
+    /// Translates addresses of nodes found in `system.peers` before connecting to them,
+    /// e.g. internal addresses behind a NAT. By default addresses aren't translated.
+    pub address_translator: Arc<dyn AddressTranslator>,
+
+    /// If set, the driver connects only to nodes accepted by the filter.
+    /// Rejected nodes are kept in the cluster topology, but queries are never sent to them.
+    pub host_filter: Option<Arc<dyn HostFilter>>,
+
+    /// Number of connections opened to each node, by default one connection to each shard.
+    /// Broken connections are reopened in the background.
+    pub pool_size: PoolSize,
+
+    /// Decides how long to wait between attempts to reopen a broken connection.
+    /// By default [`ExponentialReconnectionPolicy`] is used.
+    pub reconnection_policy: Arc<dyn ReconnectionPolicy>,
+
+    /// If set, each connection sends an OPTIONS request with this interval, so that idle connections
+    /// aren't closed by firewalls or NATs. Connections which don't respond before the next
+    /// request is due are closed and reopened.
+    pub connection_keepalive_interval: Option<Duration>,
+
+    /// Maximum number of requests sent through a single connection and waiting for a response.
+    /// Further requests wait until some response arrives, instead of failing because
+    /// the connection ran out of stream ids. By default it's 32768, the number of stream ids.
+    pub max_requests_per_connection: usize,
+
+    /// If set, at most this many queries, executions and batches run at once, further ones
+    /// wait until some of them finish. Paged queries wait for each page separately,
+    /// pages fetched by [`RowIterator`] are limited only by `max_requests_per_connection`.
+    pub max_concurrent_requests: Option<usize>,
+
+    /// If set, a warning is logged and the oversized batches metric is incremented
+    /// for every batch containing more statements than the threshold.
+    pub batch_statements_warn_threshold: Option<usize>,
+
+    /// If set, a warning is logged and the oversized batches metric is incremented
+    /// for every batch whose serialized request body, with statements and values,
+    /// takes more bytes than the threshold.
+    pub batch_size_warn_threshold: Option<usize>,
+
+    /// Consistency used by queries, prepared statements and batches which don't set their own.
+    pub default_consistency: Consistency,
+
+    /// Serial consistency used by queries, prepared statements and batches which don't set their own.
+    /// If `None`, serial consistency isn't sent and the database uses its default.
+    pub default_serial_consistency: Option<Consistency>,
+
+    /// Generates timestamps for queries, prepared statements and batches which don't set their own.
+    /// By default [`MonotonicTimestampGenerator`] is used.
+    /// If `None`, timestamps aren't sent and the database assigns them.
+    pub timestamp_generator: Option<Arc<dyn TimestampGenerator>>,
+
+    /// Number of pages fetched in advance by [`RowIterator`] for statements which don't set their own.
+    pub prefetch_pages: usize,
+
+    /// Number of attempts, interval between them and consistency used by [`Session::get_tracing_info`].
+    /// Tracing tables are written asynchronously, so the information may be missing right after the query.
+    pub tracing_info_fetch_config: GetTracingConfig,
+
+    /// If set, it's notified about the start, attempts, retries and the outcome of each request.
+    pub request_listener: Option<Arc<dyn RequestListener>>,
     /*
     These configuration options will be added in the future:
 
 
     pub tcp_keepalive: bool,
-
-    pub default_consistency: Option<String>,
     */
 }
 
@@ -97,6 +244,9 @@ pub struct SessionConfig {
 pub enum KnownNode {
     Hostname(String),
     Address(SocketAddr),
+    /// Path to a unix domain socket on which the node (or a proxy in front of it) accepts CQL connections.
+    /// The node keeps being identified by its IP address, which is read from `system.local`.
+    UnixSocket(PathBuf),
 }
 
 impl SessionConfig {
@@ -113,19 +263,48 @@ impl SessionConfig {
     pub fn new() -> Self {
         SessionConfig {
             known_nodes: Vec::new(),
+            node_port_overrides: HashMap::new(),
+            node_unix_socket_overrides: HashMap::new(),
             compression: None,
+            compression_min_size: 0,
+            protocol_version: ProtocolVersion::V4,
             tcp_nodelay: true,
             schema_agreement_interval: Duration::from_millis(200),
+            auto_await_schema_agreement_timeout: Some(Duration::from_secs(60)),
             load_balancing: Arc::new(TokenAwarePolicy::new(Box::new(RoundRobinPolicy::new()))),
             used_keyspace: None,
             keyspace_case_sensitive: false,
-            retry_policy: Box::new(DefaultRetryPolicy),
+            retry_policy: Arc::new(DefaultRetryPolicy),
+            request_timeout: None,
             speculative_execution_policy: None,
             #[cfg(feature = "ssl")]
             ssl_context: None,
+            #[cfg(feature = "ssl")]
+            tls_server_name: None,
+            #[cfg(feature = "fault-injection")]
+            fault_injection_policy: None,
             auth_username: None,
             auth_password: None,
+            authenticator: None,
             connect_timeout: std::time::Duration::from_secs(5),
+            metadata_timeout: Duration::from_secs(5),
+            topology_refresh_interval: Duration::from_secs(60),
+            pooled_datacenter: None,
+            address_translator: Arc::new(IdentityTranslator),
+            host_filter: None,
+            pool_size: Default::default(),
+            reconnection_policy: Arc::new(ExponentialReconnectionPolicy::default()),
+            connection_keepalive_interval: None,
+            max_requests_per_connection: 32768,
+            max_concurrent_requests: None,
+            batch_statements_warn_threshold: None,
+            batch_size_warn_threshold: None,
+            default_consistency: Default::default(),
+            default_serial_consistency: None,
+            timestamp_generator: Some(Arc::new(MonotonicTimestampGenerator::new())),
+            prefetch_pages: 1,
+            tracing_info_fetch_config: GetTracingConfig::default(),
+            request_listener: None,
         }
     }
 
@@ -187,16 +366,107 @@ impl SessionConfig {
         }
     }
 
+    /// Adds a known database server reachable through a unix domain socket
+    /// # Example
+    /// ```
+    /// # use scylla::SessionConfig;
+    /// let mut config = SessionConfig::new();
+    /// config.add_known_node_unix_socket("/var/run/scylla/cql.sock");
+    /// ```
+    pub fn add_known_node_unix_socket(&mut self, path: impl Into<PathBuf>) {
+        self.known_nodes.push(KnownNode::UnixSocket(path.into()));
+    }
+
+    /// Checks options which would make connecting fail or the session misbehave,
+    /// done before opening any connection, so that mistakes are reported with clear errors
+    pub(crate) fn validate(&self) -> Result<(), NewSessionError> {
+        // Ensure there is at least one known node
+        if self.known_nodes.is_empty() {
+            return Err(NewSessionError::EmptyKnownNodesList);
+        }
+
+        if let Some(keyspace_name) = &self.used_keyspace {
+            VerifiedKeyspaceName::new(keyspace_name.clone(), self.keyspace_case_sensitive)
+                .map_err(BadQuery::from)?;
+        }
+
+        let durations = [
+            ("Connection timeout", Some(self.connect_timeout)),
+            ("Metadata timeout", Some(self.metadata_timeout)),
+            (
+                "Schema agreement interval",
+                Some(self.schema_agreement_interval),
+            ),
+            (
+                "Topology refresh interval",
+                Some(self.topology_refresh_interval),
+            ),
+            (
+                "Connection keepalive interval",
+                self.connection_keepalive_interval,
+            ),
+            ("Request timeout", self.request_timeout),
+        ];
+        for (name, duration) in durations.iter() {
+            if *duration == Some(Duration::from_secs(0)) {
+                return Err(BadSessionConfig::ZeroDuration(name).into());
+            }
+        }
+
+        let request_limits = [
+            (
+                "Max requests per connection",
+                Some(self.max_requests_per_connection),
+            ),
+            ("Max concurrent requests", self.max_concurrent_requests),
+        ];
+        for (name, limit) in request_limits.iter() {
+            if *limit == Some(0) {
+                return Err(BadSessionConfig::ZeroRequestLimit(name).into());
+            }
+        }
+
+        match self.default_serial_consistency {
+            None | Some(Consistency::Serial) | Some(Consistency::LocalSerial) => {}
+            Some(consistency) => {
+                return Err(BadSessionConfig::NonSerialConsistency(consistency).into())
+            }
+        }
+
+        #[cfg(feature = "ssl")]
+        if self.tls_server_name.is_some() && self.ssl_context.is_none() {
+            return Err(BadSessionConfig::TlsServerNameWithoutSsl.into());
+        }
+
+        Ok(())
+    }
+
     /// Makes a config that should be used in Connection
-    fn get_connection_config(&self) -> ConnectionConfig {
+    pub(crate) fn get_connection_config(&self) -> ConnectionConfig {
         ConnectionConfig {
             compression: self.compression,
+            compression_min_size: self.compression_min_size,
+            protocol_version: self.protocol_version,
             tcp_nodelay: self.tcp_nodelay,
             #[cfg(feature = "ssl")]
             ssl_context: self.ssl_context.clone(),
-            auth_username: self.auth_username.to_owned(),
-            auth_password: self.auth_password.to_owned(),
+            #[cfg(feature = "ssl")]
+            tls_server_name: self.tls_server_name.clone(),
+            authenticator: self.authenticator.clone().or_else(|| {
+                let username = self.auth_username.clone()?;
+                let password = self.auth_password.clone()?;
+                Some(Arc::new(PlainTextAuthenticator::new(username, password)))
+            }),
             connect_timeout: self.connect_timeout,
+            default_consistency: self.default_consistency,
+            default_serial_consistency: self.default_serial_consistency,
+            unix_socket_overrides: Arc::new(self.node_unix_socket_overrides.clone()),
+            pool_size: self.pool_size,
+            reconnection_policy: self.reconnection_policy.clone(),
+            keepalive_interval: self.connection_keepalive_interval,
+            max_requests_per_connection: self.max_requests_per_connection,
+            #[cfg(feature = "fault-injection")]
+            fault_injection_policy: self.fault_injection_policy.clone(),
             ..Default::default()
         }
     }
@@ -209,7 +479,7 @@ impl Default for SessionConfig {
     }
 }
 
-/// Trait used to implement `Vec<result::Row>::into_typed<RowT>`
+/// Trait used to implement `Vec<result::Row>::into_typed<RowT>` and `Rows::into_typed<RowT>`
 // This is the only way to add custom method to Vec
 pub trait IntoTypedRows {
     fn into_typed<RowT: FromRow>(self) -> TypedRowIter<RowT>;
@@ -220,7 +490,19 @@ pub trait IntoTypedRows {
 impl IntoTypedRows for Vec<result::Row> {
     fn into_typed<RowT: FromRow>(self) -> TypedRowIter<RowT> {
         TypedRowIter {
-            row_iter: self.into_iter(),
+            row_iter: TypedRowSource::Deserialized(self.into_iter()),
+            row_index: 0,
+            phantom_data: Default::default(),
+        }
+    }
+}
+
+// Rows received from the database are deserialized one at a time while iterating
+impl IntoTypedRows for result::Rows {
+    fn into_typed<RowT: FromRow>(self) -> TypedRowIter<RowT> {
+        TypedRowIter {
+            row_iter: TypedRowSource::Serialized(self.into_iter()),
+            row_index: 0,
             phantom_data: Default::default(),
         }
     }
@@ -229,15 +511,37 @@ impl IntoTypedRows for Vec<result::Row> {
 /// Iterator over rows parsed as the given type  
 /// Returned by `rows.into_typed::<(...)>()`
 pub struct TypedRowIter<RowT: FromRow> {
-    row_iter: std::vec::IntoIter<result::Row>,
+    row_iter: TypedRowSource,
+    row_index: usize,
     phantom_data: std::marker::PhantomData<RowT>,
 }
 
+enum TypedRowSource {
+    Deserialized(std::vec::IntoIter<result::Row>),
+    Serialized(result::RowsIntoIter),
+}
+
 impl<RowT: FromRow> Iterator for TypedRowIter<RowT> {
     type Item = Result<RowT, FromRowError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.row_iter.next().map(RowT::from_row)
+        let row_index = self.row_index;
+        self.row_index += 1;
+
+        let row_res = match &mut self.row_iter {
+            TypedRowSource::Deserialized(rows) => rows.next().map(RowT::from_row)?,
+            TypedRowSource::Serialized(rows) => {
+                let row = match rows.next()? {
+                    Ok(row) => row,
+                    Err(err) => return Some(Err(FromRowError::MalformedRow(err.to_string()))),
+                };
+                // Rows read from a result have metadata describing the columns
+                RowT::from_row(row)
+                    .map_err(|err| err.with_column_specs(rows.metadata().col_specs()))
+            }
+        };
+
+        Some(row_res.map_err(|err| err.with_row_index(row_index)))
     }
 }
 
@@ -267,21 +571,20 @@ impl Session {
     /// # Ok(())
     /// # }
     /// ```
-    pub async fn connect(config: SessionConfig) -> Result<Session, NewSessionError> {
-        // Ensure there is at least one known node
-        if config.known_nodes.is_empty() {
-            return Err(NewSessionError::EmptyKnownNodesList);
-        }
+    pub async fn connect(mut config: SessionConfig) -> Result<Session, NewSessionError> {
+        config.validate()?;
 
         // Find IP addresses of all known nodes passed in the config
         let mut node_addresses: Vec<SocketAddr> = Vec::with_capacity(config.known_nodes.len());
 
         let mut to_resolve: Vec<&str> = Vec::new();
+        let mut unix_sockets: Vec<PathBuf> = Vec::new();
 
         for node in &config.known_nodes {
             match node {
                 KnownNode::Hostname(hostname) => to_resolve.push(&hostname),
                 KnownNode::Address(address) => node_addresses.push(*address),
+                KnownNode::UnixSocket(path) => unix_sockets.push(path.clone()),
             };
         }
 
@@ -290,6 +593,18 @@ impl Session {
 
         node_addresses.extend(resolved);
 
+        let connection_config = config.get_connection_config();
+        let socket_futures = unix_sockets
+            .iter()
+            .map(|path| resolve_unix_socket(path, connection_config.clone()));
+        let socket_addresses: Vec<SocketAddr> =
+            futures::future::try_join_all(socket_futures).await?;
+
+        for (addr, path) in socket_addresses.iter().zip(unix_sockets) {
+            config.node_unix_socket_overrides.insert(addr.ip(), path);
+        }
+        node_addresses.extend(socket_addresses);
+
         let use_ssl = match () {
             #[cfg(not(feature = "ssl"))]
             () => false,
@@ -309,27 +624,74 @@ impl Session {
                 .collect();
         }
 
+        let metrics = Arc::new(Metrics::new());
+
         // Start the session
         let cluster = if !shard_aware_addresses.is_empty() {
-            match Cluster::new(&shard_aware_addresses, config.get_connection_config()).await {
+            match Cluster::new(
+                &shard_aware_addresses,
+                config.get_connection_config(),
+                config.node_port_overrides.clone(),
+                config.metadata_timeout,
+                config.topology_refresh_interval,
+                config.pooled_datacenter.clone(),
+                config.address_translator.clone(),
+                config.host_filter.clone(),
+                metrics.clone(),
+            )
+            .await
+            {
                 Ok(clust) => clust,
                 Err(e) => {
                     warn!("Unable to establish connections at detected shard-aware port, falling back to default ports: {}", e);
-                    Cluster::new(&node_addresses, config.get_connection_config()).await?
+                    Cluster::new(
+                        &node_addresses,
+                        config.get_connection_config(),
+                        config.node_port_overrides.clone(),
+                        config.metadata_timeout,
+                        config.topology_refresh_interval,
+                        config.pooled_datacenter.clone(),
+                        config.address_translator.clone(),
+                        config.host_filter.clone(),
+                        metrics.clone(),
+                    )
+                    .await?
                 }
             }
         } else {
             info!("Shard-aware ports not available, falling back to default ports");
-            Cluster::new(&node_addresses, config.get_connection_config()).await?
+            Cluster::new(
+                &node_addresses,
+                config.get_connection_config(),
+                config.node_port_overrides.clone(),
+                config.metadata_timeout,
+                config.topology_refresh_interval,
+                config.pooled_datacenter.clone(),
+                config.address_translator.clone(),
+                config.host_filter.clone(),
+                metrics.clone(),
+            )
+            .await?
         };
 
         let session = Session {
             cluster,
             load_balancer: config.load_balancing,
             retry_policy: config.retry_policy,
+            request_timeout: config.request_timeout,
             schema_agreement_interval: config.schema_agreement_interval,
+            auto_await_schema_agreement_timeout: config.auto_await_schema_agreement_timeout,
             speculative_execution_policy: config.speculative_execution_policy,
-            metrics: Arc::new(Metrics::new()),
+            batch_statements_warn_threshold: config.batch_statements_warn_threshold,
+            batch_size_warn_threshold: config.batch_size_warn_threshold,
+            default_consistency: config.default_consistency,
+            timestamp_generator: config.timestamp_generator,
+            prefetch_pages: config.prefetch_pages,
+            tracing_info_fetch_config: config.tracing_info_fetch_config,
+            request_listener: config.request_listener,
+            request_permits: config.max_concurrent_requests.map(Semaphore::new),
+            keyspace_name: ArcSwapOption::const_empty(),
+            metrics,
         };
 
         if let Some(keyspace_name) = config.used_keyspace {
@@ -374,6 +736,9 @@ impl Session {
     /// * `query` - query to perform, can be just a `&str` or the [Query](crate::query::Query) struct.
     /// * `values` - values bound to the query, easiest way is to use a tuple of bound values
     ///
+    /// A `USE <keyspace>` query isn't sent to a single node,
+    /// it's performed by [use_keyspace](Session::use_keyspace) on all connections instead.
+    ///
     /// # Examples
     /// ```rust
     /// # use scylla::Session;
@@ -419,11 +784,38 @@ impl Session {
     }
 
     /// Queries the database with a custom paging state.
+    /// Returns a single page of rows, the [`paging_state`](QueryResult::paging_state) of the result
+    /// allows to fetch the next page later, e.g. in another request of a web application.
+    /// It's `None` after the last page.
+    ///
     /// # Arguments
     ///
     /// * `query` - query to be performed
     /// * `values` - values bound to the query
     /// * `paging_state` - previously received paging state or None
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// use scylla::query::Query;
+    ///
+    /// let query = Query::new("SELECT a, b FROM ks.tab".to_string()).with_page_size(100);
+    /// let mut paging_state = None;
+    /// loop {
+    ///     let page = session.query_paged(query.clone(), &[], paging_state).await?;
+    ///     paging_state = page.paging_state.clone();
+    ///     for row in page.rows_typed::<(i32, String)>()? {
+    ///         let (a, b): (i32, String) = row?;
+    ///     }
+    ///     if paging_state.is_none() {
+    ///         break;
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
     pub async fn query_paged(
         &self,
         query: impl Into<Query>,
@@ -431,38 +823,62 @@ impl Session {
         paging_state: Option<Bytes>,
     ) -> Result<QueryResult, QueryError> {
         let query: Query = query.into();
-        let query_text: &str = query.get_contents();
-        let serialized_values = values.serialized();
-
-        // In case the user tried doing session.query("use keyspace ks") run session::use_keyspace
-        if query_is_setting_keyspace(query_text) {
-            warn!("Raw USE KEYSPACE queries are experimental, please use session::use_keyspace instead");
 
-            let keyspace_name = &query_text["use ".len()..].trim_end_matches(';').trim();
-            let case_sensitive = keyspace_name.starts_with('"');
-            let keyspace_name = keyspace_name.trim_matches('"');
+        // In case the user tried doing session.query("USE ks") run session::use_keyspace,
+        // otherwise only a single connection would switch to the new keyspace
+        if let Some(keyspace_name) = parse_use_keyspace(query.get_contents()) {
+            debug!("Performing {:?} on all connections", query.get_contents());
 
+            // A quoted name is unquoted by VerifiedKeyspaceName and treated as case sensitive
             return self
-                .use_keyspace(keyspace_name, case_sensitive)
+                .use_keyspace(keyspace_name, false)
                 .await
                 .map(|_| QueryResult::default());
         }
 
+        let response = self.query_response(&query, values, paging_state).await?;
+        self.handle_auto_await_schema_agreement(&response).await?;
+        response.into_query_result()
+    }
+
+    // Sends the query to a node chosen by the load balancing policy.
+    // The response keeps the address of the node which sent it
+    async fn query_response(
+        &self,
+        query: &Query,
+        values: impl ValueList,
+        paging_state: Option<Bytes>,
+    ) -> Result<NonErrorQueryResponse, QueryError> {
+        let query_text: &str = query.get_contents();
+        let serialized_values = values.serialized();
+
         // Needed to avoid moving query and values into async move block
-        let query_ref: &Query = &query;
+        let query_ref: &Query = query;
         let values_ref = &serialized_values;
         let paging_state_ref = &paging_state;
+        let timestamp = self.statement_timestamp(query.get_timestamp());
 
         self.run_query(
             Statement::default(),
+            RequestInfo {
+                kind: RequestKind::Query,
+                statement: Some(query_text),
+            },
             &query.config,
             |node: Arc<Node>| async move { node.random_connection().await },
-            |connection: Arc<Connection>| async move {
+            |connection: Arc<Connection>, consistency: Option<Consistency>| async move {
                 connection
-                    .query_single_page_by_ref(query_ref, values_ref, paging_state_ref.clone())
+                    .query(
+                        &with_consistency(query_ref, consistency),
+                        values_ref,
+                        paging_state_ref.clone(),
+                        timestamp,
+                    )
                     .await
+                    .and_then(QueryResponse::into_non_error_query_response)
             },
         )
+        .instrument(debug_span!("query", statement = query_text))
         .await
     }
 
@@ -504,21 +920,20 @@ impl Session {
         query: impl Into<Query>,
         values: impl ValueList,
     ) -> Result<RowIterator, QueryError> {
-        let query: Query = query.into();
-        let serialized_values = values.serialized()?;
+        let mut query: Query = query.into();
+        // The iterator needs owned values, take them without copying if possible
+        let serialized_values = values.into_serialized()?;
 
-        let retry_session = match &query.config.retry_policy {
-            Some(policy) => policy.new_session(),
-            None => self.retry_policy.new_session(),
-        };
+        let retry_session = self.prepare_for_iterator(&mut query);
 
         Ok(RowIterator::new_for_query(
             query,
-            serialized_values.into_owned(),
+            serialized_values,
             retry_session,
             self.load_balancer.clone(),
             self.cluster.get_data(),
             self.metrics.clone(),
+            self.request_listener.clone(),
         ))
     }
 
@@ -564,37 +979,49 @@ impl Session {
 
         // Prepare statements on all connections concurrently
         let handles = connections.iter().map(|c| c.prepare(&query));
-        let mut results = join_all(handles).await;
+        let results = join_all(handles).await;
 
         // If at least one prepare was succesfull prepare returns Ok
+        let mut prepared: Option<PreparedStatement> = None;
+        let mut last_error: Option<QueryError> = None;
 
-        // Find first result that is Ok, or Err if all failed
-        let mut first_ok: Option<Result<PreparedStatement, QueryError>> = None;
-
-        while let Some(res) = results.pop() {
-            let is_ok: bool = res.is_ok();
+        for (connection, result) in connections.iter().zip(results) {
+            let statement = match result {
+                Ok(statement) => statement,
+                Err(error) => {
+                    last_error = Some(error);
+                    continue;
+                }
+            };
 
-            first_ok = Some(res);
+            match &mut prepared {
+                None => prepared = Some(statement),
+                Some(prepared) => {
+                    // Nodes running different versions can return different ids,
+                    // each node gets executions with its own one
+                    if prepared.get_id() != statement.get_id() {
+                        prepared.set_id_for_node(
+                            connection.get_connect_address(),
+                            statement.get_id().clone(),
+                        );
+                    }
 
-            if is_ok {
-                break;
+                    // Collect all tracing ids from prepare() queries in the final result
+                    prepared
+                        .prepare_tracing_ids
+                        .extend(statement.prepare_tracing_ids);
+                }
             }
         }
 
-        let mut prepared: PreparedStatement = first_ok.unwrap()?;
-
-        // Validate prepared ids equality
-        for statement in results.into_iter().flatten() {
-            if prepared.get_id() != statement.get_id() {
-                return Err(QueryError::ProtocolError(
-                    "Prepared statement Ids differ, all should be equal",
-                ));
-            }
+        // get_working_connections returns at least one connection
+        let mut prepared = prepared.ok_or_else(|| last_error.unwrap())?;
 
-            // Collect all tracing ids from prepare() queries in the final result
-            prepared
-                .prepare_tracing_ids
-                .extend(statement.prepare_tracing_ids);
+        if let (Some(keyspace), Some(table)) =
+            (prepared.get_keyspace_name(), prepared.get_table_name())
+        {
+            let partitioner = self.cluster.get_data().table_partitioner(keyspace, table);
+            prepared.set_partitioner(partitioner);
         }
 
         Ok(prepared)
@@ -645,7 +1072,9 @@ impl Session {
         self.execute_paged(prepared, values, None).await
     }
 
-    /// Executes a previously prepared statement with previously received paging state
+    /// Executes a previously prepared statement with previously received paging state.
+    /// Returns a single page of rows, works like [`query_paged`](Session::query_paged).
+    ///
     /// # Arguments
     ///
     /// * `prepared` - a statement prepared with [prepare](crate::transport::session::Session::prepare)
@@ -660,25 +1089,42 @@ impl Session {
         let serialized_values = values.serialized()?;
         let values_ref = &serialized_values;
         let paging_state_ref = &paging_state;
+        let timestamp = self.statement_timestamp(prepared.get_timestamp());
 
         let token = calculate_token(prepared, &serialized_values)?;
+        let token_ref = &token;
 
         let statement_info = Statement {
-            token: Some(token),
+            token: Some(token.clone()),
             keyspace: prepared.get_keyspace_name(),
+            is_confirmed_lwt: prepared.is_confirmed_lwt(),
         };
 
-        self.run_query(
-            statement_info,
-            &prepared.config,
-            |node: Arc<Node>| async move { node.connection_for_token(token).await },
-            |connection: Arc<Connection>| async move {
-                connection
-                    .execute_single_page(prepared, values_ref, paging_state_ref.clone())
-                    .await
-            },
-        )
-        .await
+        let response = self
+            .run_query(
+                statement_info,
+                RequestInfo {
+                    kind: RequestKind::Execute,
+                    statement: Some(prepared.get_statement()),
+                },
+                &prepared.config,
+                |node: Arc<Node>| async move { node.connection_for_token(token_ref).await },
+                |connection: Arc<Connection>, consistency: Option<Consistency>| async move {
+                    connection
+                        .execute(
+                            &with_consistency(prepared, consistency),
+                            values_ref,
+                            paging_state_ref.clone(),
+                            timestamp,
+                        )
+                        .await
+                        .and_then(QueryResponse::into_non_error_query_response)
+                },
+            )
+            .instrument(debug_span!("execute", statement = prepared.get_statement()))
+            .await?;
+        self.handle_auto_await_schema_agreement(&response).await?;
+        response.into_query_result()
     }
 
     /// Run a prepared query with paging  
@@ -728,24 +1174,33 @@ impl Session {
         values: impl ValueList,
     ) -> Result<RowIterator, QueryError> {
         let prepared: PreparedStatement = prepared.into();
-        let serialized_values = values.serialized()?;
+        // The iterator needs owned values, take them without copying if possible
+        let serialized_values = values.into_serialized()?;
 
         let token = calculate_token(&prepared, &serialized_values)?;
+        Ok(self.execute_iter_with_token(prepared, serialized_values, token))
+    }
 
-        let retry_session = match &prepared.config.retry_policy {
-            Some(policy) => policy.new_session(),
-            None => self.retry_policy.new_session(),
-        };
+    // Like execute_iter, but pages are routed using the given token
+    // instead of the one computed from partition key values
+    pub(crate) fn execute_iter_with_token(
+        &self,
+        mut prepared: PreparedStatement,
+        values: SerializedValues,
+        token: Token,
+    ) -> RowIterator {
+        let retry_session = self.prepare_for_iterator(&mut prepared);
 
-        Ok(RowIterator::new_for_prepared_statement(
+        RowIterator::new_for_prepared_statement(
             prepared,
-            serialized_values.into_owned(),
+            values,
             token,
             retry_session,
             self.load_balancer.clone(),
             self.cluster.get_data(),
             self.metrics.clone(),
-        ))
+            self.request_listener.clone(),
+        )
     }
 
     /// Perform a batch query  
@@ -792,18 +1247,76 @@ impl Session {
         &self,
         batch: &Batch,
         values: impl BatchValues,
-    ) -> Result<BatchResult, QueryError> {
+    ) -> Result<QueryResult, QueryError> {
+        // Check the values before choosing a node, this error can't be fixed by retrying
+        let statements_count = batch.get_statements().len();
+        if statements_count != values.len() {
+            return Err(QueryError::BadQuery(BadQuery::ValueLenMismatch(
+                values.len(),
+                statements_count,
+            )));
+        }
+
         let values_ref = &values;
+        let timestamp = self.statement_timestamp(batch.get_timestamp());
+        // An oversized batch is reported once, not on every retry
+        let oversized_reported =
+            AtomicBool::new(self.check_batch_statements_count(statements_count));
+        let oversized_reported_ref = &oversized_reported;
 
         self.run_query(
             Statement::default(),
+            RequestInfo {
+                kind: RequestKind::Batch,
+                statement: None,
+            },
             &batch.config,
             |node: Arc<Node>| async move { node.random_connection().await },
-            |connection: Arc<Connection>| async move { connection.batch(batch, values_ref).await },
+            |connection: Arc<Connection>, consistency: Option<Consistency>| async move {
+                connection
+                    .batch_with_size_check(
+                        &with_consistency(batch, consistency),
+                        values_ref,
+                        timestamp,
+                        |batch_size| {
+                            if !oversized_reported_ref.load(Ordering::Relaxed)
+                                && self.check_batch_size(batch_size)
+                            {
+                                oversized_reported_ref.store(true, Ordering::Relaxed);
+                            }
+                        },
+                    )
+                    .await
+            },
         )
+        .instrument(debug_span!("batch", statements = statements_count))
         .await
     }
 
+    /// Performs a batch with values bound to its statements when they were appended,
+    /// using [`Batch::append_statement_with_values`](crate::batch::Batch::append_statement_with_values).
+    /// Statements appended without values get empty values.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// use scylla::batch::Batch;
+    ///
+    /// let mut batch: Batch = Default::default();
+    /// batch.append_statement_with_values("INSERT INTO ks.tab(a, b) VALUES(?, ?)", (1, 2))?;
+    /// batch.append_statement_with_values("INSERT INTO ks.tab(a, b) VALUES(3, ?)", (4,))?;
+    /// batch.append_statement("INSERT INTO ks.tab(a, b) VALUES(5, 6)");
+    ///
+    /// session.batch_with_bound_values(&batch).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn batch_with_bound_values(&self, batch: &Batch) -> Result<QueryResult, QueryError> {
+        self.batch(batch, batch.get_values()).await
+    }
+
     /// Sends `USE <keyspace_name>` request on all connections  
     /// This allows to write `SELECT * FROM table` instead of `SELECT * FROM keyspace.table`  
     ///
@@ -819,8 +1332,10 @@ impl Session {
     /// # Arguments
     ///
     /// * `keyspace_name` - keyspace name to use,
-    /// keyspace names can have up to 48 alpha-numeric characters and contain underscores
-    /// * `case_sensitive` - if set to true the generated query will put keyspace name in quotes
+    /// keyspace names can have up to 48 alpha-numeric characters and contain underscores.
+    ///   A name already in double quotes, e.g. `"MyKeyspace"`, is used as a case sensitive name
+    /// * `case_sensitive` - if set to true the generated query will put keyspace name in quotes,
+    ///   escaping quotes inside of it. Case sensitive names can contain other characters accepted by the database
     /// # Example
     /// ```rust
     /// # use scylla::{Session, SessionBuilder};
@@ -850,11 +1365,45 @@ impl Session {
         // To avoid any possible CQL injections it's good to verify that the name is valid
         let verified_ks_name = VerifiedKeyspaceName::new(keyspace_name.into(), case_sensitive)?;
 
+        // Case insensitive names are lowercased by the database
+        let used_name = match verified_ks_name.is_case_sensitive {
+            true => verified_ks_name.as_str().to_owned(),
+            false => verified_ks_name.as_str().to_lowercase(),
+        };
+
         self.cluster.use_keyspace(verified_ks_name).await?;
 
+        self.keyspace_name.store(Some(Arc::new(used_name)));
+
         Ok(())
     }
 
+    /// Returns the keyspace set by the last successful [use_keyspace](Session::use_keyspace),
+    /// or `None` if no keyspace has been used yet.
+    ///
+    /// Case insensitive names are returned in lowercase, like the database stores them.
+    /// Keyspaces set on a single [Query](crate::query::Query) don't change it.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// session.use_keyspace("My_Keyspace", false).await?;
+    /// assert_eq!(session.get_keyspace(), Some("my_keyspace".to_string()));
+    ///
+    /// session.query("USE \"My_Keyspace\"", &[]).await?;
+    /// assert_eq!(session.get_keyspace(), Some("My_Keyspace".to_string()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_keyspace(&self) -> Option<String> {
+        self.keyspace_name
+            .load()
+            .as_ref()
+            .map(|name| name.as_ref().clone())
+    }
+
     /// Manually trigger a topology refresh  
     /// The driver will fetch current nodes in the cluster and update its topology information
     ///
@@ -864,6 +1413,96 @@ impl Session {
         self.cluster.refresh_topology().await
     }
 
+    /// Subscribes to events sent by the cluster - topology changes,
+    /// node status changes and schema changes.
+    ///
+    /// Returns a stream which yields all events received after the subscription.
+    /// If the stream isn't polled often enough the oldest events are skipped.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// use futures::StreamExt;
+    ///
+    /// let mut events = session.cluster_events();
+    /// while let Some(event) = events.next().await {
+    ///     println!("Received cluster event: {:?}", event);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn cluster_events(&self) -> impl Stream<Item = ClusterEvent> + Send + Unpin {
+        self.cluster.subscribe_to_events()
+    }
+
+    /// Watches schema changes affecting the given keyspace - e.g. created, altered or dropped
+    /// tables and types, or the keyspace itself.
+    ///
+    /// Returns a stream which yields a notification each time the driver observes such a change,
+    /// either by receiving a schema change event or by noticing a difference in schema metadata.
+    /// Events are the ones delivered by [`schema_change_receiver`](Session::schema_change_receiver),
+    /// filtered to the given keyspace.
+    /// After a schema change event only the affected keyspace's metadata is fetched again,
+    /// other changes are noticed during topology refresh. A single change can be reported more than once.
+    /// If the stream isn't polled often enough the oldest notifications are skipped.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// use futures::StreamExt;
+    ///
+    /// let mut schema_changes = session.watch_schema("ks");
+    /// while let Some(change) = schema_changes.next().await {
+    ///     println!("Schema of keyspace {} changed", change.keyspace_name());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn watch_schema(
+        &self,
+        keyspace_name: impl Into<String>,
+    ) -> impl Stream<Item = SchemaChangeNotification> + Send + Unpin {
+        self.cluster.watch_schema(keyspace_name.into())
+    }
+
+    /// Returns a receiver of schema change events sent by the cluster - e.g. created, altered
+    /// or dropped keyspaces, tables and types in any keyspace.
+    ///
+    /// Only events received after calling this function are delivered. Unlike [`watch_schema`](Session::watch_schema)
+    /// changes noticed only during topology refresh aren't reported.
+    /// If the receiver isn't polled often enough, it gets [`RecvError::Lagged`](tokio::sync::broadcast::error::RecvError::Lagged)
+    /// and the oldest events are skipped.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use scylla::Session;
+    /// # use std::error::Error;
+    /// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+    /// use scylla::frame::response::event::SchemaChangeEvent;
+    ///
+    /// let mut schema_changes = session.schema_change_receiver();
+    /// while let Ok(event) = schema_changes.recv().await {
+    ///     if let SchemaChangeEvent::TableChange { keyspace_name, object_name, .. } = event {
+    ///         println!("Table {}.{} changed", keyspace_name, object_name);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn schema_change_receiver(&self) -> broadcast::Receiver<SchemaChangeEvent> {
+        self.cluster.schema_change_receiver()
+    }
+
+    /// Returns current information about the cluster - nodes, keyspaces, cluster name and versions.
+    /// The returned data is a snapshot, it isn't updated after a topology refresh.
+    pub fn get_cluster_data(&self) -> Arc<ClusterData> {
+        self.cluster.get_data()
+    }
+
     /// Access metrics collected by the driver  
     /// Driver collects various metrics like number of queries or query latencies.
     /// They can be read using this method
@@ -871,12 +1510,13 @@ impl Session {
         self.metrics.clone()
     }
 
-    /// Get [`TracingInfo`] of a traced query performed earlier
+    /// Get [`TracingInfo`] of a traced query performed earlier.
+    /// Attempts are made according to [`SessionConfig::tracing_info_fetch_config`].
     ///
     /// See [the book](https://cvybhu.github.io/scyllabook/tracing/tracing.html)
     /// for more information about query tracing
     pub async fn get_tracing_info(&self, tracing_id: &Uuid) -> Result<TracingInfo, QueryError> {
-        self.get_tracing_info_custom(tracing_id, &GetTracingConfig::default())
+        self.get_tracing_info_custom(tracing_id, &self.tracing_info_fetch_config)
             .await
     }
 
@@ -897,16 +1537,14 @@ impl Session {
 
             match current_try {
                 Some(tracing_info) => return Ok(tracing_info),
-                None => tokio::time::sleep(config.interval).await,
+                None => runtime::sleep(config.interval).await,
             };
         }
 
-        Err(QueryError::ProtocolError(
-            "All tracing queries returned an empty result, \
-            maybe information didnt reach this node yet. \
-            Consider using get_tracing_info_custom with \
-            bigger interval in GetTracingConfig",
-        ))
+        Err(ProtocolError::TracingInfoNotFound {
+            tracing_id: *tracing_id,
+        }
+        .into())
     }
 
     // Tries getting the tracing info
@@ -920,50 +1558,56 @@ impl Session {
         // Query system_traces.sessions for TracingInfo
         let mut traces_session_query =
             Query::new(crate::tracing::TRACES_SESSION_QUERY_STR.to_string());
-        traces_session_query.config.consistency = consistency;
+        traces_session_query.config.consistency = Some(consistency);
 
         // Query system_traces.events for TracingEvents
         let mut traces_events_query =
             Query::new(crate::tracing::TRACES_EVENTS_QUERY_STR.to_string());
-        traces_events_query.config.consistency = Consistency::One;
-        traces_events_query.config.consistency = consistency;
+        traces_events_query.config.consistency = Some(consistency);
 
         let (traces_session_res, traces_events_res) = tokio::try_join!(
-            self.query(traces_session_query, (tracing_id,)),
-            self.query(traces_events_query, (tracing_id,))
+            self.query_response(&traces_session_query, (tracing_id,), None),
+            self.query_response(&traces_events_query, (tracing_id,), None)
         )?;
+        let (session_node, events_node) = (traces_session_res.node, traces_events_res.node);
 
         // Get tracing info
         let tracing_info_row_res: Option<Result<TracingInfo, _>> = traces_session_res
+            .into_query_result()?
             .rows
-            .ok_or(QueryError::ProtocolError(
-                "Response to system_traces.sessions query was not Rows",
-            ))?
+            .ok_or_else(|| {
+                ProtocolError::system_table(
+                    session_node,
+                    "system_traces.sessions",
+                    "response is not Rows",
+                )
+            })?
             .into_typed::<TracingInfo>()
             .next();
 
         let mut tracing_info: TracingInfo = match tracing_info_row_res {
-            Some(tracing_info_row_res) => tracing_info_row_res.map_err(|_| {
-                QueryError::ProtocolError(
-                    "Columns from system_traces.session have an unexpected type",
-                )
+            Some(tracing_info_row_res) => tracing_info_row_res.map_err(|err| {
+                ProtocolError::system_table(session_node, "system_traces.sessions", err.to_string())
             })?,
             None => return Ok(None),
         };
 
         // Get tracing events
         let tracing_event_rows = traces_events_res
+            .into_query_result()?
             .rows
-            .ok_or(QueryError::ProtocolError(
-                "Response to system_traces.events query was not Rows",
-            ))?
+            .ok_or_else(|| {
+                ProtocolError::system_table(
+                    events_node,
+                    "system_traces.events",
+                    "response is not Rows",
+                )
+            })?
             .into_typed::<TracingEvent>();
 
         for event in tracing_event_rows {
-            let tracing_event: TracingEvent = event.map_err(|_| {
-                QueryError::ProtocolError(
-                    "Columns from system_traces.events have an unexpected type",
-                )
+            let tracing_event: TracingEvent = event.map_err(|err| {
+                ProtocolError::system_table(events_node, "system_traces.events", err.to_string())
             })?;
 
             tracing_info.events.push(tracing_event);
@@ -976,6 +1620,35 @@ impl Session {
         Ok(Some(tracing_info))
     }
 
+    fn retry_policy_for<'a>(&'a self, config: &'a StatementConfig) -> &'a Arc<dyn RetryPolicy> {
+        config.retry_policy.as_ref().unwrap_or(&self.retry_policy)
+    }
+
+    // Timestamp set on the statement or generated by the session's generator.
+    // It's generated once for each request, all attempts of the request send the same timestamp
+    fn statement_timestamp(&self, timestamp: Option<i64>) -> Option<i64> {
+        timestamp.or_else(|| {
+            self.timestamp_generator
+                .as_ref()
+                .map(|generator| generator.next_timestamp())
+        })
+    }
+
+    // Pages of iterators are fetched in the background, so session's defaults
+    // are stored in the statement. Returns the retry session used for fetching.
+    fn prepare_for_iterator(
+        &self,
+        statement: &mut impl StatementSettings,
+    ) -> Box<dyn RetrySession> {
+        let timestamp = self.statement_timestamp(statement.get_timestamp());
+        let config = statement.config_mut();
+        config.consistency = Some(config.consistency.unwrap_or(self.default_consistency));
+        config.prefetch_pages = Some(config.prefetch_pages.unwrap_or(self.prefetch_pages));
+        config.timestamp = timestamp;
+
+        self.retry_policy_for(statement.config()).new_session()
+    }
+
     // This method allows to easily run a query using load balancing, retry policy etc.
     // Requires some information about the query and two closures
     // First closure is used to choose a connection
@@ -991,14 +1664,30 @@ impl Session {
     async fn run_query<'a, ConnFut, QueryFut, ResT>(
         &'a self,
         statement_info: Statement<'a>,
+        request: RequestInfo<'_>,
         statement_config: &StatementConfig,
         choose_connection: impl Fn(Arc<Node>) -> ConnFut,
-        do_query: impl Fn(Arc<Connection>) -> QueryFut,
+        do_query: impl Fn(Arc<Connection>, Option<Consistency>) -> QueryFut,
     ) -> Result<ResT, QueryError>
     where
         ConnFut: Future<Output = Result<Arc<Connection>, QueryError>>,
         QueryFut: Future<Output = Result<ResT, QueryError>>,
     {
+        // Wait while max_concurrent_requests other requests are running
+        let _permit = match &self.request_permits {
+            Some(permits) => Some(
+                permits
+                    .acquire()
+                    .await
+                    .expect("Session never closes its semaphore"),
+            ),
+            None => None,
+        };
+
+        if let Some(listener) = &self.request_listener {
+            listener.on_request_start(&request);
+        }
+
         let cluster_data = self.cluster.get_data();
         let query_plan = self.load_balancer.plan(&statement_info, &cluster_data);
 
@@ -1023,100 +1712,164 @@ impl Session {
             }
         }
 
-        let retry_policy = match &statement_config.retry_policy {
-            Some(policy) => policy,
-            None => &self.retry_policy,
-        };
+        let retry_policy = self.retry_policy_for(statement_config);
+
+        let consistency = statement_config
+            .consistency
+            .unwrap_or(self.default_consistency);
 
         let speculative_policy = statement_config
             .speculative_execution_policy
             .as_ref()
             .or_else(|| self.speculative_execution_policy.as_ref());
 
-        match speculative_policy {
-            Some(speculative) if statement_config.is_idempotent => {
-                let shared_query_plan = SharedPlan {
-                    iter: std::sync::Mutex::new(query_plan),
-                };
-
-                let execute_query_generator = || {
-                    self.execute_query(
-                        &shared_query_plan,
+        let execution = async {
+            match speculative_policy {
+                Some(speculative) if statement_config.is_idempotent => {
+                    let shared_query_plan = SharedPlan {
+                        iter: std::sync::Mutex::new(query_plan),
+                    };
+
+                    let execute_query_generator = || {
+                        self.execute_query(
+                            &shared_query_plan,
+                            &request,
+                            statement_config.is_idempotent,
+                            consistency,
+                            retry_policy.new_session(),
+                            &choose_connection,
+                            &do_query,
+                        )
+                    };
+
+                    let context = speculative_execution::Context {
+                        metrics: self.metrics.clone(),
+                    };
+
+                    speculative_execution::execute(
+                        speculative.as_ref(),
+                        &context,
+                        execute_query_generator,
+                    )
+                    .await
+                }
+                _ => self
+                    .execute_query(
+                        query_plan,
+                        &request,
                         statement_config.is_idempotent,
-                        statement_config.consistency,
+                        consistency,
                         retry_policy.new_session(),
                         &choose_connection,
                         &do_query,
                     )
-                };
-
-                let context = speculative_execution::Context {
-                    metrics: self.metrics.clone(),
-                };
+                    .await
+                    .unwrap_or_else(|| Err(ProtocolError::EmptyQueryPlan.into())),
+            }
+        };
 
-                speculative_execution::execute(
-                    speculative.as_ref(),
-                    &context,
-                    execute_query_generator,
-                )
+        let request_timeout = statement_config.request_timeout.or(self.request_timeout);
+        let result = match request_timeout {
+            Some(duration) => timeout(duration, execution)
                 .await
+                .unwrap_or(Err(QueryError::TimeoutError)),
+            None => execution.await,
+        };
+
+        if let (Some(listener), Err(error)) = (&self.request_listener, &result) {
+            listener.on_error(&request, error);
+        }
+        result
+    }
+
+    // Logs a warning and updates metrics if the batch has more statements than the configured threshold.
+    // Returns whether the batch was reported as oversized
+    fn check_batch_statements_count(&self, statements_count: usize) -> bool {
+        match self.batch_statements_warn_threshold {
+            Some(threshold) if statements_count > threshold => {
+                warn!(
+                    "Batch contains {} statements, which exceeds the warn threshold of {} statements",
+                    statements_count, threshold
+                );
+                self.metrics.inc_oversized_batches();
+                true
             }
-            _ => self
-                .execute_query(
-                    query_plan,
-                    statement_config.is_idempotent,
-                    statement_config.consistency,
-                    retry_policy.new_session(),
-                    &choose_connection,
-                    &do_query,
-                )
-                .await
-                .unwrap_or(Err(QueryError::ProtocolError(
-                    "Empty query plan - driver bug!",
-                ))),
+            _ => false,
         }
     }
 
+    // Logs a warning and updates metrics if the serialized batch is larger than the configured threshold.
+    // Returns whether the batch was reported as oversized
+    fn check_batch_size(&self, batch_size: usize) -> bool {
+        match self.batch_size_warn_threshold {
+            Some(threshold) if batch_size > threshold => {
+                warn!(
+                    "Batch size is {} bytes, which exceeds the warn threshold of {} bytes",
+                    batch_size, threshold
+                );
+                self.metrics.inc_oversized_batches();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     async fn execute_query<ConnFut, QueryFut, ResT>(
         &self,
         query_plan: impl Iterator<Item = Arc<Node>>,
+        request: &RequestInfo<'_>,
         is_idempotent: bool,
         consistency: Consistency,
         mut retry_session: Box<dyn RetrySession>,
         choose_connection: impl Fn(Arc<Node>) -> ConnFut,
-        do_query: impl Fn(Arc<Connection>) -> QueryFut,
+        do_query: impl Fn(Arc<Connection>, Option<Consistency>) -> QueryFut,
     ) -> Option<Result<ResT, QueryError>>
     where
         ConnFut: Future<Output = Result<Arc<Connection>, QueryError>>,
         QueryFut: Future<Output = Result<ResT, QueryError>>,
     {
         let mut last_error: Option<QueryError> = None;
+        // Consistency set by the retry policy, used instead of the statement's one
+        let mut consistency_override: Option<Consistency> = None;
 
         'nodes_in_plan: for node in query_plan {
             'same_node_retries: loop {
                 let connection: Arc<Connection> = match choose_connection(node.clone()).await {
                     Ok(connection) => connection,
                     Err(e) => {
+                        debug!("No working connection to node {}: {}", node.address, e);
                         last_error = Some(e);
                         // Broken connection doesn't count as a failed query, don't log in metrics
                         continue 'nodes_in_plan;
                     }
                 };
 
-                self.metrics.inc_total_nonpaged_queries();
+                let target = self.metrics.target(node.address, &connection);
+                self.metrics.inc_total_nonpaged_queries(&target);
                 let query_start = std::time::Instant::now();
 
-                let query_result: Result<ResT, QueryError> = do_query(connection).await;
+                trace!("Sending request to node {}", node.address);
+                if let Some(listener) = &self.request_listener {
+                    listener.on_attempt(request, node.address);
+                }
+                let query_result: Result<ResT, QueryError> =
+                    do_query(connection, consistency_override).await;
 
                 last_error = match query_result {
                     Ok(response) => {
+                        let latency = query_start.elapsed();
                         let _ = self
                             .metrics
-                            .log_query_latency(query_start.elapsed().as_millis() as u64);
+                            .log_query_latency(&target, latency.as_millis() as u64);
+                        if let Some(listener) = &self.request_listener {
+                            listener.on_success(request, node.address, latency);
+                        }
                         return Some(Ok(response));
                     }
                     Err(e) => {
-                        self.metrics.inc_failed_nonpaged_queries();
+                        debug!("Request to node {} failed: {}", node.address, e);
+                        self.metrics.inc_failed_nonpaged_queries(&target);
                         Some(e)
                     }
                 };
@@ -1125,18 +1878,35 @@ impl Session {
                 let query_info = QueryInfo {
                     error: last_error.as_ref().unwrap(),
                     is_idempotent,
-                    consistency,
+                    consistency: consistency_override.unwrap_or(consistency),
                 };
 
-                match retry_session.decide_should_retry(query_info) {
-                    RetryDecision::RetrySameNode => {
-                        self.metrics.inc_retries_num();
+                let decision = retry_session.decide_should_retry(query_info);
+                debug!("Retry policy decision: {:?}", decision);
+                if let Some(listener) = &self.request_listener {
+                    listener.on_retry_decision(
+                        request,
+                        node.address,
+                        last_error.as_ref().unwrap(),
+                        &decision,
+                    );
+                }
+                match decision {
+                    RetryDecision::RetrySameNode(delay) => {
+                        self.metrics.inc_retries_num(&target);
+                        sleep_before_retry(delay).await;
                         continue 'same_node_retries;
                     }
-                    RetryDecision::RetryNextNode => {
-                        self.metrics.inc_retries_num();
+                    RetryDecision::RetryNextNode(delay) => {
+                        self.metrics.inc_retries_num(&target);
+                        sleep_before_retry(delay).await;
                         continue 'nodes_in_plan;
                     }
+                    RetryDecision::RetryWithConsistency(new_consistency) => {
+                        self.metrics.inc_retries_num(&target);
+                        consistency_override = Some(new_consistency);
+                        continue 'same_node_retries;
+                    }
                     RetryDecision::DontRetry => return last_error.map(Result::Err),
                 };
             }
@@ -1147,7 +1917,7 @@ impl Session {
 
     pub async fn await_schema_agreement(&self) -> Result<(), QueryError> {
         while !self.check_schema_agreement().await? {
-            tokio::time::sleep(self.schema_agreement_interval).await
+            runtime::sleep(self.schema_agreement_interval).await
         }
         Ok(())
     }
@@ -1161,6 +1931,23 @@ impl Session {
             .map_or(Ok(false), |res| res.and(Ok(true)))
     }
 
+    // Waits for schema agreement after a query which changed the schema,
+    // if auto_await_schema_agreement_timeout is set
+    async fn handle_auto_await_schema_agreement(
+        &self,
+        response: &NonErrorQueryResponse,
+    ) -> Result<(), QueryError> {
+        if let Some(timeout_duration) = self.auto_await_schema_agreement_timeout {
+            if response.as_schema_change().is_some()
+                && !self.await_timed_schema_agreement(timeout_duration).await?
+            {
+                return Err(QueryError::TimeoutError);
+            }
+        }
+
+        Ok(())
+    }
+
     async fn schema_agreement_auxilary<ResT, QueryFut>(
         &self,
         do_query: impl Fn(Arc<Connection>) -> QueryFut,
@@ -1177,9 +1964,13 @@ impl Session {
 
         self.run_query(
             info,
+            RequestInfo {
+                kind: RequestKind::Internal,
+                statement: None,
+            },
             &config,
             |node: Arc<Node>| async move { node.random_connection().await },
-            do_query,
+            |connection: Arc<Connection>, _consistency| do_query(connection),
         )
         .await
     }
@@ -1206,39 +1997,140 @@ impl Session {
     }
 }
 
-/// Checks if a query sets a keyspace
-fn query_is_setting_keyspace(query: &str) -> bool {
-    let query_bytes = query.as_bytes();
+// Returns the statement with the consistency chosen by the retry policy, if there is one
+pub(crate) fn with_consistency<S: StatementSettings + Clone>(
+    statement: &S,
+    consistency: Option<Consistency>,
+) -> Cow<'_, S> {
+    match consistency {
+        Some(consistency) => {
+            let mut statement = statement.clone();
+            statement.set_consistency(consistency);
+            Cow::Owned(statement)
+        }
+        None => Cow::Borrowed(statement),
+    }
+}
+
+pub(crate) async fn sleep_before_retry(delay: Option<Duration>) {
+    if let Some(delay) = delay {
+        runtime::sleep(delay).await;
+    }
+}
+
+/// Checks if a query is a `USE <keyspace>` statement and returns the keyspace name as written,
+/// quoted names keep their quotes. Case of the keyword and whitespace around tokens don't matter.
+fn parse_use_keyspace(query: &str) -> Option<&str> {
+    let query = query.trim();
+    let keyword = query.get(..3)?;
+    if !keyword.eq_ignore_ascii_case("use") {
+        return None;
+    }
+
+    let rest = &query[3..];
+    // The keyword has to be separated from an unquoted name, e.g. "useful" isn't a USE statement
+    if !rest.starts_with(|c: char| c.is_whitespace() || c == '"') {
+        return None;
+    }
+
+    let name = rest.trim_start();
+    let name = name.strip_suffix(';').unwrap_or(name).trim_end();
 
-    if query_bytes.len() < 4 {
-        return false;
+    if name.is_empty() {
+        return None;
     }
 
-    query_bytes[0..=3].eq_ignore_ascii_case("use ".as_bytes())
+    let is_single_token = match name.strip_prefix('"') {
+        // Quotes inside of a quoted name are escaped by doubling them,
+        // any other quote between the outer ones ends the name early
+        Some(quoted) => match quoted.strip_suffix('"') {
+            Some(body) if !body.is_empty() => body.split("\"\"").all(|part| !part.contains('"')),
+            _ => false,
+        },
+        None => !name.contains(|c: char| c.is_whitespace() || c == '"' || c == ';'),
+    };
+
+    if is_single_token {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+thread_local! {
+    // Scratch buffer for partition keys, so that routing a statement doesn't allocate
+    static PARTITION_KEY_BUF: RefCell<BytesMut> = RefCell::new(BytesMut::new());
 }
 
 fn calculate_token(
     stmt: &PreparedStatement,
     values: &SerializedValues,
 ) -> Result<Token, QueryError> {
-    // TODO: take the partitioner of the table that is being queried and calculate the token using
-    // that partitioner. The below logic gives correct token only for murmur3partitioner
-    let partition_key = match stmt.compute_partition_key(values) {
-        Ok(key) => key,
-        Err(PartitionKeyError::NoPkIndexValue(_, _)) => {
-            return Err(QueryError::ProtocolError(
-                "No pk indexes - can't calculate token",
-            ))
+    PARTITION_KEY_BUF.with(|buf| {
+        let mut partition_key = buf.borrow_mut();
+        match stmt.write_partition_key(values, &mut partition_key) {
+            Ok(()) => Ok(stmt.get_partitioner().token(&partition_key[..])),
+            Err(PartitionKeyError::NoPkIndexValue(pk_index, values_len)) => {
+                Err(ProtocolError::MissingPartitionKeyValue {
+                    pk_index,
+                    values_len,
+                }
+                .into())
+            }
+            Err(PartitionKeyError::ValueTooLong(values_len)) => Err(QueryError::BadQuery(
+                BadQuery::ValuesTooLongForKey(values_len, u16::max_value().into()),
+            )),
+            Err(PartitionKeyError::UnboundPkValue(pk_index)) => Err(QueryError::BadQuery(
+                BadQuery::UnboundPartitionKeyValue(pk_index),
+            )),
         }
-        Err(PartitionKeyError::ValueTooLong(values_len)) => {
-            return Err(QueryError::BadQuery(BadQuery::ValuesTooLongForKey(
-                values_len,
-                u16::max_value().into(),
+    })
+}
+
+// Finds the address of the node listening on the unix domain socket at `path`.
+// Other nodes list it under this address in system.peers, so it's used to identify the node
+async fn resolve_unix_socket(
+    path: &Path,
+    mut config: ConnectionConfig,
+) -> Result<SocketAddr, NewSessionError> {
+    // The address isn't known yet, so the socket is used for a placeholder one
+    let placeholder = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 9042);
+    let mut overrides = HashMap::new();
+    overrides.insert(placeholder.ip(), path.to_path_buf());
+    config.unix_socket_overrides = Arc::new(overrides);
+
+    let (connection, _) = open_connection(placeholder, None, config).await?;
+    let rows = connection
+        .query_single_page(
+            "select rpc_address, broadcast_address from system.local",
+            &[],
+        )
+        .await?
+        .rows
+        .ok_or_else(|| {
+            ProtocolError::system_table(placeholder, "system.local", "response is not Rows")
+        })?;
+
+    let (rpc_address, broadcast_address) = rows
+        .into_typed::<(Option<IpAddr>, Option<IpAddr>)>()
+        .next()
+        .ok_or_else(|| {
+            ProtocolError::system_table(placeholder, "system.local", "no rows returned")
+        })?
+        .map_err(|err| ProtocolError::system_table(placeholder, "system.local", err.to_string()))?;
+
+    let ip = match (rpc_address, broadcast_address) {
+        (Some(rpc_address), _) if !rpc_address.is_unspecified() => rpc_address,
+        (_, Some(broadcast_address)) => broadcast_address,
+        _ => {
+            return Err(NewSessionError::FailedToResolveAddress(format!(
+                "{} (system.local has no address)",
+                path.display()
             )))
         }
     };
 
-    Ok(murmur3_token(partition_key))
+    Ok(SocketAddr::new(ip, 9042))
 }
 
 // Resolve the given hostname using a DNS lookup if necessary.
@@ -1247,10 +2139,13 @@ fn calculate_token(
 async fn resolve_hostname(hostname: &str) -> Result<SocketAddr, NewSessionError> {
     let failed_err = NewSessionError::FailedToResolveAddress(hostname.to_string());
     let mut ret = None;
-    let addrs: Vec<SocketAddr> = match lookup_host(hostname).await {
-        Ok(addrs) => addrs.collect(),
+    let addrs: Vec<SocketAddr> = match DefaultRuntime::lookup_host(hostname.to_string(), None).await
+    {
+        Ok(addrs) => addrs,
         // Use a default port in case of error, but propagate the original error on failure
-        Err(e) => lookup_host((hostname, 9042)).await.or(Err(e))?.collect(),
+        Err(e) => DefaultRuntime::lookup_host(hostname.to_string(), Some(9042))
+            .await
+            .or(Err(e))?,
     };
     for a in addrs {
         match a {
@@ -1269,12 +2164,115 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_query_is_setting_keyspace() {
-        assert!(query_is_setting_keyspace("use some_keyspace"));
-        assert!(query_is_setting_keyspace("UsE anotherKeySpace;"));
-        assert!(query_is_setting_keyspace("USE SCREAMINGKEYSPACE"));
-        assert!(!query_is_setting_keyspace("select * from users;"));
-        assert!(!query_is_setting_keyspace("us"));
-        assert!(!query_is_setting_keyspace(""));
+    fn test_parse_use_keyspace() {
+        assert_eq!(
+            parse_use_keyspace("use some_keyspace"),
+            Some("some_keyspace")
+        );
+        assert_eq!(
+            parse_use_keyspace("UsE anotherKeySpace;"),
+            Some("anotherKeySpace")
+        );
+        assert_eq!(
+            parse_use_keyspace("USE SCREAMINGKEYSPACE"),
+            Some("SCREAMINGKEYSPACE")
+        );
+        assert_eq!(parse_use_keyspace("select * from users;"), None);
+        assert_eq!(parse_use_keyspace("us"), None);
+        assert_eq!(parse_use_keyspace(""), None);
+
+        // Whitespace around tokens
+        assert_eq!(parse_use_keyspace("  use\tks  ;  "), Some("ks"));
+        assert_eq!(parse_use_keyspace("\nUSE\n\tks\n"), Some("ks"));
+        assert_eq!(parse_use_keyspace("use ks ;"), Some("ks"));
+
+        // Quoted names
+        assert_eq!(parse_use_keyspace("use \"MyKs\";"), Some("\"MyKs\""));
+        assert_eq!(parse_use_keyspace("USE\"MyKs\""), Some("\"MyKs\""));
+        assert_eq!(
+            parse_use_keyspace("use \"with space\""),
+            Some("\"with space\"")
+        );
+        assert_eq!(parse_use_keyspace("use \"a\"\"b\" ; "), Some("\"a\"\"b\""));
+        assert_eq!(parse_use_keyspace("use \"a\"b\""), None);
+        assert_eq!(parse_use_keyspace("use \"a\" \"b\""), None);
+        assert_eq!(parse_use_keyspace("use \"a\"\"\"b\""), None);
+        assert_eq!(parse_use_keyspace("use \"unterminated"), None);
+        assert_eq!(parse_use_keyspace("use \""), None);
+
+        // Not a single USE statement
+        assert_eq!(parse_use_keyspace("useful_table"), None);
+        assert_eq!(parse_use_keyspace("use"), None);
+        assert_eq!(parse_use_keyspace("use ;"), None);
+        assert_eq!(parse_use_keyspace("use ks other"), None);
+        assert_eq!(parse_use_keyspace("use ks; select * from tab"), None);
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn oversized_batches_are_counted() {
+        use crate::proxy::{MockNode, MockResponse};
+        use crate::SessionBuilder;
+
+        let node = MockNode::new()
+            .default_response(MockResponse::Void)
+            .run()
+            .await
+            .unwrap();
+        let session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .batch_size_warn_threshold(200)
+            .build()
+            .await
+            .unwrap();
+
+        let mut batch = Batch::default();
+        batch.append_statement("INSERT INTO ks.t (a) VALUES (?)");
+
+        session.batch(&batch, (("small",),)).await.unwrap();
+        assert_eq!(session.get_metrics().get_oversized_batches_num(), 0);
+
+        // The size of the serialized values counts
+        let large_value = "a".repeat(300);
+        session.batch(&batch, ((&large_value,),)).await.unwrap();
+        assert_eq!(session.get_metrics().get_oversized_batches_num(), 1);
+
+        node.finish().await.unwrap();
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn request_timeout_applies_to_batches() {
+        use crate::proxy::{MockNode, MockResponse, MockRule};
+        use crate::statement::query::Query;
+        use crate::SessionBuilder;
+
+        let node = MockNode::new()
+            .rule(
+                MockRule::new("INSERT INTO ks.slow", MockResponse::Void)
+                    .delay(Duration::from_millis(500)),
+            )
+            .default_response(MockResponse::Void)
+            .run()
+            .await
+            .unwrap();
+        let session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .request_timeout(Duration::from_millis(50))
+            .build()
+            .await
+            .unwrap();
+
+        let mut batch = Batch::default();
+        batch.append_statement("INSERT INTO ks.slow (a) VALUES (1)");
+        let error = session.batch(&batch, ((),)).await.unwrap_err();
+        assert!(matches!(error, QueryError::TimeoutError), "{:?}", error);
+
+        // The statement's own timeout overrides the session default
+        let mut query = Query::new("INSERT INTO ks.slow (a) VALUES (1)".to_string());
+        query.set_request_timeout(Some(Duration::from_secs(5)));
+        session.query(query, ()).await.unwrap();
+
+        node.finish().await.unwrap();
     }
 }
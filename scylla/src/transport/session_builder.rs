@@ -1,18 +1,31 @@
 //! SessionBuilder provides an easy way to create new Sessions
 
+use super::address_translator::AddressTranslator;
+use super::authentication::Authenticator;
 use super::errors::NewSessionError;
+use super::host_filter::HostFilter;
 use super::load_balancing::LoadBalancingPolicy;
+use super::node::PoolSize;
+use super::reconnection_policy::ReconnectionPolicy;
+use super::request_listener::RequestListener;
 use super::session::{Session, SessionConfig};
 use super::speculative_execution::SpeculativeExecutionPolicy;
-use super::Compression;
+use super::timestamp_generator::TimestampGenerator;
+use super::{Compression, ProtocolVersion};
+use crate::statement::Consistency;
+use crate::tracing::GetTracingConfig;
 use crate::transport::retry_policy::RetryPolicy;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
 #[cfg(feature = "ssl")]
 use openssl::ssl::SslContext;
 
+#[cfg(feature = "fault-injection")]
+use super::fault_injection::FaultInjectionPolicy;
+
 /// SessionBuilder is used to create new Session instances
 /// # Example
 ///
@@ -28,6 +41,7 @@ use openssl::ssl::SslContext;
 /// # Ok(())
 /// # }
 /// ```
+#[derive(Clone)]
 pub struct SessionBuilder {
     pub config: SessionConfig,
 }
@@ -121,6 +135,75 @@ impl SessionBuilder {
         self
     }
 
+    /// Add a known node reachable through a unix domain socket,
+    /// e.g. a co-located server or a local proxy.
+    /// The node's address is read from `system.local` and all later connections to it
+    /// go through the socket.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node_unix_socket("/var/run/scylla/cql.sock")
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn known_node_unix_socket(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.add_known_node_unix_socket(path);
+        self
+    }
+
+    /// Use a different port when connecting to the node with given IP address.
+    /// By default all nodes are contacted on the same port as the initial connection.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # use std::net::{IpAddr, Ipv4Addr};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .node_port_override(IpAddr::V4(Ipv4Addr::new(172, 17, 0, 3)), 9043)
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn node_port_override(mut self, node_ip: IpAddr, port: u16) -> Self {
+        self.config.node_port_overrides.insert(node_ip, port);
+        self
+    }
+
+    /// Connect to the node with given IP address through a unix domain socket instead of TCP.
+    /// Ports of the node don't matter then, and shard-aware connecting isn't possible,
+    /// so connections to the node land on random shards.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # use std::net::{IpAddr, Ipv4Addr};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .node_unix_socket_override(
+    ///         IpAddr::V4(Ipv4Addr::new(172, 17, 0, 3)),
+    ///         "/var/run/scylla-proxy/node3.sock",
+    ///     )
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn node_unix_socket_override(mut self, node_ip: IpAddr, path: impl Into<PathBuf>) -> Self {
+        self.config
+            .node_unix_socket_overrides
+            .insert(node_ip, path.into());
+        self
+    }
+
     /// Set preferred Compression algorithm.
     /// The default is no compression.
     /// If it is not supported by database server Session will fall back to no encryption.
@@ -143,6 +226,54 @@ impl SessionBuilder {
         self
     }
 
+    /// Set the minimal size of a request body, in bytes, which gets compressed.
+    /// Smaller requests are sent uncompressed, because compressing them
+    /// costs more CPU than it saves bandwidth.
+    /// The default is 0 - all requests are compressed if compression is enabled.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # use scylla::transport::Compression;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .compression(Some(Compression::Lz4))
+    ///     .compression_min_size(512)
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn compression_min_size(mut self, min_size: usize) -> Self {
+        self.config.compression_min_size = min_size;
+        self
+    }
+
+    /// Set the highest version of the CQL protocol used on connections.
+    /// The default is [`ProtocolVersion::V4`].
+    /// With [`ProtocolVersion::V5`] connections to nodes which don't support v5,
+    /// e.g. Scylla nodes, are reopened using v4, so v5 should be set only for clusters supporting it.
+    /// Protocol v5 supports only LZ4 compression, Snappy falls back to no compression.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # use scylla::transport::ProtocolVersion;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .protocol_version(ProtocolVersion::V5)
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn protocol_version(mut self, version: ProtocolVersion) -> Self {
+        self.config.protocol_version = version;
+        self
+    }
+
     /// Set the nodelay TCP flag.
     /// The default is true.
     ///
@@ -209,6 +340,29 @@ impl SessionBuilder {
         self
     }
 
+    /// Set a custom authenticator, e.g. implementing a Kerberos or LDAP-backed SASL mechanism.
+    /// Takes precedence over the credentials set with [`user`](SessionBuilder::user)
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// use scylla::authentication::PlainTextAuthenticator;
+    /// use std::sync::Arc;
+    ///
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .authenticator(Arc::new(PlainTextAuthenticator::new("cassandra", "cassandra")))
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn authenticator(mut self, authenticator: Arc<dyn Authenticator>) -> Self {
+        self.config.authenticator = Some(authenticator);
+        self
+    }
+
     /// Set the delay for schema agreement check. How often driver should ask if schema is in agreement
     /// The default is 200 miliseconds.
     ///
@@ -230,6 +384,49 @@ impl SessionBuilder {
         self
     }
 
+    /// Set the timeout of waiting for schema agreement after a schema changing query.
+    /// After a query which changes the schema (e.g. `CREATE TABLE`) Session will wait
+    /// until all nodes agree on the schema version before returning.
+    /// The default is 60 seconds.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # use std::time::Duration;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .auto_schema_agreement_timeout(Duration::from_secs(120))
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn auto_schema_agreement_timeout(mut self, timeout: Duration) -> Self {
+        self.config.auto_await_schema_agreement_timeout = Some(timeout);
+        self
+    }
+
+    /// Don't wait for schema agreement after schema changing queries.
+    /// Schema agreement can still be awaited manually using [`Session::await_schema_agreement`]
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .no_auto_schema_agreement()
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn no_auto_schema_agreement(mut self) -> Self {
+        self.config.auto_await_schema_agreement_timeout = None;
+        self
+    }
+
     /// Set the load balancing policy
     /// The default is Token-aware Round-robin.
     ///
@@ -293,21 +490,45 @@ impl SessionBuilder {
     /// ```
     /// # use scylla::{Session, SessionBuilder};
     /// # use scylla::transport::Compression;
+    /// # use std::sync::Arc;
     /// use scylla::transport::retry_policy::DefaultRetryPolicy;
     /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
     /// let session: Session = SessionBuilder::new()
     ///     .known_node("127.0.0.1:9042")
-    ///     .retry_policy(Box::new(DefaultRetryPolicy::new()))
+    ///     .retry_policy(Arc::new(DefaultRetryPolicy::new()))
     ///     .build()
     ///     .await?;
     /// # Ok(())
     /// # }
     /// ```
-    pub fn retry_policy(mut self, retry_policy: Box<dyn RetryPolicy + Send + Sync>) -> Self {
+    pub fn retry_policy(mut self, retry_policy: Arc<dyn RetryPolicy>) -> Self {
         self.config.retry_policy = retry_policy;
         self
     }
 
+    /// Sets the time after which queries, prepared statements and batches fail
+    /// with a timeout error, unless they set their own request timeout.
+    /// The timeout covers all attempts, including retries and speculative executions.
+    /// The default is no timeout.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # use std::time::Duration;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .request_timeout(Duration::from_secs(10))
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.config.request_timeout = Some(timeout);
+        self
+    }
+
     /// ssl feature
     /// Provide SessionBuilder with SslContext from openssl crate that will be
     /// used to create an ssl connection to the database.
@@ -340,8 +561,72 @@ impl SessionBuilder {
         self
     }
 
+    /// ssl feature
+    /// Sets the server name sent in the TLS handshake (SNI) of every connection.
+    /// Needed in deployments where nodes are reached through a proxy which routes connections by it.
+    /// Has no effect unless [`ssl_context`](SessionBuilder::ssl_context) is set.
+    /// Default is None.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # use scylla::transport::tls::ssl_context_from_pem_files;
+    /// # use std::path::Path;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("db.example.com:9142")
+    ///     .ssl_context(Some(ssl_context_from_pem_files(Path::new("ca.crt"), None)?))
+    ///     .tls_server_name("db.example.com")
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "ssl")]
+    pub fn tls_server_name(mut self, server_name: impl Into<String>) -> Self {
+        self.config.tls_server_name = Some(server_name.into());
+        self
+    }
+
+    /// Sets the policy injecting failures into connections, see [`fault_injection`](crate::transport::fault_injection).
+    /// Meant for tests only, by default no failures are injected.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # use scylla::transport::fault_injection::{ConnectFault, FaultInjectionPolicy};
+    /// # use std::net::SocketAddr;
+    /// # use std::sync::Arc;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// struct SlowConnect;
+    ///
+    /// impl FaultInjectionPolicy for SlowConnect {
+    ///     fn on_connect(&self, _addr: SocketAddr) -> ConnectFault {
+    ///         ConnectFault::Delay(std::time::Duration::from_secs(1))
+    ///     }
+    /// }
+    ///
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .fault_injection_policy(Arc::new(SlowConnect))
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "fault-injection")]
+    pub fn fault_injection_policy(mut self, policy: Arc<dyn FaultInjectionPolicy>) -> Self {
+        self.config.fault_injection_policy = Some(policy);
+        self
+    }
+
     /// Builds the Session after setting all the options
     ///
+    /// Before connecting the options are validated, invalid ones or their combinations
+    /// (e.g. no known nodes, an invalid keyspace name, a zero timeout or interval,
+    /// a non-serial default serial consistency) fail with [`NewSessionError::EmptyKnownNodesList`],
+    /// [`NewSessionError::BadQuery`] or [`NewSessionError::BadConfig`].
+    ///
     /// # Example
     /// ```
     /// # use scylla::{Session, SessionBuilder};
@@ -380,51 +665,555 @@ impl SessionBuilder {
         self.config.connect_timeout = duration;
         self
     }
-}
 
-/// Creates a [`SessionBuilder`] with default configuration, same as [`SessionBuilder::new`]
-impl Default for SessionBuilder {
-    fn default() -> Self {
-        SessionBuilder::new()
+    /// Changes timeout of fetching topology and schema information from a single node.
+    /// If the node doesn't respond in time, Session fetches the information from another node.
+    /// The default is 5 seconds.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # use std::time::Duration;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .metadata_timeout(Duration::from_secs(10))
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn metadata_timeout(mut self, timeout: Duration) -> Self {
+        self.config.metadata_timeout = timeout;
+        self
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::SessionBuilder;
-    use crate::transport::load_balancing::RoundRobinPolicy;
-    use crate::transport::session::KnownNode;
-    use crate::transport::Compression;
-    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-    use std::sync::Arc;
-
-    #[test]
-    fn default_session_builder() {
-        let builder = SessionBuilder::new();
 
-        assert!(builder.config.known_nodes.is_empty());
-        assert_eq!(builder.config.compression, None);
+    /// Changes how often the topology and schema metadata is refreshed in the background.
+    /// Topology change events sent by the cluster trigger a refresh right away,
+    /// the periodic refresh fixes stale metadata in case some events were missed.
+    /// The default is 60 seconds.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # use std::time::Duration;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .topology_refresh_interval(Duration::from_secs(10))
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn topology_refresh_interval(mut self, interval: Duration) -> Self {
+        self.config.topology_refresh_interval = interval;
+        self
     }
 
-    #[test]
-    fn add_known_node() {
-        let mut builder = SessionBuilder::new();
-
-        builder = builder.known_node("test_hostname");
-
-        assert_eq!(
-            builder.config.known_nodes,
-            vec![KnownNode::Hostname("test_hostname".into())]
-        );
-        assert_eq!(builder.config.compression, None);
+    /// Opens connection pools only to nodes in the local datacenter.
+    /// Nodes in other datacenters are still known and can be queried,
+    /// but connections to them are opened only when the first query is sent to them.
+    /// This reduces the number of cross-datacenter connections.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # use scylla::transport::load_balancing::{DcAwareRoundRobinPolicy, TokenAwarePolicy};
+    /// # use std::sync::Arc;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let dc_aware_policy = Box::new(DcAwareRoundRobinPolicy::new("us_east".to_string()));
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .load_balancing(Arc::new(TokenAwarePolicy::new(dc_aware_policy)))
+    ///     .connect_to_local_dc_only("us_east")
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn connect_to_local_dc_only(mut self, local_dc: impl Into<String>) -> Self {
+        self.config.pooled_datacenter = Some(local_dc.into());
+        self
     }
 
-    #[test]
-    fn add_known_node_addr() {
-        let mut builder = SessionBuilder::new();
-
-        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(172, 17, 0, 3)), 1357);
-        builder = builder.known_node_addr(addr);
+    /// Sets the translator of node addresses found in `system.peers`, used when nodes
+    /// advertise addresses which the client can't reach, e.g. behind a NAT or in Kubernetes.
+    /// By default addresses aren't translated.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # use std::sync::Arc;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// use scylla::transport::address_translator::MapAddressTranslator;
+    ///
+    /// let translator = MapAddressTranslator::new(vec![
+    ///     ("10.0.0.1".parse()?, "203.0.113.1:9042".parse()?),
+    ///     ("10.0.0.2".parse()?, "203.0.113.1:9043".parse()?),
+    /// ]);
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("203.0.113.1:9042")
+    ///     .address_translator(Arc::new(translator))
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn address_translator(mut self, translator: Arc<dyn AddressTranslator>) -> Self {
+        self.config.address_translator = translator;
+        self
+    }
+
+    /// Sets the filter deciding which nodes of the cluster the driver may connect to.
+    /// Rejected nodes stay in the cluster topology, but no connections are opened to them
+    /// and queries are never sent to them, e.g. to avoid crossing private network boundaries.
+    /// Known nodes are used as contact points even if the filter rejects them.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # use std::sync::Arc;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// use scylla::transport::host_filter::SubnetHostFilter;
+    ///
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("10.0.0.1:9042")
+    ///     .host_filter(Arc::new(SubnetHostFilter::new("10.0.0.0".parse()?, 8)))
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn host_filter(mut self, host_filter: Arc<dyn HostFilter>) -> Self {
+        self.config.host_filter = Some(host_filter);
+        self
+    }
+
+    /// Sets the number of connections opened to each node.
+    /// By default one connection is opened to each shard.
+    /// Broken connections are reopened in the background.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// use scylla::transport::PoolSize;
+    /// use std::num::NonZeroUsize;
+    ///
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .pool_size(PoolSize::PerShard(NonZeroUsize::new(2).unwrap()))
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn pool_size(mut self, pool_size: PoolSize) -> Self {
+        self.config.pool_size = pool_size;
+        self
+    }
+
+    /// Sets the policy deciding how long to wait between attempts to reopen a broken connection.
+    /// The default is [ExponentialReconnectionPolicy](crate::transport::reconnection_policy::ExponentialReconnectionPolicy),
+    /// with delays from 1 second up to 1 minute.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # use std::sync::Arc;
+    /// # use std::time::Duration;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// use scylla::transport::reconnection_policy::ExponentialReconnectionPolicy;
+    ///
+    /// let policy =
+    ///     ExponentialReconnectionPolicy::new(Duration::from_millis(100), Duration::from_secs(10));
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .reconnection_policy(Arc::new(policy))
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reconnection_policy(mut self, policy: Arc<dyn ReconnectionPolicy>) -> Self {
+        self.config.reconnection_policy = policy;
+        self
+    }
+
+    /// Makes each connection send an OPTIONS request every `interval`, so that idle connections
+    /// aren't closed by firewalls or NATs. Connections which don't respond before the next
+    /// request is due are closed and reopened. By default keepalive requests aren't sent.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # use std::time::Duration;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .connection_keepalive_interval(Duration::from_secs(30))
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn connection_keepalive_interval(mut self, interval: Duration) -> Self {
+        self.config.connection_keepalive_interval = Some(interval);
+        self
+    }
+
+    /// Limits the number of requests sent through a single connection and waiting for a response.
+    /// Further requests wait until some response arrives, instead of failing because
+    /// the connection ran out of stream ids. By default it's 32768, the number of stream ids,
+    /// larger values have the same effect.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .max_requests_per_connection(1024)
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn max_requests_per_connection(mut self, max_requests: usize) -> Self {
+        self.config.max_requests_per_connection = max_requests;
+        self
+    }
+
+    /// Limits the number of queries, executions and batches which the session runs at once.
+    /// Further ones wait until some of them finish, so that a burst of requests
+    /// doesn't use unbounded memory. Pages fetched by [`RowIterator`](crate::transport::iterator::RowIterator)
+    /// are limited only by [`max_requests_per_connection`](SessionBuilder::max_requests_per_connection).
+    /// By default there is no limit.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .max_concurrent_requests(4096)
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn max_concurrent_requests(mut self, max_requests: usize) -> Self {
+        self.config.max_concurrent_requests = Some(max_requests);
+        self
+    }
+
+    /// Logs a warning for every batch containing more than `max_statements` statements.
+    /// Number of such batches is available in [`Metrics`](crate::Metrics).
+    /// By default there is no limit.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .batch_statements_warn_threshold(100)
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn batch_statements_warn_threshold(mut self, max_statements: usize) -> Self {
+        self.config.batch_statements_warn_threshold = Some(max_statements);
+        self
+    }
+
+    /// Logs a warning for every batch whose statements and values take more than `max_bytes` bytes
+    /// in the serialized request. Number of such batches is available in [`Metrics`](crate::Metrics).
+    /// By default there is no limit.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .batch_size_warn_threshold(64 * 1024)
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn batch_size_warn_threshold(mut self, max_bytes: usize) -> Self {
+        self.config.batch_size_warn_threshold = Some(max_bytes);
+        self
+    }
+
+    /// Sets the consistency used by queries, prepared statements and batches
+    /// which don't specify their own consistency.
+    /// The default is `Consistency::Quorum`.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # use scylla::statement::Consistency;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .default_consistency(Consistency::LocalQuorum)
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn default_consistency(mut self, consistency: Consistency) -> Self {
+        self.config.default_consistency = consistency;
+        self
+    }
+
+    /// Sets the serial consistency used by queries, prepared statements and batches
+    /// which don't specify their own serial consistency.
+    /// By default serial consistency isn't sent and the database uses its default.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # use scylla::statement::Consistency;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .default_serial_consistency(Some(Consistency::LocalSerial))
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn default_serial_consistency(mut self, serial_consistency: Option<Consistency>) -> Self {
+        self.config.default_serial_consistency = serial_consistency;
+        self
+    }
+
+    /// Sets the generator of timestamps for queries, prepared statements and batches
+    /// which don't specify their own timestamp.
+    /// By default [`MonotonicTimestampGenerator`](crate::transport::timestamp_generator::MonotonicTimestampGenerator) is used.
+    /// With `None` timestamps aren't sent and the database assigns them.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # use std::sync::Arc;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// use scylla::transport::timestamp_generator::MonotonicTimestampGenerator;
+    ///
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .timestamp_generator(Some(Arc::new(MonotonicTimestampGenerator::new())))
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn timestamp_generator(
+        mut self,
+        timestamp_generator: Option<Arc<dyn TimestampGenerator>>,
+    ) -> Self {
+        self.config.timestamp_generator = timestamp_generator;
+        self
+    }
+
+    /// Sets how many pages are fetched in advance by [`RowIterator`](crate::transport::iterator::RowIterator)
+    /// for statements which don't specify their own value.
+    /// The default is 1, which keeps memory usage low.
+    /// Higher values help to keep the pipeline full on high-latency links.
+    /// With 0 a page is fetched only when the iterator asks for it.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .prefetch_pages(4)
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn prefetch_pages(mut self, prefetch_pages: usize) -> Self {
+        self.config.prefetch_pages = prefetch_pages;
+        self
+    }
+
+    /// Sets how [`Session::get_tracing_info`] fetches tracing information:
+    /// the number of attempts, interval between them and consistency of the queries.
+    /// Tracing tables are written asynchronously, so it can take a while until the information appears.
+    /// The default is 5 attempts, 3 milliseconds apart, with consistency `One`.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// use scylla::tracing::GetTracingConfig;
+    /// use std::num::NonZeroU32;
+    /// use std::time::Duration;
+    ///
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .tracing_info_fetch_config(GetTracingConfig {
+    ///         attempts: NonZeroU32::new(10).unwrap(),
+    ///         interval: Duration::from_millis(100),
+    ///         ..Default::default()
+    ///     })
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn tracing_info_fetch_config(mut self, config: GetTracingConfig) -> Self {
+        self.config.tracing_info_fetch_config = config;
+        self
+    }
+
+    /// Sets a listener notified about the start, attempts, retries and the outcome of each request,
+    /// e.g. to feed an external metrics system. By default no listener is set.
+    ///
+    /// # Example
+    /// ```
+    /// # use scylla::{Session, SessionBuilder};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// use scylla::transport::errors::QueryError;
+    /// use scylla::transport::request_listener::{RequestInfo, RequestListener};
+    /// use std::sync::Arc;
+    ///
+    /// struct ErrorLogger;
+    ///
+    /// impl RequestListener for ErrorLogger {
+    ///     fn on_error(&self, request: &RequestInfo<'_>, error: &QueryError) {
+    ///         println!("{:?} failed: {}", request.statement, error);
+    ///     }
+    /// }
+    ///
+    /// let session: Session = SessionBuilder::new()
+    ///     .known_node("127.0.0.1:9042")
+    ///     .request_listener(Arc::new(ErrorLogger))
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn request_listener(mut self, listener: Arc<dyn RequestListener>) -> Self {
+        self.config.request_listener = Some(listener);
+        self
+    }
+}
+
+/// Creates a [`SessionBuilder`] with default configuration, same as [`SessionBuilder::new`]
+impl Default for SessionBuilder {
+    fn default() -> Self {
+        SessionBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SessionBuilder;
+    use crate::statement::Consistency;
+    use crate::tracing::GetTracingConfig;
+    use crate::transport::address_translator::{AddressTranslator, MapAddressTranslator};
+    use crate::transport::errors::{BadQuery, BadSessionConfig, NewSessionError};
+    use crate::transport::host_filter::{DcHostFilter, HostFilter};
+    use crate::transport::load_balancing::RoundRobinPolicy;
+    use crate::transport::reconnection_policy::{ConstantReconnectionPolicy, ReconnectionPolicy};
+    use crate::transport::session::KnownNode;
+    use crate::transport::{Compression, PoolSize, ProtocolVersion};
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::num::NonZeroUsize;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn default_session_builder() {
+        let builder = SessionBuilder::new();
+
+        assert!(builder.config.known_nodes.is_empty());
+        assert_eq!(builder.config.compression, None);
+    }
+
+    #[tokio::test]
+    async fn build_validates_config() {
+        let builder = SessionBuilder::new().known_node("127.0.0.1:9042");
+
+        assert!(matches!(
+            SessionBuilder::new().build().await,
+            Err(NewSessionError::EmptyKnownNodesList)
+        ));
+        assert!(matches!(
+            builder
+                .clone()
+                .use_keyspace("bad-name", false)
+                .build()
+                .await,
+            Err(NewSessionError::BadQuery(BadQuery::BadKeyspaceName(_)))
+        ));
+        assert!(matches!(
+            builder
+                .clone()
+                .connection_timeout(Duration::from_secs(0))
+                .build()
+                .await,
+            Err(NewSessionError::BadConfig(BadSessionConfig::ZeroDuration(
+                "Connection timeout"
+            )))
+        ));
+        assert!(matches!(
+            builder
+                .clone()
+                .connection_keepalive_interval(Duration::from_secs(0))
+                .build()
+                .await,
+            Err(NewSessionError::BadConfig(BadSessionConfig::ZeroDuration(
+                "Connection keepalive interval"
+            )))
+        ));
+        assert!(matches!(
+            builder.clone().max_concurrent_requests(0).build().await,
+            Err(NewSessionError::BadConfig(
+                BadSessionConfig::ZeroRequestLimit("Max concurrent requests")
+            ))
+        ));
+        assert!(matches!(
+            builder
+                .clone()
+                .default_serial_consistency(Some(Consistency::Quorum))
+                .build()
+                .await,
+            Err(NewSessionError::BadConfig(
+                BadSessionConfig::NonSerialConsistency(Consistency::Quorum)
+            ))
+        ));
+    }
+
+    #[test]
+    fn add_known_node() {
+        let mut builder = SessionBuilder::new();
+
+        builder = builder.known_node("test_hostname");
+
+        assert_eq!(
+            builder.config.known_nodes,
+            vec![KnownNode::Hostname("test_hostname".into())]
+        );
+        assert_eq!(builder.config.compression, None);
+    }
+
+    #[test]
+    fn add_known_node_addr() {
+        let mut builder = SessionBuilder::new();
+
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(172, 17, 0, 3)), 1357);
+        builder = builder.known_node_addr(addr);
 
         assert_eq!(builder.config.known_nodes, vec![KnownNode::Address(addr)]);
         assert_eq!(builder.config.compression, None);
@@ -462,6 +1251,46 @@ mod tests {
         assert_eq!(builder.config.compression, None);
     }
 
+    #[test]
+    fn node_port_override() {
+        let mut builder = SessionBuilder::new();
+        assert!(builder.config.node_port_overrides.is_empty());
+
+        let ip = IpAddr::V4(Ipv4Addr::new(172, 17, 0, 3));
+        builder = builder.node_port_override(ip, 9043);
+        assert_eq!(builder.config.node_port_overrides.get(&ip), Some(&9043));
+    }
+
+    #[test]
+    fn add_known_node_unix_socket() {
+        let mut builder = SessionBuilder::new();
+        builder = builder.known_node_unix_socket("/tmp/scylla.sock");
+
+        assert_eq!(
+            builder.config.known_nodes,
+            vec![KnownNode::UnixSocket(PathBuf::from("/tmp/scylla.sock"))]
+        );
+    }
+
+    #[test]
+    fn node_unix_socket_override() {
+        let mut builder = SessionBuilder::new();
+        assert!(builder.config.node_unix_socket_overrides.is_empty());
+
+        let ip = IpAddr::V4(Ipv4Addr::new(172, 17, 0, 3));
+        builder = builder.node_unix_socket_override(ip, "/tmp/node3.sock");
+        assert_eq!(
+            builder.config.node_unix_socket_overrides.get(&ip),
+            Some(&PathBuf::from("/tmp/node3.sock"))
+        );
+
+        let connection_config = builder.config.get_connection_config();
+        assert_eq!(
+            connection_config.unix_socket_overrides.get(&ip),
+            Some(&PathBuf::from("/tmp/node3.sock"))
+        );
+    }
+
     #[test]
     fn compression() {
         let mut builder = SessionBuilder::new();
@@ -477,6 +1306,32 @@ mod tests {
         assert_eq!(builder.config.compression, None);
     }
 
+    #[test]
+    fn compression_min_size() {
+        let mut builder = SessionBuilder::new();
+        assert_eq!(builder.config.compression_min_size, 0);
+
+        builder = builder.compression_min_size(1024);
+        assert_eq!(builder.config.compression_min_size, 1024);
+        assert_eq!(
+            builder.config.get_connection_config().compression_min_size,
+            1024
+        );
+    }
+
+    #[test]
+    fn protocol_version() {
+        let mut builder = SessionBuilder::new();
+        assert_eq!(builder.config.protocol_version, ProtocolVersion::V4);
+
+        builder = builder.protocol_version(ProtocolVersion::V5);
+        assert_eq!(builder.config.protocol_version, ProtocolVersion::V5);
+        assert_eq!(
+            builder.config.get_connection_config().protocol_version,
+            ProtocolVersion::V5
+        );
+    }
+
     #[test]
     fn tcp_nodelay() {
         let mut builder = SessionBuilder::new();
@@ -534,6 +1389,203 @@ mod tests {
         );
     }
 
+    #[test]
+    fn auto_schema_agreement() {
+        let mut builder = SessionBuilder::new();
+        assert_eq!(
+            builder.config.auto_await_schema_agreement_timeout,
+            Some(std::time::Duration::from_secs(60))
+        );
+
+        builder = builder.no_auto_schema_agreement();
+        assert_eq!(builder.config.auto_await_schema_agreement_timeout, None);
+
+        builder = builder.auto_schema_agreement_timeout(std::time::Duration::from_secs(10));
+        assert_eq!(
+            builder.config.auto_await_schema_agreement_timeout,
+            Some(std::time::Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn metadata_timeout() {
+        let mut builder = SessionBuilder::new();
+        assert_eq!(
+            builder.config.metadata_timeout,
+            std::time::Duration::from_secs(5)
+        );
+
+        builder = builder.metadata_timeout(std::time::Duration::from_secs(10));
+        assert_eq!(
+            builder.config.metadata_timeout,
+            std::time::Duration::from_secs(10)
+        );
+    }
+
+    #[test]
+    fn topology_refresh_interval() {
+        let mut builder = SessionBuilder::new();
+        assert_eq!(
+            builder.config.topology_refresh_interval,
+            std::time::Duration::from_secs(60)
+        );
+
+        builder = builder.topology_refresh_interval(std::time::Duration::from_secs(10));
+        assert_eq!(
+            builder.config.topology_refresh_interval,
+            std::time::Duration::from_secs(10)
+        );
+    }
+
+    #[test]
+    fn connect_to_local_dc_only() {
+        let mut builder = SessionBuilder::new();
+        assert_eq!(builder.config.pooled_datacenter, None);
+
+        builder = builder.connect_to_local_dc_only("eu");
+        assert_eq!(builder.config.pooled_datacenter, Some("eu".to_string()));
+    }
+
+    #[test]
+    fn address_translator() {
+        let translator: Arc<dyn AddressTranslator> = Arc::new(MapAddressTranslator::new(vec![]));
+        let builder = SessionBuilder::new().address_translator(translator.clone());
+        assert!(Arc::ptr_eq(&builder.config.address_translator, &translator));
+    }
+
+    #[test]
+    fn host_filter() {
+        let builder = SessionBuilder::new();
+        assert!(builder.config.host_filter.is_none());
+
+        let filter: Arc<dyn HostFilter> = Arc::new(DcHostFilter::new(vec!["eu"]));
+        let builder = builder.host_filter(filter.clone());
+        assert!(Arc::ptr_eq(
+            builder.config.host_filter.as_ref().unwrap(),
+            &filter
+        ));
+    }
+
+    #[test]
+    fn pool_size() {
+        let mut builder = SessionBuilder::new();
+        assert_eq!(builder.config.pool_size, PoolSize::default());
+
+        let per_host = PoolSize::PerHost(NonZeroUsize::new(4).unwrap());
+        builder = builder.pool_size(per_host);
+        assert_eq!(builder.config.pool_size, per_host);
+    }
+
+    #[test]
+    fn reconnection_policy() {
+        let policy: Arc<dyn ReconnectionPolicy> = Arc::new(ConstantReconnectionPolicy::new(
+            std::time::Duration::from_secs(2),
+        ));
+        let builder = SessionBuilder::new().reconnection_policy(policy.clone());
+        assert!(Arc::ptr_eq(&builder.config.reconnection_policy, &policy));
+    }
+
+    #[test]
+    fn connection_keepalive_interval() {
+        let mut builder = SessionBuilder::new();
+        assert_eq!(builder.config.connection_keepalive_interval, None);
+
+        builder = builder.connection_keepalive_interval(std::time::Duration::from_secs(30));
+        assert_eq!(
+            builder.config.connection_keepalive_interval,
+            Some(std::time::Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn request_limits() {
+        let mut builder = SessionBuilder::new();
+        assert_eq!(builder.config.max_requests_per_connection, 32768);
+        assert_eq!(builder.config.max_concurrent_requests, None);
+
+        builder = builder
+            .max_requests_per_connection(128)
+            .max_concurrent_requests(1000);
+        assert_eq!(builder.config.max_requests_per_connection, 128);
+        assert_eq!(builder.config.max_concurrent_requests, Some(1000));
+    }
+
+    #[test]
+    fn batch_warn_thresholds() {
+        let mut builder = SessionBuilder::new();
+        assert_eq!(builder.config.batch_statements_warn_threshold, None);
+        assert_eq!(builder.config.batch_size_warn_threshold, None);
+
+        builder = builder
+            .batch_statements_warn_threshold(100)
+            .batch_size_warn_threshold(1024);
+        assert_eq!(builder.config.batch_statements_warn_threshold, Some(100));
+        assert_eq!(builder.config.batch_size_warn_threshold, Some(1024));
+    }
+
+    #[test]
+    fn timestamp_generator() {
+        let mut builder = SessionBuilder::new();
+        assert!(builder.config.timestamp_generator.is_some());
+
+        builder = builder.timestamp_generator(None);
+        assert!(builder.config.timestamp_generator.is_none());
+    }
+
+    #[test]
+    fn default_consistency() {
+        let mut builder = SessionBuilder::new();
+        assert_eq!(builder.config.default_consistency, Consistency::Quorum);
+        assert_eq!(builder.config.default_serial_consistency, None);
+
+        builder = builder
+            .default_consistency(Consistency::One)
+            .default_serial_consistency(Some(Consistency::LocalSerial));
+        assert_eq!(builder.config.default_consistency, Consistency::One);
+        assert_eq!(
+            builder.config.default_serial_consistency,
+            Some(Consistency::LocalSerial)
+        );
+    }
+
+    #[test]
+    fn request_timeout() {
+        let mut builder = SessionBuilder::new();
+        assert_eq!(builder.config.request_timeout, None);
+
+        builder = builder.request_timeout(Duration::from_secs(10));
+        assert_eq!(
+            builder.config.request_timeout,
+            Some(Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn prefetch_pages() {
+        let mut builder = SessionBuilder::new();
+        assert_eq!(builder.config.prefetch_pages, 1);
+
+        builder = builder.prefetch_pages(8);
+        assert_eq!(builder.config.prefetch_pages, 8);
+    }
+
+    #[test]
+    fn tracing_info_fetch_config() {
+        let mut builder = SessionBuilder::new();
+        assert_eq!(
+            builder.config.tracing_info_fetch_config,
+            GetTracingConfig::default()
+        );
+
+        let config = GetTracingConfig {
+            attempts: std::num::NonZeroU32::new(10).unwrap(),
+            interval: std::time::Duration::from_millis(100),
+            consistency: Consistency::Quorum,
+        };
+        builder = builder.tracing_info_fetch_config(config);
+        assert_eq!(builder.config.tracing_info_fetch_config, config);
+    }
+
     #[test]
     fn all_features() {
         let mut builder = SessionBuilder::new();
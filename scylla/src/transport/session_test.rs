@@ -5,8 +5,9 @@ use crate::query::Query;
 use crate::routing::hash3_x64_128;
 use crate::statement::Consistency;
 use crate::tracing::TracingInfo;
-use crate::transport::connection::{BatchResult, QueryResult};
+use crate::transport::connection::QueryResult;
 use crate::transport::errors::{BadKeyspaceName, BadQuery, DbError, QueryError};
+use crate::transport::row_sink::CsvRowSink;
 use crate::{IntoTypedRows, Session, SessionBuilder};
 use bytes::Bytes;
 use futures::StreamExt;
@@ -49,6 +50,8 @@ async fn test_unprepared_statement() {
         .await
         .unwrap()
         .rows
+        .unwrap()
+        .into_rows()
         .unwrap();
 
     let mut results: Vec<(i32, i32, &String)> = rs
@@ -66,7 +69,9 @@ async fn test_unprepared_statement() {
         vec![
             (1, 2, &String::from("abc")),
             (1, 4, &String::from("hello")),
-            (7, 11, &String::from(""))
+            (2, 3, &String::from("bound")),
+            (7, 11, &String::from("")),
+            (8, 12, &String::from("unbound"))
         ]
     );
     let mut results_from_manual_paging: Vec<Row> = vec![];
@@ -78,7 +83,7 @@ async fn test_unprepared_statement() {
             .query_paged(query.clone(), &[], paging_state)
             .await
             .unwrap();
-        results_from_manual_paging.append(&mut rs_manual.rows.unwrap());
+        results_from_manual_paging.append(&mut rs_manual.rows.unwrap().into_rows().unwrap());
         if watchdog > 30 || rs_manual.paging_state == None {
             break;
         }
@@ -140,6 +145,8 @@ async fn test_prepared_statement() {
             .await
             .unwrap()
             .rows
+            .unwrap()
+            .into_rows()
             .unwrap();
         let token: i64 = rs.first().unwrap().columns[0]
             .as_ref()
@@ -160,6 +167,8 @@ async fn test_prepared_statement() {
             .await
             .unwrap()
             .rows
+            .unwrap()
+            .into_rows()
             .unwrap();
         let token: i64 = rs.first().unwrap().columns[0]
             .as_ref()
@@ -182,6 +191,8 @@ async fn test_prepared_statement() {
             .await
             .unwrap()
             .rows
+            .unwrap()
+            .into_rows()
             .unwrap();
         let r = rs.first().unwrap();
         let a = r.columns[0].as_ref().unwrap().as_int().unwrap();
@@ -199,7 +210,7 @@ async fn test_prepared_statement() {
                 .execute_paged(&prepared_paged, &[], paging_state)
                 .await
                 .unwrap();
-            results_from_manual_paging.append(&mut rs_manual.rows.unwrap());
+            results_from_manual_paging.append(&mut rs_manual.rows.unwrap().into_rows().unwrap());
             if watchdog > 30 || rs_manual.paging_state == None {
                 break;
             }
@@ -214,6 +225,8 @@ async fn test_prepared_statement() {
             .await
             .unwrap()
             .rows
+            .unwrap()
+            .into_rows()
             .unwrap();
         let r = rs.first().unwrap();
         let a = r.columns[0].as_ref().unwrap().as_int().unwrap();
@@ -252,8 +265,6 @@ async fn test_batch() {
         .await
         .unwrap();
 
-    // TODO: Add API, that supports binding values to statements in batch creation process,
-    // to avoid problem of statements/values count mismatch
     use crate::batch::Batch;
     let mut batch: Batch = Default::default();
     batch.append_statement("INSERT INTO ks.t_batch (a, b, c) VALUES (?, ?, ?)");
@@ -264,11 +275,24 @@ async fn test_batch() {
 
     session.batch(&batch, values).await.unwrap();
 
+    // Values can also be bound to statements when they are appended
+    let mut bound_batch: Batch = Default::default();
+    bound_batch
+        .append_statement_with_values(
+            "INSERT INTO ks.t_batch (a, b, c) VALUES (?, ?, ?)",
+            (2_i32, 3_i32, "bound"),
+        )
+        .unwrap();
+    bound_batch.append_statement("INSERT INTO ks.t_batch (a, b, c) VALUES (8, 12, 'unbound')");
+    session.batch_with_bound_values(&bound_batch).await.unwrap();
+
     let rs = session
         .query("SELECT a, b, c FROM ks.t_batch", &[])
         .await
         .unwrap()
         .rows
+        .unwrap()
+        .into_rows()
         .unwrap();
 
     let mut results: Vec<(i32, i32, &String)> = rs
@@ -291,6 +315,151 @@ async fn test_batch() {
     );
 }
 
+#[tokio::test]
+async fn test_batch_lwt_result() {
+    let uri = std::env::var("SCYLLA_URI").unwrap_or_else(|_| "127.0.0.1:9042".to_string());
+    let session = SessionBuilder::new().known_node(uri).build().await.unwrap();
+
+    session.query("CREATE KEYSPACE IF NOT EXISTS ks WITH REPLICATION = {'class' : 'SimpleStrategy', 'replication_factor' : 1}", &[]).await.unwrap();
+    session
+        .query("DROP TABLE IF EXISTS ks.t_batch_lwt;", &[])
+        .await
+        .unwrap();
+    session
+        .query(
+            "CREATE TABLE IF NOT EXISTS ks.t_batch_lwt (a int, b int, c int, primary key (a, b))",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    use crate::batch::Batch;
+    let mut batch: Batch = Default::default();
+    batch.append_statement("INSERT INTO ks.t_batch_lwt (a, b, c) VALUES (1, 1, 1) IF NOT EXISTS");
+
+    // The first conditional batch is applied
+    let rows = session
+        .batch(&batch, ((),))
+        .await
+        .unwrap()
+        .rows
+        .unwrap()
+        .into_rows()
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(
+        rows[0].columns[0].as_ref().unwrap().as_boolean(),
+        Some(true)
+    );
+
+    // The second one isn't, the result contains [applied] = false
+    let rows = session
+        .batch(&batch, ((),))
+        .await
+        .unwrap()
+        .rows
+        .unwrap()
+        .into_rows()
+        .unwrap();
+    assert_eq!(
+        rows[0].columns[0].as_ref().unwrap().as_boolean(),
+        Some(false)
+    );
+
+    // The same result parsed into a ConditionalResult contains the existing row
+    let result = session.batch(&batch, ((),)).await.unwrap();
+    assert_eq!(result.was_applied(), Ok(false));
+    let result = result.into_conditional_result().unwrap();
+    assert!(!result.applied);
+    assert_eq!(result.rows.len(), 1);
+    assert_eq!(
+        result.into_existing_row::<(i32, i32, i32)>(),
+        Some(Ok((1, 1, 1)))
+    );
+}
+
+#[tokio::test]
+async fn test_query_iter_pages() {
+    let uri = std::env::var("SCYLLA_URI").unwrap_or_else(|_| "127.0.0.1:9042".to_string());
+    let session = SessionBuilder::new().known_node(uri).build().await.unwrap();
+
+    session.query("CREATE KEYSPACE IF NOT EXISTS ks WITH REPLICATION = {'class' : 'SimpleStrategy', 'replication_factor' : 1}", &[]).await.unwrap();
+    session
+        .query("DROP TABLE IF EXISTS ks.t_pages;", &[])
+        .await
+        .unwrap();
+    session
+        .query(
+            "CREATE TABLE IF NOT EXISTS ks.t_pages (a int, b int, primary key (a, b))",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    for b in 0..10 {
+        session
+            .query("INSERT INTO ks.t_pages (a, b) VALUES (1, ?)", (b,))
+            .await
+            .unwrap();
+    }
+
+    let query = Query::new("SELECT a, b FROM ks.t_pages WHERE a = 1".to_owned()).with_page_size(4);
+    let mut row_iter = session.query_iter(query, &[]).await.unwrap();
+    assert_eq!(row_iter.prefetch_pages(), 1);
+
+    // Read a single row, the first page should contain only the remaining rows
+    let first_row = row_iter.next().await.unwrap().unwrap();
+    assert_eq!(first_row.into_typed::<(i32, i32)>().unwrap(), (1, 0));
+
+    let page_sizes: Vec<usize> = row_iter
+        .into_pages()
+        .map(|page| page.unwrap().rows.len())
+        .collect()
+        .await;
+
+    // The last page might be empty, depending on whether the database knew it was the last one
+    assert_eq!(page_sizes.iter().sum::<usize>(), 9);
+    assert_eq!(&page_sizes[..3], &[3, 4, 2]);
+
+    // Fetch pages manually, without prefetching
+    let mut query =
+        Query::new("SELECT a, b FROM ks.t_pages WHERE a = 1".to_owned()).with_page_size(4);
+    query.set_prefetch_pages(0);
+    let mut row_iter = session.query_iter(query.clone(), &[]).await.unwrap();
+    assert_eq!(row_iter.prefetch_pages(), 0);
+    assert!(row_iter.paging_state().is_none());
+
+    let first_page = row_iter.next_page().await.unwrap().unwrap();
+    assert_eq!(first_page.rows.len(), 4);
+
+    // The paging state allows to continue reading after the first page
+    let paging_state = row_iter.paging_state().cloned();
+    assert!(paging_state.is_some());
+    let rest = session
+        .query_paged(query, &[], paging_state)
+        .await
+        .unwrap()
+        .rows
+        .unwrap()
+        .into_rows()
+        .unwrap();
+    let second_page = row_iter.next_page().await.unwrap().unwrap();
+    assert_eq!(rest, second_page.rows);
+
+    // Drain all rows as CSV
+    let query = Query::new("SELECT b, a FROM ks.t_pages WHERE a = 1 AND b < 3".to_owned())
+        .with_page_size(2);
+    let mut csv = CsvRowSink::new(Vec::new());
+    session
+        .query_iter(query, &[])
+        .await
+        .unwrap()
+        .drain_into(&mut csv)
+        .await
+        .unwrap();
+    assert_eq!(csv.into_inner(), b"b,a\r\n0,1\r\n1,1\r\n2,1\r\n");
+}
+
 #[tokio::test]
 async fn test_token_calculation() {
     let uri = std::env::var("SCYLLA_URI").unwrap_or_else(|_| "127.0.0.1:9042".to_string());
@@ -328,6 +497,8 @@ async fn test_token_calculation() {
             .await
             .unwrap()
             .rows
+            .unwrap()
+            .into_rows()
             .unwrap();
         let token: i64 = rs.first().unwrap().columns[0]
             .as_ref()
@@ -372,8 +543,12 @@ async fn test_use_keyspace() {
         .await
         .unwrap();
 
+    assert_eq!(session.get_keyspace(), None);
+
     session.use_keyspace("use_ks_test", false).await.unwrap();
 
+    assert_eq!(session.get_keyspace(), Some("use_ks_test".to_string()));
+
     session
         .query("INSERT INTO tab (a) VALUES ('test2')", &[])
         .await
@@ -399,6 +574,9 @@ async fn test_use_keyspace() {
         .await
         .is_err());
 
+    // A failed use_keyspace doesn't change the reported keyspace
+    assert_eq!(session.get_keyspace(), Some("use_ks_test".to_string()));
+
     // Test that invalid keyspaces get rejected
     assert!(matches!(
         session.use_keyspace("", false).await,
@@ -430,6 +608,8 @@ async fn test_use_keyspace() {
         .await
         .unwrap();
 
+    assert_eq!(session2.get_keyspace(), Some("use_ks_test".to_string()));
+
     let mut rows2: Vec<String> = session2
         .query("SELECT * FROM tab", &[])
         .await
@@ -496,6 +676,7 @@ async fn test_use_keyspace_case_sensitivity() {
     // Use uppercase keyspace without case sesitivity
     // Should select the lowercase one
     session.use_keyspace("KS_CASE_TEST", false).await.unwrap();
+    assert_eq!(session.get_keyspace(), Some("ks_case_test".to_string()));
 
     let rows: Vec<String> = session
         .query("SELECT * from tab", &[])
@@ -512,6 +693,7 @@ async fn test_use_keyspace_case_sensitivity() {
     // Use uppercase keyspace with case sesitivity
     // Should select the uppercase one
     session.use_keyspace("KS_CASE_TEST", true).await.unwrap();
+    assert_eq!(session.get_keyspace(), Some("KS_CASE_TEST".to_string()));
 
     let rows: Vec<String> = session
         .query("SELECT * from tab", &[])
@@ -585,6 +767,17 @@ async fn test_raw_use_keyspace() {
         .query("use    RAW_USE_KS_TEST    ;", &[])
         .await
         .is_ok());
+    assert_eq!(session.get_keyspace(), Some("raw_use_ks_test".to_string()));
+
+    // Keyword case and any whitespace are accepted
+    session.query("USE system;", &[]).await.unwrap();
+    assert_eq!(session.get_keyspace(), Some("system".to_string()));
+
+    session
+        .query("\n\tUsE\n\t\"raw_use_ks_test\"\n", &[])
+        .await
+        .unwrap();
+    assert_eq!(session.get_keyspace(), Some("raw_use_ks_test".to_string()));
 }
 
 #[tokio::test]
@@ -792,6 +985,22 @@ async fn test_tracing_query_iter(session: &Session) {
     for tracing_id in traced_typed_row_iter.get_tracing_ids() {
         assert_in_tracing_table(session, *tracing_id).await;
     }
+
+    // Each page received from PageIterator has its own tracing id
+    let mut traced_query: Query = Query::new("SELECT * FROM test_tracing_ks.tab".to_string());
+    traced_query.config.tracing = true;
+
+    let mut traced_page_iter = session
+        .query_iter(traced_query, &[])
+        .await
+        .unwrap()
+        .into_pages();
+    let mut page_tracing_ids: Vec<Uuid> = Vec::new();
+    while let Some(page) = traced_page_iter.next().await {
+        page_tracing_ids.push(page.unwrap().tracing_id.unwrap());
+    }
+
+    assert_eq!(page_tracing_ids, traced_page_iter.get_tracing_ids());
 }
 
 async fn test_tracing_execute_iter(session: &Session) {
@@ -840,7 +1049,7 @@ async fn test_tracing_batch(session: &Session) {
     let mut untraced_batch: Batch = Default::default();
     untraced_batch.append_statement("INSERT INTO test_tracing_ks.tab (a) VALUES('a')");
 
-    let untraced_batch_result: BatchResult = session.batch(&untraced_batch, ((),)).await.unwrap();
+    let untraced_batch_result: QueryResult = session.batch(&untraced_batch, ((),)).await.unwrap();
     assert!(untraced_batch_result.tracing_id.is_none());
 
     // Batch with tracing enabled has a tracing uuid in result
@@ -848,7 +1057,7 @@ async fn test_tracing_batch(session: &Session) {
     traced_batch.append_statement("INSERT INTO test_tracing_ks.tab (a) VALUES('a')");
     traced_batch.config.tracing = true;
 
-    let traced_batch_result: BatchResult = session.batch(&traced_batch, ((),)).await.unwrap();
+    let traced_batch_result: QueryResult = session.batch(&traced_batch, ((),)).await.unwrap();
     assert!(traced_batch_result.tracing_id.is_some());
 
     assert_in_tracing_table(session, traced_batch_result.tracing_id.unwrap()).await;
@@ -857,7 +1066,7 @@ async fn test_tracing_batch(session: &Session) {
 async fn assert_in_tracing_table(session: &Session, tracing_uuid: Uuid) {
     let mut traces_query =
         Query::new("SELECT * FROM system_traces.sessions WHERE session_id = ?".to_string());
-    traces_query.config.consistency = Consistency::One;
+    traces_query.config.consistency = Some(Consistency::One);
 
     // Tracing info might not be immediately available
     // If rows are empty perform 8 retries with a 32ms wait in between
@@ -908,3 +1117,490 @@ async fn test_await_timed_schema_agreement() {
         .await
         .unwrap();
 }
+
+#[cfg(feature = "csv")]
+#[tokio::test]
+async fn test_csv_loader() {
+    use crate::transport::csv_loader::{CsvLoadError, CsvLoader, RowError};
+
+    let uri = std::env::var("SCYLLA_URI").unwrap_or_else(|_| "127.0.0.1:9042".to_string());
+    let session = SessionBuilder::new().known_node(uri).build().await.unwrap();
+
+    session.query("CREATE KEYSPACE IF NOT EXISTS ks WITH REPLICATION = {'class' : 'SimpleStrategy', 'replication_factor' : 1}", &[]).await.unwrap();
+    session
+        .query("DROP TABLE IF EXISTS ks.t_csv;", &[])
+        .await
+        .unwrap();
+    session
+        .query(
+            "CREATE TABLE IF NOT EXISTS ks.t_csv (a int primary key, b text, c list<int>)",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    let insert = session
+        .prepare("INSERT INTO ks.t_csv (a, b, c) VALUES (?, ?, ?)")
+        .await
+        .unwrap();
+
+    // Header columns are matched with bind markers by name, line 4 has a bad value
+    let csv = "c,a,b\r\n\"[1, 2]\",1,x\r\n,2,\"y, z\"\r\n[3],three,w\r\n";
+    let mut errors: Vec<u64> = Vec::new();
+    let progress = CsvLoader::new(&session, &insert)
+        .concurrency(2)
+        .max_errors(1)
+        .on_error(|err| errors.push(err.line()))
+        .load(csv.as_bytes())
+        .await
+        .unwrap();
+    assert_eq!((progress.rows_read, progress.rows_inserted), (3, 2));
+    assert_eq!(errors, vec![4]);
+
+    let mut rows: Vec<(i32, Option<String>, Option<Vec<i32>>)> = session
+        .query("SELECT a, b, c FROM ks.t_csv", &[])
+        .await
+        .unwrap()
+        .rows_typed()
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    rows.sort();
+    assert_eq!(
+        rows,
+        vec![
+            (1, Some("x".to_string()), Some(vec![1, 2])),
+            (2, Some("y, z".to_string()), None),
+        ]
+    );
+
+    // Without a header fields are bound in order, loading stops on the first error
+    let result = CsvLoader::new(&session, &insert)
+        .has_header(false)
+        .load("5,a,[]\r\n6,b\r\n".as_bytes())
+        .await;
+    match result {
+        Err(CsvLoadError::TooManyErrors { last_error, .. })
+            if matches!(*last_error, RowError::BadFieldCount { line: 2, .. }) => {}
+        other => panic!("Expected BadFieldCount error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_table_mapper() {
+    use crate as scylla;
+    use crate::macros::{FromRow, Table};
+    use crate::transport::table_mapper::TableMapper;
+
+    #[derive(FromRow, Table, Debug, PartialEq)]
+    #[table(keyspace = "ks", name = "t_mapper")]
+    struct Entry {
+        #[partition_key]
+        a: i32,
+        #[clustering_key]
+        b: String,
+        c: Option<i64>,
+    }
+
+    let uri = std::env::var("SCYLLA_URI").unwrap_or_else(|_| "127.0.0.1:9042".to_string());
+    let session = SessionBuilder::new().known_node(uri).build().await.unwrap();
+
+    session.query("CREATE KEYSPACE IF NOT EXISTS ks WITH REPLICATION = {'class' : 'SimpleStrategy', 'replication_factor' : 1}", &[]).await.unwrap();
+    session
+        .query("DROP TABLE IF EXISTS ks.t_mapper;", &[])
+        .await
+        .unwrap();
+    session
+        .query(
+            "CREATE TABLE IF NOT EXISTS ks.t_mapper (a int, b text, c bigint, primary key (a, b))",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    let entries: TableMapper<Entry> = TableMapper::new(&session);
+    assert_eq!(
+        entries.select_by_primary_key_statement(),
+        "SELECT a, b, c FROM ks.t_mapper WHERE a = ? AND b = ?"
+    );
+
+    for (b, c) in [("x", Some(1)), ("y", None)].iter() {
+        let entry = Entry {
+            a: 1,
+            b: b.to_string(),
+            c: *c,
+        };
+        entries.insert(&entry).await.unwrap();
+    }
+
+    let entry = entries
+        .get_by_primary_key((1, "x".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(
+        entry,
+        Some(Entry {
+            a: 1,
+            b: "x".to_string(),
+            c: Some(1)
+        })
+    );
+
+    entries.delete((1, "x".to_string())).await.unwrap();
+    assert_eq!(
+        entries
+            .get_by_primary_key((1, "x".to_string()))
+            .await
+            .unwrap(),
+        None
+    );
+
+    let all: Vec<Entry> = entries
+        .select_all()
+        .await
+        .unwrap()
+        .map(|entry| entry.unwrap())
+        .collect()
+        .await;
+    assert_eq!(
+        all,
+        vec![Entry {
+            a: 1,
+            b: "y".to_string(),
+            c: None
+        }]
+    );
+}
+
+#[tokio::test]
+async fn test_query_builder() {
+    use crate::query_builder::{col, insert_into, select, update};
+
+    let uri = std::env::var("SCYLLA_URI").unwrap_or_else(|_| "127.0.0.1:9042".to_string());
+    let session = SessionBuilder::new().known_node(uri).build().await.unwrap();
+
+    session.query("CREATE KEYSPACE IF NOT EXISTS ks WITH REPLICATION = {'class' : 'SimpleStrategy', 'replication_factor' : 1}", &[]).await.unwrap();
+    session
+        .query("DROP TABLE IF EXISTS ks.t_builder;", &[])
+        .await
+        .unwrap();
+    session
+        .query(
+            "CREATE TABLE IF NOT EXISTS ks.t_builder (a int, b int, \"Text\" text, primary key (a, b))",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    for b in 0..3 {
+        let (query, values) = insert_into("ks.t_builder")
+            .value("a", 1)
+            .value("b", b)
+            .value("Text", "x")
+            .build()
+            .unwrap();
+        let prepared = session.prepare(query).await.unwrap();
+        session.execute(&prepared, values).await.unwrap();
+    }
+
+    let (query, values) = update("ks.t_builder")
+        .set("Text", "y")
+        .and_where(col("a").eq(1))
+        .and_where(col("b").eq(2))
+        .build()
+        .unwrap();
+    session.query(query, values).await.unwrap();
+
+    let (query, values) = select("ks.t_builder")
+        .columns(&["b", "Text"])
+        .and_where(col("a").eq(1))
+        .and_where(col("b").ge(1))
+        .build()
+        .unwrap();
+    let rows: Vec<(i32, String)> = session
+        .query(query, values)
+        .await
+        .unwrap()
+        .rows_typed()
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(rows, vec![(1, "x".to_string()), (2, "y".to_string())]);
+}
+
+#[tokio::test]
+async fn test_migrations() {
+    use crate::transport::migrations::{Migration, MigrationError, MigrationState, Migrator};
+
+    let uri = std::env::var("SCYLLA_URI").unwrap_or_else(|_| "127.0.0.1:9042".to_string());
+    let session = SessionBuilder::new().known_node(uri).build().await.unwrap();
+
+    session.query("CREATE KEYSPACE IF NOT EXISTS ks WITH REPLICATION = {'class' : 'SimpleStrategy', 'replication_factor' : 1}", &[]).await.unwrap();
+    for table in &["t_migrations", "t_migrated"] {
+        session
+            .query(format!("DROP TABLE IF EXISTS ks.{}", table), &[])
+            .await
+            .unwrap();
+    }
+
+    let first = Migration::new(
+        1,
+        "create table",
+        "CREATE TABLE ks.t_migrated (a int PRIMARY KEY);",
+    );
+    let second = Migration::new(
+        2,
+        "add column and insert",
+        "ALTER TABLE ks.t_migrated ADD b text;\n-- comment\nINSERT INTO ks.t_migrated (a, b) VALUES (1, 'x;y');",
+    );
+
+    let migrator = Migrator::new(&session, "ks")
+        .table("t_migrations")
+        .migration(second.clone())
+        .migration(first.clone());
+
+    let states = |statuses: Vec<_>| {
+        statuses
+            .into_iter()
+            .map(|status: crate::transport::migrations::MigrationStatus| {
+                (status.version, status.state)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        states(migrator.status().await.unwrap()),
+        vec![(1, MigrationState::Pending), (2, MigrationState::Pending)]
+    );
+    assert_eq!(migrator.run().await.unwrap(), vec![1, 2]);
+    assert_eq!(migrator.run().await.unwrap(), Vec::<i64>::new());
+    assert_eq!(
+        states(migrator.status().await.unwrap()),
+        vec![(1, MigrationState::Applied), (2, MigrationState::Applied)]
+    );
+
+    let rows: Vec<(i32, String)> = session
+        .query("SELECT a, b FROM ks.t_migrated", &[])
+        .await
+        .unwrap()
+        .rows_typed()
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(rows, vec![(1, "x;y".to_string())]);
+
+    let modified = Migrator::new(&session, "ks")
+        .table("t_migrations")
+        .migration(Migration::new(
+            1,
+            "create table",
+            "CREATE TABLE ks.other (a int PRIMARY KEY)",
+        ));
+    assert!(matches!(
+        modified.run().await,
+        Err(MigrationError::Modified { version: 1 })
+    ));
+    assert_eq!(
+        states(modified.status().await.unwrap()),
+        vec![(1, MigrationState::Modified), (2, MigrationState::Unknown)]
+    );
+}
+
+#[tokio::test]
+async fn test_schema_snapshot() {
+    use crate::statement::checked::{schema_snapshot, SchemaSnapshotError};
+
+    let uri = std::env::var("SCYLLA_URI").unwrap_or_else(|_| "127.0.0.1:9042".to_string());
+    let session = SessionBuilder::new().known_node(uri).build().await.unwrap();
+
+    session.query("CREATE KEYSPACE IF NOT EXISTS ks_snapshot WITH REPLICATION = {'class' : 'SimpleStrategy', 'replication_factor' : 1}", &[]).await.unwrap();
+    session
+        .query("DROP TABLE IF EXISTS ks_snapshot.t", &[])
+        .await
+        .unwrap();
+    session
+        .query(
+            "CREATE TABLE ks_snapshot.t (a int, b text, s int static, c frozen<list<int>>, primary key (a, b))",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    let snapshot = schema_snapshot(&session, &["ks_snapshot"]).await.unwrap();
+    let json: serde_json::Value = serde_json::from_str(&snapshot).unwrap();
+    assert_eq!(
+        json["keyspaces"]["ks_snapshot"]["tables"]["t"],
+        serde_json::json!({
+            "columns": {"a": "int", "b": "text", "s": "int", "c": "frozen<list<int>>"},
+            "partition_key": ["a"],
+            "clustering_key": ["b"],
+            "static_columns": ["s"],
+        })
+    );
+
+    assert!(matches!(
+        schema_snapshot(&session, &["no_such_keyspace"]).await,
+        Err(SchemaSnapshotError::UnknownKeyspace(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_timestamp() {
+    let uri = std::env::var("SCYLLA_URI").unwrap_or_else(|_| "127.0.0.1:9042".to_string());
+    let session = SessionBuilder::new().known_node(uri).build().await.unwrap();
+
+    session.query("CREATE KEYSPACE IF NOT EXISTS ks WITH REPLICATION = {'class' : 'SimpleStrategy', 'replication_factor' : 1}", &[]).await.unwrap();
+    session
+        .query(
+            "CREATE TABLE IF NOT EXISTS ks.t_timestamp (a int primary key, b text)",
+            &[],
+        )
+        .await
+        .unwrap();
+    session.query("TRUNCATE ks.t_timestamp", &[]).await.unwrap();
+
+    let mut query = Query::new("INSERT INTO ks.t_timestamp (a, b) VALUES (1, 'query')".to_string());
+    query.set_timestamp(Some(420));
+    session.query(query, &[]).await.unwrap();
+
+    let mut prepared = session
+        .prepare("INSERT INTO ks.t_timestamp (a, b) VALUES (?, ?)")
+        .await
+        .unwrap();
+    prepared.set_timestamp(Some(42));
+    session.execute(&prepared, (2, "prepared")).await.unwrap();
+
+    let mut batch: Batch = Default::default();
+    batch.append_statement("INSERT INTO ks.t_timestamp (a, b) VALUES (3, 'batch')");
+    batch.set_timestamp(Some(4200));
+    session.batch(&batch, ((),)).await.unwrap();
+
+    // A write with an older timestamp doesn't overwrite the value
+    let mut query = Query::new("INSERT INTO ks.t_timestamp (a, b) VALUES (1, 'old')".to_string());
+    query.set_timestamp(Some(419));
+    session.query(query, &[]).await.unwrap();
+
+    // Statements without a timestamp get one from the session's generator
+    session
+        .query(
+            "INSERT INTO ks.t_timestamp (a, b) VALUES (4, 'generated')",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    let mut results: Vec<(i32, String, i64)> = session
+        .query("SELECT a, b, WRITETIME(b) FROM ks.t_timestamp", &[])
+        .await
+        .unwrap()
+        .rows_typed()
+        .unwrap()
+        .map(|row| row.unwrap())
+        .collect();
+    results.sort_unstable();
+
+    let generated = results.pop().unwrap();
+    assert_eq!((generated.0, generated.1.as_str()), (4, "generated"));
+    assert!(generated.2 > 4200);
+    assert_eq!(
+        results,
+        vec![
+            (1, "query".to_string(), 420),
+            (2, "prepared".to_string(), 42),
+            (3, "batch".to_string(), 4200),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_result_col_specs() {
+    use crate::frame::response::result::ColumnType;
+
+    let uri = std::env::var("SCYLLA_URI").unwrap_or_else(|_| "127.0.0.1:9042".to_string());
+    let session = SessionBuilder::new().known_node(uri).build().await.unwrap();
+
+    session.query("CREATE KEYSPACE IF NOT EXISTS ks WITH REPLICATION = {'class' : 'SimpleStrategy', 'replication_factor' : 1}", &[]).await.unwrap();
+    session
+        .query(
+            "CREATE TABLE IF NOT EXISTS ks.t_col_specs (a int primary key, b text, c list<int>)",
+            &[],
+        )
+        .await
+        .unwrap();
+    session
+        .query(
+            "INSERT INTO ks.t_col_specs (a, b, c) VALUES (1, 'b', [2])",
+            &[],
+        )
+        .await
+        .unwrap();
+
+    let expected = vec![
+        ("a", ColumnType::Int),
+        ("b", ColumnType::Text),
+        ("c", ColumnType::List(Box::new(ColumnType::Int))),
+    ];
+    let check_col_specs = |col_specs: &[crate::frame::response::result::ColumnSpec]| {
+        let names_and_types: Vec<(&str, ColumnType)> = col_specs
+            .iter()
+            .map(|spec| (spec.name(), spec.typ().clone()))
+            .collect();
+        assert_eq!(names_and_types, expected);
+        for spec in col_specs {
+            assert_eq!(spec.table_spec().ks_name(), "ks");
+            assert_eq!(spec.table_spec().table_name(), "t_col_specs");
+        }
+    };
+
+    let select = "SELECT a, b, c FROM ks.t_col_specs";
+    let result = session.query(select, &[]).await.unwrap();
+    check_col_specs(&result.col_specs);
+    assert_eq!(result.col_names(), vec!["a", "b", "c"]);
+
+    let prepared = session.prepare(select).await.unwrap();
+    check_col_specs(&session.execute(&prepared, &[]).await.unwrap().col_specs);
+
+    let mut rows = session.query_iter(select, &[]).await.unwrap();
+    assert!(rows.col_specs().is_empty());
+    rows.next().await.unwrap().unwrap();
+    check_col_specs(rows.col_specs());
+
+    // Results of writes have no columns
+    let insert = session
+        .query("INSERT INTO ks.t_col_specs (a) VALUES (2)", &[])
+        .await
+        .unwrap();
+    assert!(insert.col_specs.is_empty());
+}
+
+#[tokio::test]
+async fn test_cdc_log_partitioner() {
+    use crate::routing::Partitioner;
+
+    let uri = std::env::var("SCYLLA_URI").unwrap_or_else(|_| "127.0.0.1:9042".to_string());
+    let session = SessionBuilder::new().known_node(uri).build().await.unwrap();
+
+    session.query("CREATE KEYSPACE IF NOT EXISTS ks WITH REPLICATION = {'class' : 'SimpleStrategy', 'replication_factor' : 1}", &[]).await.unwrap();
+    session
+        .query(
+            "CREATE TABLE IF NOT EXISTS ks.t_cdc (a int primary key, b int) WITH cdc = {'enabled': true}",
+            &[],
+        )
+        .await
+        .unwrap();
+    session.refresh_topology().await.unwrap();
+
+    let base = session
+        .prepare("SELECT a, b FROM ks.t_cdc WHERE a = ?")
+        .await
+        .unwrap();
+    assert_eq!(base.get_partitioner(), Partitioner::Murmur3);
+
+    // CDC log tables use their own partitioner
+    let log = session
+        .prepare("SELECT * FROM ks.t_cdc_scylla_cdc_log WHERE \"cdc$stream_id\" = ?")
+        .await
+        .unwrap();
+    assert_eq!(log.get_table_name(), Some("t_cdc_scylla_cdc_log"));
+    assert_eq!(log.get_partitioner(), Partitioner::Cdc);
+}
@@ -5,7 +5,11 @@ use futures::{
 use std::{future::Future, sync::Arc, time::Duration};
 use tracing::warn;
 
-use super::{errors::QueryError, metrics::Metrics};
+use super::{
+    errors::{ProtocolError, QueryError},
+    metrics::Metrics,
+    runtime,
+};
 
 /// Context is passed as an argument to `SpeculativeExecutionPolicy` methods
 pub struct Context {
@@ -89,7 +93,7 @@ fn can_be_ignored<ResT>(result: &Result<ResT, QueryError>) -> bool {
     }
 }
 
-const EMPTY_PLAN_ERROR: QueryError = QueryError::ProtocolError("Empty query plan - driver bug!");
+const EMPTY_PLAN_ERROR: QueryError = QueryError::ProtocolError(ProtocolError::EmptyQueryPlan);
 
 pub async fn execute<QueryFut, ResT>(
     policy: &dyn SpeculativeExecutionPolicy,
@@ -105,8 +109,7 @@ where
     let mut async_tasks = FuturesUnordered::new();
     async_tasks.push(query_runner_generator());
 
-    let sleep = tokio::time::sleep(retry_interval).fuse();
-    tokio::pin!(sleep);
+    let mut sleep = runtime::sleep(retry_interval).fuse();
 
     let mut last_error = None;
     loop {
@@ -117,7 +120,7 @@ where
                     retries_remaining -= 1;
 
                     // reset the timeout
-                    sleep.set(tokio::time::sleep(retry_interval).fuse());
+                    sleep = runtime::sleep(retry_interval).fuse();
                 }
             }
             res = async_tasks.select_next_some() => {
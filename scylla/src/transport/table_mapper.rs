@@ -0,0 +1,287 @@
+//! Typed CRUD helpers for structs mapped to tables with `#[derive(Table)]`
+
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use super::caching_session::StatementCache;
+use super::connection::RowsExpectedError;
+use super::errors::QueryError;
+use super::iterator::TypedRowIterator;
+use super::session::Session;
+use crate::frame::response::cql_to_rust::{FromRow, FromRowError};
+use crate::frame::value::{SerializeValuesError, SerializedValues, ValueList};
+use crate::prepared_statement::PreparedStatement;
+use crate::query::Query;
+use crate::statement::quote_identifier;
+
+/// A struct whose fields are columns of a table.
+/// Implemented by `#[derive(Table)]`, see [`TableMapper`] for an example
+pub trait Table: FromRow {
+    /// Keyspace of the table, `None` means the keyspace used by the session
+    const KEYSPACE: Option<&'static str>;
+
+    /// Name of the table
+    const NAME: &'static str;
+
+    /// Names of all columns, in the order of struct fields
+    const COLUMNS: &'static [&'static str];
+
+    /// Names of partition key columns followed by clustering key columns
+    const PRIMARY_KEY: &'static [&'static str];
+
+    /// Tuple of primary key values, in the order of [`Table::PRIMARY_KEY`]
+    type PrimaryKey: ValueList;
+
+    /// Serializes values of all columns, in the order of [`Table::COLUMNS`]
+    fn serialize_values(&self) -> Result<SerializedValues, SerializeValuesError>;
+}
+
+/// Error returned by [`TableMapper::get_by_primary_key`]
+#[derive(Error, Debug)]
+pub enum TableMapperError {
+    #[error(transparent)]
+    QueryError(#[from] QueryError),
+    #[error(transparent)]
+    RowsExpectedError(#[from] RowsExpectedError),
+    #[error(transparent)]
+    FromRowError(#[from] FromRowError),
+}
+
+/// Executes typed `INSERT`, `SELECT` and `DELETE` statements on the table of `T`.
+/// Statements are prepared on first use and reused afterwards
+///
+/// # Example
+/// ```rust
+/// # use scylla::Session;
+/// # use std::error::Error;
+/// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+/// use scylla::frame::response::cql_to_rust::FromRow;
+/// use scylla::macros::{FromRow, Table};
+/// use scylla::transport::table_mapper::TableMapper;
+///
+/// #[derive(FromRow, Table, Debug)]
+/// #[table(keyspace = "ks", name = "users")]
+/// struct User {
+///     #[partition_key]
+///     id: i32,
+///     #[clustering_key]
+///     login: String,
+///     name: Option<String>,
+/// }
+///
+/// let users: TableMapper<User> = TableMapper::new(session);
+///
+/// let user = User { id: 1, login: "ann".to_string(), name: None };
+/// users.insert(&user).await?;
+///
+/// let user: Option<User> = users.get_by_primary_key((1, "ann".to_string())).await?;
+/// users.delete((1, "ann".to_string())).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TableMapper<'a, T: Table> {
+    session: &'a Session,
+    insert: Query,
+    select_by_primary_key: Query,
+    delete: Query,
+    select_all: Query,
+    prepared: StatementCache,
+    phantom_data: std::marker::PhantomData<T>,
+}
+
+impl<'a, T: Table> TableMapper<'a, T> {
+    /// Creates a mapper executing statements using `session`
+    pub fn new(session: &'a Session) -> Self {
+        let table = match T::KEYSPACE {
+            Some(keyspace) => format!(
+                "{}.{}",
+                quote_identifier(keyspace),
+                quote_identifier(T::NAME)
+            ),
+            None => quote_identifier(T::NAME),
+        };
+        let columns = T::COLUMNS
+            .iter()
+            .map(|column| quote_identifier(column))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let key_condition = T::PRIMARY_KEY
+            .iter()
+            .map(|column| format!("{} = ?", quote_identifier(column)))
+            .collect::<Vec<_>>()
+            .join(" AND ");
+        let markers = vec!["?"; T::COLUMNS.len()].join(", ");
+
+        TableMapper {
+            session,
+            insert: Query::new(format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                table, columns, markers
+            )),
+            select_by_primary_key: Query::new(format!(
+                "SELECT {} FROM {} WHERE {}",
+                columns, table, key_condition
+            )),
+            delete: Query::new(format!("DELETE FROM {} WHERE {}", table, key_condition)),
+            select_all: Query::new(format!("SELECT {} FROM {}", columns, table)),
+            // Holds all four statements
+            prepared: StatementCache::new(4),
+            phantom_data: Default::default(),
+        }
+    }
+
+    /// Inserts the row, overwriting a row with the same primary key
+    pub async fn insert(&self, row: &T) -> Result<(), QueryError> {
+        let prepared = self.prepare(&self.insert).await?;
+        self.session
+            .execute(&prepared, row.serialize_values()?)
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the row with the given primary key, `None` if it doesn't exist
+    pub async fn get_by_primary_key(
+        &self,
+        key: T::PrimaryKey,
+    ) -> Result<Option<T>, TableMapperError> {
+        let prepared = self.prepare(&self.select_by_primary_key).await?;
+        let result = self.session.execute(&prepared, key).await?;
+        Ok(result.rows_typed::<T>()?.next().transpose()?)
+    }
+
+    /// Deletes the row with the given primary key
+    pub async fn delete(&self, key: T::PrimaryKey) -> Result<(), QueryError> {
+        let prepared = self.prepare(&self.delete).await?;
+        self.session.execute(&prepared, key).await?;
+        Ok(())
+    }
+
+    /// Returns an iterator over all rows of the table, which are fetched in pages
+    pub async fn select_all(&self) -> Result<TypedRowIterator<T>, QueryError> {
+        let prepared = self.prepare(&self.select_all).await?;
+        let row_iterator = self.session.execute_iter((*prepared).clone(), &[]).await?;
+        Ok(row_iterator.into_typed())
+    }
+
+    /// Returns the statement used by [`TableMapper::insert`]
+    pub fn insert_statement(&self) -> &str {
+        self.insert.get_contents()
+    }
+
+    /// Returns the statement used by [`TableMapper::get_by_primary_key`]
+    pub fn select_by_primary_key_statement(&self) -> &str {
+        self.select_by_primary_key.get_contents()
+    }
+
+    /// Returns the statement used by [`TableMapper::delete`]
+    pub fn delete_statement(&self) -> &str {
+        self.delete.get_contents()
+    }
+
+    /// Returns the statement used by [`TableMapper::select_all`]
+    pub fn select_all_statement(&self) -> &str {
+        self.select_all.get_contents()
+    }
+
+    // Statements are prepared on first use and reused afterwards
+    async fn prepare(&self, query: &Query) -> Result<Arc<PreparedStatement>, QueryError> {
+        self.prepared.get_or_prepare(self.session, query).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Table;
+    use crate as scylla;
+    use crate::frame::value::SerializedValues;
+    use crate::macros::{FromRow, Table};
+
+    #[derive(FromRow, Table)]
+    struct UserEvent {
+        #[clustering_key]
+        time: i64,
+        #[partition_key]
+        user_id: i32,
+        #[partition_key]
+        day: i32,
+        description: Option<String>,
+    }
+
+    #[derive(FromRow, Table)]
+    #[table(keyspace = "ks", name = "items")]
+    struct Item {
+        #[partition_key]
+        id: i32,
+    }
+
+    #[test]
+    fn derive_table() {
+        assert_eq!(UserEvent::KEYSPACE, None);
+        assert_eq!(UserEvent::NAME, "user_event");
+        assert_eq!(
+            UserEvent::COLUMNS,
+            &["time", "user_id", "day", "description"]
+        );
+        assert_eq!(UserEvent::PRIMARY_KEY, &["user_id", "day", "time"]);
+
+        let key: <UserEvent as Table>::PrimaryKey = (1, 2, 3_i64);
+        let event = UserEvent {
+            time: key.2,
+            user_id: key.0,
+            day: key.1,
+            description: None,
+        };
+        let mut expected = SerializedValues::new();
+        expected.add_value(&3_i64).unwrap();
+        expected.add_value(&1).unwrap();
+        expected.add_value(&2).unwrap();
+        expected.add_value(&None::<String>).unwrap();
+        assert_eq!(event.serialize_values().unwrap(), expected);
+
+        assert_eq!(Item::KEYSPACE, Some("ks"));
+        assert_eq!(Item::NAME, "items");
+        let item = Item { id: 5 };
+        assert_eq!(item.id, 5);
+    }
+
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn statements_quote_identifiers() {
+        use super::TableMapper;
+        use crate::proxy::{MockNode, MockResponse};
+        use crate::SessionBuilder;
+
+        #[derive(FromRow, Table)]
+        #[table(keyspace = "Ks", name = "Items")]
+        struct Item {
+            #[partition_key]
+            id: i32,
+            order: i32,
+        }
+
+        let node = MockNode::new()
+            .default_response(MockResponse::Void)
+            .run()
+            .await
+            .unwrap();
+        let session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .build()
+            .await
+            .unwrap();
+        let items: TableMapper<Item> = TableMapper::new(&session);
+
+        let insert = "INSERT INTO \"Ks\".\"Items\" (id, \"order\") VALUES (?, ?)";
+        assert_eq!(items.insert_statement(), insert);
+        assert_eq!(
+            items.delete_statement(),
+            "DELETE FROM \"Ks\".\"Items\" WHERE id = ?"
+        );
+
+        items.insert(&Item { id: 1, order: 2 }).await.unwrap();
+        assert_eq!(node.received_statements(), vec![insert]);
+
+        node.finish().await.unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Generates client-side timestamps sent with statements which don't set their own timestamp.
+/// The database uses them as write timestamps, like `USING TIMESTAMP` in the statement text.
+pub trait TimestampGenerator: Send + Sync {
+    /// Returns the next timestamp, in microseconds since unix epoch
+    fn next_timestamp(&self) -> i64;
+}
+
+/// Generates timestamps from the system clock, guaranteeing that each timestamp
+/// is larger than the previous one, even if the clock goes back or the timestamps are
+/// generated in the same microsecond. Then the timestamps run ahead of the clock until it catches up.
+#[derive(Debug, Default)]
+pub struct MonotonicTimestampGenerator {
+    last: AtomicI64,
+}
+
+impl MonotonicTimestampGenerator {
+    /// Creates a new generator
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl TimestampGenerator for MonotonicTimestampGenerator {
+    fn next_timestamp(&self) -> i64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_micros() as i64)
+            .unwrap_or(0);
+
+        let mut last = self.last.load(Ordering::Relaxed);
+        loop {
+            let next = now.max(last + 1);
+            match self
+                .last
+                .compare_exchange_weak(last, next, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => return next,
+                Err(current) => last = current,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MonotonicTimestampGenerator, TimestampGenerator};
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    #[test]
+    fn monotonic_timestamps() {
+        let generator = MonotonicTimestampGenerator::new();
+        let first = generator.next_timestamp();
+        // 2020-01-01 in microseconds
+        assert!(first > 1_577_836_800_000_000);
+
+        // Timestamps run ahead of a clock which went back
+        generator.last.store(i64::MAX - 10, Ordering::Relaxed);
+        assert_eq!(generator.next_timestamp(), i64::MAX - 9);
+        assert_eq!(generator.next_timestamp(), i64::MAX - 8);
+    }
+
+    #[test]
+    fn concurrent_timestamps_are_unique() {
+        let generator = Arc::new(MonotonicTimestampGenerator::new());
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let generator = generator.clone();
+                std::thread::spawn(move || {
+                    (0..1000)
+                        .map(|_| generator.next_timestamp())
+                        .collect::<Vec<i64>>()
+                })
+            })
+            .collect();
+
+        let mut timestamps: Vec<i64> = threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect();
+        timestamps.sort_unstable();
+        timestamps.dedup();
+        assert_eq!(timestamps.len(), 4000);
+    }
+}
@@ -0,0 +1,92 @@
+//! Helpers for configuring TLS connections, enabled with the `ssl` feature
+
+use openssl::error::ErrorStack;
+use openssl::ssl::{SslContext, SslContextBuilder, SslFiletype, SslMethod, SslVerifyMode};
+use openssl::x509::verify::X509CheckFlags;
+use std::path::Path;
+
+/// Creates an [`SslContext`] which verifies database certificates with the CA certificate in `ca_file`.  
+/// The certificate of a node also has to be issued for the name set with
+/// [`SessionBuilder::tls_server_name`](crate::SessionBuilder::tls_server_name),
+/// or for the IP address of the node if no name is set.  
+/// If `client_cert_and_key` is set, the driver authenticates itself with the client certificate
+/// and its private key. All files have to be in the PEM format.
+///
+/// # Example
+/// ```
+/// # use scylla::{Session, SessionBuilder};
+/// use scylla::transport::tls::ssl_context_from_pem_files;
+/// use std::path::Path;
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let context = ssl_context_from_pem_files(
+///     Path::new("ca.crt"),
+///     Some((Path::new("client.crt"), Path::new("client.key"))),
+/// )?;
+///
+/// let session: Session = SessionBuilder::new()
+///     .known_node("127.0.0.1:9142")
+///     .ssl_context(Some(context))
+///     .build()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn ssl_context_from_pem_files(
+    ca_file: &Path,
+    client_cert_and_key: Option<(&Path, &Path)>,
+) -> Result<SslContext, ErrorStack> {
+    let mut context_builder = SslContextBuilder::new(SslMethod::tls())?;
+    context_builder.set_ca_file(ca_file)?;
+    context_builder.set_verify(SslVerifyMode::PEER);
+    // Wildcards only match whole labels of the name, e.g. `*.example.com` but not `node*.example.com`
+    context_builder
+        .verify_param_mut()
+        .set_hostflags(X509CheckFlags::NO_PARTIAL_WILDCARDS);
+
+    if let Some((cert_file, key_file)) = client_cert_and_key {
+        context_builder.set_certificate_file(cert_file, SslFiletype::PEM)?;
+        context_builder.set_private_key_file(key_file, SslFiletype::PEM)?;
+        context_builder.check_private_key()?;
+    }
+
+    Ok(context_builder.build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ssl_context_from_pem_files;
+    use std::path::Path;
+
+    #[test]
+    fn missing_files() {
+        assert!(ssl_context_from_pem_files(Path::new("/nonexistent/ca.crt"), None).is_err());
+    }
+
+    // A failed handshake fails the connection instead of leaving it in an unusable state
+    #[tokio::test]
+    async fn failed_handshake() {
+        use crate::transport::connection::{Connection, ConnectionConfig};
+        use crate::transport::errors::QueryError;
+        use openssl::ssl::{SslContextBuilder, SslMethod};
+        use tokio::net::TcpListener;
+
+        // Closes connections without answering the handshake
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                drop(stream);
+            }
+        });
+
+        let config = ConnectionConfig {
+            ssl_context: Some(SslContextBuilder::new(SslMethod::tls()).unwrap().build()),
+            tls_server_name: Some("node1.example.com".to_string()),
+            ..Default::default()
+        };
+
+        let result = Connection::new(addr, None, config).await;
+        assert!(matches!(result, Err(QueryError::IoError(_))));
+    }
+}
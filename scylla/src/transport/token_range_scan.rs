@@ -0,0 +1,219 @@
+//! Parallel scans of whole tables, split into token ranges
+//!
+//! A full scan executes a statement selecting rows in a range of tokens
+//! for each vnode of the ring. Each range is queried on the node which owns it,
+//! several ranges are queried concurrently and their rows are merged into one stream.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::stream::{self, BoxStream, SelectAll};
+use futures::{future, Stream, StreamExt};
+
+use super::errors::QueryError;
+use super::iterator::NextRowError;
+use super::session::Session;
+use crate::cql_to_rust::FromRow;
+use crate::frame::response::result::Row;
+use crate::frame::value::ValueList;
+use crate::prepared_statement::PreparedStatement;
+use crate::routing::TokenRange;
+
+/// Scans a whole table by querying each token range of the ring separately.
+///
+/// The statement has to have two bind markers, the first and the last token of a range, e.g.
+/// `SELECT a, b FROM ks.tab WHERE token(a) >= ? AND token(a) <= ?`.
+/// Rows of different ranges are returned in no particular order.
+///
+/// # Example
+/// ```rust
+/// # use scylla::Session;
+/// # use std::error::Error;
+/// # async fn check_only_compiles(session: &Session) -> Result<(), Box<dyn Error>> {
+/// use futures::StreamExt;
+/// use scylla::transport::token_range_scan::TokenRangeScanner;
+///
+/// let select = session
+///     .prepare("SELECT a, b FROM ks.tab WHERE token(a) >= ? AND token(a) <= ?")
+///     .await?;
+///
+/// let mut rows = TokenRangeScanner::new(session, &select)
+///     .concurrency(32)
+///     .scan()
+///     .into_typed::<(i32, String)>();
+///
+/// while let Some((a, b)) = rows.next().await.transpose()? {
+///     println!("a, b: {}, {}", a, b);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct TokenRangeScanner<'a> {
+    session: &'a Session,
+    statement: &'a PreparedStatement,
+    concurrency: usize,
+}
+
+impl<'a> TokenRangeScanner<'a> {
+    /// Creates a scanner executing `statement` for each token range,
+    /// by default 16 ranges are queried concurrently
+    pub fn new(session: &'a Session, statement: &'a PreparedStatement) -> Self {
+        TokenRangeScanner {
+            session,
+            statement,
+            concurrency: 16,
+        }
+    }
+
+    /// Sets the maximum number of ranges queried at the same time
+    /// # Panics
+    /// Panics if `concurrency` is 0
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        assert!(concurrency > 0, "concurrency must be positive");
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Starts the scan of ranges of the current ring, see [`ClusterData::token_ranges`](super::ClusterData::token_ranges)
+    pub fn scan(self) -> TokenRangeScan<'a> {
+        let ranges = self.session.get_cluster_data().token_ranges();
+        self.scan_ranges(ranges)
+    }
+
+    /// Starts the scan of the given ranges, e.g. to split the work between many processes
+    pub fn scan_ranges(self, ranges: Vec<TokenRange>) -> TokenRangeScan<'a> {
+        TokenRangeScan {
+            session: self.session,
+            statement: self.statement,
+            concurrency: self.concurrency,
+            pending_ranges: ranges.into_iter(),
+            running: SelectAll::new(),
+            running_count: 0,
+        }
+    }
+}
+
+/// Stream of rows returned by [`TokenRangeScanner::scan`].
+/// Failure of a range is returned as an error item, other ranges are still scanned
+pub struct TokenRangeScan<'a> {
+    session: &'a Session,
+    statement: &'a PreparedStatement,
+    concurrency: usize,
+    pending_ranges: std::vec::IntoIter<TokenRange>,
+    // Rows of each range are followed by None marking that the range is finished
+    running: SelectAll<BoxStream<'static, Option<Result<Row, QueryError>>>>,
+    running_count: usize,
+}
+
+impl<'a> TokenRangeScan<'a> {
+    /// Converts this scan into a stream of rows parsed as given type
+    pub fn into_typed<RowT: FromRow>(self) -> impl Stream<Item = Result<RowT, NextRowError>> + 'a {
+        self.map(|row| Ok(RowT::from_row(row?)?))
+    }
+
+    fn start_range(&mut self, range: TokenRange) -> Result<(), QueryError> {
+        let values = (&range.start, &range.end).into_serialized()?;
+        // The node owning the end of the range owns the whole range
+        let rows = self
+            .session
+            .execute_iter_with_token(self.statement.clone(), values, range.end);
+
+        self.running.push(
+            rows.map(Some)
+                .chain(stream::once(future::ready(None)))
+                .boxed(),
+        );
+        self.running_count += 1;
+        Ok(())
+    }
+}
+
+impl<'a> Stream for TokenRangeScan<'a> {
+    type Item = Result<Row, QueryError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let scan = &mut *self;
+
+        loop {
+            while scan.running_count < scan.concurrency {
+                match scan.pending_ranges.next() {
+                    Some(range) => {
+                        if let Err(error) = scan.start_range(range) {
+                            return Poll::Ready(Some(Err(error)));
+                        }
+                    }
+                    None => break,
+                }
+            }
+
+            match scan.running.poll_next_unpin(cx) {
+                Poll::Ready(Some(Some(row))) => return Poll::Ready(Some(row)),
+                // A range has finished, start the next one
+                Poll::Ready(Some(None)) => scan.running_count -= 1,
+                // Nothing is running, so all ranges were scanned
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "proxy")]
+    #[tokio::test]
+    async fn scan_queries_each_range() {
+        use super::TokenRangeScanner;
+        use crate::frame::response::result::{ColumnType, CqlValue};
+        use crate::proxy::{MockNode, MockResponse};
+        use crate::routing::{Token, TokenRange};
+        use crate::SessionBuilder;
+        use futures::TryStreamExt;
+
+        let node = MockNode::new()
+            .default_response(MockResponse::rows(
+                &[("a", ColumnType::Int)],
+                vec![vec![Some(CqlValue::Int(1))], vec![Some(CqlValue::Int(2))]],
+            ))
+            .run()
+            .await
+            .unwrap();
+        let session = SessionBuilder::new()
+            .known_node_addr(node.addr())
+            .build()
+            .await
+            .unwrap();
+
+        let statement = "SELECT a FROM ks.t WHERE token(a) >= ? AND token(a) <= ?";
+        let select = session.prepare(statement).await.unwrap();
+
+        let ranges: Vec<TokenRange> = (0..5)
+            .map(|i| TokenRange {
+                start: Token::Murmur3(i * 10),
+                end: Token::Murmur3(i * 10 + 9),
+            })
+            .collect();
+        let rows: Vec<(i32,)> = TokenRangeScanner::new(&session, &select)
+            .concurrency(2)
+            .scan_ranges(ranges)
+            .into_typed::<(i32,)>()
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(rows.len(), 10);
+        assert_eq!(rows.iter().filter(|(a,)| *a == 1).count(), 5);
+        assert_eq!(node.received_statements(), vec![statement; 5]);
+
+        // The whole ring is scanned
+        let ranges_num = session.get_cluster_data().token_ranges().len();
+        let rows: Vec<_> = TokenRangeScanner::new(&session, &select)
+            .scan()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 2 * ranges_num);
+
+        node.finish().await.unwrap();
+    }
+}
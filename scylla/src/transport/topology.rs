@@ -1,44 +1,139 @@
 use crate::frame::response::event::Event;
-use crate::routing::Token;
-use crate::transport::connection::{Connection, ConnectionConfig};
-use crate::transport::connection_keeper::ConnectionKeeper;
-use crate::transport::errors::QueryError;
+use crate::frame::response::result::CqlValue;
+use crate::routing::{Partitioner, Token};
+use crate::transport::address_translator::AddressTranslator;
+use crate::transport::connection::{Connection, ConnectionConfig, QueryResult};
+use crate::transport::control_connection::ControlConnection;
+use crate::transport::errors::{ProtocolError, QueryError};
+use crate::transport::host_filter::HostFilter;
+use crate::transport::runtime::timeout;
 use crate::transport::session::IntoTypedRows;
+use crate::transport::ProtocolVersion;
 
-use rand::seq::SliceRandom;
-use rand::thread_rng;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc;
 use tracing::{debug, error, warn};
+use uuid::Uuid;
 
 /// Allows to read current topology info from the cluster
 pub struct TopologyReader {
-    connection_config: ConnectionConfig,
-    control_connection_address: SocketAddr,
-    control_connection: ConnectionKeeper,
+    control_connection: ControlConnection,
 
-    // when control connection fails, TopologyReader tries to connect to one of known_peers
-    known_peers: Vec<SocketAddr>,
+    // ports to use when connecting to nodes with given addresses instead of the control connection's port
+    port_overrides: HashMap<IpAddr, u16>,
+
+    // maximal time of fetching topology info from a single node
+    metadata_timeout: Duration,
+
+    // translates addresses of peers before the filter is applied
+    address_translator: Arc<dyn AddressTranslator>,
+
+    // peers rejected by the filter aren't used as control connection
+    host_filter: Option<Arc<dyn HostFilter>>,
 }
 
 /// Describes all topology information retrieved from the cluster
 pub struct TopologyInfo {
     pub peers: Vec<Peer>,
     pub keyspaces: HashMap<String, Keyspace>,
+    pub cluster_info: ClusterInfo,
+}
+
+/// General information about the cluster, as reported by the node used as control connection
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClusterInfo {
+    /// Name of the cluster
+    pub cluster_name: Option<String>,
+    /// Release version reported by the node, e.g. "3.0.8"
+    pub release_version: Option<String>,
+    /// CQL version of the node, e.g. "3.3.1".
+    /// The protocol doesn't negotiate it, all statements are parsed in this version
+    pub cql_version: Option<String>,
+    /// Highest native protocol version supported by the node, e.g. "4"
+    pub native_protocol_version: Option<String>,
+    /// Protocol version negotiated on the control connection,
+    /// lower than `native_protocol_version` if the driver doesn't support the highest one
+    pub protocol_version: ProtocolVersion,
+    /// Class name of the partitioner used by the cluster, e.g. "org.apache.cassandra.dht.Murmur3Partitioner"
+    pub partitioner: Option<String>,
 }
 
+impl ClusterInfo {
+    /// Returns the partitioner used by the cluster, Murmur3 if it's unknown
+    pub fn partitioner(&self) -> Partitioner {
+        self.partitioner
+            .as_deref()
+            .and_then(Partitioner::from_class_name)
+            .unwrap_or_default()
+    }
+}
+
+/// Node of the cluster, as described in `system.local` or `system.peers`
+#[derive(Clone, Debug)]
 pub struct Peer {
+    /// Address the driver connects to
     pub address: SocketAddr,
     pub tokens: Vec<Token>,
     pub datacenter: Option<String>,
     pub rack: Option<String>,
+    pub host_id: Option<Uuid>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Keyspace {
     pub strategy: Strategy,
+    /// Tables in the keyspace, materialized views are not included
+    pub tables: HashMap<String, Table>,
+    /// User defined types in the keyspace
+    pub user_defined_types: HashMap<String, UserDefinedType>,
+    /// Whether writes to the keyspace go through the commit log
+    pub durable_writes: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Table {
+    pub columns: HashMap<String, Column>,
+    /// Names of partition key columns, in order
+    pub partition_key: Vec<String>,
+    /// Names of clustering key columns, in order
+    pub clustering_key: Vec<String>,
+    /// Class name of the partitioner, if the table doesn't use the cluster's partitioner,
+    /// e.g. "com.scylladb.dht.CDCPartitioner" for CDC log tables
+    pub partitioner: Option<String>,
+    /// Options of the table, e.g. `gc_grace_seconds` or `compaction`, by name.
+    /// Values are CQL literals, as they appear in the `WITH` clause of `CREATE TABLE`
+    pub options: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Column {
+    /// CQL type of the column, as it appears in system_schema.columns, e.g. `frozen<list<int>>`
+    pub typ: String,
+    pub kind: ColumnKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnKind {
+    Regular,
+    Static,
+    Clustering(ClusteringOrder),
+    PartitionKey,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClusteringOrder {
+    Ascending,
+    Descending,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UserDefinedType {
+    /// Names and CQL types of the fields, in declaration order
+    pub fields: Vec<(String, String)>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -63,29 +158,22 @@ impl TopologyReader {
         known_peers: &[SocketAddr],
         mut connection_config: ConnectionConfig,
         server_event_sender: mpsc::Sender<Event>,
+        port_overrides: HashMap<IpAddr, u16>,
+        metadata_timeout: Duration,
+        address_translator: Arc<dyn AddressTranslator>,
+        host_filter: Option<Arc<dyn HostFilter>>,
     ) -> Self {
-        let control_connection_address = *known_peers
-            .choose(&mut thread_rng())
-            .expect("Tried to initialize TopologyReader with empty known_peers list!");
-
         // setting event_sender field in connection config will cause control connection to
         // - send REGISTER message to receive server events
         // - send received events via server_event_sender
         connection_config.event_sender = Some(server_event_sender);
 
-        let control_connection = ConnectionKeeper::new(
-            control_connection_address,
-            connection_config.clone(),
-            None,
-            None,
-            None,
-        );
-
         TopologyReader {
-            control_connection_address,
-            control_connection,
-            connection_config,
-            known_peers: known_peers.into(),
+            control_connection: ControlConnection::new(known_peers, connection_config),
+            port_overrides,
+            metadata_timeout,
+            address_translator,
+            host_filter,
         }
     }
 
@@ -96,37 +184,21 @@ impl TopologyReader {
             return result;
         }
 
-        // shuffle known_peers to iterate through them in random order later
-        self.known_peers.shuffle(&mut thread_rng());
-
-        let address_of_failed_control_connection = self.control_connection_address;
-        let filtered_known_peers = self
-            .known_peers
-            .iter()
-            .filter(|&peer| peer != &address_of_failed_control_connection);
-
         // if fetching topology info on current control connection failed,
         // try to fetch topology info from other known peer
-        for peer in filtered_known_peers {
+        for peer in self.control_connection.failover_candidates() {
             let err = match result {
                 Ok(_) => break,
                 Err(err) => err,
             };
 
             warn!(
-                control_connection_address = self.control_connection_address.to_string().as_str(),
+                control_connection_address = self.control_connection.address().to_string().as_str(),
                 error = err.to_string().as_str(),
                 "Falied to fetch topology info using current control connection"
             );
 
-            self.control_connection_address = *peer;
-            self.control_connection = ConnectionKeeper::new(
-                self.control_connection_address,
-                self.connection_config.clone(),
-                None,
-                None,
-                None,
-            );
+            self.control_connection.reconnect_to(peer);
 
             result = self.fetch_topology_info().await;
         }
@@ -145,155 +217,565 @@ impl TopologyReader {
         result
     }
 
+    /// Fetches current schema of a single keyspace, `None` if the keyspace doesn't exist.
+    /// Unlike [`read_topology_info`](Self::read_topology_info), doesn't try other nodes
+    /// if the control connection fails
+    pub async fn read_keyspace(&self, keyspace_name: &str) -> Result<Option<Keyspace>, QueryError> {
+        let fetch_future = async {
+            let conn = self.control_connection.get_connection().await?;
+            let mut keyspaces = query_keyspaces(&conn, Some(keyspace_name)).await?;
+            Ok(keyspaces.remove(keyspace_name))
+        };
+
+        timeout(self.metadata_timeout, fetch_future)
+            .await
+            .unwrap_or(Err(QueryError::TimeoutError))
+    }
+
+    /// Waits until the control connection is broken.
+    /// Topology should be read again then, which moves the control connection to a working node.
+    pub async fn wait_until_control_connection_broken(&self) {
+        self.control_connection.wait_until_broken().await
+    }
+
     async fn fetch_topology_info(&self) -> Result<TopologyInfo, QueryError> {
-        // TODO: Timeouts?
+        let fetch_future = async {
+            query_topology_info(
+                &*self.control_connection.get_connection().await?,
+                self.control_connection.address().port(),
+                &self.port_overrides,
+                &*self.address_translator,
+            )
+            .await
+        };
 
-        query_topology_info(
-            &self.control_connection,
-            self.control_connection_address.port(),
-        )
-        .await
+        // A slow node shouldn't stall the refresh, on timeout we try another one
+        timeout(self.metadata_timeout, fetch_future)
+            .await
+            .unwrap_or(Err(QueryError::TimeoutError))
     }
 
+    // Control connection is moved only to peers accepted by the host filter
     fn update_known_peers(&mut self, topology_info: &TopologyInfo) {
-        self.known_peers = topology_info
-            .peers
-            .iter()
-            .map(|peer| peer.address)
-            .collect();
+        self.control_connection.update_known_peers(
+            topology_info
+                .peers
+                .iter()
+                .filter(|peer| is_accepted(&self.host_filter, peer))
+                .map(|peer| peer.address)
+                .collect(),
+        );
+    }
+}
+
+/// Checks if the peer is accepted by the host filter, all peers are accepted without a filter
+pub(crate) fn is_accepted(host_filter: &Option<Arc<dyn HostFilter>>, peer: &Peer) -> bool {
+    match host_filter {
+        Some(host_filter) => host_filter.accept(peer),
+        None => true,
     }
 }
 
 async fn query_topology_info(
-    conn_keeper: &ConnectionKeeper,
+    conn: &Connection,
     connect_port: u16,
+    port_overrides: &HashMap<IpAddr, u16>,
+    address_translator: &dyn AddressTranslator,
 ) -> Result<TopologyInfo, QueryError> {
-    let conn: &Connection = &*conn_keeper.get_connection().await?;
-
-    let peers_query = query_peers(conn, connect_port);
-    let keyspaces_query = query_keyspaces(conn);
-
-    let (peers, keyspaces) = tokio::try_join!(peers_query, keyspaces_query)?;
+    let node = conn.get_connect_address();
+    // All queries are sent concurrently on the control connection
+    let peers_query = query_peers(conn, connect_port, port_overrides, address_translator);
+    let local_query = query_local(conn, connect_port, port_overrides);
+    let keyspaces_query = query_keyspaces(conn, None);
+
+    let (mut raw_peers, (local_peer, cluster_info), keyspaces) =
+        tokio::try_join!(peers_query, local_query, keyspaces_query)?;
+    raw_peers.push(local_peer);
+
+    // Tokens are parsed using the cluster's partitioner
+    let partitioner = cluster_info.partitioner();
+    let peers = raw_peers
+        .into_iter()
+        .map(|(peer, tokens)| parse_tokens(node, peer, &tokens, partitioner))
+        .collect::<Result<Vec<Peer>, QueryError>>()?;
 
     // There must be at least one peer
     if peers.is_empty() {
-        return Err(QueryError::ProtocolError(
-            "Bad TopologyInfo: peers list is empty",
-        ));
+        return Err(ProtocolError::system_table(
+            node,
+            "system.peers",
+            "no nodes in system.peers and system.local",
+        )
+        .into());
     }
 
     // At least one peer has to have some tokens
     if peers.iter().all(|peer| peer.tokens.is_empty()) {
-        return Err(QueryError::ProtocolError(
-            "Bad TopoologyInfo: All peers have empty token list",
-        ));
+        return Err(ProtocolError::system_table(
+            node,
+            "system.peers",
+            "all nodes in system.peers and system.local have empty token lists",
+        )
+        .into());
     }
 
-    Ok(TopologyInfo { peers, keyspaces })
+    Ok(TopologyInfo {
+        peers,
+        keyspaces,
+        cluster_info,
+    })
 }
 
-async fn query_peers(conn: &Connection, connect_port: u16) -> Result<Vec<Peer>, QueryError> {
-    // There shouldn't be more peers than a single page capacity
-    let peers_query = conn.query_single_page(
-        "select peer, data_center, rack, tokens from system.peers",
-        &[],
-    );
-    let local_query = conn.query_single_page(
-        "select rpc_address, data_center, rack, tokens from system.local",
-        &[],
-    );
-
-    let (peers_res, local_res) = tokio::try_join!(peers_query, local_query)?;
+const NOT_ROWS: &str = "response is not Rows";
 
-    let peers_rows = peers_res.rows.ok_or(QueryError::ProtocolError(
-        "system.peers query response was not Rows",
-    ))?;
+// Returns peers without tokens, along with text representations of their tokens
+async fn query_peers(
+    conn: &Connection,
+    connect_port: u16,
+    port_overrides: &HashMap<IpAddr, u16>,
+    address_translator: &dyn AddressTranslator,
+) -> Result<Vec<(Peer, Vec<String>)>, QueryError> {
+    let node = conn.get_connect_address();
+    // There shouldn't be more peers than a single page capacity
+    let peers_rows = conn
+        .query_single_page(
+            "select peer, rpc_address, data_center, rack, tokens, host_id from system.peers",
+            &[],
+        )
+        .await?
+        .rows
+        .ok_or_else(|| ProtocolError::system_table(node, "system.peers", NOT_ROWS))?;
+
+    let mut result: Vec<(Peer, Vec<String>)> = Vec::with_capacity(peers_rows.rows_count() + 1);
+
+    let typed_peers_rows = peers_rows.into_typed::<(
+        IpAddr,
+        Option<IpAddr>,
+        Option<String>,
+        Option<String>,
+        Option<Vec<String>>,
+        Option<Uuid>,
+    )>();
+
+    for row in typed_peers_rows {
+        let (peer, rpc_address, datacenter, rack, tokens, host_id) =
+            row.map_err(|err| ProtocolError::system_table(node, "system.peers", err.to_string()))?;
+
+        let ip_address = choose_peer_address(peer, rpc_address);
+        let address = address_translator.translate(SocketAddr::new(
+            ip_address,
+            peer_port(ip_address, connect_port, port_overrides),
+        ));
 
-    let local_rows = local_res.rows.ok_or(QueryError::ProtocolError(
-        "system.local query response was not Rows",
-    ))?;
+        let peer = Peer {
+            address,
+            tokens: Vec::new(),
+            datacenter,
+            rack,
+            host_id,
+        };
+        result.push((peer, tokens.unwrap_or_default()));
+    }
 
-    let mut result: Vec<Peer> = Vec::with_capacity(peers_rows.len() + 1);
+    Ok(result)
+}
 
-    let typed_peers_rows =
-        peers_rows.into_typed::<(IpAddr, Option<String>, Option<String>, Option<Vec<String>>)>();
+// Reads the local node and the cluster info from a single query to system.local.
+// The local node is returned without tokens, along with text representations of its tokens
+async fn query_local(
+    conn: &Connection,
+    connect_port: u16,
+    port_overrides: &HashMap<IpAddr, u16>,
+) -> Result<((Peer, Vec<String>), ClusterInfo), QueryError> {
+    let node = conn.get_connect_address();
+    let rows = conn
+        .query_single_page(
+            "select data_center, rack, tokens, host_id, cluster_name, release_version, cql_version, \
+            native_protocol_version, partitioner from system.local",
+            &[],
+        )
+        .await?
+        .rows
+        .ok_or_else(|| ProtocolError::system_table(node, "system.local", NOT_ROWS))?;
+
+    let (
+        datacenter,
+        rack,
+        tokens,
+        host_id,
+        cluster_name,
+        release_version,
+        cql_version,
+        native_protocol_version,
+        partitioner,
+    ) = rows
+        .into_typed::<(
+            Option<String>,
+            Option<String>,
+            Option<Vec<String>>,
+            Option<Uuid>,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+        )>()
+        .next()
+        .ok_or_else(|| ProtocolError::system_table(node, "system.local", "no rows returned"))?
+        .map_err(|err| ProtocolError::system_table(node, "system.local", err.to_string()))?;
 
     // For the local node we should use connection's address instead of rpc_address unless SNI is enabled (TODO)
-    // Replace address in local_rows with connection's address
+    // It is the address we are connected to, so it doesn't need translation
     let local_address: IpAddr = conn.get_connect_address().ip();
-    let typed_local_rows = local_rows
-        .into_typed::<(IpAddr, Option<String>, Option<String>, Option<Vec<String>>)>()
-        .map(|res| res.map(|(_addr, dc, rack, tokens)| (local_address, dc, rack, tokens)));
+    let local_peer = Peer {
+        address: SocketAddr::new(
+            local_address,
+            peer_port(local_address, connect_port, port_overrides),
+        ),
+        tokens: Vec::new(),
+        datacenter,
+        rack,
+        host_id,
+    };
+
+    let cluster_info = ClusterInfo {
+        cluster_name,
+        release_version,
+        cql_version,
+        native_protocol_version,
+        protocol_version: conn.get_protocol_version(),
+        partitioner,
+    };
 
-    for row in typed_peers_rows.chain(typed_local_rows) {
-        let (ip_address, datacenter, rack, tokens) = row.map_err(|_| {
-            QueryError::ProtocolError("system.peers or system.local has invalid column type")
+    Ok(((local_peer, tokens.unwrap_or_default()), cluster_info))
+}
+
+fn peer_port(ip_address: IpAddr, connect_port: u16, port_overrides: &HashMap<IpAddr, u16>) -> u16 {
+    port_overrides
+        .get(&ip_address)
+        .copied()
+        .unwrap_or(connect_port)
+}
+
+fn parse_tokens(
+    node: SocketAddr,
+    mut peer: Peer,
+    tokens: &[String],
+    partitioner: Partitioner,
+) -> Result<Peer, QueryError> {
+    peer.tokens = tokens
+        .iter()
+        .map(|token| partitioner.parse_token(token))
+        .collect::<Result<Vec<Token>, _>>()
+        .map_err(|err| {
+            ProtocolError::system_table(
+                node,
+                "system.peers",
+                format!("invalid token of node {}: {}", peer.address, err),
+            )
         })?;
+    Ok(peer)
+}
 
-        let tokens_str: Vec<String> = tokens.unwrap_or_default();
+// rpc_address in system.peers is the address on which the peer accepts client connections
+// (broadcast_rpc_address), while peer contains its broadcast_address used for internode communication.
+// peer is used only if rpc_address is missing or set to a wildcard address.
+fn choose_peer_address(peer: IpAddr, rpc_address: Option<IpAddr>) -> IpAddr {
+    match rpc_address {
+        Some(rpc_address) if !rpc_address.is_unspecified() => rpc_address,
+        _ => peer,
+    }
+}
 
-        let address = SocketAddr::new(ip_address, connect_port);
+// Reads rows of a system_schema table, only the ones describing the given keyspace if there is one
+async fn query_schema_table(
+    conn: &Connection,
+    query: &str,
+    keyspace_name: Option<&str>,
+) -> Result<QueryResult, QueryError> {
+    match keyspace_name {
+        Some(keyspace_name) => {
+            conn.query_single_page(
+                format!("{} where keyspace_name = ?", query),
+                (keyspace_name,),
+            )
+            .await
+        }
+        None => conn.query_single_page(query, &[]).await,
+    }
+}
 
-        // Parse string representation of tokens as integer values
-        let tokens: Vec<Token> = tokens_str
-            .iter()
-            .map(|s| Token::from_str(&s))
-            .collect::<Result<Vec<Token>, _>>()
-            .map_err(|_| QueryError::ProtocolError("Couldn't parse tokens as integer values"))?;
+// Reads schema of all keyspaces, or only of the given one
+async fn query_keyspaces(
+    conn: &Connection,
+    keyspace_name: Option<&str>,
+) -> Result<HashMap<String, Keyspace>, QueryError> {
+    let node = conn.get_connect_address();
+    let (mut tables, mut user_defined_types) = tokio::try_join!(
+        query_tables(conn, keyspace_name),
+        query_user_defined_types(conn, keyspace_name)
+    )?;
+
+    let rows = query_schema_table(
+        conn,
+        "select keyspace_name, durable_writes, toJson(replication) from system_schema.keyspaces",
+        keyspace_name,
+    )
+    .await?
+    .rows
+    .ok_or_else(|| ProtocolError::system_table(node, "system_schema.keyspaces", NOT_ROWS))?;
+
+    let mut result = HashMap::with_capacity(rows.rows_count());
+
+    for row in rows.into_typed::<(String, Option<bool>, String)>() {
+        let (keyspace_name, durable_writes, keyspace_json_text) = row.map_err(|err| {
+            ProtocolError::system_table(node, "system_schema.keyspaces", err.to_string())
+        })?;
 
-        result.push(Peer {
-            address,
-            tokens,
-            datacenter,
-            rack,
-        });
+        let strategy: Strategy = json_to_string_map(&keyspace_json_text)
+            .and_then(strategy_from_string_map)
+            .map_err(|reason| {
+                ProtocolError::system_table(
+                    node,
+                    "system_schema.keyspaces",
+                    format!("replication of keyspace {}: {}", keyspace_name, reason),
+                )
+            })?;
+
+        let keyspace = Keyspace {
+            strategy,
+            tables: tables.remove(&keyspace_name).unwrap_or_default(),
+            user_defined_types: user_defined_types
+                .remove(&keyspace_name)
+                .unwrap_or_default(),
+            durable_writes: durable_writes.unwrap_or(true),
+        };
+
+        result.insert(keyspace_name, keyspace);
     }
 
     Ok(result)
 }
 
-async fn query_keyspaces(conn: &Connection) -> Result<HashMap<String, Keyspace>, QueryError> {
-    let rows = conn
-        .query_single_page(
-            "select keyspace_name, toJson(replication) from system_schema.keyspaces",
-            &[],
-        )
-        .await?
+// Columns of system_schema.tables which aren't options that can be set in CREATE TABLE
+const NOT_TABLE_OPTIONS: &[&str] = &[
+    "keyspace_name",
+    "table_name",
+    "id",
+    "flags",
+    "extensions",
+    "partitioner",
+];
+
+// Renders an option value as a CQL literal, None for values which can't be options
+fn cql_literal(value: &CqlValue) -> Option<String> {
+    let quote = |text: &str| format!("'{}'", text.replace('\'', "''"));
+
+    match value {
+        CqlValue::Ascii(text) | CqlValue::Text(text) => Some(quote(text)),
+        CqlValue::Boolean(value) => Some(value.to_string()),
+        CqlValue::Int(value) => Some(value.to_string()),
+        CqlValue::BigInt(value) => Some(value.to_string()),
+        CqlValue::Double(value) => Some(value.to_string()),
+        CqlValue::Float(value) => Some(value.to_string()),
+        CqlValue::Map(entries) => {
+            let entries = entries
+                .iter()
+                .map(|(key, value)| Some(format!("{}: {}", cql_literal(key)?, cql_literal(value)?)))
+                .collect::<Option<Vec<String>>>()?;
+            Some(format!("{{{}}}", entries.join(", ")))
+        }
+        _ => None,
+    }
+}
+
+// Returns tables grouped by keyspace name
+async fn query_tables(
+    conn: &Connection,
+    keyspace_name: Option<&str>,
+) -> Result<HashMap<String, HashMap<String, Table>>, QueryError> {
+    let node = conn.get_connect_address();
+    // Only Scylla has the partitioner column, so all columns are selected
+    let tables_query =
+        query_schema_table(conn, "select * from system_schema.tables", keyspace_name);
+    let columns_query = query_schema_table(
+        conn,
+        "select keyspace_name, table_name, column_name, kind, position, clustering_order, type from system_schema.columns",
+        keyspace_name,
+    );
+
+    let (tables_res, columns_res) = tokio::try_join!(tables_query, columns_query)?;
+
+    let tables_rows = tables_res
+        .rows_named()
+        .map_err(|_| ProtocolError::system_table(node, "system_schema.tables", NOT_ROWS))?;
+
+    let columns_rows = columns_res
         .rows
-        .ok_or(QueryError::ProtocolError(
-            "system_schema.keyspaces query response was not Rows",
-        ))?;
+        .ok_or_else(|| ProtocolError::system_table(node, "system_schema.columns", NOT_ROWS))?;
+
+    let mut result: HashMap<String, HashMap<String, Table>> = HashMap::new();
+
+    // system_schema.columns contains columns of materialized views too,
+    // only columns of tables listed in system_schema.tables are taken into account
+    for row in tables_rows {
+        let row = row.map_err(|err| {
+            ProtocolError::system_table(node, "system_schema.tables", err.to_string())
+        })?;
+        let column = |name: &str| {
+            row.get::<String>(name).map_err(|err| {
+                ProtocolError::system_table(node, "system_schema.tables", err.to_string())
+            })
+        };
+        let keyspace_name = column("keyspace_name")?;
+        let table_name = column("table_name")?;
+        let partitioner = row
+            .get::<Option<String>>("partitioner")
+            .unwrap_or_default()
+            .filter(|partitioner| !partitioner.is_empty());
+
+        let options = row
+            .iter()
+            .filter(|(name, _)| !NOT_TABLE_OPTIONS.contains(name))
+            .filter_map(|(name, value)| Some((name.to_string(), cql_literal(value?)?)))
+            .collect();
+
+        let table = Table {
+            partitioner,
+            options,
+            ..Default::default()
+        };
+        result
+            .entry(keyspace_name)
+            .or_default()
+            .insert(table_name, table);
+    }
 
-    let mut result = HashMap::with_capacity(rows.len());
+    // Key columns along with their positions, used to sort them afterwards
+    let mut partition_keys: HashMap<(String, String), Vec<(i32, String)>> = HashMap::new();
+    let mut clustering_keys: HashMap<(String, String), Vec<(i32, String)>> = HashMap::new();
+
+    for row in
+        columns_rows.into_typed::<(String, String, String, String, i32, Option<String>, String)>()
+    {
+        let (keyspace_name, table_name, column_name, kind, position, clustering_order, typ) = row
+            .map_err(
+            |err| ProtocolError::system_table(node, "system_schema.columns", err.to_string()),
+        )?;
+
+        let table = match result
+            .get_mut(&keyspace_name)
+            .and_then(|tables| tables.get_mut(&table_name))
+        {
+            Some(table) => table,
+            None => continue,
+        };
+
+        let kind = match kind.as_str() {
+            "partition_key" => {
+                partition_keys
+                    .entry((keyspace_name, table_name))
+                    .or_default()
+                    .push((position, column_name.clone()));
+                ColumnKind::PartitionKey
+            }
+            "clustering" => {
+                clustering_keys
+                    .entry((keyspace_name, table_name))
+                    .or_default()
+                    .push((position, column_name.clone()));
+                match clustering_order.as_deref() {
+                    Some("desc") => ColumnKind::Clustering(ClusteringOrder::Descending),
+                    _ => ColumnKind::Clustering(ClusteringOrder::Ascending),
+                }
+            }
+            "static" => ColumnKind::Static,
+            _ => ColumnKind::Regular,
+        };
 
-    for row in rows.into_typed::<(String, String)>() {
-        let (keyspace_name, keyspace_json_text) = row.map_err(|_| {
-            QueryError::ProtocolError("system_schema.keyspaces has invalid column type")
+        table.columns.insert(column_name, Column { typ, kind });
+    }
+
+    for (keys, mut columns) in partition_keys {
+        columns.sort_unstable();
+        if let Some(table) = result.get_mut(&keys.0).and_then(|t| t.get_mut(&keys.1)) {
+            table.partition_key = columns.into_iter().map(|(_, name)| name).collect();
+        }
+    }
+
+    for (keys, mut columns) in clustering_keys {
+        columns.sort_unstable();
+        if let Some(table) = result.get_mut(&keys.0).and_then(|t| t.get_mut(&keys.1)) {
+            table.clustering_key = columns.into_iter().map(|(_, name)| name).collect();
+        }
+    }
+
+    Ok(result)
+}
+
+// Returns user defined types grouped by keyspace name
+async fn query_user_defined_types(
+    conn: &Connection,
+    keyspace_name: Option<&str>,
+) -> Result<HashMap<String, HashMap<String, UserDefinedType>>, QueryError> {
+    let node = conn.get_connect_address();
+    let rows = query_schema_table(
+        conn,
+        "select keyspace_name, type_name, field_names, field_types from system_schema.types",
+        keyspace_name,
+    )
+    .await?
+    .rows
+    .ok_or_else(|| ProtocolError::system_table(node, "system_schema.types", NOT_ROWS))?;
+
+    let mut result: HashMap<String, HashMap<String, UserDefinedType>> = HashMap::new();
+
+    for row in rows.into_typed::<(String, String, Option<Vec<String>>, Option<Vec<String>>)>() {
+        let (keyspace_name, type_name, field_names, field_types) = row.map_err(|err| {
+            ProtocolError::system_table(node, "system_schema.types", err.to_string())
         })?;
 
-        let strategy_map: HashMap<String, String> = json_to_string_map(&keyspace_json_text)?;
+        let field_names = field_names.unwrap_or_default();
+        let field_types = field_types.unwrap_or_default();
+
+        if field_names.len() != field_types.len() {
+            return Err(ProtocolError::system_table(
+                node,
+                "system_schema.types",
+                format!(
+                    "type {}.{} has {} field names and {} field types",
+                    keyspace_name,
+                    type_name,
+                    field_names.len(),
+                    field_types.len()
+                ),
+            )
+            .into());
+        }
 
-        let strategy: Strategy = strategy_from_string_map(strategy_map)?;
+        let udt = UserDefinedType {
+            fields: field_names.into_iter().zip(field_types).collect(),
+        };
 
-        result.insert(keyspace_name, Keyspace { strategy });
+        result
+            .entry(keyspace_name)
+            .or_default()
+            .insert(type_name, udt);
     }
 
     Ok(result)
 }
 
-fn json_to_string_map(json_text: &str) -> Result<HashMap<String, String>, QueryError> {
+// Errors are descriptions of the problem, the caller adds the keyspace name
+fn json_to_string_map(json_text: &str) -> Result<HashMap<String, String>, String> {
     use serde_json::Value;
 
     let json: Value = serde_json::from_str(json_text)
-        .map_err(|_| QueryError::ProtocolError("Couldn't parse keyspaces as json"))?;
+        .map_err(|err| format!("couldn't parse {} as json: {}", json_text, err))?;
 
     let object_map = match json {
         Value::Object(map) => map,
-        _ => {
-            return Err(QueryError::ProtocolError(
-                "keyspaces map json is not a json object",
-            ))
-        }
+        _ => return Err(format!("{} is not a json object", json_text)),
     };
 
     let mut result = HashMap::with_capacity(object_map.len());
@@ -301,37 +783,29 @@ fn json_to_string_map(json_text: &str) -> Result<HashMap<String, String>, QueryE
     for (key, val) in object_map.into_iter() {
         match val {
             Value::String(string) => result.insert(key, string),
-            _ => {
-                return Err(QueryError::ProtocolError(
-                    "json keyspaces map does not contain strings",
-                ))
-            }
+            _ => return Err(format!("value of {} in {} is not a string", key, json_text)),
         };
     }
 
     Ok(result)
 }
 
-fn strategy_from_string_map(
-    mut strategy_map: HashMap<String, String>,
-) -> Result<Strategy, QueryError> {
+fn strategy_from_string_map(mut strategy_map: HashMap<String, String>) -> Result<Strategy, String> {
     let strategy_name: String = strategy_map
         .remove("class")
-        .ok_or(QueryError::ProtocolError(
-            "strategy map should have a 'class' field",
-        ))?;
+        .ok_or_else(|| "strategy map should have a 'class' field".to_string())?;
 
     let strategy: Strategy = match strategy_name.as_str() {
         "org.apache.cassandra.locator.SimpleStrategy" => {
-            let rep_factor_str: String =
-                strategy_map
-                    .remove("replication_factor")
-                    .ok_or(QueryError::ProtocolError(
-                        "SimpleStrategy in strategy map does not have a replication factor",
-                    ))?;
+            let rep_factor_str: String = strategy_map
+                .remove("replication_factor")
+                .ok_or_else(|| "SimpleStrategy does not have a replication factor".to_string())?;
 
             let replication_factor: usize = usize::from_str(&rep_factor_str).map_err(|_| {
-                QueryError::ProtocolError("Could not parse replication factor as an integer")
+                format!(
+                    "couldn't parse replication factor {} as an integer",
+                    rep_factor_str
+                )
             })?;
 
             Strategy::SimpleStrategy { replication_factor }
@@ -362,3 +836,44 @@ fn strategy_from_string_map(
 
     Ok(strategy)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{choose_peer_address, cql_literal};
+    use crate::frame::response::result::CqlValue;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn test_cql_literal() {
+        let text = |text: &str| CqlValue::Text(text.to_string());
+
+        assert_eq!(cql_literal(&text("it's")), Some("'it''s'".to_string()));
+        assert_eq!(
+            cql_literal(&CqlValue::Int(864000)),
+            Some("864000".to_string())
+        );
+        assert_eq!(
+            cql_literal(&CqlValue::Double(0.01)),
+            Some("0.01".to_string())
+        );
+        assert_eq!(
+            cql_literal(&CqlValue::Map(vec![
+                (text("class"), text("SizeTieredCompactionStrategy")),
+                (text("min_threshold"), text("4")),
+            ])),
+            Some("{'class': 'SizeTieredCompactionStrategy', 'min_threshold': '4'}".to_string())
+        );
+        assert_eq!(cql_literal(&CqlValue::Blob(vec![1, 2])), None);
+    }
+
+    #[test]
+    fn test_choose_peer_address() {
+        let peer = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let rpc_address = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1));
+        let wildcard = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+
+        assert_eq!(choose_peer_address(peer, Some(rpc_address)), rpc_address);
+        assert_eq!(choose_peer_address(peer, Some(wildcard)), peer);
+        assert_eq!(choose_peer_address(peer, None), peer);
+    }
+}